@@ -0,0 +1,175 @@
+//! One telemetry cycle for a synthetic 64-sensor, 8-fan host: copy the readings out
+//! of the sensor cache and serialize the data message.
+//!
+//! `value_tree` is the previous hot path (owned `String` metadata, a
+//! `serde_json::Value` per message, then `to_string`); `direct` is the current one
+//! (`Arc<str>` metadata, `DataMessage` written into a reused buffer).
+//!
+//!     cargo bench --bench telemetry
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use serde::Serialize;
+
+use pankha_agent::hardware::types::{Fan, Sensor, SystemHealth};
+use pankha_agent::websocket::payload::{DataMessage, Entries, RegisteredHardware};
+
+const SENSORS: usize = 64;
+const FANS: usize = 8;
+const AGENT_ID: &str = "linux-bench-1a2b3c4d";
+const UPTIME_MS: u64 = 86_400_000;
+
+/// `Sensor` as it was before its metadata became `Arc<str>`.
+#[derive(Clone, Serialize)]
+struct OwnedSensor {
+    id: String,
+    name: String,
+    temperature: f64,
+    #[serde(rename = "type")]
+    sensor_type: String,
+    max_temp: Option<f64>,
+    crit_temp: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chip: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "hardwareName")]
+    hardware_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    source: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    alarms: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_temperature: Option<f64>,
+}
+
+fn sensors() -> Vec<Sensor> {
+    (0..SENSORS)
+        .map(|i| {
+            let chip = ["k10temp", "nct6798", "nvme", "drivetemp"][i % 4];
+            Sensor {
+                id: format!("{}_temp{}", chip, i).into(),
+                name: format!("{} Temperature {}", chip, i).into(),
+                display_name: None,
+                temperature: 35.0 + i as f64 * 0.7,
+                sensor_type: ["cpu", "motherboard", "nvme", "hdd"][i % 4].into(),
+                max_temp: Some(90.0),
+                crit_temp: Some(100.0),
+                chip: Some(chip.into()),
+                hardware_name: Some(format!("Synthetic {} device", chip).into()),
+                source: Some(format!("/sys/class/hwmon/hwmon{}/temp{}_input", i / 8, i % 8 + 1).into()),
+                alarms: Vec::new(),
+                raw_temperature: None,
+                stale: false,
+            }
+        })
+        .collect()
+}
+
+fn owned(sensors: &[Sensor]) -> Vec<OwnedSensor> {
+    sensors
+        .iter()
+        .map(|s| OwnedSensor {
+            id: s.id.to_string(),
+            name: s.name.to_string(),
+            temperature: s.temperature,
+            sensor_type: s.sensor_type.to_string(),
+            max_temp: s.max_temp,
+            crit_temp: s.crit_temp,
+            chip: s.chip.as_deref().map(str::to_string),
+            hardware_name: s.hardware_name.as_deref().map(str::to_string),
+            source: s.source.as_deref().map(str::to_string),
+            alarms: Vec::new(),
+            raw_temperature: None,
+        })
+        .collect()
+}
+
+fn fans() -> Vec<Fan> {
+    (0..FANS)
+        .map(|i| Fan {
+            id: format!("nct6798_fan_{}", i + 1),
+            name: format!("Chassis Fan {}", i + 1),
+            rpm: Some(800 + i as u32 * 50),
+            speed: 40,
+            target_speed: 40,
+            pwm: Some(102),
+            status: "ok".to_string(),
+            status_detail: None,
+            has_pwm_control: true,
+            control_method: None,
+            pwm_file: Some(format!("/sys/class/hwmon/hwmon2/pwm{}", i + 1)),
+            pwm_enable: None,
+            control_mode: None,
+            zone: None,
+            alarms: Vec::new(),
+            drift: false,
+            control_error: None,
+            last_set_by: None,
+        })
+        .collect()
+}
+
+fn telemetry(c: &mut Criterion) {
+    let cache = sensors();
+    let owned_cache = owned(&cache);
+    let fans = fans();
+    let health = SystemHealth { cpu_usage: 12.5, memory_usage: 41.0, agent_uptime: 86_400.0, throttled: None, connection: None, storage_health: Vec::new(), notifications: None };
+    let timestamp = 1_760_601_234_000;
+
+    let mut group = c.benchmark_group("data_message_64_sensors");
+    group.bench_function("value_tree", |b| {
+        b.iter(|| {
+            let sensors = owned_cache.clone();
+            let message = serde_json::json!({
+                "type": "data",
+                "data": {
+                    "agentId": AGENT_ID,
+                    "timestamp": timestamp,
+                    "uptime_ms": UPTIME_MS,
+                    "unit": "celsius",
+                    "sensors": serde_json::to_value(&sensors).unwrap(),
+                    "fans": serde_json::to_value(&fans).unwrap(),
+                    "systemHealth": health,
+                }
+            });
+            black_box(message.to_string())
+        })
+    });
+
+    let mut buf = Vec::new();
+    group.bench_function("direct", |b| {
+        b.iter(|| {
+            let sensors = cache.clone();
+            DataMessage::new(AGENT_ID, timestamp, UPTIME_MS, Entries::full(&sensors), Entries::full(&fans), &health)
+                .write_into(&mut buf)
+                .unwrap();
+            black_box(buf.len())
+        })
+    });
+
+    let registered = RegisteredHardware::new(&cache, &fans);
+    group.bench_function("direct_compact", |b| {
+        b.iter(|| {
+            let sensors = cache.clone();
+            DataMessage::new(AGENT_ID, timestamp, UPTIME_MS, registered.sensors(&sensors), registered.fans(&fans), &health)
+                .write_into(&mut buf)
+                .unwrap();
+            black_box(buf.len())
+        })
+    });
+    group.finish();
+
+    // Keep the two paths honest: same bytes on the wire
+    let expected = serde_json::json!({
+        "type": "data",
+        "data": {"agentId": AGENT_ID, "timestamp": timestamp, "uptime_ms": UPTIME_MS, "unit": "celsius",
+                 "sensors": owned_cache, "fans": fans, "systemHealth": health}
+    })
+    .to_string();
+    DataMessage::new(AGENT_ID, timestamp, UPTIME_MS, Entries::full(&cache), Entries::full(&fans), &health)
+        .write_into(&mut buf)
+        .unwrap();
+    assert_eq!(std::str::from_utf8(&buf).unwrap(), expected);
+}
+
+criterion_group!(benches, telemetry);
+criterion_main!(benches);
@@ -1,5 +1,14 @@
 //! Application infrastructure re-exports (CLI, logging).
 
+pub mod characterize;
 pub mod cli;
+#[cfg(target_os = "linux")]
+pub mod doctor;
+pub mod hardware_test;
 pub mod logging;
+pub mod log_rotation;
+pub mod log_throttle;
 pub mod platform;
+pub mod redact;
+pub mod rolling_window;
+pub mod suspend;
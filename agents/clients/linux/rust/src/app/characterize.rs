@@ -0,0 +1,573 @@
+//! `--characterize` and the `characterizeFans` command: how temperatures follow fan
+//! speed, as a starting point for curves on a new machine.
+//!
+//! Each fan group (fans sharing an IPMI zone, else each fan on its own) is held at a
+//! series of fixed duties, highest first. A duty is held until every sensor has
+//! settled, meaning the slope of its readings over the last `steady_window` is
+//! within `steady_slope` °C per minute, or until `max_hold` passes. The report has
+//! the settled temperatures and RPM per duty. For each sensor that responded to the
+//! group, it also has the lowest duty estimated to hold it at `target_temp`. Results
+//! are only meaningful on an idle system.
+//!
+//! Safety rails: the run doesn't start, and stops, once any sensor comes within
+//! `safety_margin` of its emergency threshold or asserts a crit alarm. Lower duties
+//! only follow higher ones. Every fan's original PWM value and `pwm_enable` mode are
+//! put back after its group and at the end, however the run ends.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+use tracing::info;
+
+use crate::config::types::{HardwareSettings, TemperatureUnit};
+use crate::hardware::types::{Fan, FanControlState, FanSpeedOrigin, Sensor};
+use crate::hardware::HardwareMonitor;
+
+/// Duties held by default, in percent.
+pub(crate) const DEFAULT_DUTIES: &[u8] = &[100, 75, 55, 40, 30];
+
+/// Lowest duty a run may hold, in percent: below it many fans stop.
+pub(crate) const MIN_DUTY: u8 = 20;
+
+/// How often a hold asks whether to stop.
+const STOP_POLL: Duration = Duration::from_millis(250);
+
+/// A sensor responds to a group when its settled temperature moves at least this
+/// much (°C) across the duties.
+const RESPONSIVE_SPREAD: f64 = 1.0;
+
+#[derive(Debug, Clone)]
+pub(crate) struct CharacterizeOptions {
+    /// Percent, held highest first
+    pub(crate) duties: Vec<u8>,
+    /// Temperature the lowest-duty estimates are for (°C)
+    pub(crate) target_temp: f64,
+    /// Only these fans; every controllable fan when empty
+    pub(crate) fans: Vec<String>,
+    pub(crate) sample_interval: Duration,
+    /// Readings the slope is fitted over, and the shortest hold
+    pub(crate) steady_window: Duration,
+    /// Longest hold of one duty, settled or not
+    pub(crate) max_hold: Duration,
+    /// Largest slope (°C per minute) that counts as settled
+    pub(crate) steady_slope: f64,
+    /// Stop this far (°C) below any sensor's emergency threshold
+    pub(crate) safety_margin: f64,
+}
+
+impl Default for CharacterizeOptions {
+    fn default() -> Self {
+        Self {
+            duties: DEFAULT_DUTIES.to_vec(),
+            target_temp: 60.0,
+            fans: Vec::new(),
+            sample_interval: Duration::from_secs(5),
+            steady_window: Duration::from_secs(60),
+            max_hold: Duration::from_secs(300),
+            steady_slope: 0.3,
+            safety_margin: 10.0,
+        }
+    }
+}
+
+impl CharacterizeOptions {
+    /// Options of a `characterizeFans` command: `duties`, `targetTemp`, `fans` and
+    /// `maxHoldSecs`, each optional.
+    pub(crate) fn from_payload(payload: &serde_json::Value) -> Result<Self> {
+        let mut options = Self::default();
+        if let Some(duties) = payload.get("duties").and_then(|v| v.as_array()) {
+            options.duties = duties.iter()
+                .map(|d| d.as_u64().filter(|d| *d <= 100).map(|d| d as u8))
+                .collect::<Option<_>>()
+                .context("duties must be percentages")?;
+        }
+        if let Some(target) = payload.get("targetTemp").and_then(|v| v.as_f64()) {
+            options.target_temp = target;
+        }
+        if let Some(fans) = payload.get("fans").and_then(|v| v.as_array()) {
+            options.fans = fans.iter().filter_map(|f| f.as_str().map(str::to_string)).collect();
+        }
+        if let Some(secs) = payload.get("maxHoldSecs").and_then(|v| v.as_f64()) {
+            options.max_hold = Duration::try_from_secs_f64(secs).context("maxHoldSecs must be a number of seconds")?;
+        }
+        options.validate()?;
+        Ok(options)
+    }
+
+    /// Sort the duties highest first; reject a run that can't be held safely.
+    pub(crate) fn validate(&mut self) -> Result<()> {
+        self.duties.sort_unstable_by_key(|duty| std::cmp::Reverse(*duty));
+        self.duties.dedup();
+        if self.duties.is_empty() {
+            bail!("No duties to hold");
+        }
+        if let Some(duty) = self.duties.iter().find(|d| **d < MIN_DUTY) {
+            bail!("Duty {}% is below the {}% minimum", duty, MIN_DUTY);
+        }
+        if self.max_hold < self.steady_window {
+            bail!("The longest hold must be at least {}s", self.steady_window.as_secs());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct CharacterizationReport {
+    /// Unix ms
+    pub(crate) started: i64,
+    pub(crate) finished: i64,
+    /// Why the run stopped early; null if every duty was held
+    pub(crate) aborted: Option<String>,
+    /// °C, as every temperature in the report
+    pub(crate) target_temp: f64,
+    pub(crate) groups: Vec<GroupReport>,
+    /// Fans whose original state could not be put back, with the error
+    pub(crate) restore_failures: Vec<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct GroupReport {
+    pub(crate) fans: Vec<String>,
+    pub(crate) levels: Vec<LevelReport>,
+    /// Sensors that responded to this group, with the lowest duty (percent)
+    /// estimated to hold them at `target_temp`; null when even the highest doesn't
+    pub(crate) min_duty: BTreeMap<String, Option<u8>>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct LevelReport {
+    pub(crate) duty: u8,
+    /// Every sensor settled before `max_hold`
+    pub(crate) steady: bool,
+    pub(crate) held_secs: u64,
+    /// Mean over the last `steady_window`
+    pub(crate) temperatures: BTreeMap<String, f64>,
+    /// At the end of the hold; null without a tachometer
+    pub(crate) rpm: BTreeMap<String, Option<u32>>,
+}
+
+/// Readings of one duty; settled once every sensor's least-squares slope over the
+/// last `window` is within `max_slope` °C per minute.
+pub(crate) struct SteadyState {
+    window: Duration,
+    max_slope: f64,
+    first: Option<Duration>,
+    last: Duration,
+    /// (seconds since the hold started, °C) within the window, per sensor
+    series: BTreeMap<String, VecDeque<(f64, f64)>>,
+}
+
+impl SteadyState {
+    pub(crate) fn new(window: Duration, max_slope: f64) -> Self {
+        Self { window, max_slope, first: None, last: Duration::ZERO, series: BTreeMap::new() }
+    }
+
+    /// Readings taken `at` after the hold started.
+    pub(crate) fn record<'a>(&mut self, at: Duration, readings: impl IntoIterator<Item = (&'a str, f64)>) {
+        self.first.get_or_insert(at);
+        self.last = at;
+        let oldest = at.saturating_sub(self.window).as_secs_f64();
+        for (id, temp) in readings {
+            let series = self.series.entry(id.to_string()).or_default();
+            series.push_back((at.as_secs_f64(), temp));
+            while series.front().is_some_and(|(t, _)| *t < oldest) {
+                series.pop_front();
+            }
+        }
+    }
+
+    /// Readings span a whole window and no sensor is still moving.
+    pub(crate) fn is_steady(&self) -> bool {
+        let spanned = self.first.is_some_and(|first| self.last.saturating_sub(first) >= self.window);
+        spanned && self.series.values().all(|series| slope_per_minute(series).abs() <= self.max_slope)
+    }
+
+    /// Mean temperature of each sensor over the window.
+    pub(crate) fn averages(&self) -> BTreeMap<String, f64> {
+        self.series.iter()
+            .filter(|(_, series)| !series.is_empty())
+            .map(|(id, series)| (id.clone(), series.iter().map(|(_, temp)| temp).sum::<f64>() / series.len() as f64))
+            .collect()
+    }
+}
+
+/// Least-squares slope of (seconds, °C) readings, in °C per minute.
+fn slope_per_minute(series: &VecDeque<(f64, f64)>) -> f64 {
+    let n = series.len() as f64;
+    if series.len() < 2 {
+        return 0.0;
+    }
+    let mean_t = series.iter().map(|(t, _)| t).sum::<f64>() / n;
+    let mean_y = series.iter().map(|(_, y)| y).sum::<f64>() / n;
+    let (cov, var) = series.iter().fold((0.0, 0.0), |(cov, var), (t, y)| {
+        (cov + (t - mean_t) * (y - mean_y), var + (t - mean_t).powi(2))
+    });
+    if var == 0.0 { 0.0 } else { cov / var * 60.0 }
+}
+
+/// Lowest duty that holds a sensor at or below `target`, from its settled
+/// (duty, °C) levels: interpolated between the last level at or below the target
+/// and the first one above it. None if even the highest duty ends up above it; the
+/// lowest duty held if none does.
+pub(crate) fn min_duty_for(levels: &[(u8, f64)], target: f64) -> Option<u8> {
+    let mut levels = levels.to_vec();
+    levels.sort_by_key(|(duty, _)| std::cmp::Reverse(*duty));
+    let &(mut held_duty, mut held_temp) = levels.first().filter(|(_, temp)| *temp <= target)?;
+    for &(duty, temp) in &levels[1..] {
+        if temp > target {
+            let fraction = (temp - target) / (temp - held_temp);
+            return Some((duty as f64 + (held_duty - duty) as f64 * fraction).ceil() as u8);
+        }
+        (held_duty, held_temp) = (duty, temp);
+    }
+    Some(held_duty)
+}
+
+/// Controllable fans to characterize, grouped: fans of one IPMI zone are driven
+/// together, every other fan on its own.
+pub(crate) fn fan_groups(fans: &[Fan], only: &[String]) -> Vec<Vec<String>> {
+    let mut groups: Vec<(Option<&str>, Vec<String>)> = Vec::new();
+    for fan in fans.iter().filter(|f| f.has_pwm_control && (only.is_empty() || only.contains(&f.id))) {
+        match fan.zone.as_deref().and_then(|zone| groups.iter_mut().find(|(z, _)| *z == Some(zone))) {
+            Some((_, group)) => group.push(fan.id.clone()),
+            None => groups.push((fan.zone.as_deref(), vec![fan.id.clone()])),
+        }
+    }
+    groups.into_iter().map(|(_, group)| group).collect()
+}
+
+/// The sensor furthest into its safety margin below the emergency threshold, or
+/// with a crit alarm, as the reason to stop.
+fn too_hot(sensors: &[Sensor], hardware: &HardwareSettings, margin: f64) -> Option<String> {
+    let watched = || sensors.iter().filter(|s| !hardware.excluded_sensors.iter().any(|id| *id == *s.id));
+    if let Some(sensor) = watched().find(|s| s.has_crit_alarm()) {
+        return Some(format!("crit alarm on {}", sensor.id));
+    }
+    watched()
+        .map(|s| (s, s.instant_temperature() - (hardware.emergency_temp_for(&s.sensor_type) - margin)))
+        .filter(|(_, over)| *over >= 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(s, _)| format!("{} at {:.1}°C, within {:.0}°C of its emergency threshold {:.0}°C",
+                              s.id, s.instant_temperature(), margin, hardware.emergency_temp_for(&s.sensor_type)))
+}
+
+/// Run the characterization. `stop` is asked while waiting for readings; a reason (Ctrl+C,
+/// connection lost) ends the run. Fans are restored however it ends, and the report
+/// says how far it got. Errors only when the run can't start.
+pub(crate) async fn run(
+    monitor: &dyn HardwareMonitor,
+    hardware: &HardwareSettings,
+    options: &CharacterizeOptions,
+    stop: &(dyn Fn() -> Option<String> + Send + Sync),
+    progress: &(dyn Fn(String) + Send + Sync),
+) -> Result<CharacterizationReport> {
+    if monitor.is_dry_run() {
+        bail!("Dry run: fan writes are not executed, so there is nothing to measure");
+    }
+    let sensors = monitor.discover_sensors().await?;
+    if let Some(reason) = too_hot(&sensors, hardware, options.safety_margin) {
+        bail!("Not starting: {}", reason);
+    }
+
+    // Fans whose state can't be saved (GPU fans) can't be put back afterwards
+    let mut originals: BTreeMap<String, FanControlState> = BTreeMap::new();
+    let mut groups = fan_groups(&monitor.discover_fans().await?, &options.fans);
+    for fan in groups.iter().flatten() {
+        match monitor.fan_control_state(fan).await {
+            Ok(Some(state)) => {
+                originals.insert(fan.clone(), state);
+            }
+            Ok(None) => progress(format!("Skipping {}: its state can't be saved to restore afterwards", fan)),
+            Err(e) => progress(format!("Skipping {}: {:#}", fan, e)),
+        }
+    }
+    groups.iter_mut().for_each(|group| group.retain(|fan| originals.contains_key(fan)));
+    groups.retain(|group| !group.is_empty());
+    if groups.is_empty() {
+        bail!("No controllable fans to characterize");
+    }
+
+    let mut report = CharacterizationReport {
+        started: chrono::Utc::now().timestamp_millis(),
+        finished: 0,
+        aborted: None,
+        target_temp: options.target_temp,
+        groups: Vec::new(),
+        restore_failures: Vec::new(),
+    };
+    for (i, group) in groups.iter().enumerate() {
+        progress(format!("Group {}/{}: {}", i + 1, groups.len(), group.join(", ")));
+        let mut levels = Vec::new();
+        let mut result = Ok(());
+        for &duty in &options.duties {
+            match hold_level(monitor, hardware, options, group, duty, stop, progress).await {
+                Ok(level) => levels.push(level),
+                Err(e) => {
+                    result = Err(e);
+                    break;
+                }
+            }
+        }
+        for fan in group {
+            if let Err(e) = monitor.restore_fan_control_state(fan, &originals[fan]).await {
+                report.restore_failures.push(format!("{}: {:#}", fan, e));
+            }
+        }
+        report.groups.push(group_report(group.clone(), levels, options.target_temp));
+        if let Err(e) = result {
+            report.aborted = Some(format!("{:#}", e));
+            break;
+        }
+    }
+    report.finished = chrono::Utc::now().timestamp_millis();
+    Ok(report)
+}
+
+/// Hold `fans` at `duty` until the temperatures settle or `max_hold` passes.
+async fn hold_level(
+    monitor: &dyn HardwareMonitor,
+    hardware: &HardwareSettings,
+    options: &CharacterizeOptions,
+    fans: &[String],
+    duty: u8,
+    stop: &(dyn Fn() -> Option<String> + Send + Sync),
+    progress: &(dyn Fn(String) + Send + Sync),
+) -> Result<LevelReport> {
+    let started = Instant::now();
+    let mut steady = SteadyState::new(options.steady_window, options.steady_slope);
+    loop {
+        // Again before every reading: a write within the per-fan rate limit is dropped
+        for fan in fans {
+            monitor.set_fan_speed(fan, duty, &FanSpeedOrigin::Characterization).await
+                .with_context(|| format!("Setting {} to {}%", fan, duty))?;
+        }
+        if let Some(reason) = wait(options.sample_interval, stop).await {
+            bail!("Stopped at {}%: {}", duty, reason);
+        }
+        let sensors = monitor.discover_sensors().await?;
+        if let Some(reason) = too_hot(&sensors, hardware, options.safety_margin) {
+            bail!("Stopped at {}%: {}", duty, reason);
+        }
+        let watched = sensors.iter().filter(|s| !hardware.excluded_sensors.iter().any(|id| *id == *s.id));
+        steady.record(started.elapsed(), watched.map(|s| (&*s.id, s.instant_temperature())));
+        if steady.is_steady() || started.elapsed() >= options.max_hold {
+            break;
+        }
+    }
+
+    let current = monitor.discover_fans().await?;
+    let rpm = fans.iter()
+        .map(|id| (id.clone(), current.iter().find(|f| f.id == *id).and_then(|f| f.rpm)))
+        .collect();
+    let level = LevelReport {
+        duty,
+        steady: steady.is_steady(),
+        held_secs: started.elapsed().as_secs(),
+        temperatures: steady.averages(),
+        rpm,
+    };
+    progress(format!("  {:>3}%: held {}s{}", duty, level.held_secs, if level.steady { ", settled" } else { ", still moving" }));
+    Ok(level)
+}
+
+/// Sleep for `interval`, asking `stop` every `STOP_POLL`; the reason if it answers.
+async fn wait(interval: Duration, stop: &(dyn Fn() -> Option<String> + Send + Sync)) -> Option<String> {
+    let until = Instant::now() + interval;
+    loop {
+        if let Some(reason) = stop() {
+            return Some(reason);
+        }
+        let left = until.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            return None;
+        }
+        tokio::time::sleep(left.min(STOP_POLL)).await;
+    }
+}
+
+/// The group's levels and, for each sensor that responded, the lowest duty.
+fn group_report(fans: Vec<String>, levels: Vec<LevelReport>, target_temp: f64) -> GroupReport {
+    let mut by_sensor: BTreeMap<&str, Vec<(u8, f64)>> = BTreeMap::new();
+    for level in &levels {
+        for (sensor, temp) in &level.temperatures {
+            by_sensor.entry(sensor).or_default().push((level.duty, *temp));
+        }
+    }
+    let min_duty = by_sensor.into_iter()
+        .filter(|(_, temps)| {
+            let (lo, hi) = temps.iter().fold((f64::MAX, f64::MIN), |(lo, hi), (_, t)| (lo.min(*t), hi.max(*t)));
+            hi - lo >= RESPONSIVE_SPREAD
+        })
+        .map(|(sensor, temps)| (sensor.to_string(), min_duty_for(&temps, target_temp)))
+        .collect();
+    GroupReport { fans, levels, min_duty }
+}
+
+/// `--characterize`: refuses while the agent runs (it drives the fans) and, since
+/// no Hub watches a local run, without `--force-local`. Returns false if the run
+/// stopped early or a fan couldn't be restored.
+pub async fn run_cli(monitor: &dyn HardwareMonitor, hardware: &HardwareSettings, force_local: bool,
+                     target_temp: Option<f64>, json: bool) -> Result<bool> {
+    if crate::daemon::pid::is_running() {
+        bail!("The agent is running and drives the fans: stop it first (--stop), or start characterizeFans from the Hub");
+    }
+    if !force_local {
+        bail!("A local characterization runs without the Hub watching: pass --force-local to run it anyway");
+    }
+    let mut options = CharacterizeOptions::default();
+    options.target_temp = target_temp.unwrap_or(options.target_temp);
+    options.validate()?;
+
+    // Ctrl+C ends the hold in progress; the fans are restored before exiting
+    let interrupted = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&interrupted);
+    tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            flag.store(true, Ordering::Relaxed);
+        }
+    });
+    let stop = move || interrupted.load(Ordering::Relaxed).then(|| "interrupted".to_string());
+    let progress = |line: String| if json { info!("{}", line) } else { println!("{}", line) };
+
+    if !json {
+        println!("\x1b[32mpankha-agent v{} ({})\x1b[0m", crate::version::VERSION, std::env::consts::ARCH);
+        println!("Fan Characterization");
+        println!("====================\n");
+        println!("Holding each fan group at {} until temperatures settle (up to {}s each). Keep the system idle.\n",
+                 options.duties.iter().map(|d| format!("{}%", d)).collect::<Vec<_>>().join(", "), options.max_hold.as_secs());
+    }
+    let report = run(monitor, hardware, &options, &stop, &progress).await?;
+    if json {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_report(&report, hardware.temperature_unit);
+    }
+    Ok(report.aborted.is_none() && report.restore_failures.is_empty())
+}
+
+/// Human summary: one table per group with the sensors that responded.
+fn print_report(report: &CharacterizationReport, unit: TemperatureUnit) {
+    for group in &report.groups {
+        println!("\n{}", group.fans.join(", "));
+        let sensors: Vec<&String> = group.min_duty.keys().collect();
+        if sensors.is_empty() {
+            println!("  No sensor responded to these fans");
+            continue;
+        }
+        print!("  {:>5} {:>6}", "Duty", "Held");
+        for sensor in &sensors {
+            print!("  {:>24}", sensor);
+        }
+        println!();
+        for level in &group.levels {
+            print!("  {:>4}% {:>5}s{}", level.duty, level.held_secs, if level.steady { " " } else { "~" });
+            for sensor in &sensors {
+                print!("  {:>24}", level.temperatures.get(*sensor).map_or("-".to_string(), |t| unit.format(*t)));
+            }
+            println!();
+        }
+        println!("  Lowest duty for {}:", unit.format(report.target_temp));
+        for (sensor, duty) in &group.min_duty {
+            println!("    {:<32} {}", sensor, duty.map_or("not reached at any duty".to_string(), |d| format!("{}%", d)));
+        }
+    }
+    println!();
+    if let Some(reason) = &report.aborted {
+        println!("\x1b[31m✗ Stopped early: {}\x1b[0m", reason);
+    }
+    for failure in &report.restore_failures {
+        println!("\x1b[31m✗ Not restored: {}\x1b[0m", failure);
+    }
+    if report.aborted.is_none() && report.restore_failures.is_empty() {
+        println!("\x1b[32m✓ Done; fans restored (~ = still moving at the end of the hold)\x1b[0m");
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::config::types::AgentConfig;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm, Temp};
+
+    fn at(secs: u64) -> Duration {
+        Duration::from_secs(secs)
+    }
+
+    #[test]
+    fn settles_once_the_slope_flattens_over_a_whole_window() {
+        let mut steady = SteadyState::new(at(60), 0.3);
+        // Warming 1°C per 10s: moving
+        for i in 0..=6 {
+            steady.record(at(i * 10), [("cpu", 40.0 + i as f64)]);
+        }
+        assert!(!steady.is_steady());
+        // Flat for a whole window, with noise
+        for i in 7..=13 {
+            steady.record(at(i * 10), [("cpu", 46.0 + if i % 2 == 0 { 0.1 } else { -0.1 })]);
+        }
+        assert!(steady.is_steady());
+        assert!((steady.averages()["cpu"] - 46.0).abs() < 0.1);
+
+        // Flat but not yet a whole window
+        let mut short = SteadyState::new(at(60), 0.3);
+        short.record(at(0), [("cpu", 40.0)]);
+        short.record(at(30), [("cpu", 40.0)]);
+        assert!(!short.is_steady());
+    }
+
+    #[test]
+    fn lowest_duty_is_interpolated_between_levels() {
+        let levels = [(100, 50.0), (75, 55.0), (55, 62.0), (40, 70.0)];
+        // 60°C lies 5/7 of the way from 55°C (75%) to 62°C (55%)
+        assert_eq!(min_duty_for(&levels, 60.0), Some(61));
+        assert_eq!(min_duty_for(&levels, 75.0), Some(40)); // every level holds it
+        assert_eq!(min_duty_for(&levels, 45.0), None); // not even at 100%
+        assert_eq!(min_duty_for(&levels, 55.0), Some(75));
+    }
+
+    #[tokio::test]
+    async fn run_holds_each_duty_and_restores_the_fans() {
+        let sysfs = FakeSysfs::new("characterize")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 45_000)).fan(1, 800).pwm(Pwm::new(1, 100).enable(5)).fan(2, 700).pwm(Pwm::new(2, 190)));
+        let read = |file: &str| std::fs::read_to_string(sysfs.chip_dir(0).join(file)).unwrap().trim().to_string();
+        let hardware = AgentConfig::default().hardware;
+        let monitor = sysfs.monitor_with(hardware.clone());
+        let options = CharacterizeOptions {
+            duties: vec![100, 50],
+            sample_interval: Duration::from_millis(150), // past the per-fan write rate limit
+            steady_window: Duration::from_millis(300),
+            max_hold: Duration::from_secs(2),
+            ..CharacterizeOptions::default()
+        };
+        let lines = std::sync::Mutex::new(Vec::new());
+        let progress = |line: String| lines.lock().unwrap().push(line);
+
+        let report = run(&monitor, &hardware, &options, &|| None, &progress).await.unwrap();
+        assert_eq!(report.aborted, None);
+        assert_eq!(report.groups.iter().map(|g| g.fans.clone()).collect::<Vec<_>>(),
+                   [vec!["nct6798_fan_1".to_string()], vec!["nct6798_fan_2".to_string()]]);
+        let levels = &report.groups[0].levels;
+        assert_eq!(levels.iter().map(|l| (l.duty, l.steady)).collect::<Vec<_>>(), [(100, true), (50, true)]);
+        assert_eq!(levels[0].temperatures["nct6798_sensor_1"], 45.0);
+        assert_eq!(levels[0].rpm["nct6798_fan_1"], Some(800));
+        // A flat temperature responded to nothing
+        assert!(report.groups[0].min_duty.is_empty());
+        assert!(lines.lock().unwrap().iter().any(|l| l.contains("50%: held")));
+        assert_eq!((read("pwm1"), read("pwm1_enable"), read("pwm2")), ("100".into(), "5".into(), "190".into()));
+
+        // Stopped during the first hold: still restored, the report says why
+        let report = run(&monitor, &hardware, &options, &|| Some("connection lost".into()), &progress).await.unwrap();
+        assert_eq!(report.aborted.as_deref(), Some("Stopped at 100%: connection lost"));
+        assert_eq!((read("pwm1"), read("pwm1_enable")), ("100".into(), "5".into()));
+
+        // Within the safety margin of the emergency threshold: never starts
+        std::fs::write(sysfs.chip_dir(0).join("temp1_input"), "78000").unwrap();
+        monitor.invalidate_cache().await;
+        let err = run(&monitor, &hardware, &options, &|| None, &progress).await.unwrap_err();
+        assert!(err.to_string().contains("Not starting: nct6798_sensor_1 at 78.0°C"), "{}", err);
+    }
+}
@@ -11,20 +11,72 @@ Options:
   -V, --version                 Print version
 Setup & Service:
   -e, --setup                   Run interactive setup wizard
+      --non-interactive         With --setup: write config.json from the flags below, no prompts
+        --server-url <URL>          Hub WebSocket URL, ws:// or wss:// (required without an existing config)
+        --agent-name <NAME>         Display name (default: hostname)
+        --update-interval <SECS>    Data interval in seconds (default: 3)
+        --enable-fan-control        Allow fan control (default)
+        --disable-fan-control       Monitoring only
+        --failsafe-speed <PCT>      Fan speed while the Hub is unreachable (default: 70)
+        --enrollment-token <TOKEN>  One-time deploy token from the Hub's Deployment page
+        --force                     Overwrite an existing config.json
+        --test                      Run the hardware test afterwards and print its JSON report
   -I, --install-service         Install systemd service for auto-start on boot
   -U, --uninstall-service       Uninstall systemd service
+      --uninstall               Stop the agent, return fans to automatic mode and remove the service,
+                                PID file and logs (asks before deleting config.json and identity.json)
+        --purge                     Also delete config.json and identity.json without asking
+      --print-udev-rules        Print udev rules granting non-root PWM write access
 Daemon Control:
   -s, --start                   Start the agent daemon in background
   -x, --stop                    Stop the agent daemon
   -r, --restart                 Restart the agent daemon
+      --systemd                 Run in the foreground as a systemd Type=notify service
 Status & Logs:
   -i, --status                  Show agent status
-  -l, --log-show [<LOG_SHOW>]   Show agent logs (tail -f by default, or tail -n <lines> if provided)
+  -l, --log-show [<LOG_SHOW>]   Show agent logs (tail -F by default, or tail -n <lines> if provided)
       --log-level <LOG_LEVEL>   Set log level (TRACE, DEBUG, INFO, WARN, ERROR). Use with --start/--restart
+      --log-format <FORMAT>     Log output format (text, json). Use with --start/--restart
 Config & Debug:
   -c, --config                  Show current configuration
+      --config-history [<N>]    Show the last configuration changes (20, or N): when, what and from where
       --check                   Run health check (verify config, service, directories)
-      --test                    Test mode (hardware discovery only)
+      --check-connection        Test the configured server URL (DNS, connect, TLS, WebSocket ping)
+      --test                    Hardware test: sensors, limits and PWM write access, with a pass/fail summary
+      --with-fan-test           With --test: briefly nudge each controllable fan and restore it
+      --characterize            Hold each fan group at fixed duties and report the settled temperatures
+                                and the lowest duty holding the target (the agent must be stopped)
+        --force-local               Run without the Hub watching (required; or use characterizeFans)
+        --target-temp <C>           Temperature the lowest duties are estimated for (default: 60)
+      --doctor                  Run every check and write a support bundle (pankha-doctor-<time>.json) here
+        --redact                    Leave the hostname, agent name and server URL credentials out of the bundle
+      --dry-run                 Log fan writes without executing them. Use with --start/--restart/--systemd
+      --clear-safe-mode         Leave safe mode (entered after repeated crashes) from the next start on
+      --json                    JSON output for --config, --config-history, --status, --test and --characterize
+                                (logs go to stderr)
+
+JSON output (--json):
+  --config   the configuration as loaded from config.json
+  --config-history
+             [{timestamp (ms), field, old_value, new_value, source, command, command_id}]
+  --status   version, arch, running, pid, uptime_secs, server_url, agent_name,
+             update_interval, log_file, last_log_time (RFC 3339)
+  --test     passed, unit, sensors, fans (as sent to the backend, Celsius), checks
+             [{device, check, result: pass|warn|fail|skip, details (in unit)}]
+  --characterize
+             started, finished (ms), aborted (reason or null), target_temp, restore_failures,
+             groups [{fans, levels [{duty, steady, held_secs, temperatures, rpm}],
+             min_duty {sensor: percent or null}}] (temperatures in Celsius)
+
+State file (agent.state_file, default /run/pankha-agent/state.json, null to turn off):
+  Rewritten atomically every data cycle or failsafe check, for external watchdogs:
+  schema_version (1), timestamp (ms), pid, connected, failsafe_active,
+  last_data_sent (ms or null), controllable_fans, last_error (string or null)
+
+Support bundle (--doctor, always JSON):
+  passed, agent_version, arch, created (RFC 3339), redacted, checks (as for --test),
+  config (config.json, credentials redacted), sensors, fans, hardware_dump, log_file,
+  log (last 200 lines)
 ";
 
 #[derive(Parser, Debug)]
@@ -39,6 +91,42 @@ pub struct Args {
     #[arg(short = 'e', long, help_heading = "Setup & Service")]
     pub setup: bool,
 
+    /// With --setup: write config.json from flags, no prompts
+    #[arg(long = "non-interactive", requires = "setup", help_heading = "Setup & Service")]
+    pub non_interactive: bool,
+
+    /// Hub WebSocket URL, ws:// or wss:// (--setup --non-interactive)
+    #[arg(long = "server-url", value_name = "URL", requires = "non_interactive", help_heading = "Setup & Service")]
+    pub server_url: Option<String>,
+
+    /// Display name, default hostname (--setup --non-interactive)
+    #[arg(long = "agent-name", value_name = "NAME", requires = "non_interactive", help_heading = "Setup & Service")]
+    pub agent_name: Option<String>,
+
+    /// Data interval in seconds (--setup --non-interactive)
+    #[arg(long = "update-interval", value_name = "SECS", requires = "non_interactive", help_heading = "Setup & Service")]
+    pub update_interval: Option<f64>,
+
+    /// Allow fan control, the default (--setup --non-interactive)
+    #[arg(long = "enable-fan-control", requires = "non_interactive", conflicts_with = "disable_fan_control", help_heading = "Setup & Service")]
+    pub enable_fan_control: bool,
+
+    /// Monitoring only (--setup --non-interactive)
+    #[arg(long = "disable-fan-control", requires = "non_interactive", help_heading = "Setup & Service")]
+    pub disable_fan_control: bool,
+
+    /// Fan speed while the Hub is unreachable (--setup --non-interactive)
+    #[arg(long = "failsafe-speed", value_name = "PCT", requires = "non_interactive", help_heading = "Setup & Service")]
+    pub failsafe_speed: Option<u8>,
+
+    /// One-time deploy token from the Hub (--setup --non-interactive)
+    #[arg(long = "enrollment-token", value_name = "TOKEN", requires = "non_interactive", help_heading = "Setup & Service")]
+    pub enrollment_token: Option<String>,
+
+    /// Overwrite an existing config.json (--setup --non-interactive)
+    #[arg(long, requires = "non_interactive", help_heading = "Setup & Service")]
+    pub force: bool,
+
     /// Install systemd service for auto-start on boot
     #[arg(short = 'I', long = "install-service", help_heading = "Setup & Service")]
     pub install_service: bool,
@@ -47,6 +135,18 @@ pub struct Args {
     #[arg(short = 'U', long = "uninstall-service", help_heading = "Setup & Service")]
     pub uninstall_service: bool,
 
+    /// Stop the agent, return fans to automatic mode and remove the service, PID file and logs
+    #[arg(long, help_heading = "Setup & Service")]
+    pub uninstall: bool,
+
+    /// With --uninstall: also delete config.json and identity.json without asking
+    #[arg(long, requires = "uninstall", help_heading = "Setup & Service")]
+    pub purge: bool,
+
+    /// Print udev rules granting non-root PWM write access
+    #[arg(long = "print-udev-rules", help_heading = "Setup & Service")]
+    pub print_udev_rules: bool,
+
     // === Daemon Control ===
     /// Start the agent daemon in background
     #[arg(short = 's', long, help_heading = "Daemon Control")]
@@ -60,12 +160,16 @@ pub struct Args {
     #[arg(short = 'r', long, help_heading = "Daemon Control")]
     pub restart: bool,
 
+    /// Run in the foreground as a systemd Type=notify service
+    #[arg(long, help_heading = "Daemon Control")]
+    pub systemd: bool,
+
     // === Status & Logs ===
     /// Show agent status
     #[arg(short = 'i', long = "status", help_heading = "Status & Logs")]
     pub status: bool,
 
-    /// Show agent logs (tail -f by default, or tail -n <lines> if provided)
+    /// Show agent logs (tail -F by default, or tail -n <lines> if provided)
     #[arg(short = 'l', long = "log-show", help_heading = "Status & Logs")]
     pub log_show: Option<Option<usize>>,
 
@@ -73,20 +177,74 @@ pub struct Args {
     #[arg(long = "log-level", help_heading = "Status & Logs")]
     pub log_level: Option<String>,
 
+    /// Log output format (text, json). Use with --start/--restart
+    #[arg(long = "log-format", value_name = "FORMAT", value_parser = ["text", "json"], help_heading = "Status & Logs")]
+    pub log_format: Option<String>,
+
     // === Config & Debug ===
     /// Show current configuration
     #[arg(short = 'c', long, help_heading = "Config & Debug")]
     pub config: bool,
 
+    /// Show the last configuration changes (20, or N): when, what and from where
+    #[arg(long = "config-history", value_name = "N", help_heading = "Config & Debug")]
+    pub config_history: Option<Option<usize>>,
+
     /// Run health check (verify config, service, directories)
     #[arg(long, help_heading = "Config & Debug")]
     pub check: bool,
 
-    /// Test mode (hardware discovery only)
+    /// Test the configured server URL (DNS, connect, TLS, WebSocket ping)
+    #[arg(long = "check-connection", help_heading = "Config & Debug")]
+    pub check_connection: bool,
+
+    /// Hardware test: sensors, limits and PWM write access, with a pass/fail summary
     #[arg(long, help_heading = "Config & Debug")]
     pub test: bool,
 
+    /// With --test: briefly nudge each controllable fan and restore it
+    #[arg(long = "with-fan-test", requires = "test", help_heading = "Config & Debug")]
+    pub with_fan_test: bool,
+
+    /// Hold each fan group at fixed duties and report the settled temperatures and
+    /// the lowest duty holding the target (the agent must be stopped)
+    #[arg(long, help_heading = "Config & Debug")]
+    pub characterize: bool,
+
+    /// With --characterize: run without the Hub watching
+    #[arg(long = "force-local", requires = "characterize", help_heading = "Config & Debug")]
+    pub force_local: bool,
+
+    /// With --characterize: temperature (°C) the lowest duties are estimated for
+    #[arg(long = "target-temp", value_name = "C", requires = "characterize", help_heading = "Config & Debug")]
+    pub target_temp: Option<f64>,
+
+    /// Run every check and write a support bundle (pankha-doctor-<time>.json) here
+    #[arg(long, help_heading = "Config & Debug")]
+    pub doctor: bool,
+
+    /// With --doctor: leave the hostname, agent name and server URL credentials out of the bundle
+    #[arg(long, requires = "doctor", help_heading = "Config & Debug")]
+    pub redact: bool,
+
+    /// Log fan writes without executing them. Use with --start/--restart/--systemd
+    #[arg(long = "dry-run", help_heading = "Config & Debug")]
+    pub dry_run: bool,
+
+    /// Leave safe mode (entered after repeated crashes) from the next start on
+    #[arg(long = "clear-safe-mode", help_heading = "Config & Debug")]
+    pub clear_safe_mode: bool,
+
+    /// JSON output for --config, --config-history, --status, --test and --characterize (logs go to stderr)
+    #[arg(long, help_heading = "Config & Debug")]
+    pub json: bool,
+
     /// Internal flag for daemon child process (do not use directly)
     #[arg(long, hide = true)]
     pub daemon_child: bool,
+
+    /// Internal flag: panic in a task after the first update interval, to test the
+    /// shutdown cleanup (do not use directly)
+    #[arg(long, hide = true)]
+    pub simulate_panic: bool,
 }
@@ -0,0 +1,213 @@
+//! `--doctor`: everything a support request needs, in one file.
+//!
+//! Validates config.json, discovers the hardware, checks PWM write access and other
+//! fan-control software (as `--test` does, without touching a fan), takes the
+//! diagnostics dump, tests the backend connection and collects the end of the agent
+//! log. The checks are printed as a table and written, with the data behind them, to
+//! `pankha-doctor-<timestamp>.json` in the current directory.
+//!
+//! Credentials (auth tokens, the webhook auth header) are always redacted. With
+//! `--redact` the bundle also loses the credentials and query of the server and proxy
+//! URLs, the hostname and the agent name, wherever they appear, log lines included
+//! (see redact.rs).
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::app::hardware_test::{check_sensor, check_write_access, print_summary, Check, Outcome};
+use crate::app::redact::{config_secrets, Redactor};
+use crate::config::persistence::load_config;
+use crate::daemon::platform::find_log_file;
+use crate::hardware::composite::CompositeHardwareMonitor;
+use crate::hardware::{HardwareMonitor, LinuxHardwareMonitor};
+use crate::websocket::connection_check::check_connection;
+use crate::websocket::transport::Route;
+
+/// Lines of the agent log included in the bundle.
+pub const LOG_LINES: usize = 200;
+
+/// The bundle. Keys are part of the CLI contract (listed in `--help`).
+#[derive(Serialize)]
+struct DoctorReport {
+    passed: bool,
+    agent_version: &'static str,
+    arch: &'static str,
+    /// RFC 3339
+    created: String,
+    redacted: bool,
+    checks: Vec<Check>,
+    /// config.json as on disk; null when it can't be parsed
+    config: Value,
+    sensors: Value,
+    fans: Value,
+    hardware_dump: Value,
+    log_file: Option<PathBuf>,
+    log: Vec<String>,
+}
+
+/// Run the checks, print them and write the bundle. Returns false if any check failed.
+pub async fn run(redact: bool) -> Result<bool> {
+    println!("\x1b[32mpankha-agent v{} ({})\x1b[0m", crate::version::VERSION, std::env::consts::ARCH);
+    println!("Doctor");
+    println!("======\n");
+
+    let mut checks = Vec::new();
+    let exe_dir = std::env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
+        .to_path_buf();
+    let config_path = exe_dir.join("config.json");
+
+    let raw_config = std::fs::read_to_string(&config_path).ok()
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .unwrap_or(Value::Null);
+    let loaded = load_config(None).await;
+    let (outcome, details) = match &loaded {
+        Ok(_) if !config_path.exists() => (Outcome::Warn, "no config.json, using defaults (run --setup)".to_string()),
+        Ok(_) => (Outcome::Pass, format!("{} is valid", config_path.display())),
+        Err(e) => (Outcome::Fail, format!("{:#}", e)),
+    };
+    checks.push(Check { device: "config".to_string(), check: "validation", outcome, details });
+    let config_ok = loaded.is_ok();
+    let config = loaded.unwrap_or_default();
+
+    // Read-only: nothing here writes a fan
+    let mut hardware = config.hardware.clone();
+    hardware.dry_run = true;
+    let monitor: Arc<dyn HardwareMonitor> = if config.backends.is_empty() {
+        Arc::new(LinuxHardwareMonitor::new(hardware.clone()))
+    } else {
+        Arc::new(CompositeHardwareMonitor::from_config(&config.backends, &hardware)?)
+    };
+
+    let sensors = monitor.discover_sensors().await.unwrap_or_else(|e| {
+        checks.push(Check { device: "sensors".to_string(), check: "discovery", outcome: Outcome::Fail, details: e.to_string() });
+        Vec::new()
+    });
+    let fans = monitor.discover_fans().await.unwrap_or_else(|e| {
+        checks.push(Check { device: "fans".to_string(), check: "discovery", outcome: Outcome::Fail, details: e.to_string() });
+        Vec::new()
+    });
+    if sensors.is_empty() && !checks.iter().any(|c| c.device == "sensors") {
+        checks.push(Check {
+            device: "sensors".to_string(),
+            check: "discovery",
+            outcome: Outcome::Fail,
+            details: "no temperature sensors found".to_string(),
+        });
+    }
+    for sensor in &sensors {
+        let (outcome, details) = check_sensor(sensor, hardware.temperature_unit);
+        checks.push(Check { device: sensor.id.to_string(), check: "reading", outcome, details });
+    }
+    for fan in &fans {
+        let (outcome, details) = check_write_access(fan, hardware.enable_fan_control);
+        checks.push(Check { device: fan.id.clone(), check: "write access", outcome, details });
+    }
+    let conflicts = monitor.fan_control_conflicts().await;
+    for conflict in &conflicts {
+        checks.push(Check {
+            device: conflict.program.clone(),
+            check: "fan control conflict",
+            outcome: Outcome::Warn,
+            details: conflict.message.clone(),
+        });
+    }
+
+    let hardware_dump = match monitor.dump_hardware_info().await {
+        Ok(dump) => {
+            let elevated = dump.metadata.is_elevated;
+            checks.push(Check {
+                device: "hardware".to_string(),
+                check: "diagnostics dump",
+                outcome: Outcome::Pass,
+                details: format!("{} device(s){}", dump.hardware.len(), if elevated { "" } else { ", not running as root" }),
+            });
+            serde_json::to_value(&dump)?
+        }
+        Err(e) => {
+            checks.push(Check { device: "hardware".to_string(), check: "diagnostics dump", outcome: Outcome::Fail, details: e.to_string() });
+            Value::Null
+        }
+    };
+
+    let (outcome, details) = if !config_ok {
+        (Outcome::Skip, "config.json is invalid".to_string())
+    } else if !config_path.exists() {
+        (Outcome::Skip, "no server URL configured".to_string())
+    } else {
+        let backend = &config.backend;
+        match check_connection(&backend.server_url, &Route::from(backend), backend.connection_timeout).await {
+            Ok(elapsed) => (Outcome::Pass, format!("handshake and ping OK ({} ms)", elapsed.as_millis())),
+            Err(e) => (Outcome::Fail, e.to_string()),
+        }
+    };
+    checks.push(Check { device: "backend".to_string(), check: "connection", outcome, details });
+
+    let log_file = find_log_file(Some(Path::new(&config.logging.log_file)));
+    let log = match &log_file {
+        Some(path) => match std::fs::read_to_string(path) {
+            Ok(content) => {
+                checks.push(Check { device: "log".to_string(), check: "log file", outcome: Outcome::Pass, details: path.display().to_string() });
+                tail(&content, LOG_LINES)
+            }
+            Err(e) => {
+                checks.push(Check { device: "log".to_string(), check: "log file", outcome: Outcome::Warn, details: format!("{}: {}", path.display(), e) });
+                Vec::new()
+            }
+        },
+        None => {
+            checks.push(Check { device: "log".to_string(), check: "log file", outcome: Outcome::Warn, details: "no log file found".to_string() });
+            Vec::new()
+        }
+    };
+
+    let passed = !checks.iter().any(|c| c.outcome == Outcome::Fail);
+    print_summary(&checks);
+    let hostname = hostname::get().ok().map(|h| h.to_string_lossy().to_string());
+    let redactor = Redactor::new(&config, hostname.as_deref(), redact);
+    let mut report = serde_json::to_value(DoctorReport {
+        passed,
+        agent_version: crate::version::VERSION,
+        arch: std::env::consts::ARCH,
+        created: chrono::Utc::now().to_rfc3339(),
+        redacted: redact,
+        checks,
+        config: config_secrets(raw_config),
+        sensors: serde_json::to_value(&sensors)?,
+        fans: serde_json::to_value(&fans)?,
+        hardware_dump,
+        log_file,
+        log,
+    })?;
+    redactor.value(&mut report);
+
+    let bundle = PathBuf::from(format!("pankha-doctor-{}.json", chrono::Local::now().format("%Y%m%d-%H%M%S")));
+    std::fs::write(&bundle, serde_json::to_string_pretty(&report)?)?;
+    println!("\nBundle written to {}{}", bundle.display(), if redact { " (redacted)" } else { "" });
+    if !redact {
+        println!("It contains the hostname and server URL; add --redact to leave them out.");
+    }
+    Ok(passed)
+}
+
+/// The last `lines` lines of `content`.
+fn tail(content: &str, lines: usize) -> Vec<String> {
+    let all: Vec<&str> = content.lines().collect();
+    all[all.len().saturating_sub(lines)..].iter().map(|l| l.to_string()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_keeps_the_last_lines() {
+        assert_eq!(tail("a\nb\nc\n", 2), vec!["b", "c"]);
+        assert_eq!(tail("a\n", 5), vec!["a"]);
+    }
+}
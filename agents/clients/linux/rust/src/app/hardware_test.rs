@@ -0,0 +1,369 @@
+//! `--test`: hardware validation pass for a new machine.
+//!
+//! Reads every sensor (value against its limits), checks PWM write access, looks for
+//! other fan-control software (fancontrol, thinkfan, ...), and with
+//! `--with-fan-test` nudges each controllable fan, confirms the tachometer follows and
+//! puts the original PWM value and `pwm_enable` mode back. Ends with a pass/fail table
+//! (or, with `--json`, one JSON document); the caller exits non-zero when anything failed.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::config::types::{HardwareSettings, TemperatureUnit};
+use crate::hardware::types::{Fan, FanSpeedOrigin, Sensor};
+use crate::hardware::HardwareMonitor;
+
+/// The fan test is skipped when any CPU sensor is at or above this (°C).
+pub const FAN_TEST_MAX_CPU_TEMP: f64 = 70.0;
+
+/// Size of the PWM nudge, in percent.
+const FAN_TEST_NUDGE_PERCENT: u8 = 20;
+
+/// Time for the fan to spin up/down before the tachometer is read again.
+const FAN_TEST_SETTLE: Duration = Duration::from_secs(5);
+
+/// Readings outside this range (°C) are reported as implausible.
+const PLAUSIBLE_TEMP: std::ops::RangeInclusive<f64> = -40.0..=150.0;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Outcome {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl Outcome {
+    fn label(self) -> &'static str {
+        match self {
+            Outcome::Pass => "\x1b[32mPASS\x1b[0m",
+            Outcome::Warn => "\x1b[33mWARN\x1b[0m",
+            Outcome::Fail => "\x1b[31mFAIL\x1b[0m",
+            Outcome::Skip => "SKIP",
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub(crate) struct Check {
+    pub(crate) device: String,
+    pub(crate) check: &'static str,
+    #[serde(rename = "result")]
+    pub(crate) outcome: Outcome,
+    pub(crate) details: String,
+}
+
+/// `--test --json` output. Keys are part of the CLI contract (listed in `--help`).
+#[derive(Serialize)]
+struct TestReport<'a> {
+    passed: bool,
+    /// Unit of the temperatures in check details; `sensors` stay Celsius
+    unit: &'static str,
+    sensors: &'a [Sensor],
+    fans: &'a [Fan],
+    checks: &'a [Check],
+}
+
+/// Run the validation and print the report. Returns false if any check failed.
+pub async fn run(monitor: &dyn HardwareMonitor, hardware: &HardwareSettings, with_fan_test: bool, json: bool) -> Result<bool> {
+    if !json {
+        println!("\x1b[32mpankha-agent v{} ({})\x1b[0m", crate::version::VERSION, std::env::consts::ARCH);
+        println!("Hardware Test");
+        println!("=============\n");
+    }
+
+    let sensors = monitor.discover_sensors().await?;
+    let fans = monitor.discover_fans().await?;
+    let mut checks = Vec::new();
+    let unit = hardware.temperature_unit;
+
+    if !json {
+        println!("Sensors ({}):", sensors.len());
+    }
+    for sensor in &sensors {
+        if !json {
+            let limit = |value: Option<f64>| value.map_or("-".to_string(), |v| format!("{:.1}", unit.convert(v)));
+            println!("  {:<32} {:<12} {:>9}  max {:>6}  crit {:>6}",
+                     sensor.id, sensor.sensor_type, unit.format(sensor.instant_temperature()),
+                     limit(sensor.max_temp), limit(sensor.crit_temp));
+        }
+        let (outcome, details) = check_sensor(sensor, unit);
+        checks.push(Check { device: sensor.id.to_string(), check: "reading", outcome, details });
+    }
+    if sensors.is_empty() {
+        checks.push(Check {
+            device: "sensors".to_string(),
+            check: "discovery",
+            outcome: Outcome::Fail,
+            details: "no temperature sensors found".to_string(),
+        });
+    }
+
+    if !json {
+        println!("\nFans ({}):", fans.len());
+    }
+    for fan in &fans {
+        if !json {
+            let rpm = fan.rpm.map_or("-".to_string(), |r| r.to_string());
+            println!("  {:<32} {:>6} RPM  {:>3}%  control: {}",
+                     fan.id, rpm, fan.speed, if fan.has_pwm_control { "yes" } else { "no" });
+        }
+        let (outcome, details) = check_write_access(fan, hardware.enable_fan_control);
+        checks.push(Check { device: fan.id.clone(), check: "write access", outcome, details });
+    }
+
+    // Another daemon writing the same PWM channels makes the fans oscillate
+    let conflicts = monitor.fan_control_conflicts().await;
+    for conflict in &conflicts {
+        if !json {
+            println!("\n\x1b[33m⚠ Conflicting fan control: {}\x1b[0m", conflict.message);
+        }
+        checks.push(Check {
+            device: conflict.program.clone(),
+            check: "fan control conflict",
+            outcome: Outcome::Warn,
+            details: conflict.message.clone(),
+        });
+    }
+    if conflicts.is_empty() && !fans.is_empty() {
+        checks.push(Check {
+            device: "fans".to_string(),
+            check: "fan control conflict",
+            outcome: Outcome::Pass,
+            details: "no other fan control software found".to_string(),
+        });
+    }
+
+    if with_fan_test {
+        if !json {
+            println!();
+        }
+        run_fan_tests(monitor, &sensors, &fans, &mut checks, unit, json).await;
+    }
+
+    let passed = !checks.iter().any(|c| c.outcome == Outcome::Fail);
+    if json {
+        let report = TestReport { passed, unit: unit.name(), sensors: &sensors, fans: &fans, checks: &checks };
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        print_summary(&checks);
+    }
+    Ok(passed)
+}
+
+/// Limits are compared in Celsius; `unit` only affects the details text.
+pub(crate) fn check_sensor(sensor: &Sensor, unit: TemperatureUnit) -> (Outcome, String) {
+    let temp = sensor.instant_temperature();
+    if !PLAUSIBLE_TEMP.contains(&temp) {
+        return (Outcome::Fail, format!("implausible reading {}", unit.format(temp)));
+    }
+    if sensor.has_crit_alarm() {
+        return (Outcome::Fail, format!("crit alarm asserted at {}", unit.format(temp)));
+    }
+    match sensor.max_temp {
+        Some(max) if max > 0.0 && temp >= max => (Outcome::Warn, format!("{} at or above max {}", unit.format(temp), unit.format(max))),
+        _ => (Outcome::Pass, unit.format(temp)),
+    }
+}
+
+pub(crate) fn check_write_access(fan: &Fan, fan_control_enabled: bool) -> (Outcome, String) {
+    if fan.has_pwm_control {
+        return (Outcome::Pass, "writable".to_string());
+    }
+    if fan.pwm_file.is_none() {
+        return (Outcome::Skip, "no PWM control on this fan".to_string());
+    }
+    let details = "no write access (run as root or see --print-udev-rules)".to_string();
+    if fan_control_enabled {
+        (Outcome::Fail, details)
+    } else {
+        (Outcome::Warn, format!("{}; fan control is disabled in config", details))
+    }
+}
+
+async fn run_fan_tests(monitor: &dyn HardwareMonitor, sensors: &[Sensor], fans: &[Fan], checks: &mut Vec<Check>, unit: TemperatureUnit, json: bool) {
+    let controllable: Vec<&Fan> = fans.iter().filter(|f| f.has_pwm_control).collect();
+    let skip_all = |checks: &mut Vec<Check>, reason: String| {
+        for fan in &controllable {
+            checks.push(Check { device: fan.id.clone(), check: "fan control", outcome: Outcome::Skip, details: reason.clone() });
+        }
+    };
+
+    if monitor.is_dry_run() {
+        skip_all(checks, "dry run: fan writes are not executed".to_string());
+        return;
+    }
+    let hottest_cpu = sensors.iter()
+        .filter(|s| &*s.sensor_type == "cpu")
+        .map(|s| s.instant_temperature())
+        .fold(f64::NEG_INFINITY, f64::max);
+    if hottest_cpu >= FAN_TEST_MAX_CPU_TEMP {
+        if !json {
+            println!("\x1b[33mFan test skipped: CPU at {} (limit {})\x1b[0m",
+                     unit.format(hottest_cpu), unit.format_whole(FAN_TEST_MAX_CPU_TEMP));
+        }
+        skip_all(checks, format!("CPU at {}, at or above {}", unit.format(hottest_cpu), unit.format_whole(FAN_TEST_MAX_CPU_TEMP)));
+        return;
+    }
+
+    for (i, fan) in controllable.iter().enumerate() {
+        if !json {
+            println!("Testing {} ...", fan.id);
+        }
+        let (outcome, details, interrupted) = exercise_fan(monitor, fan).await;
+        checks.push(Check { device: fan.id.clone(), check: "fan control", outcome, details });
+        if interrupted {
+            for fan in &controllable[i + 1..] {
+                checks.push(Check {
+                    device: fan.id.clone(),
+                    check: "fan control",
+                    outcome: Outcome::Skip,
+                    details: "interrupted".to_string(),
+                });
+            }
+            break;
+        }
+    }
+}
+
+/// Nudge one fan, read its tachometer, restore it. The original state is put back
+/// whatever happened in between, including Ctrl+C during the settle wait.
+async fn exercise_fan(monitor: &dyn HardwareMonitor, fan: &Fan) -> (Outcome, String, bool) {
+    let original = match monitor.fan_control_state(&fan.id).await {
+        Ok(Some(state)) => state,
+        Ok(None) => return (Outcome::Skip, "original state can't be saved for this fan".to_string(), false),
+        Err(e) => return (Outcome::Fail, format!("reading current state: {}", e), false),
+    };
+    let baseline = current_rpm(monitor, &fan.id).await.or(fan.rpm);
+    // As discovered, whatever the control method
+    let original_percent = fan.speed.min(100);
+    let target = if original_percent <= 100 - FAN_TEST_NUDGE_PERCENT {
+        original_percent + FAN_TEST_NUDGE_PERCENT
+    } else {
+        original_percent - FAN_TEST_NUDGE_PERCENT
+    };
+
+    // Writes closer together than the per-fan rate limit are dropped silently
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let write = monitor.set_fan_speed(&fan.id, target, &FanSpeedOrigin::LocalCli).await;
+    let mut interrupted = false;
+    let after = if write.is_ok() {
+        tokio::select! {
+            _ = tokio::time::sleep(FAN_TEST_SETTLE) => {}
+            _ = tokio::signal::ctrl_c() => interrupted = true,
+        }
+        current_rpm(monitor, &fan.id).await
+    } else {
+        None
+    };
+
+    let restored = monitor.restore_fan_control_state(&fan.id, &original).await;
+    let verified = match monitor.fan_control_state(&fan.id).await {
+        // In an automatic mode the chip moves the value itself; only the mode must match
+        Ok(Some(now)) => now.pwm_enable == original.pwm_enable
+            && (!matches!(original.pwm_enable.as_deref(), None | Some("1")) || now.value == original.value),
+        _ => false,
+    };
+    let restore_note = match (&restored, verified) {
+        (Ok(()), true) => "restored".to_string(),
+        (Ok(()), false) => "restore could not be verified".to_string(),
+        (Err(e), _) => format!("RESTORE FAILED: {}", e),
+    };
+
+    if let Err(e) = write {
+        return (Outcome::Fail, format!("write {}% failed: {}; {}", target, e, restore_note), interrupted);
+    }
+    if interrupted {
+        return (Outcome::Skip, format!("interrupted; {}", restore_note), true);
+    }
+    let mut outcome = match (baseline, after) {
+        (Some(before), Some(after)) if rpm_responded(before, after, target > original_percent) => Outcome::Pass,
+        (Some(_), Some(_)) => Outcome::Fail,
+        _ => Outcome::Pass, // no tachometer: the accepted write is all we can check
+    };
+    if restored.is_err() || !verified {
+        outcome = Outcome::Fail;
+    }
+    let rpm = match (baseline, after) {
+        (Some(before), Some(after)) => format!("{} -> {} RPM", before, after),
+        _ => "no tachometer".to_string(),
+    };
+    (outcome, format!("{}% -> {}%: {}; {}", original_percent, target, rpm, restore_note), false)
+}
+
+async fn current_rpm(monitor: &dyn HardwareMonitor, fan_id: &str) -> Option<u32> {
+    monitor.discover_fans().await.ok()?
+        .into_iter()
+        .find(|f| f.id == fan_id)?
+        .rpm
+}
+
+/// The tachometer moved in the commanded direction by a meaningful amount.
+fn rpm_responded(before: u32, after: u32, increased: bool) -> bool {
+    let threshold = (before / 20).max(60);
+    if increased {
+        after >= before.saturating_add(threshold)
+    } else {
+        after.saturating_add(threshold) <= before
+    }
+}
+
+pub(crate) fn print_summary(checks: &[Check]) {
+    let failed = checks.iter().filter(|c| c.outcome == Outcome::Fail).count();
+    let warned = checks.iter().filter(|c| c.outcome == Outcome::Warn).count();
+
+    println!("\nSummary");
+    println!("-------");
+    println!("  {:<32} {:<13} {:<6} Details", "Device", "Check", "Result");
+    for check in checks {
+        println!("  {:<32} {:<13} {}   {}", check.device, check.check, check.outcome.label(), check.details);
+    }
+
+    println!();
+    if failed == 0 && warned == 0 {
+        println!("\x1b[32m✓ All checks passed!\x1b[0m");
+    } else if failed == 0 {
+        println!("\x1b[33m⚠ {} warning(s) - see above\x1b[0m", warned);
+    } else {
+        println!("\x1b[31m✗ {} check(s) failed - see above\x1b[0m", failed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rpm_must_move_in_the_commanded_direction() {
+        assert!(rpm_responded(1000, 1300, true));
+        assert!(!rpm_responded(1000, 1030, true)); // within noise
+        assert!(!rpm_responded(1000, 700, true));
+        assert!(rpm_responded(2000, 1700, false));
+        assert!(!rpm_responded(0, 0, true)); // stopped or unplugged
+    }
+
+    #[test]
+    fn sensor_checks() {
+        let sensor = |temperature: f64, max: Option<f64>, alarms: &[&str]| -> Sensor {
+            serde_json::from_value(serde_json::json!({
+                "id": "k10temp_tctl", "name": "Tctl", "temperature": temperature, "type": "cpu",
+                "max_temp": max, "alarms": alarms,
+            }))
+            .unwrap()
+        };
+
+        let celsius = TemperatureUnit::Celsius;
+        assert_eq!(check_sensor(&sensor(45.0, Some(95.0), &[]), celsius).0, Outcome::Pass);
+        assert_eq!(check_sensor(&sensor(96.0, Some(95.0), &[]), celsius).0, Outcome::Warn);
+        assert_eq!(check_sensor(&sensor(-273.0, None, &[]), celsius).0, Outcome::Fail);
+        assert_eq!(check_sensor(&sensor(101.0, None, &["crit"]), celsius).0, Outcome::Fail);
+
+        // Fahrenheit changes the text, not the verdict
+        let (outcome, details) = check_sensor(&sensor(96.0, Some(95.0), &[]), TemperatureUnit::Fahrenheit);
+        assert_eq!(outcome, Outcome::Warn);
+        assert_eq!(details, "204.8°F at or above max 203.0°F");
+    }
+}
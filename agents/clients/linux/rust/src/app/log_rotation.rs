@@ -0,0 +1,289 @@
+//! Size-based log rotation and retention pruning for the daemon's log file.
+//!
+//! The daemon child starts with stdout/stderr redirected to the log file by the parent.
+//! Once config is loaded, `install()` routes tracing output through a `RotatingFile`
+//! that rolls `agent.log` -> `agent.log.1` -> `agent.log.2` ... when it outgrows
+//! `max_log_size_mb`. If stdout/stderr point at the same file, they are re-pointed at
+//! the fresh file on each rotation so panics and stray prints follow it too.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime};
+
+use crate::config::types::LoggingSettings;
+
+/// Rotated files kept regardless of age, so a burst can't fill the disk.
+const MAX_ROTATED_FILES: u32 = 10;
+
+/// Active log file, set once file logging is configured. `None` = write to stdout.
+static LOG_FILE: Mutex<Option<RotatingFile>> = Mutex::new(None);
+
+/// Held while rotated files are renamed or compressed, so the background gzip never
+/// works on a file the next rotation is moving.
+static ROTATION: Mutex<()> = Mutex::new(());
+
+/// Without a log file, log to stderr instead of stdout (`--json` keeps stdout parseable).
+static LOG_TO_STDERR: AtomicBool = AtomicBool::new(false);
+
+/// Send log lines to stderr until a log file is installed.
+pub fn log_to_stderr() {
+    LOG_TO_STDERR.store(true, Ordering::Relaxed);
+}
+
+pub struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+    compress: bool,
+    /// stdout/stderr were redirected to this file by the parent process
+    redirect_stdio: bool,
+}
+
+impl RotatingFile {
+    fn open(settings: &LoggingSettings) -> io::Result<Self> {
+        let path = PathBuf::from(&settings.log_file);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let meta = file.metadata()?;
+
+        // Same inode as fd 1 means the parent's redirect targets this file
+        let redirect_stdio = fs::metadata("/proc/self/fd/1")
+            .map(|m| m.dev() == meta.dev() && m.ino() == meta.ino())
+            .unwrap_or(false);
+
+        Ok(Self {
+            path,
+            file,
+            size: meta.len(),
+            max_bytes: settings.max_log_size_mb.max(1) as u64 * 1024 * 1024,
+            compress: settings.compress_rotated_logs,
+            redirect_stdio,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _rotation = ROTATION.lock().unwrap_or_else(|e| e.into_inner());
+
+        // Shift agent.log.N[.gz] -> agent.log.N+1[.gz], dropping the oldest
+        for n in (1..=MAX_ROTATED_FILES).rev() {
+            for suffix in ["", ".gz"] {
+                let from = rotated_path(&self.path, n, suffix);
+                if !from.exists() {
+                    continue;
+                }
+                if n == MAX_ROTATED_FILES {
+                    let _ = fs::remove_file(&from);
+                } else {
+                    let _ = fs::rename(&from, rotated_path(&self.path, n + 1, suffix));
+                }
+            }
+        }
+
+        let rotated = rotated_path(&self.path, 1, "");
+        fs::rename(&self.path, &rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.size = 0;
+
+        if self.redirect_stdio {
+            unsafe {
+                libc::dup2(self.file.as_raw_fd(), libc::STDOUT_FILENO);
+                libc::dup2(self.file.as_raw_fd(), libc::STDERR_FILENO);
+            }
+        }
+
+        if self.compress {
+            // Off the logging path
+            let path = self.path.clone();
+            std::thread::spawn(move || compress_rotated(&path));
+        }
+        Ok(())
+    }
+}
+
+/// gzip every rotated file not compressed yet (agent.log.N -> agent.log.N.gz), under
+/// the rotation lock. Also picks up files an earlier run left uncompressed.
+fn compress_rotated(path: &Path) {
+    let _rotation = ROTATION.lock().unwrap_or_else(|e| e.into_inner());
+    for n in 1..=MAX_ROTATED_FILES {
+        let rotated = rotated_path(path, n, "");
+        if !rotated.exists() {
+            continue;
+        }
+        let _ = std::process::Command::new("gzip")
+            .arg("-f")
+            .arg(&rotated)
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status();
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.file.write(buf)?;
+        self.size += n as u64;
+        if self.size >= self.max_bytes {
+            if let Err(e) = self.rotate() {
+                // Keep logging to the current file rather than losing output
+                let _ = writeln!(self.file, "Log rotation failed: {}", e);
+            }
+        }
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+fn rotated_path(path: &Path, n: u32, suffix: &str) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(format!(".{}{}", n, suffix));
+    PathBuf::from(name)
+}
+
+/// Route tracing output to `settings.log_file` with rotation. Call after config load.
+pub fn install(settings: &LoggingSettings) -> io::Result<()> {
+    let file = RotatingFile::open(settings)?;
+    *LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()) = Some(file);
+    Ok(())
+}
+
+/// Delete rotated log files (`<log_file>.N[.gz]`) older than `log_retention_days`.
+/// Returns how many were removed.
+pub fn prune_rotated_logs(settings: &LoggingSettings) -> usize {
+    let path = Path::new(&settings.log_file);
+    let (Some(dir), Some(base)) = (path.parent(), path.file_name()) else {
+        return 0;
+    };
+    let prefix = format!("{}.", base.to_string_lossy());
+    let max_age = Duration::from_secs(settings.log_retention_days as u64 * 24 * 60 * 60);
+
+    let Ok(entries) = fs::read_dir(dir) else {
+        return 0;
+    };
+    let mut removed = 0;
+    for entry in entries.filter_map(Result::ok) {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let is_rotated = name.strip_prefix(&prefix)
+            .map(|rest| rest.trim_end_matches(".gz").parse::<u32>().is_ok())
+            .unwrap_or(false);
+        if !is_rotated {
+            continue;
+        }
+        let age = entry.metadata().and_then(|m| m.modified()).ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok());
+        if age.is_some_and(|age| age > max_age) && fs::remove_file(entry.path()).is_ok() {
+            removed += 1;
+        }
+    }
+    removed
+}
+
+/// `MakeWriter` for the fmt layer: the rotating file once installed, stdout (or
+/// stderr, see `log_to_stderr`) before.
+pub struct LogWriter;
+
+impl<'a> tracing_subscriber::fmt::MakeWriter<'a> for LogWriter {
+    type Writer = LogWriter;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        LogWriter
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            Some(file) => file.write(buf),
+            None if LOG_TO_STDERR.load(Ordering::Relaxed) => io::stderr().write(buf),
+            None => io::stdout().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match LOG_FILE.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            Some(file) => file.flush(),
+            None if LOG_TO_STDERR.load(Ordering::Relaxed) => io::stderr().flush(),
+            None => io::stdout().flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn settings(dir: &Path) -> LoggingSettings {
+        LoggingSettings {
+            enable_file_logging: true,
+            log_file: dir.join("agent.log").to_string_lossy().to_string(),
+            max_log_size_mb: 1,
+            log_retention_days: 7,
+            compress_rotated_logs: false,
+            log_format: "text".to_string(),
+            repeated_error_interval: 600.0,
+        }
+    }
+
+    #[test]
+    fn rotates_when_size_limit_is_reached() {
+        let dir = std::env::temp_dir().join(format!("pankha-logrotate-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let settings = settings(&dir);
+        let mut log = RotatingFile::open(&settings).unwrap();
+
+        let chunk = vec![b'x'; 512 * 1024];
+        for _ in 0..5 {
+            log.write_all(&chunk).unwrap();
+        }
+
+        // 2.5 MB at 1 MB per file: two rotations, active file holds the rest
+        let path = Path::new(&settings.log_file);
+        assert_eq!(fs::metadata(path).unwrap().len(), 512 * 1024);
+        assert!(rotated_path(path, 1, "").exists());
+        assert!(rotated_path(path, 2, "").exists());
+        assert!(!rotated_path(path, 3, "").exists());
+
+        // Fresh rotated files are within retention; unrelated files are never touched
+        fs::write(dir.join("agent.log.bak"), "keep").unwrap();
+        assert_eq!(prune_rotated_logs(&settings), 0);
+        assert_eq!(prune_rotated_logs(&LoggingSettings { log_retention_days: 0, ..settings.clone() }), 2);
+        assert!(dir.join("agent.log.bak").exists());
+        assert!(path.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn compression_covers_every_rotated_file() {
+        let dir = std::env::temp_dir().join(format!("pankha-logrotate-gz-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let settings = LoggingSettings { compress_rotated_logs: true, ..settings(&dir) };
+        let mut log = RotatingFile::open(&settings).unwrap();
+
+        let chunk = vec![b'x'; 512 * 1024];
+        for _ in 0..5 {
+            log.write_all(&chunk).unwrap();
+        }
+        // Whichever compression runs last finds nothing left to do
+        compress_rotated(Path::new(&settings.log_file));
+        let path = Path::new(&settings.log_file);
+        for n in 1..=2 {
+            assert!(rotated_path(path, n, ".gz").exists());
+            assert!(!rotated_path(path, n, "").exists());
+        }
+        assert!(!rotated_path(path, 3, ".gz").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,187 @@
+//! Suppression of recurring identical errors.
+//!
+//! A failing device (USB hub unplugged, EC gone unresponsive) fails the same read or
+//! write every cycle. The first failure at a site is logged in full; repeats of the
+//! same message are only counted and summarized once per `logging.repeated_error_interval`,
+//! and the first success afterwards is logged as a recovery. Callers pick the level.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// `logging.repeated_error_interval`, in milliseconds. Process-wide like the log
+/// format, applied once the config is loaded.
+static SUMMARY_INTERVAL_MS: AtomicU64 = AtomicU64::new(600_000);
+
+/// Set the summary interval of every throttle. Zero logs every occurrence.
+pub fn set_summary_interval(interval: Duration) {
+    SUMMARY_INTERVAL_MS.store(interval.as_millis() as u64, Ordering::Relaxed);
+}
+
+struct Recurring {
+    message: String,
+    first_seen: Instant,
+    /// Start of the current summary window
+    window_start: Instant,
+    /// Repeats not logged yet
+    suppressed: u64,
+    total: u64,
+}
+
+/// Recurring errors keyed by site + path (e.g. `pwm_write:/sys/class/hwmon/hwmon3/pwm1`).
+#[derive(Default)]
+pub(crate) struct LogThrottle {
+    entries: Mutex<HashMap<String, Recurring>>,
+    /// Overrides the process-wide interval (tests)
+    interval: Option<Duration>,
+}
+
+impl LogThrottle {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    #[cfg(test)]
+    fn with_interval(interval: Duration) -> Self {
+        Self { interval: Some(interval), ..Self::default() }
+    }
+
+    fn interval(&self) -> Duration {
+        self.interval
+            .unwrap_or_else(|| Duration::from_millis(SUMMARY_INTERVAL_MS.load(Ordering::Relaxed)))
+    }
+
+    /// Record a failure at `key`. Returns the line to log: `message` the first time
+    /// (or when it differs from the last one), a summary once the interval has passed
+    /// with repeats pending, else None.
+    pub(crate) fn failed(&self, key: &str, message: impl Into<String>) -> Option<String> {
+        self.failed_at(key, message.into(), Instant::now())
+    }
+
+    fn failed_at(&self, key: &str, message: String, now: Instant) -> Option<String> {
+        let interval = self.interval();
+        let mut entries = self.entries.lock().unwrap();
+        let Some(entry) = entries.get_mut(key) else {
+            entries.insert(key.to_string(), Recurring {
+                message: message.clone(),
+                first_seen: now,
+                window_start: now,
+                suppressed: 0,
+                total: 1,
+            });
+            return Some(message);
+        };
+
+        entry.total += 1;
+        if entry.message != message {
+            let line = match entry.suppressed {
+                0 => message.clone(),
+                n => format!("{} (previous error repeated {} times before this one)", message, n),
+            };
+            entry.message = message;
+            entry.window_start = now;
+            entry.suppressed = 0;
+            return Some(line);
+        }
+
+        entry.suppressed += 1;
+        let elapsed = now.duration_since(entry.window_start);
+        if elapsed < interval {
+            return None;
+        }
+        let line = format!(
+            "{} (previous error repeated {} times in the last {})",
+            entry.message, entry.suppressed, format_elapsed(elapsed)
+        );
+        entry.window_start = now;
+        entry.suppressed = 0;
+        Some(line)
+    }
+
+    /// Record a success at `key`. Returns the recovery line if it had been failing.
+    pub(crate) fn recovered(&self, key: &str) -> Option<String> {
+        self.recovered_at(key, Instant::now())
+    }
+
+    fn recovered_at(&self, key: &str, now: Instant) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        let entry = entries.remove(key)?;
+        Some(format!(
+            "recovered after {} failure(s) over {}",
+            entry.total, format_elapsed(now.duration_since(entry.first_seen))
+        ))
+    }
+}
+
+/// "45s", "10m", "2h 5m"
+fn format_elapsed(elapsed: Duration) -> String {
+    let secs = elapsed.as_secs();
+    match (secs / 3600, secs % 3600 / 60) {
+        (0, 0) => format!("{}s", secs),
+        (0, m) => format!("{}m", m),
+        (h, 0) => format!("{}h", h),
+        (h, m) => format!("{}h {}m", h, m),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = "pwm_write:/sys/class/hwmon/hwmon3/pwm1";
+    const EIO: &str = "Failed to write PWM for fan it8689_fan_1: Input/output error (os error 5)";
+
+    #[test]
+    fn repeats_are_counted_and_summarized_once_per_interval() {
+        let throttle = LogThrottle::with_interval(Duration::from_secs(600));
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        assert_eq!(throttle.failed_at(KEY, EIO.into(), at(0)).as_deref(), Some(EIO));
+        // Every 3s cycle for the next ten minutes is only counted
+        for cycle in 1..200 {
+            assert_eq!(throttle.failed_at(KEY, EIO.into(), at(cycle * 3)), None);
+        }
+        assert_eq!(
+            throttle.failed_at(KEY, EIO.into(), at(600)).unwrap(),
+            format!("{} (previous error repeated 200 times in the last 10m)", EIO)
+        );
+        // The window starts over after a summary
+        assert_eq!(throttle.failed_at(KEY, EIO.into(), at(603)), None);
+        assert_eq!(throttle.failed_at(KEY, EIO.into(), at(1203)).unwrap(),
+                   format!("{} (previous error repeated 2 times in the last 10m)", EIO));
+
+        assert_eq!(throttle.recovered_at(KEY, at(1206)).unwrap(), "recovered after 203 failure(s) over 20m");
+        // Nothing pending any more: a success is silent, the next failure is logged in full
+        assert_eq!(throttle.recovered_at(KEY, at(1209)), None);
+        assert_eq!(throttle.failed_at(KEY, EIO.into(), at(1212)).as_deref(), Some(EIO));
+    }
+
+    #[test]
+    fn keys_are_independent_and_a_new_message_is_logged_in_full() {
+        let throttle = LogThrottle::with_interval(Duration::from_secs(600));
+        let now = Instant::now();
+        let other = "rpm_read:/sys/class/hwmon/hwmon3/fan2_input";
+
+        assert!(throttle.failed_at(KEY, EIO.into(), now).is_some());
+        assert!(throttle.failed_at(other, "Failed to read fan2_input".into(), now).is_some());
+        assert!(throttle.failed_at(KEY, EIO.into(), now).is_none());
+        assert!(throttle.failed_at(KEY, EIO.into(), now).is_none());
+
+        let enodev = "Failed to write PWM for fan it8689_fan_1: No such device (os error 19)";
+        assert_eq!(throttle.failed_at(KEY, enodev.into(), now).unwrap(),
+                   format!("{} (previous error repeated 2 times before this one)", enodev));
+        assert!(throttle.recovered_at(other, now).is_some());
+        assert_eq!(throttle.recovered_at(KEY, now).unwrap(), "recovered after 4 failure(s) over 0s");
+    }
+
+    #[test]
+    fn zero_interval_logs_every_occurrence() {
+        let throttle = LogThrottle::with_interval(Duration::ZERO);
+        let now = Instant::now();
+        assert_eq!(throttle.failed_at(KEY, EIO.into(), now).as_deref(), Some(EIO));
+        assert!(throttle.failed_at(KEY, EIO.into(), now).unwrap().contains("repeated 1 times"));
+        assert_eq!(format_elapsed(Duration::from_secs(7500)), "2h 5m");
+    }
+}
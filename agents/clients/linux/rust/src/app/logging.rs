@@ -1,11 +1,24 @@
 //! Tracing subscriber setup, custom formatters, dynamic log level reload.
 
-use tracing_subscriber::{reload, EnvFilter};
+use tracing_subscriber::layer::{Layered, SubscriberExt};
+use tracing_subscriber::{reload, EnvFilter, Layer, Registry};
+
+use super::log_rotation::LogWriter;
+use super::redact::RedactingWriter;
 
 // Global reload handle for dynamic log level changes
-pub type ReloadHandle = reload::Handle<EnvFilter, tracing_subscriber::Registry>;
+pub type ReloadHandle = reload::Handle<EnvFilter, Registry>;
 pub static RELOAD_HANDLE: std::sync::OnceLock<ReloadHandle> = std::sync::OnceLock::new();
 
+// Output format layer sits above the filter, so it is swappable independently of the level
+type FilteredRegistry = Layered<reload::Layer<EnvFilter, Registry>, Registry>;
+type FormatLayer = Box<dyn Layer<FilteredRegistry> + Send + Sync>;
+pub type FormatHandle = reload::Handle<FormatLayer, FilteredRegistry>;
+pub static FORMAT_HANDLE: std::sync::OnceLock<FormatHandle> = std::sync::OnceLock::new();
+
+/// Valid `log_format` values
+pub const LOG_FORMATS: &[&str] = &["text", "json"];
+
 // Custom time formatter for logs: "YYYY-MM-DD HH:MM:SS" (local time)
 pub struct LocalTimeFormatter;
 
@@ -73,26 +86,65 @@ where
     }
 }
 
-/// Initialize the tracing subscriber with reload capability.
-/// Returns the filter string used, so main can log it.
-pub fn init_tracing(filter: &str) {
-    use tracing_subscriber::prelude::*;
+/// Build the output layer: "json" = one object per line for log pipelines
+/// (RFC3339 UTC timestamp, level, message, target, event fields; no ANSI),
+/// anything else = the human-readable colored format. Credentials are redacted from
+/// every line (see redact.rs).
+fn format_layer(format: &str) -> FormatLayer {
+    format_layer_with(format, RedactingWriter(LogWriter))
+}
+
+/// `format_layer` writing to `writer`.
+pub(crate) fn format_layer_with<W>(format: &str, writer: W) -> FormatLayer
+where
+    W: for<'a> tracing_subscriber::fmt::MakeWriter<'a> + Send + Sync + 'static,
+{
+    if format == "json" {
+        tracing_subscriber::fmt::layer()
+            .json()
+            .with_timer(tracing_subscriber::fmt::time::UtcTime::rfc_3339())
+            .with_target(true)
+            .flatten_event(true)
+            .with_current_span(false)
+            .with_span_list(false)
+            .with_ansi(false)
+            .with_writer(writer)
+            .boxed()
+    } else {
+        tracing_subscriber::fmt::layer()
+            .with_timer(LocalTimeFormatter)
+            .with_target(false) // Hide the target (crate name)
+            .with_level(true)   // Show level
+            .fmt_fields(tracing_subscriber::fmt::format::DefaultFields::new())
+            .event_format(CustomEventFormat)
+            .with_writer(writer)
+            .boxed()
+    }
+}
+
+/// Initialize the tracing subscriber with reload capability for both level and format.
+pub fn init_tracing(filter: &str, format: &str) {
+    use tracing_subscriber::util::SubscriberInitExt;
 
     let env_filter = EnvFilter::new(filter);
     let (filter_layer, reload_handle) = reload::Layer::new(env_filter);
+    let (output_layer, format_handle) = reload::Layer::new(format_layer(format));
 
     tracing_subscriber::registry()
         .with(filter_layer)
-        .with(
-            tracing_subscriber::fmt::layer()
-                .with_timer(LocalTimeFormatter)
-                .with_target(false) // Hide the target (crate name)
-                .with_level(true)   // Show level
-                .fmt_fields(tracing_subscriber::fmt::format::DefaultFields::new())
-                .event_format(CustomEventFormat)
-        )
+        .with(output_layer)
         .init();
 
-    // Store reload handle in the global static for signal handler access
+    // Store reload handles in the global statics for signal handler access
     let _ = RELOAD_HANDLE.set(reload_handle);
+    let _ = FORMAT_HANDLE.set(format_handle);
+}
+
+/// Switch the output format at runtime (e.g. to the config file's `log_format`).
+pub fn set_log_format(format: &str) {
+    if let Some(handle) = FORMAT_HANDLE.get() {
+        if let Err(e) = handle.reload(format_layer(format)) {
+            eprintln!("Failed to switch log format: {}", e);
+        }
+    }
 }
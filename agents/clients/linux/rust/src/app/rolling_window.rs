@@ -0,0 +1,95 @@
+//! Measurements over a trailing time window, summarized as percentiles.
+//!
+//! Used for connection latencies: each sample is kept with the time it was taken and
+//! dropped once it is older than the window. Memory is bounded by `capacity`; past
+//! it the oldest samples go first, so a flood of samples shortens the window instead
+//! of growing it.
+
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Percentiles of the samples in a window (nearest-rank).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Percentiles {
+    pub(crate) p50: f64,
+    pub(crate) p95: f64,
+    pub(crate) samples: usize,
+}
+
+#[derive(Debug)]
+pub(crate) struct RollingWindow {
+    window: Duration,
+    capacity: usize,
+    samples: VecDeque<(Instant, f64)>,
+}
+
+impl RollingWindow {
+    pub(crate) fn new(window: Duration, capacity: usize) -> Self {
+        Self { window, capacity: capacity.max(1), samples: VecDeque::new() }
+    }
+
+    pub(crate) fn record(&mut self, now: Instant, value: f64) {
+        self.expire(now);
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back((now, value));
+    }
+
+    /// Percentiles of the samples taken within the window before `now`; None without any.
+    pub(crate) fn percentiles(&mut self, now: Instant) -> Option<Percentiles> {
+        self.expire(now);
+        if self.samples.is_empty() {
+            return None;
+        }
+        let mut values: Vec<f64> = self.samples.iter().map(|(_, v)| *v).collect();
+        values.sort_by(f64::total_cmp);
+        let rank = |p: f64| values[((p * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+        Some(Percentiles { p50: rank(0.5), p95: rank(0.95), samples: values.len() })
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while self.samples.front().is_some_and(|(at, _)| now.saturating_duration_since(*at) > self.window) {
+            self.samples.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn percentiles_of_the_samples_in_the_window() {
+        let start = Instant::now();
+        let mut window = RollingWindow::new(Duration::from_secs(300), 1000);
+        assert_eq!(window.percentiles(start), None);
+
+        for ms in 1..=100 {
+            window.record(start, ms as f64);
+        }
+        assert_eq!(window.percentiles(start), Some(Percentiles { p50: 50.0, p95: 95.0, samples: 100 }));
+
+        // A latency spike a minute later: the p95 sees it, the median barely moves
+        for _ in 0..10 {
+            window.record(start + Duration::from_secs(60), 900.0);
+        }
+        let p = window.percentiles(start + Duration::from_secs(60)).unwrap();
+        assert_eq!((p.p50, p.p95, p.samples), (55.0, 900.0, 110));
+
+        // Five minutes on, only the spike is left
+        let p = window.percentiles(start + Duration::from_secs(301)).unwrap();
+        assert_eq!((p.p50, p.p95, p.samples), (900.0, 900.0, 10));
+        assert_eq!(window.percentiles(start + Duration::from_secs(361)), None);
+    }
+
+    #[test]
+    fn capacity_drops_the_oldest_samples() {
+        let now = Instant::now();
+        let mut window = RollingWindow::new(Duration::from_secs(300), 3);
+        for value in [100.0, 1.0, 2.0, 3.0] {
+            window.record(now, value);
+        }
+        assert_eq!(window.percentiles(now), Some(Percentiles { p50: 2.0, p95: 3.0, samples: 3 }));
+    }
+}
@@ -0,0 +1,63 @@
+//! Suspend/resume detection.
+//!
+//! CLOCK_MONOTONIC, behind `Instant` and tokio timers, stands still while the machine
+//! is suspended; CLOCK_BOOTTIME keeps counting. Their difference is the time spent
+//! suspended since boot, so growth between two checks means the machine slept. Unlike
+//! a wall-clock comparison this ignores NTP steps and manual clock changes, and unlike
+//! logind's PrepareForSleep it needs no D-Bus (containers, non-systemd hosts).
+
+use std::time::Duration;
+
+/// Less than this between two checks is clock-read jitter, not a suspend.
+const MIN_SUSPEND: Duration = Duration::from_secs(2);
+
+pub(crate) struct ResumeDetector {
+    /// Suspended time since boot at the last check
+    suspended: Duration,
+}
+
+impl ResumeDetector {
+    pub(crate) fn new() -> Self {
+        Self { suspended: suspended_since_boot() }
+    }
+
+    /// How long the machine was suspended since the last check, if it was.
+    pub(crate) fn check(&mut self) -> Option<Duration> {
+        self.observe(suspended_since_boot())
+    }
+
+    fn observe(&mut self, suspended: Duration) -> Option<Duration> {
+        let slept = suspended.saturating_sub(self.suspended);
+        self.suspended = suspended;
+        (slept >= MIN_SUSPEND).then_some(slept)
+    }
+}
+
+fn suspended_since_boot() -> Duration {
+    clock(libc::CLOCK_BOOTTIME).saturating_sub(clock(libc::CLOCK_MONOTONIC))
+}
+
+fn clock(id: libc::clockid_t) -> Duration {
+    let mut ts = libc::timespec { tv_sec: 0, tv_nsec: 0 };
+    if unsafe { libc::clock_gettime(id, &mut ts) } != 0 {
+        return Duration::ZERO;
+    }
+    Duration::new(ts.tv_sec as u64, ts.tv_nsec as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_a_jump_in_suspended_time_is_a_resume() {
+        let mut detector = ResumeDetector { suspended: Duration::from_secs(100) };
+        assert_eq!(detector.observe(Duration::from_millis(100_001)), None);
+        assert_eq!(detector.observe(Duration::from_secs(3700)), Some(Duration::from_millis(3_599_999)));
+        assert_eq!(detector.observe(Duration::from_secs(3700)), None);
+
+        // Nothing slept on a running machine
+        let mut live = ResumeDetector::new();
+        assert_eq!(live.check(), None);
+    }
+}
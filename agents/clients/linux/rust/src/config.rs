@@ -1,6 +1,11 @@
 //! Configuration module re-exports.
 
 pub mod types;
+pub mod handle;
+pub mod history;
+pub mod identity;
 pub mod persistence;
+pub mod legacy;
 pub mod sst;
+pub mod units;
 pub mod setup;
@@ -0,0 +1,280 @@
+//! Shared agent configuration: lock-free snapshots for readers, coalesced saves.
+//!
+//! The data sender reads the config several times per cycle while backend commands
+//! change it. Readers take an `Arc<AgentConfig>` snapshot and never wait, not even
+//! for a save in progress. Writers are serialized among themselves, publish a changed
+//! copy and leave config.json to a background save that always writes the newest
+//! snapshot. The save waits `agent.config_save_delay` after the first unsaved change,
+//! so a burst of changes (the configuration block of a `registered` message, a
+//! slider dragged in the dashboard) ends up as a single write. Shutdown and
+//! self-update `flush` whatever is still waiting. Every update is recorded in the
+//! change history (history.rs) as it is published.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use arc_swap::ArcSwap;
+use tracing::{debug, error};
+
+use crate::app::redact;
+
+use super::history::{ConfigChange, ConfigHistory};
+use super::persistence::{save_config, save_config_blocking};
+use super::types::AgentConfig;
+
+pub(crate) struct ConfigHandle {
+    current: ArcSwap<AgentConfig>,
+    /// Generation of `current`, bumped by every update. Held while a writer builds
+    /// its copy, so concurrent updates can't drop each other's changes.
+    generation: Mutex<u64>,
+    /// config.json; None keeps changes in memory only
+    path: Option<PathBuf>,
+    /// Generation last written to `path`. Held for the duration of a save.
+    saved: tokio::sync::Mutex<u64>,
+    /// A background save is waiting out the save delay
+    save_scheduled: AtomicBool,
+    writes: AtomicUsize,
+    /// Beside `path`; None along with it
+    history: Option<ConfigHistory>,
+}
+
+impl ConfigHandle {
+    pub(crate) fn new(config: AgentConfig, path: Option<PathBuf>) -> Self {
+        redact::register_secrets(&config);
+        Self {
+            current: ArcSwap::from_pointee(config),
+            generation: Mutex::new(0),
+            history: path.as_deref().map(ConfigHistory::beside),
+            path,
+            saved: tokio::sync::Mutex::new(0),
+            save_scheduled: AtomicBool::new(false),
+            writes: AtomicUsize::new(0),
+        }
+    }
+
+    /// Saving to config.json next to the executable, as the agent always has.
+    pub(crate) fn beside_executable(config: AgentConfig) -> Self {
+        let path = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(|d| d.join("config.json")))
+            .unwrap_or_else(|| PathBuf::from("config.json"));
+        Self::new(config, Some(path))
+    }
+
+    /// The current configuration. Later updates don't affect a snapshot already taken.
+    pub(crate) fn load(&self) -> Arc<AgentConfig> {
+        self.current.load_full()
+    }
+
+    /// Apply `change` to a copy of the configuration, publish it, record what it
+    /// changed and schedule a save (unless one is already waiting). Returns what
+    /// `change` returns (e.g. the previous value, for logging).
+    pub(crate) fn update<R>(self: &Arc<Self>, change: impl FnOnce(&mut AgentConfig) -> R) -> R {
+        let result = {
+            let mut generation = self.generation.lock().unwrap();
+            let previous = self.current.load_full();
+            let mut config = AgentConfig::clone(&previous);
+            let result = change(&mut config);
+            if let Some(history) = &self.history {
+                history.record(&previous, &config, chrono::Utc::now().timestamp_millis());
+            }
+            redact::register_secrets(&config);
+            self.current.store(Arc::new(config));
+            *generation += 1;
+            result
+        };
+
+        if self.path.is_some() && !self.save_scheduled.swap(true, Ordering::AcqRel) {
+            let delay = Duration::from_secs_f64(self.load().agent.config_save_delay.clamp(0.0, 3600.0));
+            let handle = Arc::clone(self);
+            tokio::spawn(async move {
+                tokio::time::sleep(delay).await;
+                // Changes from here on schedule the next save
+                handle.save_scheduled.store(false, Ordering::Release);
+                if let Err(e) = handle.flush().await {
+                    error!("Failed to save configuration: {:#}", e);
+                }
+            });
+        }
+        result
+    }
+
+    /// Write the newest configuration unless an earlier save already covered it.
+    /// Returns once every update made before the call is on disk.
+    pub(crate) async fn flush(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut saved = self.saved.lock().await;
+        let (generation, config) = {
+            let generation = self.generation.lock().unwrap();
+            (*generation, self.current.load_full())
+        };
+        if *saved >= generation {
+            return Ok(());
+        }
+
+        save_config(&config, &path.to_string_lossy()).await?;
+        let writes = self.writes.fetch_add(1, Ordering::Relaxed) + 1;
+        debug!("Configuration generation {} saved ({} updates since the last save, {} writes this run)",
+               generation, generation - *saved, writes);
+        *saved = generation;
+        Ok(())
+    }
+
+    /// `flush` with blocking I/O, for exit paths where the async runtime may be gone.
+    /// Does nothing while a save is in progress: that one writes the newest snapshot.
+    pub(crate) fn flush_blocking(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let Ok(mut saved) = self.saved.try_lock() else {
+            return Ok(());
+        };
+        let (generation, config) = {
+            let generation = self.generation.lock().unwrap_or_else(std::sync::PoisonError::into_inner);
+            (*generation, self.current.load_full())
+        };
+        if *saved >= generation {
+            return Ok(());
+        }
+
+        save_config_blocking(&config, &path.to_string_lossy())?;
+        self.writes.fetch_add(1, Ordering::Relaxed);
+        *saved = generation;
+        Ok(())
+    }
+
+    /// The last `limit` recorded changes, only those at or after `since` (Unix ms).
+    pub(crate) fn history(&self, limit: usize, since: Option<i64>) -> Vec<ConfigChange> {
+        self.history.as_ref().map(|h| h.recent(limit, since)).unwrap_or_default()
+    }
+
+    /// Number of times config.json was written by this handle.
+    #[cfg(test)]
+    pub(crate) fn writes(&self) -> usize {
+        self.writes.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pankha-config-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("config.json")
+    }
+
+    fn on_disk(path: &PathBuf) -> AgentConfig {
+        serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_updates_are_all_kept_and_saved() {
+        let path = temp_path("concurrent");
+        let handle = Arc::new(ConfigHandle::new(AgentConfig::default(), Some(path.clone())));
+        let before = handle.load();
+
+        let tasks: Vec<_> = (0..32)
+            .map(|i| {
+                let handle = Arc::clone(&handle);
+                tokio::spawn(async move { handle.update(|c| c.hardware.excluded_sensors.push(format!("sensor_{}", i))) })
+            })
+            .collect();
+        for task in tasks {
+            task.await.unwrap();
+        }
+        handle.flush().await.unwrap();
+
+        assert!(before.hardware.excluded_sensors.is_empty(), "a snapshot never changes");
+        assert_eq!(handle.load().hardware.excluded_sensors.len(), 32);
+        let mut saved = on_disk(&path).hardware.excluded_sensors;
+        saved.sort();
+        let mut expected: Vec<String> = (0..32).map(|i| format!("sensor_{}", i)).collect();
+        expected.sort();
+        assert_eq!(saved, expected);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn a_burst_of_updates_is_one_write_of_the_last_value() {
+        let path = temp_path("burst");
+        let handle = Arc::new(ConfigHandle::new(AgentConfig::default(), Some(path.clone())));
+
+        for interval in [0.5, 1.0, 2.0, 3.0, 5.0] {
+            handle.update(|c| c.agent.update_interval = interval);
+        }
+        let previous = handle.update(|c| std::mem::replace(&mut c.hardware.fan_step_percent, 10));
+        assert_eq!(previous, AgentConfig::default().hardware.fan_step_percent);
+
+        handle.flush().await.unwrap();
+        // The saves scheduled by the updates find nothing left to write
+        tokio::task::yield_now().await;
+        handle.flush().await.unwrap();
+
+        assert_eq!(handle.writes(), 1);
+        let saved = on_disk(&path);
+        assert_eq!((saved.agent.update_interval, saved.hardware.fan_step_percent), (5.0, 10));
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    fn with_save_delay(path: &std::path::Path, delay: f64) -> Arc<ConfigHandle> {
+        let mut config = AgentConfig::default();
+        config.agent.config_save_delay = delay;
+        Arc::new(ConfigHandle::new(config, Some(path.to_path_buf())))
+    }
+
+    #[tokio::test]
+    async fn updates_spread_over_the_save_delay_are_one_write() {
+        let path = temp_path("debounce");
+        let handle = with_save_delay(&path, 0.3);
+
+        // A slider dragged for 100ms: one command every 10ms
+        for step in 1..=10 {
+            handle.update(|c| c.hardware.fan_step_percent = step);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(handle.writes(), 0);
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        assert_eq!(handle.writes(), 1);
+        assert_eq!(on_disk(&path).hardware.fan_step_percent, 10);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn flush_writes_changes_still_waiting_for_the_delay() {
+        let path = temp_path("shutdown");
+        let handle = with_save_delay(&path, 3600.0);
+
+        handle.update(|c| c.agent.log_level = "DEBUG".to_string());
+        tokio::task::yield_now().await;
+        assert_eq!(handle.writes(), 0);
+
+        // As on shutdown or before a self-update
+        handle.flush().await.unwrap();
+        assert_eq!(handle.writes(), 1);
+        assert_eq!(on_disk(&path).agent.log_level, "DEBUG");
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn blocking_flush_writes_what_is_still_waiting() {
+        let path = temp_path("panic");
+        let handle = with_save_delay(&path, 3600.0);
+
+        handle.update(|c| c.agent.log_level = "DEBUG".to_string());
+        // As from the panic hook, without the runtime
+        handle.flush_blocking().unwrap();
+        assert_eq!(handle.writes(), 1);
+        assert_eq!(on_disk(&path).agent.log_level, "DEBUG");
+        handle.flush_blocking().unwrap();
+        assert_eq!(handle.writes(), 1);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+}
@@ -0,0 +1,316 @@
+//! Configuration change history: every applied change with when, what and where
+//! from, appended to `config-history.jsonl` beside config.json.
+//!
+//! `ConfigHandle::update` compares the configuration before and after each change,
+//! so every setter is recorded without doing anything itself. Where a change came
+//! from is set for the task applying it with `with_origin` (backend commands, pushed
+//! configuration, SIGHUP); changes made outside one are the agent's own. The CLI
+//! edits config.json without a handle and records through `record_file_change`.
+//!
+//! One JSON object per changed field and line. The file rolls over to
+//! `config-history.jsonl.1` past 512 KiB, so the two together hold a few thousand
+//! changes. Credentials are never written, only that they changed.
+
+use std::fs::{self, OpenOptions};
+use std::future::Future;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use super::types::AgentConfig;
+
+pub const CONFIG_HISTORY_FILE: &str = "config-history.jsonl";
+
+/// Size at which the history file is rotated.
+const MAX_HISTORY_BYTES: u64 = 512 * 1024;
+
+/// Settings recorded as changed without their values.
+pub(crate) const SECRET_FIELDS: &[&str] = &["auth.auth_token", "auth.enrollment_token", "notifications.auth_header"];
+
+/// Who made a change.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    /// A set* command from the backend
+    Command,
+    /// The configuration block of `registered`
+    RegisteredPush,
+    /// A `configUpdate` message while connected
+    ConfigUpdate,
+    /// config.json reloaded on SIGHUP
+    Sighup,
+    /// The agent's own CLI (`--setup`, `--log-level`)
+    LocalCli,
+    /// The agent itself, e.g. moving the id to identity.json
+    Agent,
+}
+
+/// Where the changes made by the current task come from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigOrigin {
+    pub source: ConfigSource,
+    /// Command or message type
+    pub command: Option<String>,
+    pub command_id: Option<String>,
+}
+
+impl ConfigOrigin {
+    pub fn new(source: ConfigSource) -> Self {
+        Self { source, command: None, command_id: None }
+    }
+
+    /// A backend command (or message) of type `command`.
+    pub fn command(source: ConfigSource, command: &str, command_id: Option<&str>) -> Self {
+        Self { source, command: Some(command.to_string()), command_id: command_id.map(str::to_string) }
+    }
+}
+
+/// One changed setting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigChange {
+    /// Unix ms
+    pub timestamp: i64,
+    /// Dotted path in config.json, e.g. "hardware.emergency_temp"
+    pub field: String,
+    /// null where the setting was absent (at its default)
+    pub old_value: Value,
+    pub new_value: Value,
+    pub source: ConfigSource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command_id: Option<String>,
+}
+
+tokio::task_local! {
+    static ORIGIN: ConfigOrigin;
+}
+
+/// Run `future` with its configuration changes recorded as coming from `origin`.
+pub async fn with_origin<F: Future>(origin: ConfigOrigin, future: F) -> F::Output {
+    ORIGIN.scope(origin, future).await
+}
+
+fn current_origin() -> ConfigOrigin {
+    ORIGIN.try_with(Clone::clone).unwrap_or_else(|_| ConfigOrigin::new(ConfigSource::Agent))
+}
+
+/// Settings that differ between `old` and `new`, as (field, old value, new value).
+/// Objects are compared key by key, anything else (lists included) as a whole.
+pub fn diff(old: &AgentConfig, new: &AgentConfig) -> Vec<(String, Value, Value)> {
+    let (Ok(old), Ok(new)) = (serde_json::to_value(old), serde_json::to_value(new)) else {
+        return Vec::new();
+    };
+    let mut changes = Vec::new();
+    diff_values(String::new(), &old, &new, &mut changes);
+    for (field, old, new) in &mut changes {
+        if SECRET_FIELDS.contains(&field.as_str()) {
+            for value in [old, new] {
+                if !value.is_null() {
+                    *value = Value::from("(redacted)");
+                }
+            }
+        }
+    }
+    changes
+}
+
+fn diff_values(path: String, old: &Value, new: &Value, changes: &mut Vec<(String, Value, Value)>) {
+    if old == new {
+        return;
+    }
+    let (Value::Object(old_fields), Value::Object(new_fields)) = (old, new) else {
+        changes.push((path, old.clone(), new.clone()));
+        return;
+    };
+    let keys: std::collections::BTreeSet<&String> = old_fields.keys().chain(new_fields.keys()).collect();
+    for key in keys {
+        let field = if path.is_empty() { key.clone() } else { format!("{}.{}", path, key) };
+        diff_values(field, old_fields.get(key).unwrap_or(&Value::Null), new_fields.get(key).unwrap_or(&Value::Null), changes);
+    }
+}
+
+/// The history file of one config.json.
+#[derive(Debug)]
+pub struct ConfigHistory {
+    path: PathBuf,
+}
+
+impl ConfigHistory {
+    pub fn beside(config_path: &Path) -> Self {
+        Self { path: config_path.with_file_name(CONFIG_HISTORY_FILE) }
+    }
+
+    /// Append the changes from `old` to `new`, made by the current task's origin.
+    pub fn record(&self, old: &AgentConfig, new: &AgentConfig, now_ms: i64) {
+        self.record_from(&current_origin(), old, new, now_ms);
+    }
+
+    pub fn record_from(&self, origin: &ConfigOrigin, old: &AgentConfig, new: &AgentConfig, now_ms: i64) {
+        let changes: Vec<ConfigChange> = diff(old, new)
+            .into_iter()
+            .map(|(field, old_value, new_value)| ConfigChange {
+                timestamp: now_ms,
+                field,
+                old_value,
+                new_value,
+                source: origin.source,
+                command: origin.command.clone(),
+                command_id: origin.command_id.clone(),
+            })
+            .collect();
+        if changes.is_empty() {
+            return;
+        }
+        if let Err(e) = self.append(&changes) {
+            warn!("Could not record the configuration change in {:?}: {}", self.path, e);
+        }
+    }
+
+    fn append(&self, changes: &[ConfigChange]) -> io::Result<()> {
+        if fs::metadata(&self.path).is_ok_and(|m| m.len() >= MAX_HISTORY_BYTES) {
+            fs::rename(&self.path, self.rotated())?;
+        }
+        let mut lines = String::new();
+        for change in changes {
+            lines.push_str(&serde_json::to_string(change)?);
+            lines.push('\n');
+        }
+        // One write, so concurrent readers never see half a change
+        OpenOptions::new().create(true).append(true).open(&self.path)?.write_all(lines.as_bytes())
+    }
+
+    fn rotated(&self) -> PathBuf {
+        let mut name = self.path.clone().into_os_string();
+        name.push(".1");
+        PathBuf::from(name)
+    }
+
+    /// The last `limit` changes (oldest first), only those at or after `since` (Unix ms).
+    pub fn recent(&self, limit: usize, since: Option<i64>) -> Vec<ConfigChange> {
+        let mut changes: Vec<ConfigChange> = [self.rotated(), self.path.clone()]
+            .iter()
+            .filter_map(|path| fs::read_to_string(path).ok())
+            .flat_map(|content| {
+                content.lines().filter_map(|line| serde_json::from_str::<ConfigChange>(line).ok()).collect::<Vec<_>>()
+            })
+            .filter(|change| since.is_none_or(|since| change.timestamp >= since))
+            .collect();
+        changes.drain(..changes.len().saturating_sub(limit));
+        changes
+    }
+}
+
+/// Record a change the CLI made to `config_path` directly.
+pub fn record_file_change(config_path: &Path, old: &AgentConfig, new: &AgentConfig) {
+    ConfigHistory::beside(config_path).record_from(&ConfigOrigin::new(ConfigSource::LocalCli), old, new,
+                                                   chrono::Utc::now().timestamp_millis());
+}
+
+/// `--config-history`: the last `limit` changes to the config.json beside the executable.
+pub fn show(limit: usize, json: bool) -> anyhow::Result<()> {
+    let config_path = std::env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
+        .join("config.json");
+    let changes = ConfigHistory::beside(&config_path).recent(limit, None);
+    if json {
+        println!("{}", serde_json::to_string_pretty(&changes)?);
+        return Ok(());
+    }
+    if changes.is_empty() {
+        println!("No configuration changes recorded");
+        return Ok(());
+    }
+    for change in &changes {
+        let at = chrono::DateTime::from_timestamp_millis(change.timestamp)
+            .map(|t| t.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S").to_string())
+            .unwrap_or_default();
+        let origin = match (&change.command, &change.command_id) {
+            (Some(command), Some(id)) => format!("{} {} ({})", source_name(change.source), command, id),
+            (Some(command), None) => format!("{} {}", source_name(change.source), command),
+            _ => source_name(change.source).to_string(),
+        };
+        println!("{}  {}: {} → {}  [{}]", at, change.field, change.old_value, change.new_value, origin);
+    }
+    Ok(())
+}
+
+fn source_name(source: ConfigSource) -> &'static str {
+    match source {
+        ConfigSource::Command => "command",
+        ConfigSource::RegisteredPush => "registered push",
+        ConfigSource::ConfigUpdate => "configUpdate",
+        ConfigSource::Sighup => "SIGHUP",
+        ConfigSource::LocalCli => "local CLI",
+        ConfigSource::Agent => "agent",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_history(name: &str) -> ConfigHistory {
+        let dir = std::env::temp_dir().join(format!("pankha-history-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        ConfigHistory::beside(&dir.join("config.json"))
+    }
+
+    #[test]
+    fn changes_are_recorded_per_field_without_secrets() {
+        let old = AgentConfig::default();
+        let mut new = old.clone();
+        new.hardware.emergency_temp = 100.0;
+        new.hardware.emergency_temp_by_type.insert("hdd".to_string(), 55.0);
+        new.auth.auth_token = Some("tk_secret".to_string());
+
+        let changes = diff(&old, &new);
+        let fields: Vec<&str> = changes.iter().map(|(field, _, _)| field.as_str()).collect();
+        assert_eq!(fields, ["auth.auth_token", "hardware.emergency_temp", "hardware.emergency_temp_by_type"]);
+        assert_eq!(changes[0].2, Value::from("(redacted)"));
+        assert_eq!(changes[1], ("hardware.emergency_temp".to_string(), Value::from(old.hardware.emergency_temp), Value::from(100.0)));
+        assert!(diff(&new, &new).is_empty());
+    }
+
+    #[tokio::test]
+    async fn origin_is_that_of_the_applying_task() {
+        let history = temp_history("origin");
+        let old = AgentConfig::default();
+        let mut new = old.clone();
+        new.hardware.failsafe_speed = 90;
+
+        history.record(&old, &new, 1_000);
+        with_origin(ConfigOrigin::command(ConfigSource::Command, "setFailsafeSpeed", Some("cmd-1")), async {
+            history.record(&new, &old, 2_000);
+        }).await;
+
+        let changes = history.recent(10, None);
+        assert_eq!(changes.len(), 2);
+        assert_eq!((changes[0].source, changes[0].command.as_deref()), (ConfigSource::Agent, None));
+        assert_eq!((changes[1].source, changes[1].command_id.as_deref()), (ConfigSource::Command, Some("cmd-1")));
+        assert_eq!((&changes[1].old_value, &changes[1].new_value), (&Value::from(90), &Value::from(70)));
+        assert_eq!(history.recent(10, Some(2_000)).len(), 1);
+        let _ = fs::remove_dir_all(history.path.parent().unwrap());
+    }
+
+    #[test]
+    fn a_full_file_rolls_over() {
+        let history = temp_history("rotation");
+        let old = AgentConfig::default();
+        let mut i = 0;
+        while !history.rotated().exists() {
+            let mut new = old.clone();
+            new.agent.name = format!("{}-{}", "x".repeat(200), i);
+            history.record(&old, &new, i);
+            i += 1;
+        }
+        assert!(fs::metadata(&history.path).unwrap().len() < MAX_HISTORY_BYTES);
+        assert_eq!(history.recent(3, None).iter().map(|c| c.timestamp).collect::<Vec<_>>(), [i - 3, i - 2, i - 1]);
+        let _ = fs::remove_dir_all(history.path.parent().unwrap());
+    }
+}
@@ -0,0 +1,129 @@
+//! Agent identity, kept in identity.json next to config.json.
+//!
+//! The agent id used to live in config.json, so a regenerated config (wizard run
+//! over a broken file, automated redeploy) meant a new id and a second machine on
+//! the Hub without the first one's history. identity.json is created once, never
+//! rewritten by the wizard or by config saves, and `load_config` composes its id into
+//! `AgentConfig` at runtime. A config.json that still carries an id hands it over on
+//! first start.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+pub(crate) const IDENTITY_FILE: &str = "identity.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct Identity {
+    pub(crate) id: String,
+    /// When this id was created or taken over from config.json (RFC 3339)
+    pub(crate) first_seen: String,
+}
+
+/// identity.json beside `config_path`.
+pub(crate) fn identity_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(IDENTITY_FILE)
+}
+
+/// New agent ID: OS-hostname-UUID (short UUID: first 8 chars)
+pub(crate) fn generate_agent_id(hostname: &str) -> String {
+    let unique_id = Uuid::new_v4();
+    let short_uuid = &unique_id.to_string()[..8];
+    format!("{}-{}-{}", std::env::consts::OS, hostname, short_uuid)
+}
+
+/// The stored identity, if there is a readable one.
+pub(crate) fn load(config_path: &Path) -> Option<Identity> {
+    let content = std::fs::read_to_string(identity_path(config_path)).ok()?;
+    serde_json::from_str::<Identity>(&content).ok().filter(|identity| !identity.id.is_empty())
+}
+
+/// The stored identity, created on first use. `config_id` (the id config.json still
+/// carries, or "") becomes the identity when there is none yet; otherwise a new id is
+/// generated. An existing identity always wins over `config_id`.
+pub(crate) fn load_or_create(config_path: &Path, config_id: &str) -> Result<Identity> {
+    let path = identity_path(config_path);
+    if let Some(identity) = load(config_path) {
+        if !config_id.is_empty() && config_id != identity.id {
+            warn!("config.json has agent id {}, keeping {} from {:?}", config_id, identity.id, path);
+        }
+        return Ok(identity);
+    }
+    if path.exists() {
+        // Unreadable: keep it for inspection rather than overwrite it silently
+        let broken = path.with_extension("json.broken");
+        warn!("{:?} is not a valid identity file; moving it to {:?}", path, broken);
+        std::fs::rename(&path, &broken).with_context(|| format!("Failed to move {:?} aside", path))?;
+    }
+
+    let id = if config_id.is_empty() {
+        let hostname = hostname::get()
+            .unwrap_or_else(|_| std::ffi::OsString::from("unknown"))
+            .to_string_lossy()
+            .to_string();
+        generate_agent_id(&hostname)
+    } else {
+        config_id.to_string()
+    };
+    let identity = Identity { id, first_seen: chrono::Utc::now().to_rfc3339() };
+    write(&path, &identity)?;
+    if config_id.is_empty() {
+        info!("Created agent identity {} in {:?}", identity.id, path);
+    } else {
+        info!("Moved agent id {} from config.json to {:?}", identity.id, path);
+    }
+    Ok(identity)
+}
+
+/// Write through a temporary file, so a crash never leaves a half-written identity.
+fn write(path: &Path, identity: &Identity) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(identity)?)
+        .with_context(|| format!("Failed to write {:?}", tmp))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))?;
+    }
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_config(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("pankha-identity-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir.join("config.json")
+    }
+
+    #[test]
+    fn created_once_and_then_always_returned() {
+        let config = temp_config("once");
+        assert!(load(&config).is_none());
+
+        let created = load_or_create(&config, "").unwrap();
+        assert!(created.id.starts_with(&format!("{}-", std::env::consts::OS)));
+        assert_eq!(load_or_create(&config, "").unwrap(), created);
+        // A different id in config.json doesn't replace it
+        assert_eq!(load_or_create(&config, "linux-other-00000000").unwrap(), created);
+        let _ = std::fs::remove_dir_all(config.parent().unwrap());
+    }
+
+    #[test]
+    fn config_id_is_taken_over_and_a_broken_file_is_kept_aside() {
+        let config = temp_config("takeover");
+        assert_eq!(load_or_create(&config, "linux-nas-1a2b3c4d").unwrap().id, "linux-nas-1a2b3c4d");
+
+        std::fs::write(identity_path(&config), "{\"id\": ").unwrap();
+        assert_eq!(load_or_create(&config, "linux-nas-1a2b3c4d").unwrap().id, "linux-nas-1a2b3c4d");
+        assert_eq!(std::fs::read_to_string(config.with_file_name("identity.json.broken")).unwrap(), "{\"id\": ");
+        let _ = std::fs::remove_dir_all(config.parent().unwrap());
+    }
+}
@@ -0,0 +1,193 @@
+//! config.json files from earlier agent generations.
+//!
+//! Schema versions:
+//! - 1: the first Rust agents. `hardware.temperature_critical` instead of
+//!   `emergency_temp`, and no `fan_step_percent` / `hysteresis_temp` (the backend
+//!   applied those). Several agent/backend fields were optional.
+//! - 2: the current `AgentConfig`, also every file written before `config_version`
+//!   existed that parses as it. Files with fields since removed (`fan_safety_minimum`,
+//!   ...) are fixed up in place by `persistence::migrate_config` first.
+//!
+//! A file that doesn't parse as the current schema is read as each known older shape
+//! and converted, so the agent keeps its id, name and Hub URL rather than failing on
+//! an old config.json.
+
+use anyhow::{anyhow, Result};
+use serde::Deserialize;
+
+use super::types::{AgentConfig, LoggingSettings};
+
+/// Schema version this agent writes.
+pub const CONFIG_VERSION: u32 = 2;
+
+/// An older config converted to the current schema.
+#[derive(Debug)]
+pub(crate) struct Migrated {
+    pub(crate) config: AgentConfig,
+    pub(crate) from_version: u32,
+    /// What was renamed or filled in, for the log
+    pub(crate) changes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct V1Config {
+    agent: V1Agent,
+    backend: V1Backend,
+    hardware: V1Hardware,
+    logging: Option<LoggingSettings>,
+}
+
+#[derive(Deserialize)]
+struct V1Agent {
+    id: String,
+    name: Option<String>,
+    update_interval: Option<f64>,
+    log_level: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct V1Backend {
+    server_url: String,
+    reconnect_interval: Option<f64>,
+    max_reconnect_attempts: Option<i32>,
+    connection_timeout: Option<f64>,
+}
+
+#[derive(Deserialize)]
+struct V1Hardware {
+    enable_fan_control: Option<bool>,
+    enable_sensor_monitoring: Option<bool>,
+    temperature_critical: Option<f64>,
+    failsafe_speed: Option<u8>,
+    #[serde(default)]
+    excluded_sensors: Vec<String>,
+    // Only to tell a current file with a missing field apart from a version 1 file
+    emergency_temp: Option<serde::de::IgnoredAny>,
+}
+
+/// Convert `content`, which failed to parse as the current schema, from the first
+/// older shape it matches. Errors if it matches none.
+pub(crate) fn migrate(content: &str) -> Result<Migrated> {
+    if let Ok(v1) = serde_json::from_str::<V1Config>(content) {
+        if v1.hardware.emergency_temp.is_none() {
+            return Ok(from_v1(v1));
+        }
+    }
+    Err(anyhow!("not a known older config.json layout"))
+}
+
+fn from_v1(v1: V1Config) -> Migrated {
+    let mut config = AgentConfig::default();
+    let mut changes = Vec::new();
+
+    // A missing value takes the default, and says so
+    fn keep<T: std::fmt::Debug>(changes: &mut Vec<String>, field: &str, value: Option<T>, target: &mut T) {
+        match value {
+            Some(value) => *target = value,
+            None => changes.push(format!("added {} = {:?} (default)", field, target)),
+        }
+    }
+
+    config.agent.id = v1.agent.id;
+    keep(&mut changes, "agent.name", v1.agent.name, &mut config.agent.name);
+    keep(&mut changes, "agent.update_interval", v1.agent.update_interval, &mut config.agent.update_interval);
+    keep(&mut changes, "agent.log_level", v1.agent.log_level, &mut config.agent.log_level);
+
+    config.backend.server_url = v1.backend.server_url;
+    keep(&mut changes, "backend.reconnect_interval", v1.backend.reconnect_interval, &mut config.backend.reconnect_interval);
+    keep(&mut changes, "backend.max_reconnect_attempts", v1.backend.max_reconnect_attempts, &mut config.backend.max_reconnect_attempts);
+    keep(&mut changes, "backend.connection_timeout", v1.backend.connection_timeout, &mut config.backend.connection_timeout);
+
+    let hardware = &mut config.hardware;
+    keep(&mut changes, "hardware.enable_fan_control", v1.hardware.enable_fan_control, &mut hardware.enable_fan_control);
+    keep(&mut changes, "hardware.enable_sensor_monitoring", v1.hardware.enable_sensor_monitoring, &mut hardware.enable_sensor_monitoring);
+    match v1.hardware.temperature_critical {
+        Some(temp) => {
+            hardware.emergency_temp = temp;
+            changes.push(format!("renamed hardware.temperature_critical ({}) to hardware.emergency_temp", temp));
+        }
+        None => changes.push(format!("added hardware.emergency_temp = {:?} (default)", hardware.emergency_temp)),
+    }
+    changes.push(format!("added hardware.fan_step_percent = {} (default)", hardware.fan_step_percent));
+    changes.push(format!("added hardware.hysteresis_temp = {:?} (default)", hardware.hysteresis_temp));
+    keep(&mut changes, "hardware.failsafe_speed", v1.hardware.failsafe_speed, &mut hardware.failsafe_speed);
+    hardware.excluded_sensors = v1.hardware.excluded_sensors;
+
+    match v1.logging {
+        Some(logging) => config.logging = logging,
+        None => changes.push("added logging section (defaults)".to_string()),
+    }
+
+    Migrated { config, from_version: 1, changes }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_1_keeps_identity_and_renames_temperature_critical() {
+        let migrated = migrate(r#"{
+            "agent": {"id": "linux-rack01-1a2b3c4d", "name": "rack01", "update_interval": 2.0, "log_level": "DEBUG"},
+            "backend": {"server_url": "ws://192.168.1.20:3143/websocket", "reconnect_interval": 5.0,
+                        "max_reconnect_attempts": -1, "connection_timeout": 10.0},
+            "hardware": {"enable_fan_control": false, "enable_sensor_monitoring": true, "temperature_critical": 80.0,
+                         "failsafe_speed": 60},
+            "logging": {"enable_file_logging": false, "log_file": "/tmp/agent.log", "max_log_size_mb": 5, "log_retention_days": 3}
+        }"#).unwrap();
+
+        let config = &migrated.config;
+        assert_eq!(migrated.from_version, 1);
+        assert_eq!(config.config_version, CONFIG_VERSION);
+        assert_eq!((config.agent.id.as_str(), config.agent.name.as_str()), ("linux-rack01-1a2b3c4d", "rack01"));
+        assert_eq!((config.agent.update_interval, config.agent.log_level.as_str()), (2.0, "DEBUG"));
+        assert_eq!(config.backend.server_url, "ws://192.168.1.20:3143/websocket");
+        assert!(!config.hardware.enable_fan_control);
+        assert_eq!((config.hardware.emergency_temp, config.hardware.failsafe_speed), (80.0, 60));
+        assert_eq!(config.logging.log_file, "/tmp/agent.log");
+        assert_eq!(migrated.changes, [
+            "renamed hardware.temperature_critical (80) to hardware.emergency_temp",
+            "added hardware.fan_step_percent = 5 (default)",
+            "added hardware.hysteresis_temp = 3.0 (default)",
+        ]);
+
+        // What gets written parses as the current schema
+        let written = serde_json::to_string(config).unwrap();
+        assert!(serde_json::from_str::<AgentConfig>(&written).is_ok());
+    }
+
+    #[test]
+    fn minimal_version_1_is_filled_with_defaults() {
+        let migrated = migrate(r#"{
+            "agent": {"id": "linux-nas-99aa88bb"},
+            "backend": {"server_url": "wss://pankha.lan/websocket"},
+            "hardware": {"temperature_critical": 75}
+        }"#).unwrap();
+
+        let defaults = AgentConfig::default();
+        assert_eq!(migrated.config.agent.id, "linux-nas-99aa88bb");
+        assert_eq!(migrated.config.backend.server_url, "wss://pankha.lan/websocket");
+        assert_eq!(migrated.config.hardware.emergency_temp, 75.0);
+        assert_eq!(migrated.config.agent.update_interval, defaults.agent.update_interval);
+        assert_eq!(migrated.config.logging.max_log_size_mb, defaults.logging.max_log_size_mb);
+        assert!(migrated.changes.contains(&"added agent.update_interval = 3.0 (default)".to_string()));
+        assert!(migrated.changes.contains(&"added logging section (defaults)".to_string()));
+    }
+
+    #[test]
+    fn current_files_are_not_mistaken_for_old_ones() {
+        // Written before config_version existed: current schema, nothing to migrate
+        let mut json = serde_json::to_value(AgentConfig::default()).unwrap();
+        json.as_object_mut().unwrap().remove("config_version");
+        let config: AgentConfig = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(config.config_version, CONFIG_VERSION);
+
+        // A current file missing a required field stays an error instead of being
+        // "migrated" with everything but the version 1 fields reset
+        json["hardware"].as_object_mut().unwrap().remove("hysteresis_temp");
+        assert!(serde_json::from_value::<AgentConfig>(json.clone()).is_err());
+        assert!(migrate(&json.to_string()).is_err());
+
+        assert!(migrate(r#"{"agent": "not a config"}"#).is_err());
+    }
+}
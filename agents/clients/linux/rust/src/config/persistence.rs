@@ -4,7 +4,10 @@ use anyhow::Result;
 use std::path::{Path, PathBuf};
 use tracing::{info, warn};
 
-use crate::config::types::AgentConfig;
+use crate::app::redact;
+use crate::config::identity;
+use crate::config::legacy;
+use crate::config::types::{AgentConfig, Transport};
 
 /// Migrate config to current version (removes deprecated, adds new fields)
 /// Phase 3: Config Migration - handles old configs automatically
@@ -48,18 +51,24 @@ pub(crate) fn migrate_config(config_path: &Path) -> Result<bool> {
     Ok(migrated)
 }
 
+/// config.json next to the executable, where the agent keeps it.
+pub(crate) fn default_config_path() -> Result<PathBuf> {
+    let exe_dir = std::env::current_exe()?
+        .parent()
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
+        .to_path_buf();
+    Ok(exe_dir.join("config.json"))
+}
+
 pub async fn load_config(path: Option<&str>) -> Result<AgentConfig> {
-    let config_path = if let Some(p) = path {
-        PathBuf::from(p)
-    } else {
-        // Default config location
-        let exe_dir = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .to_path_buf();
-        exe_dir.join("config.json")
+    let config_path = match path {
+        Some(p) => PathBuf::from(p),
+        None => default_config_path()?,
     };
 
+    // As the user left it, for the backup if the schema migration below kicks in
+    let original = std::fs::read_to_string(&config_path).ok();
+
     // Migrate config first (handles old configs automatically)
     if let Err(e) = migrate_config(&config_path) {
         warn!("Config migration check failed: {}", e);
@@ -67,24 +76,86 @@ pub async fn load_config(path: Option<&str>) -> Result<AgentConfig> {
 
     if config_path.exists() {
         let content = tokio::fs::read_to_string(&config_path).await?;
-        let config: AgentConfig = serde_json::from_str(&content)?;
+        let mut migrated = None;
+        let mut config = match serde_json::from_str::<AgentConfig>(&content) {
+            Ok(config) => config,
+            // An older generation's layout: convert it rather than fail (or have
+            // callers fall back to defaults and lose the id and Hub URL)
+            Err(e) => match legacy::migrate(&content) {
+                Ok(m) => migrated.insert(m).config.clone(),
+                Err(_) => return Err(anyhow::anyhow!("Invalid config file {:?}: {}", config_path, e)),
+            },
+        };
+        if config.config_version > legacy::CONFIG_VERSION {
+            warn!("{:?} is config_version {}, newer than this agent understands ({}); unknown settings are ignored",
+                  config_path, config.config_version, legacy::CONFIG_VERSION);
+        }
+
+        // The id comes from identity.json; one still in config.json moves there
+        let id_in_file = !config.agent.id.is_empty();
+        config.agent.id = identity::load_or_create(&config_path, &config.agent.id)?.id;
+        if let Some(mut migrated) = migrated {
+            migrated.config = config.clone();
+            save_migrated(&config_path, original.as_deref().unwrap_or(&content), migrated).await?;
+        } else if id_in_file {
+            save_config(&config, &config_path.to_string_lossy()).await?;
+        }
 
         // Validate configuration
-        if config.backend.server_url.contains("[YOUR_HUB_IP]") || config.backend.server_url.is_empty() {
+        config.validate().map_err(|e| anyhow::anyhow!("Invalid config file {:?}: {}", config_path, e))?;
+        if config.backend.transport == Transport::WebSocket
+            && (config.backend.server_url.contains("[YOUR_HUB_IP]") || config.backend.server_url.is_empty())
+        {
             warn!("⚠️ Hub URL is not configured in {:?}. Agent will fail to connect.", config_path);
             warn!("Please run the setup wizard ('--setup') or edit the config file manually.");
         }
 
+        redact::register_secrets(&config);
         info!("Loaded configuration from: {:?}", config_path);
         Ok(config)
     } else {
         info!("Config file not found. Please run the setup wizard ('--setup') to generate one.");
-        Ok(AgentConfig::default())
+        let mut config = AgentConfig::default();
+        if let Some(identity) = identity::load(&config_path) {
+            config.agent.id = identity.id;
+        }
+        Ok(config)
     }
 }
 
+/// Keep the original next to config.json as `config.json.v<N>.bak`, write the
+/// converted config in its place and log what changed.
+async fn save_migrated(config_path: &Path, original: &str, migrated: legacy::Migrated) -> Result<()> {
+    let backup = config_path.with_file_name(format!(
+        "{}.v{}.bak",
+        config_path.file_name().and_then(|n| n.to_str()).unwrap_or("config.json"),
+        migrated.from_version
+    ));
+    tokio::fs::write(&backup, original).await?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        tokio::fs::set_permissions(&backup, std::fs::Permissions::from_mode(0o600)).await?;
+    }
+    save_config(&migrated.config, &config_path.to_string_lossy()).await?;
+
+    warn!("Migrated {:?} from config_version {} to {} (original kept as {:?}): {}",
+          config_path, migrated.from_version, legacy::CONFIG_VERSION, backup, migrated.changes.join("; "));
+    Ok(())
+}
+
+/// config.json contents for `config`: everything but the agent id, which lives in
+/// identity.json.
+pub(crate) fn config_file_contents(config: &AgentConfig) -> Result<String> {
+    let mut json = serde_json::to_value(config)?;
+    if let Some(agent) = json.get_mut("agent").and_then(|a| a.as_object_mut()) {
+        agent.remove("id");
+    }
+    Ok(serde_json::to_string_pretty(&json)?)
+}
+
 pub async fn save_config(config: &AgentConfig, path: &str) -> Result<()> {
-    let content = serde_json::to_string_pretty(config)?;
+    let content = config_file_contents(config)?;
     tokio::fs::write(path, content).await?;
     // config.json carries the Hub auth token - keep it owner-only
     #[cfg(unix)]
@@ -95,3 +166,45 @@ pub async fn save_config(config: &AgentConfig, path: &str) -> Result<()> {
     info!("Configuration saved to: {}", path);
     Ok(())
 }
+
+/// `save_config` with blocking I/O, for when the async runtime may be gone (see
+/// `daemon::shutdown`).
+pub(crate) fn save_config_blocking(config: &AgentConfig, path: &str) -> Result<()> {
+    std::fs::write(path, config_file_contents(config)?)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))?;
+    }
+    info!("Configuration saved to: {}", path);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn old_config_is_backed_up_and_rewritten() {
+        let dir = std::env::temp_dir().join(format!("pankha-legacy-config-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let original = r#"{"agent": {"id": "linux-old-12345678", "name": "old"},
+                           "backend": {"server_url": "ws://10.0.0.5:3143/websocket"},
+                           "hardware": {"temperature_critical": 82.0}}"#;
+        std::fs::write(&path, original).unwrap();
+
+        let config = load_config(path.to_str()).await.unwrap();
+        assert_eq!(config.agent.id, "linux-old-12345678");
+        assert_eq!(config.hardware.emergency_temp, 82.0);
+
+        assert_eq!(std::fs::read_to_string(dir.join("config.json.v1.bak")).unwrap(), original);
+        let rewritten: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(rewritten["config_version"], legacy::CONFIG_VERSION);
+        assert_eq!(rewritten["backend"]["server_url"], "ws://10.0.0.5:3143/websocket");
+
+        // Second start: already current, nothing to migrate
+        assert_eq!(load_config(path.to_str()).await.unwrap().agent.id, "linux-old-12345678");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
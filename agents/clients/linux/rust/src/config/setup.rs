@@ -1,13 +1,18 @@
-//! Interactive setup wizard for first-run configuration.
+//! Setup wizard for first-run configuration: interactive, or driven by flags
+//! (`--setup --non-interactive`) for automated provisioning.
 
 use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 use anyhow::Result;
-use uuid::Uuid;
 
 use crate::config::types::*;
+use crate::config::identity;
+use crate::config::history;
 use crate::config::persistence::{load_config, save_config};
+use crate::config::sst::{VALID_FAILSAFE_SPEEDS, VALID_UPDATE_INTERVALS};
 use crate::hardware::HardwareMonitor;
+use crate::websocket::connection_check::check_connection;
+use crate::websocket::transport::Route;
 
 #[cfg(target_os = "linux")]
 use crate::hardware::LinuxHardwareMonitor;
@@ -21,6 +26,274 @@ use crate::daemon::control::start_daemon_with_log_level;
 #[cfg(target_os = "linux")]
 use crate::daemon::SYSTEMD_SERVICE_PATH;
 
+/// Answers collected by the wizard (or given as `--setup --non-interactive` flags).
+struct SetupChoices {
+    agent_id: String,
+    agent_name: String,
+    server_url: String,
+    update_interval: f64,
+    enable_fan_control: bool,
+    failsafe_speed: u8,
+}
+
+/// Flags for `--setup --non-interactive`; `None` = not given.
+#[derive(Debug, Default)]
+pub struct SetupOptions {
+    pub server_url: Option<String>,
+    pub agent_name: Option<String>,
+    pub update_interval: Option<f64>,
+    pub enable_fan_control: Option<bool>,
+    pub failsafe_speed: Option<u8>,
+    pub enrollment_token: Option<String>,
+    /// Replace an existing config.json
+    pub force: bool,
+    /// Run the hardware test afterwards and print its JSON report
+    pub test: bool,
+}
+
+/// The config the wizard writes. Settings it doesn't ask about keep their value from
+/// `existing` (re-run over an old config) or get the defaults.
+fn build_config(existing: Option<&AgentConfig>, choices: SetupChoices) -> AgentConfig {
+    AgentConfig {
+        config_version: current_config_version(),
+        agent: AgentSettings {
+            id: choices.agent_id,
+            name: choices.agent_name,
+            update_interval: choices.update_interval,
+            log_level: "INFO".to_string(),
+            run_as_user: existing.and_then(|c| c.agent.run_as_user.clone()),
+            config_save_delay: existing
+                .map(|c| c.agent.config_save_delay)
+                .unwrap_or_else(default_config_save_delay),
+            safe_mode_after_starts: existing
+                .map(|c| c.agent.safe_mode_after_starts)
+                .unwrap_or_else(default_safe_mode_after_starts),
+            safe_mode_window_minutes: existing
+                .map(|c| c.agent.safe_mode_window_minutes)
+                .unwrap_or_else(default_safe_mode_window_minutes),
+            adaptive_interval: existing.map(|c| c.agent.adaptive_interval).unwrap_or_default(),
+            state_file: existing
+                .map(|c| c.agent.state_file.clone())
+                .unwrap_or_else(default_state_file),
+            redact_diagnostics: existing.is_some_and(|c| c.agent.redact_diagnostics),
+        },
+        backend: BackendSettings {
+            server_url: choices.server_url,
+            transport: existing.map(|c| c.backend.transport).unwrap_or_default(),
+            udp_address: existing
+                .map(|c| c.backend.udp_address)
+                .unwrap_or_else(default_udp_address),
+            udp_port: existing.map(|c| c.backend.udp_port).unwrap_or_else(default_udp_port),
+            reconnect_interval: 5.0,
+            max_reconnect_attempts: -1,
+            connection_timeout: 10.0,
+            payload_profile: existing
+                .map(|c| c.backend.payload_profile)
+                .unwrap_or_default(),
+            compression: existing.map(|c| c.backend.compression).unwrap_or_default(),
+            bind_interface: existing.and_then(|c| c.backend.bind_interface.clone()),
+            bind_address: existing.and_then(|c| c.backend.bind_address),
+            proxy_url: existing.and_then(|c| c.backend.proxy_url.clone()),
+            max_message_bytes: existing
+                .map(|c| c.backend.max_message_bytes)
+                .unwrap_or_else(default_max_message_bytes),
+            max_incoming_message_bytes: existing
+                .map(|c| c.backend.max_incoming_message_bytes)
+                .unwrap_or_else(default_max_incoming_message_bytes),
+            latency_warn_ms: existing
+                .map(|c| c.backend.latency_warn_ms)
+                .unwrap_or_else(default_latency_warn_ms),
+        },
+        hardware: HardwareSettings {
+            enable_fan_control: choices.enable_fan_control,
+            enable_sensor_monitoring: true,
+            fan_step_percent: 5,
+            hysteresis_temp: 3.0,
+            emergency_temp: 85.0,
+            emergency_temp_by_type: existing
+                .map(|c| c.hardware.emergency_temp_by_type.clone())
+                .unwrap_or_default(),
+            failsafe_speed: choices.failsafe_speed,
+            excluded_sensors: Vec::new(),
+            disabled_chips: existing
+                .map(|c| c.hardware.disabled_chips.clone())
+                .unwrap_or_default(),
+            escalate_on_crit_alarm: existing
+                .map(|c| c.hardware.escalate_on_crit_alarm)
+                .unwrap_or(true),
+            failsafe_release_checks: existing
+                .map(|c| c.hardware.failsafe_release_checks)
+                .unwrap_or_else(default_failsafe_release_checks),
+            dry_run: existing
+                .map(|c| c.hardware.dry_run)
+                .unwrap_or(false),
+            temperature_smoothing: existing
+                .map(|c| c.hardware.temperature_smoothing)
+                .unwrap_or_default(),
+            sensor_stats_window_hours: existing
+                .map(|c| c.hardware.sensor_stats_window_hours)
+                .unwrap_or_else(default_sensor_stats_window_hours),
+            monitor_only_on_conflict: existing
+                .map(|c| c.hardware.monitor_only_on_conflict)
+                .unwrap_or(false),
+            pwm_failure_threshold: existing
+                .map(|c| c.hardware.pwm_failure_threshold)
+                .unwrap_or_else(default_pwm_failure_threshold),
+            drift_reassert_limit: existing
+                .map(|c| c.hardware.drift_reassert_limit)
+                .unwrap_or_else(default_drift_reassert_limit),
+            temperature_unit: existing
+                .map(|c| c.hardware.temperature_unit)
+                .unwrap_or_default(),
+            semi_passive: existing
+                .map(|c| c.hardware.semi_passive.clone())
+                .unwrap_or_default(),
+            fan_pulses: existing
+                .map(|c| c.hardware.fan_pulses.clone())
+                .unwrap_or_default(),
+            storage_health: existing
+                .map(|c| c.hardware.storage_health)
+                .unwrap_or(false),
+            ipmi_bridge_dedup: existing
+                .map(|c| c.hardware.ipmi_bridge_dedup)
+                .unwrap_or_default(),
+            local_curves: existing
+                .map(|c| c.hardware.local_curves.clone())
+                .unwrap_or_default(),
+            virtual_sensors: existing
+                .map(|c| c.hardware.virtual_sensors.clone())
+                .unwrap_or_default(),
+            serialize_chip_access: existing
+                .map(|c| c.hardware.serialize_chip_access)
+                .unwrap_or(false),
+            skip_write_verify: existing
+                .map(|c| c.hardware.skip_write_verify.clone())
+                .unwrap_or_default(),
+            use_lm_sensors_config: existing
+                .map(|c| c.hardware.use_lm_sensors_config)
+                .unwrap_or(false),
+        },
+        logging: LoggingSettings {
+            enable_file_logging: true,
+            log_file: "/var/log/pankha-agent/agent.log".to_string(),
+            max_log_size_mb: 10,
+            log_retention_days: 7,
+            compress_rotated_logs: false,
+            log_format: existing
+                .map(|c| c.logging.log_format.clone())
+                .unwrap_or_else(default_log_format),
+            repeated_error_interval: existing
+                .map(|c| c.logging.repeated_error_interval)
+                .unwrap_or_else(default_repeated_error_interval),
+        },
+        emergency_actions: existing.map(|c| c.emergency_actions.clone()).unwrap_or_default(),
+        notifications: existing.map(|c| c.notifications.clone()).unwrap_or_default(),
+        backends: existing.map(|c| c.backends.clone()).unwrap_or_default(),
+        // Wizard setups start without credentials; enrollment needs a deploy
+        // token from the Hub's Deployment page (see enrollment_token)
+        auth: AuthSettings::default(),
+    }
+}
+
+/// Every missing or invalid flag of a non-interactive setup, so all of them are
+/// reported in one go.
+fn non_interactive_problems(options: &SetupOptions, has_existing: bool) -> Vec<String> {
+    let mut problems = Vec::new();
+
+    // Without a config to fall back on, the Hub URL has no usable default
+    match options.server_url.as_deref() {
+        None if !has_existing => problems.push("--server-url is required".to_string()),
+        Some(url) if !(url.starts_with("ws://") || url.starts_with("wss://")) => {
+            problems.push(format!("--server-url must start with ws:// or wss://, got '{}'", url));
+        }
+        _ => {}
+    }
+    if let Some(name) = options.agent_name.as_deref() {
+        if name.trim().is_empty() || name.trim().len() > 255 {
+            problems.push("--agent-name must be 1-255 characters".to_string());
+        }
+    }
+    if let Some(interval) = options.update_interval {
+        if !VALID_UPDATE_INTERVALS.contains(&interval) {
+            problems.push(format!("--update-interval must be one of {:?}, got {}", VALID_UPDATE_INTERVALS, interval));
+        }
+    }
+    if let Some(speed) = options.failsafe_speed {
+        if !VALID_FAILSAFE_SPEEDS.contains(&speed) {
+            problems.push(format!("--failsafe-speed must be one of {:?}, got {}", VALID_FAILSAFE_SPEEDS, speed));
+        }
+    }
+    if options.enrollment_token.as_deref().is_some_and(|t| t.trim().is_empty()) {
+        problems.push("--enrollment-token must not be empty".to_string());
+    }
+    problems
+}
+
+/// `--setup --non-interactive`: write config.json from flags, for unattended installs.
+/// Progress goes to stderr so `--test` leaves stdout to the JSON report.
+pub async fn run_non_interactive_setup(config_path: Option<&str>, options: &SetupOptions) -> Result<()> {
+    let config_file = if let Some(p) = config_path {
+        PathBuf::from(p)
+    } else {
+        std::env::current_exe()?
+            .parent()
+            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
+            .join("config.json")
+    };
+
+    let exists = config_file.exists();
+    if exists && !options.force {
+        anyhow::bail!("Config file already exists: {:?} (use --force to overwrite it)", config_file);
+    }
+    let existing_config = if exists { load_config(config_file.to_str()).await.ok() } else { None };
+
+    let problems = non_interactive_problems(options, existing_config.is_some());
+    if !problems.is_empty() {
+        anyhow::bail!("Non-interactive setup needs valid flags:\n  {}", problems.join("\n  "));
+    }
+
+    let hostname = hostname::get()
+        .unwrap_or_else(|_| std::ffi::OsString::from("unknown"))
+        .to_string_lossy()
+        .to_string();
+    let existing = existing_config.as_ref();
+    let mut config = build_config(existing, SetupChoices {
+        // identity.json survives an overwritten (or unreadable) config.json
+        agent_id: identity::load_or_create(&config_file, existing.map_or("", |c| &c.agent.id))?.id,
+        agent_name: options.agent_name.as_deref().map(|n| n.trim().to_string())
+            .or_else(|| existing.map(|c| c.agent.name.clone()))
+            .unwrap_or(hostname),
+        server_url: options.server_url.clone()
+            .or_else(|| existing.map(|c| c.backend.server_url.clone()))
+            .unwrap_or_default(),
+        update_interval: options.update_interval
+            .or_else(|| existing.map(|c| c.agent.update_interval))
+            .unwrap_or(3.0),
+        enable_fan_control: options.enable_fan_control
+            .or_else(|| existing.map(|c| c.hardware.enable_fan_control))
+            .unwrap_or(true),
+        failsafe_speed: options.failsafe_speed
+            .or_else(|| existing.map(|c| c.hardware.failsafe_speed))
+            .unwrap_or(70),
+    });
+    config.auth.enrollment_token = options.enrollment_token.clone();
+
+    save_config(&config, config_file.to_str().unwrap()).await?;
+    if let Some(existing) = existing {
+        history::record_file_change(&config_file, existing, &config);
+    }
+    eprintln!("✅ Configuration saved to: {:?} (agent id {})", config_file, config.agent.id);
+
+    if options.test {
+        #[cfg(target_os = "linux")]
+        let hardware_monitor = LinuxHardwareMonitor::new(config.hardware.clone());
+        if !crate::app::hardware_test::run(&hardware_monitor, &config.hardware, false, true).await? {
+            std::process::exit(1);
+        }
+    }
+    Ok(())
+}
+
 pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
     let config_file = if let Some(p) = config_path {
         PathBuf::from(p)
@@ -89,17 +362,11 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
     println!("\n📋 Configuration:\n");
     println!("Values in [brackets] are defaults - press Enter to use them.\n");
 
-    // Agent ID - Generate silently (don't ask user)
-    let agent_id = if let Some(existing) = &existing_config {
-        // Keep existing ID
-        existing.agent.id.clone()
-    } else {
-        // Generate new ID: OS-hostname-UUID (short UUID: first 8 chars)
-        let os_name = std::env::consts::OS;
-        let unique_id = Uuid::new_v4();
-        let short_uuid = &unique_id.to_string()[..8];
-        format!("{}-{}-{}", os_name, hostname, short_uuid)
-    };
+    // Agent ID - not asked; identity.json keeps it across config rewrites
+    let agent_id = identity::load_or_create(
+        &config_file,
+        existing_config.as_ref().map_or("", |c| &c.agent.id),
+    )?.id;
 
     // Agent Name - Just use hostname
     let default_name = if let Some(existing) = &existing_config {
@@ -114,18 +381,52 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
     let agent_name = agent_name.trim();
     let agent_name = if agent_name.is_empty() { default_name.clone() } else { agent_name.to_string() };
 
-    // Server URL
-    let default_url = if let Some(ref existing) = existing_config {
+    // Server URL - tested before anything is saved, so a typo shows up now rather
+    // than as a reconnect loop in the log
+    let mut default_url = if let Some(ref existing) = existing_config {
         existing.backend.server_url.clone()
     } else {
         "ws://[YOUR_HUB_IP]:3143/websocket".to_string()
     };
-    print!("Backend Server URL [{}]: ", default_url);
-    io::stdout().flush()?;
-    let mut server_url = String::new();
-    io::stdin().read_line(&mut server_url)?;
-    let server_url = server_url.trim();
-    let server_url = if server_url.is_empty() { default_url } else { server_url.to_string() };
+    let connection_timeout = existing_config.as_ref().map_or(10.0, |c| c.backend.connection_timeout);
+    let route = existing_config.as_ref().map(|c| Route::from(&c.backend)).unwrap_or_default();
+    let server_url = loop {
+        print!("Backend Server URL [{}]: ", default_url);
+        io::stdout().flush()?;
+        let mut server_url = String::new();
+        io::stdin().read_line(&mut server_url)?;
+        let server_url = server_url.trim();
+        let server_url = if server_url.is_empty() { default_url.clone() } else { server_url.to_string() };
+
+        print!("   Testing connection... ");
+        io::stdout().flush()?;
+        match check_connection(&server_url, &route, connection_timeout).await {
+            Ok(elapsed) => {
+                println!("✅ Hub reachable ({} ms)", elapsed.as_millis());
+                break server_url;
+            }
+            Err(e) => {
+                println!("❌ {}", e);
+                print!("   [R]e-enter URL, [p]roceed anyway, or [a]bort? ");
+                io::stdout().flush()?;
+                let mut choice = String::new();
+                // EOF (piped input ran out) aborts rather than re-prompting forever
+                let eof = io::stdin().read_line(&mut choice)? == 0;
+                match choice.trim().to_ascii_lowercase().as_str() {
+                    "p" => break server_url,
+                    _ if eof => {
+                        println!("\nSetup aborted - nothing was saved.");
+                        return Ok(());
+                    }
+                    "a" => {
+                        println!("\nSetup aborted - nothing was saved.");
+                        return Ok(());
+                    }
+                    _ => default_url = server_url,
+                }
+            }
+        }
+    };
 
     // Update Interval - 3.0 for new, existing value for re-run
     let default_interval = if let Some(ref existing) = existing_config {
@@ -166,41 +467,19 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
         failsafe_str.trim().parse::<u8>().unwrap_or(default_failsafe).min(100)
     };
 
-    // Create config
-    let config = AgentConfig {
-        agent: AgentSettings {
-            id: agent_id,
-            name: agent_name,
-            update_interval,
-            log_level: "INFO".to_string(),
-        },
-        backend: BackendSettings {
-            server_url,
-            reconnect_interval: 5.0,
-            max_reconnect_attempts: -1,
-            connection_timeout: 10.0,
-        },
-        hardware: HardwareSettings {
-            enable_fan_control,
-            enable_sensor_monitoring: true,
-            fan_step_percent: 5,
-            hysteresis_temp: 3.0,
-            emergency_temp: 85.0,
-            failsafe_speed,
-            excluded_sensors: Vec::new(),
-        },
-        logging: LoggingSettings {
-            enable_file_logging: true,
-            log_file: "/var/log/pankha-agent/agent.log".to_string(),
-            max_log_size_mb: 10,
-            log_retention_days: 7,
-        },
-        // Wizard setups start without credentials; enrollment needs a deploy
-        // token from the Hub's Deployment page (see enrollment_token)
-        auth: AuthSettings::default(),
-    };
+    let config = build_config(existing_config.as_ref(), SetupChoices {
+        agent_id,
+        agent_name,
+        server_url,
+        update_interval,
+        enable_fan_control,
+        failsafe_speed,
+    });
 
     save_config(&config, config_file.to_str().unwrap()).await?;
+    if let Some(existing) = &existing_config {
+        history::record_file_change(&config_file, existing, &config);
+    }
     println!("\n✅ Configuration saved to: {:?}", config_file);
 
     // Test hardware discovery
@@ -222,7 +501,7 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
         if !sensors.is_empty() {
             println!("\n📊 Sensors:");
             for sensor in sensors.iter().take(5) {
-                println!("  • {} - {:.1}°C", sensor.name, sensor.temperature);
+                println!("  • {} - {}", sensor.friendly_name(), config.hardware.temperature_unit.format(sensor.temperature));
             }
             if sensors.len() > 5 {
                 println!("  ... and {} more", sensors.len() - 5);
@@ -277,7 +556,7 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
         io::stdin().read_line(&mut start_input)?;
 
         if !start_input.trim().eq_ignore_ascii_case("n") {
-            match start_daemon_with_log_level(None) {
+            match start_daemon_with_log_level(None, None, false) {
                 Ok(_) => {}
                 Err(e) => {
                     println!("   ⚠ Could not start agent: {}", e);
@@ -302,3 +581,89 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_flag_problems_are_reported_together() {
+        let options = SetupOptions {
+            server_url: None,
+            update_interval: Some(4.0),
+            failsafe_speed: Some(55),
+            ..SetupOptions::default()
+        };
+        let problems = non_interactive_problems(&options, false);
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("--server-url is required"));
+
+        // An existing config (with --force) supplies the URL
+        let options = SetupOptions { update_interval: Some(2.0), ..SetupOptions::default() };
+        assert!(non_interactive_problems(&options, true).is_empty());
+
+        let options = SetupOptions { server_url: Some("http://hub:3143".to_string()), ..SetupOptions::default() };
+        assert_eq!(non_interactive_problems(&options, false).len(), 1);
+    }
+
+    #[test]
+    fn rebuilt_config_keeps_settings_the_wizard_does_not_ask_about() {
+        let mut existing = AgentConfig::default();
+        existing.hardware.escalate_on_crit_alarm = false;
+        existing.hardware.emergency_temp_by_type.insert("hdd".to_string(), 55.0);
+
+        let config = build_config(Some(&existing), SetupChoices {
+            agent_id: "linux-db01-12345678".to_string(),
+            agent_name: "db01".to_string(),
+            server_url: "wss://hub.example:3143/websocket".to_string(),
+            update_interval: 2.0,
+            enable_fan_control: false,
+            failsafe_speed: 80,
+        });
+        assert_eq!(config.agent.name, "db01");
+        assert_eq!(config.hardware.failsafe_speed, 80);
+        assert!(!config.hardware.escalate_on_crit_alarm);
+        assert_eq!(config.hardware.emergency_temp_by_type["hdd"], 55.0);
+    }
+
+    #[tokio::test]
+    async fn setup_over_an_existing_or_corrupt_config_keeps_the_agent_id() {
+        let dir = std::env::temp_dir().join(format!("pankha-setup-identity-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        let config_file = path.to_str().unwrap();
+
+        // An older agent's config.json: the id moves to identity.json on first load
+        let mut old = AgentConfig::default();
+        old.agent.id = "linux-rack07-5e6f7a8b".to_string();
+        old.backend.server_url = "ws://10.0.0.5:3143/websocket".to_string();
+        std::fs::write(&path, serde_json::to_string(&old).unwrap()).unwrap();
+        assert_eq!(load_config(Some(config_file)).await.unwrap().agent.id, "linux-rack07-5e6f7a8b");
+        let on_disk: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert!(on_disk["agent"].get("id").is_none(), "{}", on_disk);
+
+        // Wizard overwrite
+        let options = SetupOptions { agent_name: Some("rack07".to_string()), force: true, ..SetupOptions::default() };
+        run_non_interactive_setup(Some(config_file), &options).await.unwrap();
+        assert_eq!(load_config(Some(config_file)).await.unwrap().agent.id, "linux-rack07-5e6f7a8b");
+
+        // Corrupt config.json: loading fails, a regenerated config keeps the id
+        std::fs::write(&path, "{\"agent\": {\"name\": ").unwrap();
+        assert!(load_config(Some(config_file)).await.is_err());
+        let options = SetupOptions {
+            server_url: Some("ws://10.0.0.5:3143/websocket".to_string()),
+            force: true,
+            ..SetupOptions::default()
+        };
+        run_non_interactive_setup(Some(config_file), &options).await.unwrap();
+        let config = load_config(Some(config_file)).await.unwrap();
+        assert_eq!(config.agent.id, "linux-rack07-5e6f7a8b");
+        assert_eq!(config.backend.server_url, "ws://10.0.0.5:3143/websocket");
+
+        // Config deleted outright
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(load_config(Some(config_file)).await.unwrap().agent.id, "linux-rack07-5e6f7a8b");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
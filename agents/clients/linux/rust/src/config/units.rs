@@ -0,0 +1,77 @@
+//! Temperature unit of locally rendered output (`hardware.temperature_unit`).
+//!
+//! The agent measures, compares and sends Celsius everywhere; only what it renders
+//! for people and local scripts (`--test`, the setup wizard, hardware-info.json) is
+//! converted, here and nowhere else.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemperatureUnit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+}
+
+impl TemperatureUnit {
+    /// Name as written in config.json and JSON output.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Celsius => "celsius",
+            Self::Fahrenheit => "fahrenheit",
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+        }
+    }
+
+    /// `celsius` in this unit.
+    pub fn convert(self, celsius: f64) -> f64 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius * 9.0 / 5.0 + 32.0,
+        }
+    }
+
+    /// `celsius` in this unit with one decimal and the symbol, e.g. "113.0°F".
+    pub fn format(self, celsius: f64) -> String {
+        format!("{:.1}{}", self.convert(celsius), self.symbol())
+    }
+
+    /// A limit in this unit without decimals, e.g. "158°F".
+    pub fn format_whole(self, celsius: f64) -> String {
+        format!("{:.0}{}", self.convert(celsius), self.symbol())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let f = TemperatureUnit::Fahrenheit;
+        assert_eq!(f.convert(0.0), 32.0);
+        assert_eq!(f.convert(100.0), 212.0);
+        assert_eq!(f.convert(-40.0), -40.0);
+        assert_eq!(f.format(45.0), "113.0°F");
+        assert_eq!(f.format_whole(70.0), "158°F");
+
+        let c = TemperatureUnit::Celsius;
+        assert_eq!(c.convert(45.34), 45.34);
+        assert_eq!(c.format(45.34), "45.3°C");
+    }
+
+    #[test]
+    fn config_spelling() {
+        assert_eq!(serde_json::to_value(TemperatureUnit::Fahrenheit).unwrap(), "fahrenheit");
+        let unit: TemperatureUnit = serde_json::from_value(serde_json::json!("celsius")).unwrap();
+        assert_eq!(unit, TemperatureUnit::Celsius);
+        assert!(serde_json::from_value::<TemperatureUnit>(serde_json::json!("kelvin")).is_err());
+    }
+}
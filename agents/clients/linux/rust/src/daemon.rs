@@ -4,8 +4,19 @@ pub mod pid;
 pub mod systemd;
 pub mod control;
 pub mod status;
+pub mod notify;
+pub mod platform;
+pub mod safe_mode;
+pub mod state_file;
+#[cfg(target_os = "linux")]
+pub mod privileges;
+#[cfg(target_os = "linux")]
+pub mod shutdown;
+#[cfg(target_os = "linux")]
+pub mod uninstall;
 
-pub const PID_FILE: &str = "/run/pankha-agent/pankha-agent.pid";
+/// System runtime/log directories; see `platform` for the non-root fallbacks
+pub const RUN_DIR: &str = "/run/pankha-agent";
 pub const LOG_DIR: &str = "/var/log/pankha-agent";
 pub const SYSTEMD_SERVICE_PATH: &str = "/etc/systemd/system/pankha-agent.service";
 
@@ -14,11 +25,12 @@ Description=Pankha Hardware Monitoring Agent
 After=network.target
 
 [Service]
-Type=forking
-ExecStart={{EXEC_PATH}} --start
-ExecStop={{EXEC_PATH}} --stop
-ExecReload={{EXEC_PATH}} --restart
-PIDFile=/run/pankha-agent/pankha-agent.pid
+Type=notify
+ExecStart={{EXEC_PATH}} --systemd
+ExecReload=/bin/kill -HUP $MAINPID
+NotifyAccess=main
+WatchdogSec=60
+TimeoutStartSec=60
 Restart=on-failure
 RestartSec=10
 User=root
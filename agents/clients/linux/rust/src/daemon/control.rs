@@ -3,16 +3,53 @@ use std::fs;
 use anyhow::Result;
 
 use crate::daemon::pid::*;
-use crate::daemon::systemd::is_systemd_service_active;
-use crate::daemon::LOG_DIR;
+use crate::daemon::platform::{find_log_file, log_file_for_write, process_control};
+use crate::daemon::systemd::{is_notify_service_installed, is_systemd_service_active};
 use crate::config::types::AgentConfig;
+use crate::config::persistence::config_file_contents;
 
-pub fn start_daemon_with_log_level(log_level: Option<String>) -> Result<()> {
+/// How long a stopping agent gets to restore fans before it is force-killed
+pub(crate) const STOP_GRACE: std::time::Duration = std::time::Duration::from_secs(10);
+
+/// `logging.log_file` from config.json next to the binary, if readable
+pub(crate) fn configured_log_file(config_path: &std::path::Path) -> Option<std::path::PathBuf> {
+    let content = fs::read_to_string(config_path).ok()?;
+    let config: AgentConfig = serde_json::from_str(&content).ok()?;
+    Some(config.logging.log_file.into())
+}
+
+/// Stop `pid` via the platform process layer and clean up its PID file
+fn stop_pid(pid: u32) -> Result<()> {
+    println!("Stopping Pankha Rust Agent (PID: {})...", pid);
+    if process_control().stop(pid, STOP_GRACE)? {
+        println!("WARNING: Agent did not exit in time and was force-killed");
+    }
+    remove_pid_file()?;
+    println!("Agent stopped");
+    Ok(())
+}
+
+pub fn start_daemon_with_log_level(log_level: Option<String>, log_format: Option<String>, dry_run: bool) -> Result<()> {
     if is_running() {
         eprintln!("ERROR: Agent is already running (PID: {:?})", get_pid()?);
         process::exit(1);
     }
 
+    // A Type=notify service must be started by systemd, or it would run unsupervised
+    if is_notify_service_installed() {
+        if log_level.is_some() || log_format.is_some() || dry_run {
+            println!("Note: --log-level/--log-format/--dry-run are ignored for the systemd service; set them in config.json");
+        }
+        let status = process::Command::new("systemctl")
+            .args(["start", "pankha-agent"])
+            .status()?;
+        if !status.success() {
+            return Err(anyhow::anyhow!("systemctl start pankha-agent failed (see: journalctl -u pankha-agent)"));
+        }
+        println!("Agent started via systemd");
+        return Ok(());
+    }
+
     // Check if config file exists
     let exe_path = std::env::current_exe()?;
     let config_path = exe_path
@@ -31,37 +68,39 @@ pub fn start_daemon_with_log_level(log_level: Option<String>) -> Result<()> {
 
     println!("\x1b[32mStarting pankha-agent v{} ({})\x1b[0m", crate::version::VERSION, std::env::consts::ARCH);
 
-    // Prepare log file
-    ensure_directories()?;
-    let log_path = format!("{}/agent.log", LOG_DIR);
+    // Prepare log file (config's log_file, or a per-user fallback when not root)
+    let log_path = log_file_for_write(configured_log_file(&config_path).as_deref())?;
     let log_file = fs::OpenOptions::new()
         .create(true)
         .append(true)
-        .open(&log_path)?;
+        .open(&log_path)
+        .map_err(|e| anyhow::anyhow!("Cannot open log file {}: {}", log_path.display(), e))?;
 
     // Spawn new process in daemon mode using --daemon-child (internal flag)
-    let mut cmd = process::Command::new(&exe_path);
-    cmd.arg("--daemon-child");
+    let mut args = vec!["--daemon-child".to_string()];
 
     // Pass log level to daemon child if specified
     if let Some(level) = log_level {
-        cmd.arg("--log-level").arg(level);
+        args.extend(["--log-level".to_string(), level]);
+    }
+    if let Some(format) = log_format {
+        args.extend(["--log-format".to_string(), format]);
+    }
+    if dry_run {
+        args.push("--dry-run".to_string());
     }
 
-    let child = cmd
-        .current_dir(std::env::current_dir()?)
-        .stdin(process::Stdio::null())
-        .stdout(log_file.try_clone()?)
-        .stderr(log_file)
-        .spawn()?;
-
-    let pid = child.id();
+    let pid = process_control().start(&exe_path, &args, log_file)?;
 
     // Save PID
-    save_pid(pid)?;
+    if let Err(e) = save_pid(pid) {
+        // Without a PID file --stop/--status can't find the child; don't leave it orphaned
+        let _ = process_control().stop(pid, STOP_GRACE);
+        return Err(e);
+    }
 
     println!("Agent started successfully (PID: {})", pid);
-    println!("Logs: tail -f {}/agent.log", LOG_DIR);
+    println!("Logs: tail -f {}", log_path.display());
 
     Ok(())
 }
@@ -96,33 +135,13 @@ pub fn stop_daemon() -> Result<()> {
     }
 
     if let Some(pid) = get_pid()? {
-        println!("Stopping Pankha Rust Agent (PID: {})...", pid);
-
-        // Send SIGTERM
-        unsafe { libc::kill(pid as i32, libc::SIGTERM) };
-
-        // Wait for graceful shutdown
-        for _ in 0..10 {
-            if !is_running() {
-                break;
-            }
-            std::thread::sleep(std::time::Duration::from_secs(1));
-        }
-
-        // Force kill if necessary
-        if is_running() {
-            println!("WARNING: Force killing agent...");
-            unsafe { libc::kill(pid as i32, libc::SIGKILL) };
-        }
-
-        remove_pid_file()?;
-        println!("Agent stopped");
+        stop_pid(pid)?;
     }
 
     Ok(())
 }
 
-pub fn restart_daemon_with_log_level(log_level: Option<String>) -> Result<()> {
+pub fn restart_daemon_with_log_level(log_level: Option<String>, log_format: Option<String>, dry_run: bool) -> Result<()> {
     println!("\x1b[32mRestarting pankha-agent v{} ({})\x1b[0m", crate::version::VERSION, std::env::consts::ARCH);
 
     // Check if systemd service is actively managing the process
@@ -151,27 +170,7 @@ pub fn restart_daemon_with_log_level(log_level: Option<String>) -> Result<()> {
     // Stop the agent if it's running
     if is_running() {
         if let Some(pid) = get_pid()? {
-            println!("Stopping Pankha Rust Agent (PID: {})...", pid);
-
-            // Send SIGTERM
-            unsafe { libc::kill(pid as i32, libc::SIGTERM) };
-
-            // Wait for graceful shutdown
-            for _ in 0..10 {
-                if !is_running() {
-                    break;
-                }
-                std::thread::sleep(std::time::Duration::from_secs(1));
-            }
-
-            // Force kill if necessary
-            if is_running() {
-                println!("WARNING: Force killing agent...");
-                unsafe { libc::kill(pid as i32, libc::SIGKILL) };
-            }
-
-            remove_pid_file()?;
-            println!("Agent stopped");
+            stop_pid(pid)?;
         }
         std::thread::sleep(std::time::Duration::from_secs(1));
     } else {
@@ -179,7 +178,7 @@ pub fn restart_daemon_with_log_level(log_level: Option<String>) -> Result<()> {
     }
 
     // Always start the agent (whether it was running or not)
-    start_daemon_with_log_level(log_level)
+    start_daemon_with_log_level(log_level, log_format, dry_run)
 }
 
 pub fn set_log_level_runtime(level: &str) -> Result<()> {
@@ -214,19 +213,21 @@ pub fn set_log_level_runtime(level: &str) -> Result<()> {
     config.agent.log_level = level.to_uppercase();
 
     // Save updated config
-    let content = serde_json::to_string_pretty(&config)?;
+    let content = config_file_contents(&config)?;
     std::fs::write(&config_path, content)?;
 
     println!("Log level updated: {} → {}", old_level, level.to_uppercase());
     println!("Configuration saved to: {:?}", config_path);
 
-    // Send SIGHUP to running agent to reload config
+    // Ask the running agent to reload config (SIGHUP on Unix)
     if let Some(pid) = get_pid()? {
         println!("Sending reload signal to agent (PID: {})...", pid);
-        unsafe { libc::kill(pid as i32, libc::SIGHUP) };
+        process_control().reload(pid)?;
         println!("✅ Log level changed successfully");
         println!("\nNote: New log level will be applied immediately.");
-        println!("      Logs are written to: {}/agent.log", LOG_DIR);
+        if let Some(log_path) = find_log_file(configured_log_file(&config_path).as_deref()) {
+            println!("      Logs are written to: {}", log_path.display());
+        }
         println!("      View logs with: ./pankha-agent -l");
     }
 
@@ -0,0 +1,133 @@
+//! systemd service notifications (sd_notify protocol) and journald detection.
+//!
+//! With `Type=notify`, systemd hands us `$NOTIFY_SOCKET`; datagrams like `READY=1`
+//! report lifecycle state. All calls are no-ops when not running under systemd.
+
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// `READY=1` has been sent; later calls to `ready()` are skipped.
+static READY_SENT: AtomicBool = AtomicBool::new(false);
+
+/// True when systemd is waiting for our notifications.
+pub fn is_enabled() -> bool {
+    std::env::var_os("NOTIFY_SOCKET").is_some_and(|s| !s.is_empty())
+}
+
+/// True when `WatchdogSec=` is configured for this process.
+pub fn watchdog_enabled() -> bool {
+    let usec_set = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .is_some_and(|usec| usec > 0);
+    // WATCHDOG_PID, if present, must be us (not inherited from a parent)
+    let pid_matches = std::env::var("WATCHDOG_PID")
+        .map(|pid| pid.parse::<u32>().ok() == Some(std::process::id()))
+        .unwrap_or(true);
+    usec_set && pid_matches
+}
+
+/// Send a raw state string (e.g. "READY=1"). Returns false if not delivered.
+pub fn notify(state: &str) -> bool {
+    let Some(socket) = std::env::var_os("NOTIFY_SOCKET") else {
+        return false;
+    };
+    let Some(addr) = socket_addr(&socket.to_string_lossy()) else {
+        return false;
+    };
+    UnixDatagram::unbound()
+        .and_then(|sock| sock.send_to_addr(state.as_bytes(), &addr))
+        .is_ok()
+}
+
+/// `NOTIFY_SOCKET` is a filesystem path, or `@name` for the abstract namespace.
+fn socket_addr(socket: &str) -> Option<SocketAddr> {
+    match socket.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            SocketAddr::from_abstract_name(name.as_bytes()).ok()
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(_) => None,
+        None if socket.starts_with('/') => SocketAddr::from_pathname(socket).ok(),
+        None => None,
+    }
+}
+
+/// Startup finished (registered with the backend, or holding failsafe). Sent once.
+pub fn ready() {
+    if is_enabled() && !READY_SENT.swap(true, Ordering::SeqCst) {
+        notify(&format!("READY=1\nSTATUS=Running (PID {})", std::process::id()));
+    }
+}
+
+/// How often to send the keep-alive: half of `WatchdogSec=`, as systemd advises.
+/// `None` when no watchdog is configured.
+pub fn watchdog_interval() -> Option<Duration> {
+    if !watchdog_enabled() {
+        return None;
+    }
+    let usec = std::env::var("WATCHDOG_USEC").ok()?.parse::<u64>().ok()?;
+    Some(Duration::from_micros(usec / 2))
+}
+
+/// Keep-alive for `WatchdogSec=`.
+pub fn watchdog() {
+    if watchdog_enabled() {
+        notify("WATCHDOG=1");
+    }
+}
+
+/// Shutdown has begun; systemd stops waiting for further keep-alives.
+pub fn stopping() {
+    if is_enabled() {
+        notify("STOPPING=1");
+    }
+}
+
+/// Human-readable status line shown by `systemctl status`.
+pub fn status(text: &str) {
+    if is_enabled() {
+        notify(&format!("STATUS={}", text));
+    }
+}
+
+/// True when stderr is connected to journald (`$JOURNAL_STREAM` = "dev:inode" of
+/// our stderr), so plain stdout logging already lands in the journal.
+pub fn is_journald_stream() -> bool {
+    let Ok(stream) = std::env::var("JOURNAL_STREAM") else {
+        return false;
+    };
+    let Some((dev, ino)) = stream.split_once(':') else {
+        return false;
+    };
+    let Ok(meta) = std::fs::metadata("/proc/self/fd/2") else {
+        return false;
+    };
+    dev.parse::<u64>().ok() == Some(meta.dev()) && ino.parse::<u64>().ok() == Some(meta.ino())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delivers_state_to_notify_socket() {
+        let path = std::env::temp_dir().join(format!("pankha-notify-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let listener = UnixDatagram::bind(&path).unwrap();
+
+        let addr = socket_addr(&path.to_string_lossy()).unwrap();
+        UnixDatagram::unbound().unwrap().send_to_addr(b"WATCHDOG=1", &addr).unwrap();
+
+        let mut buf = [0u8; 64];
+        let n = listener.recv(&mut buf).unwrap();
+        assert_eq!(&buf[..n], b"WATCHDOG=1");
+        assert!(socket_addr("relative.sock").is_none());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
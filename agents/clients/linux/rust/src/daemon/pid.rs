@@ -1,52 +1,225 @@
+//! The PID file of a background agent (`--start`), and whether the process it names
+//! is still that agent.
+//!
+//! After a reboot the PID in a leftover file often belongs to some unrelated process,
+//! which must neither block `--start` nor receive `--stop`'s SIGTERM. The file holds
+//! the PID on its first line, followed by the binary and the process start time:
+//!
+//! ```text
+//! 4321
+//! exe=/opt/pankha/pankha-agent
+//! start_time=1760601234
+//! ```
+//!
+//! Files written before those lines existed hold only the PID and are checked by
+//! process name.
+
 use std::fs;
-use std::path::Path;
-use anyhow::Result;
+use std::path::{Path, PathBuf};
 
-use crate::daemon::{PID_FILE, LOG_DIR};
+use anyhow::{Context, Result};
 
-pub fn ensure_directories() -> Result<()> {
-    fs::create_dir_all("/run/pankha-agent")?;
-    fs::create_dir_all(LOG_DIR)?;
-    Ok(())
+use crate::daemon::platform::{find_pid_file, pid_file_for_write, process_control};
+use crate::daemon::systemd::systemd_main_pid;
+
+/// Contents of the PID file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PidRecord {
+    pub(crate) pid: u32,
+    /// Binary the agent was started from
+    pub(crate) exe: Option<PathBuf>,
+    /// Process start time (Unix seconds); a reused PID has a later one
+    pub(crate) start_time: Option<u64>,
+}
+
+impl PidRecord {
+    fn parse(content: &str) -> Option<Self> {
+        let mut lines = content.lines();
+        let pid = lines.next()?.trim().parse::<u32>().ok()?;
+        let mut record = Self { pid, exe: None, start_time: None };
+        for line in lines {
+            match line.split_once('=') {
+                Some(("exe", exe)) => record.exe = Some(PathBuf::from(exe.trim())),
+                Some(("start_time", time)) => record.start_time = time.trim().parse().ok(),
+                _ => {}
+            }
+        }
+        Some(record)
+    }
+
+    fn to_file_contents(&self) -> String {
+        let mut contents = format!("{}\n", self.pid);
+        if let Some(exe) = &self.exe {
+            contents.push_str(&format!("exe={}\n", exe.display()));
+        }
+        if let Some(time) = self.start_time {
+            contents.push_str(&format!("start_time={}\n", time));
+        }
+        contents
+    }
+}
+
+/// What the process table says about a PID.
+struct ProcessInfo {
+    exe: Option<PathBuf>,
+    start_time: u64,
+}
+
+fn process_info(pid: u32) -> Option<ProcessInfo> {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut sys = sysinfo::System::new();
+    sys.refresh_process(pid);
+    sys.process(pid).map(|p| ProcessInfo {
+        // Not readable for another user's process without root; argv[0] is
+        exe: p.exe().map(Path::to_path_buf).or_else(|| p.cmd().first().map(PathBuf::from)),
+        start_time: p.start_time(),
+    })
+}
+
+/// A binary replaced by self-update shows up as "<path> (deleted)".
+fn strip_deleted(exe: &Path) -> PathBuf {
+    let exe = exe.to_string_lossy();
+    PathBuf::from(exe.strip_suffix(" (deleted)").unwrap_or(&exe))
+}
+
+/// Why the live process `record` names isn't the agent that wrote the record, or
+/// None if it is (or there's no way to tell).
+fn not_ours(record: &PidRecord, process: Option<&ProcessInfo>, own_exe: Option<&Path>) -> Option<String> {
+    let process = process?;
+    if let Some(recorded) = record.start_time {
+        return (recorded != process.start_time).then(|| {
+            format!("a process started at {} instead of {} (PID reused)", process.start_time, recorded)
+        });
+    }
+
+    let exe = strip_deleted(process.exe.as_deref()?);
+    let name = exe.file_name()?.to_string_lossy().to_string();
+    let ours = record.exe.as_deref() == Some(exe.as_path())
+        || own_exe.and_then(Path::file_name).is_some_and(|own| own.to_string_lossy() == name)
+        || name.starts_with("pankha-agent");
+    (!ours).then(|| format!("{} instead of pankha-agent", exe.display()))
+}
+
+fn read_record() -> Result<Option<PidRecord>> {
+    let Some(path) = find_pid_file() else {
+        return Ok(None);
+    };
+    let content = fs::read_to_string(&path)?;
+    PidRecord::parse(&content)
+        .map(Some)
+        .with_context(|| format!("Invalid PID file {}", path.display()))
 }
 
 pub fn get_pid() -> Result<Option<u32>> {
-    if Path::new(PID_FILE).exists() {
-        let content = fs::read_to_string(PID_FILE)?;
-        let pid = content.trim().parse::<u32>()?;
-        Ok(Some(pid))
-    } else {
-        Ok(None)
+    match read_record()? {
+        Some(record) => Ok(Some(record.pid)),
+        // `--systemd` mode writes no PID file; systemd tracks the process
+        None => Ok(systemd_main_pid()),
     }
 }
 
 pub fn is_running() -> bool {
-    if let Ok(Some(pid)) = get_pid() {
-        // Check if process is still alive by sending signal 0
-        let alive = unsafe { libc::kill(pid as i32, 0) == 0 };
-
-        if !alive {
-            // Process is dead but PID file exists - cleanup stale PID
-            if let Err(e) = remove_pid_file() {
-                eprintln!("Warning: Could not remove stale PID file: {}", e);
-            }
-            return false;
-        }
-        true
+    let record = match read_record() {
+        Ok(Some(record)) => record,
+        // No PID file: a systemd-managed agent, whose PID needs no checking
+        Ok(None) => return systemd_main_pid().is_some_and(|pid| process_control().is_running(pid)),
+        Err(_) => return false,
+    };
+
+    let stale = if !process_control().is_running(record.pid) {
+        // Process is dead but PID file exists
+        None
     } else {
-        false
+        let own_exe = std::env::current_exe().ok();
+        match not_ours(&record, process_info(record.pid).as_ref(), own_exe.as_deref()) {
+            Some(reason) => Some(reason),
+            None => return true,
+        }
+    };
+
+    if let Some(reason) = stale {
+        eprintln!("Warning: PID file names PID {}, which is now {}; treating it as stale", record.pid, reason);
     }
+    if let Err(e) = remove_pid_file() {
+        eprintln!("Warning: Could not remove stale PID file: {}", e);
+    }
+    false
 }
 
 pub fn save_pid(pid: u32) -> Result<()> {
-    ensure_directories()?;
-    fs::write(PID_FILE, pid.to_string())?;
+    let path = pid_file_for_write()?;
+    let record = PidRecord {
+        pid,
+        exe: std::env::current_exe().ok(),
+        start_time: process_info(pid).map(|p| p.start_time),
+    };
+    fs::write(&path, record.to_file_contents())
+        .with_context(|| format!("Failed to write PID file {}", path.display()))?;
     Ok(())
 }
 
 pub fn remove_pid_file() -> Result<()> {
-    if Path::new(PID_FILE).exists() {
-        fs::remove_file(PID_FILE)?;
+    if let Some(path) = find_pid_file() {
+        fs::remove_file(&path)
+            .with_context(|| format!("Failed to remove PID file {}", path.display()))?;
     }
     Ok(())
 }
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+
+    fn spawn_sleep() -> std::process::Child {
+        std::process::Command::new("sleep").arg("30").spawn().unwrap()
+    }
+
+    #[test]
+    fn record_round_trips_and_old_files_parse() {
+        let record = PidRecord { pid: 4321, exe: Some("/opt/pankha/pankha-agent".into()), start_time: Some(1_760_601_234) };
+        assert_eq!(PidRecord::parse(&record.to_file_contents()), Some(record));
+        assert_eq!(PidRecord::parse("4321"), Some(PidRecord { pid: 4321, exe: None, start_time: None }));
+        assert_eq!(PidRecord::parse("not a pid\n"), None);
+    }
+
+    #[test]
+    fn a_reused_pid_is_not_the_agent() {
+        let mut child = spawn_sleep();
+        let pid = child.id();
+        let process = process_info(pid).expect("spawned process is visible");
+        let agent = Path::new("/opt/pankha/pankha-agent");
+
+        // Written by the agent, now held by an unrelated process started later
+        let reused = PidRecord { pid, exe: Some(agent.into()), start_time: Some(process.start_time - 3600) };
+        assert!(not_ours(&reused, Some(&process), Some(agent)).unwrap().contains("PID reused"));
+        // An old-format file: only the binary can tell
+        let old = PidRecord { pid, exe: None, start_time: None };
+        assert!(not_ours(&old, Some(&process), Some(agent)).is_some());
+
+        // The process that wrote the record
+        let own = PidRecord { pid, exe: process.exe.clone(), start_time: Some(process.start_time) };
+        assert_eq!(not_ours(&own, Some(&process), Some(agent)), None);
+        assert_eq!(not_ours(&old, Some(&process), process.exe.as_deref()), None);
+
+        child.kill().unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn a_dead_pid_is_stale() {
+        let mut child = spawn_sleep();
+        let pid = child.id();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert!(!process_control().is_running(pid));
+        assert!(process_info(pid).is_none());
+    }
+
+    #[test]
+    fn self_updated_binary_still_matches() {
+        let process = ProcessInfo { exe: Some("/opt/pankha/pankha-agent (deleted)".into()), start_time: 100 };
+        let old = PidRecord { pid: 1, exe: None, start_time: None };
+        assert_eq!(not_ours(&old, Some(&process), Some(Path::new("/opt/pankha/pankha-agent"))), None);
+    }
+}
@@ -0,0 +1,325 @@
+//! Process management across platforms: spawning the background agent, signalling it,
+//! and choosing where its PID file and logs live.
+//!
+//! System locations (`/run/pankha-agent`, `/var/log/pankha-agent`) are preferred. When
+//! they aren't writable (agent running as a regular user), per-user fallbacks are used:
+//! `$XDG_RUNTIME_DIR/pankha-agent` or the executable directory for the PID file, and
+//! `$XDG_STATE_HOME/pankha` (default `~/.local/state/pankha`) for logs.
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+
+use crate::daemon::{LOG_DIR, RUN_DIR};
+
+pub const PID_FILE_NAME: &str = "pankha-agent.pid";
+pub const LOG_FILE_NAME: &str = "agent.log";
+pub const STATE_FILE_NAME: &str = "state.json";
+
+/// Start/stop/inspect the background agent process.
+pub trait ProcessControl {
+    /// Spawn `exe args...` detached from the terminal with stdout/stderr appended to
+    /// `log`. Returns the child's PID.
+    fn start(&self, exe: &Path, args: &[String], log: File) -> Result<u32>;
+
+    /// Ask `pid` to shut down, forcing it after `grace`. Returns true if it had to be forced.
+    fn stop(&self, pid: u32, grace: Duration) -> Result<bool>;
+
+    /// True if a process with this PID exists (even one we may not signal).
+    fn is_running(&self, pid: u32) -> bool;
+
+    /// Ask `pid` to reload its configuration.
+    fn reload(&self, pid: u32) -> Result<()>;
+}
+
+/// The process layer for the platform we were built for.
+pub fn process_control() -> &'static dyn ProcessControl {
+    #[cfg(unix)]
+    {
+        &UnixProcess
+    }
+    #[cfg(windows)]
+    {
+        &WindowsProcess
+    }
+}
+
+#[cfg(unix)]
+pub struct UnixProcess;
+
+#[cfg(unix)]
+impl UnixProcess {
+    fn signal(pid: u32, signal: libc::c_int) -> std::io::Result<()> {
+        if unsafe { libc::kill(pid as libc::pid_t, signal) } == 0 {
+            Ok(())
+        } else {
+            Err(std::io::Error::last_os_error())
+        }
+    }
+
+    fn signal_error(pid: u32, e: std::io::Error) -> anyhow::Error {
+        if e.raw_os_error() == Some(libc::EPERM) {
+            anyhow!("Permission denied signalling PID {} (started by another user? try sudo)", pid)
+        } else {
+            anyhow!("Failed to signal PID {}: {}", pid, e)
+        }
+    }
+}
+
+#[cfg(unix)]
+impl ProcessControl for UnixProcess {
+    fn start(&self, exe: &Path, args: &[String], log: File) -> Result<u32> {
+        let child = std::process::Command::new(exe)
+            .args(args)
+            .current_dir(std::env::current_dir()?)
+            .stdin(std::process::Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()?;
+        Ok(child.id())
+    }
+
+    fn stop(&self, pid: u32, grace: Duration) -> Result<bool> {
+        Self::signal(pid, libc::SIGTERM).map_err(|e| Self::signal_error(pid, e))?;
+
+        let deadline = std::time::Instant::now() + grace;
+        while std::time::Instant::now() < deadline {
+            if !self.is_running(pid) {
+                return Ok(false);
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+
+        if !self.is_running(pid) {
+            return Ok(false);
+        }
+        Self::signal(pid, libc::SIGKILL).map_err(|e| Self::signal_error(pid, e))?;
+        Ok(true)
+    }
+
+    fn is_running(&self, pid: u32) -> bool {
+        // EPERM: it exists but belongs to someone else (e.g. root daemon, user CLI)
+        match Self::signal(pid, 0) {
+            Ok(()) => true,
+            Err(e) => e.raw_os_error() == Some(libc::EPERM),
+        }
+    }
+
+    fn reload(&self, pid: u32) -> Result<()> {
+        Self::signal(pid, libc::SIGHUP).map_err(|e| Self::signal_error(pid, e))
+    }
+}
+
+/// Windows: `CreateProcess` (via std) detached from the console; the PID file doubles
+/// as the lock file. Liveness and termination go through `tasklist`/`taskkill`.
+#[cfg(windows)]
+pub struct WindowsProcess;
+
+#[cfg(windows)]
+impl ProcessControl for WindowsProcess {
+    fn start(&self, exe: &Path, args: &[String], log: File) -> Result<u32> {
+        use std::os::windows::process::CommandExt;
+        const DETACHED_PROCESS: u32 = 0x0000_0008;
+        const CREATE_NEW_PROCESS_GROUP: u32 = 0x0000_0200;
+
+        let child = std::process::Command::new(exe)
+            .args(args)
+            .creation_flags(DETACHED_PROCESS | CREATE_NEW_PROCESS_GROUP)
+            .stdin(std::process::Stdio::null())
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .spawn()?;
+        Ok(child.id())
+    }
+
+    fn stop(&self, pid: u32, grace: Duration) -> Result<bool> {
+        let pid_arg = pid.to_string();
+        let _ = std::process::Command::new("taskkill").args(["/PID", &pid_arg]).output();
+
+        let deadline = std::time::Instant::now() + grace;
+        while std::time::Instant::now() < deadline {
+            if !self.is_running(pid) {
+                return Ok(false);
+            }
+            std::thread::sleep(Duration::from_millis(250));
+        }
+
+        let status = std::process::Command::new("taskkill").args(["/F", "/PID", &pid_arg]).status()?;
+        if !status.success() && self.is_running(pid) {
+            return Err(anyhow!("taskkill /F failed for PID {} (run as Administrator?)", pid));
+        }
+        Ok(true)
+    }
+
+    fn is_running(&self, pid: u32) -> bool {
+        std::process::Command::new("tasklist")
+            .args(["/FI", &format!("PID eq {}", pid), "/NH", "/FO", "CSV"])
+            .output()
+            .map(|o| String::from_utf8_lossy(&o.stdout).contains(&format!("\"{}\"", pid)))
+            .unwrap_or(false)
+    }
+
+    fn reload(&self, _pid: u32) -> Result<()> {
+        Err(anyhow!("Live reload is not supported on Windows; restart the agent instead"))
+    }
+}
+
+fn exe_dir() -> Option<PathBuf> {
+    std::env::current_exe().ok()?.parent().map(Path::to_path_buf)
+}
+
+/// Directories that may hold the PID file, most preferred first.
+fn run_dir_candidates() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(unix) {
+        dirs.push(PathBuf::from(RUN_DIR));
+        if let Some(xdg) = std::env::var_os("XDG_RUNTIME_DIR").filter(|v| !v.is_empty()) {
+            dirs.push(PathBuf::from(xdg).join("pankha-agent"));
+        }
+    }
+    dirs.extend(exe_dir());
+    dirs
+}
+
+/// Directories that may hold `agent.log`, most preferred first.
+fn log_dir_candidates() -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    if cfg!(windows) {
+        let program_data = std::env::var_os("ProgramData").unwrap_or_else(|| "C:\\ProgramData".into());
+        dirs.push(PathBuf::from(program_data).join("Pankha").join("logs"));
+    } else {
+        dirs.push(PathBuf::from(LOG_DIR));
+        let state_home = std::env::var_os("XDG_STATE_HOME")
+            .filter(|v| !v.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")));
+        if let Some(state_home) = state_home {
+            dirs.push(state_home.join("pankha"));
+        }
+    }
+    dirs
+}
+
+/// Runtime and log directories that hold nothing but agent files (the executable
+/// directory, a PID file fallback, is not one of them).
+pub fn agent_dirs() -> Vec<PathBuf> {
+    let exe_dir = exe_dir();
+    run_dir_candidates()
+        .into_iter()
+        .filter(|dir| Some(dir) != exe_dir.as_ref())
+        .chain(log_dir_candidates())
+        .collect()
+}
+
+/// Whether we may create files in `dir`, which must already exist.
+fn is_writable_dir(dir: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::ffi::OsStrExt;
+        let Ok(path) = std::ffi::CString::new(dir.as_os_str().as_bytes()) else {
+            return false;
+        };
+        unsafe { libc::access(path.as_ptr(), libc::W_OK) == 0 }
+    }
+    #[cfg(windows)]
+    {
+        std::fs::metadata(dir).map(|m| !m.permissions().readonly()).unwrap_or(false)
+    }
+}
+
+/// Create the first usable directory in `candidates`; the error lists every attempt.
+fn first_writable_dir(candidates: Vec<PathBuf>, purpose: &str) -> Result<PathBuf> {
+    let mut attempts = Vec::new();
+    for dir in candidates {
+        match std::fs::create_dir_all(&dir) {
+            Ok(()) if is_writable_dir(&dir) => return Ok(dir),
+            Ok(()) => attempts.push(format!("{}: not writable", dir.display())),
+            Err(e) => attempts.push(format!("{}: {}", dir.display(), e)),
+        }
+    }
+    Err(anyhow!("No writable {} directory (tried {})", purpose, attempts.join("; ")))
+}
+
+/// Where to write the PID file, creating its directory.
+pub fn pid_file_for_write() -> Result<PathBuf> {
+    Ok(first_writable_dir(run_dir_candidates(), "runtime")?.join(PID_FILE_NAME))
+}
+
+/// An existing PID file, searched in preference order (a root daemon's file is found
+/// by a non-root CLI, and vice versa).
+pub fn find_pid_file() -> Option<PathBuf> {
+    run_dir_candidates()
+        .into_iter()
+        .map(|dir| dir.join(PID_FILE_NAME))
+        .find(|path| path.exists())
+}
+
+/// Whether the directory of `path` exists or could be created, and is writable.
+fn has_usable_dir(path: &Path) -> bool {
+    path.parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .is_some_and(|dir| std::fs::create_dir_all(dir).is_ok() && is_writable_dir(dir))
+}
+
+/// Where the daemon should log: `preferred` (the config's `log_file`) if its directory
+/// is usable, else `agent.log` in the first usable platform log directory.
+pub fn log_file_for_write(preferred: Option<&Path>) -> Result<PathBuf> {
+    if let Some(path) = preferred.filter(|path| has_usable_dir(path)) {
+        return Ok(path.to_path_buf());
+    }
+    Ok(first_writable_dir(log_dir_candidates(), "log")?.join(LOG_FILE_NAME))
+}
+
+/// Where to write the state file: `preferred` (the config's `agent.state_file`) if
+/// its directory is usable, else `state.json` in the first usable runtime directory
+/// (a container's read-only /run, a non-root agent).
+pub fn state_file_for_write(preferred: &Path) -> Result<PathBuf> {
+    if has_usable_dir(preferred) {
+        return Ok(preferred.to_path_buf());
+    }
+    Ok(first_writable_dir(run_dir_candidates(), "runtime")?.join(STATE_FILE_NAME))
+}
+
+/// An existing log file: `preferred` first, then each platform log directory.
+pub fn find_log_file(preferred: Option<&Path>) -> Option<PathBuf> {
+    preferred
+        .map(Path::to_path_buf)
+        .into_iter()
+        .chain(log_dir_candidates().into_iter().map(|dir| dir.join(LOG_FILE_NAME)))
+        .find(|path| path.exists())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn falls_back_past_unusable_directories() {
+        let root = std::env::temp_dir().join(format!("pankha-platform-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(&root).unwrap();
+
+        // A regular file where a directory should be can never be created
+        let blocked = root.join("blocked");
+        std::fs::write(&blocked, "").unwrap();
+        let usable = root.join("usable");
+
+        let dir = first_writable_dir(vec![blocked.join("sub"), usable.clone()], "test").unwrap();
+        assert_eq!(dir, usable);
+
+        let err = first_writable_dir(vec![blocked.join("sub")], "test").unwrap_err();
+        assert!(err.to_string().contains("blocked/sub"));
+
+        let log = log_file_for_write(Some(&usable.join("agent.log"))).unwrap();
+        assert_eq!(log, usable.join("agent.log"));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn own_process_is_running() {
+        assert!(process_control().is_running(std::process::id()));
+    }
+}
@@ -0,0 +1,121 @@
+//! Dropping root after startup (`agent.run_as_user`).
+//!
+//! The agent starts as root, opens the hwmon PWM files it controls (the handles live in
+//! `FanInfo`), hands its config, log and PID files to the target user, then switches to
+//! that user before talking to the backend. Fan writes keep going through the open
+//! handles; anything that needs root later (NVML GPU control, self-update, rescans that
+//! find new fans) stops working.
+
+use std::ffi::{CStr, CString};
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Result};
+use tracing::{info, warn};
+
+use crate::daemon::{LOG_DIR, RUN_DIR};
+
+/// A resolved `run_as_user` account.
+#[derive(Debug, Clone)]
+pub struct RunAsUser {
+    pub name: String,
+    pub uid: libc::uid_t,
+    pub gid: libc::gid_t,
+}
+
+/// Look up `name` in the password database.
+pub fn lookup_user(name: &str) -> Result<RunAsUser> {
+    let c_name = CString::new(name).map_err(|_| anyhow!("Invalid user name: {:?}", name))?;
+    let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+    let mut buf = vec![0 as libc::c_char; 16 * 1024];
+    let mut result: *mut libc::passwd = std::ptr::null_mut();
+
+    let rc = unsafe { libc::getpwnam_r(c_name.as_ptr(), &mut pwd, buf.as_mut_ptr(), buf.len(), &mut result) };
+    if rc != 0 {
+        bail!("Failed to look up user '{}': {}", name, std::io::Error::from_raw_os_error(rc));
+    }
+    if result.is_null() {
+        bail!("User '{}' does not exist (agent.run_as_user)", name);
+    }
+    Ok(RunAsUser {
+        name: unsafe { CStr::from_ptr(pwd.pw_name) }.to_string_lossy().to_string(),
+        uid: pwd.pw_uid,
+        gid: pwd.pw_gid,
+    })
+}
+
+/// Give `user` ownership of files the agent keeps writing after the drop. Directories are
+/// only handed over when they are the agent's own (`RUN_DIR`, `LOG_DIR`), so a fallback
+/// like the executable directory stays root-owned.
+pub fn hand_over(user: &RunAsUser, files: &[PathBuf]) {
+    let own_dirs = [Path::new(RUN_DIR), Path::new(LOG_DIR)];
+    for file in files {
+        let dir = file.parent().filter(|dir| own_dirs.contains(dir));
+        for path in std::iter::once(file.as_path()).chain(dir) {
+            if !path.exists() {
+                continue;
+            }
+            if let Err(e) = chown(path, user) {
+                warn!("Could not hand {:?} to user '{}': {}", path, user.name, e);
+            }
+        }
+    }
+}
+
+fn chown(path: &Path, user: &RunAsUser) -> std::io::Result<()> {
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    if unsafe { libc::chown(c_path.as_ptr(), user.uid, user.gid) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+/// Switch the whole process (glibc/musl apply set*id to every thread) to `user`, then
+/// verify root can't be regained.
+pub fn drop_privileges(user: &RunAsUser) -> Result<()> {
+    let euid = unsafe { libc::geteuid() };
+    if euid == user.uid {
+        info!("Already running as '{}'; no privileges to drop", user.name);
+        return Ok(());
+    }
+    if euid != 0 {
+        bail!("agent.run_as_user is '{}' but the agent was not started as root", user.name);
+    }
+
+    let c_name = CString::new(user.name.as_str())?;
+    let os_err = |what: &str| anyhow!("{} failed: {}", what, std::io::Error::last_os_error());
+    if unsafe { libc::initgroups(c_name.as_ptr(), user.gid) } != 0 {
+        return Err(os_err("initgroups"));
+    }
+    if unsafe { libc::setgid(user.gid) } != 0 {
+        return Err(os_err("setgid"));
+    }
+    if unsafe { libc::setuid(user.uid) } != 0 {
+        return Err(os_err("setuid"));
+    }
+    if user.uid != 0 && unsafe { libc::setuid(0) } == 0 {
+        bail!("Still able to regain root after switching to '{}'", user.name);
+    }
+    // No setuid helpers (sudo etc.) from here on either
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } != 0 {
+        return Err(os_err("prctl(PR_SET_NO_NEW_PRIVS)"));
+    }
+
+    info!("Dropped privileges: now running as '{}' (uid {}, gid {})", user.name, user.uid, user.gid);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn looks_up_accounts() {
+        let root = lookup_user("root").unwrap();
+        assert_eq!((root.uid, root.gid), (0, 0));
+
+        let err = lookup_user("pankha-no-such-user").unwrap_err();
+        assert!(err.to_string().contains("does not exist"));
+    }
+}
@@ -0,0 +1,188 @@
+//! Crash-loop detection and safe mode.
+//!
+//! Every agent start is recorded in start-history.json beside the executable, and a
+//! clean shutdown (signal, `--stop`, self-update) clears the list, so what is left are
+//! runs that panicked, were killed by the watchdog or otherwise died. When
+//! `agent.safe_mode_after_starts` of them fall within `agent.safe_mode_window_minutes`,
+//! the agent starts in safe mode: fans go back to the control they were under before
+//! the agent took them over, fan writes and fan-control commands are refused, and
+//! monitoring and the backend connection go on as usual. Safe mode is kept in the same
+//! file across restarts until the `clearSafeMode` command or `--clear-safe-mode`.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+pub(crate) const START_HISTORY_FILE: &str = "start-history.json";
+
+/// Why the agent is in safe mode, as reported in registration.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct SafeMode {
+    /// Unix ms
+    pub(crate) since: i64,
+    /// Unclean starts within the window when it was entered
+    pub(crate) starts: usize,
+    pub(crate) window_minutes: u64,
+}
+
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub(crate) struct StartHistory {
+    /// Unix ms of each start not followed by a clean shutdown
+    #[serde(default)]
+    starts: Vec<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    safe_mode: Option<SafeMode>,
+}
+
+/// start-history.json beside the executable.
+pub(crate) fn start_history_path() -> Option<PathBuf> {
+    Some(std::env::current_exe().ok()?.parent()?.join(START_HISTORY_FILE))
+}
+
+/// A command's response in safe mode, as (success, error, data), instead of running
+/// it; None to run it. The backend's curve sends setFanSpeed every cycle, so that one
+/// is answered like with fan control disabled, without an error. `emergencyStop` and
+/// `restoreFanToAuto` still run.
+pub(crate) fn gate(command_type: &str) -> Option<(bool, Option<String>, serde_json::Value)> {
+    match command_type {
+        "setFanSpeed" => Some((true, None, serde_json::json!({"message": "Fan control is disabled: safe mode"}))),
+        "retryFanControl" | "setFanMode" | "characterizeFans" => Some((false, Some(format!(
+            "{} refused: the agent is in safe mode after repeated crashes (send clearSafeMode to resume fan control)",
+            command_type
+        )), serde_json::json!({}))),
+        _ => None,
+    }
+}
+
+impl StartHistory {
+    /// The history at `path` (empty if there is none or it is unreadable).
+    pub(crate) fn load(path: &Path) -> Self {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default()
+    }
+
+    /// Record a start at `now_ms`. Returns safe mode if this run is in it: already,
+    /// or because it is start number `after_starts` within `window_minutes`
+    /// (0 = never).
+    pub(crate) fn record_start(&mut self, now_ms: i64, after_starts: u32, window_minutes: u64) -> Option<SafeMode> {
+        let window_ms = window_minutes.saturating_mul(60_000) as i64;
+        self.starts.retain(|&at| at <= now_ms && now_ms - at < window_ms);
+        self.starts.push(now_ms);
+        if self.safe_mode.is_none() && after_starts > 0 && self.starts.len() >= after_starts as usize {
+            self.safe_mode = Some(SafeMode { since: now_ms, starts: self.starts.len(), window_minutes });
+        }
+        self.safe_mode.clone()
+    }
+
+    /// The run ended cleanly; the starts before it don't make a crash loop.
+    pub(crate) fn clean_exit(&mut self) {
+        self.starts.clear();
+    }
+
+    /// Leave safe mode, starting the count over. True if it was on.
+    pub(crate) fn clear_safe_mode(&mut self) -> bool {
+        self.starts.clear();
+        self.safe_mode.take().is_some()
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {:?}", tmp))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+        Ok(())
+    }
+}
+
+/// Apply `change` to the history beside the executable and save it.
+pub(crate) fn update<T>(change: impl FnOnce(&mut StartHistory) -> T) -> Option<T> {
+    let path = start_history_path()?;
+    let mut history = StartHistory::load(&path);
+    let result = change(&mut history);
+    if let Err(e) = history.save(&path) {
+        warn!("Could not save the start history: {:#}", e);
+    }
+    Some(result)
+}
+
+/// `--clear-safe-mode`: a running agent keeps its mode until it is restarted.
+pub fn clear_from_cli() -> Result<()> {
+    let path = start_history_path().context("Cannot determine the executable directory")?;
+    let mut history = StartHistory::load(&path);
+    if !history.clear_safe_mode() {
+        println!("The agent is not in safe mode");
+        return Ok(());
+    }
+    history.save(&path)?;
+    println!("Safe mode cleared: fan control resumes from the next start");
+    if crate::daemon::pid::is_running() {
+        println!("The agent is running - restart it to apply (--restart, or the clearSafeMode command from the server)");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MINUTE: i64 = 60_000;
+
+    #[test]
+    fn repeated_unclean_starts_enter_safe_mode_until_cleared() {
+        let start = 1_760_601_234_000;
+        let mut history = StartHistory::default();
+        for i in 0..4 {
+            assert_eq!(history.record_start(start + i * MINUTE, 5, 10), None);
+        }
+        let safe = history.record_start(start + 4 * MINUTE, 5, 10).unwrap();
+        assert_eq!(safe, SafeMode { since: start + 4 * MINUTE, starts: 5, window_minutes: 10 });
+
+        // Kept across restarts and clean exits, with the original reason
+        let path = std::env::temp_dir().join(format!("pankha-start-history-{}.json", std::process::id()));
+        history.clean_exit();
+        history.save(&path).unwrap();
+        let mut restarted = StartHistory::load(&path);
+        assert_eq!(restarted.record_start(start + 60 * MINUTE, 5, 10), Some(safe));
+        let _ = std::fs::remove_file(&path);
+
+        assert!(restarted.clear_safe_mode());
+        assert!(!restarted.clear_safe_mode());
+        assert_eq!(restarted.record_start(start + 61 * MINUTE, 5, 10), None);
+    }
+
+    #[test]
+    fn clean_exits_and_old_starts_dont_count() {
+        let start = 1_760_601_234_000;
+        let mut history = StartHistory::default();
+        for i in 0..4 {
+            history.record_start(start + i * MINUTE, 5, 10);
+        }
+        history.clean_exit();
+        assert_eq!(history.record_start(start + 5 * MINUTE, 5, 10), None);
+
+        // One start every three minutes: never five within ten
+        let mut history = StartHistory::default();
+        for i in 0..20 {
+            assert_eq!(history.record_start(start + i * 3 * MINUTE, 5, 10), None);
+        }
+        assert!(history.record_start(start + 57 * MINUTE + 1, 0, 10).is_none());
+    }
+
+    #[test]
+    fn only_fan_writing_commands_are_gated() {
+        let (success, error, data) = gate("setFanSpeed").unwrap();
+        assert!(success && error.is_none());
+        assert_eq!(data["message"], "Fan control is disabled: safe mode");
+        for refused in ["retryFanControl", "setFanMode"] {
+            let (success, error, _) = gate(refused).unwrap();
+            assert!(!success && error.unwrap().contains("clearSafeMode"));
+        }
+        for allowed in ["emergencyStop", "restoreFanToAuto", "clearSafeMode", "setEnableFanControl", "getDiagnostics"] {
+            assert_eq!(gate(allowed), None, "{}", allowed);
+        }
+    }
+}
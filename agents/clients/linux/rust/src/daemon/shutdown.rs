@@ -0,0 +1,200 @@
+//! Cleanup that runs however the agent exits: a normal stop, a signal or a panic.
+//!
+//! A panic in any task ends the process through the panic hook; a normal exit drops
+//! the `ShutdownGuard` held by main. Either way, and with the async runtime possibly
+//! gone, the cleanup uses blocking I/O only: every fan with a mode in fan-modes.json
+//! (read back at exit, so it matches fan-restore.txt) gets that mode written back to
+//! `pwmN_enable` (through the handle kept open at discovery, so it works after
+//! dropping root, and with any spin-up boost cancelled first), the PID file is
+//! removed if it still
+//! names this process, and a config.json save still waiting out its delay is done.
+//! NVML GPU fans are handed back by main on a normal exit only.
+//!
+//! The plan is global, updated from discovery and fan takeovers, so the panic hook
+//! can reach it. It runs once; later calls do nothing.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, OnceLock, PoisonError};
+
+use tracing::{error, info, warn};
+
+use crate::config::handle::ConfigHandle;
+use crate::daemon::pid::{get_pid, remove_pid_file};
+use crate::hardware::linux::fan_modes::{self, SavedMode};
+use crate::hardware::linux::monitor::SpinUp;
+
+static RESTORE_PLAN: OnceLock<Mutex<RestorePlan>> = OnceLock::new();
+
+/// What to put back on the way out.
+#[derive(Default)]
+pub(crate) struct RestorePlan {
+    /// fan-modes.json: modes the fans were in before the agent took them over
+    fan_modes: Option<PathBuf>,
+    /// Kept-open `pwmN_enable` handles of the fans discovered this run, by fan id
+    enable_handles: BTreeMap<String, (PathBuf, Arc<std::fs::File>)>,
+    /// Spin-up boosts of those fans; one settling after the restore would undo it
+    spinups: BTreeMap<String, Arc<Mutex<Option<SpinUp>>>>,
+    /// Remove the PID file (`--start` daemon child)
+    pid_file: bool,
+    config: Option<Arc<ConfigHandle>>,
+    done: bool,
+}
+
+impl RestorePlan {
+    pub(crate) fn set_fan_modes_file(&mut self, path: &Path) {
+        self.fan_modes = Some(path.to_path_buf());
+    }
+
+    pub(crate) fn set_enable_handle(&mut self, fan_id: &str, path: PathBuf, handle: Arc<std::fs::File>,
+                                    spinup: Arc<Mutex<Option<SpinUp>>>) {
+        self.enable_handles.insert(fan_id.to_string(), (path, handle));
+        self.spinups.insert(fan_id.to_string(), spinup);
+    }
+
+    pub(crate) fn remove_pid_file_on_exit(&mut self) {
+        self.pid_file = true;
+    }
+
+    pub(crate) fn flush_config_on_exit(&mut self, config: Arc<ConfigHandle>) {
+        self.config = Some(config);
+    }
+
+    /// Write the saved modes back, through the kept handle where there is one.
+    /// Returns the fans that could not be restored, with why.
+    fn restore_fans(&self, saved: &BTreeMap<String, SavedMode>) -> Vec<(String, String)> {
+        use std::os::unix::fs::FileExt;
+
+        for spinup in self.spinups.values() {
+            spinup.lock().unwrap_or_else(PoisonError::into_inner).take();
+        }
+        let mut by_path = BTreeMap::new();
+        let mut failed = Vec::new();
+        for (fan_id, mode) in saved {
+            let Some((path, handle)) = self.enable_handles.get(fan_id) else {
+                by_path.insert(fan_id.clone(), mode.clone());
+                continue;
+            };
+            if let Err(e) = handle.write_at(mode.mode.as_bytes(), 0) {
+                failed.push((fan_id.clone(), format!("writing {} to {:?}: {}", mode.mode, path, e)));
+            }
+        }
+        failed.extend(fan_modes::restore(&by_path));
+        failed
+    }
+
+    /// Run the cleanup, unless it already ran.
+    fn run(&mut self, reason: &str) {
+        if std::mem::replace(&mut self.done, true) {
+            return;
+        }
+        let saved = self.fan_modes.as_deref().map(fan_modes::load).unwrap_or_default();
+        if !saved.is_empty() {
+            let failed = self.restore_fans(&saved);
+            for (fan_id, why) in &failed {
+                warn!("Could not restore the original mode of fan {} ({}): {}", fan_id, reason, why);
+            }
+            info!("Restored the original mode of {} fan(s) ({})", saved.len() - failed.len(), reason);
+        }
+        if let Some(config) = &self.config {
+            if let Err(e) = config.flush_blocking() {
+                error!("Failed to save configuration ({}): {:#}", reason, e);
+            }
+        }
+        if self.pid_file && matches!(get_pid(), Ok(Some(pid)) if pid == std::process::id()) {
+            match remove_pid_file() {
+                Ok(()) => info!("PID file cleaned up"),
+                Err(e) => warn!("{:#}", e),
+            }
+        }
+    }
+}
+
+/// Change the global plan.
+pub(crate) fn update(change: impl FnOnce(&mut RestorePlan)) {
+    let plan = RESTORE_PLAN.get_or_init(Mutex::default);
+    change(&mut plan.lock().unwrap_or_else(PoisonError::into_inner));
+}
+
+/// Run the global plan (once).
+pub(crate) fn run(reason: &str) {
+    update(|plan| plan.run(reason));
+}
+
+/// Runs the cleanup when dropped: at the end of main, or while a panic unwinds it.
+#[must_use]
+pub struct ShutdownGuard;
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        run(if std::thread::panicking() { "panic" } else { "shutdown" });
+    }
+}
+
+/// A panic in any task (tokio would only end that task) cleans up and ends the
+/// process, after the usual panic message; systemd or the crash-loop detection of
+/// `safe_mode` take it from there.
+pub fn install_panic_hook() -> ShutdownGuard {
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |panic| {
+        default_hook(panic);
+        error!("Agent panicked: {} - restoring fans and exiting", panic);
+        run("panic");
+        std::process::exit(101);
+    }));
+    ShutdownGuard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn simulated_panic_restores_fans_once() {
+        let dir = std::env::temp_dir().join(format!("pankha-shutdown-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let enable = dir.join("pwm1_enable");
+        std::fs::write(&enable, "1").unwrap();
+        let handle = Arc::new(std::fs::OpenOptions::new().read(true).write(true).open(&enable).unwrap());
+
+        let saved = |path: &Path| SavedMode {
+            enable_path: path.to_path_buf(),
+            chip: String::new(),
+            mode: "2".into(),
+            pwm_path: None,
+            pwm_value: None,
+        };
+        let modes = BTreeMap::from([
+            ("it8689_fan_1".to_string(), saved(&enable)),
+            // Not discovered this run: written by path, and only under /sys
+            ("it8689_fan_2".to_string(), saved(&dir.join("pwm2_enable"))),
+        ]);
+        // What the cleanup restores is what fan-modes.json says at exit
+        let modes_file = dir.join(fan_modes::FAN_MODES_FILE);
+        std::fs::write(&modes_file, serde_json::to_string(&modes).unwrap()).unwrap();
+        let mut plan = RestorePlan::default();
+        plan.set_fan_modes_file(&modes_file);
+        plan.set_enable_handle("it8689_fan_1", enable.clone(), handle, Arc::default());
+        let failed = plan.restore_fans(&fan_modes::load(&modes_file));
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].0, "it8689_fan_2");
+        assert_eq!(std::fs::read_to_string(&enable).unwrap(), "2");
+
+        // Through the global plan, from the guard while a panic unwinds
+        std::fs::write(&enable, "1").unwrap();
+        update(|global| *global = plan);
+        let panicked = std::panic::catch_unwind(|| {
+            let _guard = ShutdownGuard;
+            panic!("simulated panic");
+        });
+        assert!(panicked.is_err());
+        assert_eq!(std::fs::read_to_string(&enable).unwrap(), "2");
+
+        // Runs once: a later exit path doesn't write again
+        std::fs::write(&enable, "1").unwrap();
+        run("shutdown");
+        assert_eq!(std::fs::read_to_string(&enable).unwrap(), "1");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,159 @@
+//! Machine-readable agent state for external watchdogs (`agent.state_file`).
+//!
+//! Monit, a Nagios check or a shell script can't ask the backend whether this agent
+//! is healthy, and parsing logs breaks with every wording change. Every data cycle,
+//! failsafe check or UDP broadcast rewrites a small JSON file (by default
+//! /run/pankha-agent/state.json) through a temporary file and a rename, so a reader
+//! never sees half of it. Fields are only added within a `schema_version`; removing
+//! or changing one bumps it.
+
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::app::log_throttle::LogThrottle;
+use crate::daemon::platform::state_file_for_write;
+
+/// Version of the state file's layout; see `HELP_TEXT` for the fields.
+pub(crate) const STATE_FILE_SCHEMA_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct AgentState {
+    pub(crate) schema_version: u32,
+    /// Unix ms of this write
+    pub(crate) timestamp: i64,
+    pub(crate) pid: u32,
+    /// Connected to the backend (always false under udp-broadcast)
+    pub(crate) connected: bool,
+    pub(crate) failsafe_active: bool,
+    /// Unix ms of the last data message or broadcast that went out
+    pub(crate) last_data_sent: Option<i64>,
+    /// Fans the agent can set the speed of, as of the last data cycle
+    pub(crate) controllable_fans: usize,
+    /// Most recent send or connection error; kept after recovery
+    pub(crate) last_error: Option<String>,
+}
+
+impl Default for AgentState {
+    fn default() -> Self {
+        Self {
+            schema_version: STATE_FILE_SCHEMA_VERSION,
+            timestamp: 0,
+            pid: std::process::id(),
+            connected: false,
+            failsafe_active: false,
+            last_data_sent: None,
+            controllable_fans: 0,
+            last_error: None,
+        }
+    }
+}
+
+/// The state as of the last update, and the throttle for failed writes.
+#[derive(Default)]
+pub(crate) struct StateFile {
+    state: Mutex<AgentState>,
+    /// The configured path and where it is written: itself, or a fallback when its
+    /// directory is unusable
+    target: Mutex<Option<(PathBuf, PathBuf)>>,
+    errors: LogThrottle,
+}
+
+impl StateFile {
+    /// Apply `change` and rewrite the file at `path` (`None`: `agent.state_file` is
+    /// off, the state is only kept in memory).
+    pub(crate) fn update(&self, path: Option<&Path>, change: impl FnOnce(&mut AgentState)) {
+        let state = {
+            let mut state = self.state.lock().unwrap();
+            change(&mut state);
+            state.timestamp = chrono::Utc::now().timestamp_millis();
+            state.clone()
+        };
+        let Some(path) = path else {
+            return;
+        };
+        match self.target(path).and_then(|target| write(&target, &state)) {
+            Ok(()) => {
+                if let Some(line) = self.errors.recovered("state_file") {
+                    info!("State file {}", line);
+                }
+            }
+            Err(e) => {
+                // Look for a usable directory again next time
+                *self.target.lock().unwrap() = None;
+                if let Some(line) = self.errors.failed("state_file", format!("Could not write the state file: {:#}", e)) {
+                    warn!("{}", line);
+                }
+            }
+        }
+    }
+
+    /// Where the file configured at `configured` goes, resolved on first use.
+    fn target(&self, configured: &Path) -> Result<PathBuf> {
+        let mut target = self.target.lock().unwrap();
+        if let Some((_, resolved)) = target.as_ref().filter(|(path, _)| path == configured) {
+            return Ok(resolved.clone());
+        }
+        let resolved = state_file_for_write(configured)?;
+        if resolved != configured {
+            info!("Cannot write the state file to {}; using {}", configured.display(), resolved.display());
+        }
+        *target = Some((configured.to_path_buf(), resolved.clone()));
+        Ok(resolved)
+    }
+}
+
+/// Write `state` to `path` atomically.
+fn write(path: &Path, state: &AgentState) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_vec(state)?).with_context(|| format!("Failed to write {:?}", tmp))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_rewrite_the_file_and_keep_earlier_fields() {
+        let dir = std::env::temp_dir().join(format!("pankha-state-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let path = dir.join("run/state.json");
+        let read = || -> AgentState { serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap() };
+
+        let file = StateFile::default();
+        file.update(Some(&path), |s| {
+            s.connected = true;
+            s.last_data_sent = Some(1_700_000_000_000);
+            s.controllable_fans = 3;
+        });
+        let state = read();
+        assert_eq!((state.schema_version, state.pid, state.connected, state.controllable_fans),
+                   (STATE_FILE_SCHEMA_VERSION, std::process::id(), true, 3));
+        assert!(state.timestamp > 0);
+
+        file.update(Some(&path), |s| {
+            s.connected = false;
+            s.failsafe_active = true;
+            s.last_error = Some("Connection refused".into());
+        });
+        let state = read();
+        assert_eq!((state.connected, state.failsafe_active, state.last_data_sent, state.controllable_fans),
+                   (false, true, Some(1_700_000_000_000), 3));
+        assert_eq!(state.last_error.as_deref(), Some("Connection refused"));
+        assert!(!path.with_extension("json.tmp").exists());
+
+        // Off: nothing written, the state still tracked for when it's turned back on
+        std::fs::remove_file(&path).unwrap();
+        file.update(None, |s| s.connected = true);
+        assert!(!path.exists());
+        file.update(Some(&path), |_| {});
+        assert!(read().connected);
+
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
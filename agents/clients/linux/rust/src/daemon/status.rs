@@ -2,23 +2,82 @@ use std::path::Path;
 use std::fs;
 use std::process;
 use anyhow::Result;
+use serde::Serialize;
 
+use crate::app::redact;
 use crate::daemon::pid::*;
+use crate::daemon::platform::{find_log_file, log_file_for_write, pid_file_for_write};
 use crate::daemon::systemd::*;
-use crate::daemon::{LOG_DIR, SYSTEMD_SERVICE_PATH};
+use crate::daemon::SYSTEMD_SERVICE_PATH;
 use crate::config::persistence::load_config;
+use crate::websocket::connection_check::check_connection;
+use crate::websocket::transport::Route;
+
+/// `--status --json` output. Keys are part of the CLI contract (listed in `--help`).
+#[derive(Debug, Serialize)]
+struct StatusReport {
+    version: &'static str,
+    arch: &'static str,
+    running: bool,
+    pid: Option<u32>,
+    uptime_secs: Option<u64>,
+    server_url: Option<String>,
+    agent_name: Option<String>,
+    update_interval: Option<f64>,
+    log_file: Option<String>,
+    /// RFC 3339 time the log file was last written
+    last_log_time: Option<String>,
+}
+
+/// Seconds since `pid` started.
+fn process_uptime(pid: u32) -> Option<u64> {
+    let pid = sysinfo::Pid::from_u32(pid);
+    let mut sys = sysinfo::System::new();
+    sys.refresh_process(pid);
+    sys.process(pid).map(|p| p.run_time())
+}
+
+async fn show_status_json() -> Result<()> {
+    let config = load_config(None).await.ok();
+    let pid = if is_running() { get_pid()? } else { None };
+    let log_file = find_log_file(config.as_ref().map(|c| Path::new(&c.logging.log_file)));
+    let last_log_time = log_file.as_ref()
+        .and_then(|path| fs::metadata(path).and_then(|m| m.modified()).ok())
+        .map(|modified| chrono::DateTime::<chrono::Utc>::from(modified).to_rfc3339());
+
+    let report = StatusReport {
+        version: crate::version::VERSION,
+        arch: std::env::consts::ARCH,
+        running: pid.is_some(),
+        pid,
+        uptime_secs: pid.and_then(process_uptime),
+        server_url: config.as_ref().map(|c| redact::url(&c.backend.server_url)),
+        agent_name: config.as_ref().map(|c| c.agent.name.clone()),
+        update_interval: config.as_ref().map(|c| c.agent.update_interval),
+        log_file: log_file.map(|p| p.display().to_string()),
+        last_log_time,
+    };
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(())
+}
+
+pub async fn show_status(json: bool) -> Result<()> {
+    if json {
+        return show_status_json().await;
+    }
 
-pub async fn show_status() -> Result<()> {
     println!("\x1b[32mpankha-agent v{} ({})\x1b[0m", crate::version::VERSION, std::env::consts::ARCH);
     println!("================================");
 
+    let config = load_config(None).await.ok();
+
     if is_running() {
         if let Some(pid) = get_pid()? {
             println!("Status: Running (PID: {})", pid);
 
             // Show some runtime info
-            let log_path = format!("{}/agent.log", LOG_DIR);
-            if Path::new(&log_path).exists() {
+            let configured_log = config.as_ref().map(|c| Path::new(&c.logging.log_file));
+            if let Some(log_path) = find_log_file(configured_log) {
                 println!("\nLast 5 log entries:");
                 if let Ok(content) = fs::read_to_string(&log_path) {
                     let lines: Vec<&str> = content.lines().rev().take(5).collect();
@@ -34,8 +93,8 @@ pub async fn show_status() -> Result<()> {
 
     // Show configuration info
     println!("\nConfiguration:");
-    if let Ok(config) = load_config(None).await {
-        println!("   Server: {}", config.backend.server_url);
+    if let Some(config) = config {
+        println!("   Server: {}", redact::url(&config.backend.server_url));
         println!("   Update Interval: {}s", config.agent.update_interval);
         println!("   Agent Name: {}", config.agent.name);
     } else {
@@ -68,17 +127,21 @@ pub fn run_health_check() -> Result<()> {
         all_ok = false;
     }
 
-    // Check directories
-    if Path::new("/run/pankha-agent").exists() {
-        println!("✓ Runtime dir: /run/pankha-agent");
-    } else {
-        println!("⚠ Runtime dir: Not created (will be created on start)");
+    // Check directories (creates them, falling back to per-user locations when not root)
+    match pid_file_for_write() {
+        Ok(pid_file) => println!("✓ PID file: {}", pid_file.display()),
+        Err(e) => {
+            println!("✗ PID file: {}", e);
+            all_ok = false;
+        }
     }
 
-    if Path::new(LOG_DIR).exists() {
-        println!("✓ Log dir: {}", LOG_DIR);
-    } else {
-        println!("⚠ Log dir: Not created (will be created on start)");
+    match log_file_for_write(None) {
+        Ok(log_file) => println!("✓ Log file: {}", log_file.display()),
+        Err(e) => {
+            println!("✗ Log file: {}", e);
+            all_ok = false;
+        }
     }
 
     // Check systemd service (Linux only)
@@ -128,3 +191,27 @@ pub fn run_health_check() -> Result<()> {
 
     Ok(())
 }
+
+/// `--check-connection`: test the configured server URL the way the setup wizard does.
+/// Returns false if the Hub could not be reached.
+pub async fn run_connection_check() -> Result<bool> {
+    let config = load_config(None).await?;
+    let url = &config.backend.server_url;
+    println!("Testing connection to {} (timeout {}s)...", redact::url(url), config.backend.connection_timeout);
+
+    match check_connection(url, &Route::from(&config.backend), config.backend.connection_timeout).await {
+        Ok(elapsed) => {
+            println!("\x1b[32m✓ Hub reachable: WebSocket handshake and ping OK ({} ms)\x1b[0m", elapsed.as_millis());
+            Ok(true)
+        }
+        Err(e) => {
+            println!("\x1b[31m✗ {}\x1b[0m", e);
+            if e.is_route() {
+                println!("  Check bind_interface, bind_address and proxy_url under backend in config.json");
+            } else {
+                println!("  Fix the URL with: ./pankha-agent --setup");
+            }
+            Ok(false)
+        }
+    }
+}
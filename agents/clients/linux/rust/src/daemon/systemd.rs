@@ -25,6 +25,32 @@ pub fn is_systemd_service_active() -> bool {
         .unwrap_or(false)
 }
 
+/// Check if the installed service runs the agent in `--systemd` (Type=notify) mode,
+/// where systemd owns the process and logs go to the journal
+pub fn is_notify_service_installed() -> bool {
+    has_systemd()
+        && fs::read_to_string(SYSTEMD_SERVICE_PATH)
+            .map(|unit| unit.contains("--systemd"))
+            .unwrap_or(false)
+}
+
+/// Main PID of the pankha-agent service as tracked by systemd (None if not running)
+pub fn systemd_main_pid() -> Option<u32> {
+    if !has_systemd() || !Path::new(SYSTEMD_SERVICE_PATH).exists() {
+        return None;
+    }
+
+    let output = process::Command::new("systemctl")
+        .args(["show", "--property=MainPID", "--value", "pankha-agent"])
+        .output()
+        .ok()?;
+    String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|pid| *pid != 0)
+}
+
 /// Install or repair systemd service for auto-start on boot (idempotent)
 pub fn install_systemd_service() -> Result<()> {
     // Check if running as root (using libc for Linux)
@@ -101,6 +127,15 @@ pub fn install_systemd_service() -> Result<()> {
         }
     }
 
+    // Pick up a changed unit (e.g. forking -> notify) if the service is already running
+    if is_systemd_service_active() {
+        let _ = process::Command::new("systemctl")
+            .args(["restart", "pankha-agent"])
+            .status();
+        println!("✓ Service restarted with the new unit file");
+        return Ok(());
+    }
+
     println!();
     println!("Start now with: sudo systemctl start pankha-agent");
     println!("Or use:         ./pankha-agent --start");
@@ -0,0 +1,250 @@
+//! `--uninstall`: stop the agent, hand its fans back to the firmware and remove what
+//! it created on this machine.
+//!
+//! Fans are restored from fan-modes.json (see `hardware::linux::fan_modes`) after the
+//! agent has stopped, so nothing takes them over again. Runtime and log directories
+//! and generated files always go; config.json and identity.json only with `--purge`
+//! or when confirmed at the prompt, since deleting the identity makes a reinstall a
+//! new agent on the Hub. The binary itself is left for the user to delete. Every step
+//! tolerates a partial install, and whatever couldn't be removed (usually for lack of
+//! root) is listed at the end.
+
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+
+use anyhow::{Context, Result};
+
+use crate::config::identity::IDENTITY_FILE;
+use crate::daemon::control::{configured_log_file, STOP_GRACE};
+use crate::daemon::pid::{get_pid, is_running};
+use crate::daemon::platform::{agent_dirs, find_pid_file, process_control};
+use crate::daemon::safe_mode::START_HISTORY_FILE;
+use crate::daemon::systemd::{has_systemd, is_systemd_service_active, uninstall_systemd_service};
+use crate::daemon::SYSTEMD_SERVICE_PATH;
+use crate::hardware::linux::discovery_cache::DISCOVERY_CACHE_FILE;
+use crate::hardware::linux::fan_modes::{self, fan_modes_path, FAN_MODES_FILE};
+use crate::hardware::snapshot::SNAPSHOT_FILE;
+use crate::websocket::emergency_actions::EMERGENCY_ACTIONS_FILE;
+
+#[derive(Default)]
+struct Report {
+    removed: Vec<String>,
+    left: Vec<String>,
+    failed: Vec<String>,
+}
+
+impl Report {
+    /// Remove `path` (file or directory) if it exists.
+    fn remove(&mut self, path: &Path) {
+        let result = match std::fs::symlink_metadata(path) {
+            Ok(meta) if meta.is_dir() => std::fs::remove_dir_all(path),
+            Ok(_) => std::fs::remove_file(path),
+            Err(_) => return,
+        };
+        match result {
+            Ok(()) => self.removed.push(path.display().to_string()),
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                self.failed.push(format!("{} (permission denied, run with sudo)", path.display()))
+            }
+            Err(e) => self.failed.push(format!("{} ({})", path.display(), e)),
+        }
+    }
+
+    fn print(&self) {
+        println!();
+        println!("Uninstall summary:");
+        for (title, items) in [("Removed", &self.removed), ("Left behind", &self.left), ("Could not remove", &self.failed)] {
+            if items.is_empty() {
+                continue;
+            }
+            println!("  {}:", title);
+            for item in items {
+                println!("    {}", item);
+            }
+        }
+    }
+}
+
+fn is_root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Stop the agent, whichever way it runs. Fails if it is still running afterwards.
+fn stop_agent() -> Result<()> {
+    if is_systemd_service_active() {
+        println!("Stopping the pankha-agent service...");
+        let status = process::Command::new("systemctl").args(["stop", "pankha-agent"]).status();
+        if !matches!(status, Ok(s) if s.success()) {
+            anyhow::bail!("systemctl stop pankha-agent failed (run with sudo)");
+        }
+        return Ok(());
+    }
+    if !is_running() {
+        return Ok(());
+    }
+    if let Some(pid) = get_pid()? {
+        println!("Stopping Pankha Rust Agent (PID: {})...", pid);
+        if process_control().stop(pid, STOP_GRACE)? {
+            println!("WARNING: Agent did not exit in time and was force-killed");
+        }
+    }
+    Ok(())
+}
+
+/// Put every fan the agent took over back into its original mode.
+fn restore_fans(report: &mut Report, path: &Path) {
+    let saved = fan_modes::load(path);
+    if saved.is_empty() {
+        return;
+    }
+    let failed = fan_modes::restore(&saved);
+    for fan_id in saved.keys().filter(|id| !failed.iter().any(|(f, _)| f == *id)) {
+        println!("✓ Fan {} back in automatic mode", fan_id);
+    }
+    if failed.is_empty() {
+        report.remove(path);
+        report.remove(&path.with_file_name(fan_modes::FAN_RESTORE_FILE));
+        return;
+    }
+    for (fan_id, reason) in failed {
+        report.failed.push(format!("fan {} left in manual mode: {}", fan_id, reason));
+    }
+    // Kept so a second run (as root) can still restore them
+    report.left.push(format!("{} (fans not restored)", path.display()));
+}
+
+fn confirm_purge(config_path: &Path) -> Result<bool> {
+    if !io::stdin().is_terminal() || !config_path.exists() {
+        return Ok(false);
+    }
+    println!();
+    println!("Delete config.json and identity.json as well? The Hub will see a reinstall");
+    print!("as a new agent without this one's history. (y/N): ");
+    io::stdout().flush()?;
+    let mut response = String::new();
+    io::stdin().read_line(&mut response)?;
+    Ok(response.trim().eq_ignore_ascii_case("y"))
+}
+
+/// config.json, its upgrade backups and identity.json (including a broken one kept aside).
+fn config_files(exe_dir: &Path) -> Vec<PathBuf> {
+    let mut files = vec![
+        exe_dir.join("config.json"),
+        exe_dir.join(IDENTITY_FILE),
+        exe_dir.join(format!("{}.broken", IDENTITY_FILE)),
+    ];
+    if let Ok(entries) = std::fs::read_dir(exe_dir) {
+        files.extend(entries.flatten().map(|e| e.path()).filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| name.starts_with("config.json.v") && name.ends_with(".bak"))
+        }));
+    }
+    files
+}
+
+/// A log file outside the agent's log directories, with its rotated copies.
+fn configured_logs(log_file: &Path) -> Vec<PathBuf> {
+    let (Some(dir), Some(name)) = (log_file.parent(), log_file.file_name()) else {
+        return Vec::new();
+    };
+    let name = name.to_string_lossy().to_string();
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|path| {
+            let file = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            file == name || file.strip_prefix(&format!("{}.", name)).is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit()))
+        })
+        .collect()
+}
+
+pub fn uninstall(purge: bool) -> Result<()> {
+    let exe_path = std::env::current_exe()?;
+    let exe_dir = exe_path
+        .parent()
+        .context("Cannot determine executable directory")?
+        .to_path_buf();
+    let config_path = exe_dir.join("config.json");
+    let mut report = Report::default();
+
+    stop_agent().context("Could not stop the agent; nothing was removed")?;
+
+    let modes_path = fan_modes_path().unwrap_or_else(|| exe_dir.join(FAN_MODES_FILE));
+    restore_fans(&mut report, &modes_path);
+
+    if has_systemd() && Path::new(SYSTEMD_SERVICE_PATH).exists() {
+        if is_root() {
+            match uninstall_systemd_service() {
+                Ok(()) => report.removed.push(format!("{} (service disabled)", SYSTEMD_SERVICE_PATH)),
+                Err(e) => report.failed.push(format!("{} ({:#})", SYSTEMD_SERVICE_PATH, e)),
+            }
+        } else {
+            report.failed.push(format!("{} (root required, run with sudo)", SYSTEMD_SERVICE_PATH));
+        }
+    }
+
+    if let Some(pid_file) = find_pid_file() {
+        report.remove(&pid_file);
+    }
+    let dirs = agent_dirs();
+    if let Some(log_file) = configured_log_file(&config_path).filter(|log| !dirs.iter().any(|dir| log.starts_with(dir))) {
+        for log in configured_logs(&log_file) {
+            report.remove(&log);
+        }
+    }
+    for dir in &dirs {
+        report.remove(dir);
+    }
+
+    // Generated while running or on self-update; nothing worth keeping
+    for file in [SNAPSHOT_FILE, DISCOVERY_CACHE_FILE, EMERGENCY_ACTIONS_FILE, START_HISTORY_FILE, "hardware-info.json", ".update_pending"] {
+        report.remove(&exe_dir.join(file));
+    }
+    report.remove(&exe_path.with_extension("old"));
+
+    let existing: Vec<PathBuf> = config_files(&exe_dir).into_iter().filter(|f| f.exists()).collect();
+    if purge || (!existing.is_empty() && confirm_purge(&config_path)?) {
+        for file in &existing {
+            report.remove(file);
+        }
+    } else {
+        report.left.extend(existing.iter().map(|f| format!("{} (use --purge to delete)", f.display())));
+    }
+
+    report.left.push(format!("{} (delete the binary yourself)", exe_path.display()));
+    report.print();
+    if !report.failed.is_empty() {
+        process::exit(1);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_rotated_logs_and_config_backups_only() {
+        let dir = std::env::temp_dir().join(format!("pankha-uninstall-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        for file in ["agent.log", "agent.log.1", "agent.log.2.gz", "agent.log.bak", "other.log",
+                     "config.json", "config.json.v1.bak", "config.json.tmp"] {
+            std::fs::write(dir.join(file), "").unwrap();
+        }
+
+        let mut logs = configured_logs(&dir.join("agent.log"));
+        logs.sort();
+        assert_eq!(logs, ["agent.log", "agent.log.1", "agent.log.2.gz"].map(|f| dir.join(f)));
+
+        let config = config_files(&dir);
+        assert!(config.contains(&dir.join("config.json.v1.bak")));
+        assert!(!config.contains(&dir.join("config.json.tmp")));
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
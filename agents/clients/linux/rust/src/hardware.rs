@@ -4,6 +4,12 @@ use anyhow::Result;
 use async_trait::async_trait;
 
 pub mod types;
+pub mod snapshot;
+pub mod composite;
+pub mod smoothing;
+pub mod stats;
+pub mod topology;
+pub mod virtual_sensors;
 
 #[cfg(target_os = "linux")]
 pub mod linux;
@@ -11,7 +17,8 @@ pub mod linux;
 #[cfg(target_os = "linux")]
 pub use linux::monitor::LinuxHardwareMonitor;
 
-use types::{Sensor, Fan, SystemHealth, HardwareDumpRoot};
+use crate::config::types::{TemperatureSmoothing, VirtualSensor};
+use types::{Sensor, Fan, FanControlConflict, FanControlState, FanMode, FanSpeedOrigin, RunEnvironment, SystemHealth, HardwareDumpRoot};
 
 #[async_trait]
 pub trait HardwareMonitor: Send + Sync {
@@ -24,11 +31,19 @@ pub trait HardwareMonitor: Send + Sync {
     /// Get current system information
     async fn get_system_info(&self) -> Result<SystemHealth>;
 
-    /// Set fan speed (0-100%)
-    async fn set_fan_speed(&self, fan_id: &str, speed: u8) -> Result<()>;
+    /// Set fan speed (0-100%). `origin` is logged with the write and, where the
+    /// backend tracks it, reported as the fan's `last_set_by`.
+    async fn set_fan_speed(&self, fan_id: &str, speed: u8, origin: &FanSpeedOrigin) -> Result<()>;
 
-    /// Emergency stop - set all fans to maximum
-    async fn emergency_stop(&self) -> Result<()>;
+    /// `setFanSpeed` with `raw: true`: write a `pwmN` register value (0-255) as is,
+    /// still held to the fan's semi-passive minimum. Default: the backend has no
+    /// PWM registers.
+    async fn set_fan_pwm(&self, fan_id: &str, _value: u8, _origin: &FanSpeedOrigin) -> Result<()> {
+        anyhow::bail!("Fan {} takes speeds in percent only (no raw PWM)", fan_id)
+    }
+
+    /// Drive every fan to maximum now (`emergencyStop`, failsafe emergency, crit alarm)
+    async fn max_cooling(&self) -> Result<()>;
 
     /// Hand a fan back to hardware/driver automatic control.
     ///
@@ -39,12 +54,105 @@ pub trait HardwareMonitor: Send + Sync {
         Ok(false)
     }
 
+    /// `setFanMode`: switch a fan's `pwmN_enable` mode, checked by reading it back.
+    /// Speeds are refused while the fan is in a mode other than manual. Returns the
+    /// value written. Default: the backend has no such modes.
+    async fn set_fan_mode(&self, fan_id: &str, _mode: FanMode) -> Result<u8> {
+        anyhow::bail!("Fan {} has no switchable control modes", fan_id)
+    }
+
+    /// `releaseControl`, safe mode: hand every fan back to the control it was under
+    /// before the agent took it over (the chip's, driver's or BMC's automatic mode).
+    /// Returns the fans that couldn't be, with why. Default: nothing to hand back.
+    async fn release_control(&self) -> Vec<(String, String)> {
+        Vec::new()
+    }
+
+    /// `disableChip` / `enableChip`: leave these hwmon chips (by `name`) out of sensor and
+    /// fan discovery from the next cycle on and refuse speeds for their fans. Fans of a
+    /// newly disabled chip are handed back to the mode they were in before the agent
+    /// took them over. Default: the backend has no hwmon chips.
+    async fn set_disabled_chips(&self, _chips: &[String]) {}
+
+    /// Controllable fans on hwmon chip `chip`, whose control disabling it takes away.
+    /// Default: none.
+    async fn chip_fans(&self, _chip: &str) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// `retryFanControl`: try a fan that was given up on after repeated write failures
+    /// again, restoring control if a write succeeds. Default: nothing is ever given up.
+    async fn retry_fan_control(&self, _fan_id: &str) -> Result<()> {
+        Ok(())
+    }
+
+    /// Current control state of a fan, for `restore_fan_control_state`. `None` when
+    /// this backend can't restore a fan exactly (the default).
+    async fn fan_control_state(&self, _fan_id: &str) -> Result<Option<FanControlState>> {
+        Ok(None)
+    }
+
+    /// Put a fan back into a state captured by `fan_control_state`, including handing
+    /// it back to the chip's automatic mode if that's where it was.
+    async fn restore_fan_control_state(&self, _fan_id: &str, _state: &FanControlState) -> Result<()> {
+        Ok(())
+    }
+
+    /// Other software controlling the same fans (fancontrol, thinkfan, ...), checked
+    /// once at startup and by `--test`. Default: none detectable.
+    async fn fan_control_conflicts(&self) -> Vec<FanControlConflict> {
+        Vec::new()
+    }
+
+    /// Container and hwmon detection, checked once at startup. Default: a host with
+    /// its hardware available.
+    async fn run_environment(&self) -> RunEnvironment {
+        RunEnvironment::default()
+    }
+
     /// Invalidate hardware cache (call on startup/reconnection to force rediscovery)
     async fn invalidate_cache(&self);
 
+    /// The machine resumed from suspend: devices may have been renumbered and
+    /// time-based state (rate limits, cached readings) is meaningless. Default:
+    /// `invalidate_cache`.
+    async fn resumed(&self) {
+        self.invalidate_cache().await;
+    }
+
+    /// Rediscover on reconnection without dropping the cache: readings keep coming from
+    /// it until `reconcile_discovery` has run. Default: `invalidate_cache`.
+    async fn refresh_cache(&self) {
+        self.invalidate_cache().await;
+    }
+
+    /// Run a full discovery deferred by `refresh_cache` or by starting from a saved
+    /// cache, and replace the cache with its result. Slow; meant to run in the
+    /// background. Default: nothing is ever deferred.
+    async fn reconcile_discovery(&self) -> Result<()> {
+        Ok(())
+    }
+
     /// Check if last sensor discovery was from cache (for logging)
     async fn last_discovery_from_cache(&self) -> bool;
 
     /// Generate hardware diagnostic dump (hardware-info.json)
     async fn dump_hardware_info(&self) -> Result<HardwareDumpRoot>;
+
+    /// Fan writes are logged instead of executed (`--dry-run`)
+    fn is_dry_run(&self) -> bool {
+        false
+    }
+
+    /// Change `temperature_smoothing` at runtime (`setSmoothing`). Default: backend
+    /// reports raw readings only.
+    fn set_temperature_smoothing(&self, _mode: TemperatureSmoothing) {}
+
+    /// A data cycle begins: its sensor discovery moves `temperature_smoothing` on by
+    /// one reading, others (`requestData`, diagnostics) don't. Default: no smoothing.
+    fn next_cycle(&self) {}
+
+    /// Replace `virtual_sensors` at runtime (`setVirtualSensor`). Default: backend
+    /// reports real sensors only.
+    fn set_virtual_sensors(&self, _sensors: &[VirtualSensor]) {}
 }
@@ -0,0 +1,426 @@
+//! Several HardwareMonitor backends behind one registration (e.g. hwmon for CPU/NVMe
+//! sensors plus a BMC over IPMI for the chassis fans).
+//!
+//! Sensor, fan and zone ids are prefixed with the owning backend's prefix
+//! (`hwmon:k10temp_tctl`, `ipmi:cpu_zone`) and fan commands are routed back by it.
+
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use tracing::{error, warn};
+
+use super::types::{sort_fans, sort_sensors, Fan, FanControlConflict, FanControlState, FanMode, FanSpeedOrigin, HardwareDumpRoot, RunEnvironment, Sensor, SystemHealth};
+use super::virtual_sensors::VirtualSensors;
+use super::HardwareMonitor;
+
+/// Separates the backend prefix from the backend's own id.
+pub const PREFIX_SEPARATOR: char = ':';
+
+pub struct CompositeHardwareMonitor {
+    backends: Vec<(String, Arc<dyn HardwareMonitor>)>,
+    /// Computed here rather than per backend, so sources may come from several
+    virtual_sensors: std::sync::Mutex<VirtualSensors>,
+}
+
+fn prefixed(prefix: &str, id: &str) -> String {
+    format!("{}{}{}", prefix, PREFIX_SEPARATOR, id)
+}
+
+impl CompositeHardwareMonitor {
+    pub fn new(backends: Vec<(String, Arc<dyn HardwareMonitor>)>) -> Result<Self> {
+        if backends.is_empty() {
+            return Err(anyhow!("Composite hardware monitor needs at least one backend"));
+        }
+        for (i, (prefix, _)) in backends.iter().enumerate() {
+            if prefix.is_empty() || prefix.contains(PREFIX_SEPARATOR) {
+                return Err(anyhow!("Invalid backend prefix '{}' (non-empty, no '{}')", prefix, PREFIX_SEPARATOR));
+            }
+            if backends[..i].iter().any(|(p, _)| p == prefix) {
+                return Err(anyhow!("Duplicate backend prefix '{}'", prefix));
+            }
+        }
+        Ok(Self { backends, virtual_sensors: Default::default() })
+    }
+
+    /// Backend owning `id`, and the id as that backend knows it.
+    fn route<'a>(&self, id: &'a str) -> Result<(&Arc<dyn HardwareMonitor>, &'a str)> {
+        let (prefix, inner) = id
+            .split_once(PREFIX_SEPARATOR)
+            .ok_or_else(|| anyhow!("Fan id '{}' has no backend prefix", id))?;
+        self.backends
+            .iter()
+            .find(|(p, _)| p == prefix)
+            .map(|(_, backend)| (backend, inner))
+            .ok_or_else(|| anyhow!("No hardware backend '{}' for id '{}'", prefix, id))
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl CompositeHardwareMonitor {
+    /// Backends from config.json's `backends` list, sharing the `hardware` settings
+    /// (but for the virtual sensors, which the composite computes itself).
+    pub fn from_config(
+        backends: &[crate::config::types::HardwareBackendConfig],
+        hardware: &crate::config::types::HardwareSettings,
+    ) -> Result<Self> {
+        use crate::config::types::HardwareBackendConfig;
+        use super::linux::ipmi::IpmiBackend;
+        use super::LinuxHardwareMonitor;
+
+        let per_backend = crate::config::types::HardwareSettings { virtual_sensors: Vec::new(), ..hardware.clone() };
+        let backends = backends
+            .iter()
+            .map(|backend| {
+                let monitor: Arc<dyn HardwareMonitor> = match backend {
+                    HardwareBackendConfig::Hwmon { .. } => Arc::new(LinuxHardwareMonitor::new(per_backend.clone())),
+                    HardwareBackendConfig::Ipmi { profile, .. } => Arc::new(IpmiBackend::new(hardware, profile.as_deref())),
+                };
+                (backend.prefix().to_string(), monitor)
+            })
+            .collect();
+        let composite = Self::new(backends)?;
+        composite.set_virtual_sensors(&hardware.virtual_sensors);
+        Ok(composite)
+    }
+}
+
+#[async_trait]
+impl HardwareMonitor for CompositeHardwareMonitor {
+    async fn discover_sensors(&self) -> Result<Vec<Sensor>> {
+        // One failing backend (BMC busy, ipmitool missing) must not hide the others
+        let mut sensors = Vec::new();
+        let mut last_error = None;
+        for (prefix, backend) in &self.backends {
+            match backend.discover_sensors().await {
+                Ok(found) => sensors.extend(found.into_iter().map(|mut s| {
+                    s.id = prefixed(prefix, &s.id).into();
+                    s
+                })),
+                Err(e) => {
+                    warn!("{} backend: sensor discovery failed: {}", prefix, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        self.virtual_sensors.lock().unwrap().append(&mut sensors);
+        sort_sensors(&mut sensors);
+        match last_error {
+            Some(e) if sensors.is_empty() => Err(e),
+            _ => Ok(sensors),
+        }
+    }
+
+    async fn discover_fans(&self) -> Result<Vec<Fan>> {
+        let mut fans = Vec::new();
+        let mut last_error = None;
+        for (prefix, backend) in &self.backends {
+            match backend.discover_fans().await {
+                Ok(found) => fans.extend(found.into_iter().map(|mut f| {
+                    f.id = prefixed(prefix, &f.id);
+                    f.zone = f.zone.map(|zone| prefixed(prefix, &zone));
+                    f
+                })),
+                Err(e) => {
+                    warn!("{} backend: fan discovery failed: {}", prefix, e);
+                    last_error = Some(e);
+                }
+            }
+        }
+        sort_fans(&mut fans);
+        match last_error {
+            Some(e) if fans.is_empty() => Err(e),
+            _ => Ok(fans),
+        }
+    }
+
+    async fn get_system_info(&self) -> Result<SystemHealth> {
+        // Same process, same host: the first backend's view is the system's
+        self.backends[0].1.get_system_info().await
+    }
+
+    async fn set_fan_speed(&self, fan_id: &str, speed: u8, origin: &FanSpeedOrigin) -> Result<()> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.set_fan_speed(inner, speed, origin).await
+    }
+
+    async fn set_fan_pwm(&self, fan_id: &str, value: u8, origin: &FanSpeedOrigin) -> Result<()> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.set_fan_pwm(inner, value, origin).await
+    }
+
+    async fn max_cooling(&self) -> Result<()> {
+        let mut result = Ok(());
+        for (prefix, backend) in &self.backends {
+            if let Err(e) = backend.max_cooling().await {
+                error!("{} backend: max cooling failed: {}", prefix, e);
+                result = Err(e);
+            }
+        }
+        result
+    }
+
+    async fn restore_fan_to_auto(&self, fan_id: &str) -> Result<bool> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.restore_fan_to_auto(inner).await
+    }
+
+    async fn set_fan_mode(&self, fan_id: &str, mode: FanMode) -> Result<u8> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.set_fan_mode(inner, mode).await
+    }
+
+    async fn release_control(&self) -> Vec<(String, String)> {
+        let mut failed = Vec::new();
+        for (prefix, backend) in &self.backends {
+            failed.extend(backend.release_control().await.into_iter().map(|(id, why)| (prefixed(prefix, &id), why)));
+        }
+        failed
+    }
+
+    async fn set_disabled_chips(&self, chips: &[String]) {
+        for (_, backend) in &self.backends {
+            backend.set_disabled_chips(chips).await;
+        }
+    }
+
+    async fn chip_fans(&self, chip: &str) -> Vec<String> {
+        let mut fans = Vec::new();
+        for (prefix, backend) in &self.backends {
+            fans.extend(backend.chip_fans(chip).await.into_iter().map(|id| prefixed(prefix, &id)));
+        }
+        fans
+    }
+
+    async fn retry_fan_control(&self, fan_id: &str) -> Result<()> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.retry_fan_control(inner).await
+    }
+
+    async fn fan_control_state(&self, fan_id: &str) -> Result<Option<FanControlState>> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.fan_control_state(inner).await
+    }
+
+    async fn restore_fan_control_state(&self, fan_id: &str, state: &FanControlState) -> Result<()> {
+        let (backend, inner) = self.route(fan_id)?;
+        backend.restore_fan_control_state(inner, state).await
+    }
+
+    async fn fan_control_conflicts(&self) -> Vec<FanControlConflict> {
+        let mut conflicts = Vec::new();
+        for (prefix, backend) in &self.backends {
+            for mut conflict in backend.fan_control_conflicts().await {
+                conflict.fans = conflict.fans.iter().map(|id| prefixed(prefix, id)).collect();
+                conflicts.push(conflict);
+            }
+        }
+        conflicts
+    }
+
+    /// In a container if any backend is; monitoring-only only when no backend has
+    /// hwmon (an IPMI backend controls fans over the network)
+    async fn run_environment(&self) -> RunEnvironment {
+        let mut environment = RunEnvironment { container: false, no_hwmon: true };
+        for (_, backend) in &self.backends {
+            let backend = backend.run_environment().await;
+            environment.container |= backend.container;
+            environment.no_hwmon &= backend.no_hwmon;
+        }
+        environment
+    }
+
+    async fn invalidate_cache(&self) {
+        for (_, backend) in &self.backends {
+            backend.invalidate_cache().await;
+        }
+    }
+
+    async fn resumed(&self) {
+        for (_, backend) in &self.backends {
+            backend.resumed().await;
+        }
+    }
+
+    async fn refresh_cache(&self) {
+        for (_, backend) in &self.backends {
+            backend.refresh_cache().await;
+        }
+    }
+
+    async fn reconcile_discovery(&self) -> Result<()> {
+        for (_, backend) in &self.backends {
+            backend.reconcile_discovery().await?;
+        }
+        Ok(())
+    }
+
+    async fn last_discovery_from_cache(&self) -> bool {
+        for (_, backend) in &self.backends {
+            if !backend.last_discovery_from_cache().await {
+                return false;
+            }
+        }
+        true
+    }
+
+    async fn dump_hardware_info(&self) -> Result<HardwareDumpRoot> {
+        let mut dump = self.backends[0].1.dump_hardware_info().await?;
+        for (prefix, backend) in &self.backends[1..] {
+            match backend.dump_hardware_info().await {
+                Ok(more) => dump.hardware.extend(more.hardware),
+                Err(e) => warn!("{} backend: hardware dump failed: {}", prefix, e),
+            }
+        }
+        Ok(dump)
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.backends.iter().any(|(_, backend)| backend.is_dry_run())
+    }
+
+    fn set_temperature_smoothing(&self, mode: crate::config::types::TemperatureSmoothing) {
+        for (_, backend) in &self.backends {
+            backend.set_temperature_smoothing(mode);
+        }
+    }
+
+    fn next_cycle(&self) {
+        for (_, backend) in &self.backends {
+            backend.next_cycle();
+        }
+    }
+
+    fn set_virtual_sensors(&self, sensors: &[crate::config::types::VirtualSensor]) {
+        self.virtual_sensors.lock().unwrap().set(sensors.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::Mutex;
+
+    /// Backend with one sensor and one fan that records speed writes.
+    #[derive(Default)]
+    struct FakeBackend {
+        zone: Option<String>,
+        writes: Mutex<Vec<(String, u8)>>,
+    }
+
+    #[async_trait]
+    impl HardwareMonitor for FakeBackend {
+        async fn discover_sensors(&self) -> Result<Vec<Sensor>> {
+            Ok(vec![Sensor {
+                id: "temp1".into(),
+                name: "Temp".into(),
+                display_name: None,
+                temperature: 40.0,
+                sensor_type: "cpu".into(),
+                max_temp: None,
+                crit_temp: None,
+                chip: None,
+                hardware_name: None,
+                source: None,
+                alarms: Vec::new(),
+                raw_temperature: None,
+                stale: false,
+            }])
+        }
+        async fn discover_fans(&self) -> Result<Vec<Fan>> {
+            Ok(vec![Fan {
+                id: "fan1".to_string(),
+                name: "Fan".to_string(),
+                rpm: Some(900),
+                speed: 40,
+                target_speed: 40,
+                pwm: None,
+                status: "ok".to_string(),
+                status_detail: None,
+                has_pwm_control: true,
+                control_method: None,
+                pwm_file: None,
+                pwm_enable: None,
+                control_mode: None,
+                zone: self.zone.clone(),
+                alarms: Vec::new(),
+                drift: false,
+                control_error: None,
+                last_set_by: None,
+            }])
+        }
+        async fn get_system_info(&self) -> Result<SystemHealth> {
+            Err(anyhow!("unused"))
+        }
+        async fn set_fan_speed(&self, fan_id: &str, speed: u8, _origin: &FanSpeedOrigin) -> Result<()> {
+            self.writes.lock().await.push((fan_id.to_string(), speed));
+            Ok(())
+        }
+        async fn max_cooling(&self) -> Result<()> {
+            self.set_fan_speed("fan1", 100, &FanSpeedOrigin::Emergency).await
+        }
+        async fn release_control(&self) -> Vec<(String, String)> {
+            if self.zone.is_some() {
+                return vec![("cpu_zone".to_string(), "BMC unreachable".to_string())];
+            }
+            Vec::new()
+        }
+        async fn invalidate_cache(&self) {}
+        async fn last_discovery_from_cache(&self) -> bool {
+            false
+        }
+        async fn dump_hardware_info(&self) -> Result<HardwareDumpRoot> {
+            Err(anyhow!("unused"))
+        }
+    }
+
+    fn composite() -> (CompositeHardwareMonitor, Arc<FakeBackend>, Arc<FakeBackend>) {
+        let hwmon = Arc::new(FakeBackend::default());
+        let ipmi = Arc::new(FakeBackend { zone: Some("cpu_zone".to_string()), ..Default::default() });
+        let monitor = CompositeHardwareMonitor::new(vec![
+            ("hwmon".to_string(), hwmon.clone() as Arc<dyn HardwareMonitor>),
+            ("ipmi".to_string(), ipmi.clone() as Arc<dyn HardwareMonitor>),
+        ])
+        .unwrap();
+        (monitor, hwmon, ipmi)
+    }
+
+    #[tokio::test]
+    async fn ids_are_prefixed_and_commands_routed_back() {
+        let (monitor, hwmon, ipmi) = composite();
+
+        let sensors = monitor.discover_sensors().await.unwrap();
+        let ids: Vec<_> = sensors.iter().map(|s| &*s.id).collect();
+        assert_eq!(ids, ["hwmon:temp1", "ipmi:temp1"]);
+
+        let fans = monitor.discover_fans().await.unwrap();
+        assert_eq!(fans[1].id, "ipmi:fan1");
+        assert_eq!(fans[1].zone.as_deref(), Some("ipmi:cpu_zone"));
+
+        monitor.set_fan_speed("ipmi:cpu_zone", 60, &FanSpeedOrigin::LocalCli).await.unwrap();
+        assert_eq!(*ipmi.writes.lock().await, [("cpu_zone".to_string(), 60)]);
+        assert!(hwmon.writes.lock().await.is_empty());
+        assert!(monitor.set_fan_speed("fan1", 60, &FanSpeedOrigin::LocalCli).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn max_cooling_and_release_reach_every_backend() {
+        let (monitor, hwmon, ipmi) = composite();
+        monitor.max_cooling().await.unwrap();
+        assert_eq!(hwmon.writes.lock().await.len(), 1);
+        assert_eq!(ipmi.writes.lock().await.len(), 1);
+
+        let failed = monitor.release_control().await;
+        assert_eq!(failed, [("ipmi:cpu_zone".to_string(), "BMC unreachable".to_string())]);
+    }
+
+    #[test]
+    fn duplicate_prefixes_are_rejected() {
+        let backend = Arc::new(FakeBackend::default()) as Arc<dyn HardwareMonitor>;
+        let result = CompositeHardwareMonitor::new(vec![
+            ("ipmi".to_string(), backend.clone()),
+            ("ipmi".to_string(), backend),
+        ]);
+        assert!(result.is_err());
+    }
+}
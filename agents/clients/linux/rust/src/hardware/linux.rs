@@ -5,8 +5,40 @@ pub mod monitor;
 #[cfg(target_os = "linux")]
 pub mod sensors;
 #[cfg(target_os = "linux")]
+pub(crate) mod attr;
+#[cfg(target_os = "linux")]
+pub(crate) mod access;
+#[cfg(target_os = "linux")]
 pub mod fans;
 #[cfg(target_os = "linux")]
 pub mod diagnostics;
 #[cfg(target_os = "linux")]
+pub mod alarms;
+#[cfg(target_os = "linux")]
+pub mod permissions;
+#[cfg(target_os = "linux")]
+pub(crate) mod conflicts;
+#[cfg(target_os = "linux")]
+pub(crate) mod environment;
+#[cfg(target_os = "linux")]
+pub(crate) mod hotplug;
+#[cfg(target_os = "linux")]
+pub mod ipmi;
+#[cfg(target_os = "linux")]
 pub(crate) mod nvidia;
+#[cfg(target_os = "linux")]
+pub(crate) mod firmware;
+#[cfg(target_os = "linux")]
+pub(crate) mod fan_modes;
+#[cfg(target_os = "linux")]
+pub(crate) mod discovery_cache;
+#[cfg(target_os = "linux")]
+pub(crate) mod storage_health;
+#[cfg(target_os = "linux")]
+pub(crate) mod fan_presence;
+#[cfg(target_os = "linux")]
+pub(crate) mod ipmi_bridge;
+#[cfg(target_os = "linux")]
+pub(crate) mod lm_sensors;
+#[cfg(all(test, target_os = "linux"))]
+pub(crate) mod fixture;
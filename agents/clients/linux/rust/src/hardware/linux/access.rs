@@ -0,0 +1,117 @@
+//! Coordination of overlapping hardware access.
+//!
+//! The data sender, the failsafe check, commands and a diagnostics dump each
+//! discover sensors on their own schedule. `SingleFlight` lets calls that overlap
+//! share one scan instead of each walking sysfs. `ChipLocks`
+//! (`hardware.serialize_chip_access`) goes further for embedded controllers that
+//! return garbage under concurrent access: one read or write at a time per chip.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::Result;
+use tokio::sync::{Mutex, OwnedMutexGuard};
+
+/// Calls of `run` that start while another is in progress wait for it and get its
+/// result, instead of running their own.
+pub(crate) struct SingleFlight<T> {
+    /// Calls that ran to completion
+    completed: AtomicU64,
+    /// Held for the whole call; the last call's result (errors as text)
+    last: Mutex<Option<Result<T, String>>>,
+}
+
+impl<T> Default for SingleFlight<T> {
+    fn default() -> Self {
+        Self { completed: AtomicU64::new(0), last: Mutex::new(None) }
+    }
+}
+
+impl<T: Clone> SingleFlight<T> {
+    pub(crate) async fn run<F: Future<Output = Result<T>>>(&self, call: impl FnOnce() -> F) -> Result<T> {
+        let seen = self.completed.load(Ordering::Acquire);
+        let mut last = self.last.lock().await;
+        // A call finished while this one waited: it read the hardware after we asked
+        if self.completed.load(Ordering::Acquire) != seen {
+            if let Some(result) = last.as_ref() {
+                return result.clone().map_err(anyhow::Error::msg);
+            }
+        }
+        let result = call().await;
+        *last = Some(match &result {
+            Ok(value) => Ok(value.clone()),
+            Err(e) => Err(format!("{:#}", e)),
+        });
+        self.completed.fetch_add(1, Ordering::Release);
+        result
+    }
+}
+
+/// Lock per chip directory, created on first use
+type LockMap = std::sync::Mutex<HashMap<PathBuf, Arc<Mutex<()>>>>;
+
+/// One lock per hwmon chip directory, when enabled.
+#[derive(Clone, Default)]
+pub(crate) struct ChipLocks(Option<Arc<LockMap>>);
+
+impl ChipLocks {
+    pub(crate) fn new(enabled: bool) -> Self {
+        Self(enabled.then(Default::default))
+    }
+
+    /// Wait for exclusive access to the chip `path` belongs to. `None` when disabled.
+    pub(crate) async fn lock(&self, path: &Path) -> Option<OwnedMutexGuard<()>> {
+        let locks = self.0.as_ref()?;
+        let lock = {
+            let mut locks = locks.lock().unwrap();
+            Arc::clone(locks.entry(chip_dir(path).to_path_buf()).or_default())
+        };
+        Some(lock.lock_owned().await)
+    }
+}
+
+/// The `hwmonN` directory above an attribute (some drivers keep theirs under
+/// `hwmonN/device/`), else its parent directory.
+fn chip_dir(path: &Path) -> &Path {
+    path.ancestors()
+        .skip(1)
+        .find(|dir| {
+            dir.file_name()
+                .and_then(|name| name.to_str()?.strip_prefix("hwmon"))
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+        .or_else(|| path.parent())
+        .unwrap_or(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn attributes_map_to_their_hwmon_directory() {
+        let chip = Path::new("/sys/class/hwmon/hwmon2");
+        assert_eq!(chip_dir(&chip.join("pwm1")), chip);
+        assert_eq!(chip_dir(&chip.join("device/fan1_input")), chip);
+        assert_eq!(chip_dir(Path::new("/sys/class/thermal/thermal_zone0/temp")), Path::new("/sys/class/thermal/thermal_zone0"));
+    }
+
+    #[tokio::test]
+    async fn same_chip_waits_other_chips_do_not() {
+        let locks = ChipLocks::new(true);
+        let held = locks.lock(Path::new("/sys/class/hwmon/hwmon2/pwm1")).await;
+        assert!(held.is_some());
+
+        let same_chip = locks.lock(Path::new("/sys/class/hwmon/hwmon2/device/fan1_input"));
+        assert!(tokio::time::timeout(Duration::from_millis(20), same_chip).await.is_err());
+        assert!(locks.lock(Path::new("/sys/class/hwmon/hwmon3/temp1_input")).await.is_some());
+
+        drop(held);
+        assert!(locks.lock(Path::new("/sys/class/hwmon/hwmon2/temp1_input")).await.is_some());
+        assert!(ChipLocks::new(false).lock(Path::new("/sys/class/hwmon/hwmon2/pwm1")).await.is_none());
+    }
+}
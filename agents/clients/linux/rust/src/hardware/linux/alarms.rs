@@ -0,0 +1,120 @@
+//! Linux hardware monitor: hwmon alarm file reads.
+//!
+//! Drivers latch `tempN_crit_alarm`, `fanN_min_alarm`, etc. when a limit is crossed.
+//! These are more reliable than comparing readings against `tempN_crit` ourselves,
+//! since the driver knows the chip's real thresholds and hysteresis.
+
+use std::path::{Path, PathBuf};
+
+/// Alarm file suffixes and the flag name reported for each. Generic `*_alarm` is "alarm";
+/// `*_fault` (sensor or fan failed, per the driver) is "fault".
+const ALARM_KINDS: &[(&str, &str)] = &[
+    ("alarm", "alarm"),
+    ("min_alarm", "min"),
+    ("max_alarm", "max"),
+    ("lcrit_alarm", "lcrit"),
+    ("crit_alarm", "crit"),
+    ("emergency_alarm", "emergency"),
+    ("fault", "fault"),
+];
+
+/// Existing alarm files for a channel (`prefix` like "temp1" or "fan2"), resolved once
+/// at discovery so per-cycle reads don't probe for files that aren't there.
+pub(crate) fn alarm_paths(hwmon_dir: &Path, prefix: &str) -> Vec<(&'static str, PathBuf)> {
+    ALARM_KINDS
+        .iter()
+        .map(|(suffix, flag)| (*flag, hwmon_dir.join(format!("{}_{}", prefix, suffix))))
+        .filter(|(_, path)| path.exists())
+        .collect()
+}
+
+/// Alarm files for the channel owning an `*_input` file (e.g. `.../hwmon0/temp1_input`).
+pub(crate) fn alarm_paths_for_input(input_path: &Path) -> Vec<(&'static str, PathBuf)> {
+    let prefix = input_path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .and_then(|n| n.strip_suffix("_input"));
+    match (input_path.parent(), prefix) {
+        (Some(dir), Some(prefix)) => alarm_paths(dir, prefix),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl super::monitor::LinuxHardwareMonitor {
+    /// Flags whose alarm file currently reads non-zero. Unreadable files count as clear.
+    pub(crate) async fn read_alarms(&self, paths: &[(&'static str, PathBuf)]) -> Vec<String> {
+        let mut asserted = Vec::new();
+        for (flag, path) in paths {
+            if let Ok(value) = self.read_file(path).await {
+                if value.parse::<u32>().map(|v| v != 0).unwrap_or(false) {
+                    asserted.push(flag.to_string());
+                }
+            }
+        }
+        asserted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm, Temp};
+    use crate::hardware::HardwareMonitor;
+
+    /// One k10temp chip whose temp1 is past crit and whose fan1 is below min.
+    fn alarm_tree(tag: &str) -> FakeSysfs {
+        FakeSysfs::new(tag).chip(
+            Chip::new("k10temp")
+                .temp(Temp::new(1, 95_000).label("Tctl").crit(90_000))
+                .temp(Temp::new(2, 40_000).label("Tccd1"))
+                .file("temp1_crit_alarm", "1")
+                .file("temp1_max_alarm", "0")
+                .fan(1, 0)
+                .pwm(Pwm::new(1, 128))
+                .file("fan1_min_alarm", "1"),
+        )
+    }
+
+    #[test]
+    fn alarm_paths_only_lists_existing_files() {
+        let sysfs = alarm_tree("alarm-paths");
+        let chip = sysfs.chip_dir(0);
+
+        let flags: Vec<_> = alarm_paths(&chip, "temp1").into_iter().map(|(f, _)| f).collect();
+        assert_eq!(flags, vec!["max", "crit"]);
+        assert!(alarm_paths(&chip, "temp2").is_empty());
+        assert_eq!(alarm_paths_for_input(&chip.join("fan1_input")).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn sensors_report_asserted_alarms() {
+        let sysfs = alarm_tree("alarm-sensors");
+        let monitor = sysfs.monitor();
+
+        // First call populates the path cache, second reads through it.
+        for _ in 0..2 {
+            let sensors = monitor.discover_sensors().await.unwrap();
+            let tctl = sensors.iter().find(|s| &*s.id == "k10temp_tctl").unwrap();
+            assert_eq!(tctl.alarms, vec!["crit"]);
+            assert!(tctl.has_crit_alarm());
+
+            let tccd = sensors.iter().find(|s| &*s.id == "k10temp_tccd1").unwrap();
+            assert!(tccd.alarms.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn fans_report_asserted_alarms() {
+        let sysfs = alarm_tree("alarm-fans");
+        let monitor = sysfs.monitor();
+
+        let fans = monitor.discover_fans().await.unwrap();
+        let fan = fans.iter().find(|f| f.id == "k10temp_fan_1").unwrap();
+        assert_eq!(fan.alarms, vec!["min"]);
+
+        std::fs::write(sysfs.chip_dir(0).join("fan1_min_alarm"), "0\n").unwrap();
+        let fans = monitor.discover_fans().await.unwrap();
+        assert!(fans[0].alarms.is_empty());
+    }
+}
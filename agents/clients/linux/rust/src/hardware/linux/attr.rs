@@ -0,0 +1,209 @@
+//! Low-level sysfs attribute reads.
+//!
+//! Some drivers occasionally hand back an empty read, or a value with a stray
+//! non-UTF-8 byte, for a single cycle. Reads take bytes and decode them lossily.
+//! Interrupted reads are repeated, and an empty or unparsable value is read once
+//! more after `RETRY_DELAY`. What still fails is a `ReadError`: `Transient` lets
+//! callers keep the previous value, while `Gone` means the device went away.
+
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Pause before the second read of an empty or unparsable value.
+pub(crate) const RETRY_DELAY: Duration = Duration::from_millis(2);
+
+/// sysfs attributes are at most a page.
+const MAX_ATTR_BYTES: usize = 4096;
+
+#[derive(Debug)]
+pub(crate) enum ReadError {
+    /// Empty or garbled content, or a busy/failing bus (EAGAIN, EBUSY, EIO, ...):
+    /// the next read may well succeed
+    Transient(String),
+    /// The device behind the file went away (unbind, hot-unplug)
+    Gone(io::Error),
+    /// Anything else, e.g. permission denied
+    Io(io::Error),
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Transient(reason) => write!(f, "transient read failure: {}", reason),
+            Self::Gone(e) | Self::Io(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Transient(_) => None,
+            Self::Gone(e) | Self::Io(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for ReadError {
+    fn from(e: io::Error) -> Self {
+        match e.raw_os_error() {
+            Some(code) if is_gone_code(code) => Self::Gone(e),
+            Some(libc::EAGAIN | libc::EBUSY | libc::EINTR | libc::EIO | libc::ETIMEDOUT) => Self::Transient(e.to_string()),
+            _ => Self::Io(e),
+        }
+    }
+}
+
+/// ENODEV/ENXIO/ESTALE from a kept handle, ENOENT from a path: the device is gone.
+pub(crate) fn is_gone_code(code: i32) -> bool {
+    matches!(code, libc::ENODEV | libc::ENXIO | libc::ESTALE | libc::ENOENT)
+}
+
+/// The I/O error behind a failed read, whether or not it went through `ReadError`.
+pub(crate) fn io_error(e: &anyhow::Error) -> Option<&io::Error> {
+    match e.downcast_ref::<ReadError>() {
+        Some(ReadError::Gone(io) | ReadError::Io(io)) => Some(io),
+        Some(ReadError::Transient(_)) => None,
+        None => e.downcast_ref::<io::Error>(),
+    }
+}
+
+/// A read that may succeed next time; the previous value is still the best guess.
+pub(crate) fn is_transient(e: &anyhow::Error) -> bool {
+    matches!(e.downcast_ref::<ReadError>(), Some(ReadError::Transient(_)))
+}
+
+/// A text attribute (name, label, model), trimmed. Through `file` (pread at offset 0,
+/// which makes sysfs regenerate the value) when given, else by path.
+pub(crate) fn read_text(file: Option<&File>, path: &Path) -> Result<String, ReadError> {
+    read_with(|| read_bytes(file, path), |text| Some(text.to_string()))
+}
+
+/// A numeric attribute; garbage is retried like an empty read.
+pub(crate) fn read_number<T: FromStr>(file: Option<&File>, path: &Path) -> Result<T, ReadError> {
+    read_with(|| read_bytes(file, path), |text| text.parse().ok())
+}
+
+fn read_bytes(file: Option<&File>, path: &Path) -> io::Result<Vec<u8>> {
+    match file {
+        Some(file) => {
+            use std::os::unix::fs::FileExt;
+            let mut buf = vec![0u8; MAX_ATTR_BYTES];
+            let n = file.read_at(&mut buf, 0)?;
+            buf.truncate(n);
+            Ok(buf)
+        }
+        None => std::fs::read(path),
+    }
+}
+
+/// Two reads at most, besides interrupted ones.
+fn read_with<T>(mut read: impl FnMut() -> io::Result<Vec<u8>>, parse: impl Fn(&str) -> Option<T>) -> Result<T, ReadError> {
+    let mut failure = None;
+    for attempt in 0..2 {
+        if attempt > 0 {
+            std::thread::sleep(RETRY_DELAY);
+        }
+        let bytes = loop {
+            match read() {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                result => break result,
+            }
+        };
+        let text = match bytes {
+            Ok(bytes) => decode(&bytes),
+            Err(e) => match ReadError::from(e) {
+                transient @ ReadError::Transient(_) => {
+                    failure = Some(transient);
+                    continue;
+                }
+                e => return Err(e),
+            },
+        };
+        if text.is_empty() {
+            failure = Some(ReadError::Transient("empty read".to_string()));
+        } else if let Some(value) = parse(&text) {
+            return Ok(value);
+        } else {
+            failure = Some(ReadError::Transient(format!("unexpected content {:?}", text)));
+        }
+    }
+    Err(failure.expect("read attempted"))
+}
+
+/// Attribute text from raw bytes: invalid UTF-8 replaced, and stray control or
+/// replaced bytes at either end dropped along with the newline.
+fn decode(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes)
+        .trim_matches(|c: char| c.is_whitespace() || c.is_control() || c == char::REPLACEMENT_CHARACTER)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(name: &str, contents: &[u8]) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("pankha-attr-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("temp1_input");
+        std::fs::write(&path, contents).unwrap();
+        path
+    }
+
+    #[test]
+    fn non_utf8_bytes_are_decoded_lossily() {
+        let path = temp_file("utf8", b"45000\xff\n");
+        assert_eq!(read_number::<i32>(None, &path).unwrap(), 45000);
+
+        let file = File::open(&path).unwrap();
+        assert_eq!(read_number::<i32>(Some(&file), &path).unwrap(), 45000);
+
+        std::fs::write(&path, b"Core\xfe0\n").unwrap();
+        assert_eq!(read_text(None, &path).unwrap(), "Core\u{FFFD}0");
+    }
+
+    /// Hands out `reads` in turn, like a driver whose attribute changes between reads.
+    fn fake_file(reads: Vec<io::Result<&'static [u8]>>) -> impl FnMut() -> io::Result<Vec<u8>> {
+        let mut reads = reads.into_iter();
+        move || reads.next().expect("no more reads").map(<[u8]>::to_vec)
+    }
+
+    #[test]
+    fn empty_read_is_retried_then_transient() {
+        let number = |text: &str| text.parse::<i32>().ok();
+
+        let read = fake_file(vec![Ok(b""), Ok(b"51000\n")]);
+        assert_eq!(read_with(read, number).unwrap(), 51000);
+
+        // Interrupted reads don't count as the retry
+        let read = fake_file(vec![Err(io::ErrorKind::Interrupted.into()), Ok(b"\n"), Err(io::ErrorKind::Interrupted.into()), Ok(b"52000")]);
+        assert_eq!(read_with(read, number).unwrap(), 52000);
+
+        let read = fake_file(vec![Ok(b"\xff"), Ok(b"53000\xff")]);
+        assert_eq!(read_with(read, number).unwrap(), 53000);
+
+        let read = fake_file(vec![Ok(b""), Ok(b"")]);
+        assert!(matches!(read_with(read, number), Err(ReadError::Transient(_))));
+
+        let read = fake_file(vec![Err(io::Error::from_raw_os_error(libc::EIO)), Ok(b"garbage")]);
+        let error = anyhow::Error::from(read_with(read, number).unwrap_err());
+        assert!(is_transient(&error), "{}", error);
+    }
+
+    #[test]
+    fn missing_file_is_gone_and_keeps_its_io_error() {
+        let path = temp_file("gone", b"1");
+        std::fs::remove_file(&path).unwrap();
+
+        let error = anyhow::Error::from(read_text(None, &path).unwrap_err()).context("read");
+        assert!(!is_transient(&error));
+        assert_eq!(io_error(&error).and_then(|io| io.raw_os_error()), Some(libc::ENOENT));
+        assert!(matches!(ReadError::from(io::Error::from_raw_os_error(libc::EIO)), ReadError::Transient(_)));
+        assert!(matches!(ReadError::from(io::Error::from_raw_os_error(libc::EACCES)), ReadError::Io(_)));
+    }
+}
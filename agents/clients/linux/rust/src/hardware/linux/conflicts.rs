@@ -0,0 +1,189 @@
+//! Linux hardware monitor: other fan-control software on the same machine.
+//!
+//! lm-sensors `fancontrol`, thinkfan, CoolerControl and CoreCtrl all write `pwmN` /
+//! `pwmN_enable`. Running one of them next to the agent makes two loops fight over
+//! the same fans, which shows up as oscillation rather than an error. Checked once at
+//! startup (log, registration) and by `--test`; `hardware.monitor_only_on_conflict`
+//! additionally stops the agent from writing fans.
+//!
+//! Running daemons are found by name under `/proc`. Which fans they drive comes from
+//! `/etc/fancontrol` for fancontrol; otherwise a fan already in manual mode
+//! (`pwmN_enable` = 1) before the agent wrote to it is attributed to the daemon.
+
+use std::path::{Path, PathBuf};
+
+use crate::hardware::types::FanControlConflict;
+
+/// Process names of fan controllers that conflict with the agent.
+pub const KNOWN_FAN_CONTROLLERS: &[&str] = &["fancontrol", "thinkfan", "coolercontrold", "corectrl"];
+
+pub(crate) const PROC_ROOT: &str = "/proc";
+pub(crate) const FANCONTROL_CONFIG: &str = "/etc/fancontrol";
+
+/// A fan as found at startup, before the agent wrote to it.
+pub(crate) struct FanChannel {
+    pub(crate) id: String,
+    pub(crate) pwm_path: PathBuf,
+    pub(crate) pwm_enable: Option<String>,
+}
+
+/// Known fan controllers running under `proc_root`, as (pid, name), by pid.
+pub(crate) fn running_fan_controllers(proc_root: &Path) -> Vec<(u32, &'static str)> {
+    let Ok(entries) = std::fs::read_dir(proc_root) else {
+        return Vec::new();
+    };
+    let mut found: Vec<(u32, &'static str)> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let pid = entry.file_name().to_str()?.parse::<u32>().ok()?;
+            Some((pid, fan_controller_name(&entry.path())?))
+        })
+        .collect();
+    found.sort();
+    found
+}
+
+/// Which known controller the process in `dir` is: by `comm`, or by the first two
+/// `cmdline` words for scripts started through an interpreter (`bash /usr/sbin/fancontrol`).
+fn fan_controller_name(dir: &Path) -> Option<&'static str> {
+    let comm = std::fs::read_to_string(dir.join("comm")).unwrap_or_default();
+    let cmdline = std::fs::read(dir.join("cmdline")).unwrap_or_default();
+    let args = cmdline
+        .split(|&b| b == 0)
+        .take(2)
+        .map(|arg| String::from_utf8_lossy(arg).rsplit('/').next().unwrap_or_default().to_string());
+
+    std::iter::once(comm.trim().to_string())
+        .chain(args)
+        .find_map(|name| KNOWN_FAN_CONTROLLERS.iter().copied().find(|known| *known == name))
+}
+
+/// PWM attributes an `/etc/fancontrol` file manages, relative to the hwmon class
+/// directory (`hwmon2/pwm1`), from its FCTEMPS and FCFANS lines.
+pub(crate) fn fancontrol_pwms(config: &str) -> Vec<PathBuf> {
+    let mut pwms: Vec<PathBuf> = config
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            line.strip_prefix("FCTEMPS=").or_else(|| line.strip_prefix("FCFANS="))
+        })
+        .flat_map(str::split_whitespace)
+        .filter_map(|pair| pair.split_once('=').map(|(pwm, _)| PathBuf::from(pwm)))
+        .collect();
+    pwms.sort();
+    pwms.dedup();
+    pwms
+}
+
+/// Conflicts from what was found: one per running controller, plus one for an
+/// `/etc/fancontrol` covering our fans while fancontrol itself isn't running (its
+/// service would take them over on the next boot).
+pub(crate) fn find_conflicts(
+    processes: &[(u32, &'static str)],
+    fancontrol_config: Option<&str>,
+    fans: &[FanChannel],
+) -> Vec<FanControlConflict> {
+    let configured: Vec<String> = fancontrol_config
+        .map(fancontrol_pwms)
+        .unwrap_or_default()
+        .iter()
+        .flat_map(|pwm| fans.iter().filter(move |f| f.pwm_path.ends_with(pwm)))
+        .map(|f| f.id.clone())
+        .collect();
+    let manual: Vec<String> = fans.iter()
+        .filter(|f| f.pwm_enable.as_deref() == Some("1"))
+        .map(|f| f.id.clone())
+        .collect();
+
+    let mut conflicts: Vec<FanControlConflict> = processes
+        .iter()
+        .map(|&(pid, program)| {
+            let fans = if program == "fancontrol" && !configured.is_empty() { configured.clone() } else { manual.clone() };
+            let message = if fans.is_empty() {
+                format!("{} is running (pid {}) and may also be writing fan speeds", program, pid)
+            } else {
+                format!("{} is running (pid {}) and controls {}", program, pid, fans.join(", "))
+            };
+            FanControlConflict { program: program.to_string(), pid: Some(pid), fans, message }
+        })
+        .collect();
+
+    if !configured.is_empty() && !processes.iter().any(|&(_, program)| program == "fancontrol") {
+        conflicts.push(FanControlConflict {
+            program: "fancontrol".to_string(),
+            pid: None,
+            message: format!("{} configures {}; fancontrol is not running, but its service would take these fans over when started",
+                             FANCONTROL_CONFIG, configured.join(", ")),
+            fans: configured,
+        });
+    }
+    conflicts
+}
+
+#[cfg(target_os = "linux")]
+impl super::monitor::LinuxHardwareMonitor {
+    /// Look for conflicting fan controllers under `proc_root`, with the fancontrol
+    /// configuration at `fancontrol_config`. Discovers fans if that hasn't happened yet.
+    pub(crate) async fn detect_fan_control_conflicts(&self, proc_root: &Path, fancontrol_config: &Path) -> Vec<FanControlConflict> {
+        if self.discovered_fans.read().await.is_empty() {
+            let _ = self.discover_hwmon_fans().await;
+        }
+
+        let mut fans = Vec::new();
+        for (id, info) in self.discovered_fans.read().await.iter() {
+            let pwm_enable = match &info.pwm_enable_path {
+                Some(path) => self.read_attr(info.enable_fd.as_ref(), path).await.ok(),
+                None => None,
+            };
+            fans.push(FanChannel { id: id.clone(), pwm_path: info.pwm_path.clone(), pwm_enable });
+        }
+        fans.sort_by(|a, b| a.id.cmp(&b.id));
+
+        let config = std::fs::read_to_string(fancontrol_config).ok();
+        find_conflicts(&running_fan_controllers(proc_root), config.as_deref(), &fans)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm};
+
+    #[test]
+    fn fan_controllers_found_by_comm_or_interpreter_cmdline() {
+        let sysfs = FakeSysfs::new("conflict-proc")
+            .file("proc/1/comm", "systemd")
+            .file("proc/812/comm", "thinkfan")
+            // fancontrol is a shell script: comm is the interpreter
+            .file("proc/977/comm", "bash")
+            .file("proc/977/cmdline", "/bin/bash\0/usr/sbin/fancontrol\0")
+            .file("proc/self/comm", "thinkfan");
+
+        assert_eq!(running_fan_controllers(&sysfs.root().join("proc")), [(812, "thinkfan"), (977, "fancontrol")]);
+        assert!(running_fan_controllers(&sysfs.root().join("missing")).is_empty());
+    }
+
+    #[tokio::test]
+    async fn fancontrol_config_and_manual_mode_name_the_fans() {
+        let sysfs = FakeSysfs::new("conflict-fans")
+            .chip(Chip::new("nct6798").fan(1, 900).pwm(Pwm::new(1, 128).enable(1)).fan(2, 800).pwm(Pwm::new(2, 100).enable(2)))
+            .file("etc/fancontrol", "INTERVAL=10\nFCTEMPS=hwmon0/pwm2=hwmon0/temp1_input\nFCFANS=hwmon0/pwm2=hwmon0/fan2_input\n");
+        let monitor = sysfs.monitor();
+        let proc_root = sysfs.root().join("proc");
+        let config = sysfs.root().join("etc/fancontrol");
+
+        // Configured but not running: one config-only conflict
+        let conflicts = monitor.detect_fan_control_conflicts(&proc_root, &config).await;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].program.as_str(), conflicts[0].pid), ("fancontrol", None));
+        assert_eq!(conflicts[0].fans, ["nct6798_fan_2"]);
+
+        // thinkfan running: the fan left in manual mode is attributed to it
+        std::fs::create_dir_all(proc_root.join("4242")).unwrap();
+        std::fs::write(proc_root.join("4242/comm"), "thinkfan\n").unwrap();
+        let conflicts = monitor.detect_fan_control_conflicts(&proc_root, &sysfs.root().join("etc/none")).await;
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!((conflicts[0].program.as_str(), conflicts[0].pid), ("thinkfan", Some(4242)));
+        assert_eq!(conflicts[0].fans, ["nct6798_fan_1"]);
+    }
+}
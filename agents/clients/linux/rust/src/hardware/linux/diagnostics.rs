@@ -1,57 +1,55 @@
 //! Linux hardware monitor: hardware diagnostic dump generation.
 
-use std::path::{Path, PathBuf};
+use std::path::Path;
 
 use anyhow::Result;
-use tracing::info;
+use tracing::debug;
 
 use crate::hardware::types::*;
 
+use super::monitor::ControlMethod;
+
 #[cfg(target_os = "linux")]
 impl super::monitor::LinuxHardwareMonitor {
     /// Generate comprehensive hardware dump for diagnostics
     /// Dynamically discovers all hardware via sysfs - no hardcoded paths
     pub async fn dump_hardware_info(&self) -> Result<HardwareDumpRoot> {
-        info!("Generating hardware-info.json dump...");
+        debug!("Generating hardware dump...");
 
         let mut dump = HardwareDumpRoot {
             metadata: self.build_dump_metadata().await,
             hardware: Vec::new(),
+            storage_health: self.storage_health.as_ref().map(|s| s.snapshot()).unwrap_or_default(),
         };
 
-        // Discover all hwmon devices dynamically
-        if self.hwmon_base.exists() {
-            let mut entries = tokio::fs::read_dir(&self.hwmon_base).await?;
-            while let Some(entry) = entries.next_entry().await? {
-                let hwmon_dir = entry.path();
-                if !hwmon_dir.is_dir() {
-                    continue;
-                }
-
-                if let Ok(item) = self.build_hwmon_dump_item(&hwmon_dir).await {
-                    dump.hardware.push(item);
-                }
+        // Discover all hwmon devices dynamically, in index order (not readdir order)
+        for hwmon_dir in self.list_hwmon_dirs().await? {
+            if let Ok(item) = self.build_hwmon_dump_item(&hwmon_dir).await {
+                dump.hardware.push(item);
             }
         }
 
         // Add thermal zones as separate hardware items
-        let thermal_base = PathBuf::from("/sys/class/thermal");
-        if thermal_base.exists() {
-            let mut entries = tokio::fs::read_dir(&thermal_base).await?;
+        if self.thermal_base.exists() {
+            let mut zones = Vec::new();
+            let mut entries = tokio::fs::read_dir(&self.thermal_base).await?;
             while let Some(entry) = entries.next_entry().await? {
                 let zone_dir = entry.path();
                 let name = zone_dir.file_name().unwrap_or_default().to_string_lossy();
-                if !name.starts_with("thermal_zone") {
-                    continue;
+                if let Some(index) = name.strip_prefix("thermal_zone").and_then(|n| n.parse::<u32>().ok()) {
+                    zones.push((index, zone_dir));
                 }
+            }
+            zones.sort();
 
+            for (_, zone_dir) in zones {
                 if let Ok(item) = self.build_thermal_zone_dump_item(&zone_dir).await {
                     dump.hardware.push(item);
                 }
             }
         }
 
-        info!("Hardware dump complete: {} devices discovered", dump.hardware.len());
+        debug!("Hardware dump complete: {} devices discovered", dump.hardware.len());
         Ok(dump)
     }
 
@@ -78,8 +76,8 @@ impl super::monitor::LinuxHardwareMonitor {
 
         // Get motherboard info from DMI
         let motherboard = {
-            let vendor = self.read_file(Path::new("/sys/class/dmi/id/board_vendor")).await.ok();
-            let name = self.read_file(Path::new("/sys/class/dmi/id/board_name")).await.ok();
+            let vendor = self.read_file(&self.sysfs_root.join("class/dmi/id/board_vendor")).await.ok();
+            let name = self.read_file(&self.sysfs_root.join("class/dmi/id/board_name")).await.ok();
             match (vendor, name) {
                 (Some(v), Some(n)) => Some(format!("{} {}", v, n)),
                 (None, Some(n)) => Some(n),
@@ -96,6 +94,8 @@ impl super::monitor::LinuxHardwareMonitor {
             motherboard,
             kernel_version,
             cpu_model: Some(self.cpu_brand.clone()),
+            temperature_unit: None,
+            config_history: Vec::new(),
         }
     }
 
@@ -114,7 +114,7 @@ impl super::monitor::LinuxHardwareMonitor {
             let temp_input = hwmon_dir.join(format!("temp{}_input", i));
             if !temp_input.exists() { continue; }
 
-            if let Ok(sensor) = self.build_temp_sensor_dump(&hwmon_dir, i, &chip_name).await {
+            if let Ok(sensor) = self.build_temp_sensor_dump(hwmon_dir, i, &chip_name).await {
                 sensors.push(sensor);
             }
         }
@@ -124,7 +124,7 @@ impl super::monitor::LinuxHardwareMonitor {
             let fan_input = hwmon_dir.join(format!("fan{}_input", i));
             if !fan_input.exists() { continue; }
 
-            if let Ok(sensor) = self.build_fan_sensor_dump(&hwmon_dir, i, &chip_name).await {
+            if let Ok(sensor) = self.build_fan_sensor_dump(hwmon_dir, i, &chip_name).await {
                 sensors.push(sensor);
             }
         }
@@ -134,7 +134,17 @@ impl super::monitor::LinuxHardwareMonitor {
             let pwm_file = hwmon_dir.join(format!("pwm{}", i));
             if !pwm_file.exists() { continue; }
 
-            if let Ok(sensor) = self.build_pwm_sensor_dump(&hwmon_dir, i, &chip_name).await {
+            if let Ok(sensor) = self.build_pwm_sensor_dump(hwmon_dir, i, &chip_name).await {
+                sensors.push(sensor);
+            }
+        }
+
+        // Target-RPM controls of channels without pwmN: fan*_target
+        for i in 1..=10 {
+            let fan_target = hwmon_dir.join(format!("fan{}_target", i));
+            if !fan_target.exists() || hwmon_dir.join(format!("pwm{}", i)).exists() { continue; }
+
+            if let Ok(sensor) = self.build_target_sensor_dump(hwmon_dir, i, &chip_name).await {
                 sensors.push(sensor);
             }
         }
@@ -144,7 +154,7 @@ impl super::monitor::LinuxHardwareMonitor {
             let in_input = hwmon_dir.join(format!("in{}_input", i));
             if !in_input.exists() { continue; }
 
-            if let Ok(sensor) = self.build_voltage_sensor_dump(&hwmon_dir, i, &chip_name).await {
+            if let Ok(sensor) = self.build_voltage_sensor_dump(hwmon_dir, i, &chip_name).await {
                 sensors.push(sensor);
             }
         }
@@ -154,6 +164,7 @@ impl super::monitor::LinuxHardwareMonitor {
             identifier,
             hardware_type,
             parent: None,
+            disabled: self.chip_disabled(&chip_name),
             technical_id: Some(chip_name),
             sensors,
             sub_hardware: Vec::new(),
@@ -196,6 +207,8 @@ impl super::monitor::LinuxHardwareMonitor {
             is_monitored: true,
             is_connected: None,
             control: None,
+            reported_as: self.dump_reported_sensor(&temp_input).await,
+            reported_value: None,
         })
     }
 
@@ -205,6 +218,9 @@ impl super::monitor::LinuxHardwareMonitor {
 
         let rpm: u32 = self.read_file(&fan_input).await?.parse()?;
         let is_connected = rpm > 0;
+        let correction = self.discovered_fans.read().await.values()
+            .find(|info| info.rpm_path.as_deref() == Some(fan_input.as_path()))
+            .and_then(|info| info.pulse_correction);
 
         let min = self.read_file(&fan_min).await.ok()
             .unwrap_or_else(|| "0".to_string());
@@ -226,7 +242,10 @@ impl super::monitor::LinuxHardwareMonitor {
                 current_percent: None,
                 range: [0, 100],
                 mode: None,
+                write_failures: None,
             }),
+            reported_as: None,
+            reported_value: correction.map(|c| c.apply(rpm) as f32),
         })
     }
 
@@ -254,6 +273,8 @@ impl super::monitor::LinuxHardwareMonitor {
             })
             .unwrap_or(false);
 
+        let write_failures = self.dump_write_failures(&pwm_file).await;
+
         Ok(HardwareDumpSensor {
             name: format!("Fan Control {}", index),
             identifier: format!("/{}/control/{}", chip_name, index),
@@ -271,10 +292,94 @@ impl super::monitor::LinuxHardwareMonitor {
                 current_percent: Some(percent),
                 range: [0, 100],
                 mode: mode_str,
+                write_failures,
+            }),
+            reported_as: None,
+            reported_value: None,
+        })
+    }
+
+    /// A `fanN_target` control. Percent is relative to the range the agent maps
+    /// speeds onto once it has discovered the fan, else to `fanN_max`.
+    async fn build_target_sensor_dump(&self, hwmon_dir: &Path, index: u32, chip_name: &str) -> Result<HardwareDumpSensor> {
+        let target_file = hwmon_dir.join(format!("fan{}_target", index));
+        let target_rpm: u32 = self.read_file(&target_file).await?.parse()?;
+
+        let control = self.discovered_fans.read().await.values()
+            .find(|info| info.pwm_path == target_file)
+            .map(|info| info.control);
+        let control = match control {
+            Some(control) => control,
+            None => {
+                let read_rpm = |name: String| async move {
+                    self.read_file(&hwmon_dir.join(name)).await.ok().and_then(|s| s.parse::<u32>().ok())
+                };
+                ControlMethod::TargetRpm {
+                    min_rpm: read_rpm(format!("fan{}_min", index)).await.unwrap_or(0),
+                    max_rpm: read_rpm(format!("fan{}_max", index)).await.unwrap_or(0),
+                }
+            }
+        };
+        let percent = control.percent_of(target_rpm) as f32;
+
+        let can_write = target_file.metadata()
+            .map(|m| {
+                use std::os::unix::fs::PermissionsExt;
+                m.permissions().mode() & 0o200 != 0
+            })
+            .unwrap_or(false);
+
+        Ok(HardwareDumpSensor {
+            name: format!("Fan Control {}", index),
+            identifier: format!("/{}/control/{}", chip_name, index),
+            sensor_type: "Control".to_string(),
+            value: Some(percent),
+            min: "0".to_string(),
+            max: "100".to_string(),
+            is_monitored: true,
+            is_connected: None,
+            control: Some(HardwareDumpControlInfo {
+                linked_sensor_id: Some(format!("/{}/fan/{}", chip_name, index)),
+                method: "sysfs_target_rpm".to_string(),
+                can_write,
+                can_restore_default: false,
+                current_percent: Some(percent),
+                range: [0, 100],
+                mode: Some(format!("Target {} RPM", target_rpm)),
+                write_failures: self.dump_write_failures(&target_file).await,
             }),
+            reported_as: None,
+            reported_value: None,
         })
     }
 
+    /// Write failure counters of the discovered fan controlled through `control_file`.
+    async fn dump_write_failures(&self, control_file: &Path) -> Option<HardwareDumpWriteFailures> {
+        self.discovered_fans.read().await.values()
+            .find(|info| info.pwm_path == control_file)
+            .map(|info| {
+                let failures = info.write_failures.lock().unwrap();
+                HardwareDumpWriteFailures {
+                    consecutive: failures.consecutive,
+                    total: failures.total,
+                    write_ignored: failures.write_ignored,
+                    degraded: failures.degraded,
+                    reason: failures.reason.map(String::from),
+                }
+            })
+    }
+
+    /// Id and names of the discovered sensor reading `input`.
+    async fn dump_reported_sensor(&self, input: &Path) -> Option<HardwareDumpReportedSensor> {
+        self.discovered_sensors.read().await.values()
+            .find(|info| info.temp_input_path == input)
+            .map(|info| HardwareDumpReportedSensor {
+                id: info.id.to_string(),
+                name: info.name.to_string(),
+                display_name: info.display_name.as_deref().map(str::to_string),
+            })
+    }
+
     async fn build_voltage_sensor_dump(&self, hwmon_dir: &Path, index: u32, chip_name: &str) -> Result<HardwareDumpSensor> {
         let in_input = hwmon_dir.join(format!("in{}_input", index));
         let in_label = hwmon_dir.join(format!("in{}_label", index));
@@ -307,6 +412,8 @@ impl super::monitor::LinuxHardwareMonitor {
             is_monitored: false,
             is_connected: None,
             control: None,
+            reported_as: None,
+            reported_value: None,
         })
     }
 
@@ -328,6 +435,8 @@ impl super::monitor::LinuxHardwareMonitor {
             is_monitored: true,
             is_connected: None,
             control: None,
+            reported_as: self.dump_reported_sensor(&zone_dir.join("temp")).await,
+            reported_value: None,
         };
 
         Ok(HardwareDumpItem {
@@ -338,6 +447,7 @@ impl super::monitor::LinuxHardwareMonitor {
             technical_id: Some(zone_type),
             sensors: vec![sensor],
             sub_hardware: Vec::new(),
+            disabled: false,
         })
     }
 
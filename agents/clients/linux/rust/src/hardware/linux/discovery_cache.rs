@@ -0,0 +1,152 @@
+//! The sensor cache as of the last full discovery, kept across restarts.
+//!
+//! Full discovery walks every hwmon chip and looks up drive models, which takes
+//! seconds on hosts with many drives. After each one the cache (ids, paths and
+//! metadata, no readings) is written to discovery-cache.json beside the executable.
+//! The first discovery of the next run loads it instead, if it was written by the same
+//! agent version on the same kernel and a sample of its paths still belongs to the
+//! chips it was found on, and reads from it right away while the full discovery runs
+//! in the background (`reconcile_discovery`). A cache that doesn't check out, such as
+//! after hwmon was renumbered, is ignored and discovery runs as without one. Fans are
+//! scanned every cycle and not cached.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use super::alarms::alarm_paths_for_input;
+use super::monitor::{open_attr, SensorInfo};
+
+pub(crate) const DISCOVERY_CACHE_FILE: &str = "discovery-cache.json";
+
+/// Entries whose paths are checked before a cache is used.
+const VERIFY_SAMPLE: usize = 8;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CachedSensor {
+    pub(crate) path: PathBuf,
+    pub(crate) id: String,
+    pub(crate) name: String,
+    #[serde(default)]
+    pub(crate) display_name: Option<String>,
+    pub(crate) sensor_type: String,
+    pub(crate) max_temp: Option<f64>,
+    pub(crate) crit_temp: Option<f64>,
+    /// Also the contents of `name` in the chip directory, for hwmon sensors
+    pub(crate) chip: Option<String>,
+    pub(crate) hardware_name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct DiscoveryCache {
+    pub(crate) agent_version: String,
+    /// `/proc/sys/kernel/osrelease` when the cache was written
+    pub(crate) kernel: String,
+    pub(crate) sensors: Vec<CachedSensor>,
+}
+
+/// discovery-cache.json beside the executable.
+pub(crate) fn discovery_cache_path() -> Option<PathBuf> {
+    Some(std::env::current_exe().ok()?.parent()?.join(DISCOVERY_CACHE_FILE))
+}
+
+fn kernel_release() -> String {
+    std::fs::read_to_string("/proc/sys/kernel/osrelease").map(|s| s.trim().to_string()).unwrap_or_default()
+}
+
+fn chip_name(path: &Path) -> Option<String> {
+    let name = std::fs::read_to_string(path.parent()?.join("name")).ok()?;
+    Some(name.trim().to_string())
+}
+
+impl DiscoveryCache {
+    pub(crate) fn new<'a>(sensors: impl IntoIterator<Item = &'a SensorInfo>) -> Self {
+        let mut sensors: Vec<CachedSensor> = sensors
+            .into_iter()
+            .map(|info| CachedSensor {
+                path: info.temp_input_path.clone(),
+                id: info.id.to_string(),
+                name: info.name.to_string(),
+                display_name: info.display_name.as_deref().map(str::to_string),
+                sensor_type: info.sensor_type.to_string(),
+                max_temp: info.max_temp,
+                crit_temp: info.crit_temp,
+                chip: info.chip.as_deref().map(str::to_string),
+                hardware_name: info.hardware_name.as_deref().map(str::to_string),
+            })
+            .collect();
+        sensors.sort_by(|a, b| a.id.cmp(&b.id));
+        Self {
+            agent_version: env!("CARGO_PKG_VERSION").to_string(),
+            kernel: kernel_release(),
+            sensors,
+        }
+    }
+
+    /// The cache at `path` if it can be used on this run, or why not.
+    pub(crate) fn load(path: &Path) -> std::result::Result<Self, String> {
+        let content = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let cache: Self = serde_json::from_str(&content).map_err(|e| format!("unreadable: {}", e))?;
+        if cache.agent_version != env!("CARGO_PKG_VERSION") {
+            return Err(format!("written by agent {}", cache.agent_version));
+        }
+        if cache.kernel != kernel_release() {
+            return Err(format!("written under kernel {}", cache.kernel));
+        }
+        cache.verify()?;
+        Ok(cache)
+    }
+
+    /// Check up to `VERIFY_SAMPLE` entries, spread over the cache: the input exists and
+    /// its chip has the name it had.
+    fn verify(&self) -> std::result::Result<(), String> {
+        if self.sensors.is_empty() {
+            return Err("no sensors".to_string());
+        }
+        let step = self.sensors.len().div_ceil(VERIFY_SAMPLE);
+        for sensor in self.sensors.iter().step_by(step) {
+            if !sensor.path.exists() {
+                return Err(format!("{:?} is gone", sensor.path));
+            }
+            if let Some(chip) = &sensor.chip {
+                let found = chip_name(&sensor.path).unwrap_or_default();
+                if &found != chip {
+                    return Err(format!("{:?} is on {} now, was {}", sensor.path, found, chip));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Cache entries with freshly opened handles.
+    pub(crate) fn into_sensor_infos(self) -> Vec<SensorInfo> {
+        self.sensors
+            .into_iter()
+            .map(|sensor| SensorInfo {
+                temp_fd: open_attr(&sensor.path, false),
+                alarm_paths: alarm_paths_for_input(&sensor.path),
+                compute: None,
+                source: Some(Arc::from(sensor.path.to_string_lossy().as_ref())),
+                temp_input_path: sensor.path,
+                id: sensor.id.into(),
+                name: sensor.name.into(),
+                display_name: sensor.display_name.map(Into::into),
+                sensor_type: sensor.sensor_type.into(),
+                max_temp: sensor.max_temp,
+                crit_temp: sensor.crit_temp,
+                chip: sensor.chip.map(Into::into),
+                hardware_name: sensor.hardware_name.map(Into::into),
+            })
+            .collect()
+    }
+
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("json.tmp");
+        std::fs::write(&tmp, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {:?}", tmp))?;
+        std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+        Ok(())
+    }
+}
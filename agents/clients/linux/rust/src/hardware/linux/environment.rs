@@ -0,0 +1,74 @@
+//! Linux hardware monitor: running inside a container.
+//!
+//! An LXC or Docker container for monitoring only usually sees no hwmon chips at
+//! all: `/sys/class/hwmon` is missing, empty or on a read-only `/sys` without the
+//! host's devices. Checked once at startup. A container without hwmon runs
+//! monitoring-only (no fan writes, no `pwm_enable` changes, not even on an
+//! emergency), and registration tells the backend it is in a container.
+//!
+//! A container is recognized by the runtime's marker files (`/.dockerenv`,
+//! `/run/.containerenv`) or by the cgroup path of PID 1 naming a container manager.
+
+use std::path::Path;
+
+use crate::hardware::types::RunEnvironment;
+
+/// Words in `/proc/1/cgroup` that only show up inside a container.
+const CONTAINER_CGROUP_MARKERS: &[&str] = &["docker", "lxc", "kubepods", "containerd", "libpod", "machine.slice"];
+
+/// Files container runtimes create at the container's root.
+const CONTAINER_MARKER_FILES: &[&str] = &[".dockerenv", "run/.containerenv"];
+
+/// The environment seen from `root` (`/` outside tests): in a container, and
+/// whether `hwmon_base` holds any chip.
+pub(crate) fn detect(root: &Path, proc_root: &Path, hwmon_base: &Path) -> RunEnvironment {
+    RunEnvironment {
+        container: in_container(root, proc_root),
+        no_hwmon: std::fs::read_dir(hwmon_base).map_or(true, |mut entries| entries.next().is_none()),
+    }
+}
+
+fn in_container(root: &Path, proc_root: &Path) -> bool {
+    if CONTAINER_MARKER_FILES.iter().any(|file| root.join(file).exists()) {
+        return true;
+    }
+    let cgroup = std::fs::read_to_string(proc_root.join("1/cgroup")).unwrap_or_default();
+    cgroup
+        .lines()
+        .filter_map(|line| line.splitn(3, ':').nth(2))
+        .any(|path| CONTAINER_CGROUP_MARKERS.iter().any(|marker| path.contains(marker)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn containers_without_hwmon_run_monitoring_only() {
+        let root = std::env::temp_dir().join(format!("pankha-environment-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        let (proc_root, hwmon) = (root.join("proc"), root.join("sys/class/hwmon"));
+        std::fs::create_dir_all(proc_root.join("1")).unwrap();
+        let cgroup = |contents: &str| std::fs::write(proc_root.join("1/cgroup"), contents).unwrap();
+
+        // A host: systemd's own cgroup, hwmon chips present
+        cgroup("0::/init.scope\n");
+        std::fs::create_dir_all(hwmon.join("hwmon0")).unwrap();
+        assert_eq!(detect(&root, &proc_root, &hwmon), RunEnvironment { container: false, no_hwmon: false });
+
+        // An LXC container (cgroup v1 names the manager), hwmon empty
+        cgroup("12:pids:/lxc/monitoring\n11:cpu,cpuacct:/lxc/monitoring\n");
+        std::fs::remove_dir(hwmon.join("hwmon0")).unwrap();
+        let environment = detect(&root, &proc_root, &hwmon);
+        assert!(environment.container && environment.no_hwmon && environment.monitoring_only());
+
+        // Docker under cgroup v2 shows "0::/", but leaves its marker file; no hwmon at all
+        cgroup("0::/\n");
+        std::fs::remove_dir_all(root.join("sys")).unwrap();
+        assert!(!detect(&root, &proc_root, &hwmon).container);
+        std::fs::write(root.join(".dockerenv"), "").unwrap();
+        assert!(detect(&root, &proc_root, &hwmon).monitoring_only());
+
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
@@ -0,0 +1,238 @@
+//! Fan control modes as found before the agent first took a fan over.
+//!
+//! Taking a fan means writing `pwmN_enable=1` (manual); the chip's own mode before
+//! that (2 = automatic on most Super I/O chips, 5 on some) is recorded once in
+//! fan-modes.json beside the executable and never overwritten, so `--uninstall` can
+//! hand every fan back to the firmware even after many restarts. The chip name is
+//! kept with each path because hwmon numbering can change between boots.
+//!
+//! Modes are captured when a fan is first discovered, before anything writes it, and
+//! again at a takeover for fans that appeared in manual mode. Every change also
+//! rewrites fan-restore.txt beside it: the same modes as shell commands, for putting
+//! the fans back by hand when the agent itself can't start. fan-modes.json stays the
+//! source of truth; the shutdown cleanup reads it back rather than keeping a copy.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, warn};
+
+pub(crate) const FAN_MODES_FILE: &str = "fan-modes.json";
+pub(crate) const FAN_RESTORE_FILE: &str = "fan-restore.txt";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct SavedMode {
+    pub(crate) enable_path: PathBuf,
+    /// Contents of `name` in the chip directory when the mode was saved
+    pub(crate) chip: String,
+    pub(crate) mode: String,
+    /// `pwmN` beside the enable attribute and its value when the mode was saved
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pwm_path: Option<PathBuf>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub(crate) pwm_value: Option<String>,
+}
+
+/// fan-modes.json beside the executable.
+pub(crate) fn fan_modes_path() -> Option<PathBuf> {
+    Some(std::env::current_exe().ok()?.parent()?.join(FAN_MODES_FILE))
+}
+
+/// fan-restore.txt beside the executable, if the agent has written one.
+pub(crate) fn fan_restore_path() -> Option<PathBuf> {
+    Some(fan_modes_path()?.with_file_name(FAN_RESTORE_FILE)).filter(|path| path.exists())
+}
+
+/// The saved modes, keyed by fan id (empty if there are none or the file is unreadable).
+pub(crate) fn load(path: &Path) -> BTreeMap<String, SavedMode> {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str(&content).ok())
+        .unwrap_or_default()
+}
+
+fn chip_name(enable_path: &Path) -> Option<String> {
+    let name = std::fs::read_to_string(enable_path.parent()?.join("name")).ok()?;
+    Some(name.trim().to_string())
+}
+
+/// Modes recorded by a running agent.
+pub(crate) struct FanModeStore {
+    path: PathBuf,
+    saved: Mutex<BTreeMap<String, SavedMode>>,
+}
+
+impl FanModeStore {
+    pub(crate) fn open(path: PathBuf) -> Self {
+        let saved = Mutex::new(load(&path));
+        Self { path, saved }
+    }
+
+    pub(crate) fn saved(&self) -> BTreeMap<String, SavedMode> {
+        self.saved.lock().unwrap().clone()
+    }
+
+    /// Remember `mode` as the original mode of `fan_id`, unless one is already saved.
+    pub(crate) fn record(&self, fan_id: &str, enable_path: &Path, mode: &str) {
+        let mut saved = self.saved.lock().unwrap();
+        if saved.contains_key(fan_id) || mode.is_empty() || mode == "1" {
+            return;
+        }
+        let pwm_path = enable_path.to_str()
+            .and_then(|path| path.strip_suffix("_enable"))
+            .map(PathBuf::from);
+        let pwm_value = pwm_path.as_ref()
+            .and_then(|path| std::fs::read_to_string(path).ok())
+            .map(|value| value.trim().to_string());
+        saved.insert(fan_id.to_string(), SavedMode {
+            enable_path: enable_path.to_path_buf(),
+            chip: chip_name(enable_path).unwrap_or_default(),
+            mode: mode.to_string(),
+            pwm_path,
+            pwm_value,
+        });
+        match write(&self.path, &saved) {
+            Ok(()) => debug!("Saved original mode {} of fan {} to {:?}", mode, fan_id, self.path),
+            Err(e) => warn!("Could not save the original mode of fan {}: {:#}", fan_id, e),
+        }
+    }
+}
+
+fn write(path: &Path, saved: &BTreeMap<String, SavedMode>) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(saved)?)
+        .with_context(|| format!("Failed to write {:?}", tmp))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    let script = path.with_file_name(FAN_RESTORE_FILE);
+    std::fs::write(&script, restore_script(saved, chrono::Local::now()))
+        .with_context(|| format!("Failed to write {:?}", script))?;
+    Ok(())
+}
+
+/// fan-restore.txt: each saved mode as a command that writes it back, guarded by the
+/// chip name in case hwmon numbering changed since.
+fn restore_script(saved: &BTreeMap<String, SavedMode>, updated: chrono::DateTime<chrono::Local>) -> String {
+    let mut script = format!(
+        "# Pankha agent: fan control modes as found before the agent took the fans over\n\
+         # Updated {}\n\
+         #\n\
+         # The agent restores these itself when it stops, and --uninstall does too. If it\n\
+         # can't start and fans are stuck at a manual speed, run the commands below as\n\
+         # root, or the whole file: sudo sh {}\n\
+         # Machine-readable copy (what the agent uses): {}\n",
+        updated.format("%Y-%m-%d %H:%M:%S %Z"), FAN_RESTORE_FILE, FAN_MODES_FILE
+    );
+    for (fan_id, mode) in saved {
+        script.push_str(&format!("\n# {}", fan_id));
+        if !mode.chip.is_empty() {
+            script.push_str(&format!(" (chip {})", mode.chip));
+        }
+        script.push('\n');
+        if let Some(pwm_path) = &mode.pwm_path {
+            let value = mode.pwm_value.as_deref().unwrap_or("unknown");
+            script.push_str(&format!("#   {} was {}\n", pwm_path.display(), value));
+        }
+        script.push_str(&format!("#   {} was {}\n", mode.enable_path.display(), mode.mode));
+        let write = format!("echo {} > {}", mode.mode, mode.enable_path.display());
+        match mode.enable_path.parent().filter(|_| !mode.chip.is_empty()) {
+            Some(dir) => script.push_str(&format!("[ \"$(cat {}/name)\" = {} ] && {}\n", dir.display(), mode.chip, write)),
+            None => script.push_str(&format!("{}\n", write)),
+        }
+    }
+    script
+}
+
+/// Write each saved mode back. Returns the fans that could not be restored, with why.
+pub(crate) fn restore(saved: &BTreeMap<String, SavedMode>) -> Vec<(String, String)> {
+    let mut failed = Vec::new();
+    for (fan_id, mode) in saved {
+        // Only ever write to a hwmon attribute, whatever the file says
+        if !mode.enable_path.starts_with("/sys/") || !mode.enable_path.to_string_lossy().ends_with("_enable") {
+            failed.push((fan_id.clone(), format!("{:?} is not a pwm_enable attribute", mode.enable_path)));
+            continue;
+        }
+        let chip = chip_name(&mode.enable_path);
+        if !mode.chip.is_empty() && chip.as_deref() != Some(mode.chip.as_str()) {
+            failed.push((fan_id.clone(), format!(
+                "{:?} now belongs to {} instead of {} (hwmon numbering changed)",
+                mode.enable_path, chip.as_deref().unwrap_or("nothing"), mode.chip
+            )));
+            continue;
+        }
+        if let Err(e) = std::fs::write(&mode.enable_path, &mode.mode) {
+            failed.push((fan_id.clone(), format!("writing {} to {:?}: {}", mode.mode, mode.enable_path, e)));
+        }
+    }
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_mode_recorded_wins_and_survives_a_restart() {
+        let dir = std::env::temp_dir().join(format!("pankha-fan-modes-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("name"), "it8689\n").unwrap();
+        let enable = dir.join("pwm1_enable");
+        let path = dir.join(FAN_MODES_FILE);
+
+        let store = FanModeStore::open(path.clone());
+        store.record("it8689_fan_1", &enable, "2");
+        // Later takeovers after a restart find the fan already in manual mode
+        FanModeStore::open(path.clone()).record("it8689_fan_1", &enable, "1");
+        FanModeStore::open(path.clone()).record("it8689_fan_1", &enable, "5");
+
+        let saved = load(&path);
+        assert_eq!(saved["it8689_fan_1"], SavedMode {
+            enable_path: enable,
+            chip: "it8689".into(),
+            mode: "2".into(),
+            pwm_path: Some(dir.join("pwm1")),
+            pwm_value: None,
+        });
+        // Outside /sys nothing is written back
+        assert_eq!(restore(&saved).len(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn restore_script_has_a_guarded_command_per_fan() {
+        let saved = BTreeMap::from([
+            ("nct6798_fan_1".to_string(), SavedMode {
+                enable_path: "/sys/class/hwmon/hwmon2/pwm1_enable".into(),
+                chip: "nct6798".into(),
+                mode: "5".into(),
+                pwm_path: Some("/sys/class/hwmon/hwmon2/pwm1".into()),
+                pwm_value: Some("128".into()),
+            }),
+            // Saved by an older agent: no chip name or PWM value
+            ("it8689_fan_2".to_string(), SavedMode {
+                enable_path: "/sys/class/hwmon/hwmon3/pwm2_enable".into(),
+                chip: String::new(),
+                mode: "2".into(),
+                pwm_path: None,
+                pwm_value: None,
+            }),
+        ]);
+        let script = restore_script(&saved, chrono::Local::now());
+        let commands: Vec<&str> = script.lines().filter(|line| !line.starts_with('#') && !line.is_empty()).collect();
+        assert_eq!(commands, [
+            "echo 2 > /sys/class/hwmon/hwmon3/pwm2_enable",
+            "[ \"$(cat /sys/class/hwmon/hwmon2/name)\" = nct6798 ] && echo 5 > /sys/class/hwmon/hwmon2/pwm1_enable",
+        ]);
+        assert!(script.contains("#   /sys/class/hwmon/hwmon2/pwm1 was 128\n"));
+        assert!(script.contains("# nct6798_fan_1 (chip nct6798)\n"));
+
+        // Legacy fan-modes.json entries still load
+        let legacy: BTreeMap<String, SavedMode> = serde_json::from_str(
+            r#"{"it8689_fan_1": {"enable_path": "/sys/class/hwmon/hwmon3/pwm1_enable", "chip": "it8689", "mode": "2"}}"#,
+        ).unwrap();
+        assert_eq!(legacy["it8689_fan_1"].pwm_path, None);
+    }
+}
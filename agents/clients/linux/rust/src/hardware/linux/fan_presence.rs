@@ -0,0 +1,141 @@
+//! Linux hardware monitor: telling an idle or seized fan from an empty header.
+//!
+//! RPM 0 looks the same for all three. A fan whose tach has reported RPM since the
+//! agent started is present for good; one driven at `ABSENT_MIN_DUTY`% or more for
+//! `ABSENT_AFTER` without ever reporting RPM is taken as absent, until it does.
+//! Before either, and for fans without a tach, presence is unknown. `fanN_fault`,
+//! where the driver has it, makes the fan an error whatever its history.
+
+use std::time::{Duration, Instant};
+
+use crate::hardware::types::{Fan, FanPresence, FanStatusDetail, FanStatusReason};
+
+/// Duty at which any fan spins; below it a stopped fan may just be semi-passive.
+const ABSENT_MIN_DUTY: u8 = 30;
+
+/// Driven this long without RPM: no fan on the header. Well past any spin-up time.
+const ABSENT_AFTER: Duration = Duration::from_secs(30);
+
+/// What a fan's tach has shown so far; kept across rediscovery.
+#[derive(Debug, Default)]
+pub(crate) struct PresenceHistory {
+    /// RPM > 0 was seen
+    spun: bool,
+    /// Driven at `ABSENT_MIN_DUTY` or more, without RPM, since
+    driven_since: Option<Instant>,
+    absent: bool,
+}
+
+impl PresenceHistory {
+    /// Record one discovery's reading (`rpm` None without a tach, `duty` in percent).
+    pub(crate) fn observe(&mut self, rpm: Option<u32>, duty: u8, now: Instant) -> FanPresence {
+        let Some(rpm) = rpm else { return FanPresence::Unknown };
+        if rpm > 0 {
+            *self = Self { spun: true, ..Self::default() };
+        } else if !self.spun && !self.absent {
+            if duty >= ABSENT_MIN_DUTY {
+                let since = *self.driven_since.get_or_insert(now);
+                self.absent = now.saturating_duration_since(since) >= ABSENT_AFTER;
+            } else {
+                self.driven_since = None;
+            }
+        }
+        match (self.spun, self.absent) {
+            (true, _) => FanPresence::Present,
+            (false, true) => FanPresence::Absent,
+            (false, false) => FanPresence::Unknown,
+        }
+    }
+}
+
+/// `status` and `status_detail` of a hwmon fan from its reading, presence and alarms
+/// ("fault" in `alarms` is `fanN_fault`).
+pub(crate) fn classify(fan: &Fan, presence: FanPresence) -> (&'static str, FanStatusDetail) {
+    let fault = fan.alarms.iter().any(|a| a == "fault");
+    let alarm = fan.alarms.iter().any(|a| a != "fault");
+    let (status, reason) = match fan.rpm {
+        _ if fault => ("error", FanStatusReason::Fault),
+        _ if presence == FanPresence::Absent => ("absent", FanStatusReason::Absent),
+        Some(rpm) if rpm > 0 => ("ok", FanStatusReason::Spinning),
+        Some(_) if fan.speed == 0 => ("stopped", FanStatusReason::Idle),
+        Some(_) => ("stopped", FanStatusReason::Stalled),
+        None if fan.speed > 0 => ("ok", FanStatusReason::NoTach),
+        None => ("stopped", FanStatusReason::NoTach),
+    };
+    (status, FanStatusDetail { reason, presence, fault, alarm })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm};
+    use crate::hardware::HardwareMonitor;
+
+    #[test]
+    fn presence_follows_the_tach_history() {
+        let start = Instant::now();
+        let at = |secs: u64| start + Duration::from_secs(secs);
+
+        // Semi-passive idle is no evidence either way
+        let mut idle = PresenceHistory::default();
+        assert_eq!(idle.observe(Some(0), 0, at(0)), FanPresence::Unknown);
+        assert_eq!(idle.observe(Some(0), 0, at(600)), FanPresence::Unknown);
+
+        // Driven without RPM: absent once past spin-up time, until it spins
+        let mut empty = PresenceHistory::default();
+        assert_eq!(empty.observe(Some(0), 60, at(0)), FanPresence::Unknown);
+        assert_eq!(empty.observe(Some(0), 60, at(10)), FanPresence::Unknown);
+        assert_eq!(empty.observe(Some(0), 60, at(30)), FanPresence::Absent);
+        assert_eq!(empty.observe(Some(0), 0, at(40)), FanPresence::Absent);
+        assert_eq!(empty.observe(Some(800), 60, at(50)), FanPresence::Present);
+
+        // A drop to a low duty restarts the clock
+        let mut slow = PresenceHistory::default();
+        slow.observe(Some(0), 40, at(0));
+        slow.observe(Some(0), 10, at(20));
+        assert_eq!(slow.observe(Some(0), 40, at(40)), FanPresence::Unknown);
+
+        // Once seen spinning, a stop is a stall, not an empty header
+        let mut seized = PresenceHistory::default();
+        seized.observe(Some(1200), 50, at(0));
+        assert_eq!(seized.observe(Some(0), 100, at(300)), FanPresence::Present);
+
+        assert_eq!(PresenceHistory::default().observe(None, 100, at(300)), FanPresence::Unknown);
+    }
+
+    #[tokio::test]
+    async fn status_tells_idle_stalled_faulty_and_absent_fans_apart() {
+        let sysfs = FakeSysfs::new("fan-presence").chip(
+            Chip::new("nct6798")
+                .fan(1, 1100).pwm(Pwm::new(1, 128))
+                .fan(2, 0).pwm(Pwm::new(2, 0))
+                .fan(3, 0).pwm(Pwm::new(3, 200))
+                .fan(4, 0).pwm(Pwm::new(4, 200)).file("fan4_fault", "1")
+                .fan(5, 900).pwm(Pwm::new(5, 128)).file("fan5_min_alarm", "1"),
+        );
+        let monitor = sysfs.monitor();
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        let status = |n: usize| (fans[n].status.as_str(), fans[n].status_detail.unwrap());
+
+        assert_eq!(status(0).0, "ok");
+        assert_eq!(status(0).1.presence, FanPresence::Present);
+        assert_eq!((status(1).0, status(1).1.reason), ("stopped", FanStatusReason::Idle));
+        assert_eq!((status(2).0, status(2).1.reason, status(2).1.presence), ("stopped", FanStatusReason::Stalled, FanPresence::Unknown));
+        assert_eq!((status(3).0, status(3).1.fault), ("error", true));
+        assert_eq!(fans[3].alarms, vec!["fault"]);
+        assert_eq!((status(4).0, status(4).1.alarm), ("ok", true));
+
+        // fan3 driven at 78% past the spin-up window: an empty header, left out of emergencies
+        let past = Instant::now() - ABSENT_AFTER;
+        monitor.discovered_fans.read().await["nct6798_fan_3"].presence.lock().unwrap().driven_since = Some(past);
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        assert_eq!(fans[2].status, "absent");
+        assert!(fans[2].is_absent());
+
+        tokio::time::sleep(Duration::from_millis(150)).await; // write rate limit
+        monitor.max_cooling().await.unwrap();
+        let pwm = |n: u32| std::fs::read_to_string(sysfs.chip_dir(0).join(format!("pwm{}", n))).unwrap();
+        assert_eq!(pwm(3).trim(), "200");
+        assert_eq!(pwm(1).trim(), "255");
+    }
+}
@@ -1,105 +1,901 @@
 //! Linux hardware monitor: hwmon fan discovery.
 
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::Result;
+use futures_util::stream::{self, StreamExt};
 use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
 
 use crate::hardware::types::*;
 
-use super::monitor::FanInfo;
+use super::alarms::alarm_paths;
+use super::fan_presence;
+use super::monitor::{is_device_gone, open_attr, ControlMethod, DriftAction, FanInfo, PulseCorrection, SpinUp, DEFAULT_FAN_PULSES, MAX_CONCURRENT_CHIPS};
+use super::permissions::is_writable;
+
+/// One fan found during a chip scan, before it is merged into `discovered_fans`.
+struct ScannedFan {
+    fan: Fan,
+    chip_name: String,
+    /// `pwmN` or `fanN_target`, per `control`
+    pwm_path: PathBuf,
+    control: ControlMethod,
+    rpm_path: Option<PathBuf>,
+    pwm_enable_path: Option<PathBuf>,
+    /// Already applied to `fan.rpm`
+    pulse_correction: Option<PulseCorrection>,
+    /// A kept handle for this fan failed because its device went away
+    stale: bool,
+}
+
+/// Difference between the commanded and the actual speed that counts as drift, in
+/// percent. Absorbs boards that round PWM values to a few steps.
+const DRIFT_TOLERANCE_PERCENT: u8 = 3;
+
+/// Kept-open handles from the previous discovery, by attribute path.
+type AttrHandles = HashMap<PathBuf, Arc<std::fs::File>>;
+
+/// Highest RPM seen so far per `fanN_target` without `fanN_max`, by target path.
+type LearnedMaxRpm = HashMap<PathBuf, u32>;
+
+/// Open the PWM handles of a fan, read/write when we have write access.
+fn open_pwm_handles(
+    pwm_path: &Path,
+    enable_path: Option<&Path>,
+    writable: bool,
+) -> (Option<Arc<std::fs::File>>, Option<Arc<std::fs::File>>) {
+    let enable_fd = if writable { enable_path.and_then(|p| open_attr(p, true)) } else { None };
+    (open_attr(pwm_path, writable), enable_fd)
+}
 
 #[cfg(target_os = "linux")]
 impl super::monitor::LinuxHardwareMonitor {
     pub(crate) async fn discover_hwmon_fans(&self) -> Result<Vec<Fan>> {
+        let hwmon_dirs = self.list_hwmon_dirs().await?;
+
+        let handles: AttrHandles = self.discovered_fans.read().await.values()
+            .flat_map(|info| [
+                info.pwm_fd.clone().map(|fd| (info.pwm_path.clone(), fd)),
+                info.rpm_path.clone().zip(info.rpm_fd.clone()),
+                info.pwm_enable_path.clone().zip(info.enable_fd.clone()),
+            ])
+            .flatten()
+            .collect();
+        let handles = &handles;
+        let learned: LearnedMaxRpm = self.discovered_fans.read().await.values()
+            .filter_map(|info| match info.control {
+                ControlMethod::TargetRpm { max_rpm, .. } => Some((info.pwm_path.clone(), max_rpm)),
+                ControlMethod::Pwm => None,
+            })
+            .collect();
+        let learned = &learned;
+
+        // Scan chips concurrently; `buffered` keeps results in hwmon index order
+        let scanned: Vec<Vec<ScannedFan>> = stream::iter(hwmon_dirs)
+            .map(|hwmon_dir| async move { self.scan_chip_fans(&hwmon_dir, handles, learned).await })
+            .buffered(MAX_CONCURRENT_CHIPS)
+            .collect()
+            .await;
+
+        // Only hold the map lock for the merge, not the sysfs reads
         let mut fans = Vec::new();
+        let now = std::time::Instant::now();
         let mut fan_map = self.discovered_fans.write().await;
         // DON'T CLEAR - keep existing entries with their cached state
         // fan_map.clear();  // <- REMOVED - This causes race conditions
 
-        if !self.hwmon_base.exists() {
-            return Ok(fans);
+        for scanned_fan in scanned.into_iter().flatten() {
+            let ScannedFan { mut fan, chip_name, pwm_path, control, rpm_path, pwm_enable_path, pulse_correction, stale } = scanned_fan;
+
+            // Update or insert fan info, preserving cached state
+            match fan_map.get_mut(&fan.id) {
+                Some(existing) => {
+                    // Handles follow the path: reopen for a renumbered chip, a gone device,
+                    // or write access granted since (e.g. udev rule)
+                    let keep_pwm = !stale
+                        && existing.pwm_path == pwm_path
+                        && existing.pwm_fd.is_some()
+                        && (existing.writable || !fan.has_pwm_control);
+                    if !keep_pwm {
+                        (existing.pwm_fd, existing.enable_fd) =
+                            open_pwm_handles(&pwm_path, pwm_enable_path.as_deref(), fan.has_pwm_control);
+                        existing.writable = fan.has_pwm_control;
+                        self.keep_for_shutdown(&fan.id, pwm_enable_path.as_ref(), existing.enable_fd.as_ref(), &existing.spinup);
+                    }
+                    if stale || existing.rpm_path != rpm_path || existing.rpm_fd.is_none() {
+                        existing.rpm_fd = rpm_path.as_deref().and_then(|p| open_attr(p, false));
+                    }
+                    // A read/write handle opened before a privilege drop still grants control
+                    fan.has_pwm_control = existing.writable;
+
+                    // Update paths but preserve cached PWM state
+                    existing.pwm_path = pwm_path;
+                    existing.control = control;
+                    existing.rpm_path = rpm_path;
+                    existing.pwm_enable_path = pwm_enable_path;
+                    existing.chip_name = chip_name;
+                    existing.pulse_correction = pulse_correction;
+                    if fan.has_pwm_control {
+                        // Access granted since (e.g. udev rule); warn again if it is lost
+                        existing.denied_warned.store(false, std::sync::atomic::Ordering::Relaxed);
+                    }
+                    // Keep existing last_pwm_value and last_write_time
+                }
+                None => {
+                    let (pwm_fd, enable_fd) =
+                        open_pwm_handles(&pwm_path, pwm_enable_path.as_deref(), fan.has_pwm_control);
+                    let rpm_fd = rpm_path.as_deref().and_then(|p| open_attr(p, false));
+                    let spinup = Arc::default();
+                    self.keep_for_shutdown(&fan.id, pwm_enable_path.as_ref(), enable_fd.as_ref(), &spinup);
+                    // Captured before anything writes the fan, for fan-restore.txt
+                    if let Some(enable_path) = pwm_enable_path.as_deref().filter(|_| fan.has_pwm_control) {
+                        if let Ok(mode) = std::fs::read_to_string(enable_path) {
+                            self.record_original_mode(&fan.id, enable_path, mode.trim());
+                        }
+                    }
+
+                    // Insert new fan with fresh cache
+                    fan_map.insert(fan.id.clone(), FanInfo {
+                        pwm_path,
+                        control,
+                        rpm_path,
+                        pwm_enable_path,
+                        chip_name,
+                        writable: fan.has_pwm_control,
+                        denied_warned: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+                        pwm_fd,
+                        enable_fd,
+                        rpm_fd,
+                        last_pwm_value: Arc::new(RwLock::new(None)),
+                        last_write_time: Arc::new(RwLock::new(std::time::Instant::now())),
+                        write_failures: Arc::default(),
+                        spinup,
+                        drift: Arc::default(),
+                        pinned_mode: Arc::default(),
+                        presence: Arc::default(),
+                        last_set_by: Arc::default(),
+                        pulse_correction,
+                    });
+                }
+            }
+
+            let info = &fan_map[&fan.id];
+            let presence = info.presence.lock().unwrap().observe(fan.rpm, fan.speed, now);
+            let (status, detail) = fan_presence::classify(&fan, presence);
+            fan.status = status.to_string();
+            fan.status_detail = Some(detail);
+            fan.last_set_by = info.last_set_by.lock().unwrap().clone();
+            // Writes keep failing (broken EC, ...): monitoring-only until retryFanControl
+            if let Some(reason) = info.write_failures.lock().unwrap().reason {
+                fan.has_pwm_control = false;
+                fan.status = "error".to_string();
+                fan.control_error = Some(reason.to_string());
+            }
+
+            fans.push(fan);
         }
 
-        let mut entries = tokio::fs::read_dir(&self.hwmon_base).await?;
+        Ok(fans)
+    }
+
+    /// Hand a newly opened `pwmN_enable` handle to the shutdown cleanup, which
+    /// restores the fan's original mode through it (see `daemon::shutdown`), along
+    /// with the fan's spin-up state for it to cancel.
+    fn keep_for_shutdown(&self, fan_id: &str, enable_path: Option<&PathBuf>, enable_fd: Option<&Arc<std::fs::File>>,
+                         spinup: &Arc<std::sync::Mutex<Option<SpinUp>>>) {
+        if self.fan_modes.is_none() {
+            return;
+        }
+        if let (Some(path), Some(fd)) = (enable_path, enable_fd) {
+            crate::daemon::shutdown::update(|plan| plan.set_enable_handle(fan_id, path.clone(), Arc::clone(fd), Arc::clone(spinup)));
+        }
+    }
 
-        while let Some(entry) = entries.next_entry().await? {
-            let hwmon_dir = entry.path();
-            if !hwmon_dir.is_dir() {
+    /// Compare each fan's control register, as just read by discovery, with the value
+    /// the agent last wrote. A fan off by more than the tolerance is reported with
+    /// `drift`, its `speed` from the hardware and `targetSpeed` as commanded, and is
+    /// written again up to `hardware.drift_reassert_limit` times. Fans the chip
+    /// controls itself (`pwmN_enable` other than 1) are left alone.
+    pub(crate) async fn reconcile_fans(&self, fans: &mut [Fan]) {
+        // Dry run never writes, so everything would look drifted
+        if self.dry_run {
+            return;
+        }
+        let fan_map = self.discovered_fans.read().await;
+        for fan in fans.iter_mut().filter(|f| f.has_pwm_control) {
+            let Some(info) = fan_map.get(&fan.id) else { continue };
+            let Some(commanded) = *info.last_pwm_value.read().await else { continue };
+            let commanded_percent = info.control.percent_of(commanded);
+            fan.target_speed = commanded_percent;
+            if fan.speed.abs_diff(commanded_percent) <= DRIFT_TOLERANCE_PERCENT {
                 continue;
             }
+            if let Some(path) = &info.pwm_enable_path {
+                if self.read_attr(info.enable_fd.as_ref(), path).await.ok().as_deref() != Some("1") {
+                    continue;
+                }
+            }
+
+            fan.drift = true;
+            let action = info.drift.lock().unwrap().observed(commanded, self.drift_reassert_limit);
+            match action {
+                DriftAction::Reassert => {
+                    debug!("Fan {} drifted to {}% (commanded {}%), writing {} again",
+                           fan.id, fan.speed, commanded_percent, commanded);
+                    if let Err(e) = self.write_attr(info.pwm_fd.as_ref(), &info.pwm_path, &commanded.to_string()).await {
+                        debug!("Fan {}: rewriting drifted value failed: {:#}", fan.id, e);
+                    }
+                }
+                DriftAction::GiveUp => warn!(
+                    "Fan {} keeps drifting to {}% although {}% was commanded (board limit or firmware override?); reporting the hardware value",
+                    fan.id, fan.speed, commanded_percent
+                ),
+                DriftAction::Report => {}
+            }
+        }
+    }
 
-            let chip_name = match self.read_file(&hwmon_dir.join("name")).await {
-                Ok(name) => name,
-                Err(_) => continue,
+    /// Read an attribute through its kept handle. If the device behind the handle went
+    /// away, fall back to the path and report the handle as stale. Read errors go
+    /// through the log throttle, as they repeat every discovery.
+    async fn read_scanned(&self, handles: &AttrHandles, path: &Path) -> (Option<String>, bool) {
+        let (value, stale) = match self.read_attr(handles.get(path), path).await {
+            Err(e) if handles.contains_key(path) && is_device_gone(&e) => (self.read_file(path).await, true),
+            result => (result, false),
+        };
+
+        let key = format!("fan_read:{}", path.display());
+        match value {
+            Ok(value) => {
+                if let Some(line) = self.log_throttle.recovered(&key) {
+                    info!("Reading {:?} {}", path, line);
+                }
+                (Some(value), stale)
+            }
+            Err(e) => {
+                if let Some(line) = self.log_throttle.failed(&key, format!("Fan discovery: {:#}", e)) {
+                    warn!("{}", line);
+                }
+                (None, stale)
+            }
+        }
+    }
+
+    /// Read every controllable fan channel of one hwmon chip, in channel order.
+    async fn scan_chip_fans(&self, hwmon_dir: &Path, handles: &AttrHandles, learned: &LearnedMaxRpm) -> Vec<ScannedFan> {
+        let chip_name = match self.read_file(&hwmon_dir.join("name")).await {
+            Ok(name) if self.chip_disabled(&name) => return Vec::new(),
+            Ok(name) => name,
+            Err(_) => return Vec::new(),
+        };
+
+        // Collect fan channels from tach inputs and PWM outputs, so PWM-only
+        // headers (no fanN_input, e.g. Raspberry Pi pwm-fan) are still controllable
+        let mut channels = BTreeSet::new();
+        for pattern in ["fan*_input", "pwm*"] {
+            let pattern_str = hwmon_dir.join(pattern).to_string_lossy().to_string();
+            for path in glob::glob(&pattern_str).unwrap().filter_map(Result::ok) {
+                let filename = path.file_name().unwrap().to_string_lossy();
+                let num = filename.strip_prefix("fan").and_then(|s| s.strip_suffix("_input"))
+                    .or_else(|| filename.strip_prefix("pwm"));
+                // Skip pwmN_enable, pwmN_mode, etc.
+                if let Some(n) = num.and_then(|n| n.parse::<u32>().ok()) {
+                    channels.insert(n);
+                }
+            }
+        }
+
+        let lm_chip = self.lm_sensors_chip(hwmon_dir, &chip_name);
+        let mut fans = Vec::new();
+        for fan_num in channels {
+            let lm = self.lm_sensors_feature(lm_chip.as_ref(), &format!("fan{}", fan_num));
+            if lm.ignore {
+                continue;
+            }
+            let fan_id = format!("{}_fan_{}", chip_name.to_lowercase().replace(" ", "_"), fan_num);
+            // pwmN, else fanN_target (chips that regulate to an RPM, e.g. dell_smm)
+            let mut pwm_path = hwmon_dir.join(format!("pwm{}", fan_num));
+            let target_range = if pwm_path.exists() {
+                None
+            } else {
+                pwm_path = hwmon_dir.join(format!("fan{}_target", fan_num));
+                if !pwm_path.exists() {
+                    continue;
+                }
+                let read_rpm = |name: String| async move {
+                    self.read_file(&hwmon_dir.join(name)).await.ok().and_then(|s| s.parse::<u32>().ok())
+                };
+                Some((
+                    read_rpm(format!("fan{}_min", fan_num)).await.unwrap_or(0),
+                    read_rpm(format!("fan{}_max", fan_num)).await,
+                ))
             };
 
-            // Find fan inputs
-            let pattern = hwmon_dir.join("fan*_input");
-            let pattern_str = pattern.to_string_lossy();
-
-            for fan_file in glob::glob(&pattern_str).unwrap().filter_map(Result::ok) {
-                let filename = fan_file.file_name().unwrap().to_string_lossy();
-                let fan_num = filename.strip_prefix("fan").and_then(|s| s.strip_suffix("_input")).unwrap();
-
-                let pwm_path = hwmon_dir.join(format!("pwm{}", fan_num));
-                let pwm_enable_path = hwmon_dir.join(format!("pwm{}_enable", fan_num));
-
-                let has_pwm = pwm_path.exists();
-
-                if has_pwm {
-                    let fan_id = format!("{}_fan_{}", chip_name.to_lowercase().replace(" ", "_"), fan_num);
-
-                    // Read current RPM
-                    let rpm = self.read_file(&fan_file).await.ok()
-                        .and_then(|s| s.parse::<u32>().ok());
-
-                    // Read current PWM value
-                    let pwm_value = self.read_file(&pwm_path).await.ok()
-                        .and_then(|s| s.parse::<u8>().ok())
-                        .unwrap_or(128);
-
-                    let speed_percent = (pwm_value as f32 / 255.0 * 100.0) as u8;
-
-                    let fan = Fan {
-                        id: fan_id.clone(),
-                        name: format!("{} Fan {}", chip_name, fan_num),
-                        rpm,
-                        speed: speed_percent,
-                        target_speed: speed_percent,
-                        status: if rpm.unwrap_or(0) > 0 { "ok" } else { "stopped" }.to_string(),
-                        has_pwm_control: true,
-                        pwm_file: Some(pwm_path.to_string_lossy().to_string()),
-                    };
-
-                    // Update or insert fan info, preserving cached state
-                    match fan_map.get_mut(&fan_id) {
-                        Some(existing) => {
-                            // Update paths but preserve cached PWM state
-                            existing.pwm_path = pwm_path.clone();
-                            existing.rpm_path = fan_file.clone();
-                            existing.pwm_enable_path = if pwm_enable_path.exists() { Some(pwm_enable_path) } else { None };
-                            existing.chip_name = chip_name.clone();
-                            // Keep existing last_pwm_value and last_write_time
-                        }
-                        None => {
-                            // Insert new fan with fresh cache
-                            fan_map.insert(fan_id.clone(), FanInfo {
-                                pwm_path: pwm_path.clone(),
-                                rpm_path: fan_file.clone(),
-                                pwm_enable_path: if pwm_enable_path.exists() { Some(pwm_enable_path) } else { None },
-                                chip_name: chip_name.clone(),
-                                last_pwm_value: Arc::new(RwLock::new(None)),
-                                last_write_time: Arc::new(RwLock::new(std::time::Instant::now())),
-                            });
-                        }
+            let pwm_enable_path = Some(hwmon_dir.join(format!("pwm{}_enable", fan_num)))
+                .filter(|p| target_range.is_none() && p.exists());
+            let rpm_path = Some(hwmon_dir.join(format!("fan{}_input", fan_num))).filter(|p| p.exists());
+            let pulse_correction = match self.fan_pulses_for(&fan_id) {
+                Some(pulses) if rpm_path.is_some() => {
+                    self.apply_fan_pulses(&fan_id, &hwmon_dir.join(format!("fan{}_pulses", fan_num)), pulses).await
+                }
+                _ => None,
+            };
+            let fan_alarm_paths = alarm_paths(hwmon_dir, &format!("fan{}", fan_num));
+
+            let ((rpm, rpm_stale), (pwm_value, pwm_stale), alarms, (pwm_enable, enable_stale)) = tokio::join!(
+                // Read current RPM (None for PWM-only fans without a tach)
+                async {
+                    match &rpm_path {
+                        Some(path) => self.read_scanned(handles, path).await,
+                        None => (None, false),
                     }
+                },
+                // Read current PWM value / target RPM
+                self.read_scanned(handles, &pwm_path),
+                self.read_alarms(&fan_alarm_paths),
+                // Control mode (manual, full speed or one of the chip's curves)
+                async {
+                    match &pwm_enable_path {
+                        Some(path) => self.read_scanned(handles, path).await,
+                        None => (None, false),
+                    }
+                },
+            );
+            let rpm = rpm.and_then(|s| s.parse::<u32>().ok())
+                .map(|rpm| lm.compute.as_ref().map_or(rpm, |c| c.apply(f64::from(rpm)).round().max(0.0) as u32));
+            let pwm_enable = pwm_enable.and_then(|s| s.parse::<u8>().ok());
+            let control = match target_range {
+                None => ControlMethod::Pwm,
+                Some((min_rpm, Some(max_rpm))) => ControlMethod::TargetRpm { min_rpm, max_rpm },
+                // No fanN_max: the fastest the fan has been seen spinning
+                Some((min_rpm, None)) => ControlMethod::TargetRpm {
+                    min_rpm,
+                    max_rpm: learned.get(&pwm_path).copied().unwrap_or(0).max(rpm.unwrap_or(0)),
+                },
+            };
+            // Unknown range (no fanN_max, not seen spinning yet): monitoring-only for now
+            let range_known = match control {
+                ControlMethod::TargetRpm { min_rpm, max_rpm } => max_rpm > min_rpm,
+                ControlMethod::Pwm => true,
+            };
+
+            // After `control`: fanN_target takes RPM as the driver counts it
+            let rpm = rpm.map(|rpm| pulse_correction.map_or(rpm, |c| c.apply(rpm)));
+
+            let pwm_value = pwm_value.and_then(|s| s.parse::<u32>().ok());
+            let speed_percent = pwm_value.map_or(50, |value| control.percent_of(value));
+
+            let fan = Fan {
+                id: fan_id,
+                name: match &lm.label {
+                    Some(label) => format!("{} {}", chip_name, label),
+                    None => format!("{} Fan {}", chip_name, fan_num),
+                },
+                rpm,
+                speed: speed_percent,
+                target_speed: speed_percent,
+                pwm: pwm_value.filter(|_| control == ControlMethod::Pwm).map(|value| value.min(255) as u8),
+                // From the tach history, when merged (fan_presence.rs)
+                status: String::new(),
+                status_detail: None,
+                // Unprivileged: report read-only PWM as monitoring-only
+                has_pwm_control: range_known && is_writable(&pwm_path),
+                control_method: Some(control.name().to_string()),
+                pwm_file: Some(pwm_path.to_string_lossy().to_string()),
+                pwm_enable,
+                control_mode: pwm_enable.map(|mode| FanMode::name_of(mode).to_string()),
+                zone: None,
+                alarms,
+                drift: false,
+                control_error: None,
+                last_set_by: None,
+            };
+
+            let stale = rpm_stale || pwm_stale || enable_stale;
+            fans.push(ScannedFan { fan, chip_name: chip_name.clone(), pwm_path, control, rpm_path, pwm_enable_path, pulse_correction, stale });
+        }
+        fans
+    }
 
-                    fans.push(fan);
+    /// `hardware.fan_pulses` for one fan: written to `fanN_pulses` where the driver
+    /// has it, else (or if that fails) the correction its RPM readings need.
+    async fn apply_fan_pulses(&self, fan_id: &str, pulses_path: &Path, pulses: u8) -> Option<PulseCorrection> {
+        let counted = if pulses_path.exists() {
+            let current = self.read_file(pulses_path).await.ok().and_then(|s| s.parse::<u8>().ok());
+            if current == Some(pulses) {
+                return None;
+            }
+            match self.write_attr(None, pulses_path, &pulses.to_string()).await {
+                // Dry run: the driver still counts what it did
+                Ok(()) if self.dry_run => {}
+                Ok(()) => {
+                    info!("Fan {}: {:?} set to {} pulses per revolution (hardware.fan_pulses)", fan_id, pulses_path, pulses);
+                    return None;
+                }
+                Err(e) => {
+                    let key = format!("fan_pulses:{}", pulses_path.display());
+                    if let Some(line) = self.log_throttle.failed(&key, format!("Fan {}: can't set {:?}, correcting its RPM instead: {:#}", fan_id, pulses_path, e)) {
+                        warn!("{}", line);
+                    }
                 }
             }
+            current.unwrap_or(DEFAULT_FAN_PULSES)
+        } else {
+            DEFAULT_FAN_PULSES
+        };
+        (counted != pulses).then_some(PulseCorrection { counted, actual: pulses })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::types::{AgentConfig, HardwareSettings, SemiPassive, SpinupBoost};
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm, Temp};
+    use crate::hardware::linux::monitor::{ControlMethod, LinuxHardwareMonitor, WRITES_IGNORED};
+    use crate::hardware::types::{FanMode, FanSpeedOrigin};
+    use crate::hardware::HardwareMonitor;
+
+    const CLI: &FanSpeedOrigin = &FanSpeedOrigin::LocalCli;
+
+    #[tokio::test]
+    async fn fan_with_pwm_enable_is_discovered() {
+        let sysfs = FakeSysfs::new("fan-enable")
+            .chip(Chip::new("nct6798").fan(2, 1100).pwm(Pwm::new(2, 255).enable(2)));
+        let monitor = sysfs.monitor();
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+
+        assert_eq!(fans.len(), 1);
+        assert_eq!(fans[0].id, "nct6798_fan_2");
+        assert_eq!(fans[0].rpm, Some(1100));
+        assert_eq!(fans[0].speed, 100);
+        assert_eq!(fans[0].status, "ok");
+
+        let map = monitor.discovered_fans.read().await;
+        let info = &map["nct6798_fan_2"];
+        assert_eq!(info.pwm_enable_path, Some(sysfs.chip_dir(0).join("pwm2_enable")));
+        assert_eq!(info.rpm_path, Some(sysfs.chip_dir(0).join("fan2_input")));
+    }
+
+    #[tokio::test]
+    async fn fan_without_pwm_enable_is_discovered() {
+        let sysfs = FakeSysfs::new("fan-no-enable")
+            .chip(Chip::new("it8686").fan(1, 0).pwm(Pwm::new(1, 0).mode(0o444)));
+        let monitor = sysfs.monitor();
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+
+        assert_eq!(fans.len(), 1);
+        assert_eq!(fans[0].status, "stopped");
+        assert!(monitor.discovered_fans.read().await["it8686_fan_1"].pwm_enable_path.is_none());
+    }
+
+    #[tokio::test]
+    async fn tach_without_pwm_is_not_a_fan() {
+        let sysfs = FakeSysfs::new("fan-tach-only").chip(Chip::new("nct6798").fan(1, 900));
+        let fans = sysfs.monitor().discover_hwmon_fans().await.unwrap();
+
+        assert!(fans.is_empty());
+    }
+
+    #[tokio::test]
+    async fn target_rpm_fan_maps_percent_onto_its_rpm_range() {
+        // dell_smm style: no pwmN. Fan 1 has fan1_min/fan1_max, fan 2 only a tach
+        let sysfs = FakeSysfs::new("fan-target").chip(
+            Chip::new("dell_smm")
+                .fan(1, 3000).file("fan1_target", "3000").file("fan1_min", "1000").file("fan1_max", "5000")
+                .fan(2, 3600).file("fan2_target", "3600"),
+        );
+        let monitor = sysfs.monitor();
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        // Regular files, not sysfs: a shorter write leaves digits behind, so parse
+        let target = |n: u32| -> u32 {
+            std::fs::read_to_string(sysfs.chip_dir(0).join(format!("fan{}_target", n))).unwrap().trim().parse().unwrap()
+        };
+
+        assert_eq!(fans.len(), 2);
+        assert_eq!(fans[0].control_method.as_deref(), Some("target_rpm"));
+        assert_eq!((fans[0].speed, fans[0].has_pwm_control), (50, true));
+        // Without fan2_max the range tops out at the fastest RPM seen
+        assert_eq!((fans[1].speed, fans[1].has_pwm_control), (100, true));
+        assert_eq!(monitor.discovered_fans.read().await["dell_smm_fan_2"].control,
+                   ControlMethod::TargetRpm { min_rpm: 0, max_rpm: 3600 });
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        monitor.set_fan_speed("dell_smm_fan_1", 25, CLI).await.unwrap();
+        monitor.set_fan_speed("dell_smm_fan_2", 50, CLI).await.unwrap();
+        assert_eq!((target(1), target(2)), (2000, 1800));
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        monitor.set_fan_speed("dell_smm_fan_1", 0, CLI).await.unwrap();
+        assert_eq!(target(1), 0);
+
+        // The learned maximum is kept while the fan runs slower
+        std::fs::write(sysfs.chip_dir(0).join("fan2_input"), "1800").unwrap();
+        monitor.discover_hwmon_fans().await.unwrap();
+        assert_eq!(monitor.discovered_fans.read().await["dell_smm_fan_2"].control,
+                   ControlMethod::TargetRpm { min_rpm: 0, max_rpm: 3600 });
+    }
+
+    #[tokio::test]
+    async fn pwm_only_fan_reports_commanded_state() {
+        let sysfs = FakeSysfs::new("fan-pwm-only").chip(
+            Chip::new("pwmfan")
+                .pwm(Pwm::new(1, 102).enable(1))
+                .pwm(Pwm::new(2, 0)),
+        );
+        let fans = sysfs.monitor().discover_hwmon_fans().await.unwrap();
+
+        assert_eq!(fans.len(), 2);
+        assert_eq!(fans[0].id, "pwmfan_fan_1");
+        assert_eq!(fans[0].rpm, None);
+        assert_eq!(fans[0].speed, 40);
+        assert_eq!(fans[0].status, "ok");
+        assert_eq!(fans[1].status, "stopped");
+    }
+
+    #[tokio::test]
+    async fn rediscovery_preserves_cached_pwm_state() {
+        let sysfs = FakeSysfs::new("fan-cache")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 128)));
+        let monitor = sysfs.monitor();
+        monitor.discover_hwmon_fans().await.unwrap();
+        {
+            let map = monitor.discovered_fans.read().await;
+            *map["nct6798_fan_1"].last_pwm_value.write().await = Some(200);
         }
 
-        Ok(fans)
+        monitor.discover_hwmon_fans().await.unwrap();
+        let map = monitor.discovered_fans.read().await;
+        assert_eq!(*map["nct6798_fan_1"].last_pwm_value.read().await, Some(200));
+    }
+
+    #[tokio::test]
+    async fn writes_go_through_handle_opened_at_discovery() {
+        let sysfs = FakeSysfs::new("fan-handle")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 200)));
+        let monitor = sysfs.monitor();
+        monitor.discover_hwmon_fans().await.unwrap();
+
+        // Unlink the path: only the kept handle still reaches the original file
+        let pwm = sysfs.chip_dir(0).join("pwm1");
+        let linked = sysfs.chip_dir(0).join("pwm1_link");
+        std::fs::hard_link(&pwm, &linked).unwrap();
+        std::fs::remove_file(&pwm).unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap();
+
+        assert!(!pwm.exists());
+        assert_eq!(std::fs::read_to_string(&linked).unwrap().trim(), "128");
+    }
+
+    #[tokio::test]
+    async fn last_set_by_reports_origin_of_applied_speed() {
+        let sysfs = FakeSysfs::new("fan-set-by")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 200)));
+        let monitor = sysfs.monitor();
+        assert_eq!(monitor.discover_hwmon_fans().await.unwrap()[0].last_set_by, None);
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        let command = FanSpeedOrigin::BackendCommand { command_id: Some("cmd-1".to_string()) };
+        monitor.set_fan_speed("nct6798_fan_1", 50, &command).await.unwrap();
+        // Dropped by the rate limit: the fan still runs at the command's speed
+        monitor.set_fan_speed("nct6798_fan_1", 80, &FanSpeedOrigin::Failsafe).await.unwrap();
+
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        let set_by = fans[0].last_set_by.as_ref().unwrap();
+        assert_eq!(set_by.origin, command);
+        let json = serde_json::to_value(set_by).unwrap();
+        assert_eq!((json["origin"].as_str(), json["command_id"].as_str()), (Some("backend_command"), Some("cmd-1")));
+
+        // Already at that speed: no write, but the request is now the one in effect
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        monitor.set_fan_speed("nct6798_fan_1", 50, &FanSpeedOrigin::Failsafe).await.unwrap();
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        assert_eq!(fans[0].last_set_by.as_ref().unwrap().origin, FanSpeedOrigin::Failsafe);
+    }
+
+    #[tokio::test]
+    async fn fan_pulses_are_set_in_the_driver_or_corrected_in_the_rpm() {
+        let sysfs = FakeSysfs::new("fan-pulses")
+            .chip(Chip::new("nct6798")
+                .fan(1, 1800).pwm(Pwm::new(1, 128)).file("fan1_pulses", "2")
+                .fan(2, 1800).pwm(Pwm::new(2, 128))
+                .fan(3, 1800).pwm(Pwm::new(3, 128)));
+        let fan_pulses = [("nct6798_fan_1", 4), ("nct6798_fan_2", 4), ("hwmon:nct6798_fan_3", 1)]
+            .into_iter().map(|(id, pulses)| (id.to_string(), pulses)).collect();
+        let monitor = sysfs.monitor_with(HardwareSettings { fan_pulses, ..AgentConfig::default().hardware });
+
+        // Fan 1's driver counts the new value from now on; fans 2 and 3 are corrected
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        assert_eq!(std::fs::read_to_string(sysfs.chip_dir(0).join("fan1_pulses")).unwrap().trim(), "4");
+        assert_eq!(fans.iter().map(|f| f.rpm).collect::<Vec<_>>(), [Some(1800), Some(900), Some(3600)]);
+
+        // The dump keeps the raw tach reading next to the reported one
+        let dump = monitor.dump_hardware_info().await.unwrap();
+        let tach = |n: u32| dump.hardware[0].sensors.iter()
+            .find(|s| s.identifier == format!("/nct6798/fan/{}", n))
+            .map(|s| (s.value, s.reported_value))
+            .unwrap();
+        assert_eq!((tach(1), tach(2)), ((Some(1800.0), None), (Some(1800.0), Some(900.0))));
+
+        let mut config = AgentConfig::default();
+        config.hardware.fan_pulses.insert("nct6798_fan_1".to_string(), 3);
+        assert!(config.validate().unwrap_err().to_string().contains("must be 1, 2 or 4"));
+    }
+
+    #[tokio::test]
+    async fn fan_mode_is_reported_and_speeds_wait_for_manual() {
+        let sysfs = FakeSysfs::new("fan-mode")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 128).enable(5)));
+        let monitor = sysfs.monitor();
+        let fans = monitor.discover_fans().await.unwrap();
+        assert_eq!((fans[0].pwm_enable, fans[0].control_mode.as_deref()), (Some(5), Some("auto")));
+        let read = |name: &str| std::fs::read_to_string(sysfs.chip_dir(0).join(name)).unwrap().trim().to_string();
+
+        assert_eq!(monitor.set_fan_mode("nct6798_fan_1", FanMode::FullSpeed).await.unwrap(), 0);
+        assert_eq!(read("pwm1_enable"), "0");
+        assert_eq!(monitor.discover_fans().await.unwrap()[0].control_mode.as_deref(), Some("full_speed"));
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        let err = monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap_err();
+        assert!(err.to_string().contains("setFanMode manual"), "{}", err);
+        assert_eq!(read("pwm1"), "128");
+
+        assert_eq!(monitor.set_fan_mode("nct6798_fan_1", FanMode::Manual).await.unwrap(), 1);
+        monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap();
+        assert_eq!((read("pwm1_enable").as_str(), read("pwm1").as_str()), ("1", "128"));
+
+        // No original mode saved (no fan-modes.json here): the usual automatic mode
+        assert_eq!(monitor.set_fan_mode("nct6798_fan_1", FanMode::Auto).await.unwrap(), 2);
+        // An emergency takes the fan back from the chip
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        monitor.max_cooling().await.unwrap();
+        assert_eq!((read("pwm1_enable").as_str(), read("pwm1").as_str()), ("1", "255"));
+        assert!(monitor.set_fan_mode("nct6798_fan_9", FanMode::Manual).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn disabled_chips_are_left_out_but_kept_in_the_dump() {
+        let sysfs = FakeSysfs::new("disabled-chips")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 40_000).label("SYSTIN")).fan(1, 800).pwm(Pwm::new(1, 128)))
+            .chip(Chip::new("iwlwifi_1").temp(Temp::new(1, 38_000)));
+        let monitor = sysfs.monitor_with(HardwareSettings { disabled_chips: vec!["iwlwifi_1".into()], ..AgentConfig::default().hardware });
+        let chips = |sensors: Vec<crate::hardware::types::Sensor>| sensors.into_iter().filter_map(|s| s.chip.map(|c| c.to_string())).collect::<Vec<_>>();
+        assert_eq!(chips(monitor.discover_sensors().await.unwrap()), ["nct6798"]);
+        assert_eq!(monitor.discover_fans().await.unwrap().len(), 1);
+        assert_eq!(monitor.chip_fans("nct6798").await, ["nct6798_fan_1"]);
+
+        monitor.set_disabled_chips(&["iwlwifi_1".into(), "nct6798".into()]).await;
+        assert!(monitor.discover_sensors().await.unwrap().is_empty());
+        assert!(monitor.discover_fans().await.unwrap().is_empty());
+        let err = monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap_err();
+        assert!(err.to_string().contains("disabled chip"), "{}", err);
+        let dump = monitor.dump_hardware_info().await.unwrap();
+        assert_eq!(dump.hardware.iter().map(|item| (item.name.as_str(), item.disabled)).collect::<Vec<_>>(),
+                   [("nct6798", true), ("iwlwifi_1", true)]);
+        assert!(!dump.hardware[0].sensors.is_empty());
+
+        monitor.set_disabled_chips(&[]).await;
+        assert_eq!(chips(monitor.discover_sensors().await.unwrap()), ["nct6798", "iwlwifi_1"]);
+        assert_eq!(monitor.discover_fans().await.unwrap().len(), 1);
+        monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn persistently_failing_fan_is_degraded_until_retry_succeeds() {
+        // A directory where pwm1 should be: every write fails with EISDIR, like a broken EC's EIO
+        let sysfs = FakeSysfs::new("fan-degraded")
+            .chip(Chip::new("nct6798").fan(1, 800).file("pwm1/stub", ""));
+        let monitor = sysfs.monitor_with(HardwareSettings { pwm_failure_threshold: 2, ..AgentConfig::default().hardware });
+        assert!(monitor.discover_hwmon_fans().await.unwrap()[0].has_pwm_control);
+
+        for _ in 0..2 {
+            tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+            assert!(monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.is_err());
+        }
+        let fans = monitor.discover_hwmon_fans().await.unwrap();
+        assert!(!fans[0].has_pwm_control);
+        assert_eq!(fans[0].status, "error");
+        let err = monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap_err();
+        assert!(err.to_string().contains("retryFanControl"));
+        assert!(monitor.retry_fan_control("nct6798_fan_1").await.is_err());
+
+        // Register fixed: the retry write goes through and control comes back
+        let pwm = sysfs.chip_dir(0).join("pwm1");
+        std::fs::remove_dir_all(&pwm).unwrap();
+        std::fs::write(&pwm, "128\n").unwrap();
+        monitor.retry_fan_control("nct6798_fan_1").await.unwrap();
+        assert!(monitor.discover_hwmon_fans().await.unwrap()[0].has_pwm_control);
+        let map = monitor.discovered_fans.read().await;
+        let failures = map["nct6798_fan_1"].write_failures.lock().unwrap();
+        assert_eq!((failures.consecutive, failures.total, failures.degraded), (0, 3, false));
+    }
+
+    #[tokio::test]
+    async fn writes_that_do_not_stick_degrade_the_fan() {
+        let sysfs = FakeSysfs::new("fan-write-ignored")
+            .chip(Chip::new("it8689").fan(3, 900).pwm(Pwm::new(3, 102)));
+        let pwm = sysfs.chip_dir(0).join("pwm3");
+        let settings = |skip_write_verify: Vec<String>| HardwareSettings { pwm_failure_threshold: 2, skip_write_verify, ..AgentConfig::default().hardware };
+        let monitor = sysfs.monitor_with(settings(Vec::new()));
+        monitor.discover_fans().await.unwrap();
+        async fn verify(monitor: &LinuxHardwareMonitor) -> anyhow::Result<()> {
+            let map = monitor.discovered_fans.read().await;
+            monitor.verify_pwm_write("it8689_fan_3", &map["it8689_fan_3"], 153).await
+        }
+
+        // Close enough: the chip rounded it
+        std::fs::write(&pwm, "150\n").unwrap();
+        verify(&monitor).await.unwrap();
+
+        // The firmware keeps the old value
+        std::fs::write(&pwm, "102\n").unwrap();
+        let err = verify(&monitor).await.unwrap_err();
+        assert!(err.to_string().contains("reads back 102, not 153"), "{}", err);
+        assert!(monitor.discover_fans().await.unwrap()[0].has_pwm_control);
+        assert!(verify(&monitor).await.is_err());
+
+        let fans = monitor.discover_fans().await.unwrap();
+        assert_eq!((fans[0].has_pwm_control, fans[0].status.as_str()), (false, "error"));
+        assert_eq!(fans[0].control_error.as_deref(), Some(WRITES_IGNORED));
+        let err = monitor.set_fan_speed("it8689_fan_3", 60, CLI).await.unwrap_err();
+        assert!(err.to_string().contains(WRITES_IGNORED), "{}", err);
+
+        // Listed in skip_write_verify: not read back
+        let unverified = sysfs.monitor_with(settings(vec!["it8689".to_string()]));
+        unverified.discover_fans().await.unwrap();
+        verify(&unverified).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn drifted_fan_is_reported_and_rewritten_up_to_the_limit() {
+        let sysfs = FakeSysfs::new("fan-drift")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 0).enable(1)).fan(2, 900).pwm(Pwm::new(2, 0).enable(1)));
+        let monitor = sysfs.monitor_with(HardwareSettings { drift_reassert_limit: 1, ..AgentConfig::default().hardware });
+        monitor.discover_fans().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap();
+        monitor.set_fan_speed("nct6798_fan_2", 50, CLI).await.unwrap();
+        let pwm = |n: u32| sysfs.chip_dir(0).join(format!("pwm{}", n));
+        let read = |n: u32| std::fs::read_to_string(pwm(n)).unwrap().trim().to_string();
+
+        // Where the write stuck there's no drift
+        let fans = monitor.discover_fans().await.unwrap();
+        assert!(!fans[0].drift);
+        assert_eq!((fans[0].speed, fans[0].target_speed), (50, 50));
+
+        // The EC puts fan 1 back to full speed; fan 2's chip took over (automatic mode)
+        std::fs::write(pwm(1), "255").unwrap();
+        std::fs::write(pwm(2), "255").unwrap();
+        std::fs::write(sysfs.chip_dir(0).join("pwm2_enable"), "2").unwrap();
+        let fans = monitor.discover_fans().await.unwrap();
+        assert!(fans[0].drift);
+        assert_eq!((fans[0].speed, fans[0].target_speed), (100, 50));
+        assert_eq!(read(1), "128");
+        assert!(!fans[1].drift);
+        assert_eq!(read(2), "255");
+
+        // Overridden again: the limit is reached, the drift is only reported
+        std::fs::write(pwm(1), "255").unwrap();
+        assert!(monitor.discover_fans().await.unwrap()[0].drift);
+        assert_eq!(read(1), "255");
+    }
+
+    #[tokio::test]
+    async fn semi_passive_fan_stops_and_is_kicked_back_to_life() {
+        let sysfs = FakeSysfs::new("fan-semi-passive")
+            .chip(Chip::new("nct6798").fan(1, 0).pwm(Pwm::new(1, 0).enable(1)).fan(2, 700).pwm(Pwm::new(2, 10).enable(1)));
+        let boost = SpinupBoost { percent: 60, duration_secs: 0.5 };
+        let semi_passive = [
+            ("nct6798_fan_1".to_string(), SemiPassive { allow_stop: true, stop_below_percent: 25, spinup_boost: Some(boost) }),
+            ("hwmon:nct6798_fan_2".to_string(), SemiPassive { stop_below_percent: 25, ..SemiPassive::default() }),
+        ];
+        let monitor = sysfs.monitor_with(HardwareSettings { semi_passive: semi_passive.into(), ..AgentConfig::default().hardware });
+        monitor.discover_hwmon_fans().await.unwrap();
+        let pwm = |n: u32| std::fs::read_to_string(sysfs.chip_dir(0).join(format!("pwm{}", n))).unwrap().trim().to_string();
+        let rate_limit = || tokio::time::sleep(std::time::Duration::from_millis(150));
+
+        // Below the minimum: fan 1 may stop, fan 2 (prefixed key) is held at 25%
+        rate_limit().await;
+        monitor.set_fan_speed("nct6798_fan_1", 10, CLI).await.unwrap();
+        monitor.set_fan_speed("nct6798_fan_2", 10, CLI).await.unwrap();
+        assert_eq!((pwm(1), pwm(2)), ("0".to_string(), "64".to_string()));
+
+        // Leaving the stop: boost first, later targets only update the settle value
+        monitor.set_fan_speed("nct6798_fan_1", 30, CLI).await.unwrap();
+        assert_eq!(pwm(1), "153");
+        rate_limit().await;
+        monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap();
+        assert_eq!(pwm(1), "153");
+
+        tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+        assert_eq!(pwm(1), "128");
+    }
+
+    #[tokio::test]
+    async fn spinup_boost_does_not_settle_on_a_fan_handed_to_the_chip() {
+        let sysfs = FakeSysfs::new("fan-spinup-pinned")
+            .chip(Chip::new("nct6798").fan(1, 0).pwm(Pwm::new(1, 0).enable(1)));
+        let boost = SpinupBoost { percent: 60, duration_secs: 0.3 };
+        let semi_passive = [("nct6798_fan_1".to_string(), SemiPassive { allow_stop: true, stop_below_percent: 25, spinup_boost: Some(boost) })];
+        let monitor = sysfs.monitor_with(HardwareSettings { semi_passive: semi_passive.into(), ..AgentConfig::default().hardware });
+        monitor.discover_hwmon_fans().await.unwrap();
+        let read = |file: &str| std::fs::read_to_string(sysfs.chip_dir(0).join(file)).unwrap().trim().to_string();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+
+        monitor.set_fan_speed("nct6798_fan_1", 30, CLI).await.unwrap();
+        assert_eq!(read("pwm1"), "153");
+        monitor.set_fan_mode("nct6798_fan_1", FanMode::Auto).await.unwrap();
+        assert_eq!(read("pwm1_enable"), "2");
+
+        // The boost ends with the chip in charge: no 30% written over its curve
+        tokio::time::sleep(std::time::Duration::from_millis(400)).await;
+        assert_eq!(read("pwm1"), "153");
+        assert!(monitor.discovered_fans.read().await["nct6798_fan_1"].spinup.lock().unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn raw_pwm_is_reported_and_written_above_the_minimum() {
+        let sysfs = FakeSysfs::new("fan-raw-pwm")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 254).enable(1))
+                .fan(2, 3000).file("fan2_target", "3000").file("fan2_min", "1000").file("fan2_max", "5000"));
+        let semi_passive = [("nct6798_fan_1".to_string(), SemiPassive { stop_below_percent: 40, ..SemiPassive::default() })];
+        let monitor = sysfs.monitor_with(HardwareSettings { semi_passive: semi_passive.into(), ..AgentConfig::default().hardware });
+        let fans = monitor.discover_fans().await.unwrap();
+        assert_eq!((fans[0].speed, fans[0].pwm), (100, Some(254)));
+        assert_eq!(fans[1].pwm, None);
+        let pwm = || std::fs::read_to_string(sysfs.chip_dir(0).join("pwm1")).unwrap().trim().to_string();
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        monitor.set_fan_pwm("nct6798_fan_1", 177, CLI).await.unwrap();
+        assert_eq!(pwm(), "177");
+        assert_eq!(monitor.discover_fans().await.unwrap()[0].pwm, Some(177));
+
+        // Below stop_below_percent: held at its PWM equivalent (40% = 102)
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        monitor.set_fan_pwm("nct6798_fan_1", 1, CLI).await.unwrap();
+        assert_eq!(pwm(), "102");
+
+        let err = monitor.set_fan_pwm("nct6798_fan_2", 100, CLI).await.unwrap_err();
+        assert!(err.to_string().contains("target RPM"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn control_state_is_restored_after_a_write() {
+        let sysfs = FakeSysfs::new("fan-restore")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 190).enable(2)));
+        let monitor = sysfs.monitor();
+        monitor.discover_hwmon_fans().await.unwrap();
+
+        let state = monitor.fan_control_state("nct6798_fan_1").await.unwrap().unwrap();
+        assert_eq!((state.value, state.pwm_enable.as_deref()), (190, Some("2")));
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        monitor.set_fan_speed("nct6798_fan_1", 60, CLI).await.unwrap();
+        let chip = sysfs.chip_dir(0);
+        assert_eq!(std::fs::read_to_string(chip.join("pwm1_enable")).unwrap().trim(), "1");
+
+        monitor.restore_fan_control_state("nct6798_fan_1", &state).await.unwrap();
+        assert_eq!(std::fs::read_to_string(chip.join("pwm1")).unwrap().trim(), "190");
+        assert_eq!(std::fs::read_to_string(chip.join("pwm1_enable")).unwrap().trim(), "2");
+    }
+
+    #[tokio::test]
+    async fn dry_run_leaves_sysfs_untouched() {
+        let sysfs = FakeSysfs::new("fan-dry-run")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 200).enable(2)));
+        let mut hardware = crate::config::types::AgentConfig::default().hardware;
+        hardware.dry_run = true;
+        let monitor = sysfs.monitor_with(hardware);
+        monitor.discover_hwmon_fans().await.unwrap();
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        monitor.set_fan_speed("nct6798_fan_1", 50, CLI).await.unwrap();
+        monitor.max_cooling().await.unwrap();
+
+        let chip = sysfs.chip_dir(0);
+        assert_eq!(std::fs::read_to_string(chip.join("pwm1")).unwrap().trim(), "200");
+        assert_eq!(std::fs::read_to_string(chip.join("pwm1_enable")).unwrap().trim(), "2");
     }
 }
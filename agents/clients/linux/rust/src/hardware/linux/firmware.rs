@@ -0,0 +1,179 @@
+//! Raspberry Pi firmware sensors via `vcgencmd` (VideoCore firmware mailbox).
+//!
+//! Supplementary source inside `LinuxHardwareMonitor`, same shape as the NVML source:
+//! `try_init()` returns `None` when `vcgencmd` is not installed, so non-Pi hosts are
+//! unaffected. Reports the SoC temperature as the firmware sees it and the throttle
+//! flags from `get_throttled` - an active throttle means the fan curve is not keeping
+//! the SoC below its soft temperature limit.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use tracing::{debug, info, warn};
+
+use crate::hardware::types::Sensor;
+
+/// Install locations of `vcgencmd` (Raspberry Pi OS / legacy userland).
+const VCGENCMD_PATHS: &[&str] = &["/usr/bin/vcgencmd", "/opt/vc/bin/vcgencmd"];
+
+/// `get_throttled` bits that mean the SoC is held back *right now*:
+/// 0x4 = currently throttled, 0x8 = soft temperature limit active.
+/// Under-voltage (0x1) and the sticky "has occurred" bits (0x10000+) are ignored.
+const THROTTLE_ACTIVE_MASK: u32 = 0x4 | 0x8;
+
+/// How long a reading is reused. Below the shortest data cycle (0.5 s), so the
+/// sensor scan, the system info and the UDP broadcast of one cycle share a read.
+const READING_TTL: Duration = Duration::from_millis(400);
+
+/// One read of the firmware: SoC temperature and `get_throttled` flags.
+#[derive(Debug, Clone, Copy, Default)]
+struct Reading {
+    temp: Option<f64>,
+    throttle_flags: Option<u32>,
+}
+
+/// Optional firmware-backed source. Present only when `vcgencmd` is available.
+pub(crate) struct FirmwareSource {
+    vcgencmd: PathBuf,
+    /// Last reported throttle state, for edge-triggered logging.
+    was_throttled: AtomicBool,
+    /// Last reading and when it was taken
+    reading: tokio::sync::Mutex<Option<(Instant, Reading)>>,
+}
+
+impl FirmwareSource {
+    /// Returns `None` (not an error) when `vcgencmd` is absent, so non-Pi machines
+    /// degrade gracefully.
+    pub(crate) fn try_init() -> Option<Self> {
+        let vcgencmd = VCGENCMD_PATHS.iter().map(Path::new).find(|p| p.exists())?;
+        debug!("Raspberry Pi firmware tools found: {:?}", vcgencmd);
+        Some(Self {
+            vcgencmd: vcgencmd.to_path_buf(),
+            was_throttled: AtomicBool::new(false),
+            reading: tokio::sync::Mutex::new(None),
+        })
+    }
+
+    async fn run(&self, arg: &str) -> Result<String> {
+        let output = tokio::process::Command::new(&self.vcgencmd)
+            .arg(arg)
+            .output()
+            .await
+            .with_context(|| format!("Failed to execute vcgencmd {}", arg))?;
+        if !output.status.success() {
+            anyhow::bail!("vcgencmd {} failed: {}", arg, String::from_utf8_lossy(&output.stderr).trim());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+    }
+
+    /// The reading of this cycle: taken on the first call, reused by the others.
+    /// A failed read is logged and left empty so it never breaks sysfs telemetry.
+    async fn reading(&self) -> Reading {
+        let mut cached = self.reading.lock().await;
+        if let Some((taken, reading)) = *cached {
+            if taken.elapsed() < READING_TTL {
+                return reading;
+            }
+        }
+        let (temp, throttled) = tokio::join!(self.run("measure_temp"), self.run("get_throttled"));
+        let reading = Reading {
+            temp: match temp.map(|out| parse_measure_temp(&out)) {
+                Ok(Some(temp)) => Some(temp),
+                Ok(None) => {
+                    warn!("Unrecognized vcgencmd measure_temp output");
+                    None
+                }
+                Err(e) => {
+                    warn!("Firmware temperature read failed: {}", e);
+                    None
+                }
+            },
+            throttle_flags: match throttled {
+                Ok(out) => parse_throttled(&out),
+                Err(e) => {
+                    debug!("Firmware throttle read failed: {}", e);
+                    None
+                }
+            },
+        };
+        *cached = Some((Instant::now(), reading));
+        reading
+    }
+
+    /// SoC temperature reported by the firmware, read once per cycle.
+    pub(crate) async fn discover_sensors(&self) -> Vec<Sensor> {
+        let Some(temp) = self.reading().await.temp else {
+            return Vec::new();
+        };
+        vec![Sensor {
+            id: "rpi_firmware_soc".into(),
+            name: "Raspberry Pi SoC (firmware)".into(),
+            display_name: None,
+            temperature: temp,
+            sensor_type: "cpu".into(),
+            max_temp: None,
+            crit_temp: None,
+            chip: Some("rpi_firmware".into()),
+            hardware_name: None,
+            source: Some("vcgencmd".into()),
+            alarms: Vec::new(),
+            raw_temperature: None,
+            stale: false,
+        }]
+    }
+
+    /// Whether the firmware is currently throttling the SoC. `None` if the flags
+    /// could not be read. Logs once on each transition.
+    pub(crate) async fn is_throttled(&self) -> Option<bool> {
+        let flags = self.reading().await.throttle_flags?;
+        let throttled = flags & THROTTLE_ACTIVE_MASK != 0;
+        if self.was_throttled.swap(throttled, Ordering::Relaxed) != throttled {
+            if throttled {
+                warn!("Raspberry Pi firmware is throttling the SoC (flags 0x{:x}) - fan curve may be insufficient", flags);
+            } else {
+                info!("Raspberry Pi firmware throttling cleared (flags 0x{:x})", flags);
+            }
+        }
+        Some(throttled)
+    }
+}
+
+/// Parse `temp=48.3'C` into degrees Celsius.
+fn parse_measure_temp(output: &str) -> Option<f64> {
+    output
+        .strip_prefix("temp=")?
+        .trim_end_matches("'C")
+        .parse::<f64>()
+        .ok()
+}
+
+/// Parse `throttled=0x50005` into the raw flag bits.
+fn parse_throttled(output: &str) -> Option<u32> {
+    let hex = output.strip_prefix("throttled=")?;
+    u32::from_str_radix(hex.trim_start_matches("0x"), 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn measure_temp_output_is_parsed() {
+        assert_eq!(parse_measure_temp("temp=48.3'C"), Some(48.3));
+        assert_eq!(parse_measure_temp("temp=-5.0'C"), Some(-5.0));
+        assert_eq!(parse_measure_temp("error=1 error_msg=\"Command not registered\""), None);
+        assert_eq!(parse_measure_temp("temp='C"), None);
+    }
+
+    #[test]
+    fn throttled_output_is_parsed() {
+        assert_eq!(parse_throttled("throttled=0x50005"), Some(0x50005));
+        assert_eq!(parse_throttled("throttled=0x0"), Some(0));
+        assert_eq!(parse_throttled("throttled=0x50005").map(|f| f & THROTTLE_ACTIVE_MASK != 0), Some(true));
+        assert_eq!(parse_throttled("throttled=0x50000").map(|f| f & THROTTLE_ACTIVE_MASK != 0), Some(false));
+        assert_eq!(parse_throttled("throttled=zz"), None);
+        assert_eq!(parse_throttled(""), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Test fixture: a fake sysfs tree materialized in a temp directory.
+//!
+//! Describe chips declaratively, then point a monitor at the tree:
+//!
+//! ```ignore
+//! let sysfs = FakeSysfs::new("example")
+//!     .chip(Chip::new("k10temp").temp(Temp::new(1, 45_000).label("Tctl").crit(95_000)))
+//!     .chip(Chip::new("nct6798").fan(1, 1200).pwm(Pwm::new(1, 128).enable(2)));
+//! let monitor = sysfs.monitor();
+//! ```
+//!
+//! Chips land in `class/hwmon/hwmonN` in the order added. The tree is removed on drop.
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::config::types::{AgentConfig, HardwareSettings};
+
+use super::monitor::LinuxHardwareMonitor;
+
+/// One `tempN_*` channel. Values are raw sysfs units (millidegrees).
+pub(crate) struct Temp {
+    num: u32,
+    input: i64,
+    label: Option<String>,
+    max: Option<i64>,
+    crit: Option<i64>,
+}
+
+impl Temp {
+    pub(crate) fn new(num: u32, input: i64) -> Self {
+        Self { num, input, label: None, max: None, crit: None }
+    }
+
+    pub(crate) fn label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    pub(crate) fn max(mut self, max: i64) -> Self {
+        self.max = Some(max);
+        self
+    }
+
+    pub(crate) fn crit(mut self, crit: i64) -> Self {
+        self.crit = Some(crit);
+        self
+    }
+}
+
+/// One `pwmN` output, optionally with `pwmN_enable` and non-default file mode.
+pub(crate) struct Pwm {
+    num: u32,
+    value: u8,
+    enable: Option<u8>,
+    mode: u32,
+}
+
+impl Pwm {
+    pub(crate) fn new(num: u32, value: u8) -> Self {
+        Self { num, value, enable: None, mode: 0o644 }
+    }
+
+    pub(crate) fn enable(mut self, enable: u8) -> Self {
+        self.enable = Some(enable);
+        self
+    }
+
+    /// File mode for `pwmN` (e.g. `0o444` for a read-only channel).
+    pub(crate) fn mode(mut self, mode: u32) -> Self {
+        self.mode = mode;
+        self
+    }
+}
+
+/// One hwmon chip directory.
+pub(crate) struct Chip {
+    name: String,
+    temps: Vec<Temp>,
+    fans: Vec<(u32, u32)>,
+    pwms: Vec<Pwm>,
+    files: Vec<(String, String)>,
+    device: Option<String>,
+}
+
+impl Chip {
+    pub(crate) fn new(name: &str) -> Self {
+        Self { name: name.to_string(), temps: Vec::new(), fans: Vec::new(), pwms: Vec::new(), files: Vec::new(), device: None }
+    }
+
+    /// Make `device` a symlink to `devices/<name>` under the sysfs root, like the
+    /// kernel's link to the parent device (e.g. "nvme0"). `device/...` files land there.
+    pub(crate) fn device(mut self, name: &str) -> Self {
+        self.device = Some(name.to_string());
+        self
+    }
+
+    pub(crate) fn temp(mut self, temp: Temp) -> Self {
+        self.temps.push(temp);
+        self
+    }
+
+    /// `fanN_input` tach reading in RPM.
+    pub(crate) fn fan(mut self, num: u32, rpm: u32) -> Self {
+        self.fans.push((num, rpm));
+        self
+    }
+
+    pub(crate) fn pwm(mut self, pwm: Pwm) -> Self {
+        self.pwms.push(pwm);
+        self
+    }
+
+    /// Arbitrary file relative to the chip dir (alarms, `device/model`, ...).
+    pub(crate) fn file(mut self, rel: &str, contents: &str) -> Self {
+        self.files.push((rel.to_string(), contents.to_string()));
+        self
+    }
+}
+
+/// Temp-dir sysfs root. Removed on drop.
+pub(crate) struct FakeSysfs {
+    root: PathBuf,
+    chips: usize,
+}
+
+impl FakeSysfs {
+    /// `tag` keeps parallel tests from sharing a directory.
+    pub(crate) fn new(tag: &str) -> Self {
+        let root = std::env::temp_dir().join(format!("pankha-sysfs-{}-{}", tag, std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::fs::create_dir_all(root.join("class/hwmon")).unwrap();
+        Self { root, chips: 0 }
+    }
+
+    /// The temp directory itself, for trees that live beside sysfs (`proc/`, `etc/`).
+    pub(crate) fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Directory of the `index`th chip added (`class/hwmon/hwmon<index>`).
+    pub(crate) fn chip_dir(&self, index: usize) -> PathBuf {
+        self.root.join(format!("class/hwmon/hwmon{}", index))
+    }
+
+    pub(crate) fn chip(mut self, chip: Chip) -> Self {
+        let dir = self.chip_dir(self.chips);
+        self.chips += 1;
+
+        write(&dir.join("name"), &chip.name);
+        if let Some(device) = &chip.device {
+            let target = self.root.join("devices").join(device);
+            std::fs::create_dir_all(&target).unwrap();
+            std::os::unix::fs::symlink(&target, dir.join("device")).unwrap();
+        }
+        for t in &chip.temps {
+            write(&dir.join(format!("temp{}_input", t.num)), &t.input.to_string());
+            if let Some(label) = &t.label {
+                write(&dir.join(format!("temp{}_label", t.num)), label);
+            }
+            if let Some(max) = t.max {
+                write(&dir.join(format!("temp{}_max", t.num)), &max.to_string());
+            }
+            if let Some(crit) = t.crit {
+                write(&dir.join(format!("temp{}_crit", t.num)), &crit.to_string());
+            }
+        }
+        for (num, rpm) in &chip.fans {
+            write(&dir.join(format!("fan{}_input", num)), &rpm.to_string());
+        }
+        for p in &chip.pwms {
+            let path = dir.join(format!("pwm{}", p.num));
+            write(&path, &p.value.to_string());
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(p.mode)).unwrap();
+            if let Some(enable) = p.enable {
+                write(&dir.join(format!("pwm{}_enable", p.num)), &enable.to_string());
+            }
+        }
+        for (rel, contents) in &chip.files {
+            write(&dir.join(rel), contents);
+        }
+        self
+    }
+
+    /// Arbitrary file relative to the sysfs root (e.g. `class/block/sda/device/model`).
+    pub(crate) fn file(self, rel: &str, contents: &str) -> Self {
+        write(&self.root.join(rel), contents);
+        self
+    }
+
+    /// Symlink `rel_link` -> `rel_target`, both relative to the sysfs root.
+    pub(crate) fn symlink(self, rel_link: &str, rel_target: &str) -> Self {
+        let link = self.root.join(rel_link);
+        let target = self.root.join(rel_target);
+        std::fs::create_dir_all(link.parent().unwrap()).unwrap();
+        std::fs::create_dir_all(&target).unwrap();
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+        self
+    }
+
+    pub(crate) fn monitor(&self) -> LinuxHardwareMonitor {
+        self.monitor_with(AgentConfig::default().hardware)
+    }
+
+    pub(crate) fn monitor_with(&self, hardware: HardwareSettings) -> LinuxHardwareMonitor {
+        LinuxHardwareMonitor::with_sysfs_root(hardware, &self.root)
+    }
+}
+
+impl Drop for FakeSysfs {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+/// sysfs attributes end in a newline; the monitor must trim it.
+fn write(path: &Path, contents: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, format!("{}\n", contents)).unwrap();
+}
@@ -0,0 +1,142 @@
+//! hwmon hot-plug detection via kernel uevents (`NETLINK_KOBJECT_UEVENT`).
+//!
+//! The kernel announces each hwmon device it adds or removes, a chip driver bound or
+//! unbound at runtime included, on the uevent netlink socket. Messages are drained
+//! without blocking at the start of each sensor discovery, so no task or thread is
+//! needed. Only the live `/sys` gets a listener; without one (netlink unavailable, or
+//! a fixture tree the kernel knows nothing about) the monitor counts hwmon
+//! directories every discovery instead.
+
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use anyhow::{anyhow, Result};
+
+/// Multicast group of the kernel's own uevents (udev re-broadcasts on group 2)
+const UEVENT_GROUP_KERNEL: u32 = 1;
+
+/// Actions that add, remove or re-home an hwmon device. `change` is left out: hwmon
+/// drivers raise it for alarms (`hwmon_notify_event`), which is no hardware change.
+const HOTPLUG_ACTIONS: &[&str] = &["add", "remove", "move", "bind", "unbind"];
+
+#[derive(Debug)]
+pub(crate) struct HwmonUevents {
+    fd: OwnedFd,
+    /// The socket failed; callers fall back to counting
+    dead: AtomicBool,
+}
+
+impl HwmonUevents {
+    /// Listen to the kernel's uevents.
+    pub(crate) fn open() -> Result<Self> {
+        let raw = unsafe {
+            libc::socket(libc::AF_NETLINK, libc::SOCK_DGRAM | libc::SOCK_NONBLOCK | libc::SOCK_CLOEXEC, libc::NETLINK_KOBJECT_UEVENT)
+        };
+        if raw < 0 {
+            return Err(anyhow!("uevent socket: {}", std::io::Error::last_os_error()));
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(raw) };
+
+        let mut addr: libc::sockaddr_nl = unsafe { std::mem::zeroed() };
+        addr.nl_family = libc::AF_NETLINK as libc::sa_family_t;
+        addr.nl_groups = UEVENT_GROUP_KERNEL;
+        let bound = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                (&addr as *const libc::sockaddr_nl).cast(),
+                std::mem::size_of::<libc::sockaddr_nl>() as libc::socklen_t,
+            )
+        };
+        if bound < 0 {
+            return Err(anyhow!("uevent socket bind: {}", std::io::Error::last_os_error()));
+        }
+        Ok(Self::from_fd(fd))
+    }
+
+    /// Read uevents from `fd`, a non-blocking datagram socket (tests feed one by hand).
+    pub(crate) fn from_fd(fd: OwnedFd) -> Self {
+        Self { fd, dead: AtomicBool::new(false) }
+    }
+
+    pub(crate) fn is_dead(&self) -> bool {
+        self.dead.load(Ordering::Relaxed)
+    }
+
+    /// Drain pending uevents. True if an hwmon device came or went since the last
+    /// call (or uevents were lost to a receive buffer overflow, or the socket failed).
+    pub(crate) fn changed(&self) -> bool {
+        // UEVENT_BUFFER_SIZE is 2048; room to spare
+        let mut buf = [0u8; 8192];
+        let mut changed = false;
+        loop {
+            let n = unsafe { libc::recv(self.fd.as_raw_fd(), buf.as_mut_ptr().cast(), buf.len(), 0) };
+            if n >= 0 {
+                changed |= is_hwmon_hotplug(&buf[..n as usize]);
+                continue;
+            }
+            match std::io::Error::last_os_error().raw_os_error() {
+                // Queue drained
+                Some(libc::EAGAIN) => return changed,
+                Some(libc::EINTR) => {}
+                // Uevents were dropped; any of them may have been ours
+                Some(libc::ENOBUFS) => changed = true,
+                _ => {
+                    self.dead.store(true, Ordering::Relaxed);
+                    return true;
+                }
+            }
+        }
+    }
+}
+
+/// A kernel uevent is `ACTION@DEVPATH` followed by `KEY=VALUE` fields, all
+/// NUL-terminated. True for an hwmon device being added, removed or re-homed.
+fn is_hwmon_hotplug(message: &[u8]) -> bool {
+    let mut fields = message.split(|&b| b == 0).map(String::from_utf8_lossy);
+    let Some(header) = fields.next() else {
+        return false;
+    };
+    let Some((action, _devpath)) = header.split_once('@') else {
+        return false;
+    };
+    HOTPLUG_ACTIONS.contains(&action) && fields.any(|field| field == "SUBSYSTEM=hwmon")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::net::UnixDatagram;
+
+    fn uevent(action: &str, devpath: &str, subsystem: &str) -> Vec<u8> {
+        format!("{action}@{devpath}\0ACTION={action}\0DEVPATH={devpath}\0SUBSYSTEM={subsystem}\0SEQNUM=4242\0").into_bytes()
+    }
+
+    #[test]
+    fn only_hwmon_devices_coming_and_going_count() {
+        let hwmon = "/devices/platform/nct6775.656/hwmon/hwmon3";
+        assert!(is_hwmon_hotplug(&uevent("add", hwmon, "hwmon")));
+        assert!(is_hwmon_hotplug(&uevent("remove", hwmon, "hwmon")));
+        // Alarms raise change uevents on the hwmon device
+        assert!(!is_hwmon_hotplug(&uevent("change", hwmon, "hwmon")));
+        assert!(!is_hwmon_hotplug(&uevent("add", "/devices/platform/nct6775.656", "platform")));
+        assert!(!is_hwmon_hotplug(b"libudev\0\xfe\xed\xca\xfe"));
+        assert!(!is_hwmon_hotplug(b""));
+    }
+
+    #[test]
+    fn pending_uevents_are_drained() {
+        let (events, kernel) = UnixDatagram::pair().unwrap();
+        events.set_nonblocking(true).unwrap();
+        let uevents = HwmonUevents::from_fd(events.into());
+        assert!(!uevents.changed());
+
+        kernel.send(&uevent("change", "/devices/virtual/hwmon/hwmon1", "hwmon")).unwrap();
+        assert!(!uevents.changed());
+
+        kernel.send(&uevent("add", "/devices/pci0000:00/0000:00:18.3/hwmon/hwmon1", "pci")).unwrap();
+        kernel.send(&uevent("add", "/devices/virtual/hwmon/hwmon1", "hwmon")).unwrap();
+        assert!(uevents.changed());
+        assert!(!uevents.changed(), "uevents are drained");
+        assert!(!uevents.is_dead());
+    }
+}
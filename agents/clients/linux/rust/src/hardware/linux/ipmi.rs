@@ -0,0 +1,174 @@
+//! IPMI backend for composite mode: the IPMI agent's `IpmiHardwareMonitor` (profiles,
+//! ipmitool, SDR parsing) hosted in this process and adapted to this crate's types.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use pankha_agent_ipmi::config::types::HardwareSettings as IpmiSettings;
+use pankha_agent_ipmi::hardware::ipmi::ipmi_monitor::fan_state_path;
+use pankha_agent_ipmi::hardware::{HardwareMonitor as IpmiMonitorTrait, IpmiHardwareMonitor};
+
+use crate::config::types::HardwareSettings;
+use crate::hardware::types::{Fan, FanSetBy, FanSpeedOrigin, HardwareDumpRoot, Sensor, SystemHealth};
+use crate::hardware::HardwareMonitor;
+
+pub struct IpmiBackend {
+    inner: IpmiHardwareMonitor,
+    dry_run: bool,
+    /// Fan id -> zone from the last discovery. The BMC is commanded per zone, but
+    /// failsafe and emergency paths address individual fans.
+    fan_zones: RwLock<HashMap<String, String>>,
+    /// Zone (or fan id, for fans outside a zone) -> origin of the last speed set
+    last_set_by: RwLock<HashMap<String, FanSetBy>>,
+}
+
+impl IpmiBackend {
+    /// `profile` defaults to profile.json next to the binary, like the IPMI agent.
+    pub fn new(settings: &HardwareSettings, profile: Option<&str>) -> Self {
+        let profile_path = profile.map(PathBuf::from).unwrap_or_else(|| {
+            std::env::current_exe()
+                .ok()
+                .and_then(|p| p.parent().map(|d| d.join("profile.json")))
+                .unwrap_or_else(|| PathBuf::from("profile.json"))
+        });
+        // Commanded zone speeds persist beside this agent's config.json
+        let state_path = crate::config::persistence::default_config_path().ok().map(|path| fan_state_path(&path));
+        let ipmi_settings = IpmiSettings {
+            enable_fan_control: settings.enable_fan_control,
+            enable_sensor_monitoring: settings.enable_sensor_monitoring,
+            fan_step_percent: settings.fan_step_percent,
+            hysteresis_temp: settings.hysteresis_temp,
+            emergency_temp: settings.emergency_temp,
+            failsafe_speed: settings.failsafe_speed,
+            excluded_sensors: settings.excluded_sensors.clone(),
+            ipmi_retries: pankha_agent_ipmi::config::types::default_ipmi_retries(),
+            ipmi_retry_backoff_ms: pankha_agent_ipmi::config::types::default_ipmi_retry_backoff_ms(),
+            ipmi_timeout_secs: pankha_agent_ipmi::config::types::default_ipmi_timeout_secs(),
+            // Status sensors only exist in the standalone IPMI agent's data messages
+            report_status_sensors: false,
+        };
+        Self {
+            inner: IpmiHardwareMonitor::with_profile(ipmi_settings, profile_path, state_path, settings.dry_run),
+            dry_run: settings.dry_run,
+            fan_zones: RwLock::new(HashMap::new()),
+            last_set_by: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait]
+impl HardwareMonitor for IpmiBackend {
+    async fn discover_sensors(&self) -> Result<Vec<Sensor>> {
+        let sensors = self.inner.discover_sensors().await?;
+        Ok(sensors
+            .into_iter()
+            .map(|s| Sensor {
+                id: s.id.into(),
+                name: s.name.into(),
+                display_name: None,
+                temperature: s.temperature,
+                sensor_type: s.sensor_type.into(),
+                max_temp: s.max_temp,
+                crit_temp: s.crit_temp,
+                chip: s.chip.map(Into::into),
+                hardware_name: s.hardware_name.map(Into::into),
+                source: s.source.map(Into::into),
+                alarms: Vec::new(),
+                raw_temperature: None,
+                stale: false,
+            })
+            .collect())
+    }
+
+    async fn discover_fans(&self) -> Result<Vec<Fan>> {
+        let fans = self.inner.discover_fans().await?;
+        *self.fan_zones.write().await = fans
+            .iter()
+            .filter_map(|f| Some((f.id.clone(), f.zone.clone()?)))
+            .collect();
+        let last_set_by = self.last_set_by.read().await;
+        Ok(fans
+            .into_iter()
+            .map(|f| {
+                // A zone is commanded as a unit: its fans share the origin
+                let set_by = last_set_by.get(f.zone.as_ref().unwrap_or(&f.id)).cloned();
+                Fan {
+                    id: f.id,
+                    name: f.name,
+                    rpm: f.rpm,
+                    speed: f.speed,
+                    target_speed: f.target_speed,
+                    pwm: None,
+                    status: f.status,
+                    status_detail: None,
+                    has_pwm_control: f.has_pwm_control,
+                    control_method: None,
+                    pwm_file: f.pwm_file,
+                    pwm_enable: None,
+                    control_mode: None,
+                    zone: f.zone,
+                    alarms: Vec::new(),
+                    drift: false,
+                    control_error: None,
+                    last_set_by: set_by,
+                }
+            })
+            .collect())
+    }
+
+    async fn get_system_info(&self) -> Result<SystemHealth> {
+        let health = self.inner.get_system_info().await?;
+        Ok(SystemHealth {
+            cpu_usage: health.cpu_usage,
+            memory_usage: health.memory_usage,
+            agent_uptime: health.agent_uptime,
+            throttled: None,
+            connection: None,
+            storage_health: Vec::new(),
+            notifications: None,
+        })
+    }
+
+    async fn set_fan_speed(&self, fan_id: &str, speed: u8, origin: &FanSpeedOrigin) -> Result<()> {
+        let zone = self.fan_zones.read().await.get(fan_id).cloned();
+        let target = zone.as_deref().unwrap_or(fan_id);
+        debug!("IPMI: set {} to {}% ({})", target, speed, origin);
+        self.inner.set_fan_speed(target, speed).await?;
+        self.last_set_by.write().await.insert(target.to_string(), FanSetBy::now(origin));
+        Ok(())
+    }
+
+    async fn max_cooling(&self) -> Result<()> {
+        self.inner.max_cooling().await
+    }
+
+    async fn release_control(&self) -> Vec<(String, String)> {
+        match self.inner.release_control().await {
+            Ok(()) => Vec::new(),
+            Err(e) => vec![("*".to_string(), format!("{:#}", e))],
+        }
+    }
+
+    async fn invalidate_cache(&self) {
+        self.inner.invalidate_cache().await
+    }
+
+    async fn last_discovery_from_cache(&self) -> bool {
+        self.inner.last_discovery_from_cache().await
+    }
+
+    async fn dump_hardware_info(&self) -> Result<HardwareDumpRoot> {
+        // Both crates share the dump layout (it mirrors the Windows agent's)
+        let dump = self.inner.dump_hardware_info().await?;
+        Ok(serde_json::from_value(serde_json::to_value(dump)?)?)
+    }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+}
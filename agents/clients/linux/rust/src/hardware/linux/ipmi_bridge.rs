@@ -0,0 +1,153 @@
+//! Linux hardware monitor: the kernel IPMI hwmon bridge (`hardware.ipmi_bridge_dedup`).
+//!
+//! On servers the BMC's SDR temperatures can also appear as hwmon chips named `ipmi*`,
+//! next to the native drivers reading the same silicon (apm_xgene on Ampere Altra).
+//! The two seldom agree to the degree and label the same probe differently ("CPU0
+//! Temp" vs "CPU temp"), so duplicates are found by label words, with the readings
+//! only as a sanity bound. Bridge sensors have type "ipmi" and hardware name
+//! `BRIDGE_HARDWARE_NAME`; the ones duplicating a native sensor are dropped, the rest
+//! (PSU, DIMM temps) are kept.
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use crate::config::types::IpmiBridgeDedup;
+use crate::hardware::types::Sensor;
+
+/// `hardwareName` of every bridge sensor: where the reading came from.
+pub(crate) const BRIDGE_HARDWARE_NAME: &str = "BMC (IPMI)";
+
+/// Label words that don't tell probes apart.
+const FILLER_WORDS: &[&str] = &["temp", "temperature", "sensor"];
+
+/// Whether hwmon chip `chip_name` is the kernel IPMI bridge.
+pub(crate) fn is_bridge_chip(chip_name: &str) -> bool {
+    chip_name.to_lowercase().starts_with("ipmi")
+}
+
+/// Lowercase words of a label, split at punctuation and between letters and digits
+/// ("CPU0_Temp" -> cpu, 0), without filler words.
+fn label_words(label: &str) -> BTreeSet<String> {
+    let mut words = BTreeSet::new();
+    let mut word = String::new();
+    for c in label.chars().map(|c| c.to_ascii_lowercase()) {
+        let boundary = !c.is_ascii_alphanumeric()
+            || word.chars().last().is_some_and(|last| last.is_ascii_digit() != c.is_ascii_digit());
+        if boundary && !word.is_empty() {
+            words.insert(std::mem::take(&mut word));
+        }
+        if c.is_ascii_alphanumeric() {
+            word.push(c);
+        }
+    }
+    if !word.is_empty() {
+        words.insert(word);
+    }
+    words.retain(|w| !FILLER_WORDS.contains(&w.as_str()));
+    words
+}
+
+/// Share of words two labels have in common (Jaccard), 0-1.
+fn label_similarity(a: &BTreeSet<String>, b: &BTreeSet<String>) -> f64 {
+    let union = a.union(b).count();
+    if union == 0 {
+        return 0.0;
+    }
+    a.intersection(b).count() as f64 / union as f64
+}
+
+/// The label part of a sensor id (`<chip>_<label>`).
+fn label_of(sensor: &Sensor) -> &str {
+    let chip = sensor.chip.as_deref().map(|c| c.to_lowercase().replace(' ', "_")).unwrap_or_default();
+    sensor.id.strip_prefix(&chip).and_then(|rest| rest.strip_prefix('_')).unwrap_or(&sensor.id)
+}
+
+/// Remove the bridge sensors that duplicate a native one; returns their ids.
+pub(crate) fn drop_duplicates(sensors: &mut Vec<Sensor>, settings: &IpmiBridgeDedup) -> Vec<Arc<str>> {
+    let is_bridge = |s: &Sensor| s.chip.as_deref().is_some_and(is_bridge_chip);
+    let native: Vec<(BTreeSet<String>, f64)> = sensors.iter()
+        .filter(|s| !is_bridge(s))
+        .map(|s| (label_words(label_of(s)), s.temperature))
+        .collect();
+    if native.is_empty() {
+        return Vec::new();
+    }
+
+    let mut dropped = Vec::new();
+    sensors.retain(|sensor| {
+        if !is_bridge(sensor) {
+            return true;
+        }
+        let words = label_words(label_of(sensor));
+        let best = native.iter()
+            .map(|(native_words, temperature)| (label_similarity(&words, native_words), temperature))
+            .max_by(|a, b| a.0.total_cmp(&b.0));
+        let duplicate = best.is_some_and(|(similarity, temperature)| {
+            similarity >= settings.min_similarity && (sensor.temperature - temperature).abs() <= settings.max_temp_delta
+        });
+        if duplicate {
+            dropped.push(Arc::clone(&sensor.id));
+        }
+        !duplicate
+    });
+    dropped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::{AgentConfig, HardwareSettings};
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Temp};
+    use crate::hardware::HardwareMonitor;
+
+    #[test]
+    fn labels_are_compared_by_words() {
+        let words = |label: &str| label_words(label);
+        assert_eq!(words("CPU0_Temp"), words("cpu 0"));
+        assert_eq!(label_similarity(&words("CPU0 Temp"), &words("CPU temp")), 0.5);
+        assert_eq!(label_similarity(&words("SoC Temperature"), &words("SOC_Temp")), 1.0);
+        assert_eq!(label_similarity(&words("CPU1 Temp"), &words("CPU0 Temp")), 1.0 / 3.0);
+        assert_eq!(label_similarity(&words("DIMM A1 Temp"), &words("CPU temp")), 0.0);
+        assert_eq!(label_similarity(&words("Temp"), &words("Temperature")), 0.0);
+    }
+
+    /// An Ampere Altra: apm_xgene natively, the same probes again through the bridge
+    /// a degree apart, plus bridge-only PSU and DIMM temps.
+    fn altra(tag: &str) -> FakeSysfs {
+        FakeSysfs::new(tag)
+            .chip(Chip::new("apm_xgene")
+                .temp(Temp::new(1, 45_000).label("SoC Temperature"))
+                .temp(Temp::new(2, 52_000).label("CPU temp")))
+            .chip(Chip::new("ipmi_si")
+                .temp(Temp::new(1, 46_000).label("SOC_Temp"))
+                .temp(Temp::new(2, 51_000).label("CPU0 Temp"))
+                .temp(Temp::new(3, 38_000).label("PSU1 Temp"))
+                .temp(Temp::new(4, 34_000).label("DIMM A1 Temp")))
+    }
+
+    #[tokio::test]
+    async fn bridge_duplicates_give_way_to_native_sensors() {
+        let sysfs = altra("ipmi-bridge");
+        let sensors = sysfs.monitor().discover_sensors().await.unwrap();
+        let mut ids: Vec<&str> = sensors.iter().map(|s| &*s.id).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, ["apm_xgene_cpu_temp", "apm_xgene_soc_temperature", "ipmi_si_dimm_a1_temp", "ipmi_si_psu1_temp"]);
+
+        let psu = sensors.iter().find(|s| &*s.id == "ipmi_si_psu1_temp").unwrap();
+        assert_eq!((&*psu.sensor_type, psu.hardware_name.as_deref()), ("ipmi", Some(BRIDGE_HARDWARE_NAME)));
+        assert_eq!((&*psu.name, psu.display_name.as_deref()), ("ipmi_si PSU1 Temp", Some("BMC PSU1 Temp")));
+
+        // Off: both copies stay
+        let off = IpmiBridgeDedup { enabled: false, ..IpmiBridgeDedup::default() };
+        let monitor = sysfs.monitor_with(HardwareSettings { ipmi_bridge_dedup: off, ..AgentConfig::default().hardware });
+        assert_eq!(monitor.discover_sensors().await.unwrap().len(), 6);
+    }
+
+    #[tokio::test]
+    async fn readings_far_apart_are_not_duplicates() {
+        let sysfs = FakeSysfs::new("ipmi-bridge-apart")
+            .chip(Chip::new("apm_xgene").temp(Temp::new(1, 52_000).label("CPU temp")))
+            .chip(Chip::new("ipmi_si").temp(Temp::new(1, 70_000).label("CPU0 Temp")));
+        assert_eq!(sysfs.monitor().discover_sensors().await.unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,639 @@
+//! Linux hardware monitor: lm-sensors configuration (`hardware.use_lm_sensors_config`).
+//!
+//! Hosts set up for `sensors` already say in `/etc/sensors3.conf` and `/etc/sensors.d/`
+//! what each input is ("VRM", "Water In"), which ones to hide, and how to correct raw
+//! values for a board's dividers. With the option on, those files are read at startup
+//! and their `chip`, `label`, `ignore` and `compute` statements applied in discovery:
+//! a label replaces the hwmon label in sensor and fan names (ids keep the kernel's
+//! label, so turning this on doesn't re-key anything), ignored inputs are left out,
+//! and the first expression of a `compute` is applied to each reading and limit.
+//! `set` and `bus` statements are skipped; so is anything that doesn't parse, with a
+//! warning naming the file and line.
+
+use std::path::Path;
+
+use tracing::{debug, warn};
+
+/// Where sensors3.conf (or the older sensors.conf) and sensors.d live.
+pub(crate) const SENSORS_CONFIG_DIR: &str = "/etc";
+
+/// The parsed statements of every configuration file, in libsensors' reading order.
+#[derive(Debug, Default)]
+pub(crate) struct LmSensorsConfig {
+    blocks: Vec<ChipBlock>,
+}
+
+/// The statements following one `chip` line.
+#[derive(Debug)]
+struct ChipBlock {
+    patterns: Vec<ChipPattern>,
+    statements: Vec<Statement>,
+}
+
+#[derive(Debug)]
+enum Statement {
+    Label(String, String),
+    Ignore(String),
+    Compute(String, Compute),
+}
+
+/// What the configuration says about one input (temp1, fan2, ...).
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct FeatureConfig {
+    pub(crate) label: Option<String>,
+    pub(crate) ignore: bool,
+    pub(crate) compute: Option<Compute>,
+}
+
+impl LmSensorsConfig {
+    /// `sensors3.conf` (else `sensors.conf`), then the files in `sensors.d` in name
+    /// order, under `dir`. Missing files are fine; unparsable statements are logged.
+    pub(crate) fn load(dir: &Path) -> Self {
+        let main = ["sensors3.conf", "sensors.conf"].iter().map(|name| dir.join(name)).find(|path| path.is_file());
+        let mut extra: Vec<_> = std::fs::read_dir(dir.join("sensors.d"))
+            .map(|entries| {
+                entries
+                    .flatten()
+                    .map(|entry| entry.path())
+                    .filter(|path| path.is_file() && !path.file_name().unwrap_or_default().to_string_lossy().starts_with('.'))
+                    .collect()
+            })
+            .unwrap_or_default();
+        extra.sort();
+
+        let mut config = Self::default();
+        for path in main.into_iter().chain(extra) {
+            let text = match std::fs::read(&path) {
+                Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+                Err(e) => {
+                    warn!("lm-sensors config {:?} not read: {}", path, e);
+                    continue;
+                }
+            };
+            let (blocks, errors) = parse(&text);
+            for (line, error) in errors {
+                warn!("lm-sensors config {:?} line {}: {} (statement skipped)", path, line, error);
+            }
+            debug!("lm-sensors config {:?}: {} chip block(s)", path, blocks.len());
+            config.blocks.extend(blocks);
+        }
+        config
+    }
+
+    /// The statements for `feature` of `chip`. Later statements win, like in libsensors.
+    pub(crate) fn feature(&self, chip: &ChipName, feature: &str) -> FeatureConfig {
+        let mut config = FeatureConfig::default();
+        for block in self.blocks.iter().filter(|block| block.patterns.iter().any(|p| p.matches(chip))) {
+            for statement in &block.statements {
+                match statement {
+                    Statement::Label(name, label) if name == feature => config.label = Some(label.clone()),
+                    Statement::Ignore(name) if name == feature => config.ignore = true,
+                    Statement::Compute(name, compute) if name == feature => config.compute = Some(compute.clone()),
+                    _ => {}
+                }
+            }
+        }
+        config
+    }
+}
+
+/// Chip blocks of one file, and the (line, error) of each statement skipped.
+fn parse(text: &str) -> (Vec<ChipBlock>, Vec<(usize, String)>) {
+    let mut blocks: Vec<ChipBlock> = Vec::new();
+    let mut errors = Vec::new();
+    for (line, statement) in logical_lines(text) {
+        let words = match split_words(&statement) {
+            Ok(words) => words,
+            Err(e) => {
+                errors.push((line, e));
+                continue;
+            }
+        };
+        let Some((keyword, args)) = words.split_first() else {
+            continue;
+        };
+        let parsed = match keyword.as_str() {
+            "chip" if args.is_empty() => Err("chip without a name".to_string()),
+            "chip" => args.iter().map(|arg| ChipPattern::parse(arg)).collect::<Result<Vec<_>, _>>().map(|patterns| {
+                blocks.push(ChipBlock { patterns, statements: Vec::new() });
+                None
+            }),
+            "label" => match args {
+                [feature, label] => Ok(Some(Statement::Label(feature.clone(), label.clone()))),
+                _ => Err("expected: label <input> \"<text>\"".to_string()),
+            },
+            "ignore" => match args {
+                [feature] => Ok(Some(Statement::Ignore(feature.clone()))),
+                _ => Err("expected: ignore <input>".to_string()),
+            },
+            "compute" => match args.split_first() {
+                // The second expression converts back, for `set`; only the first is used
+                Some((feature, exprs)) if !exprs.is_empty() => {
+                    let exprs = exprs.join(" ");
+                    let forward = exprs.split(',').next().unwrap_or_default();
+                    Compute::parse(forward).map(|compute| Some(Statement::Compute(feature.clone(), compute)))
+                }
+                _ => Err("expected: compute <input> <expression>, <expression>".to_string()),
+            },
+            "set" | "bus" => Ok(None),
+            other => Err(format!("unknown statement '{}'", other)),
+        };
+        match parsed {
+            Ok(Some(statement)) => match blocks.last_mut() {
+                Some(block) => block.statements.push(statement),
+                None => errors.push((line, format!("'{}' before the first chip statement", keyword))),
+            },
+            Ok(None) => {}
+            Err(e) => errors.push((line, e)),
+        }
+    }
+    (blocks, errors)
+}
+
+/// Statements with their first line number: comments dropped and lines ending in a
+/// backslash joined with the next. A `#` inside quotes is text.
+fn logical_lines(text: &str) -> Vec<(usize, String)> {
+    let mut lines = Vec::new();
+    let mut current: Option<(usize, String)> = None;
+    for (index, raw) in text.lines().enumerate() {
+        let line = strip_comment(raw);
+        let (content, continued) = match line.trim_end().strip_suffix('\\') {
+            Some(content) => (content, true),
+            None => (line, false),
+        };
+        let (_, statement) = current.get_or_insert_with(|| (index + 1, String::new()));
+        statement.push_str(content);
+        statement.push(' ');
+        if !continued {
+            lines.extend(current.take().filter(|(_, s)| !s.trim().is_empty()));
+        }
+    }
+    lines.extend(current.filter(|(_, s)| !s.trim().is_empty()));
+    lines
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            '#' if !quoted => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Whitespace-separated words; double quotes group words and take `\"`, `\\`, `\n`
+/// and `\t` escapes. `"Water"In` is one word, as in libsensors.
+fn split_words(statement: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = statement.chars();
+    let mut word: Option<String> = None;
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('t') => word.push('\t'),
+                            Some(escaped) => word.push(escaped),
+                            None => return Err("unterminated quote".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated quote".to_string()),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// A chip as libsensors names it: `<prefix>-<bus>[-<nr>]-<address>`, e.g.
+/// `nct6798-isa-0290`, `k10temp-pci-00c3`, `lm75-i2c-1-48`.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ChipName {
+    prefix: String,
+    /// `None` when the bus couldn't be worked out; only `<prefix>-*` patterns match
+    bus: Option<String>,
+    nr: Option<u32>,
+    addr: Option<u32>,
+}
+
+/// Buses whose names carry a bus number before the address.
+const NUMBERED_BUSES: &[&str] = &["i2c", "spi", "hid", "scsi"];
+
+/// Parent devices searched for a known bus (the nvme class device sits under its
+/// PCI function).
+const MAX_BUS_DEPTH: usize = 4;
+
+impl ChipName {
+    /// The name of the hwmon chip in `hwmon_dir` (`name` = `prefix`), from the bus
+    /// of the device it belongs to.
+    pub(crate) fn of(hwmon_dir: &Path, prefix: &str) -> Self {
+        let mut chip = Self { prefix: prefix.to_string(), bus: None, nr: None, addr: None };
+        let Ok(mut device) = std::fs::canonicalize(hwmon_dir.join("device")) else {
+            // No parent device: a virtual chip (acpitz on some kernels, thermal zones)
+            chip.bus = Some("virtual".to_string());
+            chip.addr = Some(0);
+            return chip;
+        };
+        for _ in 0..MAX_BUS_DEPTH {
+            let subsystem = std::fs::read_link(device.join("subsystem")).ok();
+            let subsystem = subsystem.as_deref().and_then(Path::file_name).and_then(|s| s.to_str());
+            let name = device.file_name().and_then(|s| s.to_str()).unwrap_or_default();
+            let bus = match subsystem {
+                Some("platform" | "isa") => {
+                    // nct6775.656: the ISA address in decimal; coretemp.0 an instance
+                    let addr = name.rsplit_once('.').and_then(|(_, n)| n.parse().ok()).unwrap_or(0);
+                    Some(("isa", None, Some(addr)))
+                }
+                Some("pci") => pci_address(name).map(|addr| ("pci", None, Some(addr))),
+                Some("i2c") => name
+                    .split_once('-')
+                    .and_then(|(nr, addr)| Some(("i2c", Some(nr.parse().ok()?), Some(u32::from_str_radix(addr, 16).ok()?)))),
+                Some("scsi") => {
+                    let parts: Vec<u32> = name.split(':').filter_map(|p| p.parse().ok()).collect();
+                    (parts.len() == 4).then(|| ("scsi", Some(parts[0]), Some(parts[3])))
+                }
+                Some("acpi") => Some(("acpi", None, Some(0))),
+                _ => None,
+            };
+            if let Some((bus, nr, addr)) = bus {
+                chip.bus = Some(bus.to_string());
+                chip.nr = nr;
+                chip.addr = addr;
+                break;
+            }
+            if !device.pop() {
+                break;
+            }
+        }
+        chip
+    }
+}
+
+/// `0000:00:18.3` -> 0x00c3: (domain << 16) + (bus << 8) + (device << 3) + function.
+fn pci_address(name: &str) -> Option<u32> {
+    let (domain, rest) = name.split_once(':')?;
+    let (bus, rest) = rest.split_once(':')?;
+    let (device, function) = rest.split_once('.')?;
+    let [domain, bus, device, function] = [domain, bus, device, function].map(|part| u32::from_str_radix(part, 16).ok());
+    Some((domain? << 16) + (bus? << 8) + (device? << 3) + function?)
+}
+
+/// A `chip` statement name; `*` matches anything (a whole bus, number or address,
+/// or any part of the prefix).
+#[derive(Debug, Clone, PartialEq)]
+struct ChipPattern {
+    prefix: String,
+    bus: Option<String>,
+    nr: Option<u32>,
+    addr: Option<u32>,
+}
+
+impl ChipPattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid chip name '{}'", pattern);
+        fn wildcard(part: Option<&str>) -> Option<&str> {
+            part.filter(|p| *p != "*")
+        }
+        let mut parts = pattern.split('-');
+        let prefix = parts.next().filter(|p| !p.is_empty()).ok_or_else(invalid)?.to_string();
+        let bus = wildcard(parts.next()).map(str::to_string);
+        let nr = match &bus {
+            Some(bus) if NUMBERED_BUSES.contains(&bus.as_str()) => wildcard(parts.next()).map(|nr| nr.parse().map_err(|_| invalid())).transpose()?,
+            _ => None,
+        };
+        let addr = wildcard(parts.next()).map(|addr| u32::from_str_radix(addr, 16).map_err(|_| invalid())).transpose()?;
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+        Ok(Self { prefix, bus, nr, addr })
+    }
+
+    fn matches(&self, chip: &ChipName) -> bool {
+        fn part<T: PartialEq>(pattern: &Option<T>, value: &Option<T>) -> bool {
+            pattern.is_none() || pattern == value
+        }
+        glob_match(&self.prefix, &chip.prefix) && part(&self.bus, &chip.bus) && part(&self.nr, &chip.nr) && part(&self.addr, &chip.addr)
+    }
+}
+
+/// `*` matches any run of characters.
+fn glob_match(pattern: &str, text: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == text,
+        Some((head, tail)) => {
+            let Some(rest) = text.strip_prefix(head) else {
+                return false;
+            };
+            (0..=rest.len()).filter(|&i| rest.is_char_boundary(i)).any(|i| glob_match(tail, &rest[i..]))
+        }
+    }
+}
+
+/// A `compute` expression: arithmetic (`+ - * /`, parentheses, unary minus) on the
+/// raw value `@` and constants. libsensors' `^` / `` ` `` (exp, ln) and references to
+/// other inputs aren't supported.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Compute(Expr);
+
+#[derive(Debug, Clone, PartialEq)]
+enum Expr {
+    Value,
+    Number(f64),
+    Neg(Box<Expr>),
+    Binary(Box<Expr>, char, Box<Expr>),
+}
+
+impl Compute {
+    fn parse(text: &str) -> Result<Self, String> {
+        let mut parser = ExprParser { chars: text.chars().filter(|c| !c.is_whitespace()).collect(), pos: 0 };
+        let expr = parser.sum()?;
+        match parser.peek() {
+            None => Ok(Self(expr)),
+            Some(c) => Err(format!("unexpected '{}' in expression '{}'", c, text.trim())),
+        }
+    }
+
+    /// The corrected value of a reading (or limit) `value`.
+    pub(crate) fn apply(&self, value: f64) -> f64 {
+        fn eval(expr: &Expr, value: f64) -> f64 {
+            match expr {
+                Expr::Value => value,
+                Expr::Number(n) => *n,
+                Expr::Neg(e) => -eval(e, value),
+                Expr::Binary(a, op, b) => {
+                    let (a, b) = (eval(a, value), eval(b, value));
+                    match op {
+                        '+' => a + b,
+                        '-' => a - b,
+                        '*' => a * b,
+                        _ => a / b,
+                    }
+                }
+            }
+        }
+        eval(&self.0, value)
+    }
+}
+
+struct ExprParser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl ExprParser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn sum(&mut self) -> Result<Expr, String> {
+        let mut expr = self.product()?;
+        while let Some(op @ ('+' | '-')) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.product()?));
+        }
+        Ok(expr)
+    }
+
+    fn product(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(op @ ('*' | '/')) = self.peek() {
+            self.pos += 1;
+            expr = Expr::Binary(Box::new(expr), op, Box::new(self.factor()?));
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        let Some(c) = self.peek() else {
+            return Err("expression ends early".to_string());
+        };
+        self.pos += 1;
+        match c {
+            '@' => Ok(Expr::Value),
+            '-' => Ok(Expr::Neg(Box::new(self.factor()?))),
+            '(' => {
+                let expr = self.sum()?;
+                match self.peek() {
+                    Some(')') => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            c if c.is_ascii_digit() || c == '.' => {
+                let start = self.pos - 1;
+                while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+                    self.pos += 1;
+                }
+                let number: String = self.chars[start..self.pos].iter().collect();
+                number.parse().map(Expr::Number).map_err(|_| format!("invalid number '{}'", number))
+            }
+            '^' | '`' => Err("exp/ln ('^', '`') not supported".to_string()),
+            c if c.is_ascii_alphabetic() => Err("references to other inputs not supported".to_string()),
+            c => Err(format!("unexpected '{}'", c)),
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl super::monitor::LinuxHardwareMonitor {
+    /// The libsensors name of the chip in `hwmon_dir`, when the configuration is in use.
+    pub(crate) fn lm_sensors_chip(&self, hwmon_dir: &Path, chip_name: &str) -> Option<ChipName> {
+        self.lm_sensors.as_ref().map(|_| ChipName::of(hwmon_dir, chip_name))
+    }
+
+    /// The configuration of `feature` (temp1, fan2) of `chip`; nothing when not in use.
+    pub(crate) fn lm_sensors_feature(&self, chip: Option<&ChipName>, feature: &str) -> FeatureConfig {
+        match (&self.lm_sensors, chip) {
+            (Some(config), Some(chip)) => config.feature(chip, feature),
+            _ => FeatureConfig::default(),
+        }
+    }
+
+    /// The `compute` of a temperature input (`.../hwmonN/temp1_input`).
+    pub(crate) fn lm_sensors_compute(&self, input: &Path, chip_name: Option<&str>) -> Option<Compute> {
+        let hwmon_dir = input.parent()?;
+        let feature = input.file_name()?.to_str()?.strip_suffix("_input")?;
+        let chip = self.lm_sensors_chip(hwmon_dir, chip_name?)?;
+        self.lm_sensors_feature(Some(&chip), feature).compute
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm, Temp};
+    use crate::hardware::HardwareMonitor;
+
+    /// Quoting and layout quirks seen in distribution and board-vendor files.
+    const FIXTURE: &str = r#"
+# sensors3.conf excerpt
+label temp9 "Orphan"
+chip "nct6798-*"
+    label temp1 "SYSTIN"          # comment after a statement
+    label temp2 "CPU \"Socket\""
+    label temp7 "Water # In"
+    ignore temp3
+    label fan2 "Pump"
+    compute in0 @*2, @/2
+    compute temp4 (@ - 4) * 1.5 ,\
+                  @/1.5 + 4
+    set temp1_max 80
+
+chip "k10temp-pci-00c3" "zenpower-*"
+    label "temp1" Tctl-Offset
+    compute temp1 -@ + 100, 100 - @
+
+chip "*-isa-0a40"
+    label temp1 "Motherboard"
+
+chip "nct6798-*"
+    ignore temp8
+    compute temp5 ^@, `@
+    bogus temp5
+    label temp6 "Unterminated
+"#;
+
+    fn chip(prefix: &str, bus: Option<&str>, addr: Option<u32>) -> ChipName {
+        ChipName { prefix: prefix.to_string(), bus: bus.map(str::to_string), nr: None, addr }
+    }
+
+    #[test]
+    fn quoting_comments_and_continuations() {
+        let (blocks, errors) = parse(FIXTURE);
+        let config = LmSensorsConfig { blocks };
+        let nct = chip("nct6798", Some("isa"), Some(0x290));
+        let label = |chip: &ChipName, feature: &str| config.feature(chip, feature).label;
+
+        assert_eq!(label(&nct, "temp1").as_deref(), Some("SYSTIN"));
+        assert_eq!(label(&nct, "temp2").as_deref(), Some("CPU \"Socket\""));
+        assert_eq!(label(&nct, "temp7").as_deref(), Some("Water # In"));
+        assert_eq!(label(&nct, "fan2").as_deref(), Some("Pump"));
+        assert!(config.feature(&nct, "temp3").ignore && config.feature(&nct, "temp8").ignore);
+        assert_eq!(config.feature(&nct, "temp5"), FeatureConfig::default());
+
+        // The backslash joins the inverse onto the same statement; only the first expression counts
+        let compute = config.feature(&nct, "temp4").compute.unwrap();
+        assert_eq!(compute.apply(44.0), 60.0);
+        assert_eq!(config.feature(&nct, "in0").compute.unwrap().apply(1.65), 3.3);
+
+        let k10 = chip("k10temp", Some("pci"), Some(0xc3));
+        assert_eq!(label(&k10, "temp1").as_deref(), Some("Tctl-Offset"));
+        assert_eq!(config.feature(&k10, "temp1").compute.unwrap().apply(30.0), 70.0);
+        assert_eq!(label(&chip("zenpower", Some("pci"), Some(0xc3)), "temp1").as_deref(), Some("Tctl-Offset"));
+        assert_eq!(label(&chip("k10temp", Some("pci"), Some(0xc4)), "temp1"), None);
+
+        // Later blocks win
+        assert_eq!(label(&chip("it8688", Some("isa"), Some(0xa40)), "temp1").as_deref(), Some("Motherboard"));
+
+        let lines: Vec<usize> = errors.iter().map(|(line, _)| *line).collect();
+        assert_eq!(lines, [3, 24, 25, 26], "{:?}", errors);
+        assert!(errors[0].1.contains("before the first chip"));
+        assert!(errors[1].1.contains("not supported"));
+        assert!(errors[2].1.contains("unknown statement 'bogus'"));
+        assert!(errors[3].1.contains("unterminated quote"));
+    }
+
+    #[test]
+    fn chip_patterns() {
+        let isa = chip("nct6798", Some("isa"), Some(0x290));
+        let matches = |pattern: &str, chip: &ChipName| ChipPattern::parse(pattern).unwrap().matches(chip);
+        assert!(matches("nct6798-*", &isa));
+        assert!(matches("nct6798-isa-0290", &isa));
+        assert!(matches("nct67*-isa-*", &isa));
+        assert!(matches("*-*", &isa));
+        assert!(!matches("nct6798-isa-0a40", &isa));
+        assert!(!matches("nct6798-pci-*", &isa));
+        assert!(!matches("nct6775-*", &isa));
+
+        let i2c = ChipName { prefix: "lm75".to_string(), bus: Some("i2c".to_string()), nr: Some(1), addr: Some(0x48) };
+        assert!(matches("lm75-i2c-1-48", &i2c));
+        assert!(matches("lm75-i2c-*-48", &i2c));
+        assert!(!matches("lm75-i2c-0-48", &i2c));
+
+        // A chip whose bus is unknown only matches patterns that don't name one
+        let unknown = chip("nvme", None, None);
+        assert!(matches("nvme-*", &unknown));
+        assert!(!matches("nvme-pci-*", &unknown));
+
+        assert!(ChipPattern::parse("lm75-i2c-x-48").is_err());
+        assert!(ChipPattern::parse("-isa-0290").is_err());
+    }
+
+    #[test]
+    fn chip_names_from_the_device_bus() {
+        let sysfs = FakeSysfs::new("lm-sensors-chip-names")
+            .chip(Chip::new("nct6798").device("nct6775.656"))
+            .chip(Chip::new("k10temp").device("pci0000:00/0000:00:18.3"))
+            .chip(Chip::new("nvme").device("pci0000:00/0000:01:00.0/nvme/nvme0"))
+            .chip(Chip::new("lm75").device("i2c-1/1-0048"))
+            .chip(Chip::new("acpitz"))
+            .symlink("devices/nct6775.656/subsystem", "bus/platform")
+            .symlink("devices/pci0000:00/0000:00:18.3/subsystem", "bus/pci")
+            .symlink("devices/pci0000:00/0000:01:00.0/subsystem", "bus/pci")
+            .symlink("devices/pci0000:00/0000:01:00.0/nvme/nvme0/subsystem", "class/nvme")
+            .symlink("devices/i2c-1/1-0048/subsystem", "bus/i2c");
+        let name = |index: usize, prefix: &str| ChipName::of(&sysfs.chip_dir(index), prefix);
+
+        assert_eq!(name(0, "nct6798"), chip("nct6798", Some("isa"), Some(0x290)));
+        assert_eq!(name(1, "k10temp"), chip("k10temp", Some("pci"), Some(0xc3)));
+        assert_eq!(name(2, "nvme"), chip("nvme", Some("pci"), Some(0x100)));
+        assert_eq!(name(3, "lm75"), ChipName { prefix: "lm75".to_string(), bus: Some("i2c".to_string()), nr: Some(1), addr: Some(0x48) });
+        assert_eq!(name(4, "acpitz"), chip("acpitz", Some("virtual"), Some(0)));
+    }
+
+    #[tokio::test]
+    async fn labels_ignores_and_computes_apply_to_discovery() {
+        let sysfs = FakeSysfs::new("lm-sensors-discovery")
+            .chip(
+                Chip::new("nct6798")
+                    .device("nct6775.656")
+                    .temp(Temp::new(1, 30_000).label("SYSTIN"))
+                    .temp(Temp::new(2, 35_000).label("AUXTIN0").max(50_000))
+                    .temp(Temp::new(3, 127_000).label("AUXTIN1"))
+                    .fan(1, 800)
+                    .pwm(Pwm::new(1, 128))
+                    .fan(2, 1_300)
+                    .pwm(Pwm::new(2, 200))
+                    .fan(3, 0)
+                    .pwm(Pwm::new(3, 0)),
+            )
+            .symlink("devices/nct6775.656/subsystem", "bus/platform")
+            .file("etc/sensors3.conf", "chip \"nct6798-isa-*\"\n    label temp2 \"Water In\"\n    ignore temp3\n")
+            .file("etc/sensors.d/board.conf", "chip \"nct6798-*\"\n    compute temp2 @ - 2, @ + 2\n    label fan2 \"Pump\"\n    compute fan2 @ * 2, @ / 2\n    ignore fan3\n")
+            .file("etc/sensors.d/.hidden.conf", "chip \"*-*\"\n    ignore temp1\n");
+        let mut monitor = sysfs.monitor();
+        monitor.lm_sensors = Some(LmSensorsConfig::load(&sysfs.root().join("etc")));
+
+        let sensors = monitor.discover_sensors().await.unwrap();
+        let naming: Vec<_> = sensors.iter().map(|s| (&*s.id, &*s.name, s.temperature, s.max_temp)).collect();
+        assert_eq!(naming, [
+            ("nct6798_auxtin0", "nct6798 Water In", 33.0, Some(48.0)),
+            ("nct6798_systin", "nct6798 SYSTIN", 30.0, None),
+        ]);
+        assert_eq!(sensors[0].display_name.as_deref(), Some("Motherboard Nuvoton Water In"));
+        // The cached fast path applies compute too
+        let cached = monitor.discover_sensors().await.unwrap();
+        assert!(monitor.last_discovery_from_cache().await);
+        assert_eq!(cached[0].temperature, 33.0);
+
+        let fans = monitor.discover_fans().await.unwrap();
+        let fans: Vec<_> = fans.iter().map(|f| (f.id.as_str(), f.name.as_str(), f.rpm)).collect();
+        assert_eq!(fans, [("nct6798_fan_1", "nct6798 Fan 1", Some(800)), ("nct6798_fan_2", "nct6798 Pump", Some(2_600))]);
+    }
+}
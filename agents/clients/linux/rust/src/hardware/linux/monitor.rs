@@ -1,27 +1,314 @@
 //! Linux hardware monitor: core struct, constructors, trait impl, and utility methods.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 
 use anyhow::{Context, Result};
 use async_trait::async_trait;
 use tokio::sync::RwLock;
-use tracing::{debug, error, warn};
+use tracing::{debug, error, info, warn};
 
-use crate::config::types::HardwareSettings;
+use crate::app::log_throttle::LogThrottle;
+use crate::config::types::{HardwareSettings, IpmiBridgeDedup, SemiPassive, TemperatureSmoothing, VirtualSensor};
+use crate::hardware::smoothing::SensorSmoother;
+use crate::hardware::virtual_sensors::VirtualSensors;
 use crate::hardware::types::*;
 use crate::hardware::HardwareMonitor;
+use super::alarms::alarm_paths_for_input;
+use super::access::{ChipLocks, SingleFlight};
+use super::attr;
+use super::sensors::LastReadings;
+use super::conflicts::{FANCONTROL_CONFIG, PROC_ROOT};
+use super::discovery_cache::{discovery_cache_path, DiscoveryCache};
+use super::fan_modes::{fan_modes_path, FanModeStore, SavedMode};
+use super::fan_presence::PresenceHistory;
+use super::lm_sensors::{Compute, LmSensorsConfig, SENSORS_CONFIG_DIR};
+use super::firmware::FirmwareSource;
+use super::storage_health::StorageHealthSource;
+use super::hotplug::HwmonUevents;
 use super::nvidia::NvmlSource;
 
+/// Upper bound on hwmon chips scanned in parallel during full discovery.
+pub(crate) const MAX_CONCURRENT_CHIPS: usize = 8;
+
+/// Write a sysfs attribute through its kept-open handle (pwrite at offset 0), or by
+/// path when there is none. With `dry_run` only logs the write.
+pub(crate) async fn write_attr(dry_run: bool, fd: Option<&Arc<std::fs::File>>, path: &Path, value: &str) -> Result<()> {
+    if dry_run {
+        info!("[DRY RUN] Would write {} to {:?}", value, path);
+        return Ok(());
+    }
+    let Some(file) = fd else {
+        return tokio::fs::write(path, value).await.context(format!("Failed to write to file: {:?}", path));
+    };
+    let file = Arc::clone(file);
+    let value = value.to_string();
+    tokio::task::spawn_blocking(move || {
+        use std::os::unix::fs::FileExt;
+        file.write_at(value.as_bytes(), 0)
+    })
+    .await?
+    .with_context(|| format!("Failed to write to file: {:?}", path))?;
+    Ok(())
+}
+
+/// PWM register value (0-255) for a speed in percent. Rounded, like `percent_of`, so
+/// a percent survives the trip through the register: truncating both ways turned 50%
+/// into 127 and back into 49%, and the next write moved the fan again.
+pub(crate) fn percent_to_pwm(speed: u8) -> u8 {
+    (speed.min(100) as f32 / 100.0 * 255.0).round() as u8
+}
+
+/// A per-fan setting keyed by hwmon fan id. Keys may carry a composite prefix.
+fn fan_setting<'a, T>(settings: &'a BTreeMap<String, T>, fan_id: &str) -> Option<&'a T> {
+    settings.iter()
+        .find(|(key, _)| *key == fan_id || key.split_once(':').is_some_and(|(_, id)| id == fan_id))
+        .map(|(_, setting)| setting)
+}
+
+/// A raw `setFanSpeed` value held to the fan's `stop_below_percent` minimum, as
+/// `SemiPassive::effective_speed` does for percentages.
+pub(crate) fn effective_pwm(semi_passive: Option<SemiPassive>, value: u8) -> u8 {
+    let Some(semi_passive) = semi_passive else { return value };
+    let minimum = percent_to_pwm(semi_passive.stop_below_percent);
+    if value >= minimum {
+        value
+    } else if semi_passive.allow_stop {
+        0
+    } else {
+        minimum
+    }
+}
+
+/// How a hwmon fan's speed is set.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum ControlMethod {
+    /// `pwmN`: duty cycle 0-255
+    Pwm,
+    /// `fanN_target` without `pwmN` (dell_smm, some EC drivers): the chip regulates
+    /// to an RPM. Percent maps onto `min_rpm..=max_rpm`, from `fanN_min`/`fanN_max`
+    /// or, without `fanN_max`, the highest RPM seen so far.
+    TargetRpm { min_rpm: u32, max_rpm: u32 },
+}
+
+impl ControlMethod {
+    /// `control_method` as reported to the backend
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            ControlMethod::Pwm => "pwm",
+            ControlMethod::TargetRpm { .. } => "target_rpm",
+        }
+    }
+
+    /// Value to write to the control attribute for a speed in percent. 0% stops
+    /// the fan either way.
+    pub(crate) fn value_for(&self, speed: u8) -> u32 {
+        match *self {
+            ControlMethod::Pwm => percent_to_pwm(speed) as u32,
+            ControlMethod::TargetRpm { .. } if speed == 0 => 0,
+            ControlMethod::TargetRpm { min_rpm, max_rpm } => {
+                min_rpm + max_rpm.saturating_sub(min_rpm) * speed.min(100) as u32 / 100
+            }
+        }
+    }
+
+    /// Speed in percent for a value read back from the control attribute.
+    pub(crate) fn percent_of(&self, value: u32) -> u8 {
+        match *self {
+            ControlMethod::Pwm => (value.min(255) as f32 / 255.0 * 100.0).round() as u8,
+            ControlMethod::TargetRpm { min_rpm, max_rpm } if value == 0 || max_rpm <= min_rpm => 0,
+            ControlMethod::TargetRpm { min_rpm, max_rpm } => {
+                ((value.clamp(min_rpm, max_rpm) - min_rpm) * 100 / (max_rpm - min_rpm)) as u8
+            }
+        }
+    }
+}
+
+/// What a fan write asks for.
+#[derive(Debug, Clone, Copy)]
+enum SpeedTarget {
+    /// 0-100%, mapped through the fan's `ControlMethod`
+    Percent(u8),
+    /// `pwmN` register value, written as is (`raw: true` on `setFanSpeed`)
+    Pwm(u8),
+}
+
+/// Open a sysfs attribute to keep for repeated pread/pwrite.
+pub(crate) fn open_attr(path: &Path, write: bool) -> Option<Arc<std::fs::File>> {
+    std::fs::OpenOptions::new().read(true).write(write).open(path).ok().map(Arc::new)
+}
+
+/// The device behind a sysfs file went away (unbind, hot-unplug): kept handles
+/// report ENODEV/ENXIO/ESTALE, paths ENOENT.
+pub(crate) fn is_device_gone(e: &anyhow::Error) -> bool {
+    attr::io_error(e)
+        .and_then(|io| io.raw_os_error())
+        .is_some_and(attr::is_gone_code)
+}
+
 #[cfg(target_os = "linux")]
 pub(crate) struct FanInfo {
+    /// Control attribute: `pwmN`, or `fanN_target` for `ControlMethod::TargetRpm`
     pub(crate) pwm_path: PathBuf,
-    pub(crate) rpm_path: PathBuf,
+    pub(crate) control: ControlMethod,
+    /// `fanN_input` tach file; `None` for PWM-only fans.
+    pub(crate) rpm_path: Option<PathBuf>,
     pub(crate) pwm_enable_path: Option<PathBuf>,
     pub(crate) chip_name: String,
-    pub(crate) last_pwm_value: Arc<RwLock<Option<u8>>>,
+    /// This process may write `pwm_path`: probed when `pwm_fd` is (re)opened, and kept
+    /// while that read/write handle stays valid
+    pub(crate) writable: bool,
+    /// A "no write access" warning was already logged for this fan
+    pub(crate) denied_warned: Arc<std::sync::atomic::AtomicBool>,
+    /// Kept-open handles for `pwm_path` (read/write when `writable`), `pwm_enable_path`
+    /// and `rpm_path`. Control keeps working after a privilege drop, and each cycle's
+    /// reads/writes skip an open()/close() pair.
+    pub(crate) pwm_fd: Option<Arc<std::fs::File>>,
+    pub(crate) enable_fd: Option<Arc<std::fs::File>>,
+    pub(crate) rpm_fd: Option<Arc<std::fs::File>>,
+    /// Last value written to `pwm_path` (PWM duty or target RPM)
+    pub(crate) last_pwm_value: Arc<RwLock<Option<u32>>>,
     pub(crate) last_write_time: Arc<RwLock<std::time::Instant>>,
+    /// PWM write failure counters; kept across rediscovery
+    pub(crate) write_failures: Arc<std::sync::Mutex<WriteFailures>>,
+    /// Spin-up boost in progress (`hardware.semi_passive`); kept across rediscovery
+    pub(crate) spinup: Arc<std::sync::Mutex<Option<SpinUp>>>,
+    /// Rewrites of a drifted control register; kept across rediscovery
+    pub(crate) drift: Arc<std::sync::Mutex<Drift>>,
+    /// `pwmN_enable` value other than manual set by `setFanMode`: speeds are refused
+    /// until it is set back to manual; kept across rediscovery
+    pub(crate) pinned_mode: Arc<std::sync::Mutex<Option<u8>>>,
+    /// Tach history behind `FanPresence`; kept across rediscovery
+    pub(crate) presence: Arc<std::sync::Mutex<PresenceHistory>>,
+    /// Origin of the last speed applied, reported as `Fan::last_set_by`; kept across
+    /// rediscovery
+    pub(crate) last_set_by: Arc<std::sync::Mutex<Option<FanSetBy>>>,
+    /// Applied to `fanN_input` readings (`hardware.fan_pulses` without `fanN_pulses`)
+    pub(crate) pulse_correction: Option<PulseCorrection>,
+}
+
+/// Tach pulses per revolution hwmon drivers without `fanN_pulses` count.
+pub(crate) const DEFAULT_FAN_PULSES: u8 = 2;
+
+/// A tach miscounted by the driver: it counts `counted` pulses per revolution, the
+/// fan gives `actual`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct PulseCorrection {
+    pub(crate) counted: u8,
+    pub(crate) actual: u8,
+}
+
+impl PulseCorrection {
+    pub(crate) fn apply(self, rpm: u32) -> u32 {
+        rpm * u32::from(self.counted) / u32::from(self.actual)
+    }
+}
+
+/// A stopped fan being kicked: it runs at `percent` until `until`, then a settle task
+/// writes `target`, the latest speed requested meanwhile.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct SpinUp {
+    pub(crate) started: std::time::Instant,
+    pub(crate) until: std::time::Instant,
+    pub(crate) percent: u8,
+    pub(crate) target: u8,
+}
+
+/// Largest difference between a PWM value written and read back that still counts
+/// as the write sticking (chips with fewer than 8 bits of duty cycle round it).
+pub(crate) const PWM_VERIFY_TOLERANCE: u32 = 8;
+
+/// Control-degraded reasons
+pub(crate) const WRITES_FAIL: &str = "PWM writes fail";
+pub(crate) const WRITES_IGNORED: &str = "firmware rejects writes";
+
+/// PWM write failures of one fan. After `hardware.pwm_failure_threshold` consecutive
+/// failures (e.g. a broken EC answering EIO), or as many writes in a row that don't
+/// stick (firmware locking the header), the fan is control-degraded: reported as
+/// monitoring-only with status "error" and no longer written, until `retryFanControl`.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default)]
+pub(crate) struct WriteFailures {
+    pub(crate) consecutive: u32,
+    pub(crate) total: u64,
+    /// Consecutive writes that succeeded but read back as something else
+    pub(crate) write_ignored: u32,
+    pub(crate) degraded: bool,
+    /// Why the fan is degraded: `WRITES_FAIL` or `WRITES_IGNORED`
+    pub(crate) reason: Option<&'static str>,
+}
+
+#[cfg(target_os = "linux")]
+impl WriteFailures {
+    /// Count a failure. True when this one made the fan degraded (threshold 0 = never).
+    pub(crate) fn failed(&mut self, threshold: u32) -> bool {
+        self.consecutive = self.consecutive.saturating_add(1);
+        self.total = self.total.saturating_add(1);
+        self.degrade_at(self.consecutive, threshold, WRITES_FAIL)
+    }
+
+    /// Count a write that didn't stick. True when this one made the fan degraded.
+    pub(crate) fn ignored(&mut self, threshold: u32) -> bool {
+        self.write_ignored = self.write_ignored.saturating_add(1);
+        self.degrade_at(self.write_ignored, threshold, WRITES_IGNORED)
+    }
+
+    fn degrade_at(&mut self, count: u32, threshold: u32, reason: &'static str) -> bool {
+        let crossed = threshold > 0 && !self.degraded && count >= threshold;
+        if crossed {
+            self.degraded = true;
+            self.reason = Some(reason);
+        }
+        crossed
+    }
+
+    /// Count a successful write. True if the fan was degraded until now.
+    pub(crate) fn succeeded(&mut self) -> bool {
+        self.consecutive = 0;
+        self.write_ignored = 0;
+        self.reason = None;
+        std::mem::take(&mut self.degraded)
+    }
+}
+
+/// What to do about a fan whose control register drifted from the commanded value.
+#[cfg(target_os = "linux")]
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum DriftAction {
+    Reassert,
+    /// `hardware.drift_reassert_limit` reached: log once, then only report
+    GiveUp,
+    Report,
+}
+
+/// Rewrites of one fan's drifted control register, counted per commanded value: a
+/// board that clamps or an EC that overrides the value is only fought
+/// `hardware.drift_reassert_limit` times, until the agent commands something else.
+#[cfg(target_os = "linux")]
+#[derive(Debug, Default)]
+pub(crate) struct Drift {
+    commanded: Option<u32>,
+    reasserts: u32,
+}
+
+#[cfg(target_os = "linux")]
+impl Drift {
+    /// The register was found away from `commanded` (again).
+    pub(crate) fn observed(&mut self, commanded: u32, limit: u32) -> DriftAction {
+        if self.commanded != Some(commanded) {
+            self.commanded = Some(commanded);
+            self.reasserts = 0;
+        }
+        let action = match self.reasserts.cmp(&limit) {
+            std::cmp::Ordering::Less => DriftAction::Reassert,
+            std::cmp::Ordering::Equal => DriftAction::GiveUp,
+            std::cmp::Ordering::Greater => return DriftAction::Report,
+        };
+        self.reasserts += 1;
+        action
+    }
 }
 
 /// Cached sensor metadata and path for efficient reading
@@ -29,24 +316,38 @@ pub(crate) struct FanInfo {
 #[derive(Clone)]
 pub(crate) struct SensorInfo {
     pub(crate) temp_input_path: PathBuf,
-    pub(crate) id: String,
-    pub(crate) name: String,
-    pub(crate) sensor_type: String,
+    /// Kept-open `temp_input_path`; reopened on rediscovery
+    pub(crate) temp_fd: Option<Arc<std::fs::File>>,
+    pub(crate) id: Arc<str>,
+    pub(crate) name: Arc<str>,
+    pub(crate) display_name: Option<Arc<str>>,
+    pub(crate) sensor_type: Arc<str>,
     pub(crate) max_temp: Option<f64>,
     pub(crate) crit_temp: Option<f64>,
-    pub(crate) chip: Option<String>,
-    pub(crate) hardware_name: Option<String>,
-    pub(crate) source: Option<String>,
+    pub(crate) chip: Option<Arc<str>>,
+    pub(crate) hardware_name: Option<Arc<str>>,
+    pub(crate) source: Option<Arc<str>>,
+    /// Existing hwmon alarm files for this channel, as (flag, path)
+    pub(crate) alarm_paths: Vec<(&'static str, PathBuf)>,
+    /// lm-sensors `compute` for the readings
+    pub(crate) compute: Option<Compute>,
 }
 
 #[cfg(target_os = "linux")]
 pub struct LinuxHardwareMonitor {
+    /// sysfs mount point (`/sys` in production, a fixture tree in tests)
+    pub(crate) sysfs_root: PathBuf,
     pub(crate) hwmon_base: PathBuf,
-    #[allow(dead_code)]
     pub(crate) thermal_base: PathBuf,
     pub(crate) discovered_fans: Arc<RwLock<HashMap<String, FanInfo>>>,
-    pub(crate) discovered_sensors: Arc<RwLock<HashMap<String, SensorInfo>>>,
+    pub(crate) discovered_sensors: Arc<RwLock<HashMap<Arc<str>, SensorInfo>>>,
     pub(crate) cached_hwmon_count: Arc<RwLock<usize>>,
+    /// Kernel uevent listener for hwmon hot-plug; `None` = count hwmon directories
+    pub(crate) hwmon_uevents: Option<HwmonUevents>,
+    /// Full rediscovery on the next sensor discovery
+    pub(crate) sensors_dirty: Arc<std::sync::atomic::AtomicBool>,
+    /// Sensor discoveries since start
+    pub(crate) discovery_cycles: Arc<std::sync::atomic::AtomicU64>,
     pub(crate) last_discovery_from_cache: Arc<RwLock<bool>>,
     pub(crate) system_info: Arc<RwLock<sysinfo::System>>,
     pub(crate) system_info_cache: Arc<RwLock<Option<(SystemHealth, std::time::Instant)>>>,
@@ -55,11 +356,81 @@ pub struct LinuxHardwareMonitor {
     pub(crate) storage_cache: Arc<RwLock<HashMap<String, String>>>,
     /// Optional NVIDIA GPU source (NVML). `None` on non-NVIDIA hosts.
     pub(crate) nvml: Option<NvmlSource>,
+    /// Optional Raspberry Pi firmware source (vcgencmd). `None` on other hosts.
+    pub(crate) firmware: Option<FirmwareSource>,
+    /// Optional SMART drive health (`hardware.storage_health`, smartctl)
+    pub(crate) storage_health: Option<Arc<StorageHealthSource>>,
+    /// Log fan writes instead of performing them
+    pub(crate) dry_run: bool,
+    /// Consecutive PWM write failures before a fan is control-degraded (0 = never)
+    pub(crate) pwm_failure_threshold: u32,
+    /// `hardware.skip_write_verify`: chips whose PWM writes aren't read back
+    pub(crate) skip_write_verify: Vec<String>,
+    /// Rewrites of a drifted control register before only reporting it (0 = none)
+    pub(crate) drift_reassert_limit: u32,
+    /// `hardware.disabled_chips`: hwmon chip names left out of discovery
+    pub(crate) disabled_chips: Arc<std::sync::RwLock<Vec<String>>>,
+    /// `hardware.ipmi_bridge_dedup` (see ipmi_bridge.rs)
+    pub(crate) ipmi_bridge_dedup: IpmiBridgeDedup,
+    /// `hardware.semi_passive`: per-fan minimum / zero-RPM stop and spin-up boost
+    pub(crate) semi_passive: BTreeMap<String, SemiPassive>,
+    /// `hardware.fan_pulses`: tach pulses per revolution of miscounted fans
+    pub(crate) fan_pulses: BTreeMap<String, u8>,
+    /// `temperature_smoothing` state; reset whenever the sensor cache is rebuilt
+    pub(crate) smoother: std::sync::Mutex<SensorSmoother>,
+    /// `hardware.virtual_sensors`, appended to every sensor discovery
+    pub(crate) virtual_sensors: std::sync::Mutex<VirtualSensors>,
+    /// Last good temperature per sensor, re-sent through transient read failures
+    pub(crate) last_readings: std::sync::Mutex<LastReadings>,
+    /// Repeated fan read/write errors, logged once and then summarized
+    pub(crate) log_throttle: LogThrottle,
+    /// Modes fans were in before the agent took them over, for `--uninstall` and
+    /// the shutdown cleanup
+    pub(crate) fan_modes: Option<FanModeStore>,
+    /// discovery-cache.json, loaded on the first discovery and saved after each full one
+    pub(crate) discovery_cache: Option<PathBuf>,
+    /// A full discovery is owed to `reconcile_discovery`; locked while it runs
+    pub(crate) reconcile_pending: tokio::sync::Mutex<bool>,
+    /// Overlapping sensor discoveries share one scan
+    pub(crate) sensor_scan: SingleFlight<Vec<Sensor>>,
+    /// `hardware.serialize_chip_access`: one attribute access at a time per chip
+    pub(crate) chip_locks: ChipLocks,
+    /// `hardware.use_lm_sensors_config`: labels, ignores and computes from lm-sensors
+    pub(crate) lm_sensors: Option<LmSensorsConfig>,
 }
 
 #[cfg(target_os = "linux")]
 impl LinuxHardwareMonitor {
-    pub fn new(_config: HardwareSettings) -> Self {
+    pub fn new(config: HardwareSettings) -> Self {
+        let dry_run = config.dry_run;
+        let use_lm_sensors_config = config.use_lm_sensors_config;
+        let mut monitor = Self::with_sysfs_root(config, "/sys");
+        // Uevents describe the live /sys only, so fixture trees keep counting
+        monitor.hwmon_uevents = match HwmonUevents::open() {
+            Ok(uevents) => Some(uevents),
+            Err(e) => {
+                debug!("hwmon uevents unavailable ({}); counting hwmon directories every cycle", e);
+                None
+            }
+        };
+        if use_lm_sensors_config {
+            monitor.lm_sensors = Some(LmSensorsConfig::load(Path::new(SENSORS_CONFIG_DIR)));
+        }
+        if !dry_run {
+            if let Some(path) = fan_modes_path() {
+                crate::daemon::shutdown::update(|plan| plan.set_fan_modes_file(&path));
+                monitor.fan_modes = Some(FanModeStore::open(path));
+            }
+        }
+        monitor.discovery_cache = discovery_cache_path();
+        monitor
+    }
+
+    /// Build a monitor that reads hwmon, thermal, block and DMI data under `sysfs_root`
+    /// instead of `/sys`. Used by tests to point discovery at a fake tree.
+    pub fn with_sysfs_root(config: HardwareSettings, sysfs_root: impl AsRef<Path>) -> Self {
+        let sysfs_root = sysfs_root.as_ref().to_path_buf();
+
         // Initialize sysinfo synchronously
         let mut sys = sysinfo::System::new_all();
         // We need to refresh CPU to ensure brand is available
@@ -89,11 +460,15 @@ impl LinuxHardwareMonitor {
         };
 
         let mut monitor = Self {
-            hwmon_base: PathBuf::from("/sys/class/hwmon"),
-            thermal_base: PathBuf::from("/sys/class/thermal"),
+            hwmon_base: sysfs_root.join("class/hwmon"),
+            thermal_base: sysfs_root.join("class/thermal"),
+            sysfs_root,
             discovered_fans: Arc::new(RwLock::new(HashMap::new())),
             discovered_sensors: Arc::new(RwLock::new(HashMap::new())),
             cached_hwmon_count: Arc::new(RwLock::new(0)),
+            hwmon_uevents: None,
+            sensors_dirty: Arc::new(std::sync::atomic::AtomicBool::new(true)),
+            discovery_cycles: Arc::new(std::sync::atomic::AtomicU64::new(0)),
             last_discovery_from_cache: Arc::new(RwLock::new(false)),
             system_info: Arc::new(RwLock::new(sys)),
             system_info_cache: Arc::new(RwLock::new(None)),
@@ -101,6 +476,26 @@ impl LinuxHardwareMonitor {
             motherboard_name: String::new(),
             storage_cache: Arc::new(RwLock::new(HashMap::new())),
             nvml: NvmlSource::try_init(),
+            firmware: FirmwareSource::try_init(),
+            storage_health: config.storage_health.then(StorageHealthSource::try_init).flatten().map(Arc::new),
+            dry_run: config.dry_run,
+            pwm_failure_threshold: config.pwm_failure_threshold,
+            skip_write_verify: config.skip_write_verify.clone(),
+            drift_reassert_limit: config.drift_reassert_limit,
+            disabled_chips: Arc::new(std::sync::RwLock::new(config.disabled_chips.clone())),
+            ipmi_bridge_dedup: config.ipmi_bridge_dedup,
+            semi_passive: config.semi_passive.clone(),
+            fan_pulses: config.fan_pulses.clone(),
+            smoother: std::sync::Mutex::new(SensorSmoother::new(config.temperature_smoothing)),
+            virtual_sensors: std::sync::Mutex::new(VirtualSensors::new(config.virtual_sensors.clone())),
+            last_readings: Default::default(),
+            log_throttle: LogThrottle::new(),
+            fan_modes: None,
+            discovery_cache: None,
+            reconcile_pending: tokio::sync::Mutex::new(false),
+            sensor_scan: SingleFlight::default(),
+            chip_locks: ChipLocks::new(config.serialize_chip_access),
+            lm_sensors: None,
         };
 
         // Initialize other static hardware names
@@ -113,11 +508,11 @@ impl LinuxHardwareMonitor {
 
     fn get_motherboard_name(&self) -> String {
         // Try to read DMI info
-        let vendor = std::fs::read_to_string("/sys/class/dmi/id/board_vendor")
+        let vendor = std::fs::read_to_string(self.sysfs_root.join("class/dmi/id/board_vendor"))
             .unwrap_or_default()
             .trim()
             .to_string();
-        let name = std::fs::read_to_string("/sys/class/dmi/id/board_name")
+        let name = std::fs::read_to_string(self.sysfs_root.join("class/dmi/id/board_name"))
             .unwrap_or_default()
             .trim()
             .to_string();
@@ -131,7 +526,35 @@ impl LinuxHardwareMonitor {
         }
     }
 
-    /// Count hwmon directories for hot-plug detection
+    /// hwmon chip directories, ordered by index (hwmon2 before hwmon10)
+    pub(crate) async fn list_hwmon_dirs(&self) -> Result<Vec<PathBuf>> {
+        let mut dirs = Vec::new();
+        if !self.hwmon_base.exists() {
+            return Ok(dirs);
+        }
+
+        let mut entries = tokio::fs::read_dir(&self.hwmon_base).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.is_dir() {
+                dirs.push(path);
+            }
+        }
+
+        dirs.sort_by_cached_key(|p| {
+            let name = p.file_name().unwrap_or_default().to_string_lossy().to_string();
+            let index = name.strip_prefix("hwmon").and_then(|n| n.parse::<u32>().ok()).unwrap_or(u32::MAX);
+            (index, name)
+        });
+        Ok(dirs)
+    }
+
+    /// The uevent listener, while it works. `None`: hot-plug is detected by counting.
+    fn hwmon_uevents(&self) -> Option<&HwmonUevents> {
+        self.hwmon_uevents.as_ref().filter(|uevents| !uevents.is_dead())
+    }
+
+    /// Count hwmon directories for hot-plug detection without uevents
     async fn count_hwmon_dirs(&self) -> usize {
         match tokio::fs::read_dir(&self.hwmon_base).await {
             Ok(mut entries) => {
@@ -151,46 +574,196 @@ impl LinuxHardwareMonitor {
     async fn read_sensors_from_cache(&self) -> Result<Vec<Sensor>> {
         let cache = self.discovered_sensors.read().await;
         let mut sensors = Vec::with_capacity(cache.len());
+        let mut gone: Vec<Arc<str>> = Vec::new();
 
         for info in cache.values() {
-            // Read current temperature from cached path
-            let temp_celsius = match self.read_file(&info.temp_input_path).await {
-                Ok(raw) => {
-                    match raw.parse::<i32>() {
-                        Ok(millidegrees) => millidegrees as f64 / 1000.0,
-                        Err(_) => continue, // Skip if parse fails
+            // Read current temperature through the kept handle
+            let (temperature, stale) = match self.read_number::<i32>(info.temp_fd.as_ref(), &info.temp_input_path).await {
+                Ok(millidegrees) => (self.fresh_reading(&info.id, millidegrees, info.compute.as_ref()), false),
+                Err(e) => {
+                    // A vanished device is pruned and rediscovered
+                    if is_device_gone(&e) {
+                        gone.push(info.id.clone());
+                    }
+                    match self.stale_reading(&info.id, &e) {
+                        Some(temperature) => (temperature, true),
+                        None => continue,
                     }
                 }
-                Err(_) => continue, // Skip if read fails (sensor may have been removed)
             };
 
             sensors.push(Sensor {
                 id: info.id.clone(),
                 name: info.name.clone(),
-                temperature: (temp_celsius * 10.0).round() / 10.0,
+                display_name: info.display_name.clone(),
+                temperature,
                 sensor_type: info.sensor_type.clone(),
                 max_temp: info.max_temp,
                 crit_temp: info.crit_temp,
                 chip: info.chip.clone(),
                 hardware_name: info.hardware_name.clone(),
                 source: info.source.clone(),
+                alarms: self.read_alarms(&info.alarm_paths).await,
+                raw_temperature: None,
+                stale,
             });
         }
+        drop(cache);
+
+        if !gone.is_empty() {
+            debug!("Sensor device(s) went away ({}); full rediscovery next cycle", gone.join(", "));
+            let mut cache = self.discovered_sensors.write().await;
+            for id in &gone {
+                cache.remove(id);
+            }
+            self.sensors_dirty.store(true, std::sync::atomic::Ordering::Relaxed);
+        }
 
         Ok(sensors)
     }
 
+    /// Replace the sensor cache with a full discovery, dropping sensors whose input
+    /// vanished mid-walk (device unbound during discovery), and save it for the next run.
+    async fn store_discovered(&self, discovered: &[Sensor]) {
+        {
+            let mut cache = self.discovered_sensors.write().await;
+            cache.clear();
+            for sensor in discovered {
+                if let Some(source_path) = sensor.source.as_deref().filter(|p| Path::new(p).exists()) {
+                    cache.insert(sensor.id.clone(), SensorInfo {
+                        temp_input_path: PathBuf::from(source_path),
+                        temp_fd: open_attr(Path::new(source_path), false),
+                        id: sensor.id.clone(),
+                        name: sensor.name.clone(),
+                        display_name: sensor.display_name.clone(),
+                        sensor_type: sensor.sensor_type.clone(),
+                        max_temp: sensor.max_temp,
+                        crit_temp: sensor.crit_temp,
+                        chip: sensor.chip.clone(),
+                        hardware_name: sensor.hardware_name.clone(),
+                        source: sensor.source.clone(),
+                        alarm_paths: alarm_paths_for_input(Path::new(source_path)),
+                        compute: self.lm_sensors_compute(Path::new(source_path), sensor.chip.as_deref()),
+                    });
+                }
+            }
+        }
+
+        // Baseline for the counting fallback
+        if self.hwmon_uevents().is_none() {
+            *self.cached_hwmon_count.write().await = self.count_hwmon_dirs().await;
+        }
+
+        if let Some(path) = &self.discovery_cache {
+            let cache = DiscoveryCache::new(self.discovered_sensors.read().await.values());
+            if let Err(e) = cache.save(path) {
+                debug!("Discovery cache not saved: {:#}", e);
+            }
+        }
+    }
+
+    /// Fill the empty sensor cache from discovery-cache.json, if it checks out, and leave
+    /// the full discovery to `reconcile_discovery`.
+    async fn preload_discovery_cache(&self) {
+        let Some(path) = self.discovery_cache.as_deref().filter(|p| p.exists()) else {
+            return;
+        };
+        let cache = match DiscoveryCache::load(path) {
+            Ok(cache) => cache,
+            Err(reason) => {
+                info!("Discovery cache not used ({}); running full discovery", reason);
+                return;
+            }
+        };
+        let infos: Vec<SensorInfo> = cache.into_sensor_infos()
+            .into_iter()
+            .filter(|info| !info.chip.as_deref().is_some_and(|chip| self.chip_disabled(chip)))
+            .map(|info| SensorInfo { compute: self.lm_sensors_compute(&info.temp_input_path, info.chip.as_deref()), ..info })
+            .collect();
+        info!("Reading {} sensors from the discovery cache; full discovery continues in the background", infos.len());
+        {
+            let mut sensors = self.discovered_sensors.write().await;
+            sensors.clear();
+            sensors.extend(infos.into_iter().map(|info| (info.id.clone(), info)));
+        }
+        if self.hwmon_uevents().is_none() {
+            *self.cached_hwmon_count.write().await = self.count_hwmon_dirs().await;
+        }
+        self.sensors_dirty.store(false, std::sync::atomic::Ordering::Relaxed);
+        *self.reconcile_pending.lock().await = true;
+    }
+
+    /// Whether hwmon chip `chip_name` is listed in `hardware.disabled_chips`.
+    pub(crate) fn chip_disabled(&self, chip_name: &str) -> bool {
+        self.disabled_chips.read().unwrap().iter().any(|chip| chip == chip_name)
+    }
+
+    /// Write a fan's mode from before the agent took it over back to `pwmN_enable`,
+    /// through the kept-open handle so this works after dropping root too. Fans
+    /// without a saved mode were never taken over (or dry run: nothing written).
+    async fn hand_back_fan(&self, fan_id: &str, saved: &BTreeMap<String, SavedMode>) -> Result<()> {
+        let Some(mode) = saved.get(fan_id) else {
+            return Ok(());
+        };
+        let fan_map = self.discovered_fans.read().await;
+        let Some((fan_info, path)) = fan_map.get(fan_id).and_then(|i| Some((i, i.pwm_enable_path.as_ref()?))) else {
+            return Ok(());
+        };
+        // A spin-up boost must not settle on a fan the chip owns again
+        fan_info.spinup.lock().unwrap().take();
+        let written = self.write_attr(fan_info.enable_fd.as_ref(), path, &mode.mode).await;
+        *fan_info.last_pwm_value.write().await = None;
+        written?;
+        info!("Fan {} handed back to mode {} ({:?})", fan_id, mode.mode, path);
+        Ok(())
+    }
+
     /// Invalidate sensor cache (call on reconnection)
     pub async fn invalidate_sensor_cache(&self) {
         self.discovered_sensors.write().await.clear();
-        *self.cached_hwmon_count.write().await = 0;
+        self.sensors_dirty.store(true, std::sync::atomic::Ordering::Relaxed);
+        self.smoother.lock().unwrap().reset();
+
+        // Tach handles are reopened by the next fan scan. PWM handles stay: they may be
+        // the only write access left after a privilege drop, and a stale one is
+        // replaced when a read through it fails.
+        for info in self.discovered_fans.write().await.values_mut() {
+            info.rpm_fd = None;
+        }
+    }
+
+    /// Kernel device behind a storage hwmon chip, from the `device` symlink
+    /// (`hwmonX/device -> .../nvme/nvme0` gives "nvme0").
+    pub(crate) fn storage_device_name(hwmon_dir: &Path) -> Option<String> {
+        let target = std::fs::read_link(hwmon_dir.join("device")).ok()?;
+        Some(target.file_name()?.to_string_lossy().to_string())
+    }
+
+    /// Block device (e.g. "sda") under the SCSI device a drivetemp hwmon chip points at.
+    /// The hwmon `device` link targets the SCSI device (`.../target0:0:0/0:0:0:0`), so
+    /// find the /sys/class/block entry whose resolved path lives beneath it.
+    pub(crate) fn scsi_block_device(&self, hwmon_dir: &Path) -> Option<String> {
+        let scsi_device = hwmon_dir.join("device").canonicalize().ok()?;
+        std::fs::read_dir(self.sysfs_root.join("class/block")).ok()?
+            .filter_map(Result::ok)
+            .filter(|entry| {
+                entry.path().canonicalize()
+                    .map(|block| block.starts_with(&scsi_device))
+                    .unwrap_or(false)
+            })
+            .map(|entry| entry.file_name().to_string_lossy().to_string())
+            .min() // whole disk (sda) sorts before partitions (sda1)
     }
 
     pub(crate) async fn resolve_storage_model(&self, hwmon_dir: &Path, chip_name: &str) -> Option<String> {
-        // Check cache first using chip_name as key
+        // Key by the backing device: several drives can share one chip name ("nvme")
+        let device_name = Self::storage_device_name(hwmon_dir);
+        let cache_key = device_name.clone().unwrap_or_else(|| chip_name.to_string());
+
+        // Check cache first
         {
             let cache = self.storage_cache.read().await;
-            if let Some(model) = cache.get(chip_name) {
+            if let Some(model) = cache.get(&cache_key) {
                 return Some(model.clone());
             }
         }
@@ -224,14 +797,25 @@ impl LinuxHardwareMonitor {
              }
         }
 
-        // Strategy 3: Fallback to /sys/class/block lookup if we can guess the name
+        // Strategy 3: SATA/SCSI (drivetemp) - find the block device under the SCSI
+        // device and read its model. Path: /sys/class/block/sda/device/model
         if found_model.is_none() {
-             let device_name = if chip_name.starts_with("nvme") && !chip_name.contains("n") {
-                format!("{}n1", chip_name)
-            } else {
-                chip_name.to_string()
+            if let Some(block) = self.scsi_block_device(hwmon_dir) {
+                let model_path = self.sysfs_root.join(format!("class/block/{}/device/model", block));
+                if let Ok(model) = self.read_file(&model_path).await {
+                    found_model = Some(model);
+                }
+            }
+        }
+
+        // Strategy 4: Fallback to /sys/class/block lookup if we can guess the name
+        if found_model.is_none() {
+            // NVMe controllers (nvme0) expose their model via the first namespace (nvme0n1)
+            let device_name = match &device_name {
+                Some(dev) if dev.starts_with("nvme") && !dev[4..].contains('n') => format!("{}n1", dev),
+                _ => chip_name.to_string(),
             };
-            let model_path = PathBuf::from(format!("/sys/class/block/{}/device/model", device_name));
+            let model_path = self.sysfs_root.join(format!("class/block/{}/device/model", device_name));
             if model_path.exists() {
                 if let Ok(model) = self.read_file(&model_path).await {
                     found_model = Some(model);
@@ -242,67 +826,63 @@ impl LinuxHardwareMonitor {
         if let Some(model) = found_model {
             let model = model.trim().to_string();
             let mut cache = self.storage_cache.write().await;
-            cache.insert(chip_name.to_string(), model.clone());
+            cache.insert(cache_key, model.clone());
             return Some(model);
         }
 
         None
     }
 
-    pub(crate) async fn read_file(&self, path: &Path) -> Result<String> {
-        tokio::fs::read_to_string(path)
-            .await
-            .context(format!("Failed to read file: {:?}", path))
-            .map(|s| s.trim().to_string())
-    }
-
-    pub(crate) async fn write_file(&self, path: &Path, value: &str) -> Result<()> {
-        tokio::fs::write(path, value)
-            .await
-            .context(format!("Failed to write to file: {:?}", path))
-    }
-}
+    /// One sensor discovery (`discover_sensors` shares it between overlapping calls):
+    /// the cached sensors re-read, or a full rediscovery after a hardware change.
+    async fn scan_sensors(&self) -> Result<Vec<Sensor>> {
+        use std::sync::atomic::Ordering;
 
-#[cfg(target_os = "linux")]
-#[async_trait]
-impl HardwareMonitor for LinuxHardwareMonitor {
-    async fn discover_sensors(&self) -> Result<Vec<Sensor>> {
-        // Count-based hot-plug detection
-        let current_hwmon_count = self.count_hwmon_dirs().await;
-        let cached_count = *self.cached_hwmon_count.read().await;
+        // Hot-plug detection: kernel uevents for hwmon devices; counting hwmon
+        // directories only without a listener (or once it failed)
+        let cycle = self.discovery_cycles.fetch_add(1, Ordering::Relaxed) + 1;
+        if cycle == 1 {
+            self.preload_discovery_cache().await;
+        }
+        match self.hwmon_uevents() {
+            Some(uevents) => {
+                if uevents.changed() {
+                    debug!("hwmon devices changed (uevent)");
+                    self.sensors_dirty.store(true, Ordering::Relaxed);
+                }
+            }
+            None => {
+                let current_hwmon_count = self.count_hwmon_dirs().await;
+                let cached_count = *self.cached_hwmon_count.read().await;
+                if current_hwmon_count != cached_count {
+                    debug!("hwmon_count {} -> {}", cached_count, current_hwmon_count);
+                    self.sensors_dirty.store(true, Ordering::Relaxed);
+                }
+            }
+        }
         let cache_empty = self.discovered_sensors.read().await.is_empty();
 
-        let mut sensors = if current_hwmon_count != cached_count || cache_empty {
+        let mut sensors = if self.sensors_dirty.swap(false, Ordering::Relaxed) || cache_empty {
             // Hardware changed or cache empty - full rediscovery
-            debug!("Sensor discovery triggered: hwmon_count {} -> {} (cache_empty: {})",
-                   cached_count, current_hwmon_count, cache_empty);
-
-            let discovered = self.discover_hwmon_sensors().await?;
-
-            // Populate cache with discovered sensors
-            {
-                let mut cache = self.discovered_sensors.write().await;
-                cache.clear();
-                for sensor in &discovered {
-                    if let Some(source_path) = &sensor.source {
-                        cache.insert(sensor.id.clone(), SensorInfo {
-                            temp_input_path: PathBuf::from(source_path),
-                            id: sensor.id.clone(),
-                            name: sensor.name.clone(),
-                            sensor_type: sensor.sensor_type.clone(),
-                            max_temp: sensor.max_temp,
-                            crit_temp: sensor.crit_temp,
-                            chip: sensor.chip.clone(),
-                            hardware_name: sensor.hardware_name.clone(),
-                            source: sensor.source.clone(),
-                        });
-                    }
+            debug!("Sensor discovery triggered (cache_empty: {})", cache_empty);
+
+            let discovered = match self.discover_hwmon_sensors().await {
+                Ok(discovered) => discovered,
+                Err(e) => {
+                    self.sensors_dirty.store(true, Ordering::Relaxed);
+                    return Err(e);
                 }
-            }
+            };
 
-            // Update cached hwmon count
-            *self.cached_hwmon_count.write().await = current_hwmon_count;
+            self.store_discovered(&discovered).await;
             *self.last_discovery_from_cache.write().await = false;
+            // Nothing left to reconcile, unless a reconcile is already under way
+            if let Ok(mut pending) = self.reconcile_pending.try_lock() {
+                *pending = false;
+            }
+
+            // Averages must not bridge a hardware change
+            self.smoother.lock().unwrap().reset();
 
             discovered
         } else {
@@ -317,78 +897,164 @@ impl HardwareMonitor for LinuxHardwareMonitor {
             sensors.extend(nvml.discover_sensors());
         }
 
-        Ok(sensors)
-    }
-
-    async fn discover_fans(&self) -> Result<Vec<Fan>> {
-        // Always perform fresh fan discovery (no caching)
-        let mut fans = self.discover_hwmon_fans().await?;
-
-        // Append NVIDIA GPU fan(s) via NVML (sysfs exposes no writable pwm for them).
-        if let Some(nvml) = &self.nvml {
-            fans.extend(nvml.discover_fans());
+        // Append Raspberry Pi firmware SoC temperature (vcgencmd), also read fresh each cycle.
+        if let Some(firmware) = &self.firmware {
+            sensors.extend(firmware.discover_sensors().await);
         }
 
-        Ok(fans)
+        self.smoother.lock().unwrap().apply(&mut sensors);
+        self.virtual_sensors.lock().unwrap().append(&mut sensors);
+        sort_sensors(&mut sensors);
+        Ok(sensors)
     }
 
-    async fn get_system_info(&self) -> Result<SystemHealth> {
-        // Check cache first (1 second TTL)
-        let cache = self.system_info_cache.read().await;
-        if let Some((health, timestamp)) = cache.as_ref() {
-            if timestamp.elapsed() < std::time::Duration::from_secs(1) {
-                return Ok(health.clone());
-            }
-        }
-        drop(cache);
 
-        // Cache miss or expired - refresh system info
-        let mut sys = self.system_info.write().await;
-        sys.refresh_cpu();
-        sys.refresh_memory();
+    pub(crate) async fn read_file(&self, path: &Path) -> Result<String> {
+        self.read_attr(None, path).await
+    }
 
-        let cpu_usage = sys.global_cpu_info().cpu_usage() as f64;
-        let memory_usage = (sys.used_memory() as f64 / sys.total_memory() as f64) * 100.0;
+    /// Read a sysfs attribute through its kept-open handle (pread at offset 0, which
+    /// makes sysfs regenerate the value), or by path when there is none. Empty reads
+    /// are retried once (attr.rs).
+    pub(crate) async fn read_attr(&self, fd: Option<&Arc<std::fs::File>>, path: &Path) -> Result<String> {
+        let _chip = self.chip_locks.lock(path).await;
+        let (file, owned) = (fd.cloned(), path.to_path_buf());
+        let value = tokio::task::spawn_blocking(move || attr::read_text(file.as_deref(), &owned)).await?;
+        value.with_context(|| format!("Failed to read file: {:?}", path))
+    }
 
-        let health = SystemHealth {
-            cpu_usage,
-            memory_usage,
-            agent_uptime: 0.0, // TODO: Track agent uptime
-        };
+    /// `read_attr` for a numeric attribute: unparsable content is retried once too,
+    /// and then a transient error (`attr::is_transient`).
+    pub(crate) async fn read_number<T: std::str::FromStr + Send + 'static>(&self, fd: Option<&Arc<std::fs::File>>, path: &Path) -> Result<T> {
+        let _chip = self.chip_locks.lock(path).await;
+        let (file, owned) = (fd.cloned(), path.to_path_buf());
+        let value = tokio::task::spawn_blocking(move || attr::read_number(file.as_deref(), &owned)).await?;
+        value.with_context(|| format!("Failed to read file: {:?}", path))
+    }
 
-        // Update cache
-        *self.system_info_cache.write().await = Some((health.clone(), std::time::Instant::now()));
+    /// Write a sysfs attribute through its kept-open handle (pwrite at offset 0),
+    /// or by path when there is none. In dry-run mode only logs the write.
+    pub(crate) async fn write_attr(&self, fd: Option<&Arc<std::fs::File>>, path: &Path, value: &str) -> Result<()> {
+        let _chip = self.chip_locks.lock(path).await;
+        write_attr(self.dry_run, fd, path, value).await
+    }
 
-        Ok(health)
+    /// Remember the mode a fan was in before the agent first changed it, for
+    /// `--uninstall`, the shutdown cleanup and fan-restore.txt.
+    pub(crate) fn record_original_mode(&self, fan_id: &str, enable_path: &Path, mode: &str) {
+        if let Some(store) = &self.fan_modes {
+            store.record(fan_id, enable_path, mode);
+        }
     }
 
-    async fn set_fan_speed(&self, fan_id: &str, speed: u8) -> Result<()> {
+    /// `set_fan_speed` and `set_fan_pwm`: one write to a fan, past the checks that
+    /// apply to both.
+    async fn write_fan_speed(&self, fan_id: &str, target: SpeedTarget, origin: &FanSpeedOrigin) -> Result<()> {
         // Route NVIDIA GPU fans to NVML (sysfs exposes no writable pwm for them).
         if NvmlSource::owns_fan(fan_id) {
+            let SpeedTarget::Percent(speed) = target else {
+                anyhow::bail!("GPU fan {} takes speeds in percent only (no raw PWM)", fan_id);
+            };
             return match &self.nvml {
-                Some(nvml) => nvml.set_fan_speed(fan_id, speed),
+                Some(_) if self.dry_run => {
+                    info!("[DRY RUN] Would set GPU fan {} to {}% via NVML ({})", fan_id, speed, origin);
+                    Ok(())
+                }
+                Some(nvml) => {
+                    debug!("Set GPU fan {} to {}% ({})", fan_id, speed, origin);
+                    nvml.set_fan_speed(fan_id, speed)
+                }
                 None => anyhow::bail!("GPU fan {} requested but NVML is unavailable", fan_id),
             };
         }
 
-        let speed = speed.min(100);
-        let pwm_value = (speed as f32 / 100.0 * 255.0) as u8;
+        let semi_passive = self.semi_passive_for(fan_id);
 
         let fan_map = self.discovered_fans.read().await;
         let fan_info = fan_map.get(fan_id)
             .ok_or_else(|| anyhow::anyhow!("Fan not found: {}", fan_id))?;
+        // Raw values are written as given (past the minimum); `speed` is then their percent
+        let (speed, mut pwm_value) = match target {
+            SpeedTarget::Percent(speed) => {
+                let speed = semi_passive.map_or(speed.min(100), |s| s.effective_speed(speed.min(100)));
+                (speed, fan_info.control.value_for(speed))
+            }
+            SpeedTarget::Pwm(value) if fan_info.control == ControlMethod::Pwm => {
+                let value = effective_pwm(semi_passive, value);
+                (ControlMethod::Pwm.percent_of(value as u32), value as u32)
+            }
+            SpeedTarget::Pwm(_) => anyhow::bail!("Fan {} is set by target RPM: raw PWM values don't apply", fan_id),
+        };
+        if let ControlMethod::TargetRpm { min_rpm, max_rpm } = fan_info.control {
+            if max_rpm <= min_rpm {
+                anyhow::bail!("Fan {} has no fan_max and hasn't been seen spinning yet: RPM range unknown", fan_id);
+            }
+        }
+
+        if !fan_info.writable {
+            self.warn_pwm_denied(fan_id, fan_info);
+            anyhow::bail!("No write access to {:?} (monitoring only)", fan_info.pwm_path);
+        }
+        if let Some(reason) = fan_info.write_failures.lock().unwrap().reason {
+            anyhow::bail!("Fan {} control disabled: {} (send retryFanControl to re-enable)", fan_id, reason);
+        }
+        if self.chip_disabled(&fan_info.chip_name) {
+            anyhow::bail!("Fan {} is on disabled chip {} (send enableChip to control it)", fan_id, fan_info.chip_name);
+        }
+        // The chip would ignore the value
+        if let Some(mode) = *fan_info.pinned_mode.lock().unwrap() {
+            anyhow::bail!("Fan {} is in {} mode (pwm_enable={}, set by setFanMode): send setFanMode manual to set speeds",
+                          fan_id, FanMode::name_of(mode), mode);
+        }
+
+        // SPIN-UP BOOST in progress: hold it and settle at the latest lower target.
+        // Checked before dedup and rate limiting so neither undoes the boost; a stop,
+        // a target at or above the boost, or an expired boost ends it.
+        {
+            let mut spinup = fan_info.spinup.lock().unwrap();
+            if let Some(boost) = spinup.as_mut() {
+                if std::time::Instant::now() < boost.until && speed > 0 && speed < boost.percent {
+                    boost.target = speed;
+                    debug!("Fan {} spinning up at {}%, will settle at {}% ({})", fan_id, boost.percent, speed, origin);
+                    *fan_info.last_set_by.lock().unwrap() = Some(FanSetBy::now(origin));
+                    return Ok(());
+                }
+                *spinup = None;
+            }
+        }
+
+        // Set when this write is a spin-up boost: (boost percent, duration)
+        let mut spinup = None;
 
         // DEDUPLICATION: skip only if the ACTUAL hardware pwm matches. Comparing
         // against our last *intended* write would wrongly skip a re-assert when
         // an external controller (see cooling-device note below) moved the pin.
         // Reading it back makes the agent self-correcting; on read error, write.
         {
-            let actual = self.read_file(&fan_info.pwm_path).await.ok()
-                .and_then(|s| s.parse::<u8>().ok());
+            // Dry run never changes the pin, so compare against the last logged value
+            let actual = if self.dry_run {
+                *fan_info.last_pwm_value.read().await
+            } else {
+                self.read_attr(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path).await.ok()
+                    .and_then(|s| s.parse::<u32>().ok())
+            };
             if actual == Some(pwm_value) {
-                debug!("Fan {} already at PWM {} (hardware), skipping write", fan_id, pwm_value);
+                debug!("Fan {} already at {} {} (hardware), skipping write ({})", fan_id, fan_info.control.name(), pwm_value, origin);
+                *fan_info.last_set_by.lock().unwrap() = Some(FanSetBy::now(origin));
                 return Ok(());
             }
+
+            // A stopped fan may not start at a low PWM: kick it with the boost first
+            if let Some(boost) = semi_passive.and_then(|s| s.spinup_boost).filter(|b| speed > 0 && speed < b.percent.min(100)) {
+                let rpm = match &fan_info.rpm_path {
+                    Some(path) => self.read_attr(fan_info.rpm_fd.as_ref(), path).await.ok().and_then(|s| s.parse::<u32>().ok()),
+                    None => None,
+                };
+                if actual == Some(0) || rpm == Some(0) {
+                    pwm_value = fan_info.control.value_for(boost.percent);
+                    spinup = Some((boost.percent.min(100), boost.duration()));
+                }
+            }
         }
 
         // RATE LIMITING: Max 1 write per 100ms per fan
@@ -398,7 +1064,7 @@ impl HardwareMonitor for LinuxHardwareMonitor {
             let elapsed = now.duration_since(*last_time);
 
             if elapsed < std::time::Duration::from_millis(100) {
-                debug!("Fan {} rate limited, last write {:?} ago", fan_id, elapsed);
+                debug!("Fan {} rate limited, last write {:?} ago ({})", fan_id, elapsed, origin);
                 return Ok(());
             }
             *last_time = now;
@@ -409,25 +1075,56 @@ impl HardwareMonitor for LinuxHardwareMonitor {
         // crossings and overrides us (symptom: fan won't hold high / "snaps back"
         // near 100%). pwm_enable=1 (manual) does NOT prevent it. x86 Super I/O
         // fans aren't cooling devices, so this doesn't occur there.
-        // Enable manual PWM mode if needed (with deduplication)
-        if let Some(enable_path) = &fan_info.pwm_enable_path {
-            let current_enable = self.read_file(enable_path).await.ok();
-            if current_enable.as_deref() != Some("1") {
-                debug!("Enabling manual PWM mode for fan {}", fan_id);
-                self.write_file(enable_path, "1").await?;
+        let write = async {
+            // Enable manual PWM mode if needed (with deduplication)
+            if let Some(enable_path) = &fan_info.pwm_enable_path {
+                let enable_fd = fan_info.enable_fd.as_ref();
+                let current_enable = self.read_attr(enable_fd, enable_path).await.ok();
+                if current_enable.as_deref() != Some("1") {
+                    if let Some(mode) = &current_enable {
+                        self.record_original_mode(fan_id, enable_path, mode);
+                    }
+                    debug!("Enabling manual PWM mode for fan {}", fan_id);
+                    self.write_attr(enable_fd, enable_path, "1").await?;
+                }
             }
-        }
+            self.write_attr(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path, &pwm_value.to_string()).await
+        };
 
         // Perform actual PWM write with error handling
-        match self.write_file(&fan_info.pwm_path, &pwm_value.to_string()).await {
+        match write.await {
             Ok(_) => {
+                if let Err(e) = self.verify_pwm_write(fan_id, fan_info, pwm_value).await {
+                    // Not at that value: the next request must write again
+                    *fan_info.last_pwm_value.write().await = None;
+                    return Err(e);
+                }
                 // Update cache on success
                 *fan_info.last_pwm_value.write().await = Some(pwm_value);
-                debug!("Set fan {} to {}% (PWM: {})", fan_id, speed, pwm_value);
+                fan_info.write_failures.lock().unwrap().succeeded();
+                *fan_info.last_set_by.lock().unwrap() = Some(FanSetBy::now(origin));
+                if let Some(line) = self.log_throttle.recovered(&format!("pwm_write:{}", fan_info.pwm_path.display())) {
+                    info!("Fan {} PWM writes {}", fan_id, line);
+                }
+                if let Some((percent, duration)) = spinup {
+                    let started = std::time::Instant::now();
+                    let boost = SpinUp { started, until: started + duration, percent, target: speed };
+                    *fan_info.spinup.lock().unwrap() = Some(boost);
+                    self.schedule_spinup_settle(fan_id, fan_info, boost);
+                    debug!("Fan {} was stopped: spin-up boost at {}% for {:?}, then {}% ({})", fan_id, percent, duration, speed, origin);
+                } else {
+                    debug!("Set fan {} to {}% ({}: {}, {})", fan_id, speed, fan_info.control.name(), pwm_value, origin);
+                }
                 Ok(())
             }
             Err(e) => {
-                error!("Failed to write PWM for fan {}: {}", fan_id, e);
+                let denied = e.downcast_ref::<std::io::Error>()
+                    .is_some_and(|io| io.kind() == std::io::ErrorKind::PermissionDenied);
+                if denied {
+                    self.warn_pwm_denied(fan_id, fan_info);
+                } else {
+                    self.log_pwm_write_failure(fan_id, fan_info, &e);
+                }
                 // Clear cache on failure to force retry on next attempt (self-healing)
                 *fan_info.last_pwm_value.write().await = None;
                 Err(e)
@@ -435,24 +1132,245 @@ impl HardwareMonitor for LinuxHardwareMonitor {
         }
     }
 
-    async fn emergency_stop(&self) -> Result<()> {
+    /// Semi-passive settings of a hwmon fan.
+    fn semi_passive_for(&self, fan_id: &str) -> Option<SemiPassive> {
+        fan_setting(&self.semi_passive, fan_id).copied()
+    }
+
+    /// `hardware.fan_pulses` of a hwmon fan.
+    pub(crate) fn fan_pulses_for(&self, fan_id: &str) -> Option<u8> {
+        fan_setting(&self.fan_pulses, fan_id).copied()
+    }
+
+    /// After a spin-up boost, write the speed requested meanwhile, unless the boost
+    /// was ended or replaced in between, or the fan was since pinned to a mode by
+    /// `setFanMode` or its chip disabled.
+    fn schedule_spinup_settle(&self, fan_id: &str, fan_info: &FanInfo, spinup: SpinUp) {
+        let fan_id = fan_id.to_string();
+        let dry_run = self.dry_run;
+        let pwm_fd = fan_info.pwm_fd.clone();
+        let pwm_path = fan_info.pwm_path.clone();
+        let control = fan_info.control;
+        let chip_name = fan_info.chip_name.clone();
+        let state = Arc::clone(&fan_info.spinup);
+        let pinned_mode = Arc::clone(&fan_info.pinned_mode);
+        let disabled_chips = Arc::clone(&self.disabled_chips);
+        let last_pwm_value = Arc::clone(&fan_info.last_pwm_value);
+        let last_write_time = Arc::clone(&fan_info.last_write_time);
+        let chip_locks = self.chip_locks.clone();
+
+        tokio::spawn(async move {
+            tokio::time::sleep_until(spinup.until.into()).await;
+            // Checked under the chip lock, like every other write to the fan
+            let chip = chip_locks.lock(&pwm_path).await;
+            let target = {
+                let mut state = state.lock().unwrap();
+                match *state {
+                    Some(current) if current.started == spinup.started => state.take().map(|s| s.target),
+                    _ => None,
+                }
+            };
+            let Some(target) = target else { return };
+            if pinned_mode.lock().unwrap().is_some() || disabled_chips.read().unwrap().contains(&chip_name) {
+                debug!("Fan {} spin-up boost ended: fan pinned to a mode or chip disabled", fan_id);
+                return;
+            }
+
+            let pwm_value = control.value_for(target);
+            let written = write_attr(dry_run, pwm_fd.as_ref(), &pwm_path, &pwm_value.to_string()).await;
+            drop(chip);
+            match written {
+                Ok(()) => {
+                    *last_pwm_value.write().await = Some(pwm_value);
+                    *last_write_time.write().await = std::time::Instant::now();
+                    debug!("Fan {} spin-up boost done, settled at {}%", fan_id, target);
+                }
+                Err(e) => {
+                    warn!("Fan {}: failed to settle at {}% after spin-up boost: {:#}", fan_id, target, e);
+                    *last_pwm_value.write().await = None;
+                }
+            }
+        });
+    }
+
+    /// Read a PWM write back. Some firmware locks a header: the write succeeds and the
+    /// register keeps its old value. Such writes count toward control-degrading the fan
+    /// ("firmware rejects writes"). Target-RPM registers are left alone, as drivers
+    /// clamp them to the fan's range, and so are chips in `hardware.skip_write_verify`.
+    pub(crate) async fn verify_pwm_write(&self, fan_id: &str, fan_info: &FanInfo, written: u32) -> Result<()> {
+        if self.dry_run || fan_info.control != ControlMethod::Pwm || self.skip_write_verify.contains(&fan_info.chip_name) {
+            return Ok(());
+        }
+        let read_back = match self.read_number::<u32>(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path).await {
+            Ok(value) => value,
+            Err(e) => {
+                debug!("Fan {}: PWM write not verified, read-back failed: {:#}", fan_id, e);
+                return Ok(());
+            }
+        };
+        let key = format!("pwm_ignored:{}", fan_info.pwm_path.display());
+        if read_back.abs_diff(written) <= PWM_VERIFY_TOLERANCE {
+            fan_info.write_failures.lock().unwrap().write_ignored = 0;
+            if let Some(line) = self.log_throttle.recovered(&key) {
+                info!("Fan {} PWM writes stick again: {}", fan_id, line);
+            }
+            return Ok(());
+        }
+
+        let mut failures = fan_info.write_failures.lock().unwrap();
+        if failures.ignored(self.pwm_failure_threshold) {
+            warn!(
+                "Fan {}: {} PWM writes in a row didn't stick ({:?} reads back {}, not {}): {}. Reporting it as monitoring-only and no longer writing it. Send retryFanControl to try again",
+                fan_id, failures.write_ignored, fan_info.pwm_path, read_back, written, WRITES_IGNORED
+            );
+        } else if let Some(line) = self.log_throttle.failed(
+            &key,
+            format!("Fan {}: PWM write didn't stick ({:?} reads back {}, not {})", fan_id, fan_info.pwm_path, read_back, written),
+        ) {
+            warn!("{}", line);
+        }
+        anyhow::bail!("Fan {} ignored the write: {:?} reads back {}, not {}", fan_id, fan_info.pwm_path, read_back, written)
+    }
+
+    /// Count a failed PWM write. The error is logged in full once, then summarized
+    /// while it repeats, until the fan becomes control-degraded, which is logged once.
+    fn log_pwm_write_failure(&self, fan_id: &str, fan_info: &FanInfo, e: &anyhow::Error) {
+        let mut failures = fan_info.write_failures.lock().unwrap();
+        if failures.failed(self.pwm_failure_threshold) {
+            warn!(
+                "Fan {}: {} consecutive PWM write failures (last: {:#}); reporting it as monitoring-only and no longer writing it. Send retryFanControl to try again",
+                fan_id, failures.consecutive, e
+            );
+        } else if let Some(line) = self.log_throttle.failed(
+            &format!("pwm_write:{}", fan_info.pwm_path.display()),
+            format!("Failed to write PWM for fan {}: {:#}", fan_id, e),
+        ) {
+            error!("{}", line);
+        } else {
+            debug!("Failed to write PWM for fan {} ({} in a row): {:#}", fan_id, failures.consecutive, e);
+        }
+    }
+
+    /// Log missing PWM write access once per fan rather than on every command.
+    fn warn_pwm_denied(&self, fan_id: &str, fan_info: &FanInfo) {
+        if !fan_info.denied_warned.swap(true, std::sync::atomic::Ordering::Relaxed) {
+            warn!(
+                "No write access to {:?}; fan {} is monitoring-only (run as root or see --print-udev-rules)",
+                fan_info.pwm_path, fan_id
+            );
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+#[async_trait]
+impl HardwareMonitor for LinuxHardwareMonitor {
+    async fn discover_sensors(&self) -> Result<Vec<Sensor>> {
+        self.sensor_scan.run(|| self.scan_sensors()).await
+    }
+
+    async fn discover_fans(&self) -> Result<Vec<Fan>> {
+        // Always perform fresh fan discovery (no caching)
+        let mut fans = self.discover_hwmon_fans().await?;
+        self.reconcile_fans(&mut fans).await;
+
+        // Append NVIDIA GPU fan(s) via NVML (sysfs exposes no writable pwm for them).
+        if let Some(nvml) = &self.nvml {
+            fans.extend(nvml.discover_fans());
+        }
+
+        sort_fans(&mut fans);
+        Ok(fans)
+    }
+
+    async fn get_system_info(&self) -> Result<SystemHealth> {
+        // Check cache first (1 second TTL)
+        let cache = self.system_info_cache.read().await;
+        if let Some((health, timestamp)) = cache.as_ref() {
+            if timestamp.elapsed() < std::time::Duration::from_secs(1) {
+                return Ok(health.clone());
+            }
+        }
+        drop(cache);
+
+        // Cache miss or expired - refresh system info
+        let mut sys = self.system_info.write().await;
+        sys.refresh_cpu();
+        sys.refresh_memory();
+
+        let cpu_usage = sys.global_cpu_info().cpu_usage() as f64;
+        let memory_usage = (sys.used_memory() as f64 / sys.total_memory() as f64) * 100.0;
+        drop(sys);
+
+        let throttled = match &self.firmware {
+            Some(firmware) => firmware.is_throttled().await,
+            None => None,
+        };
+
+        let health = SystemHealth {
+            cpu_usage,
+            memory_usage,
+            agent_uptime: 0.0, // TODO: Track agent uptime
+            throttled,
+            connection: None,
+            storage_health: match &self.storage_health {
+                Some(storage_health) => {
+                    storage_health.refresh_if_due();
+                    storage_health.snapshot()
+                }
+                None => Vec::new(),
+            },
+            notifications: None,
+        };
+
+        // Update cache
+        *self.system_info_cache.write().await = Some((health.clone(), std::time::Instant::now()));
+
+        Ok(health)
+    }
+
+    async fn set_fan_speed(&self, fan_id: &str, speed: u8, origin: &FanSpeedOrigin) -> Result<()> {
+        self.write_fan_speed(fan_id, SpeedTarget::Percent(speed), origin).await
+    }
+
+    async fn set_fan_pwm(&self, fan_id: &str, value: u8, origin: &FanSpeedOrigin) -> Result<()> {
+        self.write_fan_speed(fan_id, SpeedTarget::Pwm(value), origin).await
+    }
+
+    async fn max_cooling(&self) -> Result<()> {
         // Use the full fan list (sysfs + NVML GPU) so emergency covers the GPU too;
         // set_fan_speed routes each id to the correct backend.
         let fans = self.discover_fans().await?;
 
-        for fan in &fans {
-            if let Err(e) = self.set_fan_speed(&fan.id, 100).await {
+        // Fans left to the chip by setFanMode go to manual 100% like the rest
+        for (fan_id, info) in self.discovered_fans.read().await.iter() {
+            if let Some(mode) = info.pinned_mode.lock().unwrap().take() {
+                warn!("Emergency: fan {} taken out of {} mode (pwm_enable={})", fan_id, FanMode::name_of(mode), mode);
+            }
+        }
+
+        // Empty headers: nothing to cool with, and nothing to report as done
+        for fan in fans.iter().filter(|f| f.has_pwm_control && !f.is_absent()) {
+            if let Err(e) = self.set_fan_speed(&fan.id, 100, &FanSpeedOrigin::Emergency).await {
                 error!("Failed to set fan {} to 100%: {}", fan.id, e);
             }
         }
 
-        warn!("EMERGENCY STOP: All fans set to 100%");
+        if self.dry_run {
+            warn!("EMERGENCY STOP (dry run): fans left untouched, writes logged above");
+        } else {
+            warn!("EMERGENCY STOP: All fans set to 100%");
+        }
         Ok(())
     }
 
     async fn restore_fan_to_auto(&self, fan_id: &str) -> Result<bool> {
         if NvmlSource::owns_fan(fan_id) {
             if let Some(nvml) = &self.nvml {
+                if self.dry_run {
+                    info!("[DRY RUN] Would restore GPU fan {} to driver auto via NVML", fan_id);
+                    return Ok(true);
+                }
                 nvml.restore_to_auto(fan_id)?;
                 return Ok(true);
             }
@@ -460,11 +1378,248 @@ impl HardwareMonitor for LinuxHardwareMonitor {
         Ok(false)
     }
 
+    async fn set_fan_mode(&self, fan_id: &str, mode: FanMode) -> Result<u8> {
+        if NvmlSource::owns_fan(fan_id) {
+            anyhow::bail!("GPU fan {} has no pwm_enable modes (restoreFanToAuto hands it back to the driver)", fan_id);
+        }
+        let fan_map = self.discovered_fans.read().await;
+        let fan_info = fan_map.get(fan_id)
+            .ok_or_else(|| anyhow::anyhow!("Fan not found: {}", fan_id))?;
+        let Some(enable_path) = &fan_info.pwm_enable_path else {
+            anyhow::bail!("Fan {} has no pwm_enable attribute: its control mode can't be switched", fan_id);
+        };
+        if !fan_info.writable {
+            self.warn_pwm_denied(fan_id, fan_info);
+            anyhow::bail!("No write access to {:?} (monitoring only)", enable_path);
+        }
+
+        let value = match mode {
+            FanMode::FullSpeed => 0,
+            FanMode::Manual => 1,
+            FanMode::Raw(value) => value,
+            // The chip's own curve as the fan had it, else the usual automatic mode
+            FanMode::Auto => self.fan_modes.as_ref()
+                .and_then(|store| store.saved().get(fan_id)?.mode.parse::<u8>().ok())
+                .filter(|mode| *mode >= 2)
+                .unwrap_or(2),
+        };
+        let enable_fd = fan_info.enable_fd.as_ref();
+        let previous = self.read_attr(enable_fd, enable_path).await?;
+        self.record_original_mode(fan_id, enable_path, &previous);
+        self.write_attr(enable_fd, enable_path, &value.to_string()).await
+            .with_context(|| format!("Fan {} rejected pwm_enable={}", fan_id, value))?;
+
+        // Some drivers take any number and keep their own: read it back
+        if !self.dry_run {
+            let now = self.read_attr(enable_fd, enable_path).await?;
+            if now != value.to_string() {
+                if let Err(e) = self.write_attr(enable_fd, enable_path, &previous).await {
+                    warn!("Could not put fan {} back to pwm_enable={}: {:#}", fan_id, previous, e);
+                }
+                anyhow::bail!("Fan {} doesn't support pwm_enable={} (read back {})", fan_id, value, now);
+            }
+        }
+
+        *fan_info.pinned_mode.lock().unwrap() = (value != 1).then_some(value);
+        if value != 1 {
+            fan_info.spinup.lock().unwrap().take();
+        }
+        // The chip owns the control register until manual mode: the next write isn't a duplicate
+        *fan_info.last_pwm_value.write().await = None;
+        info!("Fan {} switched to {} mode (pwm_enable={}, was {})", fan_id, FanMode::name_of(value), value, previous);
+        Ok(value)
+    }
+
+    async fn release_control(&self) -> Vec<(String, String)> {
+        let fans = match self.discover_fans().await {
+            Ok(fans) => fans,
+            Err(e) => return vec![("*".to_string(), format!("{:#}", e))],
+        };
+        // Fans without a saved mode were found in manual mode (or dry run: nothing written)
+        let saved = self.fan_modes.as_ref().map(FanModeStore::saved).unwrap_or_default();
+        let mut failed = Vec::new();
+        for fan in fans.iter().filter(|f| f.has_pwm_control) {
+            if NvmlSource::owns_fan(&fan.id) {
+                if let Err(e) = self.restore_fan_to_auto(&fan.id).await {
+                    failed.push((fan.id.clone(), format!("{:#}", e)));
+                }
+                continue;
+            }
+            if let Err(e) = self.hand_back_fan(&fan.id, &saved).await {
+                failed.push((fan.id.clone(), format!("{:#}", e)));
+            }
+        }
+        failed
+    }
+
+    async fn set_disabled_chips(&self, chips: &[String]) {
+        let newly_disabled: Vec<String> = {
+            let mut disabled = self.disabled_chips.write().unwrap();
+            let newly = chips.iter().filter(|chip| !disabled.contains(chip)).cloned().collect();
+            *disabled = chips.to_vec();
+            newly
+        };
+        // Their fans get no more speeds: don't leave them at the last one written
+        let saved = self.fan_modes.as_ref().map(FanModeStore::saved).unwrap_or_default();
+        for chip in &newly_disabled {
+            for fan_id in self.chip_fans(chip).await {
+                if let Err(e) = self.hand_back_fan(&fan_id, &saved).await {
+                    warn!("Fan {} of disabled chip {} could not be handed back: {:#}", fan_id, chip, e);
+                }
+            }
+        }
+        self.invalidate_cache().await;
+    }
+
+    async fn chip_fans(&self, chip: &str) -> Vec<String> {
+        let mut fans: Vec<String> = self.discovered_fans.read().await.iter()
+            .filter(|(_, info)| info.chip_name == chip && info.writable)
+            .map(|(id, _)| id.clone())
+            .collect();
+        fans.sort();
+        fans
+    }
+
+    async fn retry_fan_control(&self, fan_id: &str) -> Result<()> {
+        if NvmlSource::owns_fan(fan_id) {
+            return Ok(());
+        }
+        let fan_map = self.discovered_fans.read().await;
+        let fan_info = fan_map.get(fan_id)
+            .ok_or_else(|| anyhow::anyhow!("Fan not found: {}", fan_id))?;
+
+        // Write back the current value: exercises the register without moving the fan
+        let current = match self.read_attr(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path).await {
+            Ok(value) => value,
+            // Unreadable as well: the last value written, else full speed
+            Err(_) => fan_info.last_pwm_value.read().await
+                .unwrap_or_else(|| fan_info.control.value_for(100))
+                .to_string(),
+        };
+        match self.write_attr(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path, &current).await {
+            Ok(()) => {
+                if fan_info.write_failures.lock().unwrap().succeeded() {
+                    info!("Fan {} control restored after retry", fan_id);
+                }
+                Ok(())
+            }
+            Err(e) => {
+                let mut failures = fan_info.write_failures.lock().unwrap();
+                failures.failed(self.pwm_failure_threshold);
+                Err(e.context(format!("Fan {} still failing ({} consecutive write failures)", fan_id, failures.consecutive)))
+            }
+        }
+    }
+
+    async fn fan_control_conflicts(&self) -> Vec<FanControlConflict> {
+        self.detect_fan_control_conflicts(Path::new(PROC_ROOT), Path::new(FANCONTROL_CONFIG)).await
+    }
+
+    async fn run_environment(&self) -> RunEnvironment {
+        super::environment::detect(Path::new("/"), Path::new(PROC_ROOT), &self.hwmon_base)
+    }
+
+    async fn fan_control_state(&self, fan_id: &str) -> Result<Option<FanControlState>> {
+        // GPU fans are handed back with restore_fan_to_auto instead
+        if NvmlSource::owns_fan(fan_id) {
+            return Ok(None);
+        }
+        let fan_map = self.discovered_fans.read().await;
+        let fan_info = fan_map.get(fan_id)
+            .ok_or_else(|| anyhow::anyhow!("Fan not found: {}", fan_id))?;
+
+        let value = self.read_attr(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path).await?
+            .parse::<u32>()
+            .with_context(|| format!("Unexpected value in {:?}", fan_info.pwm_path))?;
+        let pwm_enable = match &fan_info.pwm_enable_path {
+            Some(path) => Some(self.read_attr(fan_info.enable_fd.as_ref(), path).await?),
+            None => None,
+        };
+        Ok(Some(FanControlState { value, pwm_enable }))
+    }
+
+    async fn restore_fan_control_state(&self, fan_id: &str, state: &FanControlState) -> Result<()> {
+        let fan_map = self.discovered_fans.read().await;
+        let fan_info = fan_map.get(fan_id)
+            .ok_or_else(|| anyhow::anyhow!("Fan not found: {}", fan_id))?;
+        if !fan_info.writable {
+            anyhow::bail!("No write access to {:?} (monitoring only)", fan_info.pwm_path);
+        }
+
+        // Value first, then the mode: a chip back in automatic mode owns the register
+        self.write_attr(fan_info.pwm_fd.as_ref(), &fan_info.pwm_path, &state.value.to_string()).await?;
+        if let (Some(path), Some(mode)) = (&fan_info.pwm_enable_path, &state.pwm_enable) {
+            self.write_attr(fan_info.enable_fd.as_ref(), path, mode).await?;
+        }
+        // The next set_fan_speed must not trust a value written before the restore
+        *fan_info.last_pwm_value.write().await = None;
+        debug!("Restored fan {} to {} {} (enable: {:?})", fan_id, fan_info.control.name(), state.value, state.pwm_enable);
+        Ok(())
+    }
+
     async fn invalidate_cache(&self) {
         self.invalidate_sensor_cache().await;
         debug!("Hardware cache invalidated - next discovery will be full rediscovery");
     }
 
+    async fn refresh_cache(&self) {
+        if self.discovered_sensors.read().await.is_empty() {
+            return;
+        }
+        // Busy means a reconcile is running now, which does the same
+        if let Ok(mut pending) = self.reconcile_pending.try_lock() {
+            *pending = true;
+        }
+        for info in self.discovered_fans.write().await.values_mut() {
+            info.rpm_fd = None;
+        }
+        debug!("Hardware cache kept - full rediscovery in the background");
+    }
+
+    async fn reconcile_discovery(&self) -> Result<()> {
+        let mut pending = self.reconcile_pending.lock().await;
+        if !*pending {
+            return Ok(());
+        }
+        let started = std::time::Instant::now();
+        let known = |cache: &HashMap<Arc<str>, SensorInfo>| -> BTreeSet<(Arc<str>, PathBuf)> {
+            cache.values().map(|info| (info.id.clone(), info.temp_input_path.clone())).collect()
+        };
+        let discovered = self.discover_hwmon_sensors().await?;
+        let before = known(&*self.discovered_sensors.read().await);
+        self.store_discovered(&discovered).await;
+        let after = known(&*self.discovered_sensors.read().await);
+        *pending = false;
+
+        if before == after {
+            debug!("Background discovery matches the cache ({} sensors, {:.1}s)", after.len(), started.elapsed().as_secs_f64());
+        } else {
+            info!(
+                "Background discovery: {} sensor(s) new, {} gone or moved since the cache ({:.1}s)",
+                after.difference(&before).count(),
+                before.difference(&after).count(),
+                started.elapsed().as_secs_f64()
+            );
+            self.smoother.lock().unwrap().reset();
+        }
+        Ok(())
+    }
+
+    async fn resumed(&self) {
+        self.invalidate_sensor_cache().await;
+        *self.system_info_cache.write().await = None;
+        // The first write after resume goes through unthrottled and without trusting
+        // a value the firmware may have changed while suspended
+        let unthrottled = std::time::Instant::now().checked_sub(std::time::Duration::from_secs(1));
+        for info in self.discovered_fans.read().await.values() {
+            *info.last_pwm_value.write().await = None;
+            if let Some(earlier) = unthrottled {
+                *info.last_write_time.write().await = earlier;
+            }
+        }
+        debug!("Hardware state reset after resume");
+    }
+
     async fn last_discovery_from_cache(&self) -> bool {
         *self.last_discovery_from_cache.read().await
     }
@@ -473,4 +1628,159 @@ impl HardwareMonitor for LinuxHardwareMonitor {
         // Delegate to the inherent impl method
         LinuxHardwareMonitor::dump_hardware_info(self).await
     }
+
+    fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    fn set_temperature_smoothing(&self, mode: TemperatureSmoothing) {
+        self.smoother.lock().unwrap().set_mode(mode);
+    }
+
+    fn next_cycle(&self) {
+        self.smoother.lock().unwrap().next_cycle();
+    }
+
+    fn set_virtual_sensors(&self, sensors: &[VirtualSensor]) {
+        self.virtual_sensors.lock().unwrap().set(sensors.to_vec());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Temp};
+    use super::{percent_to_pwm, ControlMethod};
+
+    #[test]
+    fn pwm_conversion_rounds_and_round_trips() {
+        let pwm = ControlMethod::Pwm;
+        assert_eq!([0, 1, 254, 255].map(|value| pwm.percent_of(value)), [0, 0, 100, 100]);
+        assert_eq!([0, 1, 50, 99, 100].map(percent_to_pwm), [0, 3, 128, 252, 255]);
+        assert_eq!(pwm.percent_of(300), 100);
+
+        // Every percent reads back as itself, so a re-sent target never moves the fan
+        for speed in 0..=100 {
+            assert_eq!(pwm.percent_of(pwm.value_for(speed)), speed);
+        }
+    }
+
+    #[tokio::test]
+    async fn discovery_cache_serves_the_next_start_until_hwmon_changes() {
+        use crate::hardware::HardwareMonitor;
+
+        let sysfs = FakeSysfs::new("discovery-cache").chip(Chip::new("k10temp").temp(Temp::new(1, 45_000).label("Tctl")));
+        let path = sysfs.root().join("discovery-cache.json");
+        let start = || {
+            let mut monitor = sysfs.monitor();
+            monitor.discovery_cache = Some(path.clone());
+            monitor
+        };
+        let ids = |sensors: Vec<crate::hardware::types::Sensor>| sensors.into_iter().map(|s| s.id.to_string()).collect::<Vec<_>>();
+
+        let discovered = ids(start().discover_sensors().await.unwrap());
+        assert!(path.exists());
+
+        let monitor = start();
+        assert_eq!(ids(monitor.discover_sensors().await.unwrap()), discovered);
+        assert!(monitor.last_discovery_from_cache().await);
+        assert!(*monitor.reconcile_pending.lock().await);
+        monitor.reconcile_discovery().await.unwrap();
+        assert!(!*monitor.reconcile_pending.lock().await);
+
+        // Renumbered: another chip behind the cached path
+        std::fs::write(sysfs.chip_dir(0).join("name"), "nct6798\n").unwrap();
+        let monitor = start();
+        assert_ne!(ids(monitor.discover_sensors().await.unwrap()), discovered);
+        assert!(!monitor.last_discovery_from_cache().await);
+        assert!(!*monitor.reconcile_pending.lock().await);
+    }
+
+    #[tokio::test]
+    async fn overlapping_sensor_discoveries_share_one_scan() {
+        use crate::hardware::HardwareMonitor;
+        use std::sync::atomic::Ordering;
+
+        let sysfs = FakeSysfs::new("single-flight")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 45_000)).temp(Temp::new(2, 50_000)));
+        for serialize_chip_access in [false, true] {
+            let mut hardware = crate::config::types::AgentConfig::default().hardware;
+            hardware.serialize_chip_access = serialize_chip_access;
+            let monitor = sysfs.monitor_with(hardware);
+
+            let results = futures_util::future::join_all((0..8).map(|_| monitor.discover_sensors())).await;
+            assert_eq!(monitor.discovery_cycles.load(Ordering::Relaxed), 1);
+            let temperatures: Vec<Vec<f64>> = results.into_iter().map(|r| r.unwrap().iter().map(|s| s.temperature).collect()).collect();
+            assert!(temperatures.iter().all(|t| *t == [45.0, 50.0]), "{:?}", temperatures);
+
+            // Once it's done, the next call scans again
+            monitor.discover_sensors().await.unwrap();
+            assert_eq!(monitor.discovery_cycles.load(Ordering::Relaxed), 2);
+        }
+    }
+
+    #[tokio::test]
+    async fn storage_model_from_device_model() {
+        let sysfs = FakeSysfs::new("model-direct")
+            .chip(Chip::new("nvme").temp(Temp::new(1, 38_000).label("Composite")).file("device/model", "Samsung SSD 980 PRO 1TB  "));
+        let monitor = sysfs.monitor();
+
+        let model = monitor.resolve_storage_model(&sysfs.chip_dir(0), "nvme").await;
+        assert_eq!(model.as_deref(), Some("Samsung SSD 980 PRO 1TB"));
+    }
+
+    #[tokio::test]
+    async fn storage_model_from_device_block() {
+        let sysfs = FakeSysfs::new("model-block").chip(
+            Chip::new("nvme")
+                .temp(Temp::new(1, 38_000).label("Composite"))
+                .file("device/block/nvme0n1/device/model", "WD_BLACK SN850X"),
+        );
+        let monitor = sysfs.monitor();
+
+        let model = monitor.resolve_storage_model(&sysfs.chip_dir(0), "nvme").await;
+        assert_eq!(model.as_deref(), Some("WD_BLACK SN850X"));
+    }
+
+    #[tokio::test]
+    async fn storage_model_from_class_block() {
+        let sysfs = FakeSysfs::new("model-class")
+            .chip(Chip::new("sdb").temp(Temp::new(1, 33_000)))
+            .file("class/block/sdb/device/model", "ST4000DM004-2CV1");
+        let monitor = sysfs.monitor();
+
+        let model = monitor.resolve_storage_model(&sysfs.chip_dir(0), "sdb").await;
+        assert_eq!(model.as_deref(), Some("ST4000DM004-2CV1"));
+
+        // Resolved model flows into the sensor's hardware name
+        let sensors = monitor.discover_hwmon_sensors().await.unwrap();
+        assert_eq!(sensors[0].hardware_name.as_deref(), Some("ST4000DM004-2CV1"));
+    }
+
+    #[tokio::test]
+    async fn storage_model_from_nvme_namespace() {
+        let sysfs = FakeSysfs::new("model-namespace")
+            .chip(Chip::new("nvme").device("nvme1").temp(Temp::new(1, 38_000)))
+            .file("class/block/nvme1n1/device/model", "CT1000P5SSD8");
+        let monitor = sysfs.monitor();
+
+        let model = monitor.resolve_storage_model(&sysfs.chip_dir(0), "nvme").await;
+        assert_eq!(model.as_deref(), Some("CT1000P5SSD8"));
+    }
+
+    #[tokio::test]
+    async fn storage_model_unresolved() {
+        let sysfs = FakeSysfs::new("model-none").chip(Chip::new("nvme").temp(Temp::new(1, 38_000)));
+        let monitor = sysfs.monitor();
+
+        assert!(monitor.resolve_storage_model(&sysfs.chip_dir(0), "nvme").await.is_none());
+    }
+
+    #[tokio::test]
+    async fn motherboard_name_from_dmi() {
+        let sysfs = FakeSysfs::new("dmi")
+            .file("class/dmi/id/board_vendor", "ASUSTeK COMPUTER INC.")
+            .file("class/dmi/id/board_name", "ROG STRIX X570-E");
+
+        assert_eq!(sysfs.monitor().motherboard_name, "ASUSTeK COMPUTER INC. ROG STRIX X570-E");
+    }
 }
@@ -79,15 +79,19 @@ impl NvmlSource {
                 .name()
                 .unwrap_or_else(|_| format!("NVIDIA GPU {}", idx));
             out.push(Sensor {
-                id: format!("nvidia_gpu{}_temp", idx),
-                name,
+                id: format!("nvidia_gpu{}_temp", idx).into(),
+                name: name.into(),
+                display_name: None,
                 temperature: temp,
-                sensor_type: "gpu".to_string(),
+                sensor_type: "gpu".into(),
                 max_temp: None, // TODO(P3): NVML temperature thresholds (slowdown/shutdown)
                 crit_temp: None,
-                chip: Some("gpu".to_string()),
+                chip: Some("gpu".into()),
                 hardware_name: None,
-                source: Some("nvidia_nvml".to_string()),
+                source: Some("nvidia_nvml".into()),
+                alarms: Vec::new(),
+                raw_temperature: None,
+                stale: false,
             });
         }
         out
@@ -125,13 +129,23 @@ impl NvmlSource {
                 rpm,
                 speed,
                 target_speed: speed,
+                pwm: None,
                 status: if speed == 0 && rpm == Some(0) {
                     "stopped".to_string()
                 } else {
                     "ok".to_string()
                 },
+                status_detail: None,
                 has_pwm_control,
+                control_method: Some("nvml".to_string()),
                 pwm_file: None,
+                pwm_enable: None,
+                control_mode: None,
+                zone: None,
+                alarms: Vec::new(),
+                drift: false,
+                control_error: None,
+                last_set_by: None,
             });
         }
         out
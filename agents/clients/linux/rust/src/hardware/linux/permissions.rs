@@ -0,0 +1,113 @@
+//! Linux hardware monitor: PWM write access for unprivileged operation.
+//!
+//! Without root, hwmon `pwmN` files are usually read-only. Fans whose PWM file we can't
+//! write are reported with `has_pwm_control: false` instead of failing every cycle, and
+//! `--print-udev-rules` generates rules granting the `pankha` group write access.
+
+use std::collections::BTreeMap;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use anyhow::Result;
+
+/// Group the generated udev rules grant PWM write access to.
+pub const PWM_GROUP: &str = "pankha";
+
+/// True if the current process may write `path`: the file has a write bit at all
+/// (read-only attributes stay read-only even for root) and `access(2)` allows it.
+pub(crate) fn is_writable(path: &Path) -> bool {
+    let has_write_bit = std::fs::metadata(path)
+        .map(|m| m.permissions().mode() & 0o222 != 0)
+        .unwrap_or(false);
+    let Ok(c_path) = std::ffi::CString::new(path.as_os_str().as_bytes()) else {
+        return false;
+    };
+    has_write_bit && unsafe { libc::access(c_path.as_ptr(), libc::W_OK) == 0 }
+}
+
+/// True when running as root (full hardware access).
+pub fn is_elevated() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+#[cfg(target_os = "linux")]
+impl super::monitor::LinuxHardwareMonitor {
+    /// udev rules giving `PWM_GROUP` write access to the PWM files of every hwmon
+    /// fan discovered on this machine, matched by chip name.
+    pub async fn udev_rules(&self) -> Result<String> {
+        self.discover_hwmon_fans().await?;
+
+        // chip name -> pwm attribute names (pwmN, pwmN_enable), deduplicated
+        let mut chips: BTreeMap<String, Vec<String>> = BTreeMap::new();
+        for info in self.discovered_fans.read().await.values() {
+            let attrs = chips.entry(info.chip_name.clone()).or_default();
+            let paths = std::iter::once(&info.pwm_path).chain(info.pwm_enable_path.as_ref());
+            for path in paths {
+                if let Some(name) = path.file_name() {
+                    attrs.push(name.to_string_lossy().to_string());
+                }
+            }
+        }
+
+        let mut rules = format!(
+            "# Pankha agent: PWM write access for group '{group}'\n\
+             # Install: sudo groupadd -f {group} && sudo usermod -aG {group} <agent-user>\n\
+             #          save as /etc/udev/rules.d/60-pankha-pwm.rules, then run\n\
+             #          sudo udevadm control --reload && sudo udevadm trigger --subsystem-match=hwmon\n",
+            group = PWM_GROUP
+        );
+        if chips.is_empty() {
+            rules.push_str("# No PWM-controllable hwmon fans were found on this machine.\n");
+        }
+        for (chip, mut attrs) in chips {
+            attrs.sort();
+            attrs.dedup();
+            let files = attrs.iter().map(|a| format!("/sys%p/{}", a)).collect::<Vec<_>>().join(" ");
+            rules.push_str(&format!(
+                "ACTION==\"add\", SUBSYSTEM==\"hwmon\", ATTR{{name}}==\"{chip}\", \
+                 RUN+=\"/bin/sh -c 'chgrp {group} {files} && chmod g+w {files}'\"\n",
+                chip = chip,
+                group = PWM_GROUP,
+                files = files
+            ));
+        }
+        Ok(rules)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm};
+
+    #[tokio::test]
+    async fn read_only_pwm_is_not_controllable() {
+        let sysfs = FakeSysfs::new("perm-readonly").chip(
+            Chip::new("nct6798")
+                .fan(1, 900)
+                .pwm(Pwm::new(1, 128).enable(2))
+                .fan(2, 800)
+                .pwm(Pwm::new(2, 128).mode(0o444)),
+        );
+        let fans = sysfs.monitor().discover_hwmon_fans().await.unwrap();
+
+        assert!(fans[0].has_pwm_control);
+        assert!(!fans[1].has_pwm_control);
+        assert!(!is_writable(&sysfs.chip_dir(0).join("pwm2")));
+    }
+
+    #[tokio::test]
+    async fn udev_rules_cover_discovered_pwm_files() {
+        let sysfs = FakeSysfs::new("perm-udev")
+            .chip(Chip::new("nct6798").fan(1, 900).pwm(Pwm::new(1, 128).enable(2)).pwm(Pwm::new(2, 64)))
+            .chip(Chip::new("k10temp"));
+        let rules = sysfs.monitor().udev_rules().await.unwrap();
+
+        let rule_lines: Vec<_> = rules.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(rule_lines.len(), 1);
+        assert!(rule_lines[0].contains("ATTR{name}==\"nct6798\""));
+        assert!(rule_lines[0].contains("/sys%p/pwm1 /sys%p/pwm1_enable /sys%p/pwm2"));
+        assert!(rule_lines[0].contains("chgrp pankha"));
+    }
+}
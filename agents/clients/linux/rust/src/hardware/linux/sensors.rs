@@ -1,72 +1,177 @@
 //! Linux hardware monitor: hwmon sensor discovery and classification.
 
+use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 
 use anyhow::Result;
+use futures_util::future::join_all;
+use futures_util::stream::{self, StreamExt};
+use tracing::{debug, info, warn};
 
 use crate::hardware::types::*;
 
-#[cfg(target_os = "linux")]
-impl super::monitor::LinuxHardwareMonitor {
-    pub(crate) async fn discover_hwmon_sensors(&self) -> Result<Vec<Sensor>> {
-        let mut sensors = Vec::new();
+use super::alarms::alarm_paths;
+use super::attr;
+use super::ipmi_bridge;
+use super::lm_sensors::{Compute, FeatureConfig};
+use super::monitor::MAX_CONCURRENT_CHIPS;
 
-        if !self.hwmon_base.exists() {
-            return Ok(sensors);
-        }
+/// Consecutive cycles a sensor whose reads fail transiently is reported with its last
+/// good temperature, marked stale, before it is left out.
+pub(crate) const MAX_STALE_READS: u32 = 5;
 
-        let mut entries = tokio::fs::read_dir(&self.hwmon_base).await?;
+/// Last good temperature per sensor id, and the transient read failures since.
+#[derive(Default)]
+pub(crate) struct LastReadings(HashMap<Arc<str>, (f64, u32)>);
 
-        while let Some(entry) = entries.next_entry().await? {
-            let hwmon_dir = entry.path();
-            if !hwmon_dir.is_dir() {
-                continue;
+impl LastReadings {
+    fn fresh(&mut self, id: &str, temperature: f64) {
+        match self.0.get_mut(id) {
+            Some(last) => *last = (temperature, 0),
+            None => {
+                self.0.insert(id.into(), (temperature, 0));
             }
+        }
+    }
 
-            // Get chip name
-            let chip_name = match self.read_file(&hwmon_dir.join("name")).await {
-                Ok(name) => name,
-                Err(_) => continue,
-            };
+    /// The last good temperature for another failed read, while within `MAX_STALE_READS`.
+    fn stale(&mut self, id: &str) -> Option<f64> {
+        let (temperature, failures) = self.0.get_mut(id)?;
+        *failures += 1;
+        (*failures <= MAX_STALE_READS).then_some(*temperature)
+    }
+}
 
-            // Find temperature inputs
-            let pattern = hwmon_dir.join("temp*_input");
-            let pattern_str = pattern.to_string_lossy();
+#[cfg(target_os = "linux")]
+impl super::monitor::LinuxHardwareMonitor {
+    /// °C for a good read of a temperature input (through its lm-sensors `compute`),
+    /// remembered for `stale_reading`.
+    pub(crate) fn fresh_reading(&self, id: &str, millidegrees: i32, compute: Option<&Compute>) -> f64 {
+        let celsius = millidegrees as f64 / 1000.0;
+        let temperature = (compute.map_or(celsius, |c| c.apply(celsius)) * 10.0).round() / 10.0;
+        self.last_readings.lock().unwrap().fresh(id, temperature);
+        if let Some(line) = self.log_throttle.recovered(&format!("sensor_read:{}", id)) {
+            info!("Sensor {} {}", id, line);
+        }
+        temperature
+    }
 
-            for temp_file in glob::glob(&pattern_str).unwrap().filter_map(Result::ok) {
-                if let Ok(sensor) = self.parse_hwmon_sensor(&hwmon_dir, &temp_file, &chip_name).await {
-                    sensors.push(sensor);
-                }
-            }
+    /// What to report for a failed read: the last good temperature if the failure is
+    /// transient (empty or garbled read, busy bus) and hasn't lasted too long, else
+    /// nothing and the sensor is left out.
+    pub(crate) fn stale_reading(&self, id: &str, error: &anyhow::Error) -> Option<f64> {
+        if !attr::is_transient(error) {
+            return None;
+        }
+        let temperature = self.last_readings.lock().unwrap().stale(id);
+        let key = format!("sensor_read:{}", id);
+        match temperature {
+            Some(_) => if let Some(line) = self.log_throttle.failed(&key, format!("Sensor {}: {:#} (last value re-sent)", id, error)) {
+                debug!("{}", line);
+            },
+            None => if let Some(line) = self.log_throttle.failed(&key, format!("Sensor {}: {:#} (left out)", id, error)) {
+                warn!("{}", line);
+            },
         }
+        temperature
+    }
+
+    pub(crate) async fn discover_hwmon_sensors(&self) -> Result<Vec<Sensor>> {
+        let hwmon_dirs = self.list_hwmon_dirs().await?;
+
+        // Scan chips concurrently (slow drivers like drivetemp block each read);
+        // `buffered` keeps results in hwmon index order
+        let per_chip: Vec<Vec<Sensor>> = stream::iter(hwmon_dirs)
+            .map(|hwmon_dir| async move { self.scan_chip_sensors(&hwmon_dir).await })
+            .buffered(MAX_CONCURRENT_CHIPS)
+            .collect()
+            .await;
 
+        let mut sensors: Vec<Sensor> = per_chip.into_iter().flatten().collect();
+        if self.ipmi_bridge_dedup.enabled {
+            let dropped = ipmi_bridge::drop_duplicates(&mut sensors, &self.ipmi_bridge_dedup);
+            if !dropped.is_empty() {
+                debug!("IPMI bridge sensors duplicating native ones left out: {}", dropped.join(", "));
+            }
+        }
         Ok(sensors)
     }
 
-    async fn parse_hwmon_sensor(&self, hwmon_dir: &Path, temp_file: &Path, chip_name: &str) -> Result<Sensor> {
-        let filename = temp_file.file_name().unwrap().to_string_lossy();
-        let temp_num = filename.strip_prefix("temp").and_then(|s| s.strip_suffix("_input")).unwrap();
+    /// Read every temperature channel of one hwmon chip, in channel order.
+    async fn scan_chip_sensors(&self, hwmon_dir: &Path) -> Vec<Sensor> {
+        // Get chip name
+        let chip_name = match self.read_file(&hwmon_dir.join("name")).await {
+            Ok(name) if self.chip_disabled(&name) => return Vec::new(),
+            Ok(name) => name,
+            Err(_) => return Vec::new(),
+        };
 
-        // Read temperature (millidegrees to celsius)
-        let temp_raw: i32 = self.read_file(temp_file).await?.parse()?;
-        let temp_celsius = temp_raw as f64 / 1000.0;
+        // Find temperature inputs, numerically ordered (temp2 before temp10)
+        let pattern = hwmon_dir.join("temp*_input");
+        let pattern_str = pattern.to_string_lossy();
+        let mut temp_nums: Vec<u32> = glob::glob(&pattern_str).unwrap()
+            .filter_map(Result::ok)
+            .filter_map(|p| {
+                let filename = p.file_name()?.to_string_lossy().to_string();
+                filename.strip_prefix("temp")?.strip_suffix("_input")?.parse().ok()
+            })
+            .collect();
+        temp_nums.sort_unstable();
 
-        // Try to get label
-        let label_path = hwmon_dir.join(format!("temp{}_label", temp_num));
-        let sensor_label = self.read_file(&label_path).await
-            .unwrap_or_else(|_| format!("Sensor {}", temp_num));
+        // lm-sensors `ignore`d inputs are left out like a disabled chip's
+        let lm_chip = self.lm_sensors_chip(hwmon_dir, &chip_name);
+        let channels: Vec<(u32, FeatureConfig)> = temp_nums.into_iter()
+            .map(|n| (n, self.lm_sensors_feature(lm_chip.as_ref(), &format!("temp{}", n))))
+            .filter(|(_, lm)| !lm.ignore)
+            .collect();
+
+        let results = join_all(channels.iter().map(|(n, lm)| self.parse_hwmon_sensor(hwmon_dir, *n, &chip_name, lm))).await;
+
+        // Unreadable inputs would otherwise just vanish; say why when it's permissions
+        let denied = results.iter()
+            .filter_map(|r| r.as_ref().err())
+            .filter(|e| attr::io_error(e).is_some_and(|io| io.kind() == std::io::ErrorKind::PermissionDenied))
+            .count();
+        if denied > 0 {
+            warn!("{}: {} temperature input(s) unreadable (permission denied); run as root to include them", chip_name, denied);
+        }
 
-        // Try to get limits
+        results.into_iter().filter_map(Result::ok).collect()
+    }
+
+    async fn parse_hwmon_sensor(&self, hwmon_dir: &Path, temp_num: u32, chip_name: &str, lm: &FeatureConfig) -> Result<Sensor> {
+        let temp_file = hwmon_dir.join(format!("temp{}_input", temp_num));
+        let label_path = hwmon_dir.join(format!("temp{}_label", temp_num));
         let max_path = hwmon_dir.join(format!("temp{}_max", temp_num));
         let crit_path = hwmon_dir.join(format!("temp{}_crit", temp_num));
+        let sensor_alarm_paths = alarm_paths(hwmon_dir, &format!("temp{}", temp_num));
 
-        let max_temp = self.read_file(&max_path).await.ok()
-            .and_then(|s| s.parse::<i32>().ok())
-            .map(|v| v as f64 / 1000.0);
+        // Batch the per-channel reads; each can block on a slow driver
+        let (temp_raw, label, max_raw, crit_raw, alarms) = tokio::join!(
+            self.read_number::<i32>(None, &temp_file),
+            self.read_file(&label_path),
+            self.read_file(&max_path),
+            self.read_file(&crit_path),
+            self.read_alarms(&sensor_alarm_paths),
+        );
+
+        // A transient failure may still be covered by the last reading (below)
+        let temp_raw = match temp_raw {
+            Err(e) if !attr::is_transient(&e) => return Err(e),
+            result => result,
+        };
+
+        // Try to get label
+        let sensor_label = label.unwrap_or_else(|_| format!("Sensor {}", temp_num));
 
-        let crit_temp = self.read_file(&crit_path).await.ok()
+        // Try to get limits, corrected like the reading
+        let limit = |raw: Result<String>| raw.ok()
             .and_then(|s| s.parse::<i32>().ok())
-            .map(|v| v as f64 / 1000.0);
+            .map(|v| v as f64 / 1000.0)
+            .map(|v| lm.compute.as_ref().map_or(v, |c| (c.apply(v) * 10.0).round() / 10.0));
+        let max_temp = limit(max_raw);
+        let crit_temp = limit(crit_raw);
 
         // Generate descriptive ID
         // Old: k10temp_1
@@ -78,39 +183,102 @@ impl super::monitor::LinuxHardwareMonitor {
             .replace("(", "")
             .replace(")", "");
 
-        // Ensure ID is unique by combining chip and label
-        // Note: This assumes chip_name is unique or we don't have identical sensors.
-        // For identical chips, we might need a better strategy later, but this matches Windows parity.
-        let sensor_id = format!("{}_{}", chip_name.to_lowercase().replace(" ", "_"), sanitized_label);
         let sensor_type = Self::classify_sensor_type(chip_name);
 
+        // Every NVMe hwmon chip is named "nvme" and every SATA one "drivetemp", so
+        // qualify with the device (nvme0, sda) or two drives collide on the same IDs
+        let storage_device = match sensor_type.as_str() {
+            "nvme" => Self::storage_device_name(hwmon_dir),
+            "hdd" => self.scsi_block_device(hwmon_dir),
+            _ => None,
+        };
+        if let (Some(device), Some(storage_health)) = (&storage_device, &self.storage_health) {
+            storage_health.watch(device);
+        }
+
+        // Ensure ID is unique by combining chip (or storage device) and label
+        let sensor_id = match &storage_device {
+            Some(device) => format!("{}_{}", device, sanitized_label),
+            None => format!("{}_{}", chip_name.to_lowercase().replace(" ", "_"), sanitized_label),
+        };
+
         // Determine full hardware name based on type
         let mut hardware_name = chip_name.to_string();
 
-        if sensor_type == "cpu" && !self.cpu_brand.is_empty() {
+        if sensor_type == "ipmi" {
+            hardware_name = ipmi_bridge::BRIDGE_HARDWARE_NAME.to_string();
+        } else if sensor_type == "cpu" && !self.cpu_brand.is_empty() {
              hardware_name = self.cpu_brand.clone();
         } else if sensor_type == "motherboard" && !self.motherboard_name.is_empty() {
              hardware_name = self.motherboard_name.clone();
-        } else if sensor_type == "nvme" || chip_name.contains("nvme") || chip_name.contains("sd") {
+        } else if sensor_type == "nvme" || sensor_type == "hdd" || chip_name.contains("nvme") || chip_name.contains("sd") {
             // Try to resolve storage model using the hwmon path
             if let Some(model) = self.resolve_storage_model(hwmon_dir, chip_name).await {
                 hardware_name = model;
             }
         }
 
+        // The id keeps the kernel's label; names take an lm-sensors label instead
+        let sensor_label = lm.label.clone().unwrap_or(sensor_label);
+
+        // What the kernel reports, independent of the naming heuristics below
+        let name = match &storage_device {
+            Some(device) => format!("{} {} {}", chip_name, device, sensor_label),
+            None => format!("{} {}", chip_name, sensor_label),
+        };
+
+        let model_resolved = hardware_name != chip_name;
+        let display_name = match (&storage_device, sensor_type.as_str()) {
+            // "Samsung SSD 980 PRO 1TB (nvme0) Composite"
+            (Some(device), "nvme") => {
+                let drive = if model_resolved { hardware_name.clone() } else { Self::get_friendly_chip_name(chip_name) };
+                format!("{} ({}) {}", drive, device, Self::friendly_nvme_label(&sensor_label))
+            }
+            // "Storage WD WDC WD80EFAX-68L (sda)" - drivetemp has a single unlabeled channel
+            (Some(device), _) if model_resolved => {
+                let brand = Self::extract_brand(&hardware_name);
+                let prefix = if brand.is_empty() { "Storage".to_string() } else { format!("Storage {}", brand) };
+                format!("{} {} ({})", prefix, hardware_name, device)
+            }
+            (Some(device), _) => format!("{} ({})", Self::get_friendly_chip_name(chip_name), device),
+            (None, _) => format!("{} {}", Self::get_friendly_chip_name(chip_name), sensor_label),
+        };
+
+        // Millidegrees to °C
+        let (temperature, stale) = match temp_raw {
+            Ok(millidegrees) => (self.fresh_reading(&sensor_id, millidegrees, lm.compute.as_ref()), false),
+            Err(e) => match self.stale_reading(&sensor_id, &e) {
+                Some(temperature) => (temperature, true),
+                None => return Err(e),
+            },
+        };
+
         Ok(Sensor {
-            id: sensor_id,
-            name: format!("{} {}", Self::get_friendly_chip_name(chip_name), sensor_label),
-            temperature: temp_celsius.round() * 10.0 / 10.0,
-            sensor_type,
+            id: sensor_id.into(),
+            name: name.into(),
+            display_name: Some(display_name.into()),
+            temperature,
+            sensor_type: sensor_type.into(),
             max_temp,
             crit_temp,
-            chip: Some(chip_name.to_string()),
-            hardware_name: Some(hardware_name),
-            source: Some(temp_file.to_string_lossy().to_string()),
+            chip: Some(chip_name.into()),
+            hardware_name: Some(hardware_name.into()),
+            source: Some(temp_file.to_string_lossy().into()),
+            alarms,
+            raw_temperature: None,
+            stale,
         })
     }
 
+    /// NVMe "Sensor N" channels are vendor-defined extra probes (controller, NAND, ...);
+    /// the spec doesn't say which, so only make it clear they are drive-internal.
+    fn friendly_nvme_label(label: &str) -> String {
+        match label.strip_prefix("Sensor ") {
+            Some(n) => format!("Drive Sensor {}", n),
+            None => label.to_string(),
+        }
+    }
+
     /// Extract hardware brand from chip name for TYPE-first display
     fn extract_brand(chip_name: &str) -> String {
         let name = chip_name.to_lowercase();
@@ -152,13 +320,15 @@ impl super::monitor::LinuxHardwareMonitor {
         let brand = Self::extract_brand(chip_name);
         let chip_lower = chip_name.to_lowercase();
 
-        if chip_lower.contains("k10temp") || chip_lower.contains("coretemp") || chip_lower.contains("cpu") {
+        if ipmi_bridge::is_bridge_chip(chip_name) {
+            "BMC".to_string()
+        } else if chip_lower.contains("k10temp") || chip_lower.contains("coretemp") || chip_lower.contains("cpu") {
             if !brand.is_empty() {
                 format!("CPU {}", brand)
             } else {
                 "CPU".to_string()
             }
-        } else if chip_lower.contains("nvme") || chip_lower.contains("storage") {
+        } else if chip_lower.contains("nvme") || chip_lower.contains("drivetemp") || chip_lower.contains("storage") {
             if !brand.is_empty() {
                 format!("Storage {}", brand)
             } else {
@@ -179,10 +349,15 @@ impl super::monitor::LinuxHardwareMonitor {
 
     fn classify_sensor_type(chip_name: &str) -> String {
         let chip_lower = chip_name.to_lowercase();
-        if chip_lower.contains("k10temp") || chip_lower.contains("coretemp") || chip_lower.contains("cpu") {
+        if ipmi_bridge::is_bridge_chip(chip_name) {
+            // The BMC's view of several components; native drivers get precedence
+            "ipmi".to_string()
+        } else if chip_lower.contains("k10temp") || chip_lower.contains("coretemp") || chip_lower.contains("cpu") {
             "cpu".to_string()
         } else if chip_lower.contains("nvme") {
             "nvme".to_string()
+        } else if chip_lower.contains("drivetemp") {
+            "hdd".to_string()
         } else if chip_lower.contains("it8") || chip_lower.contains("nct") {
             "motherboard".to_string()
         } else if chip_lower.contains("acpi") {
@@ -192,3 +367,340 @@ impl super::monitor::LinuxHardwareMonitor {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Temp};
+    use crate::hardware::linux::hotplug::HwmonUevents;
+    use crate::hardware::linux::monitor::LinuxHardwareMonitor;
+    use crate::hardware::HardwareMonitor;
+    use crate::hardware::types::Sensor;
+
+    #[tokio::test]
+    async fn unlabeled_sensor_falls_back_to_channel_number() {
+        let sysfs = FakeSysfs::new("sensor-label")
+            .chip(Chip::new("acpitz").temp(Temp::new(3, 27_800)));
+        let sensors = sysfs.monitor().discover_hwmon_sensors().await.unwrap();
+
+        assert_eq!(sensors.len(), 1);
+        assert_eq!(&*sensors[0].id, "acpitz_sensor_3");
+        assert_eq!(&*sensors[0].name, "acpitz Sensor 3");
+        assert_eq!(sensors[0].display_name.as_deref(), Some("ACPI Sensor 3"));
+        assert_eq!(&*sensors[0].sensor_type, "acpi");
+    }
+
+    #[tokio::test]
+    async fn label_is_sanitized_into_id() {
+        let sysfs = FakeSysfs::new("sensor-id")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 30_000).label("CPU Socket (PECI)/A-B")));
+        let sensors = sysfs.monitor().discover_hwmon_sensors().await.unwrap();
+
+        assert_eq!(&*sensors[0].id, "nct6798_cpu_socket_peci_a_b");
+        assert_eq!(&*sensors[0].name, "nct6798 CPU Socket (PECI)/A-B");
+        assert_eq!(sensors[0].display_name.as_deref(), Some("Motherboard Nuvoton CPU Socket (PECI)/A-B"));
+    }
+
+    /// `id` and `name` are what the backend keys on: friendlier names (a resolved
+    /// drive model, ...) may only ever change `display_name`, and all three come out
+    /// of the path cache and into the diagnostics dump unchanged.
+    #[tokio::test]
+    async fn ids_and_names_do_not_depend_on_naming_heuristics() {
+        let sysfs = FakeSysfs::new("sensor-naming")
+            .chip(Chip::new("k10temp").temp(Temp::new(1, 48_000).label("Tctl")))
+            .chip(Chip::new("it8688").temp(Temp::new(2, 33_000).label("CPU Temp")))
+            .chip(Chip::new("nvme").device("nvme0").temp(Temp::new(1, 41_000).label("Composite")).file("device/model", "Samsung SSD 980 PRO 1TB"))
+            .chip(Chip::new("acpitz").temp(Temp::new(1, 27_800)));
+        let naming = |sensors: Vec<Sensor>| -> Vec<(String, String, String)> {
+            let mut naming: Vec<_> = sensors
+                .into_iter()
+                .map(|s| (s.id.to_string(), s.name.to_string(), s.display_name.as_deref().unwrap_or_default().to_string()))
+                .collect();
+            naming.sort();
+            naming
+        };
+        let owned = |expected: &[(&str, &str, &str)]| -> Vec<(String, String, String)> {
+            expected.iter().map(|(id, name, display)| (id.to_string(), name.to_string(), display.to_string())).collect()
+        };
+
+        let monitor = sysfs.monitor();
+        let fresh = naming(monitor.discover_sensors().await.unwrap());
+        assert_eq!(fresh, owned(&[
+            ("acpitz_sensor_1", "acpitz Sensor 1", "ACPI Sensor 1"),
+            ("it8688_cpu_temp", "it8688 CPU Temp", "Motherboard ITE CPU Temp"),
+            ("k10temp_tctl", "k10temp Tctl", "CPU AMD Tctl"),
+            ("nvme0_composite", "nvme nvme0 Composite", "Samsung SSD 980 PRO 1TB (nvme0) Composite"),
+        ]));
+        assert_eq!(naming(monitor.discover_sensors().await.unwrap()), fresh);
+
+        // Without a resolvable drive model only the display name differs
+        let unresolved = FakeSysfs::new("sensor-naming-unresolved")
+            .chip(Chip::new("nvme").device("nvme0").temp(Temp::new(1, 41_000).label("Composite")));
+        let unresolved = naming(unresolved.monitor().discover_sensors().await.unwrap());
+        assert_eq!(unresolved, owned(&[("nvme0_composite", "nvme nvme0 Composite", "Storage (nvme0) Composite")]));
+
+        // What goes on the wire
+        let sensor = monitor.discover_sensors().await.unwrap().into_iter().find(|s| &*s.id == "k10temp_tctl").unwrap();
+        let json = serde_json::to_value(&sensor).unwrap();
+        assert_eq!((&json["name"], &json["display_name"]), (&serde_json::json!("k10temp Tctl"), &serde_json::json!("CPU AMD Tctl")));
+
+        // And in the diagnostics dump, next to the raw hwmon entry
+        let dump = monitor.dump_hardware_info().await.unwrap();
+        let reported = dump.hardware.iter()
+            .flat_map(|item| &item.sensors)
+            .find_map(|s| s.reported_as.as_ref().filter(|r| r.id == "k10temp_tctl"))
+            .unwrap();
+        assert_eq!((reported.name.as_str(), reported.display_name.as_deref()), ("k10temp Tctl", Some("CPU AMD Tctl")));
+    }
+
+    #[tokio::test]
+    async fn limits_are_parsed_from_millidegrees() {
+        let sysfs = FakeSysfs::new("sensor-limits").chip(
+            Chip::new("coretemp")
+                .temp(Temp::new(1, 45_000).label("Package id 0").max(80_000).crit(100_000))
+                .temp(Temp::new(2, 44_000).label("Core 0"))
+                .file("temp2_max", "garbage"),
+        );
+        let sensors = sysfs.monitor().discover_hwmon_sensors().await.unwrap();
+
+        let package = sensors.iter().find(|s| &*s.id == "coretemp_package_id_0").unwrap();
+        assert_eq!(package.temperature, 45.0);
+        assert_eq!(package.max_temp, Some(80.0));
+        assert_eq!(package.crit_temp, Some(100.0));
+
+        let core = sensors.iter().find(|s| &*s.id == "coretemp_core_0").unwrap();
+        assert_eq!(core.max_temp, None);
+        assert_eq!(core.crit_temp, None);
+    }
+
+    #[tokio::test]
+    async fn unreadable_input_is_skipped() {
+        let sysfs = FakeSysfs::new("sensor-skip").chip(
+            Chip::new("k10temp")
+                .temp(Temp::new(1, 50_000).label("Tctl"))
+                .file("temp2_input", "not-a-number"),
+        );
+        let sensors = sysfs.monitor().discover_hwmon_sensors().await.unwrap();
+
+        assert_eq!(sensors.len(), 1);
+        assert_eq!(&*sensors[0].id, "k10temp_tctl");
+    }
+
+    #[tokio::test]
+    async fn cached_reads_go_through_kept_handle() {
+        let sysfs = FakeSysfs::new("sensor-handle")
+            .chip(Chip::new("k10temp").temp(Temp::new(1, 50_000).label("Tctl")));
+        let monitor = sysfs.monitor();
+        monitor.discover_sensors().await.unwrap();
+
+        // Replace the file: the path now names a new inode, the handle the original
+        let input = sysfs.chip_dir(0).join("temp1_input");
+        std::fs::write(&input, "51000").unwrap();
+        let replacement = sysfs.chip_dir(0).join("temp1_input.new");
+        std::fs::write(&replacement, "99000").unwrap();
+        std::fs::rename(&replacement, &input).unwrap();
+
+        let sensors = monitor.discover_sensors().await.unwrap();
+        assert!(monitor.last_discovery_from_cache().await);
+        assert_eq!(sensors[0].temperature, 51.0);
+    }
+
+    #[tokio::test]
+    async fn transient_read_failures_resend_the_last_value_as_stale() {
+        let sysfs = FakeSysfs::new("sensor-transient")
+            .chip(Chip::new("k10temp").temp(Temp::new(1, 50_000).label("Tctl")).file("temp2_input", "41000\u{1}"));
+        let monitor = sysfs.monitor();
+        let input = sysfs.chip_dir(0).join("temp1_input");
+        let reading = |sensors: Vec<Sensor>| sensors.iter().find(|s| &*s.id == "k10temp_tctl").map(|s| (s.temperature, s.stale));
+
+        // A stray byte is dropped, even in discovery
+        let sensors = monitor.discover_sensors().await.unwrap();
+        assert_eq!(sensors.iter().find(|s| &*s.id == "k10temp_sensor_2").map(|s| s.temperature), Some(41.0));
+
+        std::fs::write(&input, b"").unwrap();
+        for _ in 0..super::MAX_STALE_READS {
+            assert_eq!(reading(monitor.discover_sensors().await.unwrap()), Some((50.0, true)));
+        }
+        assert_eq!(reading(monitor.discover_sensors().await.unwrap()), None);
+
+        std::fs::write(&input, b"52500\xff\n").unwrap();
+        assert_eq!(reading(monitor.discover_sensors().await.unwrap()), Some((52.5, false)));
+        assert!(monitor.last_discovery_from_cache().await);
+    }
+
+    #[tokio::test]
+    async fn replaced_chip_with_same_hwmon_count_is_rediscovered() {
+        let sysfs = FakeSysfs::new("sensor-hotplug")
+            .chip(Chip::new("usbtemp").temp(Temp::new(1, 30_000)));
+        let mut monitor = sysfs.monitor();
+        let (events, kernel) = std::os::unix::net::UnixDatagram::pair().unwrap();
+        events.set_nonblocking(true).unwrap();
+        monitor.hwmon_uevents = Some(HwmonUevents::from_fd(events.into()));
+        monitor.discover_sensors().await.unwrap();
+        monitor.discover_sensors().await.unwrap();
+        assert!(monitor.last_discovery_from_cache().await);
+
+        // A different USB sensor takes the same hwmon slot between polls
+        let dir = sysfs.chip_dir(0);
+        std::fs::remove_dir_all(&dir).unwrap();
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("name"), "drivetemp").unwrap();
+        std::fs::write(dir.join("temp1_input"), "41000").unwrap();
+        for action in ["remove", "add"] {
+            let uevent = format!("{action}@/devices/virtual/hwmon/hwmon0\0ACTION={action}\0SUBSYSTEM=hwmon\0");
+            kernel.send(uevent.as_bytes()).unwrap();
+        }
+
+        let sensors = monitor.discover_sensors().await.unwrap();
+        assert!(!monitor.last_discovery_from_cache().await);
+        assert_eq!(sensors.len(), 1);
+        assert_eq!(sensors[0].chip.as_deref(), Some("drivetemp"));
+    }
+
+    #[test]
+    fn vanished_device_errors_are_recognized() {
+        use crate::hardware::linux::monitor::is_device_gone;
+        let gone = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::ENODEV));
+        let other = anyhow::Error::from(std::io::Error::from_raw_os_error(libc::EIO));
+
+        assert!(is_device_gone(&gone.context("read")));
+        assert!(!is_device_gone(&other));
+    }
+
+    #[tokio::test]
+    async fn discovery_order_is_numeric_by_chip_then_channel() {
+        let mut sysfs = FakeSysfs::new("sensor-order");
+        for i in 0..11 {
+            let chip = Chip::new(&format!("chip{}", i)).temp(Temp::new(1, 30_000));
+            sysfs = sysfs.chip(if i == 2 {
+                chip.temp(Temp::new(10, 31_000)).temp(Temp::new(2, 32_000))
+            } else {
+                chip
+            });
+        }
+        let sensors = sysfs.monitor().discover_hwmon_sensors().await.unwrap();
+        let ids: Vec<_> = sensors.iter().map(|s| &*s.id).collect();
+
+        assert_eq!(ids[..5], ["chip0_sensor_1", "chip1_sensor_1", "chip2_sensor_1", "chip2_sensor_2", "chip2_sensor_10"]);
+        assert_eq!(ids.last(), Some(&"chip10_sensor_1"));
+    }
+
+    #[tokio::test]
+    async fn payload_order_is_by_type_then_id_and_stable_across_cycles() {
+        let sysfs = FakeSysfs::new("sensor-payload-order")
+            .chip(Chip::new("k10temp").temp(Temp::new(10, 50_000)).temp(Temp::new(2, 49_000)).temp(Temp::new(1, 48_000)))
+            .chip(Chip::new("acpitz").temp(Temp::new(1, 40_000)));
+        let monitor = sysfs.monitor();
+
+        let ids = |sensors: Vec<crate::hardware::types::Sensor>| -> Vec<String> { sensors.into_iter().map(|s| s.id.to_string()).collect() };
+        let fresh = ids(monitor.discover_sensors().await.unwrap());
+        // Second cycle is served from the HashMap cache
+        let cached = ids(monitor.discover_sensors().await.unwrap());
+        assert!(monitor.last_discovery_from_cache().await);
+
+        assert_eq!(fresh, ["acpitz_sensor_1", "k10temp_sensor_1", "k10temp_sensor_2", "k10temp_sensor_10"]);
+        assert_eq!(cached, fresh);
+    }
+
+    #[tokio::test]
+    async fn identical_nvme_drives_get_distinct_ids() {
+        let drive = |device: &str| {
+            Chip::new("nvme")
+                .device(device)
+                .temp(Temp::new(1, 41_850).label("Composite").max(81_850).crit(84_850))
+                .temp(Temp::new(2, 45_850).label("Sensor 1").max(65_261_850))
+                .temp(Temp::new(3, 39_850).label("Sensor 2"))
+                .file("device/model", "Samsung SSD 980 PRO 1TB")
+        };
+        let sysfs = FakeSysfs::new("sensor-nvme").chip(drive("nvme0")).chip(drive("nvme1"));
+        let monitor = sysfs.monitor();
+        let sensors = monitor.discover_hwmon_sensors().await.unwrap();
+        let ids: Vec<_> = sensors.iter().map(|s| &*s.id).collect();
+
+        assert_eq!(ids, [
+            "nvme0_composite", "nvme0_sensor_1", "nvme0_sensor_2",
+            "nvme1_composite", "nvme1_sensor_1", "nvme1_sensor_2",
+        ]);
+        assert_eq!(&*sensors[0].name, "nvme nvme0 Composite");
+        assert_eq!(sensors[0].display_name.as_deref(), Some("Samsung SSD 980 PRO 1TB (nvme0) Composite"));
+        assert_eq!(sensors[4].display_name.as_deref(), Some("Samsung SSD 980 PRO 1TB (nvme1) Drive Sensor 1"));
+        assert_eq!(sensors[0].hardware_name.as_deref(), Some("Samsung SSD 980 PRO 1TB"));
+
+        // Limits come from the matching temp index
+        assert_eq!((sensors[0].max_temp, sensors[0].crit_temp), (Some(81.85), Some(84.85)));
+        assert_eq!((sensors[1].max_temp, sensors[1].crit_temp), (Some(65261.85), None));
+        assert_eq!((sensors[2].max_temp, sensors[2].crit_temp), (None, None));
+
+        // Both drives survive the id-keyed path cache
+        assert_eq!(monitor.discover_sensors().await.unwrap().len(), 6);
+    }
+
+    /// drivetemp layout: hwmon `device` -> SCSI device, block dev beneath it,
+    /// /sys/class/block/sdX -> that block dir.
+    fn sata_drive(sysfs: FakeSysfs, scsi: &str, block: &str, model: &str) -> FakeSysfs {
+        let scsi_dir = format!("devices/{}", scsi);
+        sysfs
+            .chip(Chip::new("drivetemp").device(scsi).temp(Temp::new(1, 36_000).max(60_000)).file("device/model", model))
+            .file(&format!("{}/block/{}/size", scsi_dir, block), "15628053168")
+            .file(&format!("{}/block/{}/{}1/size", scsi_dir, block, block), "15628051087")
+            .symlink(&format!("class/block/{}", block), &format!("{}/block/{}", scsi_dir, block))
+            .symlink(&format!("class/block/{}1", block), &format!("{}/block/{}/{}1", scsi_dir, block, block))
+            .symlink(&format!("{}/block/{}/device", scsi_dir, block), &scsi_dir)
+    }
+
+    #[tokio::test]
+    async fn sata_drives_are_named_by_model_and_block_device() {
+        let sysfs = FakeSysfs::new("sensor-sata");
+        let sysfs = sata_drive(sysfs, "0:0:0:0", "sda", "WDC WD80EFAX-68L");
+        let sysfs = sata_drive(sysfs, "1:0:0:0", "sdb", "WDC WD80EFAX-68L");
+        let monitor = sysfs.monitor();
+        let sensors = monitor.discover_hwmon_sensors().await.unwrap();
+
+        assert_eq!(sensors.len(), 2);
+        assert_eq!(&*sensors[0].id, "sda_sensor_1");
+        assert_eq!(&*sensors[1].id, "sdb_sensor_1");
+        assert_eq!(&*sensors[0].sensor_type, "hdd");
+        assert_eq!(&*sensors[0].name, "drivetemp sda Sensor 1");
+        assert_eq!(sensors[0].display_name.as_deref(), Some("Storage WD WDC WD80EFAX-68L (sda)"));
+        assert_eq!(sensors[1].hardware_name.as_deref(), Some("WDC WD80EFAX-68L"));
+
+        // Whole disk wins over its partition; cached per SCSI device, not per shared chip name
+        assert_eq!(monitor.scsi_block_device(&sysfs.chip_dir(1)).as_deref(), Some("sdb"));
+        assert_eq!(monitor.storage_cache.read().await.len(), 2);
+    }
+
+    #[test]
+    fn classify_sensor_type_mappings() {
+        for (chip, expected) in [
+            ("k10temp", "cpu"),
+            ("coretemp", "cpu"),
+            ("cpu_thermal", "cpu"),
+            ("nvme", "nvme"),
+            ("it8686", "motherboard"),
+            ("nct6798", "motherboard"),
+            ("acpitz", "acpi"),
+            ("amdgpu", "other"),
+            ("drivetemp", "hdd"),
+            ("ipmi_si", "ipmi"),
+        ] {
+            assert_eq!(LinuxHardwareMonitor::classify_sensor_type(chip), expected, "{}", chip);
+        }
+    }
+
+    #[test]
+    fn friendly_chip_name_mappings() {
+        for (chip, expected) in [
+            ("k10temp", "CPU AMD"),
+            ("coretemp", "CPU Intel"),
+            ("cpu_thermal", "CPU"),
+            ("nvme", "Storage"),
+            ("nvme-samsung", "Storage Samsung"),
+            ("drivetemp", "Storage"),
+            ("it8686", "Motherboard ITE"),
+            ("nct6798", "Motherboard Nuvoton"),
+            ("acpitz", "ACPI"),
+            ("amdgpu", "amdgpu"),
+        ] {
+            assert_eq!(LinuxHardwareMonitor::get_friendly_chip_name(chip), expected, "{}", chip);
+        }
+    }
+}
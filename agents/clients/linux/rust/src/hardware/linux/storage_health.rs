@@ -0,0 +1,198 @@
+//! SMART drive health for storage sensors via `smartctl -j` (`hardware.storage_health`).
+//!
+//! The drives are the ones storage sensors were discovered on (nvme0, sda - the prefix
+//! of their sensor ids). Each is read at most hourly, in the background, with
+//! `-n standby` so a spun-down disk is skipped instead of woken; the last reading is
+//! kept until a newer one succeeds. Without smartctl, or without permission to open a
+//! device, drives get no health entry and report temperatures only, as before.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use serde_json::Value;
+use tracing::{debug, info};
+
+use crate::hardware::types::DriveHealth;
+
+/// Install locations of `smartctl` (smartmontools).
+const SMARTCTL_PATHS: &[&str] = &["/usr/sbin/smartctl", "/usr/bin/smartctl", "/sbin/smartctl", "/usr/local/sbin/smartctl"];
+
+/// How often a drive is read. SMART data changes slowly; reading it is a disk command.
+const REFRESH_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// A drive that doesn't answer within this long is skipped until the next refresh.
+const SMARTCTL_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// ATA attributes reported: reallocated and pending sectors.
+const ATA_REALLOCATED_SECTORS: u64 = 5;
+const ATA_PENDING_SECTORS: u64 = 197;
+
+/// Optional smartctl-backed source. Present only when enabled and `smartctl` is installed.
+pub(crate) struct StorageHealthSource {
+    smartctl: PathBuf,
+    /// Drives to read, with when each was last tried
+    devices: Mutex<BTreeMap<String, Option<Instant>>>,
+    health: Mutex<BTreeMap<String, DriveHealth>>,
+}
+
+impl StorageHealthSource {
+    /// Returns `None` (not an error) when `smartctl` is absent.
+    pub(crate) fn try_init() -> Option<Self> {
+        let Some(smartctl) = SMARTCTL_PATHS.iter().map(Path::new).find(|p| p.exists()) else {
+            info!("storage_health is enabled but smartctl is not installed; drives report temperature only");
+            return None;
+        };
+        debug!("smartctl found: {:?}", smartctl);
+        Some(Self {
+            smartctl: smartctl.to_path_buf(),
+            devices: Mutex::new(BTreeMap::new()),
+            health: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// A storage sensor was discovered on `device`; read it from the next refresh on.
+    pub(crate) fn watch(&self, device: &str) {
+        self.devices.lock().unwrap().entry(device.to_string()).or_insert(None);
+    }
+
+    /// The last reading of every drive that has one.
+    pub(crate) fn snapshot(&self) -> Vec<DriveHealth> {
+        self.health.lock().unwrap().values().cloned().collect()
+    }
+
+    /// Read, in the background, the drives not read within `REFRESH_INTERVAL`.
+    pub(crate) fn refresh_if_due(self: &Arc<Self>) {
+        let now = Instant::now();
+        let due: Vec<String> = self.devices.lock().unwrap()
+            .iter_mut()
+            .filter(|(_, last)| last.is_none_or(|last| now.duration_since(last) >= REFRESH_INTERVAL))
+            .map(|(device, last)| {
+                *last = Some(now);
+                device.clone()
+            })
+            .collect();
+        if due.is_empty() {
+            return;
+        }
+
+        let source = Arc::clone(self);
+        tokio::spawn(async move {
+            for device in due {
+                match source.read(&device).await {
+                    Ok(Some(health)) => {
+                        source.health.lock().unwrap().insert(device, health);
+                    }
+                    Ok(None) => debug!("{} is in standby; SMART health not read", device),
+                    Err(e) => debug!("SMART health of {} unavailable: {:#}", device, e),
+                }
+            }
+        });
+    }
+
+    async fn read(&self, device: &str) -> Result<Option<DriveHealth>> {
+        let output = tokio::time::timeout(
+            SMARTCTL_TIMEOUT,
+            tokio::process::Command::new(&self.smartctl)
+                .args(["-j", "-i", "-H", "-A", "-n", "standby"])
+                .arg(Path::new("/dev").join(device))
+                .kill_on_drop(true)
+                .output(),
+        )
+        .await
+        .map_err(|_| anyhow!("smartctl timed out after {}s", SMARTCTL_TIMEOUT.as_secs()))?
+        .context("Failed to execute smartctl")?;
+        parse_smartctl(device, &output.stdout, output.status.code(), chrono::Utc::now().timestamp_millis())
+    }
+}
+
+/// Health from `smartctl -j` output; `None` when the drive was in standby.
+///
+/// The exit status is a bit mask: bits 0-1 mean no data (bad arguments, or the device
+/// could not be opened - which includes standby with `-n standby`), bit 2 a failed
+/// SMART command, and the higher bits are the drive's own findings, kept in the data.
+pub(crate) fn parse_smartctl(device: &str, stdout: &[u8], exit_code: Option<i32>, checked_at: i64) -> Result<Option<DriveHealth>> {
+    let json: Value = serde_json::from_slice(stdout).context("smartctl output is not JSON")?;
+    let code = exit_code.ok_or_else(|| anyhow!("smartctl was killed"))?;
+    if code & 0b11 != 0 {
+        let messages: Vec<&str> = json.pointer("/smartctl/messages")
+            .and_then(Value::as_array)
+            .map(|messages| messages.iter().filter_map(|m| m.get("string").and_then(Value::as_str)).collect())
+            .unwrap_or_default();
+        if messages.iter().any(|m| m.contains("STANDBY")) {
+            return Ok(None);
+        }
+        bail!("smartctl exit status {}: {}", code, messages.join("; "));
+    }
+
+    let number = |pointer: &str| json.pointer(pointer).and_then(Value::as_u64);
+    let ata_attribute = |id: u64| {
+        json.pointer("/ata_smart_attributes/table")?
+            .as_array()?
+            .iter()
+            .find(|attribute| attribute.get("id").and_then(Value::as_u64) == Some(id))?
+            .pointer("/raw/value")?
+            .as_u64()
+    };
+    Ok(Some(DriveHealth {
+        device: device.to_string(),
+        model: json.get("model_name").and_then(Value::as_str).map(|m| m.trim().to_string()),
+        passed: json.pointer("/smart_status/passed").and_then(Value::as_bool),
+        power_on_hours: number("/power_on_time/hours"),
+        media_errors: number("/nvme_smart_health_information_log/media_errors"),
+        percentage_used: number("/nvme_smart_health_information_log/percentage_used"),
+        reallocated_sectors: ata_attribute(ATA_REALLOCATED_SECTORS),
+        pending_sectors: ata_attribute(ATA_PENDING_SECTORS),
+        checked_at,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_nvme_and_ata_health_and_skips_standby() {
+        let nvme = br#"{
+            "smartctl": {"version": [7, 3], "exit_status": 0},
+            "device": {"name": "/dev/nvme0", "type": "nvme"},
+            "model_name": "Samsung SSD 980 PRO 1TB",
+            "smart_status": {"passed": true, "nvme": {"value": 0}},
+            "nvme_smart_health_information_log": {"temperature": 41, "percentage_used": 3, "power_on_hours": 5120, "media_errors": 0},
+            "power_on_time": {"hours": 5120}
+        }"#;
+        let health = parse_smartctl("nvme0", nvme, Some(0), 1_700_000_000_000).unwrap().unwrap();
+        assert_eq!(health.model.as_deref(), Some("Samsung SSD 980 PRO 1TB"));
+        assert_eq!(health.passed, Some(true));
+        assert_eq!((health.power_on_hours, health.percentage_used, health.media_errors), (Some(5120), Some(3), Some(0)));
+        assert_eq!(health.reallocated_sectors, None);
+        assert_eq!(health.checked_at, 1_700_000_000_000);
+
+        // A failing drive sets exit bit 3: still data
+        let ata = br#"{
+            "smartctl": {"exit_status": 8},
+            "model_name": "WDC WD80EFAX-68LHPN0",
+            "smart_status": {"passed": false},
+            "ata_smart_attributes": {"table": [
+                {"id": 5, "name": "Reallocated_Sector_Ct", "raw": {"value": 24, "string": "24"}},
+                {"id": 9, "name": "Power_On_Hours", "raw": {"value": 31000, "string": "31000"}},
+                {"id": 197, "name": "Current_Pending_Sector", "raw": {"value": 2, "string": "2"}}
+            ]},
+            "power_on_time": {"hours": 31000}
+        }"#;
+        let health = parse_smartctl("sda", ata, Some(8), 0).unwrap().unwrap();
+        assert_eq!(health.passed, Some(false));
+        assert_eq!((health.reallocated_sectors, health.pending_sectors), (Some(24), Some(2)));
+        assert_eq!(health.percentage_used, None);
+
+        let standby = br#"{"smartctl": {"exit_status": 2, "messages": [{"string": "Device is in STANDBY mode, exit(2)", "severity": "information"}]}}"#;
+        assert_eq!(parse_smartctl("sdb", standby, Some(2), 0).unwrap(), None);
+
+        let denied = br#"{"smartctl": {"exit_status": 2, "messages": [{"string": "Smartctl open device: /dev/sdb failed: Permission denied", "severity": "error"}]}}"#;
+        let error = parse_smartctl("sdb", denied, Some(2), 0).unwrap_err().to_string();
+        assert!(error.contains("Permission denied"), "{}", error);
+        assert!(parse_smartctl("sdb", b"", Some(0), 0).is_err());
+    }
+}
@@ -0,0 +1,180 @@
+//! Per-sensor temperature smoothing (`hardware.temperature_smoothing`).
+//!
+//! Applied to each discovery before the readings leave the hardware layer, so noisy
+//! sensors (k10temp jumping several °C between cycles) don't make curve-driven fan
+//! commands oscillate. The instantaneous value stays available as `raw_temperature`,
+//! which the emergency and crit checks use.
+//!
+//! The history moves on once per data cycle (`next_cycle`), not per discovery:
+//! `requestData` frames, diagnostics and other reads in between are smoothed against
+//! it without adding to it, so `window` counts cycles whoever else reads the sensors.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
+
+use crate::config::types::TemperatureSmoothing;
+
+use super::types::Sensor;
+
+/// Running state per sensor id.
+enum History {
+    Window(VecDeque<f64>),
+    Exponential(f64),
+}
+
+pub struct SensorSmoother {
+    mode: TemperatureSmoothing,
+    history: HashMap<Arc<str>, History>,
+    /// `next_cycle` was called: the next `apply` adds its readings to the history
+    cycle_pending: bool,
+}
+
+impl SensorSmoother {
+    pub fn new(mode: TemperatureSmoothing) -> Self {
+        Self { mode, history: HashMap::new(), cycle_pending: false }
+    }
+
+    /// Switch modes; the accumulated history belongs to the old one and is dropped.
+    pub fn set_mode(&mut self, mode: TemperatureSmoothing) {
+        if mode != self.mode {
+            self.mode = mode;
+            self.history.clear();
+        }
+    }
+
+    /// Forget every sensor's history (sensor cache invalidated / hardware changed).
+    pub fn reset(&mut self) {
+        self.history.clear();
+    }
+
+    /// A data cycle begins: the next `apply` is its discovery and moves the history on.
+    pub fn next_cycle(&mut self) {
+        self.cycle_pending = true;
+    }
+
+    /// Replace each sensor's `temperature` with its smoothed value and record the
+    /// reading in `raw_temperature`. A sensor's first reading is reported as is.
+    /// Outside a data cycle the history is read, not changed.
+    pub fn apply(&mut self, sensors: &mut [Sensor]) {
+        let advance = std::mem::take(&mut self.cycle_pending);
+        if self.mode.is_off() {
+            return;
+        }
+
+        for sensor in sensors.iter_mut() {
+            let raw = sensor.temperature;
+            let smoothed = match self.mode {
+                TemperatureSmoothing::Off => raw,
+                _ if !advance => self.peek(&sensor.id, raw),
+                TemperatureSmoothing::MovingAverage { window } => {
+                    let entry = self.history.entry(sensor.id.clone())
+                        .or_insert_with(|| History::Window(VecDeque::with_capacity(window)));
+                    let History::Window(readings) = entry else { unreachable!("history matches mode") };
+                    if readings.len() >= window {
+                        readings.pop_front();
+                    }
+                    readings.push_back(raw);
+                    readings.iter().sum::<f64>() / readings.len() as f64
+                }
+                TemperatureSmoothing::Exponential { alpha } => {
+                    let entry = self.history.entry(sensor.id.clone()).or_insert(History::Exponential(raw));
+                    let History::Exponential(value) = entry else { unreachable!("history matches mode") };
+                    *value = alpha * raw + (1.0 - alpha) * *value;
+                    *value
+                }
+            };
+            sensor.temperature = (smoothed * 10.0).round() / 10.0;
+            sensor.raw_temperature = Some(raw);
+        }
+
+        // Sensors that disappeared must not resume from an old average later
+        if advance {
+            let present: HashSet<&str> = sensors.iter().map(|s| &*s.id).collect();
+            self.history.retain(|id, _| present.contains(&**id));
+        }
+    }
+
+    /// The value `raw` would be smoothed to in a data cycle, leaving the history as is.
+    fn peek(&self, id: &str, raw: f64) -> f64 {
+        match (self.mode, self.history.get(id)) {
+            (TemperatureSmoothing::MovingAverage { window }, Some(History::Window(readings))) => {
+                let kept = readings.len().min(window.saturating_sub(1));
+                let earlier = readings.iter().skip(readings.len() - kept);
+                (earlier.sum::<f64>() + raw) / (kept + 1) as f64
+            }
+            (TemperatureSmoothing::Exponential { alpha }, Some(History::Exponential(value))) => alpha * raw + (1.0 - alpha) * value,
+            _ => raw,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reading(temperature: f64) -> Vec<Sensor> {
+        vec![serde_json::from_value(serde_json::json!({
+            "id": "k10temp_tctl", "name": "Tctl", "temperature": temperature, "type": "cpu",
+        }))
+        .unwrap()]
+    }
+
+    fn run(smoother: &mut SensorSmoother, temperatures: &[f64]) -> Vec<f64> {
+        temperatures
+            .iter()
+            .map(|&t| {
+                let mut sensors = reading(t);
+                smoother.next_cycle();
+                smoother.apply(&mut sensors);
+                assert_eq!(sensors[0].instant_temperature(), t);
+                sensors[0].temperature
+            })
+            .collect()
+    }
+
+    #[test]
+    fn moving_average_over_window() {
+        let mut smoother = SensorSmoother::new(TemperatureSmoothing::MovingAverage { window: 3 });
+        assert_eq!(run(&mut smoother, &[50.0, 56.0, 50.0, 62.0]), [50.0, 53.0, 52.0, 56.0]);
+
+        // Invalidation starts over instead of bridging old readings
+        smoother.reset();
+        assert_eq!(run(&mut smoother, &[40.0]), [40.0]);
+    }
+
+    #[test]
+    fn reads_between_cycles_leave_the_history_alone() {
+        let mut smoother = SensorSmoother::new(TemperatureSmoothing::MovingAverage { window: 3 });
+        assert_eq!(run(&mut smoother, &[50.0, 56.0]), [50.0, 53.0]);
+
+        // Smoothed as a cycle would be, but without adding to the window
+        for _ in 0..5 {
+            let mut sensors = reading(62.0);
+            smoother.apply(&mut sensors);
+            assert_eq!((sensors[0].temperature, sensors[0].instant_temperature()), (56.0, 62.0));
+        }
+        assert_eq!(run(&mut smoother, &[50.0, 62.0]), [52.0, 56.0]);
+
+        let mut smoother = SensorSmoother::new(TemperatureSmoothing::Exponential { alpha: 0.5 });
+        assert_eq!(run(&mut smoother, &[50.0]), [50.0]);
+        let mut sensors = reading(56.0);
+        smoother.apply(&mut sensors);
+        assert_eq!(sensors[0].temperature, 53.0);
+        assert_eq!(run(&mut smoother, &[56.0]), [53.0]);
+    }
+
+    #[test]
+    fn exponential_smoothing() {
+        let mut smoother = SensorSmoother::new(TemperatureSmoothing::Exponential { alpha: 0.5 });
+        assert_eq!(run(&mut smoother, &[50.0, 56.0, 50.0]), [50.0, 53.0, 51.5]);
+    }
+
+    #[test]
+    fn off_leaves_readings_untouched() {
+        let mut smoother = SensorSmoother::new(TemperatureSmoothing::Off);
+        let mut sensors = reading(51.3);
+        smoother.apply(&mut sensors);
+        assert_eq!(sensors[0].temperature, 51.3);
+        assert!(sensors[0].raw_temperature.is_none());
+    }
+}
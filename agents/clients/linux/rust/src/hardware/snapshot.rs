@@ -0,0 +1,319 @@
+//! Compact record of discovered hardware, persisted between runs so that sensors and
+//! fans appearing, disappearing or being renamed (hardware swap, kernel driver rename)
+//! are reported instead of silently breaking dashboard history.
+//!
+//! The snapshot is saved after each full discovery; the first discovery of a run is
+//! compared against the snapshot left by the previous run. Renames are matched by
+//! source path first (same file, new id), then by label (same label, different chip).
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, warn};
+
+use super::types::{Fan, Sensor};
+
+/// File name of the snapshot, next to the agent binary.
+pub const SNAPSHOT_FILE: &str = "hardware-snapshot.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SnapshotEntry {
+    pub id: String,
+    pub label: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub chip: Option<String>,
+    /// sysfs file the reading comes from (`tempN_input` / `pwmN`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct HardwareSnapshot {
+    pub sensors: Vec<SnapshotEntry>,
+    pub fans: Vec<SnapshotEntry>,
+}
+
+/// How a removed entry was matched to an added one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RenameMatch {
+    SamePath,
+    SameLabel,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Renamed {
+    pub from: SnapshotEntry,
+    pub to: SnapshotEntry,
+    pub matched_by: RenameMatch,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct EntryChanges {
+    pub added: Vec<SnapshotEntry>,
+    pub removed: Vec<SnapshotEntry>,
+    pub renamed: Vec<Renamed>,
+}
+
+impl EntryChanges {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.renamed.is_empty()
+    }
+}
+
+/// Difference between the previous run's hardware and this one's.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct HardwareChanges {
+    pub sensors: EntryChanges,
+    pub fans: EntryChanges,
+}
+
+impl HardwareChanges {
+    pub fn is_empty(&self) -> bool {
+        self.sensors.is_empty() && self.fans.is_empty()
+    }
+
+    /// One line per change, for the log.
+    pub fn describe(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        for (kind, changes) in [("sensor", &self.sensors), ("fan", &self.fans)] {
+            for r in &changes.renamed {
+                let why = match r.matched_by {
+                    RenameMatch::SamePath => format!("same path {}", r.to.path.as_deref().unwrap_or("?")),
+                    RenameMatch::SameLabel => format!("same label '{}'", r.to.label),
+                };
+                lines.push(format!("renamed {} {} -> {} ({})", kind, r.from.id, r.to.id, why));
+            }
+            for e in &changes.removed {
+                lines.push(format!("removed {} {} '{}'", kind, e.id, e.label));
+            }
+            for e in &changes.added {
+                lines.push(format!("added {} {} '{}'", kind, e.id, e.label));
+            }
+        }
+        lines
+    }
+}
+
+impl HardwareSnapshot {
+    pub fn from_discovery(sensors: &[Sensor], fans: &[Fan]) -> Self {
+        let mut snapshot = Self {
+            sensors: sensors
+                .iter()
+                .map(|s| SnapshotEntry {
+                    id: s.id.to_string(),
+                    label: s.friendly_name().to_string(),
+                    chip: s.chip.as_deref().map(str::to_string),
+                    path: s.source.as_deref().map(str::to_string),
+                })
+                .collect(),
+            fans: fans
+                .iter()
+                .map(|f| SnapshotEntry {
+                    id: f.id.clone(),
+                    label: f.name.clone(),
+                    chip: None,
+                    path: f.pwm_file.clone(),
+                })
+                .collect(),
+        };
+        snapshot.sensors.sort_by(|a, b| a.id.cmp(&b.id));
+        snapshot.fans.sort_by(|a, b| a.id.cmp(&b.id));
+        snapshot
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path).with_context(|| format!("Failed to read {:?}", path))?;
+        serde_json::from_str(&content).with_context(|| format!("Failed to parse {:?}", path))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?).with_context(|| format!("Failed to write {:?}", path))
+    }
+
+    /// What changed going from `self` (previous run) to `current`.
+    pub fn diff(&self, current: &HardwareSnapshot) -> HardwareChanges {
+        HardwareChanges {
+            sensors: diff_entries(&self.sensors, &current.sensors),
+            fans: diff_entries(&self.fans, &current.fans),
+        }
+    }
+}
+
+fn diff_entries(previous: &[SnapshotEntry], current: &[SnapshotEntry]) -> EntryChanges {
+    let previous_ids: BTreeSet<&str> = previous.iter().map(|e| e.id.as_str()).collect();
+    let current_ids: BTreeSet<&str> = current.iter().map(|e| e.id.as_str()).collect();
+    let mut removed: Vec<&SnapshotEntry> = previous.iter().filter(|e| !current_ids.contains(e.id.as_str())).collect();
+    let mut added: Vec<&SnapshotEntry> = current.iter().filter(|e| !previous_ids.contains(e.id.as_str())).collect();
+    let mut renamed = Vec::new();
+
+    // Same source file under a new id (driver renamed the chip)
+    removed.retain(|old| {
+        let Some(path) = old.path.as_deref() else { return true };
+        match added.iter().position(|new| new.path.as_deref() == Some(path)) {
+            Some(i) => {
+                let new = added.remove(i);
+                renamed.push(Renamed { from: (*old).clone(), to: new.clone(), matched_by: RenameMatch::SamePath });
+                false
+            }
+            None => true,
+        }
+    });
+
+    // Same label on a different chip; only when the label is unambiguous on both
+    // sides, since generic labels ("temp1", "Composite") repeat across chips
+    let count = |entries: &[&SnapshotEntry]| {
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        for e in entries {
+            *counts.entry(e.label.clone()).or_default() += 1;
+        }
+        counts
+    };
+    let (removed_labels, added_labels) = (count(&removed), count(&added));
+    removed.retain(|old| {
+        let unique = !old.label.is_empty() && removed_labels.get(&old.label) == Some(&1) && added_labels.get(&old.label) == Some(&1);
+        if !unique {
+            return true;
+        }
+        match added.iter().position(|new| new.label == old.label && new.chip != old.chip) {
+            Some(i) => {
+                let new = added.remove(i);
+                renamed.push(Renamed { from: (*old).clone(), to: new.clone(), matched_by: RenameMatch::SameLabel });
+                false
+            }
+            None => true,
+        }
+    });
+
+    EntryChanges {
+        added: added.into_iter().cloned().collect(),
+        removed: removed.into_iter().cloned().collect(),
+        renamed,
+    }
+}
+
+/// Saves the snapshot after discoveries and keeps the startup comparison.
+#[derive(Debug)]
+pub struct SnapshotTracker {
+    path: PathBuf,
+    last_saved: Option<HardwareSnapshot>,
+    compared: bool,
+    startup_changes: Option<HardwareChanges>,
+}
+
+impl SnapshotTracker {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path, last_saved: None, compared: false, startup_changes: None }
+    }
+
+    /// Snapshot next to the agent binary.
+    pub fn beside_executable() -> Self {
+        let path = std::env::current_exe()
+            .ok()
+            .and_then(|p| p.parent().map(|d| d.join(SNAPSHOT_FILE)))
+            .unwrap_or_else(|| PathBuf::from(SNAPSHOT_FILE));
+        Self::new(path)
+    }
+
+    /// Record a successful discovery. The first call of a run compares against the
+    /// previous run's snapshot and logs the differences. Returns those startup
+    /// changes (`None` when there was no previous snapshot).
+    pub fn observe(&mut self, sensors: &[Sensor], fans: &[Fan]) -> Option<&HardwareChanges> {
+        let current = HardwareSnapshot::from_discovery(sensors, fans);
+
+        if !self.compared {
+            self.compared = true;
+            match HardwareSnapshot::load(&self.path) {
+                Ok(previous) => {
+                    let changes = previous.diff(&current);
+                    if changes.is_empty() {
+                        debug!("Hardware unchanged since last run");
+                    } else {
+                        info!("Hardware changed since last run:");
+                        for line in changes.describe() {
+                            info!("  {}", line);
+                        }
+                    }
+                    self.startup_changes = Some(changes);
+                }
+                Err(e) if self.path.exists() => warn!("Ignoring previous hardware snapshot: {:#}", e),
+                Err(_) => debug!("No previous hardware snapshot at {:?}", self.path),
+            }
+        }
+
+        if self.last_saved.as_ref() != Some(&current) {
+            match current.save(&self.path) {
+                Ok(()) => self.last_saved = Some(current),
+                Err(e) => warn!("Failed to save hardware snapshot: {:#}", e),
+            }
+        }
+
+        self.startup_changes.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(id: &str, label: &str, chip: &str, path: &str) -> SnapshotEntry {
+        SnapshotEntry {
+            id: id.to_string(),
+            label: label.to_string(),
+            chip: Some(chip.to_string()),
+            path: Some(path.to_string()),
+        }
+    }
+
+    fn snapshot(sensors: Vec<SnapshotEntry>) -> HardwareSnapshot {
+        HardwareSnapshot { sensors, fans: Vec::new() }
+    }
+
+    #[test]
+    fn same_path_new_id_is_a_rename() {
+        let before = snapshot(vec![entry("k10temp_tctl", "Tctl", "k10temp", "/sys/class/hwmon/hwmon2/temp1_input")]);
+        let after = snapshot(vec![entry("zenpower_tctl", "Tdie", "zenpower", "/sys/class/hwmon/hwmon2/temp1_input")]);
+
+        let changes = before.diff(&after);
+        assert!(changes.sensors.added.is_empty() && changes.sensors.removed.is_empty());
+        assert_eq!(changes.sensors.renamed[0].from.id, "k10temp_tctl");
+        assert_eq!(changes.sensors.renamed[0].to.id, "zenpower_tctl");
+        assert_eq!(changes.sensors.renamed[0].matched_by, RenameMatch::SamePath);
+    }
+
+    #[test]
+    fn same_label_different_chip_is_a_rename() {
+        let before = snapshot(vec![entry("it8686_cpu", "CPU Temp", "it8686", "/sys/class/hwmon/hwmon3/temp1_input")]);
+        let after = snapshot(vec![entry("it8688_cpu", "CPU Temp", "it8688", "/sys/class/hwmon/hwmon4/temp1_input")]);
+
+        let changes = before.diff(&after);
+        assert_eq!(changes.sensors.renamed.len(), 1);
+        assert_eq!(changes.sensors.renamed[0].matched_by, RenameMatch::SameLabel);
+        assert_eq!(
+            changes.describe(),
+            vec!["renamed sensor it8686_cpu -> it8688_cpu (same label 'CPU Temp')".to_string()]
+        );
+    }
+
+    #[test]
+    fn ambiguous_labels_are_reported_as_added_and_removed() {
+        let before = snapshot(vec![
+            entry("nvme0_composite", "Composite", "nvme", "/sys/class/hwmon/hwmon1/temp1_input"),
+            entry("nvme1_composite", "Composite", "nvme", "/sys/class/hwmon/hwmon2/temp1_input"),
+        ]);
+        let after = snapshot(vec![entry("drivetemp_composite", "Composite", "drivetemp", "/sys/class/hwmon/hwmon5/temp1_input")]);
+
+        let changes = before.diff(&after);
+        assert!(changes.sensors.renamed.is_empty());
+        assert_eq!(changes.sensors.removed.len(), 2);
+        assert_eq!(changes.sensors.added[0].id, "drivetemp_composite");
+    }
+
+    #[test]
+    fn unchanged_hardware_has_no_changes() {
+        let sensors = vec![entry("k10temp_tctl", "Tctl", "k10temp", "/sys/class/hwmon/hwmon2/temp1_input")];
+        assert!(snapshot(sensors.clone()).diff(&snapshot(sensors)).is_empty());
+    }
+}
@@ -0,0 +1,209 @@
+//! Per-sensor temperature statistics kept by the agent itself: min/max/average since
+//! the process started, and over a rolling window (`sensor_stats_window_hours`) so the
+//! last day's peak is known even if the backend wasn't recording.
+//!
+//! The window is a fixed ring of hourly buckets per sensor (count/sum/min/max), so
+//! memory does not grow with uptime. Entries are keyed by sensor id and survive
+//! sensor cache invalidation; only `resetSensorStats` clears them.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use super::types::Sensor;
+
+const HOUR_MS: i64 = 3_600_000;
+
+/// count/sum/min/max of a set of readings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Aggregate {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Aggregate {
+    const EMPTY: Self = Self { count: 0, sum: 0.0, min: f64::INFINITY, max: f64::NEG_INFINITY };
+
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    fn summary(&self) -> Option<StatSummary> {
+        (self.count > 0).then(|| StatSummary {
+            min: self.min,
+            max: self.max,
+            avg: ((self.sum / self.count as f64) * 10.0).round() / 10.0,
+            samples: self.count,
+        })
+    }
+}
+
+/// One hour of readings; `hour` is the hour since the epoch it covers (`None` for a
+/// slot that was never used).
+#[derive(Debug, Clone, Copy)]
+struct Bucket {
+    hour: Option<i64>,
+    aggregate: Aggregate,
+}
+
+struct SensorRecord {
+    since_start: Aggregate,
+    /// Indexed by `hour % window_hours`; a slot whose `hour` is stale is reused
+    ring: Vec<Bucket>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatSummary {
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub samples: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorStatsEntry {
+    pub since_start: StatSummary,
+    /// `None` once the sensor has had no reading for a whole window
+    pub window: Option<StatSummary>,
+}
+
+/// What `getSensorStats` and registration report.
+#[derive(Debug, Clone, Serialize)]
+pub struct SensorStatsReport {
+    pub window_hours: u32,
+    /// Since-start figures cover readings from this time (ms), i.e. start or last reset
+    pub since: i64,
+    pub sensors: BTreeMap<String, SensorStatsEntry>,
+}
+
+pub struct SensorStats {
+    window_hours: u32,
+    since: i64,
+    sensors: HashMap<Arc<str>, SensorRecord>,
+}
+
+impl SensorStats {
+    pub fn new(window_hours: u32, now_ms: i64) -> Self {
+        Self { window_hours: window_hours.max(1), since: now_ms, sensors: HashMap::new() }
+    }
+
+    /// Record one reading per sensor (the instantaneous value, not the smoothed one).
+    pub fn record(&mut self, sensors: &[Sensor], now_ms: i64) {
+        let hour = now_ms.div_euclid(HOUR_MS);
+        let slots = self.window_hours as usize;
+        for sensor in sensors {
+            let value = sensor.instant_temperature();
+            let record = self.sensors.entry(sensor.id.clone()).or_insert_with(|| SensorRecord {
+                since_start: Aggregate::EMPTY,
+                ring: vec![Bucket { hour: None, aggregate: Aggregate::EMPTY }; slots],
+            });
+            record.since_start.add(value);
+
+            let bucket = &mut record.ring[hour.rem_euclid(slots as i64) as usize];
+            if bucket.hour != Some(hour) {
+                *bucket = Bucket { hour: Some(hour), aggregate: Aggregate::EMPTY };
+            }
+            bucket.aggregate.add(value);
+        }
+    }
+
+    pub fn report(&self, now_ms: i64) -> SensorStatsReport {
+        let hour = now_ms.div_euclid(HOUR_MS);
+        let window = i64::from(self.window_hours);
+        let sensors = self.sensors.iter()
+            .filter_map(|(id, record)| {
+                let mut in_window = Aggregate::EMPTY;
+                // Unused slots, and hours ahead of `now_ms` (clock stepped back), are skipped
+                let in_range = |b: &&Bucket| {
+                    b.hour.and_then(|h| hour.checked_sub(h)).is_some_and(|age| (0..window).contains(&age))
+                };
+                for bucket in record.ring.iter().filter(in_range) {
+                    in_window.merge(&bucket.aggregate);
+                }
+                let entry = SensorStatsEntry {
+                    since_start: record.since_start.summary()?,
+                    window: in_window.summary(),
+                };
+                Some((id.to_string(), entry))
+            })
+            .collect();
+        SensorStatsReport { window_hours: self.window_hours, since: self.since, sensors }
+    }
+
+    /// `resetSensorStats`: start over from `now_ms`.
+    pub fn reset(&mut self, now_ms: i64) {
+        self.sensors.clear();
+        self.since = now_ms;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cpu(temperature: f64) -> Vec<Sensor> {
+        vec![serde_json::from_value(serde_json::json!({
+            "id": "k10temp_tctl", "name": "Tctl", "temperature": temperature, "type": "cpu",
+        }))
+        .unwrap()]
+    }
+
+    #[test]
+    fn window_forgets_hours_that_rolled_out() {
+        let mut stats = SensorStats::new(3, 0);
+        stats.record(&cpu(90.0), 0); // hour 0: the overnight spike
+        stats.record(&cpu(50.0), HOUR_MS);
+        stats.record(&cpu(60.0), 2 * HOUR_MS + 5);
+
+        let entry = &stats.report(2 * HOUR_MS + 10).sensors["k10temp_tctl"];
+        assert_eq!(entry.window.as_ref().unwrap().max, 90.0);
+        assert_eq!(entry.since_start.samples, 3);
+
+        // Hour 3 reuses hour 0's slot; the spike leaves the window but not since_start
+        stats.record(&cpu(55.0), 3 * HOUR_MS);
+        let entry = &stats.report(3 * HOUR_MS).sensors["k10temp_tctl"];
+        let window = entry.window.as_ref().unwrap();
+        assert_eq!((window.min, window.max, window.avg, window.samples), (50.0, 60.0, 55.0, 3));
+        assert_eq!(entry.since_start.max, 90.0);
+
+        // Nothing recorded for a whole window
+        assert!(stats.report(10 * HOUR_MS).sensors["k10temp_tctl"].window.is_none());
+    }
+
+    #[test]
+    fn partly_filled_ring_reports_only_used_hours() {
+        let mut stats = SensorStats::new(24, 0);
+        stats.record(&cpu(40.0), 5 * HOUR_MS);
+        stats.record(&cpu(44.0), 6 * HOUR_MS);
+
+        // 22 slots were never used
+        let window = stats.report(6 * HOUR_MS).sensors["k10temp_tctl"].window.clone().unwrap();
+        assert_eq!((window.min, window.max, window.samples), (40.0, 44.0, 2));
+        // A clock that went back doesn't count future hours
+        let window = stats.report(5 * HOUR_MS).sensors["k10temp_tctl"].window.clone().unwrap();
+        assert_eq!(window.samples, 1);
+    }
+
+    #[test]
+    fn reset_clears_everything() {
+        let mut stats = SensorStats::new(24, 0);
+        stats.record(&cpu(70.0), 1_000);
+        stats.reset(2_000);
+        let report = stats.report(3_000);
+        assert!(report.sensors.is_empty());
+        assert_eq!(report.since, 2_000);
+    }
+}
@@ -0,0 +1,210 @@
+//! Registration `topology`: each chip with the sensors and fans on it, so the backend
+//! can show which fan headers sit next to which sensors when assigning curves.
+//!
+//! Built from the diagnostics dump (`HardwareDumpRoot`) and slimmed down: voltages,
+//! readings and limits are left out, and each entry carries the capability `id` of the
+//! sensor or fan it is, next to the dump identifier and the tach/control pairing the
+//! dump already computes. Chips holding nothing the registration describes (thermal
+//! zones, unmonitored chips) are dropped. Sent at registration only, never in data
+//! messages; the flat `sensors`/`fans` lists stay as they are.
+
+use std::collections::HashMap;
+use std::path::Path;
+
+use serde::Serialize;
+
+use super::types::{Fan, HardwareDumpItem, HardwareDumpRoot, HardwareDumpSensor, Sensor};
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopologyChip {
+    /// Dump identifier, e.g. `/hwmon/hwmon2`
+    pub identifier: String,
+    pub name: String,
+    #[serde(rename = "type")]
+    pub hardware_type: String,
+    pub entries: Vec<TopologyEntry>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub children: Vec<TopologyChip>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TopologyEntry {
+    /// Dump identifier, e.g. `/nct6798/fan/2`
+    pub identifier: String,
+    /// "temperature", "fan" (tach) or "control"
+    pub kind: &'static str,
+    /// Sensor or fan id in the registration capabilities; None for channels the agent
+    /// doesn't report (disconnected tach, filtered sensor)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The paired control or tach (`LinkedSensorId` in the dump)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub linked: Option<String>,
+}
+
+/// Chip directory and file name of a sysfs attribute (`hwmon2`, `temp1_input`).
+fn attribute(path: &str) -> Option<(String, String)> {
+    let path = Path::new(path);
+    let dir = path.parent()?.file_name()?.to_str()?;
+    Some((dir.to_string(), path.file_name()?.to_str()?.to_string()))
+}
+
+/// The chips of `dump`, with entries matched to `sensors` and `fans` by the sysfs
+/// attribute they read or write.
+pub fn topology(dump: &HardwareDumpRoot, sensors: &[Sensor], fans: &[Fan]) -> Vec<TopologyChip> {
+    let sources = sensors.iter().filter_map(|s| Some((attribute(s.source.as_deref()?)?, s.id.to_string())));
+    let controls = fans.iter().filter_map(|f| Some((attribute(f.pwm_file.as_deref()?)?, f.id.clone())));
+    let ids: HashMap<(String, String), String> = sources.chain(controls).collect();
+    dump.hardware.iter().filter_map(|item| chip(item, &ids)).collect()
+}
+
+fn chip(item: &HardwareDumpItem, ids: &HashMap<(String, String), String>) -> Option<TopologyChip> {
+    let dir = item.identifier.rsplit('/').next().unwrap_or_default();
+    let entries: Vec<TopologyEntry> = item.sensors.iter().filter_map(|s| entry(s, dir, ids)).collect();
+    let children: Vec<TopologyChip> = item.sub_hardware.iter().filter_map(|sub| chip(sub, ids)).collect();
+    if children.is_empty() && entries.iter().all(|e| e.id.is_none()) {
+        return None;
+    }
+    Some(TopologyChip {
+        identifier: item.identifier.clone(),
+        name: item.name.clone(),
+        hardware_type: item.hardware_type.clone(),
+        entries,
+        children,
+    })
+}
+
+fn entry(sensor: &HardwareDumpSensor, dir: &str, ids: &HashMap<(String, String), String>) -> Option<TopologyEntry> {
+    let kind = match sensor.sensor_type.as_str() {
+        "Temperature" => "temperature",
+        "Fan" => "fan",
+        "Control" => "control",
+        _ => return None,
+    };
+    // Dump identifiers end in the channel number: /<chip>/<kind>/<N>
+    let index = sensor.identifier.rsplit('/').next()?;
+    let files = match kind {
+        "temperature" => vec![format!("temp{}_input", index)],
+        // Fans are known by their control attribute, tach and control alike
+        _ => vec![format!("pwm{}", index), format!("fan{}_target", index)],
+    };
+    let id = files.into_iter().find_map(|file| ids.get(&(dir.to_string(), file)).cloned());
+    Some(TopologyEntry {
+        identifier: sensor.identifier.clone(),
+        kind,
+        id,
+        linked: sensor.control.as_ref().and_then(|c| c.linked_sensor_id.clone()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::types::{HardwareDumpControlInfo, HardwareDumpMetadata};
+
+    fn dump_sensor(identifier: &str, sensor_type: &str, linked: Option<&str>) -> HardwareDumpSensor {
+        HardwareDumpSensor {
+            name: identifier.to_string(),
+            identifier: identifier.to_string(),
+            sensor_type: sensor_type.to_string(),
+            value: Some(1.0),
+            min: "null".to_string(),
+            max: "null".to_string(),
+            is_monitored: true,
+            is_connected: None,
+            control: linked.map(|linked| HardwareDumpControlInfo {
+                linked_sensor_id: Some(linked.to_string()),
+                method: "sysfs".to_string(),
+                can_write: true,
+                can_restore_default: true,
+                current_percent: None,
+                range: [0, 100],
+                mode: None,
+                write_failures: None,
+            }),
+            reported_as: None,
+            reported_value: None,
+        }
+    }
+
+    fn dump_item(identifier: &str, name: &str, sensors: Vec<HardwareDumpSensor>) -> HardwareDumpItem {
+        HardwareDumpItem {
+            name: name.to_string(),
+            identifier: identifier.to_string(),
+            hardware_type: "SuperIO".to_string(),
+            parent: None,
+            technical_id: None,
+            sensors,
+            sub_hardware: Vec::new(),
+            disabled: false,
+        }
+    }
+
+    #[test]
+    fn entries_carry_capability_ids_and_tach_control_links() {
+        let dump = HardwareDumpRoot {
+            metadata: HardwareDumpMetadata::default(),
+            hardware: vec![
+                dump_item("/hwmon/hwmon2", "nct6798", vec![
+                    dump_sensor("/nct6798/temp/1", "Temperature", None),
+                    dump_sensor("/nct6798/fan/2", "Fan", Some("/nct6798/control/2")),
+                    dump_sensor("/nct6798/fan/3", "Fan", Some("/nct6798/control/3")),
+                    dump_sensor("/nct6798/control/2", "Control", Some("/nct6798/fan/2")),
+                    dump_sensor("/nct6798/voltage/0", "Voltage", None),
+                ]),
+                // Nothing the registration describes
+                dump_item("/thermal/thermal_zone0", "Thermal Zone: acpitz", vec![
+                    dump_sensor("/thermal/thermal_zone0/temp/0", "Temperature", None),
+                ]),
+            ],
+            storage_health: Vec::new(),
+        };
+        let sensors = vec![Sensor {
+            id: "nct6798_systin".into(),
+            name: "NCT6798 SYSTIN".into(),
+            display_name: None,
+            temperature: 34.0,
+            sensor_type: "motherboard".into(),
+            max_temp: None,
+            crit_temp: None,
+            chip: Some("nct6798".into()),
+            hardware_name: None,
+            source: Some("/sys/class/hwmon/hwmon2/temp1_input".into()),
+            alarms: Vec::new(),
+            raw_temperature: None,
+            stale: false,
+        }];
+        let fans = vec![Fan {
+            id: "nct6798_fan_2".to_string(),
+            name: "nct6798 Fan 2".to_string(),
+            rpm: Some(900),
+            speed: 40,
+            target_speed: 40,
+            pwm: None,
+            status: "ok".to_string(),
+            status_detail: None,
+            has_pwm_control: true,
+            control_method: Some("pwm".to_string()),
+            pwm_file: Some("/sys/class/hwmon/hwmon2/pwm2".to_string()),
+            pwm_enable: None,
+            control_mode: None,
+            zone: None,
+            alarms: Vec::new(),
+            drift: false,
+            control_error: None,
+            last_set_by: None,
+        }];
+
+        let chips = topology(&dump, &sensors, &fans);
+        assert_eq!(chips.len(), 1);
+        let ids: Vec<_> = chips[0].entries.iter().map(|e| (e.kind, e.id.as_deref(), e.linked.as_deref())).collect();
+        assert_eq!(ids, [
+            ("temperature", Some("nct6798_systin"), None),
+            ("fan", Some("nct6798_fan_2"), Some("/nct6798/control/2")),
+            ("fan", None, Some("/nct6798/control/3")),
+            ("control", Some("nct6798_fan_2"), Some("/nct6798/fan/2")),
+        ]);
+        assert_eq!(serde_json::to_value(&chips[0]).unwrap()["entries"][0],
+                   serde_json::json!({"identifier": "/nct6798/temp/1", "kind": "temperature", "id": "nct6798_systin"}));
+    }
+}
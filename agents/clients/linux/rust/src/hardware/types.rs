@@ -1,24 +1,100 @@
 //! Hardware data types: Sensor, Fan, SystemHealth, and diagnostic dump structures.
 
+use std::cmp::Ordering;
+use std::sync::Arc;
+
 use serde::{Deserialize, Serialize};
 
-/// Sensor reading with temperature data
+use crate::config::units::TemperatureUnit;
+
+/// Sensor reading with temperature data. The metadata strings are shared with the
+/// monitor's sensor cache, so a cached read clones pointers rather than text.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sensor {
-    pub id: String,
-    pub name: String,
+    pub id: Arc<str>,
+    /// hwmon sensors: the chip and label as the kernel has them ("k10temp Tctl"; drives
+    /// "nvme nvme0 Composite"). Deprecated for `display_name`, see `SENSOR_NAMING_VERSION`.
+    pub name: Arc<str>,
+    /// Name for people (CPU brand, drive model, ...); may change between releases,
+    /// so never key anything on it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<Arc<str>>,
     pub temperature: f64,
     #[serde(rename = "type")]
-    pub sensor_type: String,
+    pub sensor_type: Arc<str>,
     pub max_temp: Option<f64>,
     pub crit_temp: Option<f64>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub chip: Option<String>,
+    pub chip: Option<Arc<str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     #[serde(rename = "hardwareName")]
-    pub hardware_name: Option<String>,
+    pub hardware_name: Option<Arc<str>>,
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub source: Option<String>,
+    pub source: Option<Arc<str>>,
+    /// Asserted hwmon alarm flags (e.g. "max", "crit"), read from `tempN_*alarm`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alarms: Vec<String>,
+    /// Instantaneous reading when `temperature` is smoothed (`temperature_smoothing`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_temperature: Option<f64>,
+    /// Last value re-sent because there is no current one: a hwmon input whose reads
+    /// are failing transiently, or a virtual sensor whose source is gone.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+}
+
+impl Sensor {
+    /// `display_name`, else `name`.
+    pub fn friendly_name(&self) -> &str {
+        self.display_name.as_deref().unwrap_or(&self.name)
+    }
+
+    /// Kernel driver reports this sensor past its critical limit.
+    pub fn has_crit_alarm(&self) -> bool {
+        self.alarms.iter().any(|a| a == "crit" || a == "emergency")
+    }
+
+    /// The unsmoothed reading - what emergency thresholds are compared against.
+    pub fn instant_temperature(&self) -> f64 {
+        self.raw_temperature.unwrap_or(self.temperature)
+    }
+}
+
+/// Payload order of sensors: by type, then id. Cached reads come out of a HashMap,
+/// so without this the array order changes from run to run.
+pub fn sort_sensors(sensors: &mut [Sensor]) {
+    sensors.sort_by(|a, b| a.sensor_type.cmp(&b.sensor_type).then_with(|| natural_cmp(&a.id, &b.id)));
+}
+
+/// Payload order of fans: by id.
+pub fn sort_fans(fans: &mut [Fan]) {
+    fans.sort_by(|a, b| natural_cmp(&a.id, &b.id));
+}
+
+/// Compares runs of digits by value, so `chip2_sensor_2` comes before `chip2_sensor_10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    fn next_run(s: &str) -> (bool, &str, &str) {
+        let digits = s.starts_with(|c: char| c.is_ascii_digit());
+        let end = s.find(|c: char| c.is_ascii_digit() != digits).unwrap_or(s.len());
+        (digits, &s[..end], &s[end..])
+    }
+
+    let (mut rest_a, mut rest_b) = (a, b);
+    while !rest_a.is_empty() && !rest_b.is_empty() {
+        let (digits_a, run_a, next_a) = next_run(rest_a);
+        let (digits_b, run_b, next_b) = next_run(rest_b);
+        let order = if digits_a && digits_b {
+            let (run_a, run_b) = (run_a.trim_start_matches('0'), run_b.trim_start_matches('0'));
+            run_a.len().cmp(&run_b.len()).then_with(|| run_a.cmp(run_b))
+        } else {
+            run_a.cmp(run_b)
+        };
+        if order != Ordering::Equal {
+            return order;
+        }
+        (rest_a, rest_b) = (next_a, next_b);
+    }
+    rest_a.len().cmp(&rest_b.len()).then_with(|| a.cmp(b))
 }
 
 /// Fan information with RPM and PWM control
@@ -30,10 +106,222 @@ pub struct Fan {
     pub speed: u8, // 0-100%
     #[serde(rename = "targetSpeed")]
     pub target_speed: u8,
-    pub status: String, // "ok", "stopped", "error"
+    /// Raw `pwmN` register (0-255) `speed` was read from; None for other control methods
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm: Option<u8>,
+    pub status: String, // "ok", "stopped", "absent", "error"
+    /// What `status` was derived from (hwmon fans); None where not reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub status_detail: Option<FanStatusDetail>,
+    /// The speed can be set (any control method, despite the name)
     pub has_pwm_control: bool,
+    /// "pwm", "target_rpm" (hwmon `fanN_target`), "nvml"; None where not reported
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_method: Option<String>,
+    /// Control attribute (`pwmN` or `fanN_target`)
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pwm_file: Option<String>,
+    /// `pwmN_enable` as read at discovery, and its `FanMode::name_of`; None without
+    /// that file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pwm_enable: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_mode: Option<String>,
+    /// Fan zone commanded as a unit (IPMI backends in composite mode); None for hwmon
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub zone: Option<String>,
+    /// Asserted hwmon alarm flags (e.g. "min", "alarm"), read from `fanN_*alarm`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub alarms: Vec<String>,
+    /// The control register doesn't hold what the agent last wrote (board clamp, EC
+    /// override); `speed` is what the hardware has, `targetSpeed` what was commanded
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub drift: bool,
+    /// Why the agent stopped controlling the fan (`status` "error"), e.g. "firmware
+    /// rejects writes"; until `retryFanControl` succeeds
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub control_error: Option<String>,
+    /// What last had the agent set this fan's speed, and when; None until something
+    /// did (and for backends that don't track it)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_set_by: Option<FanSetBy>,
+}
+
+impl Fan {
+    /// Judged to be an empty header: emergency and failsafe writes skip it.
+    pub fn is_absent(&self) -> bool {
+        self.status_detail.is_some_and(|d| d.presence == FanPresence::Absent)
+    }
+}
+
+/// What asked for a fan speed write. Logged with the write and reported as
+/// `last_set_by`, so a speed nobody expected can be traced to its source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "origin", rename_all = "snake_case")]
+pub enum FanSpeedOrigin {
+    /// `setFanSpeed` from the backend
+    BackendCommand {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        command_id: Option<String>,
+    },
+    /// Failsafe mode: the backend is unreachable
+    Failsafe,
+    /// `max_cooling`: `emergencyStop`, failsafe emergency, crit alarm
+    Emergency,
+    /// Agent-side curves (`local_curves`) while the backend is away
+    LocalCurve,
+    /// The agent's own CLI (`--test`)
+    LocalCli,
+    /// A characterization run holding fixed duties (`--characterize`, `characterizeFans`)
+    Characterization,
+}
+
+impl std::fmt::Display for FanSpeedOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BackendCommand { command_id: Some(id) } => write!(f, "backend command {}", id),
+            Self::BackendCommand { command_id: None } => f.write_str("backend command"),
+            Self::Failsafe => f.write_str("failsafe"),
+            Self::Emergency => f.write_str("emergency"),
+            Self::LocalCurve => f.write_str("local curve"),
+            Self::LocalCli => f.write_str("local CLI"),
+            Self::Characterization => f.write_str("characterization"),
+        }
+    }
+}
+
+/// `Fan::last_set_by`: the origin of the last speed applied to a fan.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FanSetBy {
+    #[serde(flatten)]
+    pub origin: FanSpeedOrigin,
+    /// Unix ms
+    pub timestamp: i64,
+}
+
+impl FanSetBy {
+    pub fn now(origin: &FanSpeedOrigin) -> Self {
+        Self { origin: origin.clone(), timestamp: chrono::Utc::now().timestamp_millis() }
+    }
+}
+
+/// Whether a fan header has a fan on it, judged from its tach since the agent started.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FanPresence {
+    /// The tach has reported RPM
+    Present,
+    /// Driven for a while and never reported RPM: probably an empty header
+    Absent,
+    /// Not enough evidence yet, or no tach to judge by
+    Unknown,
+}
+
+/// Why a fan has the `status` it has.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FanStatusReason {
+    Spinning,
+    /// Not turning at 0% duty (semi-passive idle)
+    Idle,
+    /// Not turning although driven: seized, unplugged since, or still spinning up
+    Stalled,
+    /// No tach: judged from the duty alone
+    NoTach,
+    Absent,
+    /// The driver asserts `fanN_fault`
+    Fault,
+}
+
+/// The evidence behind a fan's `status`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FanStatusDetail {
+    pub reason: FanStatusReason,
+    pub presence: FanPresence,
+    /// `fanN_fault` is asserted
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub fault: bool,
+    /// A `fanN_*alarm` is asserted (listed in `alarms`)
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub alarm: bool,
+}
+
+/// `pwmN_enable` mode requested by `setFanMode`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FanMode {
+    /// 0 on most chips: the fan runs flat out and the PWM value is ignored
+    FullSpeed,
+    /// 1: the agent sets the speed
+    Manual,
+    /// The chip's own curve: the mode the fan was in before the agent took it over,
+    /// or 2 without one
+    Auto,
+    /// A chip-specific value, e.g. 5 (Smart Fan IV) on nct67xx
+    Raw(u8),
+}
+
+impl FanMode {
+    /// `mode` of a `setFanMode` payload: "full_speed", "manual", "auto" or a raw value.
+    pub fn parse(value: &serde_json::Value) -> Option<Self> {
+        if let Some(raw) = value.as_u64() {
+            return u8::try_from(raw).ok().map(Self::Raw);
+        }
+        match value.as_str()? {
+            "full_speed" => Some(Self::FullSpeed),
+            "manual" => Some(Self::Manual),
+            "auto" => Some(Self::Auto),
+            _ => None,
+        }
+    }
+
+    /// `control_mode` reported for a `pwmN_enable` value.
+    pub fn name_of(pwm_enable: u8) -> &'static str {
+        match pwm_enable {
+            0 => "full_speed",
+            1 => "manual",
+            _ => "auto",
+        }
+    }
+}
+
+/// A fan's control registers as found, so a temporary change can be undone exactly
+/// (`--test --with-fan-test`, shutdown).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FanControlState {
+    /// Raw control value: PWM (0-255), or RPM for a `fanN_target` fan
+    pub value: u32,
+    /// `pwmN_enable` mode (1 = manual, 2+ = chip automatic); `None` without that file
+    pub pwm_enable: Option<String>,
+}
+
+/// Other fan-control software found at startup (see `hardware/linux/conflicts.rs`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FanControlConflict {
+    /// The other program, e.g. "fancontrol"
+    pub program: String,
+    /// Its pid; `None` when only its configuration was found
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pid: Option<u32>,
+    /// Fans it manages (per its config) or appears to be driving
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fans: Vec<String>,
+    pub message: String,
+}
+
+/// Where the agent runs, as found at startup (see `hardware/linux/environment.rs`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunEnvironment {
+    /// Inside a container (LXC, Docker, Podman, Kubernetes, systemd-nspawn)
+    pub container: bool,
+    /// `/sys/class/hwmon` is missing, empty or unreadable
+    pub no_hwmon: bool,
+}
+
+impl RunEnvironment {
+    /// A container without hwmon: the agent only monitors and never writes fans.
+    pub fn monitoring_only(&self) -> bool {
+        self.container && self.no_hwmon
+    }
 }
 
 /// System health metrics
@@ -45,6 +333,83 @@ pub struct SystemHealth {
     pub memory_usage: f64,
     #[serde(rename = "agentUptime")]
     pub agent_uptime: f64,
+    /// Firmware is throttling the SoC (Raspberry Pi `vcgencmd get_throttled`). Omitted when unavailable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub throttled: Option<bool>,
+    /// Latencies of the backend connection over the last 5 minutes. Omitted without a
+    /// connection (UDP broadcast) or before any were measured.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub connection: Option<ConnectionQuality>,
+    /// SMART health of the storage drives (`hardware.storage_health`). Omitted when
+    /// disabled or before any drive was read.
+    #[serde(rename = "storageHealth", default, skip_serializing_if = "Vec::is_empty")]
+    pub storage_health: Vec<DriveHealth>,
+    /// Webhook deliveries since the agent started. Omitted without `notifications.webhook_url`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub notifications: Option<NotificationStats>,
+}
+
+/// Webhook notification attempts (see notifications.rs).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NotificationStats {
+    pub sent: u64,
+    pub failed: u64,
+    /// Not sent: same event type within `notifications.rate_limit_minutes`
+    pub suppressed: u64,
+}
+
+/// SMART health of one drive, from smartctl. Fields the drive doesn't report are omitted.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DriveHealth {
+    /// Kernel device ("nvme0", "sda"), the prefix of the drive's sensor ids
+    pub device: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// Overall SMART self-assessment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passed: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub power_on_hours: Option<u64>,
+    /// NVMe: unrecovered data integrity errors
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_errors: Option<u64>,
+    /// NVMe: estimated share of the rated endurance used; may exceed 100
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub percentage_used: Option<u64>,
+    /// ATA attribute 5
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reallocated_sectors: Option<u64>,
+    /// ATA attribute 197
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pending_sectors: Option<u64>,
+    /// When it was read (Unix ms); readings are hourly
+    pub checked_at: i64,
+}
+
+/// Backend connection latencies, in ms. Each is omitted until measured.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ConnectionQuality {
+    /// Round trip of the agent's WebSocket pings
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ping_rtt_ms: Option<LatencyStats>,
+    /// From receiving a backend `ping` to our `pong` being sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pong_delay_ms: Option<LatencyStats>,
+    /// From receiving a command to its response being sent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command_ms: Option<LatencyStats>,
+    /// Sending one data message
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_ms: Option<LatencyStats>,
+    /// p95 round trip or send time is past `backend.latency_warn_ms`
+    pub degraded: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct LatencyStats {
+    pub p50: f64,
+    pub p95: f64,
+    pub samples: usize,
 }
 
 // ============================================================================
@@ -57,10 +422,39 @@ pub struct SystemHealth {
 pub struct HardwareDumpRoot {
     pub metadata: HardwareDumpMetadata,
     pub hardware: Vec<HardwareDumpItem>,
+    /// Last SMART readings (`hardware.storage_health`), by drive
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub storage_health: Vec<DriveHealth>,
+}
+
+impl HardwareDumpRoot {
+    /// The dump with temperature readings and limits in `unit`, for the local file.
+    /// What goes to the backend (`getDiagnostics`) stays Celsius.
+    pub fn in_unit(mut self, unit: TemperatureUnit) -> Self {
+        fn convert(item: &mut HardwareDumpItem, unit: TemperatureUnit) {
+            for sensor in item.sensors.iter_mut().filter(|s| s.sensor_type == "Temperature") {
+                sensor.value = sensor.value.map(|v| unit.convert(f64::from(v)) as f32);
+                for limit in [&mut sensor.min, &mut sensor.max] {
+                    if let Ok(celsius) = limit.parse::<f64>() {
+                        *limit = format!("{}", (unit.convert(celsius) * 10.0).round() / 10.0);
+                    }
+                }
+            }
+            for sub in &mut item.sub_hardware {
+                convert(sub, unit);
+            }
+        }
+
+        for item in &mut self.hardware {
+            convert(item, unit);
+        }
+        self.metadata.temperature_unit = Some(unit.name().to_string());
+        self
+    }
 }
 
 /// Metadata section with system context
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct HardwareDumpMetadata {
     pub agent_version: String,
@@ -71,6 +465,12 @@ pub struct HardwareDumpMetadata {
     pub motherboard: Option<String>,
     pub kernel_version: Option<String>,
     pub cpu_model: Option<String>,
+    /// Unit of the temperature values; set on the local hardware-info.json only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub temperature_unit: Option<String>,
+    /// Last entries of config-history.jsonl; set on `getDiagnostics` dumps only
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub config_history: Vec<serde_json::Value>,
 }
 
 /// Hardware item (chip/device) with sensors
@@ -85,6 +485,9 @@ pub struct HardwareDumpItem {
     pub technical_id: Option<String>,
     pub sensors: Vec<HardwareDumpSensor>,
     pub sub_hardware: Vec<HardwareDumpItem>,
+    /// Listed in `hardware.disabled_chips`: present, but not reported or controlled
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub disabled: bool,
 }
 
 /// Individual sensor with value and control info
@@ -102,6 +505,23 @@ pub struct HardwareDumpSensor {
     pub is_monitored: bool,
     pub is_connected: Option<bool>,
     pub control: Option<HardwareDumpControlInfo>,
+    /// Linux: how the agent reports this temperature input, once discovered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reported_as: Option<HardwareDumpReportedSensor>,
+    /// Linux: fan RPM as reported, where `hardware.fan_pulses` corrects the raw
+    /// tach reading in `value`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reported_value: Option<f32>,
+}
+
+/// Id and names a temperature input is reported under (see `Sensor`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HardwareDumpReportedSensor {
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
 }
 
 /// Control interface details for fan/pwm sensors
@@ -116,18 +536,21 @@ pub struct HardwareDumpControlInfo {
     pub current_percent: Option<f32>,
     pub range: [i32; 2],
     pub mode: Option<String>,
+    /// Linux: PWM write failure counters of this channel, once the agent has it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub write_failures: Option<HardwareDumpWriteFailures>,
 }
 
-impl Default for HardwareDumpMetadata {
-    fn default() -> Self {
-        Self {
-            agent_version: String::new(),
-            os_version: String::new(),
-            is_elevated: false,
-            timestamp: String::new(),
-            motherboard: None,
-            kernel_version: None,
-            cpu_model: None,
-        }
-    }
+/// PWM write failures of one control channel (see `hardware.pwm_failure_threshold`)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct HardwareDumpWriteFailures {
+    pub consecutive: u32,
+    pub total: u64,
+    /// Consecutive writes that didn't stick (read back as another value)
+    pub write_ignored: u32,
+    /// Control given up until `retryFanControl` succeeds
+    pub degraded: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
 }
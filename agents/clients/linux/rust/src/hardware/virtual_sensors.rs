@@ -0,0 +1,210 @@
+//! Virtual sensors (`hardware.virtual_sensors`): readings computed from other sensors
+//! each cycle, e.g. a weighted loop temperature from two inline probes, or the delta
+//! between water in and out.
+//!
+//! Computed after the real sensors are read (and smoothed), so every consumer of a
+//! discovery sees them. A virtual sensor missing a source isn't computed from the rest:
+//! its last value is re-sent marked `stale`, or it is left out if it never had one.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::config::types::{Aggregation, VirtualSensor};
+
+use super::composite::PREFIX_SEPARATOR;
+use super::types::Sensor;
+
+/// `type` of every virtual sensor.
+pub const VIRTUAL_SENSOR_TYPE: &str = "virtual";
+
+/// The definitions and each one's last reported sensor.
+#[derive(Default)]
+pub struct VirtualSensors {
+    definitions: Vec<VirtualSensor>,
+    last: Vec<Sensor>,
+}
+
+impl VirtualSensors {
+    pub fn new(definitions: Vec<VirtualSensor>) -> Self {
+        Self { definitions, last: Vec::new() }
+    }
+
+    /// Replace the definitions (`setVirtualSensor`); values of removed ones are dropped.
+    pub fn set(&mut self, definitions: Vec<VirtualSensor>) {
+        self.last.retain(|s| definitions.iter().any(|d| *d.id == *s.id));
+        self.definitions = definitions;
+    }
+
+    /// Append the virtual sensors computed from `sensors`.
+    pub fn append(&mut self, sensors: &mut Vec<Sensor>) {
+        if self.definitions.is_empty() {
+            return;
+        }
+        let computed = compute(&self.definitions, sensors, &self.last);
+        sensors.extend(computed.iter().cloned());
+        self.last = computed;
+    }
+}
+
+/// Virtual sensors for `definitions` from the real `sensors`. `previous` is the last
+/// result: what a definition missing a source falls back to, marked stale.
+pub fn compute(definitions: &[VirtualSensor], sensors: &[Sensor], previous: &[Sensor]) -> Vec<Sensor> {
+    let by_id: HashMap<&str, &Sensor> = sensors.iter().map(|s| (&*s.id, s)).collect();
+    definitions
+        .iter()
+        .filter_map(|definition| match evaluate(definition, &by_id) {
+            Some((temperature, raw_temperature)) => Some(Sensor {
+                id: definition.id.as_str().into(),
+                name: definition.name.as_str().into(),
+                display_name: None,
+                temperature,
+                sensor_type: Arc::from(VIRTUAL_SENSOR_TYPE),
+                max_temp: None,
+                crit_temp: None,
+                chip: None,
+                hardware_name: None,
+                source: None,
+                alarms: Vec::new(),
+                raw_temperature,
+                stale: false,
+            }),
+            None => previous.iter().find(|s| *s.id == *definition.id).map(|last| Sensor { stale: true, ..last.clone() }),
+        })
+        .collect()
+}
+
+/// The value of one virtual sensor and, when a source is smoothed, its unsmoothed
+/// value. `None` when a source isn't reported.
+fn evaluate(definition: &VirtualSensor, sensors: &HashMap<&str, &Sensor>) -> Option<(f64, Option<f64>)> {
+    let sources = definition
+        .sources
+        .iter()
+        .map(|source| find(sensors, &source.sensor).map(|sensor| (sensor, source.weight)))
+        .collect::<Option<Vec<_>>>()?;
+    let temperature = aggregate(definition.aggregation, sources.iter().map(|(s, w)| (s.temperature, *w)))?;
+    let raw_temperature = if sources.iter().any(|(s, _)| s.raw_temperature.is_some()) {
+        aggregate(definition.aggregation, sources.iter().map(|(s, w)| (s.instant_temperature(), *w)))
+    } else {
+        None
+    };
+    Some((round(temperature), raw_temperature.map(round)))
+}
+
+/// A source by id; in composite mode the backend prefix may be left out.
+fn find<'a>(sensors: &HashMap<&str, &'a Sensor>, id: &str) -> Option<&'a Sensor> {
+    sensors.get(id).copied().or_else(|| {
+        sensors
+            .iter()
+            .find(|(sensor_id, _)| sensor_id.split_once(PREFIX_SEPARATOR).is_some_and(|(_, inner)| inner == id))
+            .map(|(_, sensor)| *sensor)
+    })
+}
+
+/// `values` are (°C, weight) pairs, in source order.
+fn aggregate(aggregation: Aggregation, mut values: impl Iterator<Item = (f64, f64)>) -> Option<f64> {
+    match aggregation {
+        Aggregation::WeightedAvg => {
+            let (sum, weights) = values.fold((0.0, 0.0), |(sum, weights), (value, weight)| (sum + value * weight, weights + weight));
+            (weights > 0.0).then(|| sum / weights)
+        }
+        Aggregation::Max => values.map(|(value, _)| value).reduce(f64::max),
+        Aggregation::Min => values.map(|(value, _)| value).reduce(f64::min),
+        Aggregation::Delta => {
+            let (first, _) = values.next()?;
+            let (second, _) = values.next()?;
+            Some(first - second)
+        }
+    }
+}
+
+fn round(value: f64) -> f64 {
+    (value * 10.0).round() / 10.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::VirtualSource;
+
+    fn sensor(id: &str, temperature: f64) -> Sensor {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "name": id, "temperature": temperature, "type": "other", "max_temp": null, "crit_temp": null,
+        }))
+        .unwrap()
+    }
+
+    fn virtual_sensor(id: &str, aggregation: Aggregation, sources: &[(&str, f64)]) -> VirtualSensor {
+        VirtualSensor {
+            id: id.to_string(),
+            name: id.to_string(),
+            sources: sources.iter().map(|(sensor, weight)| VirtualSource { sensor: sensor.to_string(), weight: *weight }).collect(),
+            aggregation,
+        }
+    }
+
+    fn temperatures(sensors: &[Sensor]) -> Vec<(&str, f64, bool)> {
+        sensors.iter().map(|s| (&*s.id, s.temperature, s.stale)).collect()
+    }
+
+    #[test]
+    fn aggregations() {
+        let sensors = [sensor("in", 30.0), sensor("out", 36.0)];
+        let sources = [("out", 0.7), ("in", 0.3)];
+        let definitions = [
+            virtual_sensor("loop", Aggregation::WeightedAvg, &sources),
+            virtual_sensor("hottest", Aggregation::Max, &sources),
+            virtual_sensor("coolest", Aggregation::Min, &sources),
+            virtual_sensor("delta", Aggregation::Delta, &sources),
+        ];
+
+        let computed = compute(&definitions, &sensors, &[]);
+        assert_eq!(temperatures(&computed), [("loop", 34.2, false), ("hottest", 36.0, false), ("coolest", 30.0, false), ("delta", 6.0, false)]);
+        assert!(computed.iter().all(|s| *s.sensor_type == *VIRTUAL_SENSOR_TYPE && s.raw_temperature.is_none()));
+        assert_eq!(&*computed[0].name, "loop");
+    }
+
+    #[test]
+    fn missing_source_resends_the_last_value_as_stale() {
+        let definitions = [virtual_sensor("loop", Aggregation::WeightedAvg, &[("in", 1.0), ("out", 1.0)])];
+
+        // Never computed: nothing to report
+        assert!(compute(&definitions, &[sensor("in", 30.0)], &[]).is_empty());
+
+        let last = compute(&definitions, &[sensor("in", 30.0), sensor("out", 34.0)], &[]);
+        assert_eq!(temperatures(&last), [("loop", 32.0, false)]);
+
+        // The other source isn't used on its own
+        let stale = compute(&definitions, &[sensor("in", 40.0)], &last);
+        assert_eq!(temperatures(&stale), [("loop", 32.0, true)]);
+        let stale = compute(&definitions, &[], &stale);
+        assert_eq!(temperatures(&stale), [("loop", 32.0, true)]);
+
+        let back = compute(&definitions, &[sensor("in", 40.0), sensor("out", 44.0)], &stale);
+        assert_eq!(temperatures(&back), [("loop", 42.0, false)]);
+    }
+
+    #[test]
+    fn sources_match_with_or_without_a_backend_prefix() {
+        let mut smoothed = sensor("hwmon:nct6798_auxtin0", 35.0);
+        smoothed.raw_temperature = Some(37.0);
+        let sensors = [smoothed, sensor("ipmi:inlet", 25.0)];
+        let definitions = [virtual_sensor("delta", Aggregation::Delta, &[("nct6798_auxtin0", 1.0), ("ipmi:inlet", 1.0)])];
+
+        let computed = compute(&definitions, &sensors, &[]);
+        assert_eq!(temperatures(&computed), [("delta", 10.0, false)]);
+        assert_eq!(computed[0].raw_temperature, Some(12.0));
+    }
+
+    #[test]
+    fn appended_each_cycle_and_dropped_with_their_definition() {
+        let mut virtual_sensors = VirtualSensors::new(vec![virtual_sensor("loop", Aggregation::Max, &[("in", 1.0)])]);
+        let mut sensors = vec![sensor("in", 30.0)];
+        virtual_sensors.append(&mut sensors);
+        assert_eq!(temperatures(&sensors), [("in", 30.0, false), ("loop", 30.0, false)]);
+
+        virtual_sensors.set(Vec::new());
+        let mut sensors = vec![sensor("in", 30.0)];
+        virtual_sensors.append(&mut sensors);
+        assert_eq!(sensors.len(), 1);
+    }
+}
@@ -0,0 +1,13 @@
+//! Library view of the telemetry payload (hardware data types, data message
+//! serialization) for the benchmarks under benches/. The binary in main.rs does not
+//! depend on it.
+
+pub mod config {
+    pub mod units;
+}
+pub mod hardware {
+    pub mod types;
+}
+pub mod websocket {
+    pub mod payload;
+}
@@ -15,25 +15,41 @@ use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 use app::cli::{Args, HELP_TEXT};
-use app::logging::{init_tracing, RELOAD_HANDLE};
+use app::log_rotation;
+use app::log_throttle;
+use app::logging::{init_tracing, set_log_format, LOG_FORMATS, RELOAD_HANDLE};
+use config::history::{self, ConfigOrigin, ConfigSource};
 use config::persistence::load_config;
-use config::setup::run_setup_wizard;
-use daemon::pid::{ensure_directories, get_pid, remove_pid_file, save_pid};
+use config::setup::{run_non_interactive_setup, run_setup_wizard, SetupOptions};
+use daemon::pid::save_pid;
+use daemon::platform::{find_log_file, log_file_for_write};
+use daemon::notify;
 use daemon::control::{start_daemon_with_log_level, stop_daemon, restart_daemon_with_log_level, set_log_level_runtime};
-use daemon::status::{show_status, run_health_check};
+use daemon::status::{show_status, run_health_check, run_connection_check};
 use hardware::HardwareMonitor;
 use websocket::client::WebSocketClient;
 
 #[cfg(target_os = "linux")]
 use hardware::LinuxHardwareMonitor;
-
 #[cfg(target_os = "linux")]
-use daemon::systemd::{install_systemd_service, uninstall_systemd_service};
+use hardware::composite::CompositeHardwareMonitor;
 
-use daemon::LOG_DIR;
+#[cfg(target_os = "linux")]
+use daemon::systemd::{install_systemd_service, is_notify_service_installed, uninstall_systemd_service};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    let result = run().await;
+    // Fans may be left at the agent's last speed; point at the manual way back
+    #[cfg(target_os = "linux")]
+    if let (Err(e), Some(restore)) = (&result, hardware::linux::fan_modes::fan_restore_path()) {
+        error!("Agent failed: {:#}", e);
+        eprintln!("Fans may still be under manual control. To hand them back to the firmware, see {}", restore.display());
+    }
+    result
+}
+
+async fn run() -> Result<()> {
     // Install the ring crypto provider before any TLS use so wss:// connections
     // don't panic on a missing default CryptoProvider. Idempotent; safe to ignore.
     let _ = rustls::crypto::ring::default_provider().install_default();
@@ -68,7 +84,7 @@ async fn main() -> Result<()> {
 
     // Handle management commands first (before async setup)
     if args.start {
-        return start_daemon_with_log_level(args.log_level);  // Spawns new process and exits
+        return start_daemon_with_log_level(args.log_level, args.log_format, args.dry_run);  // Spawns new process and exits
     }
 
     if args.stop {
@@ -76,17 +92,40 @@ async fn main() -> Result<()> {
     }
 
     if args.restart {
-        return restart_daemon_with_log_level(args.log_level);
+        return restart_daemon_with_log_level(args.log_level, args.log_format, args.dry_run);
     }
 
     if args.status {
-        return show_status().await;
+        return show_status(args.json).await;
     }
 
     if args.check {
         return run_health_check();
     }
 
+    if args.clear_safe_mode {
+        return daemon::safe_mode::clear_from_cli();
+    }
+
+    if let Some(limit) = args.config_history {
+        return config::history::show(limit.unwrap_or(20), args.json);
+    }
+
+    if args.check_connection {
+        if !run_connection_check().await? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    #[cfg(target_os = "linux")]
+    if args.doctor {
+        if !app::doctor::run(args.redact).await? {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
     // Systemd service management (Linux only)
     #[cfg(target_os = "linux")]
     if args.install_service {
@@ -98,9 +137,39 @@ async fn main() -> Result<()> {
         return uninstall_systemd_service();
     }
 
+    #[cfg(target_os = "linux")]
+    if args.uninstall {
+        return daemon::uninstall::uninstall(args.purge);
+    }
+
+    #[cfg(target_os = "linux")]
+    if args.print_udev_rules {
+        let config = load_config(None).await.unwrap_or_default();
+        print!("{}", LinuxHardwareMonitor::new(config.hardware).udev_rules().await?);
+        return Ok(());
+    }
+
     if let Some(lines) = args.log_show {
-        // Show agent logs
-        let log_path = format!("{}/agent.log", LOG_DIR);
+        // The notify service logs to the journal instead of agent.log, so there may
+        // be no log file at all
+        #[cfg(target_os = "linux")]
+        if is_notify_service_installed() {
+            let mut cmd = std::process::Command::new("journalctl");
+            cmd.args(["-u", "pankha-agent", "-o", "cat"]);
+            match lines {
+                Some(n) => cmd.arg("-n").arg(n.to_string()),
+                None => cmd.arg("-f"),
+            };
+            let status = cmd.status()?;
+            std::process::exit(status.code().unwrap_or(1));
+        }
+
+        // Show agent logs (config's log_file, else the system or per-user log dir)
+        let config = load_config(None).await.ok();
+        let Some(log_path) = find_log_file(config.as_ref().map(|c| std::path::Path::new(&c.logging.log_file))) else {
+            eprintln!("No agent log file found. Is the agent running?");
+            std::process::exit(1);
+        };
 
         let mut cmd = std::process::Command::new("tail");
 
@@ -112,10 +181,10 @@ async fn main() -> Result<()> {
                 cmd.arg("-n").arg(n.to_string());
             }
             None => {
-                // Follow logs: tail -f
+                // Follow logs by name (tail -F) so it survives log rotation
                 println!("Showing live agent logs (Ctrl+C to exit)...");
                 println!("\x1b[32mpankha-agent v{} ({})\x1b[0m\n", crate::version::VERSION, std::env::consts::ARCH);
-                cmd.arg("-f");
+                cmd.arg("-F");
             }
         }
 
@@ -124,16 +193,22 @@ async fn main() -> Result<()> {
         std::process::exit(status.code().unwrap_or(1));
     }
 
+    // systemd Type=notify service: run in the foreground, no PID file, readiness and
+    // watchdog reported over $NOTIFY_SOCKET. Implied when systemd starts us bare.
+    let systemd_mode = args.systemd
+        || (notify::is_enabled() && args.log_level.is_none() && !args.daemon_child && !args.test && !args.characterize && !args.config && !args.setup);
+    let service_mode = args.daemon_child || systemd_mode;
+
     // If user provided --log-level without other commands, set it for running agent
     if let Some(level) = args.log_level.as_ref() {
-        if !args.daemon_child && !args.test && !args.config && !args.setup {
+        if !service_mode && !args.test && !args.characterize && !args.config && !args.setup {
             // Set log level for running agent
             return set_log_level_runtime(level);
         }
     }
 
     // If no command was provided at all (user just ran the binary), show help
-    if !args.daemon_child && !args.test && !args.config && !args.setup {
+    if !service_mode && !args.test && !args.characterize && !args.config && !args.setup {
         eprintln!("ERROR: No command specified. You must specify a command.");
         eprintln!();
         Args::command().print_help().unwrap();
@@ -172,13 +247,24 @@ async fn main() -> Result<()> {
         }
     };
 
-    // Initialize tracing subscriber with reload capability
-    init_tracing(filter);
+    // --json / non-interactive setup: stdout carries only the JSON document
+    if args.json || args.non_interactive {
+        log_rotation::log_to_stderr();
+    }
 
-    // If we're a daemon child, save our PID and check for failed update
-    if args.daemon_child {
-        ensure_directories()?;
-        save_pid(std::process::id())?;
+    // Initialize tracing subscriber with reload capability
+    // Format: --log-format flag wins, else config's log_format is applied after load
+    init_tracing(filter, args.log_format.as_deref().unwrap_or("text"));
+
+    // Service process (daemon child or systemd): save our PID and check for failed update
+    if service_mode {
+        // Without a writable runtime directory (read-only container) the agent still
+        // runs; only --stop and --status won't find it
+        if args.daemon_child {
+            if let Err(e) = save_pid(std::process::id()) {
+                warn!("{:#}", e);
+            }
+        }
 
         // Check for failed update and rollback if needed
         #[cfg(target_os = "linux")]
@@ -212,10 +298,16 @@ async fn main() -> Result<()> {
                                     // Re-exec into restored binary
                                     use std::os::unix::process::CommandExt;
                                     let mut cmd = std::process::Command::new(&current_exe);
-                                    cmd.arg("--daemon-child");
+                                    cmd.arg(if systemd_mode { "--systemd" } else { "--daemon-child" });
                                     if let Some(level) = args.log_level.as_ref() {
                                         cmd.arg("--log-level").arg(level);
                                     }
+                                    if let Some(format) = args.log_format.as_ref() {
+                                        cmd.arg("--log-format").arg(format);
+                                    }
+                                    if args.dry_run {
+                                        cmd.arg("--dry-run");
+                                    }
                                     let _ = cmd.exec();
                                     // If exec failed, exit and let systemd restart us
                                     std::process::exit(1);
@@ -236,13 +328,34 @@ async fn main() -> Result<()> {
     // Show config if requested
     if args.config {
         let config = load_config(None).await?;
-        println!("\n{}", serde_json::to_string_pretty(&config)?);
+        if !args.json {
+            println!();
+        }
+        println!("{}", serde_json::to_string_pretty(&config)?);
         return Ok(());
     }
 
     // Run setup wizard if requested
     if args.setup {
-        run_setup_wizard(None).await?;
+        if args.non_interactive {
+            let options = SetupOptions {
+                server_url: args.server_url.clone(),
+                agent_name: args.agent_name.clone(),
+                update_interval: args.update_interval,
+                enable_fan_control: if args.disable_fan_control {
+                    Some(false)
+                } else {
+                    args.enable_fan_control.then_some(true)
+                },
+                failsafe_speed: args.failsafe_speed,
+                enrollment_token: args.enrollment_token.clone(),
+                force: args.force,
+                test: args.test,
+            };
+            run_non_interactive_setup(None, &options).await?;
+        } else {
+            run_setup_wizard(None).await?;
+        }
         return Ok(());
     }
 
@@ -281,22 +394,98 @@ async fn main() -> Result<()> {
         }
     }
 
+    // Apply config file log format if no CLI flag was provided
+    if args.log_format.is_none() && config.logging.log_format != "text" {
+        if LOG_FORMATS.contains(&config.logging.log_format.as_str()) {
+            set_log_format(&config.logging.log_format);
+        } else {
+            warn!("Unknown log_format '{}' in config; using text. Valid: {}", config.logging.log_format, LOG_FORMATS.join(", "));
+        }
+    }
+    // Recurring identical errors: full once, then a summary per interval (invalid = every one)
+    log_throttle::set_summary_interval(
+        std::time::Duration::try_from_secs_f64(config.logging.repeated_error_interval).unwrap_or_default(),
+    );
+
+    // Daemon: take over the log file with size rotation + retention pruning.
+    // Under journald, stdout already lands in the journal; a log file would duplicate it.
+    let journald = notify::is_journald_stream();
+    if systemd_mode && journald && config.logging.enable_file_logging {
+        info!("Logging to journald (journalctl -u pankha-agent); file logging skipped");
+    }
+    let mut active_log_file: Option<PathBuf> = None;
+    if service_mode && config.logging.enable_file_logging && !journald {
+        let mut logging = config.logging.clone();
+        match log_file_for_write(Some(std::path::Path::new(&logging.log_file))) {
+            Ok(path) => logging.log_file = path.to_string_lossy().to_string(),
+            Err(e) => warn!("{}", e),
+        }
+        match log_rotation::install(&logging) {
+            Ok(()) => {
+                active_log_file = Some(PathBuf::from(&logging.log_file));
+                tokio::spawn(async move {
+                    let mut daily = tokio::time::interval(std::time::Duration::from_secs(24 * 60 * 60));
+                    loop {
+                        daily.tick().await; // first tick fires immediately (startup prune)
+                        let removed = log_rotation::prune_rotated_logs(&logging);
+                        if removed > 0 {
+                            info!("Pruned {} rotated log file(s) older than {} days", removed, logging.log_retention_days);
+                        }
+                    }
+                });
+            }
+            Err(e) => warn!("Failed to open log file {} for rotation: {}", logging.log_file, e),
+        }
+    }
+
+    // From here on fans, the PID file and a pending config save are put back however
+    // the agent exits, a panic in any task included
+    #[cfg(target_os = "linux")]
+    let shutdown_guard = {
+        if args.daemon_child {
+            daemon::shutdown::update(|plan| plan.remove_pid_file_on_exit());
+        }
+        daemon::shutdown::install_panic_hook()
+    };
+
     // Create platform-specific hardware monitor
     #[cfg(target_os = "linux")]
-    let hardware_monitor: Arc<dyn HardwareMonitor> = Arc::new(LinuxHardwareMonitor::new(config.hardware.clone()));
+    let hardware_monitor: Arc<dyn HardwareMonitor> = {
+        // --dry-run applies to this run only; it never reaches the saved config
+        let mut hardware = config.hardware.clone();
+        hardware.dry_run |= args.dry_run;
+        if hardware.dry_run {
+            warn!("DRY RUN: fan writes are logged, not executed");
+        }
+        if !hardware.enable_sensor_monitoring {
+            warn!("SENSOR MONITORING DISABLED: no sensor readings are sent; they are still read for the emergency and failsafe checks");
+        }
+        if !hardware.enable_fan_control && config.backend.transport != config::types::Transport::UdpBroadcast {
+            warn!("FAN CONTROL DISABLED: setFanSpeed and emergencyStop are rejected, and neither failsafe nor emergencies touch the fans");
+        }
+        if config.backends.is_empty() {
+            Arc::new(LinuxHardwareMonitor::new(hardware))
+        } else {
+            let prefixes: Vec<_> = config.backends.iter().map(|b| b.prefix()).collect();
+            info!("Composite hardware mode: {}", prefixes.join(", "));
+            Arc::new(CompositeHardwareMonitor::from_config(&config.backends, &hardware)?)
+        }
+    };
 
     // Generate hardware-info.json diagnostic dump on startup (matches Windows agent behavior)
     #[cfg(target_os = "linux")]
     {
-        let linux_monitor = LinuxHardwareMonitor::new(config.hardware.clone());
-        match linux_monitor.dump_hardware_info().await {
+        match hardware_monitor.dump_hardware_info().await {
             Ok(dump) => {
+                let dump = dump.in_unit(config.hardware.temperature_unit);
                 let dump_path = std::env::current_exe()
                     .ok()
                     .and_then(|p| p.parent().map(|d| d.join("hardware-info.json")))
                     .unwrap_or_else(|| PathBuf::from("hardware-info.json"));
 
-                match serde_json::to_string_pretty(&dump) {
+                let json = serde_json::to_value(&dump)
+                    .and_then(|dump| serde_json::to_string_pretty(&app::redact::diagnostics(&config, dump)));
+                match json {
                     Ok(json) => {
                         if let Err(e) = std::fs::write(&dump_path, json) {
                             warn!("Failed to write hardware-info.json: {}", e);
@@ -311,27 +500,79 @@ async fn main() -> Result<()> {
         }
     }
 
-    // Test mode
+    // Test mode: exit status 1 when any check failed, for scripted setups
     if args.test {
         info!("Running in test mode");
-        let sensors = hardware_monitor.discover_sensors().await?;
-        let fans = hardware_monitor.discover_fans().await?;
-        info!("Discovered {} sensors and {} fans", sensors.len(), fans.len());
+        let passed = app::hardware_test::run(hardware_monitor.as_ref(), &config.hardware, args.with_fan_test, args.json).await?;
+        if !passed {
+            std::process::exit(1);
+        }
         return Ok(());
     }
 
+    // Characterization: exit status 1 when it stopped early or a fan wasn't restored
+    if args.characterize {
+        info!("Running fan characterization");
+        let completed = app::characterize::run_cli(hardware_monitor.as_ref(), &config.hardware, args.force_local,
+                                                   args.target_temp, args.json).await?;
+        if !completed {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Crash-loop detection: this start counts until a clean shutdown
+    let safe_mode = daemon::safe_mode::update(|history| {
+        history.record_start(chrono::Utc::now().timestamp_millis(),
+                             config.agent.safe_mode_after_starts, config.agent.safe_mode_window_minutes)
+    }).flatten();
+
+    // Drop root once the PWM files are open; writes go through the kept handles
+    #[cfg(target_os = "linux")]
+    if let Some(user) = config.agent.run_as_user.as_deref().filter(|u| !u.is_empty()) {
+        use daemon::privileges::{drop_privileges, hand_over, lookup_user};
+        let user = lookup_user(user)?;
+        hardware_monitor.discover_fans().await?;
+
+        let mut handoff: Vec<PathBuf> = active_log_file.into_iter().collect();
+        handoff.extend(daemon::platform::find_pid_file());
+        if let Some(exe_dir) = std::env::current_exe()?.parent() {
+            handoff.push(exe_dir.join("config.json"));
+            handoff.push(exe_dir.join(hardware::snapshot::SNAPSHOT_FILE));
+            handoff.push(exe_dir.join(hardware::linux::discovery_cache::DISCOVERY_CACHE_FILE));
+            handoff.push(exe_dir.join(websocket::emergency_actions::EMERGENCY_ACTIONS_FILE));
+            handoff.push(exe_dir.join(daemon::safe_mode::START_HISTORY_FILE));
+        }
+        hand_over(&user, &handoff);
+        drop_privileges(&user)?;
+    }
+
     // Keep a handle to restore GPU fans to driver-auto on shutdown (no-op for sysfs/IPMI).
     let hw_for_shutdown = Arc::clone(&hardware_monitor);
 
     // Create and run WebSocket client
     let client = WebSocketClient::new(config, hardware_monitor);
     let client = Arc::new(client);
+    #[cfg(target_os = "linux")]
+    daemon::shutdown::update(|plan| plan.flush_config_on_exit(Arc::clone(&client.config)));
+    if let Some(mode) = safe_mode {
+        client.enter_safe_mode(mode).await;
+    }
+
+    if args.simulate_panic {
+        let delay = std::time::Duration::from_secs_f64(client.config.load().agent.update_interval);
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            panic!("Simulated panic (--simulate-panic)");
+        });
+    }
 
     // Setup SIGHUP handler for log level reload
     #[cfg(target_os = "linux")]
-    if args.daemon_child {
+    if service_mode {
         use tokio::signal::unix::{signal, SignalKind};
         let mut sighup = signal(SignalKind::hangup()).expect("Failed to setup SIGHUP handler");
+        let config = Arc::clone(&client.config);
 
         tokio::spawn(async move {
             loop {
@@ -341,6 +582,12 @@ async fn main() -> Result<()> {
                 // Reload config from file
                 match load_config(None).await {
                     Ok(new_config) => {
+                        // The running config too, or its next save writes the old level back
+                        let level = new_config.agent.log_level.clone();
+                        history::with_origin(ConfigOrigin::new(ConfigSource::Sighup), async {
+                            config.update(|c| c.agent.log_level = level);
+                        }).await;
+
                         let new_level = new_config.agent.log_level.to_lowercase();
                         let filter = match new_level.as_str() {
                             "critical" => "error",
@@ -394,6 +641,7 @@ async fn main() -> Result<()> {
             tokio::signal::ctrl_c().await.ok();
             info!("Shutdown signal received (Ctrl+C)");
         }
+        notify::stopping();
         client_clone.stop().await;
     });
 
@@ -409,6 +657,14 @@ async fn main() -> Result<()> {
         }
     }
 
+    // A characterization run puts its fans back before the original modes are restored
+    client.finish_characterization(std::time::Duration::from_secs(10)).await;
+
+    // Settings changed right before the signal may still be waiting for their save
+    if let Err(e) = client.config.flush().await {
+        error!("Failed to save configuration on shutdown: {:#}", e);
+    }
+
     // On shutdown, hand any agent-controlled GPU fan back to the driver's auto curve.
     // No-op for sysfs/IPMI fans; only NVML-owned GPU fans respond (Ok(true)).
     match hw_for_shutdown.discover_fans().await {
@@ -422,14 +678,11 @@ async fn main() -> Result<()> {
         Err(e) => warn!("Could not enumerate fans for shutdown restore: {}", e),
     }
 
-    // Clean up PID file after shutdown
-    if let Ok(Some(pid)) = get_pid() {
-        if pid == std::process::id() {
-            let _ = remove_pid_file();
-            info!("PID file cleaned up");
-        }
-    }
+    // Original fan modes back, PID file removed (systemd mode never wrote one)
+    #[cfg(target_os = "linux")]
+    drop(shutdown_guard);
 
+    daemon::safe_mode::update(|history| history.clean_exit());
     info!("Agent shutdown complete");
     Ok(())
 }
@@ -2,3 +2,6 @@
 //! See build.rs for the resolution logic.
 
 pub const VERSION: &str = env!("PANKHA_VERSION");
+
+/// Agent <-> backend message protocol; bump on incompatible message changes.
+pub const PROTOCOL_VERSION: u32 = 1;
@@ -1,6 +1,28 @@
 //! WebSocket module re-exports.
 
+pub mod adaptive_interval;
+pub mod characterization;
+pub mod chunked;
 pub mod client;
+pub mod command_cache;
 pub mod commands;
+pub mod compression;
+pub mod config_report;
+pub mod data_request;
+pub mod disconnect;
+pub mod emergency_actions;
+pub mod connection_check;
+pub mod event_log;
+pub mod failsafe;
+pub mod incoming;
+pub mod link_quality;
+pub mod link_status;
+pub mod local_curve;
 pub mod messaging;
+pub mod notifications;
+pub mod payload;
+pub mod protocol;
 pub mod self_update;
+pub mod time_sync;
+pub mod transport;
+pub mod udp_broadcast;
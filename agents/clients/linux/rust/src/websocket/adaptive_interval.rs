@@ -0,0 +1,180 @@
+//! `agent.adaptive_interval`: the time between data frames, from how fast sensors move.
+//!
+//! Each frame's readings are compared with the previous frame's; the fastest change,
+//! in °C per second so short and long intervals compare alike, decides the next
+//! interval. Past `ramp_threshold` it halves (down to `min_interval`), below half the
+//! threshold it grows by a quarter (up to `update_interval`), and in between it holds.
+//! Frames carry the interval chosen after them as `interval_ms`, so the backend can
+//! tell a planned gap from a missed frame. A frame sooner than `min_interval` after the
+//! last (a `requestData` one) reports the interval without moving it.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::client::WebSocketClient;
+use crate::config::types::AdaptiveInterval;
+use crate::hardware::types::Sensor;
+
+/// Factor the interval grows by per steady frame.
+const RELAX_FACTOR: f64 = 1.25;
+
+#[derive(Debug, Default)]
+pub(crate) struct IntervalController {
+    /// Readings of the last frame, by sensor id
+    last: HashMap<Arc<str>, f64>,
+    last_at: Option<Instant>,
+    /// Seconds; `None` until the first adaptive frame (then the maximum)
+    current: Option<f64>,
+}
+
+impl IntervalController {
+    /// The interval after a frame with `sensors`, taken at `now`.
+    pub(crate) fn frame(&mut self, settings: &AdaptiveInterval, max_interval: f64, sensors: &[Sensor], now: Instant) -> Duration {
+        let too_soon = self.last_at.is_some_and(|at| {
+            now.saturating_duration_since(at).as_secs_f64() < settings.min_interval.min(max_interval)
+        });
+        if settings.enabled && too_soon {
+            return self.current(settings, max_interval);
+        }
+        let fastest_change = self.fastest_change(sensors, now);
+        self.next(settings, max_interval, fastest_change)
+    }
+
+    /// Fastest change of any sensor since the last call, in °C per second. `None` on the
+    /// first call, or when no sensor was in both frames.
+    pub(crate) fn fastest_change(&mut self, sensors: &[Sensor], now: Instant) -> Option<f64> {
+        let elapsed = self.last_at.map(|at| now.saturating_duration_since(at).as_secs_f64());
+        let fastest = elapsed.filter(|e| *e > 0.0).and_then(|elapsed| {
+            sensors
+                .iter()
+                .filter_map(|s| self.last.get(&s.id).map(|last| (s.temperature - last).abs() / elapsed))
+                .max_by(f64::total_cmp)
+        });
+        self.last = sensors.iter().map(|s| (Arc::clone(&s.id), s.temperature)).collect();
+        self.last_at = Some(now);
+        fastest
+    }
+
+    /// Interval until the next frame after one whose fastest change was `fastest_change`.
+    /// `max_interval` is `update_interval`, also the interval with adaptive mode off.
+    pub(crate) fn next(&mut self, settings: &AdaptiveInterval, max_interval: f64, fastest_change: Option<f64>) -> Duration {
+        if !settings.enabled {
+            self.current = None;
+            return Duration::from_secs_f64(max_interval);
+        }
+        let min_interval = settings.min_interval.min(max_interval);
+        let current = self.current.unwrap_or(max_interval);
+        let next = match fastest_change {
+            Some(rate) if rate >= settings.ramp_threshold => current / 2.0,
+            Some(rate) if rate >= settings.ramp_threshold / 2.0 => current,
+            _ => current * RELAX_FACTOR,
+        }
+        .clamp(min_interval, max_interval);
+        self.current = Some(next);
+        Duration::from_secs_f64(next)
+    }
+
+    /// The interval last chosen, within the current bounds.
+    pub(crate) fn current(&self, settings: &AdaptiveInterval, max_interval: f64) -> Duration {
+        let interval = match self.current {
+            Some(current) if settings.enabled => current.clamp(settings.min_interval.min(max_interval), max_interval),
+            _ => max_interval,
+        };
+        Duration::from_secs_f64(interval)
+    }
+}
+
+impl WebSocketClient {
+    /// `interval_ms` for a data frame with `sensors`; `None` with adaptive mode off.
+    pub(crate) fn frame_interval_ms(&self, sensors: &[Sensor]) -> Option<u64> {
+        let config = self.config.load();
+        let interval = self.frame_interval.lock().unwrap()
+            .frame(&config.agent.adaptive_interval, config.agent.update_interval, sensors, Instant::now());
+        config.agent.adaptive_interval.enabled.then_some(interval.as_millis() as u64)
+    }
+
+    /// Time until the next periodic data frame.
+    pub(crate) fn next_frame_interval(&self) -> Duration {
+        let config = self.config.load();
+        self.frame_interval.lock().unwrap().current(&config.agent.adaptive_interval, config.agent.update_interval)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor(id: &str, temperature: f64) -> Sensor {
+        Sensor {
+            id: id.into(),
+            name: id.into(),
+            display_name: None,
+            temperature,
+            sensor_type: "cpu".into(),
+            max_temp: None,
+            crit_temp: None,
+            chip: None,
+            hardware_name: None,
+            source: None,
+            alarms: Vec::new(),
+            raw_temperature: None,
+            stale: false,
+        }
+    }
+
+    #[test]
+    fn interval_follows_the_fastest_sensor() {
+        let settings = AdaptiveInterval { enabled: true, min_interval: 1.0, ramp_threshold: 0.5 };
+        let mut controller = IntervalController::default();
+        let mut next = |change: Option<f64>| controller.next(&settings, 5.0, change).as_secs_f64();
+
+        // Steady: stays at update_interval
+        assert_eq!(next(None), 5.0);
+        assert_eq!(next(Some(0.1)), 5.0);
+        // A ramp halves it down to the minimum
+        assert_eq!(next(Some(2.0)), 2.5);
+        assert_eq!(next(Some(0.8)), 1.25);
+        assert_eq!(next(Some(0.8)), 1.0);
+        // Between half the threshold and the threshold: holds
+        assert_eq!(next(Some(0.3)), 1.0);
+        // Steady again: relaxes back to update_interval
+        let relaxed: Vec<f64> = (0..8).map(|_| next(Some(0.0))).collect();
+        assert_eq!(relaxed[0], 1.25);
+        assert!(relaxed.windows(2).all(|w| w[0] <= w[1]));
+        assert_eq!(relaxed[7], 5.0);
+
+        // Off: always update_interval, which also bounds the minimum
+        let off = AdaptiveInterval { enabled: false, ..settings };
+        assert_eq!(controller.next(&off, 3.0, Some(10.0)), Duration::from_secs(3));
+        assert_eq!(controller.current(&off, 3.0), Duration::from_secs(3));
+        let slow_minimum = AdaptiveInterval { min_interval: 10.0, ..settings };
+        assert_eq!(controller.next(&slow_minimum, 2.0, Some(10.0)), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn change_is_measured_per_second_across_frames() {
+        let start = Instant::now();
+        let mut controller = IntervalController::default();
+        assert_eq!(controller.fastest_change(&[sensor("cpu", 50.0), sensor("nvme", 40.0)], start), None);
+
+        let change = controller.fastest_change(&[sensor("cpu", 54.0), sensor("nvme", 39.0)], start + Duration::from_secs(2));
+        assert_eq!(change, Some(2.0));
+        // A sensor that wasn't in the last frame has nothing to compare with
+        let change = controller.fastest_change(&[sensor("gpu", 90.0), sensor("nvme", 39.5)], start + Duration::from_secs(3));
+        assert_eq!(change, Some(0.5));
+    }
+
+    #[test]
+    fn frames_sooner_than_the_minimum_leave_the_interval_alone() {
+        let settings = AdaptiveInterval { enabled: true, min_interval: 1.0, ramp_threshold: 0.5 };
+        let start = Instant::now();
+        let mut controller = IntervalController::default();
+        assert_eq!(controller.frame(&settings, 4.0, &[sensor("cpu", 50.0)], start), Duration::from_secs(4));
+        assert_eq!(controller.frame(&settings, 4.0, &[sensor("cpu", 60.0)], start + Duration::from_secs(4)), Duration::from_secs(2));
+        // A requested frame 100ms later: a 0.3°C jitter is not a 3°C/s ramp
+        let requested = start + Duration::from_millis(4100);
+        assert_eq!(controller.frame(&settings, 4.0, &[sensor("cpu", 60.3)], requested), Duration::from_secs(2));
+        assert_eq!(controller.frame(&settings, 4.0, &[sensor("cpu", 60.0)], start + Duration::from_secs(6)), Duration::from_millis(2500));
+    }
+}
@@ -0,0 +1,209 @@
+//! `characterizeFans`: a fan characterization (app/characterize.rs) started from the
+//! Hub, which watches it through `getCharacterization`.
+//!
+//! The run goes on in the background; the command is answered right away. Its fans
+//! are left to it: the backend's `setFanSpeed` for them is refused until it ends. It
+//! is stopped by `cancelCharacterization`, `emergencyStop`, a lost connection
+//! (failsafe) and the agent stopping. However it ends, the fans get their original
+//! state back, and then what stopped it applies again: full speed after an
+//! `emergencyStop`, the failsafe speed while disconnected.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tracing::{info, warn};
+
+use super::client::WebSocketClient;
+use super::event_log::Severity;
+use crate::app::characterize::{self, CharacterizationReport, CharacterizeOptions};
+
+/// What ends a run early.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum StopRequest {
+    Cancelled,
+    EmergencyStop,
+    ConnectionLost,
+    Shutdown,
+}
+
+impl std::fmt::Display for StopRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Cancelled => "cancelled",
+            Self::EmergencyStop => "emergency stop",
+            Self::ConnectionLost => "backend connection lost",
+            Self::Shutdown => "agent stopping",
+        })
+    }
+}
+
+/// The run in progress, if any, and the outcome of the last one.
+#[derive(Debug, Default, Serialize)]
+pub(crate) struct CharacterizationStatus {
+    pub(crate) running: bool,
+    /// Fans of the run in progress
+    pub(crate) fans: Vec<String>,
+    /// Last progress line of the run in progress
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) progress: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) report: Option<CharacterizationReport>,
+    /// Why the last run couldn't start
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) error: Option<String>,
+    #[serde(skip)]
+    stop: Option<StopRequest>,
+}
+
+impl CharacterizationStatus {
+    /// A run in progress holds this fan.
+    pub(crate) fn drives(&self, fan_id: &str) -> bool {
+        self.running && self.fans.iter().any(|fan| fan == fan_id)
+    }
+}
+
+impl WebSocketClient {
+    /// `characterizeFans`: check the run may start and start it. Returns the fans
+    /// it covers, or why it can't run.
+    pub(crate) async fn start_characterization(&self, payload: &serde_json::Value) -> Result<Vec<String>, String> {
+        let options = CharacterizeOptions::from_payload(payload).map_err(|e| format!("{:#}", e))?;
+        if let Some(reason) = self.fan_control_disabled() {
+            return Err(reason.to_string());
+        }
+        if self.fan_writes_blocked().await {
+            return Err("Fan control is disabled: conflicting fan control software detected".to_string());
+        }
+        if *self.failsafe_active.read().await {
+            return Err("Not connected to the backend (failsafe)".to_string());
+        }
+        if self.hardware_monitor.is_dry_run() {
+            return Err("Dry run: fan writes are not executed".to_string());
+        }
+        let fans = self.hardware_monitor.discover_fans().await.map_err(|e| format!("{:#}", e))?;
+        let fans: Vec<String> = characterize::fan_groups(&fans, &options.fans).into_iter().flatten().collect();
+        if fans.is_empty() {
+            return Err("No controllable fans to characterize".to_string());
+        }
+
+        {
+            let mut status = self.characterization.lock().unwrap();
+            if status.running {
+                return Err("A characterization is already running".to_string());
+            }
+            *status = CharacterizationStatus { running: true, fans: fans.clone(), report: status.report.take(), ..Default::default() };
+        }
+        info!("Fan characterization started: {}", fans.join(", "));
+        let client = self.clone_for_update();
+        tokio::spawn(async move { client.run_characterization(options).await });
+        Ok(fans)
+    }
+
+    /// Stop the run in progress. False if none is running.
+    pub(crate) fn stop_characterization(&self, reason: StopRequest) -> bool {
+        let mut status = self.characterization.lock().unwrap();
+        if status.running && status.stop.is_none() {
+            info!("Stopping the fan characterization: {}", reason);
+            status.stop = Some(reason);
+        }
+        status.running
+    }
+
+    /// Stop the run in progress and wait up to `timeout` for its fans to be restored
+    /// (agent shutdown).
+    pub async fn finish_characterization(&self, timeout: Duration) {
+        if !self.stop_characterization(StopRequest::Shutdown) {
+            return;
+        }
+        let deadline = std::time::Instant::now() + timeout;
+        while self.characterization.lock().unwrap().running && std::time::Instant::now() < deadline {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    async fn run_characterization(&self, options: CharacterizeOptions) {
+        let hardware = self.config.load().hardware.clone();
+        let stop = || self.characterization.lock().unwrap().stop.map(|reason| reason.to_string());
+        let progress = |line: String| {
+            info!("Characterization: {}", line.trim());
+            self.characterization.lock().unwrap().progress = Some(line.trim().to_string());
+        };
+        let result = characterize::run(self.hardware_monitor.as_ref(), &hardware, &options, &stop, &progress).await;
+
+        let stopped_by = {
+            let mut status = self.characterization.lock().unwrap();
+            let stopped_by = status.stop.take();
+            status.running = false;
+            status.fans.clear();
+            status.progress = None;
+            match &result {
+                Ok(report) => status.report = Some(report.clone()),
+                Err(e) => status.error = Some(format!("{:#}", e)),
+            }
+            stopped_by
+        };
+        match result {
+            Ok(report) => {
+                let severity = if report.aborted.is_some() || !report.restore_failures.is_empty() { Severity::Warning } else { Severity::Info };
+                let message = match &report.aborted {
+                    Some(reason) => format!("Fan characterization stopped early: {}", reason),
+                    None => format!("Fan characterization finished: {} fan groups", report.groups.len()),
+                };
+                info!("{}", message);
+                self.record_event(severity, "characterization_finished", message, serde_json::json!({
+                    "aborted": report.aborted, "groups": report.groups.len(), "restore_failures": report.restore_failures,
+                })).await;
+            }
+            Err(e) => {
+                warn!("Fan characterization did not start: {:#}", e);
+                self.record_event(Severity::Warning, "characterization_finished", format!("Fan characterization did not start: {:#}", e),
+                                  serde_json::json!({ "error": format!("{:#}", e) })).await;
+            }
+        }
+
+        // The original states are back; what stopped the run takes the fans again
+        if stopped_by == Some(StopRequest::EmergencyStop) {
+            if let Err(e) = self.hardware_monitor.max_cooling().await {
+                warn!("Failed to return fans to full speed after the characterization: {:#}", e);
+            }
+        } else if *self.failsafe_active.read().await {
+            self.local_curves.lock().await.reset();
+            if let Err(e) = self.set_all_fans_to_speed(hardware.failsafe_speed).await {
+                warn!("Failed to return fans to failsafe speed after the characterization: {:#}", e);
+            }
+        }
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use super::*;
+    use crate::config::types::AgentConfig;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm, Temp};
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn stopped_runs_restore_fans_then_reapply_full_speed() {
+        let sysfs = FakeSysfs::new("characterization-client")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 45_000)).fan(1, 800).pwm(Pwm::new(1, 100).enable(1)));
+        let read = |file: &str| std::fs::read_to_string(sysfs.chip_dir(0).join(file)).unwrap().trim().to_string();
+        let config = AgentConfig::default();
+        let client = WebSocketClient::new(config.clone(), Arc::new(sysfs.monitor_with(config.hardware)));
+
+        assert_eq!(client.start_characterization(&serde_json::json!({"duties": [10]})).await.unwrap_err(), "Duty 10% is below the 20% minimum");
+        client.hardware_monitor.discover_fans().await.unwrap();
+        tokio::time::sleep(Duration::from_millis(150)).await; // past the per-fan write rate limit
+        assert_eq!(client.start_characterization(&serde_json::json!({})).await.unwrap(), ["nct6798_fan_1"]);
+        assert!(client.characterization.lock().unwrap().drives("nct6798_fan_1"));
+        assert_eq!(client.start_characterization(&serde_json::json!({})).await.unwrap_err(), "A characterization is already running");
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        assert_eq!(read("pwm1"), "255");
+        client.stop_characterization(StopRequest::EmergencyStop);
+        client.finish_characterization(Duration::from_secs(10)).await;
+        let status = client.characterization.lock().unwrap();
+        assert!(!status.running && !status.drives("nct6798_fan_1"));
+        assert_eq!(status.report.as_ref().unwrap().aborted.as_deref(), Some("Stopped at 100%: emergency stop"));
+        // Back to pwm1=100 in manual mode, then full speed for the emergency stop
+        assert_eq!((read("pwm1"), read("pwm1_enable")), ("255".into(), "1".into()));
+    }
+}
@@ -0,0 +1,137 @@
+//! Chunked transfer of large command responses (`chunked_responses`).
+//!
+//! A diagnostics dump of a host with dozens of drives runs to most of a megabyte,
+//! more than a proxy or the backend takes in one frame. A response whose JSON is
+//! over `CHUNK_THRESHOLD` is gzipped and sent as a `commandResponseStart`, base64
+//! `commandResponseChunk`s of at most `CHUNK_SIZE` bytes and a `commandResponseEnd`
+//! with the CRC-32 of the bytes sent, all carrying the response's commandId. The
+//! backend reassembles them into the `commandResponse` it would have received in
+//! one piece. Smaller responses, and backends that don't list the feature, keep the
+//! single text frame.
+
+use std::io::Write;
+
+use anyhow::Result;
+use base64::Engine;
+use flate2::write::GzEncoder;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// Largest response JSON sent as one frame.
+pub(crate) const CHUNK_THRESHOLD: usize = 256 * 1024;
+
+/// Bytes of (gzipped) response per chunk, before base64.
+pub(crate) const CHUNK_SIZE: usize = 64 * 1024;
+
+/// The frames for a serialized response: the JSON as is, or, when `chunked` and
+/// over the threshold, the start/chunk/end messages of its gzipped bytes.
+pub(crate) fn response_frames(command_id: &str, json: String, chunked: bool) -> Result<Vec<Message>> {
+    if !chunked || json.len() <= CHUNK_THRESHOLD {
+        return Ok(vec![Message::text(json)]);
+    }
+    let mut encoder = GzEncoder::new(Vec::with_capacity(json.len() / 4), flate2::Compression::default());
+    encoder.write_all(json.as_bytes())?;
+    let messages = chunk_messages(command_id, &encoder.finish()?, json.len(), "gzip");
+    Ok(messages.into_iter().map(|m| Message::text(m.to_string())).collect())
+}
+
+/// Start, chunk and end messages for `bytes`, the `encoding` of `original_size`
+/// bytes of JSON.
+fn chunk_messages(command_id: &str, bytes: &[u8], original_size: usize, encoding: &str) -> Vec<serde_json::Value> {
+    let chunks: Vec<&[u8]> = bytes.chunks(CHUNK_SIZE).collect();
+    let mut crc = flate2::Crc::new();
+    crc.update(bytes);
+
+    let mut messages = Vec::with_capacity(chunks.len() + 2);
+    messages.push(serde_json::json!({
+        "type": "commandResponseStart",
+        "commandId": command_id,
+        "totalSize": bytes.len(),
+        "originalSize": original_size,
+        "chunkCount": chunks.len(),
+        "encoding": encoding,
+    }));
+    for (index, chunk) in chunks.iter().enumerate() {
+        messages.push(serde_json::json!({
+            "type": "commandResponseChunk",
+            "commandId": command_id,
+            "index": index,
+            "data": base64::engine::general_purpose::STANDARD.encode(chunk),
+        }));
+    }
+    messages.push(serde_json::json!({
+        "type": "commandResponseEnd",
+        "commandId": command_id,
+        "checksum": format!("crc32:{:08x}", crc.sum()),
+    }));
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use serde_json::Value;
+
+    /// The backend's side: the JSON the frames of one transfer carry, checked
+    /// against the start and end messages.
+    fn reassemble(frames: &[Message]) -> String {
+        let messages: Vec<Value> = frames.iter().map(|f| serde_json::from_str(f.to_text().unwrap()).unwrap()).collect();
+        let (start, rest) = messages.split_first().unwrap();
+        let (end, chunks) = rest.split_last().unwrap();
+        assert_eq!(start["type"], "commandResponseStart");
+        assert_eq!(end["type"], "commandResponseEnd");
+        assert_eq!(start["chunkCount"], chunks.len());
+
+        let mut bytes = Vec::new();
+        for (index, chunk) in chunks.iter().enumerate() {
+            assert_eq!((&chunk["type"], &chunk["commandId"], &chunk["index"]), (&"commandResponseChunk".into(), &start["commandId"], &index.into()));
+            let data = base64::engine::general_purpose::STANDARD.decode(chunk["data"].as_str().unwrap()).unwrap();
+            assert!(data.len() <= CHUNK_SIZE);
+            bytes.extend(data);
+        }
+        assert_eq!(start["totalSize"], bytes.len());
+        let mut crc = flate2::Crc::new();
+        crc.update(&bytes);
+        assert_eq!(end["checksum"], format!("crc32:{:08x}", crc.sum()));
+
+        assert_eq!(start["encoding"], "gzip");
+        let mut json = String::new();
+        flate2::read::GzDecoder::new(&bytes[..]).read_to_string(&mut json).unwrap();
+        assert_eq!(start["originalSize"], json.len());
+        json
+    }
+
+    /// A `getDiagnostics` response of about `drives` drives' worth of entries.
+    fn large_response(drives: usize) -> String {
+        let entries: Vec<Value> = (0..drives * 400)
+            .map(|i| serde_json::json!({"path": format!("/sys/class/hwmon/hwmon{}/temp{}_input", i / 8, i % 8), "value": i * 37 % 1000 }))
+            .collect();
+        serde_json::json!({"type": "commandResponse", "commandId": "diag-1", "success": true, "data": {"entries": entries}}).to_string()
+    }
+
+    #[test]
+    fn small_responses_keep_one_frame() {
+        let json = serde_json::json!({"type": "commandResponse", "commandId": "c1", "success": true}).to_string();
+        assert_eq!(response_frames("c1", json.clone(), true).unwrap(), [Message::text(json.clone())]);
+
+        // Too large, but the backend doesn't reassemble
+        let json = large_response(24);
+        assert!(json.len() > CHUNK_THRESHOLD);
+        assert_eq!(response_frames("diag-1", json.clone(), false).unwrap(), [Message::text(json)]);
+    }
+
+    #[test]
+    fn large_response_reassembles_and_matches_checksum() {
+        let json = large_response(24);
+        let frames = response_frames("diag-1", json.clone(), true).unwrap();
+        assert!(frames.len() > 2);
+        assert_eq!(reassemble(&frames), json);
+
+        // Three chunks, the last one short
+        let bytes: Vec<u8> = (0..CHUNK_SIZE * 5 / 2).map(|i| (i * 7919 % 251) as u8).collect();
+        let messages = chunk_messages("c2", &bytes, bytes.len(), "identity");
+        assert_eq!((messages.len(), &messages[0]["chunkCount"]), (5, &3.into()));
+        assert!(messages.iter().all(|m| m["commandId"] == "c2"));
+    }
+}
@@ -6,22 +6,58 @@ use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
 use tokio::time;
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio_tungstenite::tungstenite::{self, protocol::frame::coding::CloseCode, protocol::CloseFrame, protocol::Message};
 use tracing::{debug, error, info, warn};
 
-use crate::config::types::AgentConfig;
+use crate::app::log_throttle::LogThrottle;
+use crate::app::redact;
+use crate::app::suspend::ResumeDetector;
+use crate::config::handle::ConfigHandle;
+use crate::config::history::{self, ConfigOrigin, ConfigSource};
+use crate::config::types::{AgentConfig, HardwareSettings, Transport};
+use crate::daemon::notify;
+use crate::daemon::safe_mode::{self, SafeMode};
+use crate::daemon::state_file::{AgentState, StateFile};
+use crate::hardware::types::{FanControlConflict, FanSpeedOrigin, RunEnvironment, Sensor};
 use crate::hardware::HardwareMonitor;
+use crate::hardware::snapshot::SnapshotTracker;
+use crate::hardware::stats::SensorStats;
+use super::characterization::{CharacterizationStatus, StopRequest};
+use super::failsafe::{FailsafeAction, FailsafeController, FailsafeState};
+use super::incoming;
+use super::command_cache::CommandCache;
+use super::commands::{FAN_CONTROL_DISABLED, MONITORING_ONLY_CONTAINER};
+use super::data_request::DataRequests;
+use super::disconnect::{self, DisconnectCategory, DisconnectCause};
+use super::emergency_actions::EmergencyActionState;
+use super::event_log::{EventLog, Severity};
+use super::adaptive_interval::IntervalController;
+use super::link_quality::{LinkQuality, Measure};
+use super::link_status::LinkStatus;
+use super::local_curve::CurveEngine;
+use super::notifications::NotificationState;
+use super::payload::RegisteredHardware;
+use super::protocol::{Negotiated, FEATURE_CONFIGURATION_APPLIED};
+use super::time_sync::{self, ClockSkew};
+use super::transport::{self, Route, WsStream};
 
 /// Type alias for the WebSocket write half (used across websocket submodules).
-pub(crate) type WsSink = futures_util::stream::SplitSink<
-    tokio_tungstenite::WebSocketStream<
-        tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>,
-    >,
-    Message,
->;
+pub(crate) type WsSink = futures_util::stream::SplitSink<WsStream, Message>;
+
+/// A failsafe emergency that tripped while disconnected; reported to the backend as
+/// an `emergencyAlert` message once reconnected.
+#[derive(Debug, Clone)]
+pub(crate) struct EmergencyTrip {
+    pub(crate) sensor_id: String,
+    pub(crate) sensor_type: String,
+    pub(crate) temperature: f64,
+    /// `None` for a kernel crit alarm rather than a threshold
+    pub(crate) threshold: Option<f64>,
+    pub(crate) timestamp: i64,
+}
 
 pub struct WebSocketClient {
-    pub(crate) config: Arc<RwLock<AgentConfig>>,
+    pub(crate) config: Arc<ConfigHandle>,
     pub(crate) hardware_monitor: Arc<dyn HardwareMonitor>,
     pub(crate) running: Arc<RwLock<bool>>,
     // Failsafe mode tracking - activates when disconnected from backend
@@ -29,19 +65,270 @@ pub struct WebSocketClient {
     // Edge-triggered dedup of agent-emitted `{type:"error"}` messages.
     // Prevents spamming the backend on every retry while init is broken.
     pub(crate) last_reported_error: Arc<tokio::sync::Mutex<Option<String>>>,
+    // First emergency of the current outage, sent after the next registration
+    pub(crate) pending_emergency: Arc<tokio::sync::Mutex<Option<EmergencyTrip>>>,
+    // Offline emergency escalation / cooldown state (see failsafe.rs)
+    pub(crate) failsafe_controller: Arc<tokio::sync::Mutex<FailsafeController>>,
+    // Crit alarm escalated while connected; held until the alarm clears
+    pub(crate) crit_alarm_tripped: Arc<std::sync::atomic::AtomicBool>,
+    /// `hardware.local_curves` between failsafe emergencies
+    pub(crate) local_curves: Arc<tokio::sync::Mutex<CurveEngine>>,
+    // Emergency held at full fan speed, and the last-resort actions it triggered
+    pub(crate) emergency_actions: Arc<tokio::sync::Mutex<EmergencyActionState>>,
+    // Outage history for the `status` block of data messages
+    pub(crate) link_status: Arc<tokio::sync::Mutex<LinkStatus>>,
+    // Responses to recent commandIds, replayed when the backend redelivers a command
+    pub(crate) command_cache: Arc<tokio::sync::Mutex<CommandCache>>,
+    // Optional features agreed with the backend at registration (see protocol.rs)
+    pub(crate) negotiated: Arc<RwLock<Negotiated>>,
+    // Devices whose metadata the backend got at registration (compact payloads)
+    pub(crate) registered_hardware: Arc<RwLock<RegisteredHardware>>,
+    // Message types from a newer backend we've already logged as unhandled
+    pub(crate) unknown_message_types: Arc<tokio::sync::Mutex<std::collections::HashSet<String>>>,
+    // Discovered hardware persisted between runs; startup diff goes into registration
+    pub(crate) hardware_snapshot: Arc<tokio::sync::Mutex<SnapshotTracker>>,
+    // Per-sensor min/max/avg since start and over the rolling window, fed by every
+    // data cycle and failsafe check (connected or not)
+    pub(crate) sensor_stats: Arc<tokio::sync::Mutex<SensorStats>>,
+    // Notable events (failsafe, emergencies, write failures, ...) for `getEvents`
+    pub(crate) events: Arc<tokio::sync::Mutex<EventLog>>,
+    // Other fan-control software found at startup (see hardware/linux/conflicts.rs)
+    pub(crate) fan_control_conflicts: Arc<RwLock<Vec<FanControlConflict>>>,
+    // Container without hwmon found at startup (see hardware/linux/environment.rs)
+    pub(crate) environment: Arc<std::sync::Mutex<RunEnvironment>>,
+    // Repeated data send failures, summarized across reconnects
+    pub(crate) send_errors: Arc<LogThrottle>,
+    // Suspend/resume detection, checked by the read loop and the reconnect wait
+    pub(crate) resume: Arc<std::sync::Mutex<ResumeDetector>>,
+    // Set on resume: reconnect without the backoff wait
+    pub(crate) reconnect_now: Arc<std::sync::atomic::AtomicBool>,
+    // Size of the last data message, the capacity of the next one's buffer
+    pub(crate) data_message_bytes: Arc<std::sync::atomic::AtomicUsize>,
+    // Agent start, for the monotonic `uptime_ms` of data messages and pongs
+    pub(crate) started: std::time::Instant,
+    // `uptime_ms` of the last monitoring cycle, connected or not; feeds the systemd watchdog
+    pub(crate) last_cycle_ms: Arc<std::sync::atomic::AtomicU64>,
+    // Whether the backend last reported our clock as skewed (see time_sync.rs)
+    pub(crate) clock_skew: Arc<std::sync::Mutex<ClockSkew>>,
+    // Backend connection latencies for systemHealth.connection (see link_quality.rs)
+    pub(crate) link_quality: Arc<std::sync::Mutex<LinkQuality>>,
+    // Time between data frames with agent.adaptive_interval (see adaptive_interval.rs)
+    pub(crate) frame_interval: Arc<std::sync::Mutex<IntervalController>>,
+    // Why the previous connection ended, for the next registration (see disconnect.rs)
+    pub(crate) last_disconnect: Arc<std::sync::Mutex<Option<DisconnectCause>>>,
+    /// Webhook rate limit and counters (`notifications`)
+    pub(crate) notifications: Arc<std::sync::Mutex<NotificationState>>,
+    // Set when this run started in a crash loop (see daemon/safe_mode.rs)
+    pub(crate) safe_mode: Arc<RwLock<Option<SafeMode>>>,
+    // requestData commands for the current connection's data sender (see data_request.rs)
+    pub(crate) data_requests: Arc<std::sync::Mutex<DataRequests>>,
+    // `agent.state_file` for external watchdogs (see daemon/state_file.rs)
+    pub(crate) state_file: Arc<StateFile>,
+    // characterizeFans run in progress and the last report (see characterization.rs)
+    pub(crate) characterization: Arc<std::sync::Mutex<CharacterizationStatus>>,
 }
 
 impl WebSocketClient {
     pub fn new(config: AgentConfig, hardware_monitor: Arc<dyn HardwareMonitor>) -> Self {
+        let sensor_stats = SensorStats::new(config.hardware.sensor_stats_window_hours, chrono::Utc::now().timestamp_millis());
         Self {
-            config: Arc::new(RwLock::new(config)),
+            config: Arc::new(ConfigHandle::beside_executable(config)),
             hardware_monitor,
             running: Arc::new(RwLock::new(false)),
             failsafe_active: Arc::new(RwLock::new(false)),
             last_reported_error: Arc::new(tokio::sync::Mutex::new(None)),
+            pending_emergency: Arc::new(tokio::sync::Mutex::new(None)),
+            failsafe_controller: Arc::new(tokio::sync::Mutex::new(FailsafeController::new())),
+            crit_alarm_tripped: Arc::default(),
+            local_curves: Arc::new(tokio::sync::Mutex::new(CurveEngine::default())),
+            emergency_actions: Arc::new(tokio::sync::Mutex::new(EmergencyActionState::beside_executable())),
+            link_status: Arc::new(tokio::sync::Mutex::new(LinkStatus::default())),
+            command_cache: Arc::new(tokio::sync::Mutex::new(CommandCache::default())),
+            negotiated: Arc::new(RwLock::new(Negotiated::default())),
+            registered_hardware: Arc::new(RwLock::new(RegisteredHardware::default())),
+            unknown_message_types: Arc::new(tokio::sync::Mutex::new(std::collections::HashSet::new())),
+            hardware_snapshot: Arc::new(tokio::sync::Mutex::new(SnapshotTracker::beside_executable())),
+            sensor_stats: Arc::new(tokio::sync::Mutex::new(sensor_stats)),
+            events: Arc::new(tokio::sync::Mutex::new(EventLog::default())),
+            fan_control_conflicts: Arc::new(RwLock::new(Vec::new())),
+            environment: Arc::new(std::sync::Mutex::new(RunEnvironment::default())),
+            send_errors: Arc::new(LogThrottle::new()),
+            resume: Arc::new(std::sync::Mutex::new(ResumeDetector::new())),
+            reconnect_now: Arc::new(std::sync::atomic::AtomicBool::new(false)),
+            data_message_bytes: Arc::new(std::sync::atomic::AtomicUsize::new(0)),
+            started: std::time::Instant::now(),
+            last_cycle_ms: Arc::default(),
+            clock_skew: Arc::new(std::sync::Mutex::new(ClockSkew::default())),
+            link_quality: Arc::new(std::sync::Mutex::new(LinkQuality::default())),
+            frame_interval: Arc::new(std::sync::Mutex::new(IntervalController::default())),
+            last_disconnect: Arc::new(std::sync::Mutex::new(None)),
+            notifications: Arc::new(std::sync::Mutex::new(NotificationState::default())),
+            safe_mode: Arc::new(RwLock::new(None)),
+            data_requests: Arc::new(std::sync::Mutex::new(DataRequests::default())),
+            state_file: Arc::new(StateFile::default()),
+            characterization: Arc::new(std::sync::Mutex::new(CharacterizationStatus::default())),
+        }
+    }
+
+    /// True if the machine was suspended since the last check. The hardware state is
+    /// refreshed and the resume recorded; the caller drops the connection, which
+    /// didn't survive the suspend, and reconnects without waiting.
+    async fn check_resumed(&self) -> bool {
+        let Some(slept) = self.resume.lock().unwrap().check() else {
+            return false;
+        };
+        info!("Resumed from suspend ({}s asleep); refreshing hardware and reconnecting", slept.as_secs());
+        self.hardware_monitor.resumed().await;
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        self.link_status.lock().await.resumed(now_ms, slept);
+        self.record_event(Severity::Info, "resumed", format!("Resumed from suspend after {}s", slept.as_secs()),
+                          serde_json::json!({ "suspended_secs": slept.as_secs() })).await;
+        self.reconnect_now.store(true, std::sync::atomic::Ordering::Relaxed);
+        true
+    }
+
+    /// Update the agent state and rewrite `agent.state_file`, if set.
+    pub(crate) fn write_state(&self, change: impl FnOnce(&mut AgentState)) {
+        let path = self.config.load().agent.state_file.clone();
+        self.state_file.update(path.as_deref().map(std::path::Path::new), change);
+    }
+
+    /// Monotonic milliseconds since the agent started, unaffected by clock changes.
+    pub(crate) fn uptime_ms(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// A monitoring cycle (data frame, failsafe check or broadcast) ran.
+    pub(crate) fn cycle_done(&self) {
+        self.last_cycle_ms.store(self.uptime_ms(), std::sync::atomic::Ordering::Relaxed);
+    }
+
+    /// Keep-alive for `WatchdogSec=`, sent every `interval` while monitoring cycles
+    /// keep running, whether the backend is reachable or not. A stuck agent stops
+    /// cycling and gets restarted; one riding out an outage in failsafe does not.
+    async fn run_watchdog(&self, interval: Duration) {
+        while *self.running.read().await {
+            let since_cycle = self.uptime_ms().saturating_sub(self.last_cycle_ms.load(std::sync::atomic::Ordering::Relaxed));
+            if since_cycle < 2 * interval.as_millis() as u64 {
+                notify::watchdog();
+            }
+            time::sleep(interval).await;
+        }
+    }
+
+    /// Add an entry to the event log (see event_log.rs).
+    pub(crate) async fn record_event(
+        &self,
+        severity: Severity,
+        kind: &'static str,
+        message: impl Into<String>,
+        details: serde_json::Value,
+    ) {
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        let message = message.into();
+        self.notify(severity, kind, &message, &details);
+        self.events.lock().await.record(now_ms, severity, kind, message, details);
+    }
+
+    /// Fan writes are off: conflicting fan-control software was found at startup and
+    /// `hardware.monitor_only_on_conflict` is set, or the agent is in safe mode.
+    /// Emergencies still go to 100%.
+    pub(crate) async fn fan_writes_blocked(&self) -> bool {
+        (self.config.load().hardware.monitor_only_on_conflict && !self.fan_control_conflicts.read().await.is_empty())
+            || self.safe_mode.read().await.is_some()
+    }
+
+    /// Why fan commands are refused outright: `enable_fan_control` is off, or the
+    /// agent runs monitoring-only in a container without hwmon. None if they run.
+    pub(crate) fn fan_control_disabled(&self) -> Option<&'static str> {
+        if !self.config.load().hardware.enable_fan_control {
+            Some(FAN_CONTROL_DISABLED)
+        } else if self.environment.lock().unwrap().monitoring_only() {
+            Some(MONITORING_ONLY_CONTAINER)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the agent drives the fans on its own: failsafe speed, emergencies and
+    /// local curves. Not with `enable_fan_control` off, except under udp-broadcast,
+    /// where this is the only fan control there is, and never monitoring-only in a
+    /// container.
+    pub(crate) fn local_fan_control(&self) -> bool {
+        let config = self.config.load();
+        (config.hardware.enable_fan_control || config.backend.transport == Transport::UdpBroadcast)
+            && !self.environment.lock().unwrap().monitoring_only()
+    }
+
+    /// Run in safe mode after a crash loop: fans back to automatic control, and no fan
+    /// writes until `clearSafeMode`.
+    pub async fn enter_safe_mode(&self, mode: SafeMode) {
+        error!("🛟 SAFE MODE: {} starts without a clean shutdown within {} min - fans handed back to automatic control, \
+                fan control disabled until clearSafeMode (or --clear-safe-mode and a restart)",
+               mode.starts, mode.window_minutes);
+        self.record_event(Severity::Critical, "safe_mode_entered", format!("Safe mode after {} unclean starts", mode.starts),
+                          serde_json::to_value(&mode).unwrap_or_default()).await;
+        *self.safe_mode.write().await = Some(mode);
+        self.release_control().await;
+    }
+
+    /// `clearSafeMode`: resume fan control. False if the agent wasn't in safe mode.
+    pub(crate) async fn clear_safe_mode(&self) -> bool {
+        safe_mode::update(|history| history.clear_safe_mode());
+        if self.safe_mode.write().await.take().is_none() {
+            return false;
+        }
+        info!("Safe mode cleared - fan control resumes");
+        self.record_event(Severity::Info, "safe_mode_cleared", "Safe mode cleared, fan control resumes", serde_json::json!({})).await;
+        true
+    }
+
+    /// Hand every fan back to automatic control (safe mode, `releaseControl`).
+    /// Returns the fans that couldn't be, with why.
+    pub(crate) async fn release_control(&self) -> Vec<(String, String)> {
+        let failed = self.hardware_monitor.release_control().await;
+        for (fan_id, reason) in &failed {
+            warn!("Could not hand fan {} back to automatic control: {}", fan_id, reason);
+        }
+        failed
+    }
+
+    /// Look for other fan-control software once at startup and warn loudly: two
+    /// programs writing the same PWM channels make the fans oscillate.
+    /// Log once if the agent runs in a container, and go monitoring-only when it has
+    /// no hwmon there.
+    async fn check_run_environment(&self) {
+        let environment = self.hardware_monitor.run_environment().await;
+        *self.environment.lock().unwrap() = environment;
+        if environment.monitoring_only() {
+            warn!("Running in a container without hwmon (/sys/class/hwmon missing or empty): monitoring only, \
+                   fans are never written. Pass the host's /sys/class/hwmon into the container for fan control");
+            self.record_event(Severity::Warning, "container_monitoring_only",
+                              "Container without hwmon: monitoring only", serde_json::json!({})).await;
+        } else if environment.container {
+            info!("Running in a container");
         }
     }
 
+    async fn check_fan_control_conflicts(&self) {
+        let conflicts = self.hardware_monitor.fan_control_conflicts().await;
+        if !conflicts.is_empty() {
+            let monitor_only = self.config.load().hardware.monitor_only_on_conflict;
+            for conflict in &conflicts {
+                warn!("⚠️  CONFLICTING FAN CONTROL: {}", conflict.message);
+            }
+            if monitor_only {
+                warn!("Fan control disabled for this run (hardware.monitor_only_on_conflict) - stop the other program and restart the agent");
+            } else {
+                warn!("Both programs will fight over these fans - stop the other one, or set hardware.monitor_only_on_conflict");
+            }
+            self.record_event(Severity::Warning, "fan_control_conflict",
+                              format!("Conflicting fan control software: {}", conflicts.iter().map(|c| c.program.as_str()).collect::<Vec<_>>().join(", ")),
+                              serde_json::json!({ "conflicts": conflicts, "monitor_only": monitor_only })).await;
+        }
+        *self.fan_control_conflicts.write().await = conflicts;
+    }
+
     /// Enter failsafe mode - set all fans to failsafe speed and enable local temp monitoring
     async fn enter_failsafe_mode(&self) -> Result<()> {
         let mut failsafe = self.failsafe_active.write().await;
@@ -50,20 +337,31 @@ impl WebSocketClient {
         }
         *failsafe = true;
         drop(failsafe);
+        self.failsafe_controller.lock().await.reset();
+        self.local_curves.lock().await.reset();
+        self.link_status.lock().await.outage_started(chrono::Utc::now().timestamp_millis());
+        self.stop_characterization(StopRequest::ConnectionLost);
 
         // Read configurable failsafe speed
-        let config = self.config.read().await;
+        let config = self.config.load();
         let failsafe_speed = config.hardware.failsafe_speed;
         drop(config);
 
         warn!("ENTERING FAILSAFE MODE - Backend disconnected");
         warn!("Setting all fans to {}% (failsafe speed)", failsafe_speed);
+        self.record_event(Severity::Warning, "failsafe_entered", "Backend disconnected, entered failsafe mode",
+                          serde_json::json!({ "failsafe_speed": failsafe_speed })).await;
 
         // Set all fans to failsafe speed
         if let Err(e) = self.set_all_fans_to_speed(failsafe_speed).await {
             error!("Failed to set failsafe fan speed: {}", e);
         }
 
+        // Holding failsafe is a working state: don't leave `systemctl start` blocked
+        // (and eventually timed out) just because the backend is down at boot.
+        notify::ready();
+        notify::status(&format!("Failsafe mode ({}%) - backend unreachable", failsafe_speed));
+
         Ok(())
     }
 
@@ -75,8 +373,14 @@ impl WebSocketClient {
         }
         *failsafe = false;
         drop(failsafe);
+        self.failsafe_controller.lock().await.reset();
+        // The failsafe owned the fans meanwhile: a crit alarm still asserted escalates again
+        self.crit_alarm_tripped.store(false, std::sync::atomic::Ordering::Relaxed);
+        self.check_failsafe_duration(false).await;
         info!("✅ EXITING FAILSAFE MODE - Backend connection restored");
         info!("Backend will resume fan control");
+        self.record_event(Severity::Info, "failsafe_exited", "Backend connection restored, left failsafe mode",
+                          serde_json::json!({})).await;
 
         // Hand any GPU fan back to the driver's auto curve so an unassigned GPU isn't left
         // pinned (e.g. at 100% after a failsafe emergency). The backend re-commands it
@@ -89,10 +393,24 @@ impl WebSocketClient {
     }
 
     /// Set all fans to a specific speed percentage
-    async fn set_all_fans_to_speed(&self, speed: u8) -> Result<()> {
+    pub(crate) async fn set_all_fans_to_speed(&self, speed: u8) -> Result<()> {
+        if self.safe_mode.read().await.is_some() {
+            debug!("Not setting fans to {}%: safe mode, handing them back to automatic control", speed);
+            self.release_control().await;
+            return Ok(());
+        }
+        if self.fan_writes_blocked().await {
+            debug!("Not setting fans to {}%: monitor-only (conflicting fan control software)", speed);
+            return Ok(());
+        }
+        if !self.local_fan_control() {
+            debug!("Not setting fans to {}%: fan control is disabled", speed);
+            return Ok(());
+        }
         let fans = self.hardware_monitor.discover_fans().await?;
         let mut success_count = 0;
         let mut fail_count = 0;
+        let mut absent_count = 0;
 
         for fan in fans.iter() {
             // Hybrid failsafe: GPU / driver-auto-capable fans are handed back to their own
@@ -111,91 +429,324 @@ impl WebSocketClient {
                 ),
             }
 
-            match self.hardware_monitor.set_fan_speed(&fan.id, speed).await {
+            // Monitoring-only fan (no PWM write access); nothing to command
+            if !fan.has_pwm_control {
+                continue;
+            }
+            // Empty header (never reported RPM while driven); a write would count as a success
+            if fan.is_absent() {
+                debug!("Not setting fan {}: no fan on the header", fan.id);
+                absent_count += 1;
+                continue;
+            }
+
+            match self.hardware_monitor.set_fan_speed(&fan.id, speed, &FanSpeedOrigin::Failsafe).await {
                 Ok(_) => {
                     debug!("Set fan {} to {}%", fan.id, speed);
                     success_count += 1;
                 }
                 Err(e) => {
                     error!("Failed to set fan {} to {}%: {}", fan.id, speed, e);
+                    self.record_event(Severity::Warning, "fan_write_failed", format!("Failed to set fan {}", fan.id),
+                                      serde_json::json!({ "fan_id": fan.id, "speed": speed, "error": e.to_string() })).await;
                     fail_count += 1;
                 }
             }
         }
 
-        info!("Fan speed set to {}%: {} succeeded, {} failed", speed, success_count, fail_count);
+        info!("Fan speed set to {}%: {} succeeded, {} failed, {} absent skipped", speed, success_count, fail_count, absent_count);
         Ok(())
     }
 
-    /// Check emergency temperature while in failsafe mode
-    /// If any sensor >= emergency_temp, set all fans to 100%. Excludes any
-    /// sensor IDs the user has hidden (pushed by the backend, persisted in
-    /// config) so hide selection is honored even when the backend is gone.
-    async fn check_emergency_temp(&self) -> Result<()> {
-        let (emergency_temp, excluded) = {
-            let config = self.config.read().await;
-            (config.hardware.emergency_temp, config.hardware.excluded_sensors.clone())
+    /// First CPU/motherboard sensor with a kernel crit alarm asserted, skipping
+    /// user-hidden sensors. Drives `escalate_on_crit_alarm`.
+    pub(crate) fn find_crit_alarm<'a>(sensors: &'a [Sensor], excluded: &[String]) -> Option<&'a Sensor> {
+        sensors.iter().find(|s| {
+            (&*s.sensor_type == "cpu" || &*s.sensor_type == "motherboard")
+                && s.has_crit_alarm()
+                && !excluded.iter().any(|id| *id == *s.id)
+        })
+    }
+
+    /// Escalate a crit alarm found in a data cycle (`escalate_on_crit_alarm`): all fans
+    /// to 100% when it asserts, then nothing more until it clears, as the failsafe
+    /// does for thresholds. Returns the alarmed sensor.
+    pub(crate) async fn check_crit_alarm<'a>(&self, sensors: &'a [Sensor]) -> Option<&'a Sensor> {
+        use std::sync::atomic::Ordering;
+
+        let hardware = &self.config.load().hardware;
+        let crit_alarm = if hardware.escalate_on_crit_alarm {
+            Self::find_crit_alarm(sensors, &hardware.excluded_sensors)
+        } else {
+            None
         };
+        let was_tripped = self.crit_alarm_tripped.swap(crit_alarm.is_some(), Ordering::Relaxed);
+        match crit_alarm {
+            Some(sensor) if !was_tripped => {
+                self.record_event(Severity::Critical, "crit_alarm", format!("Crit alarm on {}", sensor.id),
+                                  serde_json::json!({ "sensor_id": sensor.id, "temperature": sensor.instant_temperature() })).await;
+                if self.local_fan_control() {
+                    warn!("🚨 CRIT ALARM: {} ({:.1}°C) - ALL FANS TO 100%", sensor.id, sensor.instant_temperature());
+                    if let Err(e) = self.hardware_monitor.max_cooling().await {
+                        error!("Emergency escalation failed: {}", e);
+                        // Tried again next cycle
+                        self.crit_alarm_tripped.store(false, Ordering::Relaxed);
+                    }
+                } else {
+                    warn!("🚨 CRIT ALARM: {} ({:.1}°C) - fans left alone, fan control is disabled", sensor.id, sensor.instant_temperature());
+                }
+            }
+            Some(sensor) => debug!("Crit alarm still asserted on {}", sensor.id),
+            None if was_tripped => info!("Crit alarm cleared"),
+            None => {}
+        }
+        crit_alarm
+    }
 
+    /// The sensor furthest past its own emergency threshold (`emergency_temp_by_type`,
+    /// else `emergency_temp`), with that threshold. Hidden sensors are skipped.
+    pub(crate) fn find_emergency<'a>(sensors: &'a [Sensor], hardware: &HardwareSettings) -> Option<(&'a Sensor, f64)> {
+        sensors.iter()
+            .filter(|s| !hardware.excluded_sensors.iter().any(|id| *id == *s.id))
+            .map(|s| (s, hardware.emergency_temp_for(&s.sensor_type)))
+            .filter(|(s, threshold)| s.instant_temperature() >= *threshold)
+            .max_by(|(a, ta), (b, tb)| {
+                (a.instant_temperature() - ta).partial_cmp(&(b.instant_temperature() - tb))
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    }
+
+    /// Every non-hidden sensor is below its emergency threshold minus `hysteresis_temp`,
+    /// the point where an offline emergency may start to cool down.
+    pub(crate) fn is_cooled(sensors: &[Sensor], hardware: &HardwareSettings) -> bool {
+        sensors.iter()
+            .filter(|s| !hardware.excluded_sensors.iter().any(|id| *id == *s.id))
+            .all(|s| s.instant_temperature() < hardware.emergency_temp_for(&s.sensor_type) - hardware.hysteresis_temp)
+    }
+
+    /// Check emergency temperature while in failsafe mode
+    /// If any sensor reaches its type's emergency threshold, set all fans to 100%;
+    /// once every sensor has stayed below threshold − hysteresis for
+    /// `failsafe_release_checks` checks, return to `failsafe_speed`. Excludes any
+    /// sensor IDs the user has hidden (pushed by the backend, persisted in config)
+    /// so hide selection is honored even when the backend is gone.
+    async fn check_emergency_temp(&self) -> Result<()> {
         let sensors = self.hardware_monitor.discover_sensors().await?;
-        let excluded_set: std::collections::HashSet<&String> = excluded.iter().collect();
-        let considered: Vec<_> = sensors.iter()
-            .filter(|s| !excluded_set.contains(&s.id))
-            .collect();
+        self.check_failsafe_sensors(&sensors).await
+    }
+
+    /// `check_emergency_temp` on readings the caller already has.
+    pub(crate) async fn check_failsafe_sensors(&self, sensors: &[Sensor]) -> Result<()> {
+        let hardware = self.config.load().hardware.clone();
 
-        if considered.is_empty() {
+        if hardware.enable_sensor_monitoring {
+            self.sensor_stats.lock().await.record(sensors, chrono::Utc::now().timestamp_millis());
+        }
+        self.check_failsafe_duration(*self.failsafe_active.read().await).await;
+
+        let crit_alarm = if hardware.escalate_on_crit_alarm {
+            Self::find_crit_alarm(sensors, &hardware.excluded_sensors)
+        } else {
+            None
+        };
+
+        if crit_alarm.is_none() && sensors.iter().all(|s| hardware.excluded_sensors.iter().any(|id| *id == *s.id)) {
             warn!("All discovered sensors are excluded - failsafe cannot detect emergency. \
-                   Holding failsafe_speed without escalation.");
+                   Holding current fan speeds without escalation.");
             return Ok(());
         }
 
-        let max_temp = considered.iter()
-            .map(|s| s.temperature)
-            .max_by(|a, b| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal))
-            .unwrap_or(0.0);
+        let hottest = sensors.iter()
+            .filter(|s| !hardware.excluded_sensors.iter().any(|id| *id == *s.id))
+            .map(|s| s.instant_temperature())
+            .fold(f64::NEG_INFINITY, f64::max);
+        self.link_status.lock().await.record_temperature(hottest);
+
+        let emergency = Self::find_emergency(sensors, &hardware);
+        let tripped = crit_alarm.is_some() || emergency.is_some();
+        let cooled = crit_alarm.is_none() && Self::is_cooled(sensors, &hardware);
+        let (action, state) = {
+            let mut controller = self.failsafe_controller.lock().await;
+            let action = controller.observe(tripped, cooled, hardware.failsafe_release_checks);
+            (action, controller.state())
+        };
 
-        if max_temp >= emergency_temp {
-            warn!("🚨 FAILSAFE EMERGENCY: {:.1}°C >= {:.1}°C threshold - ALL FANS TO 100%",
-                  max_temp, emergency_temp);
-            self.hardware_monitor.emergency_stop().await?;
+        match action {
+            FailsafeAction::Escalate => {
+                if let Some(sensor) = crit_alarm {
+                    warn!("🚨 FAILSAFE EMERGENCY: crit alarm on {} ({:.1}°C) - ALL FANS TO 100%",
+                          sensor.id, sensor.instant_temperature());
+                    self.record_event(Severity::Critical, "emergency", format!("Failsafe emergency: crit alarm on {}", sensor.id),
+                                      serde_json::json!({ "sensor_id": sensor.id, "temperature": sensor.instant_temperature(),
+                                                          "reason": "crit_alarm" })).await;
+                    self.record_emergency(sensor, None).await;
+                } else if let Some((sensor, threshold)) = emergency {
+                    warn!("🚨 FAILSAFE EMERGENCY: {} ({}) {:.1}°C >= {:.1}°C threshold - ALL FANS TO 100%",
+                          sensor.id, sensor.sensor_type, sensor.instant_temperature(), threshold);
+                    self.record_event(Severity::Critical, "emergency", format!("Failsafe emergency: {} over threshold", sensor.id),
+                                      serde_json::json!({ "sensor_id": sensor.id, "temperature": sensor.instant_temperature(),
+                                                          "threshold": threshold, "reason": "threshold" })).await;
+                    self.record_emergency(sensor, Some(threshold)).await;
+                }
+                self.link_status.lock().await.emergency_triggered(chrono::Utc::now().timestamp_millis(), hottest);
+                if self.local_fan_control() {
+                    self.hardware_monitor.max_cooling().await?;
+                } else {
+                    warn!("Fan control is disabled: fans left alone during the emergency");
+                }
+            }
+            FailsafeAction::Release => {
+                info!("Failsafe emergency cleared: all sensors {:.1}°C below their thresholds for {} checks - \
+                       returning fans to {}% failsafe speed",
+                      hardware.hysteresis_temp, hardware.failsafe_release_checks.max(1), hardware.failsafe_speed);
+                self.record_event(Severity::Info, "emergency_cleared", "Failsafe emergency cleared",
+                                  serde_json::json!({ "failsafe_speed": hardware.failsafe_speed })).await;
+                self.set_all_fans_to_speed(hardware.failsafe_speed).await?;
+                self.local_curves.lock().await.reset();
+            }
+            FailsafeAction::None => debug!("Failsafe check: {:?}", state),
         }
 
+        // Between emergencies, fans with a local curve follow it
+        if state == FailsafeState::Normal && !hardware.local_curves.is_empty() {
+            self.apply_local_curves(sensors, &hardware).await;
+        }
+
+        // The failsafe holds every fan at 100% from escalation until release
+        let trigger = crit_alarm.or(emergency.map(|(sensor, _)| sensor));
+        self.check_emergency_actions(trigger, state != FailsafeState::Normal).await;
+
         Ok(())
     }
 
+    /// Remember the first emergency of this outage for the backend.
+    async fn record_emergency(&self, sensor: &Sensor, threshold: Option<f64>) {
+        let mut pending = self.pending_emergency.lock().await;
+        if pending.is_none() {
+            *pending = Some(EmergencyTrip {
+                sensor_id: sensor.id.to_string(),
+                sensor_type: sensor.sensor_type.to_string(),
+                temperature: sensor.instant_temperature(),
+                threshold,
+                timestamp: chrono::Utc::now().timestamp_millis(),
+            });
+        }
+    }
+
     /// Run failsafe checks during disconnected period
     async fn run_failsafe_check(&self) {
-        if *self.failsafe_active.read().await {
+        let failsafe_active = *self.failsafe_active.read().await;
+        self.write_state(|state| state.failsafe_active = failsafe_active);
+        self.cycle_done();
+        if failsafe_active {
+            // The data cycle while disconnected
+            self.hardware_monitor.next_cycle();
             if let Err(e) = self.check_emergency_temp().await {
                 error!("Failed to check emergency temp in failsafe mode: {}", e);
             }
         }
     }
 
+    /// Finish a startup discovery served from the discovery cache, then compare the
+    /// hardware with the previous run's snapshot.
+    async fn compare_startup_hardware(&self, mut snapshot: tokio::sync::OwnedMutexGuard<SnapshotTracker>) {
+        if let Err(e) = self.hardware_monitor.reconcile_discovery().await {
+            warn!("Background hardware discovery failed: {:#}", e);
+        }
+        let (sensors, fans) = match (self.hardware_monitor.discover_sensors().await, self.hardware_monitor.discover_fans().await) {
+            (Ok(sensors), Ok(fans)) => (sensors, fans),
+            (Err(e), _) | (_, Err(e)) => {
+                warn!("Startup hardware discovery failed: {}", e);
+                return;
+            }
+        };
+        let changes = snapshot.observe(&sensors, &fans).cloned();
+        drop(snapshot);
+        if let Some(changes) = changes.filter(|c| !c.is_empty()) {
+            let lines = changes.describe();
+            self.record_event(Severity::Warning, "hardware_changed",
+                              format!("Hardware changed since last run ({} change(s))", lines.len()),
+                              serde_json::json!({ "changes": lines })).await;
+        }
+    }
+
     pub async fn run(&self) -> Result<()> {
         *self.running.write().await = true;
         let mut retry_count = 0;
 
+        if let Some(interval) = notify::watchdog_interval() {
+            let client = self.clone_for_update();
+            tokio::spawn(async move { client.run_watchdog(interval).await });
+        }
+
+        // Compare with the previous run's hardware now, not only once a backend answers.
+        // Readings may come from the saved discovery cache meanwhile; the comparison
+        // waits for the full discovery, and holds the snapshot so registration does too.
+        match (self.hardware_monitor.discover_sensors().await, self.hardware_monitor.discover_fans().await) {
+            (Ok(_), Ok(_)) => {
+                let snapshot = Arc::clone(&self.hardware_snapshot).lock_owned().await;
+                let client = self.clone_for_update();
+                tokio::spawn(async move { client.compare_startup_hardware(snapshot).await });
+            }
+            (Err(e), _) | (_, Err(e)) => warn!("Startup hardware discovery failed: {}", e),
+        }
+        self.check_run_environment().await;
+        self.check_fan_control_conflicts().await;
+
+        if self.config.load().backend.transport == Transport::UdpBroadcast {
+            return self.run_udp_broadcast().await;
+        }
+
         loop {
             if !*self.running.read().await {
                 break;
             }
 
-            match self.connect_and_communicate().await {
-                Ok(_) => {
-                    info!("WebSocket connection closed normally");
+            let cause = self.connect_and_communicate().await;
+            let report = match cause.connected_secs {
+                Some(secs) => {
                     retry_count = 0; // Reset on successful connection
+                    if cause.is_clean() {
+                        info!("WebSocket connection closed: {}", cause.error);
+                    } else {
+                        warn!("WebSocket connection lost after {:.0}s: {}", secs, cause.error);
+                    }
+                    !cause.is_clean()
+                }
+                // A connect attempt that hung across a suspend
+                None if self.check_resumed().await => {
+                    debug!("Connection attempt failed across suspend: {}", cause.error);
+                    false
                 }
-                Err(e) => error!("WebSocket error: {}", e),
+                None => {
+                    error!("WebSocket error: {}", cause.error);
+                    true
+                }
+            };
+            if report {
+                self.record_event(Severity::Warning, "connection_error", "Backend connection failed",
+                                  serde_json::json!({ "error": cause.error, "category": cause.category })).await;
             }
+            self.write_state(|state| {
+                state.connected = false;
+                if report {
+                    state.last_error = Some(cause.error.clone());
+                }
+            });
+            *self.last_disconnect.lock().unwrap() = Some(cause);
 
             // Connection lost or failed - enter failsafe mode
             if let Err(e) = self.enter_failsafe_mode().await {
                 error!("Failed to enter failsafe mode: {}", e);
             }
 
+            if self.reconnect_now.swap(false, std::sync::atomic::Ordering::Relaxed) {
+                retry_count = 0;
+                continue;
+            }
+
             if *self.running.read().await {
-                let config = self.config.read().await;
+                let config = self.config.load();
                 // Hardware-safe exponential backoff: max 15s to prevent thermal issues
                 let base_interval = config.backend.reconnect_interval;
                 let wait_time = match retry_count {
@@ -221,6 +772,10 @@ impl WebSocketClient {
                         break;
                     }
 
+                    if self.check_resumed().await {
+                        break;
+                    }
+
                     // Run failsafe check (monitors emergency_temp)
                     self.run_failsafe_check().await;
 
@@ -237,29 +792,56 @@ impl WebSocketClient {
         Ok(())
     }
 
-    async fn connect_and_communicate(&self) -> Result<()> {
+    /// One backend connection, from connecting until it ends; returns why it ended.
+    async fn connect_and_communicate(&self) -> DisconnectCause {
+        let ws_stream = match self.connect().await {
+            Ok(ws_stream) => ws_stream,
+            Err(e) => return DisconnectCause::failed(&e, None),
+        };
+        let connected = std::time::Instant::now();
+        match self.communicate(ws_stream).await {
+            Ok((category, error)) => DisconnectCause::new(category, error, Some(connected.elapsed())),
+            Err(e) => DisconnectCause::failed(&e, Some(connected.elapsed())),
+        }
+    }
+
+    async fn connect(&self) -> Result<WsStream> {
         use tracing::trace;
 
-        trace!("Acquiring config lock for connection");
-        let config = self.config.read().await;
-        info!("Connecting to WebSocket: {}", config.backend.server_url);
+        trace!("Taking config snapshot for connection");
+        let config = self.config.load();
+        info!("Connecting to WebSocket: {}", redact::url(&config.backend.server_url));
         trace!("Connection timeout: {}s", config.backend.connection_timeout);
 
         // Apply connection timeout to prevent hanging connections
         let timeout_duration = Duration::from_secs_f64(config.backend.connection_timeout);
-        let connect_future = connect_async(&config.backend.server_url);
+        let route = Route::from(&config.backend);
+        let connect_future = transport::connect(&config.backend.server_url, &route);
 
         let (ws_stream, _) = tokio::time::timeout(timeout_duration, connect_future)
             .await
             .context("Connection timeout")??;
-        drop(config); // Release read lock
+        Ok(ws_stream)
+    }
+
+    /// Register and exchange messages until the connection ends; returns how it ended.
+    async fn communicate(&self, ws_stream: WsStream) -> Result<(DisconnectCategory, String)> {
         info!("✅ WebSocket connected");
+        self.link_status.lock().await.connected();
+        self.link_quality.lock().unwrap().connected();
+        self.record_event(Severity::Info, "connected", "Connected to backend", serde_json::json!({})).await;
 
         // Exit failsafe mode - backend connection restored
         self.exit_failsafe_mode().await;
 
-        // Invalidate hardware cache on connection/reconnection to ensure fresh discovery
-        self.hardware_monitor.invalidate_cache().await;
+        // Rediscover hardware on connection/reconnection, reading from the cache until done
+        self.hardware_monitor.refresh_cache().await;
+        let monitor = Arc::clone(&self.hardware_monitor);
+        tokio::spawn(async move {
+            if let Err(e) = monitor.reconcile_discovery().await {
+                warn!("Background hardware discovery failed: {:#}", e);
+            }
+        });
 
         // Reset error dedup so this connection reports errors fresh to the new backend session
         *self.last_reported_error.lock().await = None;
@@ -267,18 +849,17 @@ impl WebSocketClient {
         let (write, read) = ws_stream.split();
         let write = Arc::new(tokio::sync::Mutex::new(write));
 
-        // Send registration
+        // Send registration, then any emergency that tripped while we were away
         {
             let mut w = write.lock().await;
-            self.send_registration(&mut *w).await?;
+            self.send_registration(&mut w).await?;
+            self.send_pending_emergency(&mut w).await?;
         }
 
         // Start data sender task
-        let config = Arc::clone(&self.config);
-        let hardware_monitor = Arc::clone(&self.hardware_monitor);
-        let running = Arc::clone(&self.running);
+        let client = self.clone_for_update();
         let write_clone = Arc::clone(&write);
-        let last_reported_error = Arc::clone(&self.last_reported_error);
+        let mut data_requests = self.data_requests.lock().unwrap().connect();
 
         // Max consecutive send_data failures before closing the write half to
         // trigger the outer reconnect loop. At 3s update_interval this is ~30s
@@ -289,16 +870,16 @@ impl WebSocketClient {
         let data_sender = tokio::spawn(async move {
             let mut heartbeat_counter = 0;
             let mut consecutive_failures: u32 = 0;
-            while *running.read().await {
+            while *client.running.read().await {
                 let mut w = write_clone.lock().await;
-                match Self::send_data(&mut *w, &config, &hardware_monitor, &last_reported_error).await {
+                client.hardware_monitor.next_cycle();
+                let sent = client.send_data(&mut w).await;
+                client.cycle_done();
+                match sent {
                     Ok(_) => {
-                        if consecutive_failures > 0 {
-                            info!(
-                                "Data send recovered after {} failed attempt(s)",
-                                consecutive_failures
-                            );
-                            consecutive_failures = 0;
+                        consecutive_failures = 0;
+                        if let Some(line) = client.send_errors.recovered("data_send") {
+                            info!("Data send {}", line);
                         }
 
                         // Heartbeat logging: only in DEBUG mode, every 20 cycles (60s at 3s intervals)
@@ -309,9 +890,13 @@ impl WebSocketClient {
                     }
                     Err(e) => {
                         consecutive_failures += 1;
-                        // Dampen log spam: first failure, then every 5th attempt
-                        if consecutive_failures == 1 || consecutive_failures % 5 == 0 {
-                            error!(
+                        let message = format!("Failed to send data: {}", e);
+                        client.write_state(|state| state.last_error = Some(message.clone()));
+                        // Logged in full once, then summarized while the same error repeats
+                        if let Some(line) = client.send_errors.failed("data_send", message) {
+                            error!("{}", line);
+                        } else {
+                            debug!(
                                 "Failed to send data (attempt {}/{}): {}",
                                 consecutive_failures, MAX_CONSECUTIVE_SEND_FAILURES, e
                             );
@@ -326,118 +911,157 @@ impl WebSocketClient {
                             // itself fails, break anyway - the 30s connection
                             // health timeout is a backstop.
                             let _ = w.close().await;
-                            break;
+                            return Some(format!("Data send failed {} consecutive times: {}", consecutive_failures, e));
                         }
                     }
                 }
                 drop(w);
 
-                let interval = config.read().await.agent.update_interval;
-                time::sleep(Duration::from_secs_f64(interval)).await;
+                // Frames for requestData in between don't move the next cycle
+                let next_cycle = time::Instant::now() + client.next_frame_interval();
+                loop {
+                    tokio::select! {
+                        _ = time::sleep_until(next_cycle) => break,
+                        Some(request) = data_requests.recv() => {
+                            let mut w = write_clone.lock().await;
+                            client.send_requested_data(&mut w, request).await;
+                        }
+                    }
+                }
             }
+            None
         });
 
         // Connection health tracking: detect stale connections
         // This prevents "half-open" TCP connections where we can send but not receive
         let mut last_message_received = std::time::Instant::now();
-        const CONNECTION_HEALTH_TIMEOUT_SECS: u64 = 30; // If no message for 30s, reconnect
 
         // Handle incoming messages with timeout to allow checking shutdown signal
         let mut read = read;
-        loop {
+        let mut ended = loop {
             // Check if we should shut down
             if !*self.running.read().await {
                 info!("Shutdown requested, closing WebSocket");
-                break;
+                break (DisconnectCategory::Shutdown, "Agent shutting down".to_string());
+            }
+
+            // After a suspend the connection is dead; don't wait out the health timeout
+            if self.check_resumed().await {
+                break (DisconnectCategory::Suspended, "System resumed from suspend".to_string());
             }
 
             // Check connection health: if no message received for too long, assume connection is dead
-            let elapsed_since_last_message = last_message_received.elapsed();
-            if elapsed_since_last_message.as_secs() > CONNECTION_HEALTH_TIMEOUT_SECS {
-                warn!(
-                    "Connection health check failed: no message received for {}s, reconnecting",
-                    elapsed_since_last_message.as_secs()
-                );
-                break; // Trigger reconnection
+            if let Some(ended) = disconnect::health_check(last_message_received.elapsed()) {
+                break ended; // Trigger reconnection
+            }
+
+            // Our own pings, for the round trip in systemHealth.connection
+            let ping = self.link_quality.lock().unwrap().ping_due(std::time::Instant::now());
+            if let Some(payload) = ping {
+                if let Err(e) = write.lock().await.send(Message::Ping(payload.into())).await {
+                    debug!("Failed to send WebSocket ping: {}", e);
+                }
             }
 
             // Read with timeout to periodically check shutdown flag and connection health
-            let timeout = time::timeout(Duration::from_secs(1), read.next()).await;
-
-            match timeout {
-                Ok(Some(msg)) => {
-                    match msg {
-                        Ok(Message::Text(text)) => {
-                            // Update last message time on successful receive
-                            last_message_received = std::time::Instant::now();
-                            let mut w = write.lock().await;
-                            if let Err(e) = self.handle_message(&text, &mut *w).await {
-                                error!("Failed to handle message: {}", e);
-                            }
-                        }
-                        Ok(Message::Ping(_)) | Ok(Message::Pong(_)) => {
-                            // Update last message time on ping/pong
-                            last_message_received = std::time::Instant::now();
-                            debug!("Received keepalive ping/pong");
-                        }
-                        Ok(Message::Close(_)) => {
-                            info!("Server closed connection");
-                            break;
-                        }
-                        Err(e) => {
-                            error!("WebSocket error: {}", e);
-                            break;
-                        }
-                        _ => {
-                            // Update last message time for any valid message
-                            last_message_received = std::time::Instant::now();
-                        }
+            let Ok(next) = time::timeout(Duration::from_secs(1), read.next()).await else {
+                // Timeout - loop back to check shutdown flag and connection health
+                continue;
+            };
+            if let Some(ended) = disconnect::read_ended(next.as_ref()) {
+                if let Some(Err(tungstenite::Error::Capacity(e))) = &next {
+                    // The rest of the message can't be skipped: close, then reconnect
+                    warn!("Backend sent a message over backend.max_incoming_message_bytes ({}); reconnecting", e);
+                    let close = CloseFrame { code: CloseCode::Size, reason: "message too big".into() };
+                    let _ = write.lock().await.send(Message::Close(Some(close))).await;
+                }
+                break ended;
+            }
+
+            match next {
+                Some(Ok(Message::Text(text))) => {
+                    // Update last message time on successful receive
+                    last_message_received = std::time::Instant::now();
+                    let mut w = write.lock().await;
+                    if let Err(e) = self.handle_message(&text, &mut w, last_message_received).await {
+                        error!("Failed to handle message: {}", e);
                     }
                 }
-                Ok(None) => {
-                    info!("WebSocket stream ended");
-                    break;
+                Some(Ok(Message::Ping(_))) => {
+                    // Update last message time on ping/pong
+                    last_message_received = std::time::Instant::now();
+                    debug!("Received keepalive ping");
                 }
-                Err(_) => {
-                    // Timeout - loop back to check shutdown flag and connection health
-                    continue;
+                Some(Ok(Message::Pong(payload))) => {
+                    last_message_received = std::time::Instant::now();
+                    self.link_quality.lock().unwrap().pong(&payload, last_message_received);
+                    debug!("Received keepalive pong");
+                }
+                _ => {
+                    // Update last message time for any valid message
+                    last_message_received = std::time::Instant::now();
                 }
             }
-        }
+        };
 
+        // A data sender that gave up closed the connection, whatever the read loop saw
         data_sender.abort();
         match data_sender.await {
-            Ok(_) => debug!("Data sender task completed"),
+            Ok(Some(error)) => ended = (DisconnectCategory::SendError, error),
+            Ok(None) => debug!("Data sender task completed"),
             Err(e) if e.is_cancelled() => debug!("Data sender task cancelled"),
             Err(e) => error!("Data sender task error: {}", e),
         }
-        Ok(())
+        Ok(ended)
     }
 
-    async fn handle_message(&self, text: &str, write: &mut WsSink) -> Result<()> {
+    /// `received`: when the message arrived, for the latencies in systemHealth.connection.
+    async fn handle_message(&self, text: &str, write: &mut WsSink, received: std::time::Instant) -> Result<()> {
         use tracing::trace;
 
         trace!("Received message: {} bytes", text.len());
-        let message: serde_json::Value = serde_json::from_str(text)?;
+        let message = match incoming::parse(text) {
+            Ok(message) => message,
+            Err(rejected) => {
+                // Logged once, then summarized while the backend keeps sending them
+                if let Some(line) = self.send_errors.failed("incoming_message", format!("Ignoring message from backend: {}", rejected)) {
+                    warn!("{}", line);
+                }
+                return Ok(());
+            }
+        };
         trace!("Parsed message type: {:?}", message.get("type"));
 
         if let Some(msg_type) = message.get("type").and_then(|v| v.as_str()) {
             match msg_type {
                 "command" => {
                     if let Some(data) = message.get("data") {
-                        self.handle_command(data, write).await?;
+                        self.handle_command(data, write, received).await?;
                     }
                 }
                 "ping" => {
-                    // Respond to ping
-                    let pong = serde_json::json!({
-                        "type": "pong",
-                        "timestamp": chrono::Utc::now().timestamp_millis()
-                    });
+                    // Echo the ping's timestamp with ours, for the backend's clock-offset estimate
+                    let pong = time_sync::pong(&message, chrono::Utc::now().timestamp_millis(), self.uptime_ms());
                     write.send(Message::text(pong.to_string())).await?;
+                    self.link_quality.lock().unwrap().record(Measure::PongDelay, received.elapsed());
+                }
+                "pongAck" => {
+                    if let Some(offset) = time_sync::acked_offset(&message) {
+                        match self.clock_skew.lock().unwrap().observe(offset) {
+                            Some(true) => warn!(
+                                "System clock is {:.1}s {} the backend's; telemetry timestamps will be off (check NTP / the RTC)",
+                                offset.unsigned_abs() as f64 / 1000.0,
+                                if offset > 0 { "behind" } else { "ahead of" }
+                            ),
+                            Some(false) => info!("System clock back in sync with the backend (offset {}ms)", offset),
+                            None => debug!("Clock offset reported by backend: {}ms", offset),
+                        }
+                    }
                 }
                 "registered" => {
                     info!("Agent successfully registered with backend");
+                    notify::ready();
+                    notify::status("Connected to backend");
 
                     // Enrollment exchange: persist the Hub-minted auth token
                     // (delivered in the registered response) and drop the
@@ -475,56 +1099,37 @@ impl WebSocketClient {
                         }
                     }
 
-                    // Apply configuration from registration response
-                    if let Some(config) = message.get("configuration") {
-                        info!("Applying configuration from server");
+                    // Feature negotiation: keep only what this backend understands
+                    let data = message.get("data").unwrap_or(&serde_json::Value::Null);
+                    self.negotiated.write().await.apply(data);
 
-                        // Apply update_interval
-                        if let Some(interval) = config.get("update_interval").and_then(|v| v.as_f64()) {
-                            if let Err(e) = self.set_update_interval(interval).await {
-                                error!("Failed to apply update_interval: {}", e);
-                            } else {
-                                info!("Applied update_interval: {}s", interval);
-                            }
-                        }
-
-                        // Apply fan_step_percent
-                        if let Some(step) = config.get("fan_step_percent").and_then(|v| v.as_f64()) {
-                            if let Err(e) = self.set_fan_step(step.round() as u8).await {
-                                error!("Failed to apply fan_step_percent: {}", e);
-                            } else {
-                                info!("Applied fan_step_percent: {}%", step);
-                            }
-                        }
-
-                        // Apply hysteresis_temp
-                        if let Some(hysteresis) = config.get("hysteresis_temp").and_then(|v| v.as_f64()) {
-                            if let Err(e) = self.set_hysteresis(hysteresis).await {
-                                error!("Failed to apply hysteresis_temp: {}", e);
-                            } else {
-                                info!("Applied hysteresis_temp: {}°C", hysteresis);
-                            }
-                        }
-
-                        // Apply emergency_temp
-                        if let Some(temp) = config.get("emergency_temp").and_then(|v| v.as_f64()) {
-                            if let Err(e) = self.set_emergency_temp(temp).await {
-                                error!("Failed to apply emergency_temp: {}", e);
-                            } else {
-                                info!("Applied emergency_temp: {}°C", temp);
-                            }
-                        }
-
-                        // Apply log_level
-                        if let Some(level) = config.get("log_level").and_then(|v| v.as_str()) {
-                            if let Err(e) = self.set_log_level(level).await {
-                                error!("Failed to apply log_level: {}", e);
-                            } else {
-                                info!("Applied log_level: {}", level);
-                            }
+                    // Apply configuration from registration response and tell the
+                    // backend what was accepted, rejected and is now in effect
+                    let pushed_config = data.get("configuration").or_else(|| message.get("configuration"));
+                    if let Some(config) = pushed_config {
+                        info!("Applying configuration from server");
+                        let origin = ConfigOrigin::new(ConfigSource::RegisteredPush);
+                        let report = history::with_origin(origin, self.apply_configuration(config, "registration", None)).await;
+                        if self.negotiated.read().await.is_enabled(FEATURE_CONFIGURATION_APPLIED) {
+                            write.send(Message::text(report.to_message().to_string())).await?;
                         }
                     }
                 }
+                "configUpdate" => {
+                    // Settings changed on the backend while connected: same object and
+                    // handling as the configuration of `registered`, answered the same way
+                    let data = message.get("data").unwrap_or(&serde_json::Value::Null);
+                    let pushed = data.get("configuration").unwrap_or(data);
+                    if pushed.is_object() {
+                        let command_id = data.get("commandId").and_then(|v| v.as_str());
+                        info!("Applying configuration update from server");
+                        let origin = ConfigOrigin::command(ConfigSource::ConfigUpdate, "configUpdate", command_id);
+                        let report = history::with_origin(origin, self.apply_configuration(pushed, "configUpdate", command_id)).await;
+                        write.send(Message::text(report.to_message().to_string())).await?;
+                    } else {
+                        warn!("Ignoring configUpdate without a configuration object");
+                    }
+                }
                 "registrationPending" => {
                     // Hub is holding this agent for admin approval.
                     // Keep the connection; the Hub promotes us with a
@@ -553,8 +1158,22 @@ impl WebSocketClient {
                         .unwrap_or("no reason given");
                     error!("Hub rejected registration: {}", reason);
                 }
+                "protocolMismatch" => {
+                    // Backend can't fully speak our protocol: fall back to the
+                    // feature subset it lists rather than disconnecting
+                    let data = message.get("data").unwrap_or(&serde_json::Value::Null);
+                    warn!("Backend reported a protocol mismatch (agent protocol v{}): {}",
+                          super::protocol::PROTOCOL_VERSION,
+                          data.get("message").and_then(|v| v.as_str()).unwrap_or("no details"));
+                    self.negotiated.write().await.apply(data);
+                }
                 _ => {
-                    debug!("Received message type: {}", msg_type);
+                    // Newer backend: note each unhandled type once, not per message
+                    if self.unknown_message_types.lock().await.insert(msg_type.to_string()) {
+                        info!("Ignoring unhandled message type '{}' from backend", msg_type);
+                    } else {
+                        debug!("Received message type: {}", msg_type);
+                    }
                 }
             }
         }
@@ -566,3 +1185,86 @@ impl WebSocketClient {
         *self.running.write().await = false;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor(id: &str, sensor_type: &str, temperature: f64) -> Sensor {
+        serde_json::from_value(serde_json::json!({
+            "id": id, "name": id, "temperature": temperature, "type": sensor_type,
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn emergency_uses_each_sensor_types_threshold() {
+        let mut hardware = AgentConfig::default().hardware;
+        hardware.emergency_temp = 85.0;
+        hardware.emergency_temp_by_type.insert("hdd".to_string(), 60.0);
+        hardware.emergency_temp_by_type.insert("gpu".to_string(), 100.0);
+
+        let sensors = vec![sensor("gpu_hotspot", "gpu", 96.0), sensor("nvme0", "nvme", 70.0)];
+        assert!(WebSocketClient::find_emergency(&sensors, &hardware).is_none());
+
+        let sensors = vec![sensor("cpu", "cpu", 86.0), sensor("sda", "hdd", 64.0)];
+        let (tripped, threshold) = WebSocketClient::find_emergency(&sensors, &hardware).unwrap();
+        assert_eq!((&*tripped.id, threshold), ("sda", 60.0));
+
+        hardware.excluded_sensors.push("sda".to_string());
+        let (tripped, threshold) = WebSocketClient::find_emergency(&sensors, &hardware).unwrap();
+        assert_eq!((&*tripped.id, threshold), ("cpu", 85.0));
+    }
+
+    #[test]
+    fn emergency_compares_the_raw_reading_not_the_smoothed_one() {
+        let mut hardware = AgentConfig::default().hardware;
+        hardware.emergency_temp = 85.0;
+
+        let mut spiking = sensor("cpu", "cpu", 78.0);
+        spiking.raw_temperature = Some(88.0);
+        assert!(WebSocketClient::find_emergency(std::slice::from_ref(&spiking), &hardware).is_some());
+        assert!(!WebSocketClient::is_cooled(&[spiking], &hardware));
+    }
+
+    #[test]
+    fn cooled_means_every_sensor_below_threshold_minus_hysteresis() {
+        let mut hardware = AgentConfig::default().hardware;
+        hardware.emergency_temp = 85.0;
+        hardware.hysteresis_temp = 3.0;
+        hardware.emergency_temp_by_type.insert("hdd".to_string(), 60.0);
+
+        assert!(WebSocketClient::is_cooled(&[sensor("cpu", "cpu", 81.9), sensor("sda", "hdd", 56.0)], &hardware));
+        assert!(!WebSocketClient::is_cooled(&[sensor("cpu", "cpu", 70.0), sensor("sda", "hdd", 57.0)], &hardware));
+    }
+
+    #[tokio::test]
+    async fn crit_alarm_escalates_once_until_it_clears() {
+        use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm};
+
+        let sysfs = FakeSysfs::new("client-crit-alarm").chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 100).enable(1)));
+        let pwm = || std::fs::read_to_string(sysfs.chip_dir(0).join("pwm1")).unwrap().trim().to_string();
+        let set_pwm = |value: &str| std::fs::write(sysfs.chip_dir(0).join("pwm1"), value).unwrap();
+        let config = AgentConfig::default();
+        let client = WebSocketClient::new(config.clone(), Arc::new(sysfs.monitor_with(config.hardware)));
+        client.hardware_monitor.discover_fans().await.unwrap();
+        let rate_limit = || tokio::time::sleep(Duration::from_millis(150));
+        let mut alarmed = sensor("cpu", "cpu", 101.0);
+        alarmed.alarms = vec!["crit".to_string()];
+
+        rate_limit().await;
+        assert!(client.check_crit_alarm(std::slice::from_ref(&alarmed)).await.is_some());
+        assert_eq!(pwm(), "255");
+
+        // Still asserted: the fans aren't driven again every cycle
+        set_pwm("128");
+        rate_limit().await;
+        assert!(client.check_crit_alarm(std::slice::from_ref(&alarmed)).await.is_some());
+        assert_eq!(pwm(), "128");
+
+        // Cleared, then asserted again: a new escalation
+        assert!(client.check_crit_alarm(&[sensor("cpu", "cpu", 80.0)]).await.is_none());
+        assert!(client.check_crit_alarm(std::slice::from_ref(&alarmed)).await.is_some());
+        assert_eq!(pwm(), "255");
+    }
+}
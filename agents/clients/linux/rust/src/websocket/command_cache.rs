@@ -0,0 +1,76 @@
+//! Recently answered commands, so a command redelivered by the backend (retry after a
+//! flaky connection) gets its original `commandResponse` instead of running twice.
+//!
+//! Lives on the WebSocketClient: survives reconnects, not restarts.
+
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+/// How many commandIds are remembered.
+pub(crate) const COMMAND_CACHE_CAPACITY: usize = 256;
+
+#[derive(Debug)]
+pub(crate) struct CommandCache {
+    capacity: usize,
+    /// Least recently used first
+    order: VecDeque<String>,
+    responses: HashMap<String, (Instant, serde_json::Value)>,
+}
+
+impl CommandCache {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity, order: VecDeque::new(), responses: HashMap::new() }
+    }
+
+    /// The cached response for `command_id` and when it was first sent.
+    pub(crate) fn get(&mut self, command_id: &str) -> Option<(Instant, serde_json::Value)> {
+        let entry = self.responses.get(command_id)?.clone();
+        self.touch(command_id);
+        Some(entry)
+    }
+
+    pub(crate) fn insert(&mut self, command_id: &str, response: serde_json::Value) {
+        if self.responses.insert(command_id.to_string(), (Instant::now(), response)).is_some() {
+            self.touch(command_id);
+            return;
+        }
+        self.order.push_back(command_id.to_string());
+        while self.order.len() > self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.responses.remove(&oldest);
+            }
+        }
+    }
+
+    fn touch(&mut self, command_id: &str) {
+        if let Some(pos) = self.order.iter().position(|id| id == command_id) {
+            if let Some(id) = self.order.remove(pos) {
+                self.order.push_back(id);
+            }
+        }
+    }
+}
+
+impl Default for CommandCache {
+    fn default() -> Self {
+        Self::new(COMMAND_CACHE_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn evicts_least_recently_used() {
+        let mut cache = CommandCache::new(2);
+        cache.insert("a", serde_json::json!({"commandId": "a"}));
+        cache.insert("b", serde_json::json!({"commandId": "b"}));
+        assert_eq!(cache.get("a").unwrap().1["commandId"], "a"); // a is now most recent
+
+        cache.insert("c", serde_json::json!({"commandId": "c"}));
+        assert!(cache.get("b").is_none());
+        assert!(cache.get("a").is_some());
+        assert!(cache.get("c").is_some());
+    }
+}
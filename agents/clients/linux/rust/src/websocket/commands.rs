@@ -3,22 +3,39 @@
 use anyhow::Result;
 use futures_util::SinkExt;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio_tungstenite::tungstenite::protocol::Message;
 use tracing::{debug, error, info, warn};
 use tracing_subscriber::EnvFilter;
 
 use crate::app::logging::RELOAD_HANDLE;
-use crate::config::persistence::save_config;
+use crate::app::redact;
+use crate::config::history::{self, ConfigOrigin, ConfigSource};
+use crate::config::types::{TemperatureSmoothing, VirtualSensor};
 use crate::config::sst::{
     VALID_EMERGENCY_TEMPS, VALID_FAILSAFE_SPEEDS, VALID_FAN_STEPS,
     VALID_HYSTERESIS, VALID_LOG_LEVELS, VALID_UPDATE_INTERVALS,
 };
+use crate::daemon::safe_mode;
+use crate::hardware::types::{FanMode, FanSpeedOrigin};
 
+use super::characterization::StopRequest;
+use super::chunked;
 use super::client::WsSink;
+use super::event_log::Severity;
+use super::link_quality::Measure;
+
+/// Configuration changes included in a `getDiagnostics` dump.
+const DIAGNOSTICS_CONFIG_HISTORY: usize = 10;
+
+/// Answer to fan commands with `hardware.enable_fan_control` off, as the IPMI backend's.
+pub(crate) const FAN_CONTROL_DISABLED: &str = "Fan control is disabled in agent settings";
+
+/// Answer to fan commands in a container without hwmon (see hardware/linux/environment.rs).
+pub(crate) const MONITORING_ONLY_CONTAINER: &str = "Fan control is disabled: container without hwmon, monitoring only";
 
 impl super::client::WebSocketClient {
-    pub(crate) async fn handle_command(&self, data: &serde_json::Value, write: &mut WsSink) -> Result<()> {
+    pub(crate) async fn handle_command(&self, data: &serde_json::Value, write: &mut WsSink, received: Instant) -> Result<()> {
         // Validate command structure first
         let command_type = data.get("type")
             .and_then(|v| v.as_str())
@@ -31,45 +48,145 @@ impl super::client::WebSocketClient {
         let payload = data.get("payload")
             .ok_or_else(|| anyhow::anyhow!("Missing command payload"))?;
 
+        // Redelivered command (backend retry across a reconnect): replay, don't re-run
+        if let Some((answered_at, response)) = self.command_cache.lock().await.get(command_id) {
+            debug!("Duplicate command {} ({}), resending response from {:?} ago",
+                   command_id, command_type, answered_at.elapsed());
+            self.send_command_response(write, command_id, &response).await?;
+            self.link_quality.lock().unwrap().record(Measure::Command, received.elapsed());
+            return Ok(());
+        }
+
+        // A new token is a credential before it is in the config (see redact.rs)
+        if let Some(token) = payload.get("authToken").and_then(|v| v.as_str()) {
+            redact::register_secret(token);
+        }
         debug!("Processing command: {} with payload: {:?}", command_type, payload);
 
+        // Answered by the data sender along with the frame it sends (see data_request.rs)
+        let queued = if command_type == "requestData" {
+            Some(self.data_requests.lock().unwrap().request(command_id, received))
+        } else {
+            None
+        };
+        let response = match queued {
+            Some(Ok(())) => return Ok(()),
+            Some(Err(reason)) => command_response(command_id, false, Some(reason), serde_json::json!({})),
+            None => {
+                let origin = ConfigOrigin::command(ConfigSource::Command, command_type, Some(command_id));
+                history::with_origin(origin, self.build_command_response(command_id, command_type, payload)).await
+            }
+        };
+        self.command_cache.lock().await.insert(command_id, response.clone());
+
+        self.send_command_response(write, command_id, &response).await?;
+        self.link_quality.lock().unwrap().record(Measure::Command, received.elapsed());
+        debug!("Sent command response: {}, success: {}", command_id, response["success"]);
+
+        // Fan control is back: registration tells the backend
+        if command_type == "clearSafeMode" && response["data"]["cleared"] == true {
+            self.send_registration(write).await?;
+        }
+
+        // A rejected setting: report the effective values so the backend can reconcile
+        let report_enabled = self.negotiated.read().await
+            .is_enabled(super::protocol::FEATURE_CONFIGURATION_APPLIED);
+        if let Some(reason) = response.get("error").and_then(|v| v.as_str()).filter(|_| report_enabled) {
+            if let Some(report) = self.command_rejection(command_id, command_type, payload, reason).await {
+                write.send(Message::text(report.to_message().to_string())).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a `commandResponse`: one text frame, or in chunks when it is large and
+    /// the backend reassembles them (see chunked.rs).
+    async fn send_command_response(&self, write: &mut WsSink, command_id: &str, response: &serde_json::Value) -> Result<()> {
+        let chunked = self.negotiated.read().await.is_enabled(super::protocol::FEATURE_CHUNKED_RESPONSES);
+        let frames = chunked::response_frames(command_id, response.to_string(), chunked)?;
+        if frames.len() > 1 {
+            debug!("Sending response {} in {} frames", command_id, frames.len());
+        }
+        for frame in frames {
+            write.send(frame).await?;
+        }
+        Ok(())
+    }
+
+    /// Run a command and build its `commandResponse` message (cached by commandId).
+    async fn build_command_response(&self, command_id: &str, command_type: &str, payload: &serde_json::Value) -> serde_json::Value {
+        let gated = if self.safe_mode.read().await.is_some() { safe_mode::gate(command_type) } else { None };
         let (success, error_msg, result_data) = match command_type {
+            _ if gated.is_some() => {
+                debug!("Not running {} command: safe mode", command_type);
+                gated.unwrap_or_default()
+            }
             "setFanSpeed" => {
-                // Check if fan control is enabled
-                let fan_control_enabled = {
-                    let config = self.config.read().await;
-                    config.hardware.enable_fan_control
-                };
-
-                if !fan_control_enabled {
-                    debug!("Ignoring setFanSpeed command (fan control disabled)");
-                    // Return success silently to avoid error spam
-                    (true, None, serde_json::json!({"message": "Fan control is disabled"}))
+                if let Some(reason) = self.fan_control_disabled() {
+                    debug!("Rejecting setFanSpeed command (fan control disabled)");
+                    (false, Some(reason.to_string()), serde_json::json!({}))
+                } else if self.fan_writes_blocked().await {
+                    debug!("Rejecting setFanSpeed command (monitor-only: conflicting fan control software)");
+                    (false, Some("Fan control is disabled: conflicting fan control software detected".to_string()), serde_json::json!({}))
+                } else if let Some(fan_id) = payload.get("fanId").and_then(|v| v.as_str())
+                    .filter(|fan_id| self.characterization.lock().unwrap().drives(fan_id)) {
+                    debug!("Rejecting setFanSpeed command for {} (characterization running)", fan_id);
+                    (false, Some("Fan is held by a characterization run".to_string()), serde_json::json!({}))
                 } else if let (Some(fan_id), Some(speed)) = (
                     payload.get("fanId").and_then(|v| v.as_str()),
                     payload.get("speed").and_then(|v| v.as_u64())
                 ) {
+                    // `raw: true`: speed is a pwmN register value instead of a percent
+                    let raw = payload.get("raw").and_then(|v| v.as_bool()).unwrap_or(false);
+                    let max = if raw { 255 } else { 100 };
                     // Validate fan ID and speed
                     if fan_id.trim().is_empty() {
                         (false, Some("Fan ID cannot be empty".to_string()), serde_json::json!({}))
-                    } else if speed > 100 {
-                        (false, Some(format!("Invalid fan speed: {}. Must be between 0-100", speed)), serde_json::json!({}))
+                    } else if speed > max {
+                        (false, Some(format!("Invalid fan speed: {}. Must be between 0-{}", speed, max)), serde_json::json!({}))
                     } else {
-                        match self.hardware_monitor.set_fan_speed(fan_id, speed as u8).await {
+                        let origin = FanSpeedOrigin::BackendCommand { command_id: Some(command_id.to_string()) };
+                        let result = if raw {
+                            self.hardware_monitor.set_fan_pwm(fan_id, speed as u8, &origin).await
+                        } else {
+                            self.hardware_monitor.set_fan_speed(fan_id, speed as u8, &origin).await
+                        };
+                        match result {
+                            Ok(_) if raw => (true, None, serde_json::json!({"fanId": fan_id, "speed": speed, "raw": true})),
                             Ok(_) => (true, None, serde_json::json!({"fanId": fan_id, "speed": speed})),
-                            Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
+                            Err(e) => {
+                                self.record_event(Severity::Warning, "fan_write_failed", format!("Failed to set fan {}", fan_id),
+                                                  serde_json::json!({ "fan_id": fan_id, "speed": speed, "error": e.to_string() })).await;
+                                (false, Some(e.to_string()), serde_json::json!({}))
+                            }
                         }
                     }
                 } else {
                     (false, Some("Missing fanId or speed in setFanSpeed command".to_string()), serde_json::json!({}))
                 }
             }
+            "emergencyStop" if self.fan_control_disabled().is_some() => {
+                warn!("Rejecting emergencyStop command (fan control disabled)");
+                (false, self.fan_control_disabled().map(str::to_string), serde_json::json!({}))
+            }
             "emergencyStop" => {
-                match self.hardware_monitor.emergency_stop().await {
+                self.stop_characterization(StopRequest::EmergencyStop);
+                match self.hardware_monitor.max_cooling().await {
                     Ok(_) => (true, None, serde_json::json!({"message": "Emergency stop executed"})),
                     Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
                 }
             }
+            "releaseControl" => {
+                // Fans back to chip/driver/BMC auto until the next speed is written.
+                // Allowed even with fan control disabled - it RELEASES control.
+                let failed = self.release_control().await;
+                let error = (!failed.is_empty()).then(|| {
+                    let fans: Vec<_> = failed.iter().map(|(fan_id, reason)| format!("{} ({})", fan_id, reason)).collect();
+                    format!("Could not hand back: {}", fans.join(", "))
+                });
+                let failed: Vec<_> = failed.iter().map(|(fan_id, reason)| serde_json::json!({"fanId": fan_id, "error": reason})).collect();
+                (error.is_none(), error, serde_json::json!({"failed": failed}))
+            }
             "restoreFanToAuto" => {
                 // Hand the fan back to its driver's own curve (NVML GPU fans).
                 // handled=false for sysfs fans (no driver-auto to restore).
@@ -83,6 +200,37 @@ impl super::client::WebSocketClient {
                     (false, Some("Missing fanId in restoreFanToAuto command".to_string()), serde_json::json!({}))
                 }
             }
+            "setFanMode" => {
+                // Switches pwm_enable, so only with fan control on and nothing else driving the fans
+                let fan_id = payload.get("fanId").and_then(|v| v.as_str()).filter(|id| !id.trim().is_empty());
+                let mode = payload.get("mode").and_then(FanMode::parse);
+                if let Some(reason) = self.fan_control_disabled() {
+                    (false, Some(reason.to_string()), serde_json::json!({}))
+                } else if self.fan_writes_blocked().await {
+                    (false, Some("Fan control is disabled: conflicting fan control software detected".to_string()), serde_json::json!({}))
+                } else if let (Some(fan_id), Some(mode)) = (fan_id, mode) {
+                    match self.hardware_monitor.set_fan_mode(fan_id, mode).await {
+                        Ok(value) => (true, None, serde_json::json!({
+                            "fanId": fan_id, "mode": FanMode::name_of(value), "pwmEnable": value
+                        })),
+                        Err(e) => (false, Some(format!("{:#}", e)), serde_json::json!({})),
+                    }
+                } else {
+                    (false, Some("Missing fanId or invalid mode in setFanMode command (full_speed, manual, auto or a pwm_enable value)".to_string()),
+                     serde_json::json!({}))
+                }
+            }
+            "retryFanControl" => {
+                // Re-arm a fan given up on after repeated PWM write failures
+                if let Some(fan_id) = payload.get("fanId").and_then(|v| v.as_str()) {
+                    match self.hardware_monitor.retry_fan_control(fan_id).await {
+                        Ok(_) => (true, None, serde_json::json!({"fanId": fan_id})),
+                        Err(e) => (false, Some(format!("{:#}", e)), serde_json::json!({})),
+                    }
+                } else {
+                    (false, Some("Missing fanId in retryFanControl command".to_string()), serde_json::json!({}))
+                }
+            }
             "setUpdateInterval" => {
                 if let Some(interval) = payload.get("interval").and_then(|v| v.as_f64()) {
                     match self.set_update_interval(interval).await {
@@ -114,15 +262,33 @@ impl super::client::WebSocketClient {
                 }
             }
             "setEmergencyTemp" => {
-                if let Some(temp) = payload.get("temp").and_then(|v| v.as_f64()) {
-                    match self.set_emergency_temp(temp).await {
-                        Ok(_) => (true, None, serde_json::json!({"temp": temp})),
+                // Optional sensorType: set (or with temp null, clear) that type's override
+                let sensor_type = payload.get("sensorType").and_then(|v| v.as_str());
+                let temp = payload.get("temp").filter(|v| !v.is_null());
+                if let (Some(sensor_type), None) = (sensor_type, temp) {
+                    match self.clear_emergency_temp_override(sensor_type).await {
+                        Ok(_) => (true, None, serde_json::json!({"sensorType": sensor_type, "temp": null})),
+                        Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
+                    }
+                } else if let Some(temp) = temp.and_then(|v| v.as_f64()) {
+                    match self.set_emergency_temp(temp, sensor_type).await {
+                        Ok(_) => (true, None, serde_json::json!({"temp": temp, "sensorType": sensor_type})),
                         Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
                     }
                 } else {
                     (false, Some("Missing or invalid temp".to_string()), serde_json::json!({}))
                 }
             }
+            "setSmoothing" => {
+                // Payload is the temperature_smoothing object: {"mode": ..., "window"/"alpha"}
+                match serde_json::from_value::<TemperatureSmoothing>(payload.clone()) {
+                    Ok(mode) => match self.set_temperature_smoothing(mode).await {
+                        Ok(_) => (true, None, payload.clone()),
+                        Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
+                    },
+                    Err(e) => (false, Some(format!("Invalid smoothing settings: {}", e)), serde_json::json!({})),
+                }
+            }
             "setLogLevel" => {
                 if let Some(level) = payload.get("level").and_then(|v| v.as_str()) {
                     match self.set_log_level(level).await {
@@ -177,6 +343,25 @@ impl super::client::WebSocketClient {
                     (false, Some("Missing or invalid excludedSensors".to_string()), serde_json::json!({}))
                 }
             }
+            "setVirtualSensor" => {
+                // The payload is the definition, or {id, remove: true}
+                match self.set_virtual_sensor(payload).await {
+                    Ok(data) => (true, None, data),
+                    Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
+                }
+            }
+            "disableChip" | "enableChip" => {
+                // Whole hwmon chips, by name; disabling one with fans needs force
+                let disable = command_type == "disableChip";
+                let force = payload.get("force").and_then(|v| v.as_bool()).unwrap_or(false);
+                match payload.get("chip").and_then(|v| v.as_str()).filter(|c| !c.trim().is_empty()) {
+                    Some(chip) => match self.set_chip_disabled(chip, disable, force).await {
+                        Ok(data) => (true, None, data),
+                        Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
+                    },
+                    None => (false, Some(format!("Missing or invalid chip in {} command", command_type)), serde_json::json!({})),
+                }
+            }
             "setAuthToken" => {
                 // Persist happens inside set_auth_token BEFORE the ack below -
                 // the Hub only commits the token hash once we respond success,
@@ -204,14 +389,70 @@ impl super::client::WebSocketClient {
                 });
                 (true, None, serde_json::json!({"message": "Update initiated"}))
             }
+            "characterizeFans" => {
+                // Optional `duties`, `targetTemp`, `fans` and `maxHoldSecs`; see getCharacterization
+                match self.start_characterization(payload).await {
+                    Ok(fans) => (true, None, serde_json::json!({"message": "Characterization started", "fans": fans})),
+                    Err(reason) => (false, Some(reason), serde_json::json!({})),
+                }
+            }
+            "getCharacterization" => {
+                match serde_json::to_value(&*self.characterization.lock().unwrap()) {
+                    Ok(status) => (true, None, status),
+                    Err(e) => (false, Some(format!("Failed to serialize characterization: {}", e)), serde_json::json!({})),
+                }
+            }
+            "cancelCharacterization" => {
+                let running = self.stop_characterization(StopRequest::Cancelled);
+                (true, None, serde_json::json!({"cancelled": running}))
+            }
             "ping" => (true, None, serde_json::json!({"pong": true})),
+            "clearSafeMode" => {
+                let cleared = self.clear_safe_mode().await;
+                (true, None, serde_json::json!({"cleared": cleared}))
+            }
+            "getSensorStats" => {
+                let report = self.sensor_stats.lock().await.report(chrono::Utc::now().timestamp_millis());
+                match serde_json::to_value(&report) {
+                    Ok(json_value) => (true, None, json_value),
+                    Err(e) => (false, Some(format!("Failed to serialize sensor stats: {}", e)), serde_json::json!({})),
+                }
+            }
+            "resetSensorStats" => {
+                self.sensor_stats.lock().await.reset(chrono::Utc::now().timestamp_millis());
+                info!("Sensor statistics reset");
+                (true, None, serde_json::json!({"message": "Sensor statistics reset"}))
+            }
+            "getEvents" => {
+                // Optional `since` (Unix ms): only events that occurred or repeated from then on
+                let since = payload.get("since").and_then(|v| v.as_i64());
+                let events = self.events.lock().await.since(since);
+                match serde_json::to_value(&events) {
+                    Ok(events) => (true, None, serde_json::json!({"events": events})),
+                    Err(e) => (false, Some(format!("Failed to serialize events: {}", e)), serde_json::json!({})),
+                }
+            }
+            "getConfigHistory" => {
+                // Optional `limit` (default 50, at most 500) and `since` (Unix ms)
+                let limit = payload.get("limit").and_then(|v| v.as_u64()).unwrap_or(50).min(500) as usize;
+                let since = payload.get("since").and_then(|v| v.as_i64());
+                let changes = self.config.history(limit, since);
+                match serde_json::to_value(&changes) {
+                    Ok(changes) => (true, None, serde_json::json!({"changes": changes})),
+                    Err(e) => (false, Some(format!("Failed to serialize config history: {}", e)), serde_json::json!({})),
+                }
+            }
             "getDiagnostics" => {
                 // Generate fresh hardware dump and return as response
                 info!("Generating fresh hardware diagnostics for remote request");
                 match self.hardware_monitor.dump_hardware_info().await {
-                    Ok(dump) => {
+                    Ok(mut dump) => {
+                        dump.metadata.config_history = self.config.history(DIAGNOSTICS_CONFIG_HISTORY, None)
+                            .iter()
+                            .filter_map(|change| serde_json::to_value(change).ok())
+                            .collect();
                         match serde_json::to_value(&dump) {
-                            Ok(json_value) => (true, None, json_value),
+                            Ok(json_value) => (true, None, redact::diagnostics(&self.config.load(), json_value)),
                             Err(e) => (false, Some(format!("Failed to serialize diagnostics: {}", e)), serde_json::json!({})),
                         }
                     }
@@ -224,27 +465,13 @@ impl super::client::WebSocketClient {
             }
         };
 
-        // Send command response back to backend
-        {
-            let mut response = serde_json::json!({
-                "type": "commandResponse",
-                "commandId": command_id,
-                "success": success,
-                "data": result_data,
-                "timestamp": chrono::Utc::now().timestamp_millis()
-            });
-
-            if !success {
-                if let Some(err) = error_msg {
-                    response["error"] = serde_json::Value::String(err);
-                }
-            }
-
-            write.send(Message::text(response.to_string())).await?;
-            debug!("Sent command response: {}, success: {}", command_id, success);
+        // Settings changed by the backend; the auth token is a credential, not config
+        if success && command_type.starts_with("set") && !matches!(command_type, "setFanSpeed" | "setAuthToken") {
+            self.record_event(Severity::Info, "config_changed", format!("{} applied", command_type),
+                              serde_json::json!({ "command": command_type, "values": result_data })).await;
         }
 
-        Ok(())
+        command_response(command_id, success, error_msg, result_data)
     }
 
     pub(crate) async fn set_update_interval(&self, interval: f64) -> Result<()> {
@@ -253,21 +480,7 @@ impl super::client::WebSocketClient {
             return Err(anyhow::anyhow!("Invalid interval: {}. Must be one of: {:?}", interval, VALID_UPDATE_INTERVALS));
         }
 
-        // Get write lock, update quickly, release lock
-        let old_interval;
-        {
-            let mut config = self.config.write().await;
-            old_interval = config.agent.update_interval;
-            config.agent.update_interval = interval;
-        } // Lock released here
-
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+        let old_interval = self.config.update(|c| std::mem::replace(&mut c.agent.update_interval, interval));
 
         info!("Update interval changed: {}s → {}s (saved to config)", old_interval, interval);
         Ok(())
@@ -279,19 +492,7 @@ impl super::client::WebSocketClient {
             return Err(anyhow::anyhow!("Invalid fan step: {}. Must be one of: {:?}", step, VALID_FAN_STEPS));
         }
 
-        // Update config quickly with minimal lock time
-        {
-            let mut config = self.config.write().await;
-            config.hardware.fan_step_percent = step;
-        } // Lock released here
-
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+        self.config.update(|c| c.hardware.fan_step_percent = step);
 
         info!("Fan Step changed → {}%", step);
         Ok(())
@@ -303,46 +504,55 @@ impl super::client::WebSocketClient {
             return Err(anyhow::anyhow!("Invalid hysteresis: {}. Must be one of: {:?}", hysteresis, VALID_HYSTERESIS));
         }
 
-        // Update config quickly with minimal lock time
-        {
-            let mut config = self.config.write().await;
-            config.hardware.hysteresis_temp = hysteresis;
-        } // Lock released here
-
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+        self.config.update(|c| c.hardware.hysteresis_temp = hysteresis);
 
         info!("Hysteresis changed → {}°C", hysteresis);
         Ok(())
     }
 
-    pub(crate) async fn set_emergency_temp(&self, temp: f64) -> Result<()> {
+    /// Set the global emergency temp, or with `sensor_type` that type's override.
+    pub(crate) async fn set_emergency_temp(&self, temp: f64, sensor_type: Option<&str>) -> Result<()> {
         // Validate using SST values (generated from ui-options.json at compile time)
         let temp_u8 = temp as u8;
         if !VALID_EMERGENCY_TEMPS.contains(&temp_u8) {
             return Err(anyhow::anyhow!("Invalid emergency temp: {}. Must be one of: {:?}", temp, VALID_EMERGENCY_TEMPS));
         }
+        if sensor_type.is_some_and(|t| t.trim().is_empty()) {
+            return Err(anyhow::anyhow!("sensorType must not be empty"));
+        }
 
-        // Update config quickly with minimal lock time
-        {
-            let mut config = self.config.write().await;
-            config.hardware.emergency_temp = temp;
-        } // Lock released here
+        self.config.update(|c| match sensor_type {
+            Some(sensor_type) => {
+                c.hardware.emergency_temp_by_type.insert(sensor_type.to_string(), temp);
+            }
+            None => c.hardware.emergency_temp = temp,
+        });
 
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
+        match sensor_type {
+            Some(sensor_type) => info!("Emergency Temp for {} sensors changed → {}°C", sensor_type, temp),
+            None => info!("Emergency Temp changed → {}°C", temp),
+        }
+        Ok(())
+    }
 
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+    /// Drop a per-type emergency temp override; that type falls back to the global value.
+    pub(crate) async fn clear_emergency_temp_override(&self, sensor_type: &str) -> Result<()> {
+        if !self.config.load().hardware.emergency_temp_by_type.contains_key(sensor_type) {
+            return Ok(());
+        }
+        self.config.update(|c| c.hardware.emergency_temp_by_type.remove(sensor_type));
 
-        info!("Emergency Temp changed → {}°C", temp);
+        info!("Emergency Temp override for {} sensors removed", sensor_type);
+        Ok(())
+    }
+
+    pub(crate) async fn set_temperature_smoothing(&self, mode: TemperatureSmoothing) -> Result<()> {
+        mode.validate()?;
+
+        self.config.update(|c| c.hardware.temperature_smoothing = mode);
+        self.hardware_monitor.set_temperature_smoothing(mode);
+
+        info!("Temperature smoothing changed → {:?}", mode);
         Ok(())
     }
 
@@ -357,21 +567,7 @@ impl super::client::WebSocketClient {
         }
         let level_lower = level.to_lowercase();
 
-        // Update config quickly with minimal lock time
-        let old_level;
-        {
-            let mut config = self.config.write().await;
-            old_level = config.agent.log_level.clone();
-            config.agent.log_level = level.to_uppercase();
-        } // Lock released here
-
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+        let old_level = self.config.update(|c| std::mem::replace(&mut c.agent.log_level, level.to_uppercase()));
 
         // Reload the tracing filter dynamically
         let filter = match level_lower.as_str() {
@@ -402,21 +598,7 @@ impl super::client::WebSocketClient {
             return Err(anyhow::anyhow!("Invalid failsafe speed: {}. Must be one of: {:?}", speed, VALID_FAILSAFE_SPEEDS));
         }
 
-        // Update config quickly with minimal lock time
-        let old_speed;
-        {
-            let mut config = self.config.write().await;
-            old_speed = config.hardware.failsafe_speed;
-            config.hardware.failsafe_speed = speed;
-        } // Lock released here
-
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+        let old_speed = self.config.update(|c| std::mem::replace(&mut c.hardware.failsafe_speed, speed));
 
         info!("Failsafe Speed changed: {}% → {}%", old_speed, speed);
         Ok(())
@@ -424,37 +606,72 @@ impl super::client::WebSocketClient {
 
     pub(crate) async fn set_excluded_sensors(&self, excluded: Vec<String>) -> Result<()> {
         let count = excluded.len();
-        {
-            let mut config = self.config.write().await;
-            config.hardware.excluded_sensors = excluded;
-        }
-
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+        self.config.update(|c| c.hardware.excluded_sensors = excluded);
 
         info!("Excluded sensors updated: {} sensor(s)", count);
         Ok(())
     }
 
-    pub(crate) async fn set_enable_fan_control(&self, enabled: bool) -> Result<()> {
-        // Update config quickly with minimal lock time
-        let old_enabled;
-        {
-            let mut config = self.config.write().await;
-            old_enabled = config.hardware.enable_fan_control;
-            config.hardware.enable_fan_control = enabled;
-        } // Lock released here
+    /// Add or replace the virtual sensor with the payload's id, or remove it with
+    /// `remove: true`. Computed from the next cycle on.
+    pub(crate) async fn set_virtual_sensor(&self, payload: &serde_json::Value) -> Result<serde_json::Value> {
+        let (id, definition) = if payload.get("remove").and_then(|v| v.as_bool()).unwrap_or(false) {
+            let id = payload.get("id").and_then(|v| v.as_str())
+                .ok_or_else(|| anyhow::anyhow!("Missing id in setVirtualSensor command"))?;
+            if !self.config.load().hardware.virtual_sensors.iter().any(|s| s.id == id) {
+                anyhow::bail!("No virtual sensor {}", id);
+            }
+            (id.to_string(), None)
+        } else {
+            let definition: VirtualSensor = serde_json::from_value(payload.clone())
+                .map_err(|e| anyhow::anyhow!("Invalid virtual sensor: {}", e))?;
+            definition.validate()?;
+            (definition.id.clone(), Some(definition))
+        };
+
+        let virtual_sensors = self.config.update(|c| {
+            let sensors = &mut c.hardware.virtual_sensors;
+            match (sensors.iter_mut().find(|s| s.id == id), definition) {
+                (Some(existing), Some(definition)) => *existing = definition,
+                (None, Some(definition)) => sensors.push(definition),
+                (_, None) => sensors.retain(|s| s.id != id),
+            }
+            sensors.clone()
+        });
+        self.hardware_monitor.set_virtual_sensors(&virtual_sensors);
 
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
+        let removed = !virtual_sensors.iter().any(|s| s.id == id);
+        info!("Virtual sensor {} {} ({} defined)", id, if removed { "removed" } else { "set" }, virtual_sensors.len());
+        Ok(serde_json::json!({"id": id, "removed": removed, "virtualSensors": virtual_sensors.len()}))
+    }
 
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+    /// Add `chip` to `hardware.disabled_chips` or take it out, and apply it from the
+    /// next cycle on. A chip whose fans the agent controls is only disabled with `force`.
+    pub(crate) async fn set_chip_disabled(&self, chip: &str, disable: bool, force: bool) -> Result<serde_json::Value> {
+        let released = if disable { self.hardware_monitor.chip_fans(chip).await } else { Vec::new() };
+        if !released.is_empty() && !force {
+            anyhow::bail!(
+                "Chip {} provides controllable fan(s) {}; disabling it removes their control (send force: true to disable it anyway)",
+                chip, released.join(", ")
+            );
+        }
+
+        let disabled_chips = self.config.update(|c| {
+            let chips = &mut c.hardware.disabled_chips;
+            chips.retain(|c| c != chip);
+            if disable {
+                chips.push(chip.to_string());
+            }
+            chips.clone()
+        });
+        self.hardware_monitor.set_disabled_chips(&disabled_chips).await;
+
+        info!("Chip {} {} ({} disabled)", chip, if disable { "disabled" } else { "enabled" }, disabled_chips.len());
+        Ok(serde_json::json!({"chip": chip, "disabledChips": disabled_chips, "releasedFans": released}))
+    }
+
+    pub(crate) async fn set_enable_fan_control(&self, enabled: bool) -> Result<()> {
+        let old_enabled = self.config.update(|c| std::mem::replace(&mut c.hardware.enable_fan_control, enabled));
 
         let status = if enabled { "enabled" } else { "disabled" };
         let old_status = if old_enabled { "enabled" } else { "disabled" };
@@ -467,21 +684,13 @@ impl super::client::WebSocketClient {
             return Err(anyhow::anyhow!("Auth token cannot be empty"));
         }
 
-        // Update config quickly with minimal lock time
-        {
-            let mut config = self.config.write().await;
-            config.auth.auth_token = Some(token.to_string());
+        self.config.update(|c| {
+            c.auth.auth_token = Some(token.to_string());
             // One-time bootstrap credential - no longer needed
-            config.auth.enrollment_token = None;
-        } // Lock released here
-
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
-
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+            c.auth.enrollment_token = None;
+        });
+        // Wait for the save: the Hub only keeps the token once we ack it
+        self.config.flush().await?;
 
         // Never log the token itself
         info!("Hub auth token stored (saved to config)");
@@ -498,23 +707,214 @@ impl super::client::WebSocketClient {
             return Err(anyhow::anyhow!("Agent name must be 255 characters or less"));
         }
 
-        // Update config quickly with minimal lock time
-        let old_name;
+        let old_name = self.config.update(|c| std::mem::replace(&mut c.agent.name, trimmed_name.to_string()));
+
+        info!("Agent Name changed: {} → {}", old_name, trimmed_name);
+        Ok(())
+    }
+}
+
+/// A `commandResponse` message; `error` is only sent on failure.
+pub(crate) fn command_response(command_id: &str, success: bool, error: Option<String>, data: serde_json::Value) -> serde_json::Value {
+    let mut response = serde_json::json!({
+        "type": "commandResponse",
+        "commandId": command_id,
+        "success": success,
+        "data": data,
+        "timestamp": chrono::Utc::now().timestamp_millis()
+    });
+
+    if !success {
+        if let Some(err) = error {
+            response["error"] = serde_json::Value::String(err);
+        }
+    }
+
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::config::types::AgentConfig;
+    use crate::hardware::linux::fan_modes::FanModeStore;
+    use crate::hardware::linux::fixture::{Chip, FakeSysfs, Pwm, Temp};
+    use crate::hardware::types::{FanControlConflict, RunEnvironment};
+    use crate::websocket::client::WebSocketClient;
+
+    fn client(sysfs: &FakeSysfs, fan_control: bool, sensor_monitoring: bool) -> WebSocketClient {
+        let mut config = AgentConfig::default();
+        config.hardware.enable_fan_control = fan_control;
+        config.hardware.enable_sensor_monitoring = sensor_monitoring;
+        WebSocketClient::new(config.clone(), Arc::new(sysfs.monitor_with(config.hardware)))
+    }
+
+    #[tokio::test]
+    async fn fan_commands_follow_enable_fan_control() {
+        let sysfs = FakeSysfs::new("commands-fan-control")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 45_000)).fan(1, 800).pwm(Pwm::new(1, 100)));
+        let pwm = || std::fs::read_to_string(sysfs.chip_dir(0).join("pwm1")).unwrap().trim().to_string();
+        let set_speed = serde_json::json!({"fanId": "nct6798_fan_1", "speed": 50});
+
+        // Sensor monitoring has no say in fan commands
+        for sensor_monitoring in [true, false] {
+            let disabled = client(&sysfs, false, sensor_monitoring);
+            disabled.hardware_monitor.discover_fans().await.unwrap();
+            for (command, payload) in [("setFanSpeed", &set_speed), ("emergencyStop", &serde_json::json!({}))] {
+                let response = disabled.build_command_response("cmd-1", command, payload).await;
+                assert_eq!(response["success"], false, "{}", command);
+                assert_eq!(response["error"], super::FAN_CONTROL_DISABLED);
+            }
+            disabled.set_all_fans_to_speed(70).await.unwrap();
+            assert_eq!(pwm(), "100");
+        }
+
+        // A container without hwmon monitors only, whatever enable_fan_control says
+        let container = client(&sysfs, true, true);
+        *container.environment.lock().unwrap() = RunEnvironment { container: true, no_hwmon: true };
+        container.hardware_monitor.discover_fans().await.unwrap();
+        for (command, payload) in [("setFanSpeed", &set_speed), ("emergencyStop", &serde_json::json!({}))] {
+            let response = container.build_command_response("cmd-1", command, payload).await;
+            assert_eq!(response["error"], super::MONITORING_ONLY_CONTAINER, "{}", command);
+        }
+        container.set_all_fans_to_speed(70).await.unwrap();
+        assert_eq!(pwm(), "100");
+
+        let enabled = client(&sysfs, true, false);
+        enabled.hardware_monitor.discover_fans().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+        let response = enabled.build_command_response("cmd-2", "setFanSpeed", &set_speed).await;
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(pwm(), "128");
+    }
+
+    #[tokio::test]
+    async fn fan_speeds_are_refused_while_monitor_only() {
+        let sysfs = FakeSysfs::new("commands-monitor-only")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 45_000)).fan(1, 800).pwm(Pwm::new(1, 100)));
+        let pwm = || std::fs::read_to_string(sysfs.chip_dir(0).join("pwm1")).unwrap().trim().to_string();
+        let client = client(&sysfs, true, true);
+        client.config.update(|c| c.hardware.monitor_only_on_conflict = true);
+        *client.fan_control_conflicts.write().await = vec![FanControlConflict {
+            program: "fancontrol".to_string(), pid: Some(4242), fans: Vec::new(), message: "fancontrol is running".to_string(),
+        }];
+        client.hardware_monitor.discover_fans().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+
+        let set_speed = serde_json::json!({"fanId": "nct6798_fan_1", "speed": 50});
+        let response = client.build_command_response("cmd-1", "setFanSpeed", &set_speed).await;
+        assert_eq!(response["success"], false, "{}", response);
+        assert_eq!(response["error"], "Fan control is disabled: conflicting fan control software detected");
+        assert_eq!(pwm(), "100");
+    }
+
+    #[tokio::test]
+    async fn fan_speeds_are_refused_for_fans_being_characterized() {
+        let sysfs = FakeSysfs::new("commands-characterizing")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 45_000)).fan(1, 800).pwm(Pwm::new(1, 100)));
+        let pwm = || std::fs::read_to_string(sysfs.chip_dir(0).join("pwm1")).unwrap().trim().to_string();
+        let client = client(&sysfs, true, true);
         {
-            let mut config = self.config.write().await;
-            old_name = config.agent.name.clone();
-            config.agent.name = trimmed_name.to_string();
-        } // Lock released here
+            let mut status = client.characterization.lock().unwrap();
+            status.running = true;
+            status.fans = vec!["nct6798_fan_1".to_string()];
+        }
+        client.hardware_monitor.discover_fans().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+
+        let set_speed = serde_json::json!({"fanId": "nct6798_fan_1", "speed": 50});
+        let response = client.build_command_response("cmd-1", "setFanSpeed", &set_speed).await;
+        assert_eq!(response["success"], false, "{}", response);
+        assert_eq!(response["error"], "Fan is held by a characterization run");
+        assert_eq!(pwm(), "100");
+
+        // Taken once the run is over
+        client.characterization.lock().unwrap().running = false;
+        let response = client.build_command_response("cmd-2", "setFanSpeed", &set_speed).await;
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(pwm(), "128");
+    }
+
+    #[test]
+    fn every_command_is_advertised() {
+        let source = include_str!("commands.rs");
+        let start = source.find("let (success, error_msg, result_data) = match command_type {").unwrap();
+        let end = start + source[start..].find("            _ => {").unwrap();
+        let mut handled: Vec<&str> = source[start..end]
+            .lines()
+            .filter_map(|line| line.strip_prefix("            \"")?.split_once(" =>"))
+            // Without a guard, if any
+            .flat_map(|(arm, _)| arm.split(" if ").next().unwrap_or_default().split(" | "))
+            .map(|command| command.trim_matches('"'))
+            .collect();
+        // Answered by the data sender, before build_command_response
+        handled.push("requestData");
+
+        let supported = crate::websocket::protocol::SUPPORTED_COMMANDS;
+        for command in &handled {
+            assert!(supported.contains(command), "{} is handled but not in SUPPORTED_COMMANDS", command);
+        }
+        for command in supported {
+            assert!(handled.contains(command), "{} is in SUPPORTED_COMMANDS but not handled", command);
+        }
+    }
 
-        // Perform I/O outside of lock
-        let config_path = std::env::current_exe()?
-            .parent()
-            .ok_or_else(|| anyhow::anyhow!("Cannot determine executable directory"))?
-            .join("config.json");
+    #[tokio::test]
+    async fn release_control_hands_fans_back_even_with_fan_control_off() {
+        let sysfs = FakeSysfs::new("commands-release-control")
+            .chip(Chip::new("nct6798").fan(1, 800).pwm(Pwm::new(1, 100).enable(5)));
+        let enable = || std::fs::read_to_string(sysfs.chip_dir(0).join("pwm1_enable")).unwrap().trim().to_string();
+        let config = AgentConfig::default();
+        let mut monitor = sysfs.monitor_with(config.hardware.clone());
+        monitor.fan_modes = Some(FanModeStore::open(sysfs.root().join("fan-modes.json")));
+        let client = WebSocketClient::new(config, Arc::new(monitor));
+        client.hardware_monitor.discover_fans().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await; // write rate limit
+
+        let set_speed = serde_json::json!({"fanId": "nct6798_fan_1", "speed": 50});
+        let response = client.build_command_response("cmd-1", "setFanSpeed", &set_speed).await;
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(enable(), "1");
+
+        client.config.update(|c| c.hardware.enable_fan_control = false);
+        let response = client.build_command_response("cmd-2", "releaseControl", &serde_json::json!({})).await;
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(response["data"]["failed"], serde_json::json!([]));
+        assert_eq!(enable(), "5");
+    }
 
-        save_config(&*self.config.read().await, config_path.to_str().unwrap()).await?;
+    #[tokio::test]
+    async fn virtual_sensors_are_set_and_removed() {
+        let sysfs = FakeSysfs::new("commands-virtual-sensor")
+            .chip(Chip::new("nct6798").temp(Temp::new(1, 30_000)).temp(Temp::new(2, 40_000)));
+        let client = client(&sysfs, true, true);
+        let loop_temp = |aggregation: &str| serde_json::json!({
+            "id": "loop_temp", "name": "Loop Temp", "aggregation": aggregation,
+            "sources": [{"sensor": "nct6798_sensor_1", "weight": 0.7}, {"sensor": "nct6798_sensor_2", "weight": 0.3}],
+        });
+        let virtual_temperature = || async {
+            let sensors = client.hardware_monitor.discover_sensors().await.unwrap();
+            sensors.iter().find(|s| *s.id == *"loop_temp").map(|s| (s.temperature, s.sensor_type.to_string()))
+        };
 
-        info!("Agent Name changed: {} → {}", old_name, trimmed_name);
-        Ok(())
+        let response = client.build_command_response("cmd-1", "setVirtualSensor", &loop_temp("weighted_avg")).await;
+        assert_eq!(response["success"], true, "{}", response);
+        assert_eq!(virtual_temperature().await, Some((33.0, "virtual".to_string())));
+
+        // Same id: replaced, and saved with the config
+        client.build_command_response("cmd-2", "setVirtualSensor", &loop_temp("delta")).await;
+        assert_eq!(virtual_temperature().await, Some((-10.0, "virtual".to_string())));
+        assert_eq!(client.config.load().hardware.virtual_sensors.len(), 1);
+
+        let invalid = serde_json::json!({"id": "bad", "name": "Bad", "aggregation": "delta", "sources": [{"sensor": "nct6798_sensor_1"}]});
+        let response = client.build_command_response("cmd-3", "setVirtualSensor", &invalid).await;
+        assert_eq!(response["success"], false);
+
+        let remove = serde_json::json!({"id": "loop_temp", "remove": true});
+        let response = client.build_command_response("cmd-4", "setVirtualSensor", &remove).await;
+        assert_eq!(response["data"]["removed"], true, "{}", response);
+        assert_eq!(virtual_temperature().await, None);
+        assert!(client.config.load().hardware.virtual_sensors.is_empty());
     }
 }
@@ -0,0 +1,137 @@
+//! `backend.compression`: data messages as binary frames of deflated JSON.
+//!
+//! Only data messages are compressed, as they are nearly all of the traffic and
+//! repeat the same keys and metadata every cycle. Registration, command responses
+//! and the rest stay text, readable in a packet capture. The backend lists
+//! `deflate_data` in `registered` when it can inflate them; until it does, and with
+//! older backends, data goes out as text. Raw deflate (RFC 1951) rather than zstd,
+//! since Node's built-in zlib inflates it.
+
+use std::io::Write;
+
+use anyhow::Result;
+use flate2::write::DeflateEncoder;
+use tokio_tungstenite::tungstenite::protocol::Message;
+
+/// The frame for a serialized data message: deflated binary, or the JSON as text.
+pub(crate) fn data_frame(json: Vec<u8>, deflate: bool) -> Result<Message> {
+    if !deflate {
+        return Ok(Message::text(String::from_utf8(json)?));
+    }
+    let mut encoder = DeflateEncoder::new(Vec::with_capacity(json.len() / 4), flate2::Compression::default());
+    encoder.write_all(&json)?;
+    Ok(Message::binary(encoder.finish()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+
+    use crate::hardware::types::{Fan, Sensor, SystemHealth};
+    use crate::websocket::payload::{DataMessage, Entries};
+    use crate::websocket::transport::{self, Route};
+
+    /// A data message of a 50-sensor, 6-fan host.
+    fn data_message() -> Vec<u8> {
+        let sensors: Vec<Sensor> = (0..50)
+            .map(|i| {
+                let chip = ["k10temp", "nct6798", "nvme", "drivetemp", "amdgpu"][i % 5];
+                Sensor {
+                    id: format!("{}_temp{}", chip, i).into(),
+                    name: format!("{} Temperature {}", chip, i).into(),
+                    display_name: None,
+                    temperature: 35.0 + i as f64 * 0.7,
+                    sensor_type: ["cpu", "motherboard", "nvme", "hdd", "gpu"][i % 5].into(),
+                    max_temp: Some(90.0),
+                    crit_temp: Some(100.0),
+                    chip: Some(chip.into()),
+                    hardware_name: Some(format!("{} device", chip).into()),
+                    source: Some(format!("/sys/class/hwmon/hwmon{}/temp{}_input", i / 8, i % 8 + 1).into()),
+                    alarms: Vec::new(),
+                    raw_temperature: None,
+                    stale: false,
+                }
+            })
+            .collect();
+        let fans: Vec<Fan> = (0..6)
+            .map(|i| Fan {
+                id: format!("nct6798_fan_{}", i + 1),
+                name: format!("Chassis Fan {}", i + 1),
+                rpm: Some(800 + i * 50),
+                speed: 40,
+                target_speed: 40,
+                pwm: None,
+                status: "ok".to_string(),
+                status_detail: None,
+                has_pwm_control: true,
+                control_method: None,
+                pwm_file: Some(format!("/sys/class/hwmon/hwmon2/pwm{}", i + 1)),
+                pwm_enable: None,
+                control_mode: None,
+                zone: None,
+                alarms: Vec::new(),
+                drift: false,
+                control_error: None,
+                last_set_by: None,
+            })
+            .collect();
+        let health = SystemHealth { cpu_usage: 12.5, memory_usage: 41.0, agent_uptime: 86_400.0, throttled: None, connection: None, storage_health: Vec::new(), notifications: None };
+        let mut buf = Vec::new();
+        DataMessage::new("linux-test-1a2b3c4d", 1_760_601_234_000, 86_400_000, Entries::full(&sensors), Entries::full(&fans), &health)
+            .write_into(&mut buf)
+            .unwrap();
+        buf
+    }
+
+    fn inflate(bytes: &[u8]) -> Vec<u8> {
+        let mut json = Vec::new();
+        flate2::read::DeflateDecoder::new(bytes).read_to_end(&mut json).unwrap();
+        json
+    }
+
+    #[test]
+    fn data_messages_deflate_to_a_fraction() {
+        let json = data_message();
+        let Message::Binary(deflated) = data_frame(json.clone(), true).unwrap() else { panic!("expected a binary frame") };
+        assert!(deflated.len() * 8 < json.len(), "{} -> {} bytes", json.len(), deflated.len());
+        assert_eq!(inflate(&deflated), json);
+
+        assert_eq!(data_frame(json.clone(), false).unwrap(), Message::text(String::from_utf8(json).unwrap()));
+    }
+
+    #[tokio::test]
+    async fn compressed_frames_inflate_to_the_original_json() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            let mut received = Vec::new();
+            while let Some(Ok(message)) = ws.next().await {
+                match message {
+                    Message::Binary(bytes) => received.push(inflate(&bytes)),
+                    Message::Text(text) => received.push(text.as_bytes().to_vec()),
+                    _ => break,
+                }
+            }
+            received
+        });
+
+        let (mut ws, _) = transport::connect(&format!("ws://127.0.0.1:{}/websocket", port), &Route::default()).await.unwrap();
+        let json = data_message();
+        ws.send(data_frame(json.clone(), true).unwrap()).await.unwrap();
+        ws.send(data_frame(json.clone(), false).unwrap()).await.unwrap();
+        ws.close(None).await.unwrap();
+
+        let received = server.await.unwrap();
+        let original: serde_json::Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(received.len(), 2);
+        for message in received {
+            assert_eq!(serde_json::from_slice::<serde_json::Value>(&message).unwrap(), original);
+        }
+    }
+}
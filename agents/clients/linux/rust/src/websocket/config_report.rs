@@ -0,0 +1,397 @@
+//! `configurationApplied` messages: which server-pushed settings the agent accepted or
+//! rejected (with reasons), plus the values it is actually running with, so the
+//! backend can reconcile what the UI shows.
+
+use anyhow::{anyhow, Result};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use tracing::{error, info};
+
+use crate::config::types::{AgentConfig, TemperatureSmoothing};
+
+use super::event_log::Severity;
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct RejectedField {
+    pub(crate) field: String,
+    pub(crate) value: serde_json::Value,
+    pub(crate) reason: String,
+}
+
+/// Settings the backend can push, as currently in effect.
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct EffectiveConfig {
+    pub(crate) update_interval: f64,
+    pub(crate) fan_step_percent: u8,
+    pub(crate) hysteresis_temp: f64,
+    pub(crate) emergency_temp: f64,
+    pub(crate) emergency_temp_by_type: BTreeMap<String, f64>,
+    pub(crate) failsafe_speed: u8,
+    pub(crate) enable_fan_control: bool,
+    pub(crate) log_level: String,
+    pub(crate) temperature_smoothing: TemperatureSmoothing,
+}
+
+impl From<&AgentConfig> for EffectiveConfig {
+    fn from(config: &AgentConfig) -> Self {
+        Self {
+            update_interval: config.agent.update_interval,
+            fan_step_percent: config.hardware.fan_step_percent,
+            hysteresis_temp: config.hardware.hysteresis_temp,
+            emergency_temp: config.hardware.emergency_temp,
+            emergency_temp_by_type: config.hardware.emergency_temp_by_type.clone(),
+            failsafe_speed: config.hardware.failsafe_speed,
+            enable_fan_control: config.hardware.enable_fan_control,
+            log_level: config.agent.log_level.clone(),
+            temperature_smoothing: config.hardware.temperature_smoothing,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ConfigurationApplied {
+    pub(crate) agent_id: String,
+    /// "registration", "configUpdate", or the set* command type
+    pub(crate) source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) command_id: Option<String>,
+    pub(crate) accepted: Vec<String>,
+    pub(crate) rejected: Vec<RejectedField>,
+    /// Filled in by `finish` once every field was applied
+    pub(crate) effective: Option<EffectiveConfig>,
+}
+
+impl ConfigurationApplied {
+    pub(crate) fn new(agent_id: &str, source: &str, command_id: Option<&str>) -> Self {
+        Self {
+            agent_id: agent_id.to_string(),
+            source: source.to_string(),
+            command_id: command_id.map(str::to_string),
+            accepted: Vec::new(),
+            rejected: Vec::new(),
+            effective: None,
+        }
+    }
+
+    /// Record the outcome of applying `field`, logging it like the individual setters.
+    pub(crate) fn record(&mut self, field: &str, value: &serde_json::Value, result: Result<()>) {
+        match result {
+            Ok(()) => {
+                info!("Applied {}: {}", field, value);
+                self.accepted.push(field.to_string());
+            }
+            Err(e) => {
+                error!("Failed to apply {}: {}", field, e);
+                self.reject(field, value, &e.to_string());
+            }
+        }
+    }
+
+    pub(crate) fn reject(&mut self, field: &str, value: &serde_json::Value, reason: &str) {
+        self.rejected.push(RejectedField {
+            field: field.to_string(),
+            value: value.clone(),
+            reason: reason.to_string(),
+        });
+    }
+
+    pub(crate) fn finish(mut self, config: &AgentConfig) -> Self {
+        self.effective = Some(EffectiveConfig::from(config));
+        self
+    }
+
+    pub(crate) fn to_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "type": "configurationApplied",
+            "data": self,
+        })
+    }
+}
+
+/// A pushed value that must be a number.
+fn number(value: &serde_json::Value) -> Result<f64> {
+    value.as_f64().ok_or_else(|| anyhow!("Expected a number, got {}", value))
+}
+
+/// Keys of a pushed configuration object, in the order they are applied.
+const CONFIGURATION_FIELDS: &[&str] = &[
+    "name",
+    "update_interval",
+    "fan_step_percent",
+    "hysteresis_temp",
+    "emergency_temp",
+    "emergency_temp_by_type",
+    "log_level",
+    "failsafe_speed",
+    "enable_fan_control",
+    "temperature_smoothing",
+];
+
+impl super::client::WebSocketClient {
+    /// Apply a pushed configuration object (of `registered`, or of a `configUpdate`
+    /// while connected) field by field. Fields it doesn't carry keep their value.
+    pub(crate) async fn apply_configuration(
+        &self,
+        pushed: &serde_json::Value,
+        source: &str,
+        command_id: Option<&str>,
+    ) -> ConfigurationApplied {
+        let agent_id = self.config.load().agent.id.clone();
+        let mut report = ConfigurationApplied::new(&agent_id, source, command_id);
+
+        if let Some(value) = pushed.get("name") {
+            let result = match value.as_str() {
+                Some(name) => self.set_agent_name(name).await,
+                None => Err(anyhow!("Expected a string, got {}", value)),
+            };
+            report.record("name", value, result);
+        }
+
+        if let Some(value) = pushed.get("update_interval") {
+            let result = match number(value) {
+                Ok(interval) => self.set_update_interval(interval).await,
+                Err(e) => Err(e),
+            };
+            report.record("update_interval", value, result);
+        }
+
+        if let Some(value) = pushed.get("fan_step_percent") {
+            let result = match number(value) {
+                Ok(step) => self.set_fan_step(step.round() as u8).await,
+                Err(e) => Err(e),
+            };
+            report.record("fan_step_percent", value, result);
+        }
+
+        if let Some(value) = pushed.get("hysteresis_temp") {
+            let result = match number(value) {
+                Ok(hysteresis) => self.set_hysteresis(hysteresis).await,
+                Err(e) => Err(e),
+            };
+            report.record("hysteresis_temp", value, result);
+        }
+
+        if let Some(value) = pushed.get("emergency_temp") {
+            let result = match number(value) {
+                Ok(temp) => self.set_emergency_temp(temp, None).await,
+                Err(e) => Err(e),
+            };
+            report.record("emergency_temp", value, result);
+        }
+
+        // Per-sensor-type emergency_temp overrides, one field per type
+        if let Some(value) = pushed.get("emergency_temp_by_type") {
+            match value.as_object() {
+                Some(by_type) => {
+                    for (sensor_type, temp) in by_type {
+                        let result = match number(temp) {
+                            Ok(t) => self.set_emergency_temp(t, Some(sensor_type)).await,
+                            Err(e) => Err(e),
+                        };
+                        report.record(&format!("emergency_temp_by_type.{}", sensor_type), temp, result);
+                    }
+                }
+                None => report.reject("emergency_temp_by_type", value, "Expected an object of sensor type -> °C"),
+            }
+        }
+
+        if let Some(value) = pushed.get("log_level") {
+            let result = match value.as_str() {
+                Some(level) => self.set_log_level(level).await,
+                None => Err(anyhow!("Expected a string, got {}", value)),
+            };
+            report.record("log_level", value, result);
+        }
+
+        if let Some(value) = pushed.get("failsafe_speed") {
+            let result = match number(value) {
+                Ok(speed) => self.set_failsafe_speed(speed.round() as u8).await,
+                Err(e) => Err(e),
+            };
+            report.record("failsafe_speed", value, result);
+        }
+
+        if let Some(value) = pushed.get("enable_fan_control") {
+            let result = match value.as_bool() {
+                Some(enabled) => self.set_enable_fan_control(enabled).await,
+                None => Err(anyhow!("Expected true or false, got {}", value)),
+            };
+            report.record("enable_fan_control", value, result);
+        }
+
+        if let Some(value) = pushed.get("temperature_smoothing") {
+            let result = match serde_json::from_value::<TemperatureSmoothing>(value.clone()) {
+                Ok(mode) => self.set_temperature_smoothing(mode).await,
+                Err(e) => Err(anyhow!("Invalid smoothing settings: {}", e)),
+            };
+            report.record("temperature_smoothing", value, result);
+        }
+
+        // Settings of a newer backend this agent can't apply
+        if let Some(fields) = pushed.as_object() {
+            for (field, value) in fields.iter().filter(|(f, _)| !CONFIGURATION_FIELDS.contains(&f.as_str())) {
+                report.reject(field, value, "Unknown setting");
+            }
+        }
+
+        if !report.accepted.is_empty() {
+            self.record_event(Severity::Info, "config_changed", "Configuration from server applied",
+                              serde_json::json!({ "source": source, "accepted": report.accepted })).await;
+        }
+
+        report.finish(&self.config.load())
+    }
+
+    /// Rejection report for a failed set* command, so the backend can roll back what
+    /// it optimistically shows. `None` for commands that don't set a pushed setting.
+    pub(crate) async fn command_rejection(
+        &self,
+        command_id: &str,
+        command_type: &str,
+        payload: &serde_json::Value,
+        reason: &str,
+    ) -> Option<ConfigurationApplied> {
+        // setSmoothing's payload is the setting itself
+        if command_type == "setSmoothing" {
+            let config = self.config.load();
+            let mut report = ConfigurationApplied::new(&config.agent.id, command_type, Some(command_id));
+            report.reject("temperature_smoothing", payload, reason);
+            return Some(report.finish(&config));
+        }
+
+        let (field, key) = match command_type {
+            "setUpdateInterval" => ("update_interval".to_string(), "interval"),
+            "setFanStep" => ("fan_step_percent".to_string(), "step"),
+            "setHysteresis" => ("hysteresis_temp".to_string(), "hysteresis"),
+            "setEmergencyTemp" => match payload.get("sensorType").and_then(|v| v.as_str()) {
+                Some(sensor_type) => (format!("emergency_temp_by_type.{}", sensor_type), "temp"),
+                None => ("emergency_temp".to_string(), "temp"),
+            },
+            "setLogLevel" => ("log_level".to_string(), "level"),
+            "setFailsafeSpeed" => ("failsafe_speed".to_string(), "failsafeSpeed"),
+            "setEnableFanControl" => ("enable_fan_control".to_string(), "enabled"),
+            _ => return None,
+        };
+
+        let config = self.config.load();
+        let mut report = ConfigurationApplied::new(&config.agent.id, command_type, Some(command_id));
+        let value = payload.get(key).cloned().unwrap_or(serde_json::Value::Null);
+        report.reject(&field, &value, reason);
+        Some(report.finish(&config))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_accepted_rejected_and_effective_values() {
+        let config = AgentConfig::default();
+        let mut report = ConfigurationApplied::new("linux-host-1234", "registration", None);
+        report.record("update_interval", &serde_json::json!(5), Ok(()));
+        report.record("fan_step_percent", &serde_json::json!(7), Err(anyhow!("Invalid fan step: 7")));
+
+        let message = report.finish(&config).to_message();
+        assert_eq!(message["type"], "configurationApplied");
+
+        let data = &message["data"];
+        assert_eq!(data["agentId"], "linux-host-1234");
+        assert!(data.get("commandId").is_none());
+        assert_eq!(data["accepted"], serde_json::json!(["update_interval"]));
+        assert_eq!(data["rejected"][0]["field"], "fan_step_percent");
+        assert_eq!(data["rejected"][0]["value"], 7);
+        assert_eq!(data["rejected"][0]["reason"], "Invalid fan step: 7");
+        assert_eq!(data["effective"]["fan_step_percent"], config.hardware.fan_step_percent);
+    }
+
+    #[test]
+    fn non_numeric_values_are_rejected() {
+        assert!(number(&serde_json::json!("fast")).is_err());
+        assert_eq!(number(&serde_json::json!(2.5)).unwrap(), 2.5);
+    }
+
+    #[tokio::test]
+    async fn registration_configuration_is_applied_in_order_and_saved_once() {
+        use std::sync::Arc;
+        use crate::config::handle::ConfigHandle;
+        use crate::hardware::linux::fixture::FakeSysfs;
+        use crate::websocket::client::WebSocketClient;
+
+        let sysfs = FakeSysfs::new("config-bulk");
+        let path = sysfs.root().join("config.json");
+        let mut client = WebSocketClient::new(AgentConfig::default(), Arc::new(sysfs.monitor()));
+        client.config = Arc::new(ConfigHandle::new(AgentConfig::default(), Some(path.clone())));
+
+        let report = client.apply_configuration(&serde_json::json!({
+            "update_interval": 5,
+            "fan_step_percent": 10,
+            "hysteresis_temp": 6.0,
+            "emergency_temp": 90,
+            "emergency_temp_by_type": { "nvme": 70, "gpu": 85 },
+            "log_level": "debug",
+        }), "registration", None).await;
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].field, "hysteresis_temp");
+
+        client.config.flush().await.unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(client.config.writes(), 1, "six changes, one save");
+
+        // Memory, disk and the reported effective values agree
+        let saved: AgentConfig = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        let effective = serde_json::to_value(report.effective.as_ref().unwrap()).unwrap();
+        for config in [&*client.config.load(), &saved] {
+            assert_eq!(config.agent.update_interval, 5.0);
+            assert_eq!(config.hardware.fan_step_percent, 10);
+            assert_eq!(config.hardware.hysteresis_temp, AgentConfig::default().hardware.hysteresis_temp);
+            assert_eq!(config.hardware.emergency_temp, 90.0);
+            assert_eq!(config.hardware.emergency_temp_by_type.get("nvme"), Some(&70.0));
+            assert_eq!(config.agent.log_level, "DEBUG");
+            assert_eq!(serde_json::to_value(EffectiveConfig::from(config)).unwrap(), effective);
+        }
+    }
+
+    #[tokio::test]
+    async fn partial_config_update_only_touches_the_given_fields() {
+        use std::sync::Arc;
+        use crate::config::handle::ConfigHandle;
+        use crate::hardware::linux::fixture::FakeSysfs;
+        use crate::websocket::client::WebSocketClient;
+
+        let sysfs = FakeSysfs::new("config-update");
+        let mut client = WebSocketClient::new(AgentConfig::default(), Arc::new(sysfs.monitor()));
+        client.config = Arc::new(ConfigHandle::new(AgentConfig::default(), None));
+        let before = client.config.load();
+
+        let report = client.apply_configuration(&serde_json::json!({
+            "name": "rack-02",
+            "failsafe_speed": 90,
+            "fan_curve_v2": {"points": []},
+        }), "configUpdate", Some("cmd-7")).await;
+        assert_eq!(report.accepted, ["name", "failsafe_speed"]);
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!((report.rejected[0].field.as_str(), report.rejected[0].reason.as_str()), ("fan_curve_v2", "Unknown setting"));
+        let message = report.to_message();
+        assert_eq!((message["data"]["source"].as_str(), message["data"]["commandId"].as_str()), (Some("configUpdate"), Some("cmd-7")));
+
+        // Everything else is as it was
+        let after = client.config.load();
+        assert_eq!(after.agent.name, "rack-02");
+        assert_eq!(after.hardware.failsafe_speed, 90);
+        let mut expected = AgentConfig::clone(&before);
+        expected.agent.name = "rack-02".to_string();
+        expected.hardware.failsafe_speed = 90;
+        assert_eq!(serde_json::to_value(&*after).unwrap(), serde_json::to_value(&expected).unwrap());
+
+        // A bad value is rejected without touching the rest of the update
+        let report = client.apply_configuration(&serde_json::json!({
+            "enable_fan_control": "yes",
+            "temperature_smoothing": {"mode": "exponential", "alpha": 0.5},
+        }), "configUpdate", None).await;
+        assert_eq!(report.accepted, ["temperature_smoothing"]);
+        assert_eq!(report.rejected[0].field, "enable_fan_control");
+        assert_eq!(client.config.load().hardware.enable_fan_control, before.hardware.enable_fan_control);
+    }
+}
@@ -0,0 +1,169 @@
+//! Backend reachability test for the setup wizard and `--check-connection`.
+//!
+//! Resolves the host, opens the WebSocket (over the configured interface, address
+//! or proxy, see transport.rs), exchanges one ping/pong and closes again.
+//! Nothing is registered, so the dashboard doesn't list a half-configured agent. Each
+//! stage fails with its own variant so the user sees *why* (typo'd hostname vs Hub
+//! not running vs certificate problem) instead of a generic connection error.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use futures_util::{SinkExt, StreamExt};
+use tokio::time::timeout;
+use tokio_tungstenite::tungstenite::{self, client::IntoClientRequest, protocol::Message};
+
+use super::transport::{self, Route, TransportError};
+
+#[derive(Debug)]
+pub(crate) enum ConnectionFailure {
+    /// Not a ws:// or wss:// URL with a host
+    InvalidUrl(String),
+    /// Hostname did not resolve
+    Dns(String),
+    /// Nothing listening on that port
+    Refused(String),
+    /// Certificate or TLS handshake problem (wss://)
+    Tls(String),
+    /// No answer within the connection timeout
+    Timeout(f64),
+    /// Reached a server, but it did not complete a WebSocket exchange (wrong path, proxy, ...)
+    Handshake(String),
+    /// `backend.bind_interface` / `bind_address` could not be used
+    Bind(String),
+    /// `backend.proxy_url` unreachable or refused the tunnel
+    Proxy(String),
+    Other(String),
+}
+
+impl fmt::Display for ConnectionFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidUrl(e) => write!(f, "invalid URL: {}", e),
+            Self::Dns(e) => write!(f, "DNS lookup failed: {}", e),
+            Self::Refused(e) => write!(f, "connection refused: {}", e),
+            Self::Tls(e) => write!(f, "TLS error: {}", e),
+            Self::Timeout(secs) => write!(f, "timed out after {}s", secs),
+            Self::Handshake(e) => write!(f, "WebSocket handshake failed: {}", e),
+            Self::Bind(e) => write!(f, "local bind failed: {}", e),
+            Self::Proxy(e) => write!(f, "proxy failed: {}", e),
+            Self::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+fn classify(error: tungstenite::Error) -> ConnectionFailure {
+    match error {
+        tungstenite::Error::Io(e) if e.kind() == std::io::ErrorKind::ConnectionRefused => {
+            ConnectionFailure::Refused(e.to_string())
+        }
+        // rustls handshake errors surface as io::Error wrapping rustls::Error
+        tungstenite::Error::Io(e) if e.get_ref().is_some_and(|inner| inner.is::<rustls::Error>()) => {
+            ConnectionFailure::Tls(e.to_string())
+        }
+        tungstenite::Error::Tls(e) => ConnectionFailure::Tls(e.to_string()),
+        tungstenite::Error::Url(e) => ConnectionFailure::InvalidUrl(e.to_string()),
+        tungstenite::Error::Http(response) => {
+            ConnectionFailure::Handshake(format!("server answered HTTP {}", response.status()))
+        }
+        tungstenite::Error::Protocol(e) => ConnectionFailure::Handshake(e.to_string()),
+        e => ConnectionFailure::Other(e.to_string()),
+    }
+}
+
+impl ConnectionFailure {
+    /// Failed on the agent's side of the route rather than at the Hub
+    pub(crate) fn is_route(&self) -> bool {
+        matches!(self, Self::Bind(_) | Self::Proxy(_))
+    }
+}
+
+/// Connect to `url` over `route`, ping, disconnect. Returns the round-trip time of the
+/// whole exchange. Every network step is bounded by `timeout_secs`.
+pub(crate) async fn check_connection(url: &str, route: &Route, timeout_secs: f64) -> Result<Duration, ConnectionFailure> {
+    let started = Instant::now();
+    let limit = Duration::from_secs_f64(timeout_secs);
+
+    let request = url.into_client_request().map_err(classify)?;
+    let uri = request.uri();
+    let default_port = match uri.scheme_str() {
+        Some("ws") => 80,
+        Some("wss") => 443,
+        _ => return Err(ConnectionFailure::InvalidUrl("scheme must be ws:// or wss://".to_string())),
+    };
+    let host = uri.host()
+        .ok_or_else(|| ConnectionFailure::InvalidUrl("no host".to_string()))?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_string();
+    let port = uri.port_u16().unwrap_or(default_port);
+
+    // Resolve separately: the connect folds DNS errors into a generic io::Error.
+    // Behind a proxy, the proxy resolves the Hub.
+    if route.proxy_url.is_none() {
+        let mut addrs = timeout(limit, tokio::net::lookup_host((host.as_str(), port)))
+            .await
+            .map_err(|_| ConnectionFailure::Timeout(timeout_secs))?
+            .map_err(|e| ConnectionFailure::Dns(format!("{}: {}", host, e)))?;
+        if addrs.next().is_none() {
+            return Err(ConnectionFailure::Dns(format!("{}: no addresses", host)));
+        }
+    }
+
+    let (mut ws, _) = timeout(limit, transport::connect(url, route))
+        .await
+        .map_err(|_| ConnectionFailure::Timeout(timeout_secs))?
+        .map_err(|e| match e {
+            TransportError::Bind(e) => ConnectionFailure::Bind(e),
+            TransportError::Proxy(e) => ConnectionFailure::Proxy(e),
+            TransportError::Upstream(e) => classify(e),
+        })?;
+
+    ws.send(Message::Ping(Default::default())).await.map_err(classify)?;
+    let pong = timeout(limit, async {
+        // The backend may push its own messages first; only the pong matters here
+        while let Some(message) = ws.next().await {
+            match message.map_err(classify)? {
+                Message::Pong(_) => return Ok(()),
+                Message::Close(_) => return Err(ConnectionFailure::Handshake("server closed the connection".to_string())),
+                _ => {}
+            }
+        }
+        Err(ConnectionFailure::Handshake("connection ended before the server answered".to_string()))
+    })
+    .await
+    .map_err(|_| ConnectionFailure::Timeout(timeout_secs))?;
+    let elapsed = started.elapsed();
+    let _ = ws.close(None).await;
+
+    pong.map(|_| elapsed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn failures_are_told_apart() {
+        assert!(matches!(check_connection("http://localhost:3143", &Route::default(), 2.0).await, Err(ConnectionFailure::InvalidUrl(_))));
+
+        // Bind then release a port so nothing is listening on it
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let url = format!("ws://127.0.0.1:{}/websocket", port);
+        assert!(matches!(check_connection(&url, &Route::default(), 2.0).await, Err(ConnectionFailure::Refused(_))));
+    }
+
+    #[tokio::test]
+    async fn ping_round_trip_against_a_websocket_server() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}/websocket", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut ws = tokio_tungstenite::accept_async(stream).await.unwrap();
+            // Replies to the ping while reading; runs until the client closes
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+
+        assert!(check_connection(&url, &Route::default(), 2.0).await.is_ok());
+    }
+}
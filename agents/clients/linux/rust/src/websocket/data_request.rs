@@ -0,0 +1,113 @@
+//! `requestData`: a data frame right away instead of at the next update_interval
+//! cycle, for a dashboard that is being clicked around on a slow interval.
+//!
+//! handle_command only queues the request: the data sender task owns the periodic
+//! sends, so it sends the extra frame between cycles (the schedule stays as it was)
+//! and answers the command with that frame's timestamp.
+
+use std::time::{Duration, Instant};
+
+use futures_util::SinkExt;
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tracing::{debug, warn};
+
+use super::client::WsSink;
+use super::commands::command_response;
+use super::link_quality::Measure;
+
+/// At most one forced refresh per this long.
+pub(crate) const MIN_REFRESH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A `requestData` command waiting for the data sender.
+#[derive(Debug)]
+pub(crate) struct DataRequest {
+    pub(crate) command_id: String,
+    /// When the command arrived, for the command latency in systemHealth.connection
+    pub(crate) received: Instant,
+}
+
+/// Hands `requestData` commands to the data sender of the current connection.
+#[derive(Debug, Default)]
+pub(crate) struct DataRequests {
+    sender: Option<mpsc::Sender<DataRequest>>,
+    last: Option<Instant>,
+}
+
+impl DataRequests {
+    /// A new connection: requests go to the returned receiver (its data sender) from now on.
+    pub(crate) fn connect(&mut self) -> mpsc::Receiver<DataRequest> {
+        let (sender, receiver) = mpsc::channel(1);
+        self.sender = Some(sender);
+        receiver
+    }
+
+    /// Queue a refresh, or why not.
+    pub(crate) fn request(&mut self, command_id: &str, received: Instant) -> Result<(), String> {
+        if let Some(last) = self.last.filter(|last| received.saturating_duration_since(*last) < MIN_REFRESH_INTERVAL) {
+            return Err(format!(
+                "requestData rate limited: at most one forced refresh per {}s (last {}ms ago)",
+                MIN_REFRESH_INTERVAL.as_secs(),
+                received.saturating_duration_since(last).as_millis()
+            ));
+        }
+        let sender = self.sender.as_ref().ok_or("No data sender running")?;
+        sender
+            .try_send(DataRequest { command_id: command_id.to_string(), received })
+            .map_err(|e| match e {
+                mpsc::error::TrySendError::Full(_) => "A forced refresh is already pending".to_string(),
+                mpsc::error::TrySendError::Closed(_) => "No data sender running".to_string(),
+            })?;
+        self.last = Some(received);
+        Ok(())
+    }
+}
+
+impl super::client::WebSocketClient {
+    /// Data sender: send a frame for `request` and answer it with the frame's timestamp.
+    pub(crate) async fn send_requested_data(&self, write: &mut WsSink, request: DataRequest) {
+        let response = match self.send_data(write).await {
+            Ok(timestamp) => {
+                debug!("Sent requested data frame {} for command {}", timestamp, request.command_id);
+                command_response(&request.command_id, true, None, serde_json::json!({"timestamp": timestamp}))
+            }
+            Err(e) => command_response(&request.command_id, false, Some(format!("Failed to send data: {}", e)), serde_json::json!({})),
+        };
+        self.command_cache.lock().await.insert(&request.command_id, response.clone());
+        if let Err(e) = write.send(Message::text(response.to_string())).await {
+            warn!("Failed to answer requestData command {}: {}", request.command_id, e);
+            return;
+        }
+        self.link_quality.lock().unwrap().record(Measure::Command, request.received.elapsed());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refreshes_are_queued_at_most_once_per_second() {
+        let start = Instant::now();
+        let mut requests = DataRequests::default();
+        assert_eq!(requests.request("a", start), Err("No data sender running".to_string()));
+
+        let mut receiver = requests.connect();
+        assert_eq!(requests.request("a", start), Ok(()));
+        let error = requests.request("b", start + Duration::from_millis(400)).unwrap_err();
+        assert!(error.contains("rate limited"), "{}", error);
+        assert_eq!(receiver.try_recv().unwrap().command_id, "a");
+
+        assert_eq!(requests.request("c", start + MIN_REFRESH_INTERVAL), Ok(()));
+        // Not picked up yet: the next one isn't queued behind it
+        let error = requests.request("d", start + 3 * MIN_REFRESH_INTERVAL).unwrap_err();
+        assert!(error.contains("already pending"), "{}", error);
+        assert_eq!(receiver.try_recv().unwrap().command_id, "c");
+
+        // The connection's data sender is gone
+        drop(receiver);
+        assert_eq!(requests.request("e", start + 5 * MIN_REFRESH_INTERVAL), Err("No data sender running".to_string()));
+        let _new_connection = requests.connect();
+        assert_eq!(requests.request("e", start + 5 * MIN_REFRESH_INTERVAL), Ok(()));
+    }
+}
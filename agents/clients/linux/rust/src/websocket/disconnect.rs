@@ -0,0 +1,213 @@
+//! Why the previous backend connection ended, sent as `last_disconnect` in the next
+//! registration so the backend can tell a server restart from a flaky network.
+//!
+//! A connection ends in the read loop (close frame, stream end, health timeout, read
+//! error), because the data sender gave up, or with an error while connecting or
+//! registering. Each becomes a `DisconnectCause` with a category, the message that was
+//! logged, when it happened and how long the connection had been up (`None` when it
+//! never was). Errors are categorized from their source chain.
+
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio_tungstenite::tungstenite::{self, protocol::CloseFrame};
+
+use super::transport::TransportError;
+
+/// How long without any message from the backend before the connection counts as dead.
+pub(crate) const CONNECTION_HEALTH_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum DisconnectCategory {
+    /// The backend sent a close frame
+    ServerClosed,
+    /// The stream ended without a close frame
+    StreamEnded,
+    /// No message from the backend for `CONNECTION_HEALTH_TIMEOUT`
+    HealthTimeout,
+    /// Data messages kept failing to send
+    SendError,
+    /// The backend sent a message over `backend.max_incoming_message_bytes`
+    MessageTooBig,
+    /// WebSocket protocol violation
+    ProtocolError,
+    /// Reset, broken pipe or another I/O error on an open connection
+    NetworkError,
+    /// The connection attempt didn't finish within `backend.connection_timeout`
+    ConnectTimeout,
+    /// The backend's host name didn't resolve
+    DnsError,
+    /// Nothing listening at the backend's address
+    ConnectionRefused,
+    TlsError,
+    /// The backend answered the WebSocket handshake with an HTTP error
+    HandshakeRejected,
+    /// `backend.proxy_url` unreachable or refused the tunnel
+    ProxyError,
+    /// `backend.bind_interface` / `bind_address` unusable
+    BindError,
+    /// The machine was suspended
+    Suspended,
+    /// The agent is shutting down
+    Shutdown,
+    Other,
+}
+
+/// Why a connection ended, as reported in `last_disconnect`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct DisconnectCause {
+    pub(crate) category: DisconnectCategory,
+    pub(crate) error: String,
+    /// When it ended (Unix ms)
+    pub(crate) at: i64,
+    /// How long the connection had been up; `None` if it never connected
+    pub(crate) connected_secs: Option<f64>,
+}
+
+impl DisconnectCause {
+    pub(crate) fn new(category: DisconnectCategory, error: impl Into<String>, connected_for: Option<Duration>) -> Self {
+        Self {
+            category,
+            error: error.into(),
+            at: chrono::Utc::now().timestamp_millis(),
+            connected_secs: connected_for.map(|d| (d.as_secs_f64() * 10.0).round() / 10.0),
+        }
+    }
+
+    /// A connection that failed with `error`.
+    pub(crate) fn failed(error: &anyhow::Error, connected_for: Option<Duration>) -> Self {
+        Self::new(categorize(error), format!("{:#}", error), connected_for)
+    }
+
+    /// Whether the connection ended the way connections normally do (the backend
+    /// closing it, a suspend, shutdown) rather than failing.
+    pub(crate) fn is_clean(&self) -> bool {
+        matches!(
+            self.category,
+            DisconnectCategory::ServerClosed | DisconnectCategory::Suspended | DisconnectCategory::Shutdown
+        )
+    }
+}
+
+/// The cause for a read that ended the connection: a close frame, the end of the
+/// stream or an error. `None` for messages that keep it open.
+pub(crate) fn read_ended(next: Option<&Result<tungstenite::Message, tungstenite::Error>>) -> Option<(DisconnectCategory, String)> {
+    match next {
+        None => Some((DisconnectCategory::StreamEnded, "WebSocket stream ended".to_string())),
+        Some(Ok(tungstenite::Message::Close(frame))) => Some((DisconnectCategory::ServerClosed, match frame {
+            Some(CloseFrame { code, reason }) if !reason.is_empty() => format!("Server closed connection ({}: {})", code, reason),
+            Some(CloseFrame { code, .. }) => format!("Server closed connection ({})", code),
+            None => "Server closed connection".to_string(),
+        })),
+        Some(Ok(_)) => None,
+        Some(Err(e)) => Some((categorize_websocket(e), format!("WebSocket error: {}", e))),
+    }
+}
+
+/// The cause when nothing was received for `silent`, once that is past the health timeout.
+pub(crate) fn health_check(silent: Duration) -> Option<(DisconnectCategory, String)> {
+    (silent > CONNECTION_HEALTH_TIMEOUT).then(|| {
+        (DisconnectCategory::HealthTimeout, format!("No message received for {}s", silent.as_secs()))
+    })
+}
+
+/// Category of a connection error, from the first source in its chain that says.
+pub(crate) fn categorize(error: &anyhow::Error) -> DisconnectCategory {
+    for cause in error.chain() {
+        if cause.is::<tokio::time::error::Elapsed>() {
+            return DisconnectCategory::ConnectTimeout;
+        }
+        if let Some(e) = cause.downcast_ref::<TransportError>() {
+            return match e {
+                TransportError::Bind(_) => DisconnectCategory::BindError,
+                TransportError::Proxy(_) => DisconnectCategory::ProxyError,
+                TransportError::Upstream(e) => categorize_websocket(e),
+            };
+        }
+        if let Some(e) = cause.downcast_ref::<tungstenite::Error>() {
+            return categorize_websocket(e);
+        }
+        if let Some(e) = cause.downcast_ref::<std::io::Error>() {
+            return categorize_io(e);
+        }
+    }
+    DisconnectCategory::Other
+}
+
+fn categorize_websocket(error: &tungstenite::Error) -> DisconnectCategory {
+    match error {
+        tungstenite::Error::Io(e) => categorize_io(e),
+        tungstenite::Error::Tls(_) => DisconnectCategory::TlsError,
+        tungstenite::Error::Http(_) | tungstenite::Error::HttpFormat(_) => DisconnectCategory::HandshakeRejected,
+        tungstenite::Error::Capacity(_) => DisconnectCategory::MessageTooBig,
+        tungstenite::Error::Protocol(_) | tungstenite::Error::Utf8(_) => DisconnectCategory::ProtocolError,
+        tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed => DisconnectCategory::StreamEnded,
+        _ => DisconnectCategory::Other,
+    }
+}
+
+fn categorize_io(error: &std::io::Error) -> DisconnectCategory {
+    use std::io::ErrorKind;
+    match error.kind() {
+        ErrorKind::ConnectionRefused => DisconnectCategory::ConnectionRefused,
+        ErrorKind::TimedOut => DisconnectCategory::ConnectTimeout,
+        // getaddrinfo failures carry no kind of their own; "no addresses" is transport.rs's
+        _ if error.to_string().contains("lookup address") || error.kind() == ErrorKind::NotFound => {
+            DisconnectCategory::DnsError
+        }
+        _ => DisconnectCategory::NetworkError,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::Context;
+    use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[test]
+    fn reads_that_end_the_connection_are_categorized() {
+        let close = Message::Close(Some(CloseFrame { code: CloseCode::Away, reason: "restarting".into() }));
+        let (category, error) = read_ended(Some(&Ok(close))).unwrap();
+        assert_eq!(category, DisconnectCategory::ServerClosed);
+        assert_eq!(error, "Server closed connection (1001: restarting)");
+        assert_eq!(read_ended(Some(&Ok(Message::Close(None)))).unwrap().0, DisconnectCategory::ServerClosed);
+        assert_eq!(read_ended(None).unwrap().0, DisconnectCategory::StreamEnded);
+        assert_eq!(read_ended(Some(&Ok(Message::text("{}")))), None);
+
+        let reset = tungstenite::Error::Io(std::io::Error::from(std::io::ErrorKind::ConnectionReset));
+        assert_eq!(read_ended(Some(&Err(reset))).unwrap().0, DisconnectCategory::NetworkError);
+    }
+
+    #[test]
+    fn silence_past_the_health_timeout_is_a_health_timeout() {
+        assert_eq!(health_check(Duration::from_secs(30)), None);
+        let (category, error) = health_check(Duration::from_secs(31)).unwrap();
+        assert_eq!(category, DisconnectCategory::HealthTimeout);
+        assert_eq!(error, "No message received for 31s");
+    }
+
+    #[tokio::test]
+    async fn connect_errors_are_categorized_from_their_source() {
+        // The same wrapping as connect_and_communicate
+        let elapsed = tokio::time::timeout(Duration::from_millis(1), std::future::pending::<()>()).await.unwrap_err();
+        let timeout = Err::<(), _>(elapsed).context("Connection timeout").unwrap_err();
+        let cause = DisconnectCause::failed(&timeout, None);
+        assert_eq!(cause.category, DisconnectCategory::ConnectTimeout);
+        assert_eq!(cause.connected_secs, None);
+        assert!(!cause.is_clean());
+
+        let upstream = |e: tungstenite::Error| anyhow::Error::new(TransportError::Upstream(e));
+        let io = |kind: std::io::ErrorKind| tungstenite::Error::Io(std::io::Error::from(kind));
+        assert_eq!(categorize(&upstream(io(std::io::ErrorKind::ConnectionRefused))), DisconnectCategory::ConnectionRefused);
+        let dns = std::io::Error::other("failed to lookup address information: Name or service not known");
+        assert_eq!(categorize(&upstream(tungstenite::Error::Io(dns))), DisconnectCategory::DnsError);
+        assert_eq!(categorize(&anyhow::Error::new(TransportError::Proxy("407".into()))), DisconnectCategory::ProxyError);
+        assert_eq!(categorize(&anyhow::anyhow!("Sensor discovery failed")), DisconnectCategory::Other);
+
+        let after_a_minute = DisconnectCause::failed(&upstream(io(std::io::ErrorKind::BrokenPipe)), Some(Duration::from_millis(61_234)));
+        assert_eq!((after_a_minute.category, after_a_minute.connected_secs), (DisconnectCategory::NetworkError, Some(61.2)));
+    }
+}
@@ -0,0 +1,299 @@
+//! `emergency_actions`: what the agent does when fans at 100% aren't enough.
+//!
+//! Every failsafe check (disconnected, or UDP broadcast mode) and every data cycle
+//! reports whether some sensor is in emergency with the fans at their maximum: the
+//! failsafe's own escalation while disconnected, every controllable fan reading 100%
+//! while the backend's curve is in charge. Once that has held for `after_secs` the
+//! configured actions run in order, in the background, each logged, recorded as a
+//! critical event and reported to the backend in an `emergencyAction` message. They
+//! run again only after the emergency has ended and `min_interval_minutes` have
+//! passed; the time of the last run is kept in emergency-actions.json beside the
+//! executable so a reboot doesn't reset it.
+
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use futures_util::SinkExt;
+use serde::{Deserialize, Serialize};
+use tokio_tungstenite::tungstenite::protocol::Message;
+use tracing::{error, info, warn};
+
+use super::client::{WebSocketClient, WsSink};
+use super::event_log::Severity;
+use super::protocol::FEATURE_EMERGENCY_ACTION;
+use crate::config::types::{EmergencyAction, EmergencyActions};
+use crate::hardware::types::{Fan, Sensor};
+
+pub(crate) const EMERGENCY_ACTIONS_FILE: &str = "emergency-actions.json";
+
+/// How long a shutdown waits for the reports of the actions before it to go out.
+const REPORT_FLUSH_WAIT: Duration = Duration::from_secs(5);
+
+/// Every fan the agent can control is at 100% (true when there are none).
+pub(crate) fn fans_at_max(fans: &[Fan]) -> bool {
+    fans.iter().filter(|f| f.has_pwm_control).all(|f| f.speed >= 100)
+}
+
+/// How long the current emergency has held with fans at their maximum.
+#[derive(Debug, Default)]
+pub(crate) struct EmergencyTimer {
+    since: Option<Instant>,
+    fired: bool,
+}
+
+impl EmergencyTimer {
+    /// Advance on one check. True once per emergency, when it has held for `after`.
+    pub(crate) fn observe(&mut self, held: bool, now: Instant, after: Duration) -> bool {
+        if !held {
+            *self = Self::default();
+            return false;
+        }
+        let since = *self.since.get_or_insert(now);
+        if self.fired || now.duration_since(since) < after {
+            return false;
+        }
+        self.fired = true;
+        true
+    }
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct LastRun {
+    /// Unix ms
+    last_run: Option<i64>,
+}
+
+/// Timer, rate limit and reports not yet sent to the backend.
+#[derive(Debug, Default)]
+pub(crate) struct EmergencyActionState {
+    timer: EmergencyTimer,
+    path: Option<PathBuf>,
+    last_run: Option<i64>,
+    reports: Vec<serde_json::Value>,
+}
+
+impl EmergencyActionState {
+    pub(crate) fn beside_executable() -> Self {
+        let path = std::env::current_exe().ok().and_then(|p| p.parent().map(|d| d.join(EMERGENCY_ACTIONS_FILE)));
+        Self::open(path)
+    }
+
+    fn open(path: Option<PathBuf>) -> Self {
+        let last_run = path
+            .as_deref()
+            .and_then(|p| std::fs::read_to_string(p).ok())
+            .and_then(|content| serde_json::from_str::<LastRun>(&content).ok())
+            .and_then(|saved| saved.last_run);
+        Self { path, last_run, ..Self::default() }
+    }
+
+    /// Minutes since the last run when that is within `min_interval_minutes`.
+    fn rate_limited(&self, now_ms: i64, min_interval_minutes: u64) -> Option<i64> {
+        let minutes = (now_ms - self.last_run?) / 60_000;
+        (minutes >= 0 && (minutes as u64) < min_interval_minutes).then_some(minutes)
+    }
+
+    fn record_run(&mut self, now_ms: i64) {
+        self.last_run = Some(now_ms);
+        let Some(path) = &self.path else {
+            return;
+        };
+        if let Err(e) = write_last_run(path, now_ms) {
+            warn!("Could not save the time of the emergency actions: {:#}", e);
+        }
+    }
+}
+
+fn write_last_run(path: &Path, now_ms: i64) -> Result<()> {
+    let tmp = path.with_extension("json.tmp");
+    std::fs::write(&tmp, serde_json::to_string_pretty(&LastRun { last_run: Some(now_ms) })?)
+        .with_context(|| format!("Failed to write {:?}", tmp))?;
+    std::fs::rename(&tmp, path).with_context(|| format!("Failed to write {:?}", path))?;
+    Ok(())
+}
+
+fn describe(action: &EmergencyAction) -> String {
+    match action {
+        EmergencyAction::Script { path, .. } => format!("script {}", path.display()),
+        EmergencyAction::Shutdown => "shutdown".to_string(),
+    }
+}
+
+async fn run_script(path: &Path, args: &[String], timeout_secs: u64, run_as: Option<&str>, dry_run: bool) -> Result<String> {
+    if dry_run {
+        info!("[DRY RUN] Would run {} {:?}", path.display(), args);
+        return Ok("dry run".to_string());
+    }
+    let mut command = tokio::process::Command::new(path);
+    command.args(args).stdin(std::process::Stdio::null()).kill_on_drop(true);
+    #[cfg(target_os = "linux")]
+    if let Some(name) = run_as {
+        let user = crate::daemon::privileges::lookup_user(name)?;
+        command.uid(user.uid).gid(user.gid);
+    }
+    #[cfg(not(target_os = "linux"))]
+    let _ = run_as;
+    let status = tokio::time::timeout(Duration::from_secs(timeout_secs), command.status())
+        .await
+        .map_err(|_| anyhow!("timed out after {}s and was killed", timeout_secs))?
+        .with_context(|| format!("Cannot run {}", path.display()))?;
+    if !status.success() {
+        bail!("{}", status);
+    }
+    Ok(status.to_string())
+}
+
+async fn shutdown(dry_run: bool) -> Result<String> {
+    if dry_run {
+        info!("[DRY RUN] Would run systemctl poweroff");
+        return Ok("dry run".to_string());
+    }
+    let status = tokio::process::Command::new("systemctl")
+        .arg("poweroff")
+        .status()
+        .await
+        .context("Cannot run systemctl poweroff")?;
+    if !status.success() {
+        bail!("systemctl poweroff failed: {}", status);
+    }
+    Ok("poweroff requested".to_string())
+}
+
+impl WebSocketClient {
+    /// Feed one check: `trigger` is the sensor in emergency (None when there is none),
+    /// `at_max` whether the fans are already as fast as they go.
+    pub(crate) async fn check_emergency_actions(&self, trigger: Option<&Sensor>, at_max: bool) {
+        let settings = self.config.load().emergency_actions.clone();
+        let mut state = self.emergency_actions.lock().await;
+        let held = settings.enabled && trigger.is_some() && at_max;
+        if !state.timer.observe(held, Instant::now(), Duration::from_secs(settings.after_secs)) {
+            return;
+        }
+        let Some(sensor) = trigger else {
+            return;
+        };
+        let details = serde_json::json!({ "sensor_id": sensor.id, "temperature": sensor.instant_temperature() });
+        let now_ms = chrono::Utc::now().timestamp_millis();
+        if let Some(minutes) = state.rate_limited(now_ms, settings.min_interval_minutes) {
+            drop(state);
+            warn!("🚨 {} still in emergency with fans at 100%, but the emergency actions ran {} min ago - \
+                   not again within emergency_actions.min_interval_minutes ({})",
+                  sensor.id, minutes, settings.min_interval_minutes);
+            self.record_event(Severity::Critical, "emergency_actions_skipped",
+                              format!("Emergency actions not run again ({} min since the last run)", minutes), details).await;
+            return;
+        }
+        state.record_run(now_ms);
+        drop(state);
+
+        warn!("🚨 {} in emergency for {}s with fans at 100% ({:.1}°C) - running {} emergency action(s)",
+              sensor.id, settings.after_secs, sensor.instant_temperature(), settings.actions.len());
+        let client = self.clone_for_update();
+        tokio::spawn(async move { client.run_emergency_actions(settings, details).await });
+    }
+
+    async fn run_emergency_actions(&self, settings: EmergencyActions, trigger: serde_json::Value) {
+        let dry_run = self.config.load().hardware.dry_run;
+        for action in &settings.actions {
+            let outcome = match action {
+                EmergencyAction::Script { path, args, timeout_secs, run_as } => {
+                    run_script(path, args, *timeout_secs, run_as.as_deref(), dry_run).await
+                }
+                EmergencyAction::Shutdown => {
+                    self.flush_emergency_action_reports().await;
+                    warn!("🚨 Emergency action: shutting the machine down");
+                    shutdown(dry_run).await
+                }
+            };
+            let name = describe(action);
+            let (success, result) = match outcome {
+                Ok(result) => {
+                    warn!("🚨 Emergency action {}: {}", name, result);
+                    (true, result)
+                }
+                Err(e) => {
+                    error!("🚨 Emergency action {} failed: {:#}", name, e);
+                    (false, format!("{:#}", e))
+                }
+            };
+            let report = serde_json::json!({
+                "action": name,
+                "success": success,
+                "result": result,
+                "trigger": trigger,
+                "timestamp": chrono::Utc::now().timestamp_millis(),
+            });
+            self.record_event(Severity::Critical, "emergency_action", format!("Emergency action {}: {}", name, result),
+                              report.clone()).await;
+            self.emergency_actions.lock().await.reports.push(report);
+        }
+    }
+
+    /// Give the data loop a moment to send the reports so far, when connected.
+    async fn flush_emergency_action_reports(&self) {
+        if *self.failsafe_active.read().await || !self.negotiated.read().await.is_enabled(FEATURE_EMERGENCY_ACTION) {
+            return;
+        }
+        let deadline = Instant::now() + REPORT_FLUSH_WAIT;
+        while Instant::now() < deadline && !self.emergency_actions.lock().await.reports.is_empty() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+    }
+
+    /// Send the results of emergency actions not yet reported. Kept for the next
+    /// connection if the backend doesn't take them.
+    pub(crate) async fn send_emergency_action_reports(&self, write: &mut WsSink) -> Result<()> {
+        if !self.negotiated.read().await.is_enabled(FEATURE_EMERGENCY_ACTION) {
+            return Ok(());
+        }
+        let mut state = self.emergency_actions.lock().await;
+        if state.reports.is_empty() {
+            return Ok(());
+        }
+        let agent_id = self.config.load().agent.id.clone();
+        while let Some(report) = state.reports.first() {
+            let payload = serde_json::json!({ "type": "emergencyAction", "data": { "agentId": agent_id, "report": report } });
+            write.send(Message::text(payload.to_string())).await?;
+            state.reports.remove(0);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fires_once_per_emergency_after_the_delay() {
+        let after = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut timer = EmergencyTimer::default();
+        assert!(!timer.observe(true, start, after));
+        assert!(!timer.observe(true, start + Duration::from_secs(59), after));
+        assert!(timer.observe(true, start + Duration::from_secs(60), after));
+        assert!(!timer.observe(true, start + Duration::from_secs(120), after));
+
+        // A reading out of emergency (or a fan below 100%) starts the wait over
+        assert!(!timer.observe(false, start + Duration::from_secs(121), after));
+        assert!(!timer.observe(true, start + Duration::from_secs(122), after));
+        assert!(timer.observe(true, start + Duration::from_secs(182), after));
+    }
+
+    #[test]
+    fn last_run_survives_a_restart() {
+        let path = std::env::temp_dir().join(format!("pankha-emergency-actions-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let now_ms = 1_760_601_234_000;
+
+        let mut state = EmergencyActionState::open(Some(path.clone()));
+        assert_eq!(state.rate_limited(now_ms, 60), None);
+        state.record_run(now_ms);
+
+        let restarted = EmergencyActionState::open(Some(path.clone()));
+        assert_eq!(restarted.rate_limited(now_ms + 5 * 60_000, 60), Some(5));
+        assert_eq!(restarted.rate_limited(now_ms + 60 * 60_000, 60), None);
+        let _ = std::fs::remove_file(&path);
+    }
+}
@@ -0,0 +1,143 @@
+//! Bounded in-memory log of notable agent events (failsafe, emergencies, fan write
+//! failures, reconnects, applied config, hardware changes), served by `getEvents`.
+//! The most recent warnings and criticals also go into registration, so the backend
+//! learns what happened while it wasn't listening.
+//!
+//! Events are recorded next to the existing tracing calls, not instead of them.
+//! Lives on the WebSocketClient: survives reconnects, not restarts.
+
+use std::collections::VecDeque;
+
+use serde::Serialize;
+
+/// How many events are kept; the oldest are dropped first.
+pub(crate) const EVENT_LOG_CAPACITY: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub(crate) struct Event {
+    /// Unix ms of the first occurrence
+    pub(crate) timestamp: i64,
+    /// Unix ms of the latest occurrence; differs from `timestamp` once repeated
+    pub(crate) last_seen: i64,
+    /// Consecutive identical events collapsed into this entry
+    pub(crate) count: u32,
+    pub(crate) severity: Severity,
+    /// Machine-readable event type, e.g. "failsafe_entered"
+    pub(crate) kind: &'static str,
+    pub(crate) message: String,
+    /// Structured payload; the latest occurrence's when repeated
+    pub(crate) details: serde_json::Value,
+}
+
+#[derive(Debug)]
+pub(crate) struct EventLog {
+    capacity: usize,
+    /// Oldest first
+    events: VecDeque<Event>,
+}
+
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new(EVENT_LOG_CAPACITY)
+    }
+}
+
+impl EventLog {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), events: VecDeque::new() }
+    }
+
+    /// Append an event. One identical to the newest entry (same severity, kind and
+    /// message) only bumps its count, so a condition that persists across data cycles
+    /// doesn't flush the rest of the log.
+    pub(crate) fn record(
+        &mut self,
+        now_ms: i64,
+        severity: Severity,
+        kind: &'static str,
+        message: String,
+        details: serde_json::Value,
+    ) {
+        if let Some(last) = self.events.back_mut() {
+            if last.severity == severity && last.kind == kind && last.message == message {
+                last.count = last.count.saturating_add(1);
+                last.last_seen = now_ms;
+                last.details = details;
+                return;
+            }
+        }
+
+        self.events.push_back(Event { timestamp: now_ms, last_seen: now_ms, count: 1, severity, kind, message, details });
+        while self.events.len() > self.capacity {
+            self.events.pop_front();
+        }
+    }
+
+    /// Events that occurred (or repeated) at or after `since_ms`, oldest first.
+    pub(crate) fn since(&self, since_ms: Option<i64>) -> Vec<Event> {
+        self.events.iter()
+            .filter(|e| since_ms.is_none_or(|since| e.last_seen >= since))
+            .cloned()
+            .collect()
+    }
+
+    /// The newest `limit` warning/critical events, oldest first.
+    pub(crate) fn recent_high_severity(&self, limit: usize) -> Vec<Event> {
+        let mut recent: Vec<Event> = self.events.iter().rev()
+            .filter(|e| e.severity >= Severity::Warning)
+            .take(limit)
+            .cloned()
+            .collect();
+        recent.reverse();
+        recent
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(log: &mut EventLog, now_ms: i64, severity: Severity, kind: &'static str, message: &str) {
+        log.record(now_ms, severity, kind, message.to_string(), serde_json::json!({}));
+    }
+
+    #[test]
+    fn repeats_collapse_and_capacity_drops_oldest() {
+        let mut log = EventLog::new(3);
+        record(&mut log, 1, Severity::Warning, "failsafe_entered", "Backend disconnected");
+        record(&mut log, 2, Severity::Critical, "crit_alarm", "Crit alarm on cpu");
+        record(&mut log, 3, Severity::Critical, "crit_alarm", "Crit alarm on cpu");
+        record(&mut log, 4, Severity::Info, "connected", "Connected");
+
+        let events = log.since(None);
+        assert_eq!(events.len(), 3);
+        assert_eq!((events[1].count, events[1].timestamp, events[1].last_seen), (2, 2, 3));
+
+        record(&mut log, 5, Severity::Info, "failsafe_exited", "Backend connection restored");
+        let kinds: Vec<_> = log.since(None).iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, ["crit_alarm", "connected", "failsafe_exited"]);
+    }
+
+    #[test]
+    fn since_and_recent_high_severity_filters() {
+        let mut log = EventLog::default();
+        record(&mut log, 10, Severity::Warning, "failsafe_entered", "Backend disconnected");
+        record(&mut log, 20, Severity::Critical, "emergency", "Emergency on cpu");
+        record(&mut log, 30, Severity::Info, "failsafe_exited", "Backend connection restored");
+        record(&mut log, 40, Severity::Warning, "fan_write_failed", "Failed to set fan1");
+
+        assert_eq!(log.since(Some(20)).len(), 3);
+        assert!(log.since(Some(41)).is_empty());
+
+        let recent: Vec<_> = log.recent_high_severity(2).iter().map(|e| e.kind).collect();
+        assert_eq!(recent, ["emergency", "fan_write_failed"]);
+    }
+}
@@ -0,0 +1,120 @@
+//! Failsafe emergency state machine: escalation to 100% and hysteresis-based release.
+//!
+//! While disconnected, each failsafe check reports whether any sensor reached its
+//! emergency threshold (`tripped`) and whether every sensor is back below
+//! threshold − `hysteresis_temp` (`cooled`). Fans go to 100% on a trip and return to
+//! `failsafe_speed` only after `failsafe_release_checks` consecutive cooled checks.
+
+/// Where the offline failsafe currently stands.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FailsafeState {
+    /// Fans at `failsafe_speed`
+    Normal,
+    /// Fans at 100%; some sensor is above its release point
+    Emergency,
+    /// Fans still at 100%; `clear_checks` consecutive checks below the release point
+    Cooldown { clear_checks: u32 },
+}
+
+/// What the caller should do with the fans after a check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FailsafeAction {
+    None,
+    /// Entered `Emergency`: drive all fans to 100%
+    Escalate,
+    /// Cooldown finished: back to `failsafe_speed`
+    Release,
+}
+
+#[derive(Debug)]
+pub(crate) struct FailsafeController {
+    state: FailsafeState,
+}
+
+impl FailsafeController {
+    pub(crate) fn new() -> Self {
+        Self { state: FailsafeState::Normal }
+    }
+
+    pub(crate) fn state(&self) -> FailsafeState {
+        self.state
+    }
+
+    /// Back to `Normal` (entering or leaving failsafe mode).
+    pub(crate) fn reset(&mut self) {
+        self.state = FailsafeState::Normal;
+    }
+
+    /// Advance on one failsafe check. `release_checks` of 0 is treated as 1.
+    pub(crate) fn observe(&mut self, tripped: bool, cooled: bool, release_checks: u32) -> FailsafeAction {
+        let release_checks = release_checks.max(1);
+        let (next, action) = match self.state {
+            FailsafeState::Normal if tripped => (FailsafeState::Emergency, FailsafeAction::Escalate),
+            FailsafeState::Normal => (FailsafeState::Normal, FailsafeAction::None),
+            // Fans are already at 100%; any reading above the release point restarts the count
+            _ if tripped || !cooled => (FailsafeState::Emergency, FailsafeAction::None),
+            FailsafeState::Emergency => Self::count_clear(1, release_checks),
+            FailsafeState::Cooldown { clear_checks } => Self::count_clear(clear_checks + 1, release_checks),
+        };
+        self.state = next;
+        action
+    }
+
+    fn count_clear(clear_checks: u32, release_checks: u32) -> (FailsafeState, FailsafeAction) {
+        if clear_checks >= release_checks {
+            (FailsafeState::Normal, FailsafeAction::Release)
+        } else {
+            (FailsafeState::Cooldown { clear_checks }, FailsafeAction::None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escalates_once_and_releases_after_consecutive_cool_checks() {
+        let mut controller = FailsafeController::new();
+        assert_eq!(controller.observe(false, true, 3), FailsafeAction::None);
+        assert_eq!(controller.state(), FailsafeState::Normal);
+
+        assert_eq!(controller.observe(true, false, 3), FailsafeAction::Escalate);
+        assert_eq!(controller.observe(true, false, 3), FailsafeAction::None);
+        assert_eq!(controller.state(), FailsafeState::Emergency);
+
+        assert_eq!(controller.observe(false, true, 3), FailsafeAction::None);
+        assert_eq!(controller.observe(false, true, 3), FailsafeAction::None);
+        assert_eq!(controller.state(), FailsafeState::Cooldown { clear_checks: 2 });
+        assert_eq!(controller.observe(false, true, 3), FailsafeAction::Release);
+        assert_eq!(controller.state(), FailsafeState::Normal);
+    }
+
+    #[test]
+    fn hysteresis_band_restarts_cooldown() {
+        let mut controller = FailsafeController::new();
+        controller.observe(true, false, 2);
+        controller.observe(false, true, 2);
+        assert_eq!(controller.state(), FailsafeState::Cooldown { clear_checks: 1 });
+
+        // Below the trip point but not below threshold - hysteresis: still hot
+        assert_eq!(controller.observe(false, false, 2), FailsafeAction::None);
+        assert_eq!(controller.state(), FailsafeState::Emergency);
+
+        // A re-trip during cooldown doesn't escalate again (fans already at 100%)
+        controller.observe(false, true, 2);
+        assert_eq!(controller.observe(true, false, 2), FailsafeAction::None);
+        assert_eq!(controller.state(), FailsafeState::Emergency);
+    }
+
+    #[test]
+    fn zero_release_checks_releases_on_first_cool_check() {
+        let mut controller = FailsafeController::new();
+        controller.observe(true, false, 0);
+        assert_eq!(controller.observe(false, true, 0), FailsafeAction::Release);
+
+        controller.observe(true, false, 0);
+        controller.reset();
+        assert_eq!(controller.state(), FailsafeState::Normal);
+    }
+}
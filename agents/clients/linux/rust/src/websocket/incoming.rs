@@ -0,0 +1,134 @@
+//! Limits on what the backend can send: message size and JSON nesting.
+//!
+//! The size limit (`backend.max_incoming_message_bytes`) is enforced by tungstenite
+//! while reading, from the frame header, so an oversized message is never buffered.
+//! Its remaining bytes can't be skipped, so the connection is closed with 1009
+//! (message too big) and the usual reconnect follows. Nesting is checked with a scan
+//! of the raw text before serde_json builds anything; a message that is too deep or
+//! not JSON is dropped and the connection carries on.
+
+use std::fmt;
+
+use serde_json::Value;
+use tokio_tungstenite::tungstenite::protocol::WebSocketConfig;
+
+/// Deepest JSON accepted from the backend. Its messages nest about 6 levels.
+pub(crate) const MAX_JSON_DEPTH: usize = 64;
+
+/// tungstenite settings for a backend connection; `max_message_bytes` 0 = no limit.
+pub(crate) fn websocket_config(max_message_bytes: usize) -> WebSocketConfig {
+    let limit = (max_message_bytes > 0).then_some(max_message_bytes);
+    WebSocketConfig::default().max_message_size(limit).max_frame_size(limit)
+}
+
+/// Why an incoming text message was dropped.
+#[derive(Debug)]
+pub(crate) enum Rejected {
+    TooDeep,
+    Malformed(serde_json::Error),
+}
+
+impl fmt::Display for Rejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooDeep => write!(f, "JSON nested deeper than {} levels", MAX_JSON_DEPTH),
+            Self::Malformed(e) => write!(f, "not valid JSON: {}", e),
+        }
+    }
+}
+
+/// Parse an incoming text message, refusing nesting beyond `MAX_JSON_DEPTH` first.
+pub(crate) fn parse(text: &str) -> Result<Value, Rejected> {
+    if nested_deeper_than(text.as_bytes(), MAX_JSON_DEPTH) {
+        return Err(Rejected::TooDeep);
+    }
+    serde_json::from_str(text).map_err(Rejected::Malformed)
+}
+
+/// Whether brackets outside strings open more than `max` levels. Stops at the first
+/// level too many; malformed input is left to the parser.
+fn nested_deeper_than(bytes: &[u8], max: usize) -> bool {
+    let mut depth = 0usize;
+    let mut in_string = false;
+    let mut escaped = false;
+    for &byte in bytes {
+        if in_string {
+            match byte {
+                _ if escaped => escaped = false,
+                b'\\' => escaped = true,
+                b'"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match byte {
+            b'"' => in_string = true,
+            b'[' | b'{' => {
+                depth += 1;
+                if depth > max {
+                    return true;
+                }
+            }
+            b']' | b'}' => depth = depth.saturating_sub(1),
+            _ => {}
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn nesting_is_capped_before_parsing() {
+        let nested = |depth: usize| format!("{}{}", "[".repeat(depth), "]".repeat(depth));
+        assert!(parse(&nested(MAX_JSON_DEPTH)).is_ok());
+        assert!(matches!(parse(&nested(MAX_JSON_DEPTH + 1)), Err(Rejected::TooDeep)));
+        // A JSON bomb: rejected at the first level too many, without parsing the rest
+        assert!(matches!(parse(&format!("{}1", "{\"a\":".repeat(1_000_000))), Err(Rejected::TooDeep)));
+
+        // Brackets inside strings, escaped quotes included, don't count
+        let command = format!(
+            r#"{{"type":"command","data":{{"type":"setAgentName","payload":{{"name":"{}\"{}"}}}}}}"#,
+            "[".repeat(200), "{".repeat(200)
+        );
+        assert_eq!(parse(&command).unwrap()["data"]["type"], "setAgentName");
+
+        // Unbalanced closers don't go negative and hide later nesting
+        assert!(matches!(parse(&format!("{}{}", "]".repeat(100), nested(MAX_JSON_DEPTH + 1))), Err(Rejected::TooDeep)));
+    }
+
+    #[test]
+    fn pathological_messages_are_rejected_without_panicking() {
+        for text in [
+            "", " ", "{", "}", "[", "\"", "\"\\", "{\"type\":", "{\"type\":\"command\"", "nul", "1e999999",
+            "{\"a\":\"\\uD800\"}", "{\"a\":1,}", "\u{feff}{}", "\0", &"9".repeat(100_000),
+        ] {
+            let _ = parse(text);
+        }
+        assert!(matches!(parse("{\"type\":"), Err(Rejected::Malformed(_))));
+
+        // Deterministic pseudo-random inputs over the JSON alphabet
+        let alphabet = b"{}[]\":,\\ 0-1.eE+truefalsnl\xc3\xa9";
+        let mut state: u64 = 0x2545_f491_4f6c_dd1d;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+        for _ in 0..2_000 {
+            let len = (next() % 512) as usize;
+            let bytes: Vec<u8> = (0..len).map(|_| alphabet[(next() % alphabet.len() as u64) as usize]).collect();
+            // Only a panic or a hang fails the test
+            let _ = parse(&String::from_utf8_lossy(&bytes));
+        }
+    }
+
+    #[test]
+    fn zero_means_no_size_limit() {
+        assert_eq!(websocket_config(0).max_message_size, None);
+        assert_eq!(websocket_config(1 << 20).max_frame_size, Some(1 << 20));
+    }
+}
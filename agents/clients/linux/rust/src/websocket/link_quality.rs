@@ -0,0 +1,166 @@
+//! Backend connection quality: latencies over the last five minutes, sent in every
+//! data message as `systemHealth.connection`.
+//!
+//! Four measurements: the round trip of WebSocket pings the agent sends every
+//! `PING_INTERVAL` (answered by the backend's WebSocket stack, not its code); the time
+//! from receiving a backend `ping` to our `pong` being sent; the time from receiving a
+//! command to its response being sent; and how long sending a data message takes.
+//! Slow sends and round trips point at the network, slow pongs and commands at a busy
+//! agent. When the p95 round trip or send time passes `backend.latency_warn_ms` the
+//! connection is `degraded`, which is logged once, and again when it recovers.
+
+use std::time::{Duration, Instant};
+
+use crate::app::rolling_window::RollingWindow;
+use crate::hardware::types::{ConnectionQuality, LatencyStats};
+
+/// Time between the agent's WebSocket pings.
+pub(crate) const PING_INTERVAL: Duration = Duration::from_secs(10);
+
+const WINDOW: Duration = Duration::from_secs(300);
+
+/// Samples kept per measurement; commands in a burst can outnumber the rest.
+const CAPACITY: usize = 2048;
+
+/// Samples a p95 needs before it can mark the connection degraded.
+const MIN_SAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Measure {
+    PongDelay,
+    Command,
+    Send,
+}
+
+#[derive(Debug)]
+pub(crate) struct LinkQuality {
+    ping_rtt: RollingWindow,
+    pong_delay: RollingWindow,
+    command: RollingWindow,
+    send: RollingWindow,
+    /// Sequence number and send time of the ping awaiting its pong
+    ping_out: Option<(u64, Instant)>,
+    last_ping: Option<Instant>,
+    degraded: bool,
+}
+
+impl Default for LinkQuality {
+    fn default() -> Self {
+        let window = || RollingWindow::new(WINDOW, CAPACITY);
+        Self {
+            ping_rtt: window(),
+            pong_delay: window(),
+            command: window(),
+            send: window(),
+            ping_out: None,
+            last_ping: None,
+            degraded: false,
+        }
+    }
+}
+
+fn millis(elapsed: Duration) -> f64 {
+    (elapsed.as_secs_f64() * 10_000.0).round() / 10.0
+}
+
+fn stats(window: &mut RollingWindow, now: Instant) -> Option<LatencyStats> {
+    window.percentiles(now).map(|p| LatencyStats { p50: p.p50, p95: p.p95, samples: p.samples })
+}
+
+impl LinkQuality {
+    pub(crate) fn record(&mut self, measure: Measure, elapsed: Duration) {
+        let window = match measure {
+            Measure::PongDelay => &mut self.pong_delay,
+            Measure::Command => &mut self.command,
+            Measure::Send => &mut self.send,
+        };
+        window.record(Instant::now(), millis(elapsed));
+    }
+
+    /// A new connection: a ping sent on the old one won't be answered.
+    pub(crate) fn connected(&mut self) {
+        self.ping_out = None;
+        self.last_ping = None;
+    }
+
+    /// Payload of the next ping, when one is due. An unanswered ping is given up.
+    pub(crate) fn ping_due(&mut self, now: Instant) -> Option<Vec<u8>> {
+        if self.last_ping.is_some_and(|at| now.duration_since(at) < PING_INTERVAL) {
+            return None;
+        }
+        let seq = self.ping_out.map_or(0, |(seq, _)| seq) + 1;
+        self.last_ping = Some(now);
+        self.ping_out = Some((seq, now));
+        Some(seq.to_be_bytes().to_vec())
+    }
+
+    pub(crate) fn pong(&mut self, payload: &[u8], now: Instant) {
+        let Ok(bytes) = <[u8; 8]>::try_from(payload) else {
+            return;
+        };
+        if let Some((_, sent)) = self.ping_out.filter(|(seq, _)| *seq == u64::from_be_bytes(bytes)) {
+            self.ping_rtt.record(now, millis(now.duration_since(sent)));
+            self.ping_out = None;
+        }
+    }
+
+    /// The figures for a data message (None before anything was measured), and
+    /// Some(degraded) when the connection just became or stopped being degraded.
+    /// `warn_ms` of 0 never marks it degraded.
+    pub(crate) fn report(&mut self, now: Instant, warn_ms: u64) -> (Option<ConnectionQuality>, Option<bool>) {
+        let mut quality = ConnectionQuality {
+            ping_rtt_ms: stats(&mut self.ping_rtt, now),
+            pong_delay_ms: stats(&mut self.pong_delay, now),
+            command_ms: stats(&mut self.command, now),
+            send_ms: stats(&mut self.send, now),
+            degraded: false,
+        };
+        quality.degraded = warn_ms > 0
+            && [quality.ping_rtt_ms, quality.send_ms]
+                .iter()
+                .flatten()
+                .any(|s| s.samples >= MIN_SAMPLES && s.p95 > warn_ms as f64);
+        let change = (quality.degraded != self.degraded).then_some(quality.degraded);
+        self.degraded = quality.degraded;
+
+        let measured = quality != ConnectionQuality::default();
+        (measured.then_some(quality), change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ping_round_trips_mark_the_link_degraded_and_recovered() {
+        let start = Instant::now();
+        let mut link = LinkQuality::default();
+        assert_eq!(link.report(start, 500), (None, None));
+
+        // Pongs to an older ping, or with a foreign payload, don't count
+        let stale = link.ping_due(start).unwrap();
+        assert_eq!(link.ping_due(start + Duration::from_secs(1)), None);
+        let mut now = start + PING_INTERVAL;
+        link.ping_due(now).unwrap();
+        link.pong(&stale, now);
+        link.pong(b"keepalive", now);
+        assert_eq!(link.report(now, 500).0, None);
+
+        // A 800ms round trip, five pings in a row
+        for _ in 0..5 {
+            now += PING_INTERVAL;
+            let payload = link.ping_due(now).unwrap();
+            link.pong(&payload, now + Duration::from_millis(800));
+        }
+        let (quality, change) = link.report(now, 500);
+        assert_eq!(change, Some(true));
+        let quality = quality.unwrap();
+        assert!(quality.degraded);
+        assert_eq!(quality.ping_rtt_ms, Some(LatencyStats { p50: 800.0, p95: 800.0, samples: 5 }));
+        assert_eq!(link.report(now, 500).1, None);
+
+        // Reported once more when it's back
+        assert_eq!(link.report(now + WINDOW + PING_INTERVAL, 500), (None, Some(false)));
+    }
+}
@@ -0,0 +1,124 @@
+//! Connection and failsafe history, reported in the `status` block of every data
+//! message so the backend learns what happened while the agent was offline.
+//!
+//! Field names carry their reset semantics: `process_*` counts since the agent
+//! started, `last_outage_*` describes the most recent (or current) period without a
+//! backend and is reset when the next one begins.
+
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+pub(crate) struct LinkStatus {
+    /// Unix ms when failsafe was last entered
+    failsafe_entered_at: Option<i64>,
+    /// Start of the current outage; cleared on reconnect
+    disconnected_at: Option<Instant>,
+    last_outage_duration_secs: Option<f64>,
+    last_outage_emergency_at: Option<i64>,
+    /// Hottest non-hidden sensor seen by failsafe checks after the emergency tripped
+    last_outage_peak_temp: Option<f64>,
+    process_reconnect_count: u64,
+    connected_before: bool,
+    /// Unix ms of the last resume from suspend, and how long the machine slept
+    last_resume_at: Option<i64>,
+    last_suspend_duration_secs: Option<f64>,
+    process_resume_count: u64,
+}
+
+impl LinkStatus {
+    /// Backend lost (or never reached): a new outage begins.
+    pub(crate) fn outage_started(&mut self, now_ms: i64) {
+        self.failsafe_entered_at = Some(now_ms);
+        self.disconnected_at = Some(Instant::now());
+        self.last_outage_duration_secs = None;
+        self.last_outage_emergency_at = None;
+        self.last_outage_peak_temp = None;
+    }
+
+    /// Backend connection established.
+    pub(crate) fn connected(&mut self) {
+        if self.connected_before {
+            self.process_reconnect_count += 1;
+        }
+        self.connected_before = true;
+        if let Some(since) = self.disconnected_at.take() {
+            self.last_outage_duration_secs = Some(since.elapsed().as_secs_f64());
+        }
+    }
+
+    /// The machine resumed from suspend (explains a gap in the data).
+    pub(crate) fn resumed(&mut self, now_ms: i64, suspended: Duration) {
+        self.last_resume_at = Some(now_ms);
+        self.last_suspend_duration_secs = Some(suspended.as_secs_f64());
+        self.process_resume_count += 1;
+    }
+
+    /// The offline emergency override fired.
+    pub(crate) fn emergency_triggered(&mut self, now_ms: i64, temperature: f64) {
+        self.last_outage_emergency_at.get_or_insert(now_ms);
+        self.record_temperature(temperature);
+    }
+
+    /// Track the peak while an emergency of this outage is (or was) in progress.
+    pub(crate) fn record_temperature(&mut self, temperature: f64) {
+        if self.last_outage_emergency_at.is_some() {
+            let peak = self.last_outage_peak_temp.get_or_insert(temperature);
+            *peak = peak.max(temperature);
+        }
+    }
+
+    pub(crate) fn to_json(&self, failsafe_active: bool) -> serde_json::Value {
+        serde_json::json!({
+            "failsafe_active": failsafe_active,
+            "failsafe_entered_at": self.failsafe_entered_at,
+            "last_outage_duration_secs": self.last_outage_duration_secs
+                .map(|secs| (secs * 10.0).round() / 10.0),
+            "last_outage_emergency": {
+                "triggered": self.last_outage_emergency_at.is_some(),
+                "triggered_at": self.last_outage_emergency_at,
+                "peak_temp": self.last_outage_peak_temp,
+            },
+            "process_reconnect_count": self.process_reconnect_count,
+            "last_resume_at": self.last_resume_at,
+            "last_suspend_duration_secs": self.last_suspend_duration_secs.map(|secs| secs.round()),
+            "process_resume_count": self.process_resume_count,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summarizes_the_last_outage() {
+        let mut status = LinkStatus::default();
+        status.connected();
+        assert_eq!(status.to_json(false)["process_reconnect_count"], 0);
+
+        status.outage_started(1_000);
+        status.record_temperature(80.0); // before the trip: not a peak
+        status.emergency_triggered(2_000, 91.0);
+        status.record_temperature(94.5);
+        status.emergency_triggered(3_000, 92.0);
+        status.connected();
+
+        let json = status.to_json(false);
+        assert_eq!(json["failsafe_entered_at"], 1_000);
+        assert_eq!(json["process_reconnect_count"], 1);
+        assert!(json["last_outage_duration_secs"].is_number());
+        assert_eq!(json["last_outage_emergency"]["triggered_at"], 2_000);
+        assert_eq!(json["last_outage_emergency"]["peak_temp"], 94.5);
+
+        // The next outage starts clean
+        status.outage_started(5_000);
+        let json = status.to_json(true);
+        assert_eq!(json["last_outage_emergency"]["triggered"], false);
+        assert!(json["last_outage_duration_secs"].is_null());
+
+        status.resumed(9_000, Duration::from_millis(3_600_400));
+        let json = status.to_json(false);
+        assert_eq!((json["last_resume_at"].as_i64(), json["last_suspend_duration_secs"].as_f64()), (Some(9_000), Some(3600.0)));
+        assert_eq!(json["process_resume_count"], 1);
+    }
+}
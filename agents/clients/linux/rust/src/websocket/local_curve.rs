@@ -0,0 +1,299 @@
+//! `hardware.local_curves`: fan curves the agent runs itself, while the backend that
+//! normally runs them is unreachable (failsafe) and with udp-broadcast, which has none.
+//!
+//! Each curve drives its fans from one source: a sensor (`source_sensor`) or the
+//! hottest of a set (`source_aggregate`). Every check takes a fan the way the
+//! backend's curves do: source temperature → curve → hysteresis (the target is only
+//! recalculated once the temperature has moved `hysteresis_temp` from where it was
+//! last set) → steps of at most `fan_step_percent` per check → the curve's
+//! `min_speed`. A source sensor that isn't reported falls back to the hottest sensor.
+//! Excluded sensors are never a source.
+//!
+//! The engine only turns readings into speeds; the failsafe check writes them, and
+//! keeps emergencies at 100% (failsafe.rs). Fans without a curve stay at
+//! `failsafe_speed`.
+
+use std::collections::{HashMap, HashSet};
+
+use tracing::{debug, info, warn};
+
+use super::client::WebSocketClient;
+use crate::config::types::{CurveSource, HardwareSettings, LocalCurve};
+use crate::hardware::types::{FanSpeedOrigin, Sensor};
+
+/// Where one fan's curve stands.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct FanState {
+    /// Temperature the target was last calculated at
+    significant_temp: f64,
+    target: f64,
+    /// Last commanded speed
+    speed: u8,
+}
+
+/// Outcome of one check.
+#[derive(Debug, Default, PartialEq)]
+pub(crate) struct Evaluation {
+    /// Fans whose speed changed (or that were never set since the last reset)
+    pub(crate) changed: Vec<(String, u8)>,
+    /// Source sensors that just went missing, with their fans now on the hottest sensor
+    pub(crate) missing: Vec<String>,
+    /// Source sensors that are back
+    pub(crate) found: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct CurveEngine {
+    fans: HashMap<String, FanState>,
+    /// Source sensors currently missing
+    missing: HashSet<String>,
+}
+
+impl CurveEngine {
+    /// Forget every fan's speed: the next check starts over from `failsafe_speed`
+    /// (entering failsafe, or after an emergency held the fans at 100%).
+    pub(crate) fn reset(&mut self) {
+        self.fans.clear();
+    }
+
+    /// Run every curve on `sensors`.
+    pub(crate) fn evaluate(&mut self, sensors: &[Sensor], hardware: &HardwareSettings) -> Evaluation {
+        let mut evaluation = Evaluation::default();
+        // A stale virtual sensor is an old value: its curve follows the hottest instead
+        let sensors: Vec<&Sensor> = sensors
+            .iter()
+            .filter(|s| !s.stale && !hardware.excluded_sensors.iter().any(|id| *id == *s.id))
+            .collect();
+        let hottest = max_temperature(sensors.iter().copied());
+
+        let mut missing = HashSet::new();
+        for curve in &hardware.local_curves {
+            // Validated with the config
+            let Ok(source) = curve.source() else { continue };
+            let temperature = match &source {
+                CurveSource::Sensor(id) => match sensors.iter().find(|s| *s.id == **id) {
+                    Some(sensor) => Some(sensor.temperature),
+                    None => {
+                        missing.insert(id.clone());
+                        hottest
+                    }
+                },
+                CurveSource::MaxAll => hottest,
+                CurveSource::MaxOfType(sensor_type) => max_temperature(sensors.iter().copied().filter(|s| *s.sensor_type == **sensor_type)),
+            };
+            // Nothing to follow: the fans keep their speed
+            let Some(temperature) = temperature else { continue };
+            for fan in &curve.fans {
+                if let Some(speed) = self.step(fan, curve, temperature, hardware) {
+                    evaluation.changed.push((fan.clone(), speed));
+                }
+            }
+        }
+
+        evaluation.missing = missing.difference(&self.missing).cloned().collect();
+        evaluation.found = self.missing.difference(&missing).cloned().collect();
+        evaluation.missing.sort();
+        evaluation.found.sort();
+        self.missing = missing;
+        evaluation
+    }
+
+    /// Advance one fan; its new speed if it changed.
+    fn step(&mut self, fan: &str, curve: &LocalCurve, temperature: f64, hardware: &HardwareSettings) -> Option<u8> {
+        let previous = self.fans.get(fan).copied();
+        let (significant_temp, target) = match previous {
+            Some(state) if hardware.hysteresis_temp > 0.0 && (temperature - state.significant_temp).abs() < hardware.hysteresis_temp => {
+                (state.significant_temp, state.target)
+            }
+            _ => (temperature, interpolate(&curve.points, temperature)),
+        };
+
+        let current = previous.map_or(hardware.failsafe_speed, |state| state.speed);
+        let target = target.round().clamp(0.0, 100.0) as u8;
+        let step = hardware.fan_step_percent.max(1);
+        let stepped = if step >= 100 || current.abs_diff(target) <= step {
+            target
+        } else if target > current {
+            current + step
+        } else {
+            current - step
+        };
+        let speed = stepped.max(curve.min_speed.min(100));
+
+        self.fans.insert(fan.to_string(), FanState { significant_temp, target: f64::from(target), speed });
+        (previous.map(|state| state.speed) != Some(speed)).then_some(speed)
+    }
+}
+
+impl WebSocketClient {
+    /// One failsafe check of the local curves: write the fans whose speed changed.
+    pub(crate) async fn apply_local_curves(&self, sensors: &[Sensor], hardware: &HardwareSettings) {
+        let evaluation = self.local_curves.lock().await.evaluate(sensors, hardware);
+        for sensor_id in &evaluation.missing {
+            warn!("Local curve source {} is not reported - its fans follow the hottest sensor", sensor_id);
+        }
+        for sensor_id in &evaluation.found {
+            info!("Local curve source {} is reported again", sensor_id);
+        }
+        if evaluation.changed.is_empty() || self.fan_writes_blocked().await || !self.local_fan_control() {
+            return;
+        }
+        for (fan_id, speed) in evaluation.changed {
+            match self.hardware_monitor.set_fan_speed(&fan_id, speed, &FanSpeedOrigin::LocalCurve).await {
+                Ok(()) => debug!("Local curve: fan {} to {}%", fan_id, speed),
+                Err(e) => warn!("Local curve: failed to set fan {} to {}%: {}", fan_id, speed, e),
+            }
+        }
+    }
+}
+
+fn max_temperature<'a>(sensors: impl Iterator<Item = &'a Sensor>) -> Option<f64> {
+    sensors.map(|s| s.temperature).reduce(f64::max)
+}
+
+/// Speed (%) of a curve of [°C, %] `points` at `temperature`: linear between points,
+/// flat beyond the first and last.
+fn interpolate(points: &[(f64, u8)], temperature: f64) -> f64 {
+    let Some(&(first_temp, first_speed)) = points.first() else { return 100.0 };
+    if temperature <= first_temp {
+        return f64::from(first_speed);
+    }
+    for pair in points.windows(2) {
+        let ((t0, s0), (t1, s1)) = (pair[0], pair[1]);
+        if temperature <= t1 {
+            return f64::from(s0) + (f64::from(s1) - f64::from(s0)) * (temperature - t0) / (t1 - t0);
+        }
+    }
+    points.last().map_or(100.0, |&(_, speed)| f64::from(speed))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::types::AgentConfig;
+
+    fn sensor(id: &str, sensor_type: &str, temperature: f64) -> Sensor {
+        Sensor {
+            id: id.into(),
+            name: id.into(),
+            display_name: None,
+            temperature,
+            sensor_type: sensor_type.into(),
+            max_temp: None,
+            crit_temp: None,
+            chip: None,
+            hardware_name: None,
+            source: None,
+            alarms: Vec::new(),
+            raw_temperature: None,
+            stale: false,
+        }
+    }
+
+    fn curve(fans: &[&str], source_sensor: Option<&str>, source_aggregate: Option<&str>) -> LocalCurve {
+        LocalCurve {
+            fans: fans.iter().map(|f| f.to_string()).collect(),
+            source_sensor: source_sensor.map(str::to_string),
+            source_aggregate: source_aggregate.map(str::to_string),
+            points: vec![(30.0, 20), (50.0, 60), (70.0, 100)],
+            min_speed: 0,
+        }
+    }
+
+    /// Hysteresis and stepping off: speeds straight from the curve.
+    fn hardware(curves: Vec<LocalCurve>) -> HardwareSettings {
+        HardwareSettings {
+            local_curves: curves,
+            hysteresis_temp: 0.0,
+            fan_step_percent: 100,
+            failsafe_speed: 70,
+            ..AgentConfig::default().hardware
+        }
+    }
+
+    #[test]
+    fn curves_parse_and_validate() {
+        let curve: LocalCurve = serde_json::from_value(serde_json::json!({
+            "fans": ["nct6798_fan_3"], "source_aggregate": "max_of_type:hdd", "points": [[30, 25], [45, 60], [55, 100]],
+        })).unwrap();
+        assert_eq!(curve.source().unwrap(), CurveSource::MaxOfType("hdd".to_string()));
+        assert!(curve.validate().is_ok());
+
+        let invalid = [
+            LocalCurve { source_aggregate: Some("avg_all".to_string()), ..curve.clone() },
+            LocalCurve { source_sensor: Some("k10temp_tctl".to_string()), ..curve.clone() },
+            LocalCurve { points: vec![(50.0, 40), (40.0, 60)], ..curve.clone() },
+            LocalCurve { fans: Vec::new(), ..curve.clone() },
+        ];
+        assert!(invalid.iter().all(|c| c.validate().is_err()));
+
+        let mut config = AgentConfig::default();
+        config.hardware.local_curves = vec![curve.clone(), curve];
+        assert!(config.validate().unwrap_err().to_string().contains("more than one curve"));
+    }
+
+    #[test]
+    fn each_curve_follows_its_own_source() {
+        let hardware = hardware(vec![
+            curve(&["cpu_fan"], Some("k10temp_tctl"), None),
+            curve(&["hdd_fan"], None, Some("max_of_type:hdd")),
+            curve(&["case_fan"], None, Some("max_all")),
+        ]);
+        let sensors = [
+            sensor("k10temp_tctl", "cpu", 60.0),
+            sensor("drivetemp_sda", "hdd", 35.0),
+            sensor("drivetemp_sdb", "hdd", 40.0),
+            sensor("nct6798_systin", "motherboard", 30.0),
+        ];
+        let mut engine = CurveEngine::default();
+        let evaluation = engine.evaluate(&sensors, &hardware);
+        assert_eq!(evaluation.changed, [("cpu_fan".to_string(), 80), ("hdd_fan".to_string(), 40), ("case_fan".to_string(), 80)]);
+
+        // Same readings: nothing to write
+        assert!(engine.evaluate(&sensors, &hardware).changed.is_empty());
+        assert_eq!(interpolate(&[(30.0, 20), (70.0, 100)], 20.0), 20.0);
+        assert_eq!(interpolate(&[(30.0, 20), (70.0, 100)], 90.0), 100.0);
+    }
+
+    #[test]
+    fn a_missing_source_sensor_falls_back_to_the_hottest() {
+        let hardware = hardware(vec![curve(&["cpu_fan"], Some("k10temp_tctl"), None)]);
+        let mut engine = CurveEngine::default();
+        let evaluation = engine.evaluate(&[sensor("nvme_composite", "nvme", 70.0), sensor("acpitz", "other", 90.0)], &hardware);
+        assert_eq!(evaluation.changed, [("cpu_fan".to_string(), 100)]);
+        assert_eq!(evaluation.missing, ["k10temp_tctl"]);
+        // Warned about once
+        assert!(engine.evaluate(&[sensor("nvme_composite", "nvme", 70.0)], &hardware).missing.is_empty());
+
+        let back = engine.evaluate(&[sensor("k10temp_tctl", "cpu", 30.0)], &hardware);
+        assert_eq!((back.found, back.changed), (vec!["k10temp_tctl".to_string()], vec![("cpu_fan".to_string(), 20)]));
+
+        // Excluded sensors are never a source
+        let excluded = HardwareSettings { excluded_sensors: vec!["k10temp_tctl".to_string()], ..hardware };
+        assert_eq!(engine.evaluate(&[sensor("k10temp_tctl", "cpu", 70.0), sensor("nct6798_systin", "motherboard", 30.0)], &excluded).changed, []);
+    }
+
+    #[test]
+    fn hysteresis_steps_and_minimum_apply_in_order() {
+        let mut hardware = hardware(vec![LocalCurve { min_speed: 30, ..curve(&["case_fan"], None, None) }]);
+        hardware.hysteresis_temp = 2.0;
+        hardware.fan_step_percent = 10;
+        let mut engine = CurveEngine::default();
+        let mut speed_at = |temperature| engine.evaluate(&[sensor("nct6798_systin", "motherboard", temperature)], &hardware).changed;
+
+        // From failsafe_speed (70%) toward 40% in steps of 10
+        assert_eq!(speed_at(40.0), [("case_fan".to_string(), 60)]);
+        assert_eq!(speed_at(41.0), [("case_fan".to_string(), 50)]);
+        assert_eq!(speed_at(40.0), [("case_fan".to_string(), 40)]);
+        assert_eq!(speed_at(41.5), []);
+        // 2°C up from where the target was set: 44°C is 48%
+        assert_eq!(speed_at(44.0), [("case_fan".to_string(), 48)]);
+        // Down to 20% on the curve, held at min_speed
+        assert_eq!(speed_at(20.0), [("case_fan".to_string(), 38)]);
+        assert_eq!(speed_at(20.0), [("case_fan".to_string(), 30)]);
+        assert_eq!(speed_at(20.0), []);
+
+        engine.reset();
+        assert_eq!(engine.evaluate(&[sensor("nct6798_systin", "motherboard", 20.0)], &hardware).changed, [("case_fan".to_string(), 60)]);
+    }
+}
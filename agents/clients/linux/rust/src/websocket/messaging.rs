@@ -2,15 +2,28 @@
 
 use anyhow::Result;
 use futures_util::SinkExt;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use tokio::sync::{Mutex, RwLock};
+use tokio::sync::Mutex;
 use tokio_tungstenite::tungstenite::protocol::Message;
-use tracing::{debug, info};
+use tracing::{debug, info, warn};
 
-use crate::config::types::AgentConfig;
-use crate::hardware::HardwareMonitor;
+#[cfg(target_os = "linux")]
+use crate::hardware::linux::permissions::is_elevated;
 
 use super::client::WsSink;
+use super::compression;
+use super::emergency_actions;
+use super::event_log::Severity;
+use super::link_quality::Measure;
+use crate::config::types::{Compression, PayloadProfile};
+use crate::hardware::topology;
+use crate::hardware::types::ConnectionQuality;
+use super::payload::{self, DataMessage, Entries, RegisteredHardware};
+use super::protocol::{
+    FEATURE_COMPACT_PAYLOAD, FEATURE_DEFLATE_DATA, FEATURE_EMERGENCY_ALERT, FEATURE_TELEMETRY_STATUS, PROTOCOL_VERSION,
+    SENSOR_NAMING_VERSION, SUPPORTED_COMMANDS, SUPPORTED_FEATURES,
+};
 
 /// Edge-triggered error reporting: send `{type:"error"}` to backend only on
 /// transition (when the message differs from the last one we reported). Prevents
@@ -38,12 +51,81 @@ async fn clear_reported_error(last_reported_error: &Arc<Mutex<Option<String>>>)
     *last_reported_error.lock().await = None;
 }
 
+/// Warning/critical events included in the registration message.
+const RECENT_EVENTS_IN_REGISTRATION: usize = 5;
+
+/// The first truncated message is a warning with advice; later ones are debug.
+static TRUNCATION_WARNED: AtomicBool = AtomicBool::new(false);
+
+fn log_truncated(message: &str, dropped: usize, total: usize, max_bytes: usize) {
+    if TRUNCATION_WARNED.swap(true, Ordering::Relaxed) {
+        debug!("{} message: sent {} of {} sensors", message, total - dropped, total);
+        return;
+    }
+    warn!(
+        "{} message over the limit (backend.max_message_bytes = {}, at most {} sensors at registration): \
+         sent {} of {} sensors, cpu, gpu and motherboard first. Exclude sensors with \
+         hardware.excluded_sensors or set backend.payload_profile to \"compact\" to send them all",
+        message, max_bytes, payload::MAX_REGISTERED_SENSORS, total - dropped, total
+    );
+}
+
 impl super::client::WebSocketClient {
+    /// Report the failsafe emergency recorded during the last outage, if any. Kept for
+    /// the next connection if sending fails.
+    pub(crate) async fn send_pending_emergency(&self, write: &mut WsSink) -> Result<()> {
+        let mut pending = self.pending_emergency.lock().await;
+        let Some(trip) = pending.as_ref() else {
+            return Ok(());
+        };
+        if !self.negotiated.read().await.is_enabled(FEATURE_EMERGENCY_ALERT) {
+            // Registration hasn't been answered yet on this connection; the next
+            // reconnect's negotiation decides. Already logged locally either way.
+            return Ok(());
+        }
+        let agent_id = self.config.load().agent.id.clone();
+        let payload = serde_json::json!({
+            "type": "emergencyAlert",
+            "data": {
+                "agentId": agent_id,
+                "sensorId": trip.sensor_id,
+                "sensorType": trip.sensor_type,
+                "temperature": trip.temperature,
+                "threshold": trip.threshold,
+                "reason": if trip.threshold.is_some() { "threshold" } else { "crit_alarm" },
+                "timestamp": trip.timestamp,
+            }
+        });
+        write.send(Message::text(payload.to_string())).await?;
+        info!("Reported failsafe emergency on {} to backend", trip.sensor_id);
+        *pending = None;
+        Ok(())
+    }
+
     pub(crate) async fn send_registration(&self, write: &mut WsSink) -> Result<()> {
+        // Taken first: held by the startup comparison until its full discovery is done
+        let mut snapshot = self.hardware_snapshot.lock().await;
         let sensors = self.hardware_monitor.discover_sensors().await?;
         let fans = self.hardware_monitor.discover_fans().await?;
+        let hardware_changes = snapshot.observe(&sensors, &fans).cloned();
+        drop(snapshot);
+        // Sensor monitoring off: the backend gets no sensors, only that they're withheld
+        let monitoring = self.config.load().hardware.enable_sensor_monitoring;
+        let sensors = if monitoring { sensors } else { Vec::new() };
+        let sensor_stats = self.sensor_stats.lock().await.report(chrono::Utc::now().timestamp_millis());
+        let recent_events = self.events.lock().await.recent_high_severity(RECENT_EVENTS_IN_REGISTRATION);
+        let fan_control_conflicts = self.fan_control_conflicts.read().await.clone();
+        let fan_writes_blocked = self.fan_writes_blocked().await;
+        let safe_mode = self.safe_mode.read().await.clone();
+        let topology = match self.hardware_monitor.dump_hardware_info().await {
+            Ok(dump) => topology::topology(&dump, &sensors, &fans),
+            Err(e) => {
+                debug!("No hardware topology for registration: {:#}", e);
+                Vec::new()
+            }
+        };
 
-        let config = self.config.read().await;
+        let config = self.config.load();
         let mut registration = serde_json::json!({
             "type": "register",
             "data": {
@@ -51,22 +133,74 @@ impl super::client::WebSocketClient {
                 "name": config.agent.name,
                 "agent_type": "os_linux",
                 "agent_version": crate::version::VERSION,
+                "protocol_version": PROTOCOL_VERSION,
+                "naming_version": SENSOR_NAMING_VERSION,
                 "platform": std::env::consts::OS, // "linux", "macos", "windows", etc.
                 "architecture": crate::app::platform::project_arch(),
                 "update_interval": config.agent.update_interval as u64, // Send in seconds to match frontend/backend format
                 "fan_step_percent": config.hardware.fan_step_percent,
                 "hysteresis_temp": config.hardware.hysteresis_temp,
                 "emergency_temp": config.hardware.emergency_temp,
+                "emergency_temp_by_type": config.hardware.emergency_temp_by_type,
                 "failsafe_speed": config.hardware.failsafe_speed,
+                // Temperatures on the wire are always Celsius, whatever temperature_unit says
+                "unit": "celsius",
                 "log_level": config.agent.log_level.clone(),
                 "capabilities": {
                     "sensors": sensors,
                     "fans": fans,
-                    "fan_control": config.hardware.enable_fan_control
+                    "fan_control": self.fan_control_disabled().is_none() && !fan_writes_blocked,
+                    // Reduced-privilege runs: backend can show fans as monitoring-only
+                    "is_elevated": is_elevated(),
+                    "control_capable": fans.iter().any(|f| f.has_pwm_control),
+                    "dry_run": self.hardware_monitor.is_dry_run(),
+                    "sensor_monitoring": monitoring,
+                    "commands": SUPPORTED_COMMANDS,
+                    "features": SUPPORTED_FEATURES
                 }
             }
         });
 
+        // Peaks the backend may have missed while it was down or not recording
+        if !sensor_stats.sensors.is_empty() {
+            registration["data"]["sensor_stats"] = serde_json::to_value(&sensor_stats)?;
+        }
+
+        // Warnings/criticals from before this connection (outage, emergency, ...)
+        if !recent_events.is_empty() {
+            registration["data"]["recent_events"] = serde_json::to_value(&recent_events)?;
+        }
+
+        // Why the previous connection ended: server restart, network, timeout, ...
+        if let Some(last_disconnect) = self.last_disconnect.lock().unwrap().as_ref() {
+            registration["data"]["last_disconnect"] = serde_json::to_value(last_disconnect)?;
+        }
+
+        // Monitoring-only or not, the backend can tell a container from the host
+        if self.environment.lock().unwrap().container {
+            registration["data"]["environment"] = serde_json::json!("container");
+        }
+
+        // Crash loop: fans on automatic control until clearSafeMode
+        if let Some(safe_mode) = safe_mode {
+            registration["data"]["safe_mode"] = serde_json::to_value(&safe_mode)?;
+        }
+
+        // fancontrol/thinkfan/... fighting over the same fans; shown prominently
+        if !fan_control_conflicts.is_empty() {
+            registration["data"]["fan_control_conflicts"] = serde_json::to_value(&fan_control_conflicts)?;
+        }
+
+        // What appeared, disappeared or was renamed since the previous run
+        if let Some(changes) = hardware_changes {
+            registration["data"]["hardware_changes"] = serde_json::to_value(&changes)?;
+        }
+
+        // Which sensors and fans share a chip, for assigning curves in the UI
+        if !topology.is_empty() {
+            registration["data"]["capabilities"]["topology"] = serde_json::to_value(&topology)?;
+        }
+
         // Present the permanent token if we have one; otherwise the one-time
         // enrollment token from the install script (exchanged on first register)
         if let Some(token) = &config.auth.auth_token {
@@ -75,19 +209,49 @@ impl super::client::WebSocketClient {
             registration["data"]["enrollment_token"] = serde_json::json!(token);
         }
 
+        // Huge hosts (JBODs): keep the message within the backend's frame limit
+        let max_bytes = config.backend.max_message_bytes;
+        // The topology goes first: it only adds detail, and would name cut sensors
+        let oversized = max_bytes > 0 && registration.to_string().len() > max_bytes;
+        if oversized || sensors.len() > payload::MAX_REGISTERED_SENSORS {
+            if let Some(capabilities) = registration["data"]["capabilities"].as_object_mut() {
+                if capabilities.remove("topology").is_some() {
+                    debug!("Registration too large, hardware topology left out");
+                }
+            }
+        }
+        let truncated = payload::truncate_sensors(
+            &mut registration, "/data/capabilities/sensors", &sensors, max_bytes, payload::MAX_REGISTERED_SENSORS,
+        );
+        let registered_sensors: Vec<_> = if truncated > 0 {
+            registration["data"]["capabilities"]["truncated"] = serde_json::json!(truncated);
+            log_truncated("Registration", truncated, sensors.len(), max_bytes);
+            let kept: std::collections::HashSet<&str> = registration["data"]["capabilities"]["sensors"]
+                .as_array()
+                .map(|entries| entries.iter().filter_map(|e| e["id"].as_str()).collect())
+                .unwrap_or_default();
+            sensors.iter().filter(|s| kept.contains(&*s.id)).cloned().collect()
+        } else {
+            sensors
+        };
+
+        // Compact data messages refer to what this registration described
+        *self.registered_hardware.write().await = RegisteredHardware::new(&registered_sensors, &fans);
+        self.negotiated.write().await.new_connection();
+
         write.send(Message::text(registration.to_string())).await?;
         info!("✅ Agent registered: {}", config.agent.id);
         Ok(())
     }
 
-    pub(crate) async fn send_data(
-        write: &mut WsSink,
-        config: &Arc<RwLock<AgentConfig>>,
-        hardware_monitor: &Arc<dyn HardwareMonitor>,
-        last_reported_error: &Arc<Mutex<Option<String>>>,
-    ) -> Result<()> {
+    /// Collect and send one data frame; returns its timestamp.
+    pub(crate) async fn send_data(&self, write: &mut WsSink) -> Result<i64> {
         use tracing::trace;
 
+        let config = &self.config;
+        let hardware_monitor = &self.hardware_monitor;
+        let last_reported_error = &self.last_reported_error;
+
         trace!("Starting hardware data collection");
 
         // On discover/get_system_info failure: emit an edge-triggered error
@@ -106,6 +270,15 @@ impl super::client::WebSocketClient {
             }
         };
         trace!("Collected {} sensors", sensors.len());
+        // With monitoring off the readings still feed the emergency checks below, not the backend
+        let monitoring = config.load().hardware.enable_sensor_monitoring;
+        if monitoring {
+            self.sensor_stats.lock().await.record(&sensors, chrono::Utc::now().timestamp_millis());
+        }
+
+        // Kernel-asserted crit alarm on a CPU/motherboard sensor: go to 100% now
+        // rather than waiting for the backend's curve (or for failsafe) to react.
+        let crit_alarm = self.check_crit_alarm(&sensors).await;
 
         let fans = match hardware_monitor.discover_fans().await {
             Ok(f) => f,
@@ -117,7 +290,14 @@ impl super::client::WebSocketClient {
         };
         trace!("Collected {} fans", fans.len());
 
-        let system_health = match hardware_monitor.get_system_info().await {
+        // The backend's curve is in charge; the fans report whether it has them at 100%
+        let trigger = crit_alarm.or(Self::find_emergency(&sensors, &config.load().hardware).map(|(sensor, _)| sensor));
+        self.check_emergency_actions(trigger, emergency_actions::fans_at_max(&fans)).await;
+        // Crit alarms notify as they're recorded above
+        self.check_notifications(trigger.filter(|_| crit_alarm.is_none()), &fans).await;
+        self.send_emergency_action_reports(write).await?;
+
+        let mut system_health = match hardware_monitor.get_system_info().await {
             Ok(h) => h,
             Err(e) => {
                 let msg = format!("System info collection failed: {}", e);
@@ -126,22 +306,55 @@ impl super::client::WebSocketClient {
             }
         };
         trace!("Collected system health info");
+        system_health.connection = self.connection_quality().await;
+        system_health.notifications = self.notification_stats();
+
+        // Outage summary; the first frame after a reconnect is the one that matters
+        let status = if self.negotiated.read().await.is_enabled(FEATURE_TELEMETRY_STATUS) {
+            Some(self.link_status.lock().await.to_json(*self.failsafe_active.read().await))
+        } else {
+            None
+        };
+
+        let config_read = config.load();
+        let compact = config_read.backend.payload_profile == PayloadProfile::Compact
+            && self.negotiated.read().await.is_enabled(FEATURE_COMPACT_PAYLOAD);
+        let registered = self.registered_hardware.read().await;
+        let reported = if monitoring { &sensors[..] } else { &[] };
+        let (sensor_entries, fan_entries) = if compact {
+            (registered.sensors(reported), registered.fans(&fans))
+        } else {
+            (Entries::full(reported), Entries::full(&fans))
+        };
 
-        let config_read = config.read().await;
         let timestamp = chrono::Utc::now().timestamp_millis();
-        let data = serde_json::json!({
-            "type": "data",
-            "data": {
-                "agentId": config_read.agent.id,
-                "timestamp": timestamp,
-                "sensors": sensors,
-                "fans": fans,
-                "systemHealth": system_health
-            }
-        });
+        let data = DataMessage::new(&config_read.agent.id, timestamp, self.uptime_ms(), sensor_entries, fan_entries, &system_health)
+            .with_status(status)
+            .with_interval(self.frame_interval_ms(&sensors))
+            .with_sensor_monitoring(monitoring);
+
+        // The socket takes ownership of each message, so the buffer can't be reused;
+        // sizing it like the last one at least spares the regrowth copies.
+        let mut buf = Vec::with_capacity(self.data_message_bytes.load(Ordering::Relaxed));
+        data.write_into(&mut buf)?;
+        self.data_message_bytes.store(buf.len(), Ordering::Relaxed);
+        let max_bytes = config_read.backend.max_message_bytes;
+        if max_bytes > 0 && buf.len() > max_bytes {
+            let mut data = serde_json::to_value(&data)?;
+            let truncated = payload::truncate_sensors(&mut data, "/data/sensors", reported, max_bytes, usize::MAX);
+            data["data"]["truncated"] = serde_json::json!(truncated);
+            log_truncated("Data", truncated, reported.len(), max_bytes);
+            buf = serde_json::to_vec(&data)?;
+        }
+        drop(registered);
+        let deflate = config_read.backend.compression == Compression::Deflate
+            && self.negotiated.read().await.is_enabled(FEATURE_DEFLATE_DATA);
+        let message = compression::data_frame(buf, deflate)?;
 
-        trace!("Sending WebSocket message (timestamp: {})", timestamp);
-        write.send(Message::text(data.to_string())).await?;
+        trace!("Sending WebSocket message (timestamp: {}, {} bytes)", timestamp, message.len());
+        let started = std::time::Instant::now();
+        write.send(message).await?;
+        self.link_quality.lock().unwrap().record(Measure::Send, started.elapsed());
 
         // Success - clear dedup so the next failure (if any) is reported fresh.
         clear_reported_error(last_reported_error).await;
@@ -149,7 +362,37 @@ impl super::client::WebSocketClient {
         // Log with cache status indicator
         let from_cache = hardware_monitor.last_discovery_from_cache().await;
         let source = if from_cache { "from cache" } else { "from hardware" };
-        debug!("Sent telemetry: {} sensors, {} fans ({})", sensors.len(), fans.len(), source);
-        Ok(())
+        debug!("Sent telemetry: {} sensors, {} fans ({})", reported.len(), fans.len(), source);
+        let failsafe_active = *self.failsafe_active.read().await;
+        self.write_state(|state| {
+            state.connected = true;
+            state.failsafe_active = failsafe_active;
+            state.last_data_sent = Some(timestamp);
+            state.controllable_fans = fans.iter().filter(|f| f.has_pwm_control).count();
+        });
+        Ok(timestamp)
+    }
+
+    /// Latency percentiles for systemHealth.connection; logs when the connection
+    /// becomes or stops being degraded.
+    async fn connection_quality(&self) -> Option<ConnectionQuality> {
+        let warn_ms = self.config.load().backend.latency_warn_ms;
+        let (quality, change) = self.link_quality.lock().unwrap().report(std::time::Instant::now(), warn_ms);
+        match change {
+            Some(true) => {
+                warn!("Backend connection degraded: p95 ping round trip or data send past {}ms (backend.latency_warn_ms)", warn_ms);
+                self.record_event(Severity::Warning, "connection_degraded",
+                                  format!("p95 latency to the backend past {}ms", warn_ms),
+                                  serde_json::to_value(&quality).unwrap_or_default()).await;
+            }
+            Some(false) => {
+                info!("Backend connection latency back under {}ms", warn_ms);
+                self.record_event(Severity::Info, "connection_recovered",
+                                  format!("p95 latency to the backend back under {}ms", warn_ms),
+                                  serde_json::to_value(&quality).unwrap_or_default()).await;
+            }
+            None => {}
+        }
+        quality
     }
 }
@@ -0,0 +1,387 @@
+//! `notifications`: critical events POSTed as JSON to a webhook (ntfy, Gotify, a
+//! script behind a reverse proxy), for setups without the backend's notifications.
+//!
+//! Events reach the webhook through the event log: `record_event` hands every entry
+//! to `notify`, which sends the kinds mapped to a notification type. Emergencies and
+//! crit alarms are recorded anyway; fan failures (a fan turning stalled or faulty),
+//! emergencies while connected and a failsafe that outlasts `failsafe_after_minutes`
+//! are recorded here for it. A type is sent at most once per `rate_limit_minutes`.
+//!
+//! Delivery runs in its own task with a timeout, so a slow or dead endpoint never
+//! holds up fan control. The client is a bare HTTP/1.1 POST over tokio, with rustls
+//! for https:// (the roots wss:// uses); attempts are logged and counted in
+//! `systemHealth.notifications`.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context, Result};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio_tungstenite::tungstenite::http::Uri;
+use tracing::{debug, info, warn};
+
+use super::client::WebSocketClient;
+use super::emergency_actions::EmergencyTimer;
+use super::event_log::Severity;
+use crate::config::types::{NotificationEvent, Notifications};
+use crate::hardware::types::{Fan, FanStatusReason, NotificationStats, Sensor};
+
+/// Longest a delivery may take, connect to answer.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Longest status line read from the endpoint.
+const MAX_STATUS_LINE: usize = 1024;
+
+/// The notification type of event log entries of `kind`, if they are one.
+fn event_for_kind(kind: &str) -> Option<NotificationEvent> {
+    match kind {
+        "emergency" | "emergency_temp" | "crit_alarm" => Some(NotificationEvent::Emergency),
+        "fan_failed" => Some(NotificationEvent::FanFailure),
+        "failsafe_prolonged" => Some(NotificationEvent::Failsafe),
+        "safe_mode_entered" => Some(NotificationEvent::SafeMode),
+        _ => None,
+    }
+}
+
+/// Rate limit, edge detection and counters.
+#[derive(Debug, Default)]
+pub(crate) struct NotificationState {
+    last_sent: HashMap<NotificationEvent, Instant>,
+    /// Fans stalled or faulty at the last data cycle
+    failing_fans: HashSet<String>,
+    /// Sensor in emergency at the last data cycle
+    emergency: Option<Arc<str>>,
+    failsafe: EmergencyTimer,
+    stats: NotificationStats,
+}
+
+impl NotificationState {
+    /// Whether an `event` may be sent at `now`; counts it as suppressed if not.
+    fn admit(&mut self, event: NotificationEvent, now: Instant, rate_limit: Duration) -> bool {
+        if self.last_sent.get(&event).is_some_and(|last| now.saturating_duration_since(*last) < rate_limit) {
+            self.stats.suppressed += 1;
+            return false;
+        }
+        self.last_sent.insert(event, now);
+        true
+    }
+
+    /// Fans that turned stalled or faulty since the last call.
+    fn new_fan_failures<'a>(&mut self, fans: &'a [Fan]) -> Vec<&'a Fan> {
+        let failing: Vec<&Fan> = fans
+            .iter()
+            .filter(|fan| fan.status_detail.is_some_and(|d| matches!(d.reason, FanStatusReason::Stalled | FanStatusReason::Fault)))
+            .collect();
+        let new = failing.iter().copied().filter(|fan| !self.failing_fans.contains(&fan.id)).collect();
+        self.failing_fans = failing.iter().map(|fan| fan.id.clone()).collect();
+        new
+    }
+
+    /// Whether `trigger` is an emergency that wasn't one at the last call.
+    fn emergency_started(&mut self, trigger: Option<&Sensor>) -> bool {
+        let started = trigger.is_some() && self.emergency.is_none();
+        self.emergency = trigger.map(|sensor| Arc::clone(&sensor.id));
+        started
+    }
+}
+
+/// POST `body` as JSON to `url`; returns the HTTP status, an error unless 2xx.
+pub(crate) async fn post(url: &str, auth_header: Option<&str>, body: &[u8]) -> Result<u16> {
+    tokio::time::timeout(DELIVERY_TIMEOUT, deliver(url, auth_header, body))
+        .await
+        .map_err(|_| anyhow!("no answer within {}s", DELIVERY_TIMEOUT.as_secs()))?
+}
+
+async fn deliver(url: &str, auth_header: Option<&str>, body: &[u8]) -> Result<u16> {
+    let uri: Uri = url.parse().context("invalid webhook_url")?;
+    let https = match uri.scheme_str() {
+        Some("https") => true,
+        Some("http") => false,
+        _ => bail!("webhook_url must start with http:// or https://"),
+    };
+    let host = uri.host().filter(|h| !h.is_empty()).context("webhook_url has no host")?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+    // Host header without credentials
+    let authority = uri.authority().map(|a| a.as_str().rsplit('@').next().unwrap_or_default()).unwrap_or(host);
+    let path = uri.path_and_query().map(|p| p.as_str()).unwrap_or("/");
+
+    let mut head = format!(
+        "POST {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: pankha-agent/{}\r\nContent-Type: application/json\r\n\
+         Content-Length: {}\r\nConnection: close\r\n",
+        path, authority, crate::version::VERSION, body.len()
+    );
+    if let Some(header) = auth_header {
+        head.push_str(header);
+        head.push_str("\r\n");
+    }
+    head.push_str("\r\n");
+
+    let stream = TcpStream::connect((host, port)).await.with_context(|| format!("connecting to {}:{}", host, port))?;
+    if !https {
+        return exchange(stream, &head, body).await;
+    }
+    let roots = tokio_rustls::rustls::RootCertStore::from_iter(webpki_roots::TLS_SERVER_ROOTS.iter().cloned());
+    let config = tokio_rustls::rustls::ClientConfig::builder_with_provider(Arc::new(tokio_rustls::rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    let server_name = tokio_rustls::rustls::pki_types::ServerName::try_from(host.to_string()).context("invalid TLS server name")?;
+    let stream = tokio_rustls::TlsConnector::from(Arc::new(config))
+        .connect(server_name, stream)
+        .await
+        .context("TLS handshake")?;
+    exchange(stream, &head, body).await
+}
+
+/// Send the request and read the status line of the answer.
+async fn exchange<S: AsyncRead + AsyncWrite + Unpin>(mut stream: S, head: &str, body: &[u8]) -> Result<u16> {
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(body).await?;
+    stream.flush().await?;
+
+    let mut response = Vec::new();
+    let mut buf = [0u8; 256];
+    while !response.windows(2).any(|w| w == b"\r\n") {
+        let n = stream.read(&mut buf).await?;
+        if n == 0 {
+            bail!("connection closed without an answer");
+        }
+        response.extend_from_slice(&buf[..n]);
+        if response.len() > MAX_STATUS_LINE {
+            bail!("answer is not HTTP");
+        }
+    }
+    let status_line = String::from_utf8_lossy(&response).lines().next().unwrap_or_default().to_string();
+    let status: u16 = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|code| code.parse().ok())
+        .with_context(|| format!("answer is not HTTP: {:?}", status_line))?;
+    if !(200..300).contains(&status) {
+        bail!("answered \"{}\"", status_line.trim());
+    }
+    Ok(status)
+}
+
+impl WebSocketClient {
+    /// Send the event log entry `kind` to the webhook when it is a notification
+    /// type the settings ask for; returns at once.
+    pub(crate) fn notify(&self, severity: Severity, kind: &'static str, message: &str, details: &serde_json::Value) {
+        let Some(event) = event_for_kind(kind) else {
+            return;
+        };
+        let config = self.config.load();
+        let settings: &Notifications = &config.notifications;
+        let Some(url) = settings.webhook_url.clone().filter(|_| settings.sends(event)) else {
+            return;
+        };
+        let rate_limit = Duration::from_secs(settings.rate_limit_minutes * 60);
+        if !self.notifications.lock().unwrap().admit(event, Instant::now(), rate_limit) {
+            debug!("Not sending {:?} notification: one was sent within notifications.rate_limit_minutes", event);
+            return;
+        }
+
+        let body = serde_json::json!({
+            "event": event,
+            "kind": kind,
+            "severity": severity,
+            "title": format!("Pankha: {}", config.agent.name),
+            "message": message,
+            "agent_id": config.agent.id,
+            "agent_name": config.agent.name,
+            "timestamp": chrono::Utc::now().timestamp_millis(),
+            "details": details,
+        });
+        let auth_header = settings.auth_header.clone();
+        let state = Arc::clone(&self.notifications);
+        tokio::spawn(async move {
+            let outcome = post(&url, auth_header.as_deref(), body.to_string().as_bytes()).await;
+            let mut state = state.lock().unwrap();
+            match outcome {
+                Ok(status) => {
+                    state.stats.sent += 1;
+                    info!("Sent {:?} notification to the webhook (HTTP {})", event, status);
+                }
+                Err(e) => {
+                    state.stats.failed += 1;
+                    warn!("Webhook notification {:?} failed: {:#}", event, e);
+                }
+            }
+        });
+    }
+
+    /// Per data cycle: record emergencies and fan failures that just began, for
+    /// the webhook.
+    pub(crate) async fn check_notifications(&self, trigger: Option<&Sensor>, fans: &[Fan]) {
+        if self.config.load().notifications.webhook_url.is_none() {
+            return;
+        }
+        let (emergency_started, failed) = {
+            let mut state = self.notifications.lock().unwrap();
+            let failed: Vec<(String, &'static str, Option<u32>)> = state
+                .new_fan_failures(fans)
+                .into_iter()
+                .map(|fan| {
+                    let reason = if fan.status_detail.is_some_and(|d| d.reason == FanStatusReason::Fault) { "fault" } else { "stalled" };
+                    (fan.id.clone(), reason, fan.rpm)
+                })
+                .collect();
+            (state.emergency_started(trigger), failed)
+        };
+        if let Some(sensor) = trigger.filter(|_| emergency_started) {
+            self.record_event(Severity::Critical, "emergency_temp", format!("{} reached its emergency temperature", sensor.id),
+                              serde_json::json!({ "sensor_id": sensor.id, "temperature": sensor.instant_temperature() })).await;
+        }
+        for (fan_id, reason, rpm) in failed {
+            warn!("Fan {} {}", fan_id, if reason == "fault" { "reports a fault" } else { "stalled" });
+            self.record_event(Severity::Warning, "fan_failed", format!("Fan {} {}", fan_id, reason),
+                              serde_json::json!({ "fan_id": fan_id, "reason": reason, "rpm": rpm })).await;
+        }
+    }
+
+    /// Per failsafe check: `active` whether failsafe mode is on. Records one
+    /// `failsafe_prolonged` event once it has lasted `failsafe_after_minutes`.
+    pub(crate) async fn check_failsafe_duration(&self, active: bool) {
+        let after_minutes = self.config.load().notifications.failsafe_after_minutes;
+        let prolonged = self.notifications.lock().unwrap().failsafe.observe(active, Instant::now(), Duration::from_secs(after_minutes * 60));
+        if prolonged {
+            self.record_event(Severity::Warning, "failsafe_prolonged", format!("Failsafe mode for {} minutes", after_minutes),
+                              serde_json::json!({ "minutes": after_minutes })).await;
+        }
+    }
+
+    /// `systemHealth.notifications`; `None` without a webhook.
+    pub(crate) fn notification_stats(&self) -> Option<NotificationStats> {
+        self.config.load().notifications.webhook_url.as_ref()?;
+        Some(self.notifications.lock().unwrap().stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hardware::types::{FanPresence, FanStatusDetail};
+    use tokio::net::TcpListener;
+
+    fn fan(id: &str, reason: FanStatusReason) -> Fan {
+        Fan {
+            id: id.to_string(),
+            name: id.to_string(),
+            rpm: Some(0),
+            speed: 60,
+            target_speed: 60,
+            pwm: None,
+            status: "stopped".to_string(),
+            status_detail: Some(FanStatusDetail { reason, presence: FanPresence::Present, fault: false, alarm: false }),
+            has_pwm_control: true,
+            control_method: None,
+            pwm_file: None,
+            pwm_enable: None,
+            control_mode: None,
+            zone: None,
+            alarms: Vec::new(),
+            drift: false,
+            control_error: None,
+            last_set_by: None,
+        }
+    }
+
+    #[test]
+    fn each_event_type_is_rate_limited_on_its_own() {
+        let start = Instant::now();
+        let limit = Duration::from_secs(15 * 60);
+        let mut state = NotificationState::default();
+        assert!(state.admit(NotificationEvent::Emergency, start, limit));
+        assert!(!state.admit(NotificationEvent::Emergency, start + Duration::from_secs(60), limit));
+        assert!(state.admit(NotificationEvent::FanFailure, start + Duration::from_secs(60), limit));
+        assert!(state.admit(NotificationEvent::Emergency, start + limit, limit));
+        assert_eq!(state.stats.suppressed, 1);
+
+        assert_eq!(event_for_kind("crit_alarm"), Some(NotificationEvent::Emergency));
+        assert_eq!(event_for_kind("failsafe_entered"), None);
+        let only_fans = Notifications {
+            webhook_url: Some("http://ntfy.lan/fans".to_string()),
+            events: vec![NotificationEvent::FanFailure],
+            ..Notifications::default()
+        };
+        assert!(only_fans.sends(NotificationEvent::FanFailure));
+        assert!(!only_fans.sends(NotificationEvent::Emergency));
+        assert!(!Notifications::default().sends(NotificationEvent::Emergency));
+    }
+
+    #[test]
+    fn fan_failures_are_reported_when_they_begin() {
+        let mut state = NotificationState::default();
+        let fans = [fan("fan1", FanStatusReason::Stalled), fan("fan2", FanStatusReason::Idle)];
+        let ids = |failed: Vec<&Fan>| failed.iter().map(|f| f.id.clone()).collect::<Vec<_>>();
+        assert_eq!(ids(state.new_fan_failures(&fans)), ["fan1"]);
+        assert!(state.new_fan_failures(&fans).is_empty());
+
+        // Recovers, then fails again: a new failure
+        assert!(state.new_fan_failures(&[fan("fan1", FanStatusReason::Spinning)]).is_empty());
+        assert_eq!(ids(state.new_fan_failures(&[fan("fan1", FanStatusReason::Fault)])), ["fan1"]);
+    }
+
+    /// HTTP server answering one request with `status`; the request comes back
+    /// through the channel.
+    async fn webhook(status: &'static str) -> (u16, tokio::sync::oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (tx, rx) = tokio::sync::oneshot::channel();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0u8; 1024];
+            // Head, then Content-Length bytes of body
+            loop {
+                let n = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..n]);
+                let text = String::from_utf8_lossy(&request);
+                if let Some((head, body)) = text.split_once("\r\n\r\n") {
+                    let length: usize = head
+                        .lines()
+                        .find_map(|l| l.strip_prefix("Content-Length: "))
+                        .and_then(|v| v.parse().ok())
+                        .unwrap_or(0);
+                    if body.len() >= length {
+                        break;
+                    }
+                }
+            }
+            let _ = tx.send(String::from_utf8(request).unwrap());
+            stream.write_all(format!("HTTP/1.1 {}\r\nContent-Length: 0\r\n\r\n", status).as_bytes()).await.unwrap();
+        });
+        (port, rx)
+    }
+
+    #[tokio::test]
+    async fn posts_json_with_the_auth_header() {
+        let (port, request) = webhook("200 OK").await;
+        let url = format!("http://user:pw@127.0.0.1:{}/fans?priority=high", port);
+        let status = post(&url, Some("Authorization: Bearer tk_test"), br#"{"event":"emergency"}"#).await.unwrap();
+        assert_eq!(status, 200);
+
+        let request = request.await.unwrap();
+        let (head, body) = request.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("POST /fans?priority=high HTTP/1.1\r\n"), "{}", head);
+        assert!(head.contains(&format!("\r\nHost: 127.0.0.1:{}\r\n", port)));
+        assert!(head.contains("\r\nContent-Type: application/json\r\n"));
+        assert!(head.contains("\r\nAuthorization: Bearer tk_test"));
+        assert_eq!(body, r#"{"event":"emergency"}"#);
+    }
+
+    #[tokio::test]
+    async fn failed_deliveries_are_errors() {
+        let (port, _request) = webhook("503 Service Unavailable").await;
+        let error = post(&format!("http://127.0.0.1:{}/", port), None, b"{}").await.unwrap_err();
+        assert!(error.to_string().contains("503"), "{}", error);
+
+        // Nothing listening
+        let closed = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        assert!(post(&format!("http://127.0.0.1:{}/", closed), None, b"{}").await.is_err());
+        assert!(post("ftp://example.net/", None, b"{}").await.is_err());
+    }
+}
@@ -0,0 +1,477 @@
+//! `sensors` / `fans` arrays of data messages for `backend.payload_profile`.
+//!
+//! Full entries repeat metadata that never changes between cycles (name, type, chip,
+//! hardwareName, source path, limits, pwm_file, zone). In compact mode that metadata
+//! goes only into the registration capabilities, and data messages carry the readings
+//! and state of each device. Devices the backend hasn't seen in a registration yet
+//! (hot-plugged since) are still sent in full.
+//!
+//! Data messages are serialized straight from the readings (`DataMessage`), without
+//! an intermediate `serde_json::Value` tree; only an oversized message is converted
+//! to one for `truncate_sensors`.
+
+use std::collections::BTreeSet;
+
+use serde::ser::SerializeSeq;
+use serde::{Serialize, Serializer};
+
+use crate::hardware::types::{Fan, FanSetBy, FanStatusDetail, Sensor, SystemHealth};
+
+/// Value of the data message `payload` field in compact mode.
+pub const COMPACT: &str = "compact";
+
+/// Sensor types kept when a message has to shed sensors, most important first.
+const SENSOR_PRIORITY: &[&str] = &["cpu", "gpu", "motherboard"];
+
+/// Most sensors described in the registration capabilities.
+pub const MAX_REGISTERED_SENSORS: usize = 256;
+
+/// Room kept for the `truncated` field added to a shortened message.
+const TRUNCATED_FIELD_BYTES: usize = 32;
+
+/// Sensor and fan ids sent in the last registration.
+#[derive(Debug, Default)]
+pub struct RegisteredHardware {
+    sensors: BTreeSet<String>,
+    fans: BTreeSet<String>,
+}
+
+impl RegisteredHardware {
+    pub fn new(sensors: &[Sensor], fans: &[Fan]) -> Self {
+        Self {
+            sensors: sensors.iter().map(|s| s.id.to_string()).collect(),
+            fans: fans.iter().map(|f| f.id.clone()).collect(),
+        }
+    }
+
+    /// `sensors` with compact entries for the registered ones.
+    pub fn sensors<'a>(&'a self, sensors: &'a [Sensor]) -> Entries<'a, Sensor> {
+        Entries { items: sensors, registered: Some(&self.sensors) }
+    }
+
+    /// `fans` with compact entries for the registered ones.
+    pub fn fans<'a>(&'a self, fans: &'a [Fan]) -> Entries<'a, Fan> {
+        Entries { items: fans, registered: Some(&self.fans) }
+    }
+}
+
+/// A `sensors` or `fans` array: compact entries for ids in `registered`, full ones
+/// for the rest (all of them without a registration).
+pub struct Entries<'a, T> {
+    items: &'a [T],
+    registered: Option<&'a BTreeSet<String>>,
+}
+
+impl<'a, T> Entries<'a, T> {
+    pub fn full(items: &'a [T]) -> Self {
+        Self { items, registered: None }
+    }
+}
+
+impl Entries<'_, Sensor> {
+    fn compact(&self, sensor: &Sensor) -> bool {
+        self.registered.is_some_and(|ids| ids.contains(&*sensor.id))
+    }
+}
+
+impl Entries<'_, Fan> {
+    fn compact(&self, fan: &Fan) -> bool {
+        self.registered.is_some_and(|ids| ids.contains(&fan.id))
+    }
+}
+
+impl Serialize for Entries<'_, Sensor> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.items.len()))?;
+        for sensor in self.items {
+            if self.compact(sensor) {
+                seq.serialize_element(&CompactSensor::from(sensor))?;
+            } else {
+                seq.serialize_element(sensor)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+impl Serialize for Entries<'_, Fan> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut seq = serializer.serialize_seq(Some(self.items.len()))?;
+        for fan in self.items {
+            if self.compact(fan) {
+                seq.serialize_element(&CompactFan::from(fan))?;
+            } else {
+                seq.serialize_element(fan)?;
+            }
+        }
+        seq.end()
+    }
+}
+
+/// A `data` message. Field order is the wire order.
+#[derive(Serialize)]
+pub struct DataMessage<'a> {
+    #[serde(rename = "type")]
+    kind: &'static str,
+    data: Data<'a>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Data<'a> {
+    agent_id: &'a str,
+    timestamp: i64,
+    /// Monotonic milliseconds since the agent started (see time_sync.rs)
+    #[serde(rename = "uptime_ms")]
+    uptime_ms: u64,
+    unit: &'static str,
+    sensors: Entries<'a, Sensor>,
+    fans: Entries<'a, Fan>,
+    system_health: &'a SystemHealth,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    payload: Option<&'static str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<serde_json::Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    part: Option<Part>,
+    /// Time until the next frame, with `agent.adaptive_interval` (see adaptive_interval.rs)
+    #[serde(rename = "interval_ms", skip_serializing_if = "Option::is_none")]
+    interval_ms: Option<u64>,
+    /// false with `hardware.enable_sensor_monitoring` off, when `sensors` is left empty
+    #[serde(rename = "sensor_monitoring", skip_serializing_if = "Option::is_none")]
+    sensor_monitoring: Option<bool>,
+}
+
+/// Place of a UDP datagram in the cycle's message, split to fit the MTU.
+#[derive(Serialize)]
+struct Part {
+    /// Cycle number, shared by every part of one message
+    seq: u64,
+    index: usize,
+    count: usize,
+}
+
+impl<'a> DataMessage<'a> {
+    pub fn new(
+        agent_id: &'a str,
+        timestamp: i64,
+        uptime_ms: u64,
+        sensors: Entries<'a, Sensor>,
+        fans: Entries<'a, Fan>,
+        system_health: &'a SystemHealth,
+    ) -> Self {
+        let payload = sensors.registered.map(|_| COMPACT);
+        Self {
+            kind: "data",
+            data: Data {
+                agent_id,
+                timestamp,
+                uptime_ms,
+                unit: "celsius",
+                sensors,
+                fans,
+                system_health,
+                payload,
+                status: None,
+                part: None,
+                interval_ms: None,
+                sensor_monitoring: None,
+            },
+        }
+    }
+
+    pub fn with_status(mut self, status: Option<serde_json::Value>) -> Self {
+        self.data.status = status;
+        self
+    }
+
+    pub fn with_interval(mut self, interval_ms: Option<u64>) -> Self {
+        self.data.interval_ms = interval_ms;
+        self
+    }
+
+    /// Marks the message as sent with sensor monitoring off; the caller leaves the sensors out.
+    pub fn with_sensor_monitoring(mut self, enabled: bool) -> Self {
+        self.data.sensor_monitoring = (!enabled).then_some(false);
+        self
+    }
+
+    pub fn with_part(mut self, seq: u64, index: usize, count: usize) -> Self {
+        self.data.part = Some(Part { seq, index, count });
+        self
+    }
+
+    /// Serialize into `buf`, replacing what it held but keeping its capacity.
+    pub fn write_into(&self, buf: &mut Vec<u8>) -> serde_json::Result<()> {
+        buf.clear();
+        serde_json::to_writer(buf, self)
+    }
+}
+
+/// Shorten the `sensors` array at `pointer` (built from `sensors`, one entry each, same
+/// order) until `message` serializes to at most `max_bytes` (0 = no limit) and holds
+/// at most `max_count` sensors. cpu, gpu and motherboard sensors are kept first, the
+/// rest in payload order; what is kept stays in payload order. Returns how many
+/// sensors were dropped.
+pub fn truncate_sensors(
+    message: &mut serde_json::Value,
+    pointer: &str,
+    sensors: &[Sensor],
+    max_bytes: usize,
+    max_count: usize,
+) -> usize {
+    let total = message.to_string().len();
+    let Some(entries) = message.pointer_mut(pointer).and_then(|v| v.as_array_mut()) else {
+        return 0;
+    };
+    let over_size = max_bytes > 0 && total > max_bytes;
+    if !over_size && entries.len() <= max_count {
+        return 0;
+    }
+
+    // Bytes the array may take: the message without its entries, plus room for the count
+    let sizes: Vec<usize> = entries.iter().map(|e| e.to_string().len() + 1).collect();
+    let budget = match max_bytes {
+        0 => usize::MAX,
+        max => max.saturating_sub(total - sizes.iter().sum::<usize>() + TRUNCATED_FIELD_BYTES),
+    };
+
+    let rank = |i: usize| {
+        let sensor_type = sensors.get(i).map_or("", |s| &*s.sensor_type);
+        SENSOR_PRIORITY.iter().position(|t| *t == sensor_type).unwrap_or(SENSOR_PRIORITY.len())
+    };
+    let mut order: Vec<usize> = (0..entries.len()).collect();
+    order.sort_by_key(|&i| (rank(i), i));
+
+    let mut keep = vec![false; entries.len()];
+    let (mut used, mut kept) = (0, 0);
+    for i in order {
+        if kept == max_count || used + sizes[i] > budget {
+            break;
+        }
+        keep[i] = true;
+        used += sizes[i];
+        kept += 1;
+    }
+
+    let dropped = entries.len() - kept;
+    let mut keep = keep.into_iter();
+    entries.retain(|_| keep.next().unwrap_or(false));
+    dropped
+}
+
+/// What changes between cycles: the reading, plus the unsmoothed value, alarms and
+/// staleness.
+#[derive(Serialize)]
+struct CompactSensor<'a> {
+    id: &'a str,
+    temperature: f64,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    alarms: &'a Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_temperature: Option<f64>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    stale: bool,
+}
+
+impl<'a> From<&'a Sensor> for CompactSensor<'a> {
+    fn from(s: &'a Sensor) -> Self {
+        Self { id: &s.id, temperature: s.temperature, alarms: &s.alarms, raw_temperature: s.raw_temperature, stale: s.stale }
+    }
+}
+
+/// `has_pwm_control` stays: it drops when the agent gives up on a failing fan.
+#[derive(Serialize)]
+struct CompactFan<'a> {
+    id: &'a str,
+    rpm: Option<u32>,
+    speed: u8,
+    #[serde(rename = "targetSpeed")]
+    target_speed: u8,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pwm: Option<u8>,
+    status: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status_detail: Option<FanStatusDetail>,
+    has_pwm_control: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pwm_enable: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    control_mode: Option<&'a str>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    alarms: &'a Vec<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    drift: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    control_error: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    last_set_by: Option<&'a FanSetBy>,
+}
+
+impl<'a> From<&'a Fan> for CompactFan<'a> {
+    fn from(f: &'a Fan) -> Self {
+        Self {
+            id: &f.id,
+            rpm: f.rpm,
+            speed: f.speed,
+            target_speed: f.target_speed,
+            pwm: f.pwm,
+            status: &f.status,
+            status_detail: f.status_detail,
+            has_pwm_control: f.has_pwm_control,
+            pwm_enable: f.pwm_enable,
+            control_mode: f.control_mode.as_deref(),
+            alarms: &f.alarms,
+            drift: f.drift,
+            control_error: f.control_error.as_deref(),
+            last_set_by: f.last_set_by.as_ref(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn sensor(id: &str) -> Sensor {
+        serde_json::from_value(json!({
+            "id": id, "name": "Tctl", "temperature": 45.5, "type": "cpu", "max_temp": 95.0, "crit_temp": null,
+            "chip": "k10temp", "hardwareName": "AMD Ryzen 7", "source": "/sys/class/hwmon/hwmon1/temp1_input",
+        }))
+        .unwrap()
+    }
+
+    fn fan(id: &str) -> Fan {
+        serde_json::from_value(json!({
+            "id": id, "name": "CPU Fan", "rpm": 900, "speed": 40, "targetSpeed": 40, "status": "ok",
+            "has_pwm_control": true, "pwm_file": "/sys/class/hwmon/hwmon2/pwm1",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn full_entries_keep_the_metadata() {
+        assert_eq!(serde_json::to_value([sensor("k10temp_tctl")]).unwrap(), json!([{
+            "id": "k10temp_tctl", "name": "Tctl", "temperature": 45.5, "type": "cpu", "max_temp": 95.0, "crit_temp": null,
+            "chip": "k10temp", "hardwareName": "AMD Ryzen 7", "source": "/sys/class/hwmon/hwmon1/temp1_input",
+        }]));
+        assert_eq!(serde_json::to_value([fan("it8628_fan_1")]).unwrap(), json!([{
+            "id": "it8628_fan_1", "name": "CPU Fan", "rpm": 900, "speed": 40, "targetSpeed": 40, "status": "ok",
+            "has_pwm_control": true, "pwm_file": "/sys/class/hwmon/hwmon2/pwm1",
+        }]));
+    }
+
+    #[test]
+    fn compact_entries_for_registered_devices_only() {
+        let registered = RegisteredHardware::new(&[sensor("k10temp_tctl")], &[fan("it8628_fan_1")]);
+
+        let mut alarmed = sensor("k10temp_tctl");
+        alarmed.alarms = vec!["max".to_string()];
+        alarmed.raw_temperature = Some(46.1);
+        assert_eq!(serde_json::to_value(registered.sensors(&[alarmed, sensor("nvme_composite")])).unwrap(), json!([
+            {"id": "k10temp_tctl", "temperature": 45.5, "alarms": ["max"], "raw_temperature": 46.1},
+            // Hot-plugged after registration: the backend doesn't know its metadata yet
+            {
+                "id": "nvme_composite", "name": "Tctl", "temperature": 45.5, "type": "cpu", "max_temp": 95.0, "crit_temp": null,
+                "chip": "k10temp", "hardwareName": "AMD Ryzen 7", "source": "/sys/class/hwmon/hwmon1/temp1_input",
+            },
+        ]));
+        assert_eq!(serde_json::to_value(registered.fans(&[fan("it8628_fan_1")])).unwrap(), json!([
+            {"id": "it8628_fan_1", "rpm": 900, "speed": 40, "targetSpeed": 40, "status": "ok", "has_pwm_control": true},
+        ]));
+    }
+
+    #[test]
+    fn data_message_is_byte_for_byte_the_value_tree_it_replaced() {
+        let mut alarmed = sensor("nvme_composite");
+        alarmed.alarms = vec!["crit".to_string()];
+        alarmed.raw_temperature = Some(71.25);
+        let sensors = vec![sensor("k10temp_tctl"), alarmed];
+        let fans = vec![fan("it8628_fan_1")];
+        let health = SystemHealth { cpu_usage: 12.5, memory_usage: 41.0, agent_uptime: 3600.0, throttled: Some(false), connection: None, storage_health: Vec::new(), notifications: None };
+        let status = json!({"failsafe_active": false, "reconnects": 2});
+        let mut buf = Vec::new();
+
+        let mut expected = json!({"type": "data", "data": {
+            "agentId": "linux-nas-1a2b3c4d", "timestamp": 1_760_601_234_000_i64, "uptime_ms": 3_600_000, "unit": "celsius",
+            "sensors": serde_json::to_value(&sensors).unwrap(), "fans": serde_json::to_value(&fans).unwrap(),
+            "systemHealth": health,
+        }});
+        expected["data"]["status"] = status.clone();
+        DataMessage::new("linux-nas-1a2b3c4d", 1_760_601_234_000, 3_600_000, Entries::full(&sensors), Entries::full(&fans), &health)
+            .with_status(Some(status))
+            .write_into(&mut buf)
+            .unwrap();
+        assert_eq!(std::str::from_utf8(&buf).unwrap(), expected.to_string());
+
+        // Compact: the second sensor was hot-plugged after registration
+        let registered = RegisteredHardware::new(&sensors[..1], &fans);
+        let mut expected = json!({"type": "data", "data": {
+            "agentId": "linux-nas-1a2b3c4d", "timestamp": 1_760_601_234_000_i64, "uptime_ms": 3_600_000, "unit": "celsius",
+            "sensors": [serde_json::to_value(CompactSensor::from(&sensors[0])).unwrap(), serde_json::to_value(&sensors[1]).unwrap()],
+            "fans": [serde_json::to_value(CompactFan::from(&fans[0])).unwrap()],
+            "systemHealth": health,
+        }});
+        expected["data"]["payload"] = json!(COMPACT);
+        DataMessage::new("linux-nas-1a2b3c4d", 1_760_601_234_000, 3_600_000, registered.sensors(&sensors), registered.fans(&fans), &health)
+            .write_into(&mut buf)
+            .unwrap();
+        assert_eq!(std::str::from_utf8(&buf).unwrap(), expected.to_string());
+    }
+
+    #[test]
+    fn sensor_monitoring_off_sends_no_sensors_and_says_so() {
+        let fans = vec![fan("it8628_fan_1")];
+        let health = SystemHealth { cpu_usage: 12.5, memory_usage: 41.0, agent_uptime: 3600.0, throttled: None, connection: None, storage_health: Vec::new(), notifications: None };
+        let message = |enabled: bool| {
+            let sensors = if enabled { vec![sensor("k10temp_tctl")] } else { Vec::new() };
+            let mut buf = Vec::new();
+            DataMessage::new("linux-nas-1a2b3c4d", 1_760_601_234_000, 3_600_000, Entries::full(&sensors), Entries::full(&fans), &health)
+                .with_sensor_monitoring(enabled)
+                .write_into(&mut buf)
+                .unwrap();
+            serde_json::from_slice::<serde_json::Value>(&buf).unwrap()
+        };
+
+        let off = message(false);
+        assert_eq!(off["data"]["sensors"], json!([]));
+        assert_eq!(off["data"]["sensor_monitoring"], false);
+        assert_eq!(off["data"]["fans"][0]["id"], "it8628_fan_1");
+
+        let on = message(true);
+        assert_eq!(on["data"]["sensors"][0]["id"], "k10temp_tctl");
+        assert!(on["data"].get("sensor_monitoring").is_none());
+    }
+
+    #[test]
+    fn oversized_messages_keep_cpu_gpu_and_motherboard_sensors_first() {
+        let typed = |id: &str, sensor_type: &str| Sensor { sensor_type: sensor_type.into(), ..sensor(id) };
+        // Payload order: by type, then id
+        let mut sensors = vec![typed("k10temp_tctl", "cpu")];
+        sensors.extend((0..90).map(|i| typed(&format!("drivetemp_{:02}", i), "hdd")));
+        sensors.push(typed("nct6798_systin", "motherboard"));
+        sensors.push(typed("nvme_composite", "nvme"));
+
+        let message = |sensors: &[Sensor]| json!({"type": "data", "data": {"agentId": "linux-jbod-1", "sensors": sensors}});
+        let mut full = message(&sensors);
+        assert_eq!(truncate_sensors(&mut full, "/data/sensors", &sensors, 0, usize::MAX), 0);
+
+        // Room for about ten sensors
+        let limit = message(&sensors[..10]).to_string().len();
+        let mut data = message(&sensors);
+        let dropped = truncate_sensors(&mut data, "/data/sensors", &sensors, limit, usize::MAX);
+        assert!(data.to_string().len() + TRUNCATED_FIELD_BYTES <= limit);
+
+        let kept: Vec<&str> = data["data"]["sensors"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap()).collect();
+        assert_eq!(kept.len() + dropped, sensors.len());
+        assert_eq!(&kept[..3], ["k10temp_tctl", "drivetemp_00", "drivetemp_01"]);
+        assert!(kept.contains(&"nct6798_systin"));
+        assert!(!kept.contains(&"drivetemp_89") && !kept.contains(&"nvme_composite"));
+
+        // Count cap alone
+        let mut registration = json!({"capabilities": {"sensors": sensors}});
+        assert_eq!(truncate_sensors(&mut registration, "/capabilities/sensors", &sensors, 0, 2), sensors.len() - 2);
+        assert_eq!(registration["capabilities"]["sensors"][1]["id"], "nct6798_systin");
+    }
+}
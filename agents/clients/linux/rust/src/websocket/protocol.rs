@@ -0,0 +1,187 @@
+//! Protocol versioning and feature negotiation for the registration handshake.
+//!
+//! The agent registers with `protocol_version` plus the commands and optional features
+//! it supports. The backend answers (in `registered`, or a `protocolMismatch`) with the
+//! features it understands; optional outgoing messages the backend doesn't list are
+//! switched off instead of failing. A backend that sends no list predates negotiation
+//! and keeps everything enabled, except opt-in features, which change the shape of
+//! messages every backend reads and are only used once the backend lists them.
+
+use std::collections::BTreeSet;
+
+use tracing::{info, warn};
+
+pub use crate::version::PROTOCOL_VERSION;
+
+/// `naming_version` of the registration: how sensor names are built.
+/// 1: `name` is the friendly, brand-decorated name.
+/// 2: `name` is the raw chip + label and the friendly one is in `display_name`.
+/// `name` is deprecated and dropped after this release: key on `id`, show `display_name`.
+pub const SENSOR_NAMING_VERSION: u32 = 2;
+
+/// Command types handled by `handle_command`.
+pub const SUPPORTED_COMMANDS: &[&str] = &[
+    "setFanSpeed",
+    "emergencyStop",
+    "releaseControl",
+    "restoreFanToAuto",
+    "retryFanControl",
+    "setFanMode",
+    "setUpdateInterval",
+    "setFanStep",
+    "setHysteresis",
+    "setEmergencyTemp",
+    "setSmoothing",
+    "setLogLevel",
+    "setFailsafeSpeed",
+    "setEnableFanControl",
+    "setAgentName",
+    "setExcludedSensors",
+    "setVirtualSensor",
+    "disableChip",
+    "enableChip",
+    "setAuthToken",
+    "selfUpdate",
+    "ping",
+    "getDiagnostics",
+    "getSensorStats",
+    "resetSensorStats",
+    "getEvents",
+    "getConfigHistory",
+    "clearSafeMode",
+    "requestData",
+    "characterizeFans",
+    "getCharacterization",
+    "cancelCharacterization",
+];
+
+/// `commandResponse` replayed for redelivered commandIds
+pub const FEATURE_COMMAND_DEDUP: &str = "command_dedup";
+/// `configurationApplied` reports for pushed settings
+pub const FEATURE_CONFIGURATION_APPLIED: &str = "configuration_applied";
+/// `emergencyAlert` after an offline emergency
+pub const FEATURE_EMERGENCY_ALERT: &str = "emergency_alert";
+/// `status` block (failsafe/outage history) in data messages
+pub const FEATURE_TELEMETRY_STATUS: &str = "telemetry_status";
+/// `getDiagnostics` hardware dump
+pub const FEATURE_DIAGNOSTICS: &str = "diagnostics";
+/// `selfUpdate` from the Hub
+pub const FEATURE_SELF_UPDATE: &str = "self_update";
+/// `emergency_temp_by_type` / `setEmergencyTemp` with `sensorType`
+pub const FEATURE_SENSOR_TYPE_EMERGENCY_TEMP: &str = "sensor_type_emergency_temp";
+/// Data messages without per-device metadata (`backend.payload_profile` = compact)
+pub const FEATURE_COMPACT_PAYLOAD: &str = "compact_payload";
+/// `configUpdate` messages while connected
+pub const FEATURE_CONFIG_UPDATE: &str = "config_update";
+/// `pong` echoing the ping's timestamp, `pongAck` with the measured clock offset
+pub const FEATURE_TIME_SYNC: &str = "time_sync";
+/// `emergencyAction` results of the configured emergency actions
+pub const FEATURE_EMERGENCY_ACTION: &str = "emergency_action";
+/// Data messages as binary frames of deflated JSON (`backend.compression` = deflate)
+pub const FEATURE_DEFLATE_DATA: &str = "deflate_data";
+/// Command responses over `chunked::CHUNK_THRESHOLD` sent gzipped in chunks
+pub const FEATURE_CHUNKED_RESPONSES: &str = "chunked_responses";
+
+/// Optional features this agent offers.
+pub const SUPPORTED_FEATURES: &[&str] = &[
+    FEATURE_COMMAND_DEDUP,
+    FEATURE_CONFIGURATION_APPLIED,
+    FEATURE_EMERGENCY_ALERT,
+    FEATURE_TELEMETRY_STATUS,
+    FEATURE_DIAGNOSTICS,
+    FEATURE_SELF_UPDATE,
+    FEATURE_SENSOR_TYPE_EMERGENCY_TEMP,
+    FEATURE_COMPACT_PAYLOAD,
+    FEATURE_CONFIG_UPDATE,
+    FEATURE_TIME_SYNC,
+    FEATURE_EMERGENCY_ACTION,
+    FEATURE_DEFLATE_DATA,
+    FEATURE_CHUNKED_RESPONSES,
+];
+
+/// Features off until the backend of the current connection lists them.
+const OPT_IN_FEATURES: &[&str] = &[FEATURE_COMPACT_PAYLOAD, FEATURE_DEFLATE_DATA, FEATURE_CHUNKED_RESPONSES];
+
+/// Features in use with the current backend.
+#[derive(Debug, Clone)]
+pub(crate) struct Negotiated {
+    enabled: BTreeSet<&'static str>,
+}
+
+impl Default for Negotiated {
+    fn default() -> Self {
+        Self { enabled: SUPPORTED_FEATURES.iter().copied().filter(|f| !OPT_IN_FEATURES.contains(f)).collect() }
+    }
+}
+
+impl Negotiated {
+    pub(crate) fn is_enabled(&self, feature: &str) -> bool {
+        self.enabled.contains(feature)
+    }
+
+    /// A new connection may reach a different backend: drop opt-in features until its
+    /// `registered` confirms them. The rest stays as last negotiated.
+    pub(crate) fn new_connection(&mut self) {
+        self.enabled.retain(|f| !OPT_IN_FEATURES.contains(f));
+    }
+
+    /// Apply the backend's side of the handshake (`data` of `registered` or
+    /// `protocolMismatch`). Missing fields leave the defaults in place.
+    pub(crate) fn apply(&mut self, data: &serde_json::Value) {
+        if let Some(version) = data.get("protocol_version").and_then(|v| v.as_u64()) {
+            if version != u64::from(PROTOCOL_VERSION) {
+                info!("Backend speaks protocol v{}, agent v{}; using the common feature set",
+                      version, PROTOCOL_VERSION);
+            }
+        }
+
+        let Some(features) = data.get("features").and_then(|v| v.as_array()) else {
+            *self = Self::default();
+            return;
+        };
+        let offered: BTreeSet<&str> = features.iter().filter_map(|v| v.as_str()).collect();
+        self.enabled = SUPPORTED_FEATURES.iter().copied().filter(|f| offered.contains(f)).collect();
+
+        let disabled: Vec<_> = SUPPORTED_FEATURES.iter()
+            .filter(|f| !self.enabled.contains(*f) && !OPT_IN_FEATURES.contains(f))
+            .collect();
+        if !disabled.is_empty() {
+            warn!("Backend does not support {:?}; those features are disabled", disabled);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backend_feature_subset_disables_the_rest() {
+        let mut negotiated = Negotiated::default();
+        negotiated.apply(&serde_json::json!({
+            "protocol_version": 2,
+            "features": ["telemetry_status", "delta_telemetry"],
+        }));
+        assert!(negotiated.is_enabled(FEATURE_TELEMETRY_STATUS));
+        assert!(!negotiated.is_enabled(FEATURE_EMERGENCY_ALERT));
+        assert!(!negotiated.is_enabled("delta_telemetry"));
+
+        // A backend without negotiation gets everything back
+        negotiated.apply(&serde_json::json!({"status": "success"}));
+        assert!(negotiated.is_enabled(FEATURE_EMERGENCY_ALERT));
+    }
+
+    #[test]
+    fn opt_in_features_need_the_backend_to_list_them() {
+        let mut negotiated = Negotiated::default();
+        assert!(!negotiated.is_enabled(FEATURE_COMPACT_PAYLOAD));
+        negotiated.apply(&serde_json::json!({"status": "success"}));
+        assert!(!negotiated.is_enabled(FEATURE_COMPACT_PAYLOAD));
+
+        negotiated.apply(&serde_json::json!({"features": ["telemetry_status", "compact_payload"]}));
+        assert!(negotiated.is_enabled(FEATURE_COMPACT_PAYLOAD));
+        negotiated.new_connection();
+        assert!(!negotiated.is_enabled(FEATURE_COMPACT_PAYLOAD));
+        assert!(negotiated.is_enabled(FEATURE_TELEMETRY_STATUS));
+    }
+}
@@ -8,7 +8,8 @@ use tracing::{debug, error, info, warn};
 use crate::daemon::systemd::is_systemd_service_active;
 
 impl super::client::WebSocketClient {
-    /// Create a lightweight clone for spawning the update task.
+    /// Create a lightweight clone (shared state) for spawned tasks: self-update and
+    /// the data sender.
     pub(crate) fn clone_for_update(&self) -> Self {
         Self {
             config: Arc::clone(&self.config),
@@ -16,6 +17,36 @@ impl super::client::WebSocketClient {
             running: Arc::clone(&self.running),
             failsafe_active: Arc::clone(&self.failsafe_active),
             last_reported_error: Arc::clone(&self.last_reported_error),
+            pending_emergency: Arc::clone(&self.pending_emergency),
+            failsafe_controller: Arc::clone(&self.failsafe_controller),
+            crit_alarm_tripped: Arc::clone(&self.crit_alarm_tripped),
+            local_curves: Arc::clone(&self.local_curves),
+            emergency_actions: Arc::clone(&self.emergency_actions),
+            link_status: Arc::clone(&self.link_status),
+            command_cache: Arc::clone(&self.command_cache),
+            negotiated: Arc::clone(&self.negotiated),
+            registered_hardware: Arc::clone(&self.registered_hardware),
+            hardware_snapshot: Arc::clone(&self.hardware_snapshot),
+            unknown_message_types: Arc::clone(&self.unknown_message_types),
+            sensor_stats: Arc::clone(&self.sensor_stats),
+            events: Arc::clone(&self.events),
+            fan_control_conflicts: Arc::clone(&self.fan_control_conflicts),
+            environment: Arc::clone(&self.environment),
+            send_errors: Arc::clone(&self.send_errors),
+            resume: Arc::clone(&self.resume),
+            reconnect_now: Arc::clone(&self.reconnect_now),
+            data_message_bytes: Arc::clone(&self.data_message_bytes),
+            started: self.started,
+            last_cycle_ms: Arc::clone(&self.last_cycle_ms),
+            clock_skew: Arc::clone(&self.clock_skew),
+            link_quality: Arc::clone(&self.link_quality),
+            frame_interval: Arc::clone(&self.frame_interval),
+            last_disconnect: Arc::clone(&self.last_disconnect),
+            notifications: Arc::clone(&self.notifications),
+            safe_mode: Arc::clone(&self.safe_mode),
+            data_requests: Arc::clone(&self.data_requests),
+            state_file: Arc::clone(&self.state_file),
+            characterization: Arc::clone(&self.characterization),
         }
     }
 
@@ -45,7 +76,7 @@ impl super::client::WebSocketClient {
         }
 
         let arch = crate::app::platform::project_arch();
-        let server_url = self.config.read().await.backend.server_url.clone();
+        let server_url = self.config.load().backend.server_url.clone();
 
         // Convert ws://host:port/websocket to http://host:port
         let base_url = server_url.replace("ws://", "http://").replace("/websocket", "");
@@ -132,6 +163,11 @@ impl super::client::WebSocketClient {
         }
 
         info!("✅ Update applied successfully. Restarting service...");
+        if let Err(e) = self.config.flush().await {
+            warn!("Failed to save configuration before restart: {:#}", e);
+        }
+        // A restart for an update is not a crash
+        crate::daemon::safe_mode::update(|history| history.clean_exit());
 
         // Restart service
         #[cfg(target_os = "linux")]
@@ -155,9 +191,12 @@ impl super::client::WebSocketClient {
                 cmd.arg("--daemon-child");
 
                 // Inherit log level if it was set explicitly
-                let config = self.config.read().await;
+                let config = self.config.load();
                 cmd.arg("--log-level").arg(&config.agent.log_level);
                 drop(config);
+                if std::env::args().any(|a| a == "--dry-run") {
+                    cmd.arg("--dry-run");
+                }
 
                 let err = cmd.exec();
 
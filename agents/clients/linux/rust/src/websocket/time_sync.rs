@@ -0,0 +1,88 @@
+//! Clock-sync hints for the backend.
+//!
+//! Data messages are stamped with the agent's wall clock, which on a machine with a
+//! drifting RTC and no NTP can be far off. Each data message also carries `uptime_ms`
+//! (monotonic, since the agent started), and a `ping` is answered with the ping's own
+//! timestamp echoed next to the agent's time, so the backend can work out round-trip
+//! and offset. A backend that does sends the offset back in `pongAck`; one past
+//! `CLOCK_SKEW_WARN_MS` is logged.
+
+use serde_json::{json, Value};
+
+/// Offset between the backend's clock and ours worth a warning.
+pub(crate) const CLOCK_SKEW_WARN_MS: i64 = 2_000;
+
+/// `pong` for `ping`. `ping_timestamp` is the ping's `timestamp` (null without one).
+pub(crate) fn pong(ping: &Value, now_ms: i64, uptime_ms: u64) -> Value {
+    let sent = ping
+        .get("data")
+        .and_then(|d| d.get("timestamp"))
+        .or_else(|| ping.get("timestamp"))
+        .and_then(Value::as_i64);
+    json!({
+        "type": "pong",
+        "timestamp": now_ms,
+        "data": {
+            "ping_timestamp": sent,
+            "agent_time": now_ms,
+            "uptime_ms": uptime_ms,
+        }
+    })
+}
+
+/// `clock_offset_ms` of a `pongAck` (backend clock minus agent clock).
+pub(crate) fn acked_offset(message: &Value) -> Option<i64> {
+    let offset = message.get("data")?.get("clock_offset_ms")?;
+    offset.as_i64().or_else(|| offset.as_f64().map(|ms| ms.round() as i64))
+}
+
+/// Whether the last reported offset was past the threshold, so a skewed clock is
+/// logged once rather than on every ping.
+#[derive(Debug, Default)]
+pub(crate) struct ClockSkew {
+    skewed: bool,
+}
+
+impl ClockSkew {
+    /// Record an offset. Some(true) when the clock just went out of sync, Some(false)
+    /// when it is back within the threshold, None when nothing changed.
+    pub(crate) fn observe(&mut self, offset_ms: i64) -> Option<bool> {
+        let skewed = offset_ms.unsigned_abs() > CLOCK_SKEW_WARN_MS as u64;
+        if skewed == self.skewed {
+            return None;
+        }
+        self.skewed = skewed;
+        Some(skewed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pong_echoes_the_ping_timestamp_next_to_ours() {
+        let ping = json!({"type": "ping", "data": {"timestamp": 1_760_601_234_000_i64}});
+        assert_eq!(pong(&ping, 1_760_601_299_500, 86_400_000), json!({
+            "type": "pong",
+            "timestamp": 1_760_601_299_500_i64,
+            "data": {"ping_timestamp": 1_760_601_234_000_i64, "agent_time": 1_760_601_299_500_i64, "uptime_ms": 86_400_000},
+        }));
+        // Older backends put it at the top level, or send none
+        assert_eq!(pong(&json!({"type": "ping", "timestamp": 5}), 9, 1)["data"]["ping_timestamp"], 5);
+        assert!(pong(&json!({"type": "ping"}), 9, 1)["data"]["ping_timestamp"].is_null());
+    }
+
+    #[test]
+    fn skew_is_reported_on_crossing_the_threshold_only() {
+        assert_eq!(acked_offset(&json!({"type": "pongAck", "data": {"clock_offset_ms": -65_000}})), Some(-65_000));
+        assert_eq!(acked_offset(&json!({"type": "pongAck", "data": {"clock_offset_ms": 12.6}})), Some(13));
+        assert_eq!(acked_offset(&json!({"type": "pongAck", "data": {}})), None);
+
+        let mut skew = ClockSkew::default();
+        assert_eq!(skew.observe(150), None);
+        assert_eq!(skew.observe(-65_000), Some(true));
+        assert_eq!(skew.observe(-64_000), None);
+        assert_eq!(skew.observe(CLOCK_SKEW_WARN_MS), Some(false));
+    }
+}
@@ -0,0 +1,233 @@
+//! `backend.transport = "udp-broadcast"`: telemetry for isolated networks without a
+//! Hub. Each cycle's data message goes out as UDP datagrams to `udp_address`:`udp_port`
+//! for a collector that only listens. There is no registration and no command channel,
+//! so fan control stays with the failsafe for the whole run: fans on their
+//! `hardware.local_curves` (local_curve.rs) or at `failsafe_speed`, 100% on an
+//! emergency, released with the usual hysteresis.
+//!
+//! Datagrams use compact entries (there is no registration to repeat metadata for) and
+//! carry a `part` field ({seq, index, count}). A message that doesn't fit one datagram
+//! is split across several, sensors spread evenly and fans in the first.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use anyhow::{Context, Result};
+use tokio::net::UdpSocket;
+use tokio::time;
+use tracing::{debug, error, info, warn};
+
+use super::client::WebSocketClient;
+use super::payload::{DataMessage, RegisteredHardware};
+use crate::config::types::BackendSettings;
+use crate::daemon::notify;
+use crate::hardware::types::{Fan, Sensor, SystemHealth};
+
+/// Payload of one datagram: a 1500-byte Ethernet MTU minus the IPv4 and UDP headers.
+pub(crate) const MAX_DATAGRAM_BYTES: usize = 1472;
+
+/// The data message of cycle `seq` as datagrams of at most `max_bytes`, in as few
+/// parts as possible. A part holding a single sensor is sent whatever its size.
+/// Every part carries `interval_ms` (adaptive_interval.rs) when set, and
+/// `sensor_monitoring: false` when the sensors are left out.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn datagrams(
+    agent_id: &str,
+    timestamp: i64,
+    uptime_ms: u64,
+    seq: u64,
+    interval_ms: Option<u64>,
+    sensor_monitoring: bool,
+    sensors: &[Sensor],
+    fans: &[Fan],
+    health: &SystemHealth,
+    max_bytes: usize,
+) -> serde_json::Result<Vec<Vec<u8>>> {
+    let registered = RegisteredHardware::new(sensors, fans);
+    let mut count = 1;
+    loop {
+        let size = sensors.len().div_ceil(count).max(1);
+        let parts: Vec<&[Sensor]> = if sensors.is_empty() { vec![sensors] } else { sensors.chunks(size).collect() };
+        let mut datagrams = Vec::with_capacity(parts.len());
+        for (index, part) in parts.iter().enumerate() {
+            let part_fans = if index == 0 { fans } else { &[] };
+            let mut buf = Vec::new();
+            DataMessage::new(agent_id, timestamp, uptime_ms, registered.sensors(part), registered.fans(part_fans), health)
+                .with_part(seq, index, parts.len())
+                .with_interval(interval_ms)
+                .with_sensor_monitoring(sensor_monitoring)
+                .write_into(&mut buf)?;
+            datagrams.push(buf);
+        }
+        if size == 1 || datagrams.iter().all(|d| d.len() <= max_bytes) {
+            return Ok(datagrams);
+        }
+        let total: usize = datagrams.iter().map(Vec::len).sum();
+        count = (parts.len() + 1).max(total.div_ceil(max_bytes));
+    }
+}
+
+/// A socket that may send to `target`, from `backend.bind_address` / `bind_interface`.
+async fn open_socket(target: SocketAddr, backend: &BackendSettings) -> Result<UdpSocket> {
+    let local = backend.bind_address.unwrap_or(match target {
+        SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+        SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+    });
+    let socket = UdpSocket::bind(SocketAddr::new(local, 0))
+        .await
+        .with_context(|| format!("Cannot bind a UDP socket to {}", local))?;
+    if target.is_ipv4() {
+        socket.set_broadcast(true).context("Cannot enable UDP broadcast")?;
+    }
+    #[cfg(target_os = "linux")]
+    if let Some(interface) = &backend.bind_interface {
+        socket
+            .bind_device(Some(interface.as_bytes()))
+            .with_context(|| format!("Cannot bind to interface {} (needs root or CAP_NET_RAW)", interface))?;
+    }
+    Ok(socket)
+}
+
+impl WebSocketClient {
+    /// Broadcast telemetry until stopped, with fans under the failsafe throughout.
+    pub(crate) async fn run_udp_broadcast(&self) -> Result<()> {
+        let (target, socket) = {
+            let config = self.config.load();
+            let target = SocketAddr::new(config.backend.udp_address, config.backend.udp_port);
+            (target, open_socket(target, &config.backend).await?)
+        };
+
+        *self.failsafe_active.write().await = true;
+        let failsafe_speed = self.config.load().hardware.failsafe_speed;
+        info!("UDP broadcast mode: telemetry to {}, no backend or commands", target);
+        info!("Fans held by the local failsafe: {}%, 100% on emergency", failsafe_speed);
+        if let Err(e) = self.set_all_fans_to_speed(failsafe_speed).await {
+            error!("Failed to set failsafe fan speed: {}", e);
+        }
+        notify::ready();
+        notify::status(&format!("UDP broadcast to {} - fans at failsafe ({}%)", target, failsafe_speed));
+
+        let mut seq: u64 = 0;
+        while *self.running.read().await {
+            self.hardware_monitor.next_cycle();
+            let broadcast = self.broadcast_cycle(&socket, target, seq).await;
+            self.cycle_done();
+            match broadcast {
+                Ok(()) => {
+                    if let Some(line) = self.send_errors.recovered("udp_send") {
+                        info!("UDP broadcast {}", line);
+                    }
+                }
+                Err(e) => {
+                    let message = format!("UDP broadcast failed: {:#}", e);
+                    self.write_state(|state| state.last_error = Some(message.clone()));
+                    if let Some(line) = self.send_errors.failed("udp_send", message) {
+                        warn!("{}", line);
+                    }
+                }
+            }
+            seq = seq.wrapping_add(1);
+            time::sleep(self.next_frame_interval()).await;
+        }
+        Ok(())
+    }
+
+    async fn broadcast_cycle(&self, socket: &UdpSocket, target: SocketAddr, seq: u64) -> Result<()> {
+        let sensors = self.hardware_monitor.discover_sensors().await?;
+        if let Err(e) = self.check_failsafe_sensors(&sensors).await {
+            error!("Failsafe check failed: {}", e);
+        }
+        let fans = self.hardware_monitor.discover_fans().await?;
+        let health = self.hardware_monitor.get_system_info().await?;
+
+        let config = self.config.load();
+        let monitoring = config.hardware.enable_sensor_monitoring;
+        let timestamp = chrono::Utc::now().timestamp_millis();
+        let interval_ms = self.frame_interval_ms(&sensors);
+        let reported = if monitoring { &sensors[..] } else { &[] };
+        let datagrams = datagrams(&config.agent.id, timestamp, self.uptime_ms(), seq, interval_ms, monitoring, reported, &fans, &health,
+                                  MAX_DATAGRAM_BYTES)?;
+        for datagram in &datagrams {
+            socket.send_to(datagram, target).await.with_context(|| format!("Cannot send to {}", target))?;
+        }
+        debug!("Broadcast telemetry: {} sensors, {} fans in {} datagram(s)", reported.len(), fans.len(), datagrams.len());
+        self.write_state(|state| {
+            state.failsafe_active = true;
+            state.last_data_sent = Some(timestamp);
+            state.controllable_fans = fans.iter().filter(|f| f.has_pwm_control).count();
+        });
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sensor(i: usize) -> Sensor {
+        Sensor {
+            id: format!("drivetemp_sd{}_temp1", i).into(),
+            name: format!("Storage sd{}", i).into(),
+            display_name: None,
+            temperature: 30.0 + i as f64,
+            sensor_type: "hdd".into(),
+            max_temp: Some(60.0),
+            crit_temp: None,
+            chip: Some("drivetemp".into()),
+            hardware_name: None,
+            source: None,
+            alarms: Vec::new(),
+            raw_temperature: None,
+            stale: false,
+        }
+    }
+
+    fn fan() -> Fan {
+        Fan {
+            id: "it8628_fan_1".to_string(),
+            name: "it8628 Fan 1".to_string(),
+            rpm: Some(900),
+            speed: 40,
+            target_speed: 40,
+            pwm: None,
+            status: "ok".to_string(),
+            status_detail: None,
+            has_pwm_control: true,
+            control_method: Some("pwm".to_string()),
+            pwm_file: Some("/sys/class/hwmon/hwmon3/pwm1".to_string()),
+            pwm_enable: None,
+            control_mode: None,
+            zone: None,
+            alarms: Vec::new(),
+            drift: false,
+            control_error: None,
+            last_set_by: None,
+        }
+    }
+
+    #[test]
+    fn large_messages_are_split_into_datagrams_that_fit() {
+        let health = SystemHealth { cpu_usage: 3.0, memory_usage: 20.0, agent_uptime: 60.0, throttled: None, connection: None, storage_health: Vec::new(), notifications: None };
+        let fans = vec![fan()];
+
+        let one = datagrams("linux-lab-1", 1, 2, 7, None, true, &[sensor(0)], &fans, &health, MAX_DATAGRAM_BYTES).unwrap();
+        assert_eq!(one.len(), 1);
+        let message: serde_json::Value = serde_json::from_slice(&one[0]).unwrap();
+        assert_eq!(message["data"]["payload"], "compact");
+        assert_eq!(message["data"]["part"], serde_json::json!({"seq": 7, "index": 0, "count": 1}));
+        assert!(message["data"].get("interval_ms").is_none());
+
+        let sensors: Vec<Sensor> = (0..60).map(sensor).collect();
+        let parts = datagrams("linux-lab-1", 1, 2, 8, Some(1500), true, &sensors, &fans, &health, MAX_DATAGRAM_BYTES).unwrap();
+        assert!(parts.len() > 1);
+        let mut ids = Vec::new();
+        for (index, datagram) in parts.iter().enumerate() {
+            assert!(datagram.len() <= MAX_DATAGRAM_BYTES, "part {} is {} bytes", index, datagram.len());
+            let message: serde_json::Value = serde_json::from_slice(datagram).unwrap();
+            assert_eq!(message["data"]["part"], serde_json::json!({"seq": 8, "index": index, "count": parts.len()}));
+            assert_eq!(message["data"]["fans"].as_array().unwrap().len(), usize::from(index == 0));
+            assert_eq!(message["data"]["interval_ms"], 1500);
+            ids.extend(message["data"]["sensors"].as_array().unwrap().iter().map(|s| s["id"].as_str().unwrap().to_string()));
+        }
+        assert_eq!(ids, sensors.iter().map(|s| s.id.to_string()).collect::<Vec<_>>());
+    }
+}
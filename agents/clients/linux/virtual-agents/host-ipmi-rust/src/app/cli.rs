@@ -28,6 +28,8 @@ Config & Debug:
 IPMI:
       --profile <PATH>          Path to BMC JSON profile (default: ./profile.json)
       --dry-run                 Log ipmitool commands without executing them
+      --validate-profile <PATH> Check a BMC profile and list every problem, then exit
+      --fetch-profile           Download the profile matching this board (FRU) from the profile repository
 ";
 
 #[derive(Parser, Debug)]
@@ -98,6 +100,14 @@ pub struct Args {
     #[arg(long, help_heading = "IPMI")]
     pub dry_run: bool,
 
+    /// Check a BMC profile and list every problem, then exit
+    #[arg(long = "validate-profile", value_name = "PATH", help_heading = "IPMI")]
+    pub validate_profile: Option<String>,
+
+    /// Download the profile matching this board (FRU) from the profile repository
+    #[arg(long = "fetch-profile", help_heading = "IPMI")]
+    pub fetch_profile: bool,
+
     /// Internal flag for daemon child process (do not use directly)
     #[arg(long, hide = true)]
     pub daemon_child: bool,
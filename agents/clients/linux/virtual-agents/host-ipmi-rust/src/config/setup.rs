@@ -177,6 +177,8 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
             reconnect_interval: 5.0,
             max_reconnect_attempts: -1,
             connection_timeout: 10.0,
+            profile_repository_url: existing_config.as_ref()
+                .and_then(|c| c.backend.profile_repository_url.clone()),
         },
         hardware: HardwareSettings {
             enable_fan_control,
@@ -186,6 +188,17 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
             emergency_temp: 85.0,
             failsafe_speed,
             excluded_sensors: Vec::new(),
+            ipmi_retries: existing_config.as_ref()
+                .map(|c| c.hardware.ipmi_retries)
+                .unwrap_or_else(default_ipmi_retries),
+            ipmi_retry_backoff_ms: existing_config.as_ref()
+                .map(|c| c.hardware.ipmi_retry_backoff_ms)
+                .unwrap_or_else(default_ipmi_retry_backoff_ms),
+            ipmi_timeout_secs: existing_config.as_ref()
+                .map(|c| c.hardware.ipmi_timeout_secs)
+                .unwrap_or_else(default_ipmi_timeout_secs),
+            report_status_sensors: existing_config.as_ref()
+                .is_some_and(|c| c.hardware.report_status_sensors),
         },
         logging: LoggingSettings {
             enable_file_logging: true,
@@ -193,6 +206,7 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
             max_log_size_mb: 10,
             log_retention_days: 7,
         },
+        ipmi: existing_config.as_ref().map(|c| c.ipmi.clone()).unwrap_or_default(),
         // Wizard setups start without credentials; enrollment needs a deploy
         // token from the Hub's Deployment page (see enrollment_token)
         auth: AuthSettings::default(),
@@ -209,7 +223,7 @@ pub async fn run_setup_wizard(config_path: Option<&str>) -> Result<()> {
     if !test_str.trim().eq_ignore_ascii_case("n") {
         println!("\nTesting IPMI connectivity...\n");
 
-        let hardware_monitor = IpmiHardwareMonitor::new(config.hardware.clone());
+        let hardware_monitor = IpmiHardwareMonitor::new(config.hardware.clone(), &config_file);
 
         let sensors = hardware_monitor.discover_sensors().await?;
         let fans = hardware_monitor.discover_fans().await?;
@@ -9,6 +9,9 @@ pub struct AgentConfig {
     pub backend: BackendSettings,
     pub hardware: HardwareSettings,
     pub logging: LoggingSettings,
+    // How ipmitool reaches the BMC. Defaults to the local /dev/ipmi0 interface.
+    #[serde(default)]
+    pub ipmi: IpmiConnection,
     // Hub credentials. Declared last so it serializes as the final section
     // of config.json. #[serde(default)] keeps pre-auth config files parsing.
     #[serde(default)]
@@ -26,6 +29,80 @@ pub struct AuthSettings {
     pub auth_token: Option<String>,
 }
 
+/// ipmitool interface and, for IPMI over LAN, the remote BMC and its credentials.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IpmiConnection {
+    /// "open" (in-band, /dev/ipmi0), "lanplus" (IPMI 2.0 over LAN) or "lan" (IPMI 1.5)
+    #[serde(default = "default_ipmi_interface")]
+    pub interface: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    #[serde(default = "default_ipmi_port")]
+    pub port: u16,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    /// Prefer password_file: a password here sits in config.json in plain text
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+    /// File whose first line is the BMC password (e.g. /etc/pankha/bmc-password, mode 0600)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub password_file: Option<String>,
+}
+
+impl Default for IpmiConnection {
+    fn default() -> Self {
+        Self {
+            interface: default_ipmi_interface(),
+            host: None,
+            port: default_ipmi_port(),
+            username: None,
+            password: None,
+            password_file: None,
+        }
+    }
+}
+
+impl IpmiConnection {
+    /// True when ipmitool talks to a BMC over the network.
+    pub fn is_remote(&self) -> bool {
+        self.interface != "open"
+    }
+
+    /// PANKHA_IPMI_HOST (plus _PORT, _USER, _PASS) switches to lanplus and
+    /// overrides config.json - used for emulator testing and ad-hoc runs.
+    pub fn with_env_overrides(mut self) -> Self {
+        if let Ok(host) = std::env::var("PANKHA_IPMI_HOST") {
+            self.interface = "lanplus".to_string();
+            self.host = Some(host);
+            if let Some(port) = std::env::var("PANKHA_IPMI_PORT").ok().and_then(|p| p.parse().ok()) {
+                self.port = port;
+            }
+            self.username = std::env::var("PANKHA_IPMI_USER").ok()
+                .or(self.username)
+                .or_else(|| Some("admin".to_string()));
+            if let Ok(pass) = std::env::var("PANKHA_IPMI_PASS") {
+                self.password = Some(pass);
+                self.password_file = None;
+            } else if self.password.is_none() && self.password_file.is_none() {
+                self.password = Some("password".to_string());
+            }
+        }
+        self
+    }
+
+    /// Copy safe to print: the password is masked.
+    pub fn redacted(&self) -> Self {
+        Self {
+            password: self.password.as_ref().map(|_| "********".to_string()),
+            ..self.clone()
+        }
+    }
+}
+
+pub fn default_ipmi_interface() -> String { "open".to_string() }
+
+pub fn default_ipmi_port() -> u16 { 623 }
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AgentSettings {
     pub id: String,
@@ -40,6 +117,10 @@ pub struct BackendSettings {
     pub reconnect_interval: f64,
     pub max_reconnect_attempts: i32, // -1 for infinite
     pub connection_timeout: f64,
+    // Profile repository index for --fetch-profile (static JSON: board ids ->
+    // profile files + sha256). Defaults to the Pankha server's own index.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub profile_repository_url: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,21 +138,52 @@ pub struct HardwareSettings {
     // makes this non-breaking for existing v0.5.2 config.json files.
     #[serde(default)]
     pub excluded_sensors: Vec<String>,
+    // ipmitool resilience: extra attempts after a transient failure (busy SDR
+    // repository, RMCP+ session setup), the first backoff delay (doubles per
+    // retry), and how long a single invocation may run before it is killed.
+    #[serde(default = "default_ipmi_retries")]
+    pub ipmi_retries: u32,
+    #[serde(default = "default_ipmi_retry_backoff_ms")]
+    pub ipmi_retry_backoff_ms: u64,
+    #[serde(default = "default_ipmi_timeout_secs")]
+    pub ipmi_timeout_secs: f64,
+    // Also report discrete/status SDR sensors (PSU status, chassis intrusion,
+    // voltage rails) with their state, and alert the backend when one turns
+    // critical. Off by default: temperature-only setups see no change.
+    #[serde(default)]
+    pub report_status_sensors: bool,
 }
 
 pub fn default_failsafe_speed() -> u8 { 70 }
 
+pub fn default_ipmi_retries() -> u32 { 2 }
+
+pub fn default_ipmi_retry_backoff_ms() -> u64 { 500 }
+
+pub fn default_ipmi_timeout_secs() -> f64 { 10.0 }
+
 impl AgentConfig {
     /// Derive the profile fetch URL from backend.server_url + agent.id.
     /// ws(s)://host:port/websocket → http(s)://host:port/api/deploy/profiles/assigned/{id}
     pub fn profile_url(&self) -> String {
-        let base = self.backend.server_url
+        format!("{}/api/deploy/profiles/assigned/{}", self.http_base(), self.agent.id)
+    }
+
+    /// Profile repository index: backend.profile_repository_url, or the
+    /// server's http(s)://host:port/api/deploy/profiles/index.
+    pub fn profile_repository_url(&self) -> String {
+        self.backend.profile_repository_url.clone()
+            .unwrap_or_else(|| format!("{}/api/deploy/profiles/index", self.http_base()))
+    }
+
+    /// ws(s)://host:port/websocket → http(s)://host:port
+    fn http_base(&self) -> String {
+        self.backend.server_url
             .replace("wss://", "https://")
             .replace("ws://", "http://")
             .trim_end_matches("/websocket")
             .trim_end_matches('/')
-            .to_string();
-        format!("{}/api/deploy/profiles/assigned/{}", base, self.agent.id)
+            .to_string()
     }
 }
 
@@ -108,6 +220,7 @@ impl Default for AgentConfig {
                 reconnect_interval: 5.0,
                 max_reconnect_attempts: -1,
                 connection_timeout: 10.0,
+                profile_repository_url: None,
             },
             hardware: HardwareSettings {
                 enable_fan_control: true,
@@ -117,6 +230,10 @@ impl Default for AgentConfig {
                 emergency_temp: 85.0,
                 failsafe_speed: 70,
                 excluded_sensors: Vec::new(),
+                ipmi_retries: default_ipmi_retries(),
+                ipmi_retry_backoff_ms: default_ipmi_retry_backoff_ms(),
+                ipmi_timeout_secs: default_ipmi_timeout_secs(),
+                report_status_sensors: false,
             },
             logging: LoggingSettings {
                 enable_file_logging: true,
@@ -124,6 +241,7 @@ impl Default for AgentConfig {
                 max_log_size_mb: 10,
                 log_retention_days: 7,
             },
+            ipmi: IpmiConnection::default(),
             auth: AuthSettings::default(),
         }
     }
@@ -8,13 +8,19 @@ pub mod ipmi;
 
 pub use ipmi::ipmi_monitor::IpmiHardwareMonitor;
 
-use types::{Sensor, Fan, SystemHealth, HardwareDumpRoot};
+use types::{Sensor, Fan, StatusSensor, SystemHealth, HardwareDumpRoot};
 
 #[async_trait]
 pub trait HardwareMonitor: Send + Sync {
     /// Discover all available temperature sensors
     async fn discover_sensors(&self) -> Result<Vec<Sensor>>;
 
+    /// Status sensors (PSU, intrusion, voltages) read by the last
+    /// `discover_sensors`; empty unless `hardware.report_status_sensors` is on.
+    async fn status_sensors(&self) -> Vec<StatusSensor> {
+        Vec::new()
+    }
+
     /// Discover all available fans
     async fn discover_fans(&self) -> Result<Vec<Fan>>;
 
@@ -24,8 +30,12 @@ pub trait HardwareMonitor: Send + Sync {
     /// Set fan speed (0-100%)
     async fn set_fan_speed(&self, fan_id: &str, speed: u8) -> Result<()>;
 
-    /// Emergency stop - set all fans to maximum
-    async fn emergency_stop(&self) -> Result<()>;
+    /// Drive every fan to maximum now (`emergencyStop`, failsafe emergency)
+    async fn max_cooling(&self) -> Result<()>;
+
+    /// Hand the fans back to the BMC's automatic control (`releaseControl`).
+    /// The next speed written takes them over again.
+    async fn release_control(&self) -> Result<()>;
 
     /// Invalidate hardware cache (call on startup/reconnection to force rediscovery)
     async fn invalidate_cache(&self);
@@ -0,0 +1,79 @@
+//! Circuit breaker for SDR polling.
+//!
+//! After a few consecutive failed polls the BMC is treated as unresponsive: polling
+//! pauses for a cooldown (doubling on every re-trip, capped), and the monitor
+//! reports its last good readings flagged stale. When the cooldown expires one
+//! probe poll runs; success closes the breaker, failure re-opens it.
+
+use std::time::{Duration, Instant};
+
+/// Consecutive failed polls before the breaker opens
+const FAILURE_THRESHOLD: u32 = 3;
+const BASE_COOLDOWN: Duration = Duration::from_secs(15);
+const MAX_COOLDOWN: Duration = Duration::from_secs(300);
+
+#[derive(Debug, Default)]
+pub(crate) struct CircuitBreaker {
+    failures: u32,
+    trips: u32,
+    open_until: Option<Instant>,
+}
+
+impl CircuitBreaker {
+    /// True if a poll may run now (closed, or cooldown over and a probe is due).
+    pub(crate) fn allow(&self, now: Instant) -> bool {
+        self.open_until.is_none_or(|until| now >= until)
+    }
+
+    /// The BMC is currently considered unresponsive.
+    pub(crate) fn is_open(&self) -> bool {
+        self.failures >= FAILURE_THRESHOLD
+    }
+
+    /// Returns true if this success closed an open breaker.
+    pub(crate) fn record_success(&mut self) -> bool {
+        let was_open = self.is_open();
+        *self = Self::default();
+        was_open
+    }
+
+    /// Returns the cooldown if this failure (re-)opened the breaker.
+    pub(crate) fn record_failure(&mut self, now: Instant) -> Option<Duration> {
+        self.failures += 1;
+        if !self.is_open() {
+            return None;
+        }
+        let cooldown = BASE_COOLDOWN.saturating_mul(2u32.saturating_pow(self.trips)).min(MAX_COOLDOWN);
+        self.trips += 1;
+        self.open_until = Some(now + cooldown);
+        Some(cooldown)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn opens_after_threshold_and_backs_off_until_a_probe_succeeds() {
+        let start = Instant::now();
+        let mut breaker = CircuitBreaker::default();
+
+        assert_eq!(breaker.record_failure(start), None);
+        assert_eq!(breaker.record_failure(start), None);
+        assert_eq!(breaker.record_failure(start), Some(BASE_COOLDOWN));
+        assert!(breaker.is_open());
+        assert!(!breaker.allow(start + Duration::from_secs(5)));
+
+        // Probe after the cooldown fails: re-open for twice as long
+        let probe = start + BASE_COOLDOWN;
+        assert!(breaker.allow(probe));
+        assert_eq!(breaker.record_failure(probe), Some(BASE_COOLDOWN * 2));
+        assert!(!breaker.allow(probe + BASE_COOLDOWN));
+
+        assert!(breaker.record_success());
+        assert!(!breaker.is_open());
+        assert!(breaker.allow(probe));
+        assert!(!breaker.record_success());
+    }
+}
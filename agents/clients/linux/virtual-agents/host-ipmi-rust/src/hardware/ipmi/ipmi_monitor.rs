@@ -2,7 +2,7 @@
 //! All hardware logic is driven by JSON profiles; this binary contains zero hardcoded hex values.
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::RwLock;
 use std::time::Instant;
@@ -15,20 +15,37 @@ use tracing::{debug, error, info, warn};
 use crate::config::types::HardwareSettings;
 use crate::hardware::HardwareMonitor;
 use crate::hardware::types::{
-    Sensor, Fan, SystemHealth,
+    Sensor, Fan, StatusSensor, SystemHealth, IpmiCommandStats,
     HardwareDumpRoot, HardwareDumpMetadata, HardwareDumpItem, HardwareDumpSensor,
 };
-use crate::profiles::types::{BmcProfile, Parsing};
+use crate::profiles::types::{BmcProfile, IpmiProtocol, Metadata, Parsing};
 use crate::profiles::loader::load_profile;
+use crate::profiles::validator::{has_errors, validate_profile, Severity};
 use crate::profiles::interpolator::{translate_speed, reverse_translate_speed, interpolate_command};
-use crate::system::executor;
-use crate::system::parser;
+use crate::system::executor::{self, ExecPolicy};
+use super::breaker::CircuitBreaker;
+use crate::system::parser::{self, ZoneMembership};
+
+/// (max_temp, crit_temp) of one SDR sensor
+type Thresholds = (Option<f64>, Option<f64>);
+
+/// Last commanded speed per zone, kept next to config.json so a restarted agent
+/// still reports the duty cycle the BMC is running at.
+const FAN_STATE_FILE: &str = "fan-state.json";
+
+/// fan-state.json beside `config_path`.
+pub fn fan_state_path(config_path: &Path) -> PathBuf {
+    config_path.with_file_name(FAN_STATE_FILE)
+}
 
 pub struct IpmiHardwareMonitor {
     settings: HardwareSettings,
     profile: RwLock<Option<BmcProfile>>,
     profile_path: PathBuf,
     initialized: AtomicBool,
+    /// Fans handed back to the BMC by reset_to_factory: the initialization commands
+    /// run again before the next speed is written
+    released: AtomicBool,
     dry_run: bool,
     start_time: Instant,
     /// Cache for last SDR CSV output to avoid double-querying within the same cycle
@@ -37,13 +54,23 @@ pub struct IpmiHardwareMonitor {
     /// Track last commanded speed per zone (zone_id → speed%).
     /// IPMI SDR only reports RPM, not duty cycle - this lets telemetry report the actual speed we set.
     commanded_speeds: Mutex<HashMap<String, u8>>,
+    /// Where commanded_speeds is persisted (None: in-memory only)
+    state_path: Option<PathBuf>,
     /// Cached sensor thresholds (SDR name → (max_temp, crit_temp)).
     /// Queried once at init - thresholds don't change at runtime.
-    sensor_thresholds: Mutex<HashMap<String, (Option<f64>, Option<f64>)>>,
+    sensor_thresholds: Mutex<HashMap<String, Thresholds>>,
+    /// Pauses SDR polling while the BMC is unresponsive
+    breaker: std::sync::Mutex<CircuitBreaker>,
+    /// Last good readings, re-sent (flagged stale) while the breaker is open
+    last_sensors: Mutex<Vec<Sensor>>,
+    last_fans: Mutex<Vec<Fan>>,
+    /// Status sensors from the last SDR read (`report_status_sensors`)
+    last_status_sensors: Mutex<Vec<StatusSensor>>,
 }
 
 impl IpmiHardwareMonitor {
-    pub fn new(settings: HardwareSettings) -> Self {
+    /// Commanded speeds are kept beside `config_path`.
+    pub fn new(settings: HardwareSettings, config_path: &Path) -> Self {
         // Determine profile path from CLI args or default
         let profile_path = std::env::args()
             .skip_while(|a| a != "--profile")
@@ -58,10 +85,26 @@ impl IpmiHardwareMonitor {
 
         let dry_run = std::env::args().any(|a| a == "--dry-run");
 
+        Self::with_profile(settings, profile_path, Some(fan_state_path(config_path)), dry_run)
+    }
+
+    /// Monitor driven by the profile at `profile_path`, independent of this
+    /// process's command line (used when hosted inside another agent). Commanded
+    /// speeds are persisted to `state_path` (see `fan_state_path`), or kept in
+    /// memory only without one.
+    pub fn with_profile(settings: HardwareSettings, profile_path: PathBuf, state_path: Option<PathBuf>, dry_run: bool) -> Self {
         // Attempt to load profile (may fail if file doesn't exist yet)
         let profile = match load_profile(&profile_path) {
-            Ok(p) => {
+            Ok(mut p) => {
                 info!("BMC profile loaded from {:?}", profile_path);
+                if !check_profile(&profile_path, &p) {
+                    // Keep the parsing rules for SDR, but never take over the fans
+                    warn!("Running in monitor-only mode (no fan control) until the profile is fixed.");
+                    if let Some(ipmi) = p.protocols.as_mut().and_then(|p| p.ipmi.as_mut()) {
+                        ipmi.fan_zones.clear();
+                        ipmi.lifecycle.initialization.clear();
+                    }
+                }
                 Some(p)
             }
             Err(e) => {
@@ -70,17 +113,53 @@ impl IpmiHardwareMonitor {
             }
         };
 
+        executor::set_policy(ExecPolicy::from_settings(&settings));
+
+        let commanded_speeds = state_path.as_deref().map(load_fan_state).unwrap_or_default();
+        if !commanded_speeds.is_empty() {
+            info!("Restored last commanded speeds for {} fan zone(s)", commanded_speeds.len());
+        }
+
         Self {
             settings,
             profile: RwLock::new(profile),
             profile_path,
             initialized: AtomicBool::new(false),
+            released: AtomicBool::new(false),
             dry_run,
             start_time: Instant::now(),
             last_sdr_cache: Mutex::new(None),
             cache_from_sdr: AtomicBool::new(false),
-            commanded_speeds: Mutex::new(HashMap::new()),
+            commanded_speeds: Mutex::new(commanded_speeds),
+            state_path,
             sensor_thresholds: Mutex::new(HashMap::new()),
+            breaker: std::sync::Mutex::new(CircuitBreaker::default()),
+            last_sensors: Mutex::new(Vec::new()),
+            last_fans: Mutex::new(Vec::new()),
+            last_status_sensors: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Write commanded speeds to the state file. Dry runs never touched the BMC,
+    /// so they leave the file alone.
+    async fn save_fan_state(&self, speeds: &HashMap<String, u8>) {
+        let Some(path) = self.state_path.as_ref().filter(|_| !self.dry_run) else {
+            return;
+        };
+        let result = if speeds.is_empty() {
+            match tokio::fs::remove_file(path).await {
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                other => other,
+            }
+        } else {
+            let sorted: std::collections::BTreeMap<_, _> = speeds.iter().collect();
+            match serde_json::to_string_pretty(&sorted) {
+                Ok(json) => tokio::fs::write(path, json).await,
+                Err(e) => Err(e.into()),
+            }
+        };
+        if let Err(e) = result {
+            warn!("Failed to update {:?}: {}", path, e);
         }
     }
 
@@ -117,25 +196,7 @@ impl IpmiHardwareMonitor {
             }
         };
 
-        info!("Running {} initialization commands...", ipmi.lifecycle.initialization.len());
-        for cmd in &ipmi.lifecycle.initialization {
-            if let Some(bytes) = &cmd.bytes {
-                info!("  Init: {} -> {}", cmd.name, bytes);
-                if self.dry_run {
-                    info!("  [DRY RUN] Would execute: ipmitool raw {}", bytes);
-                } else {
-                    match executor::run_ipmitool_raw(bytes).await {
-                        Ok(_) => info!("  Init command succeeded: {}", cmd.name),
-                        Err(e) => {
-                            if cmd.critical {
-                                return Err(anyhow!("Critical init command failed: {} - {}", cmd.name, e));
-                            }
-                            warn!("Non-critical init command failed: {} - {}", cmd.name, e);
-                        }
-                    }
-                }
-            }
-        }
+        self.run_initialization_commands(&ipmi).await?;
 
         self.initialized.store(true, Ordering::SeqCst);
 
@@ -180,8 +241,43 @@ impl IpmiHardwareMonitor {
         Ok(())
     }
 
+    /// The profile's initialization commands (take fan control from the BMC).
+    async fn run_initialization_commands(&self, ipmi: &IpmiProtocol) -> Result<()> {
+        info!("Running {} initialization commands...", ipmi.lifecycle.initialization.len());
+        for cmd in &ipmi.lifecycle.initialization {
+            if let Some(bytes) = &cmd.bytes {
+                info!("  Init: {} -> {}", cmd.name, bytes);
+                if self.dry_run {
+                    info!("  [DRY RUN] Would execute: {}", executor::command_line(&["raw", bytes.as_str()]));
+                } else {
+                    match executor::run_ipmitool_raw(bytes).await {
+                        Ok(_) => info!("  Init command succeeded: {}", cmd.name),
+                        Err(e) => {
+                            if cmd.critical {
+                                return Err(anyhow!("Critical init command failed: {} - {}", cmd.name, e));
+                            }
+                            warn!("Non-critical init command failed: {} - {}", cmd.name, e);
+                        }
+                    }
+                }
+            }
+        }
+        self.released.store(false, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// After reset_to_factory, run the initialization commands again before writing
+    /// a speed: a BMC in auto mode ignores (or fights) manual duty cycles.
+    async fn take_back_control(&self, ipmi: &IpmiProtocol) -> Result<()> {
+        if self.released.load(Ordering::SeqCst) {
+            info!("Taking fan control back from the BMC");
+            self.run_initialization_commands(ipmi).await?;
+        }
+        Ok(())
+    }
+
     /// Run reset_to_factory commands (restore BMC auto-control).
-    /// Called on shutdown, disconnect, or emergency.
+    /// Called on shutdown, by `releaseControl`, and on emergencies without fan control.
     pub async fn run_reset_to_factory(&self) -> Result<()> {
         let ipmi = match self.ipmi_protocol() {
             Some(p) => p,
@@ -201,7 +297,7 @@ impl IpmiHardwareMonitor {
             if let Some(bytes) = &cmd.bytes {
                 info!("  Reset: {} -> {}", cmd.name, bytes);
                 if self.dry_run {
-                    info!("  [DRY RUN] Would execute: ipmitool raw {}", bytes);
+                    info!("  [DRY RUN] Would execute: {}", executor::command_line(&["raw", bytes.as_str()]));
                 } else {
                     match executor::run_ipmitool_raw(bytes).await {
                         Ok(_) => info!("  Reset command succeeded: {}", cmd.name),
@@ -214,7 +310,11 @@ impl IpmiHardwareMonitor {
         }
 
         // Clear commanded speeds - BMC is back in control, our duty-cycle values are stale
-        self.commanded_speeds.lock().await.clear();
+        let mut speeds = self.commanded_speeds.lock().await;
+        speeds.clear();
+        self.save_fan_state(&speeds).await;
+        drop(speeds);
+        self.released.store(true, Ordering::SeqCst);
 
         info!("Reset to factory complete - fans returned to BMC auto-control");
         Ok(())
@@ -229,52 +329,56 @@ impl IpmiHardwareMonitor {
             return Ok(cached.clone());
         }
 
+        if !self.breaker.lock().unwrap().allow(Instant::now()) {
+            return Err(anyhow!("BMC unresponsive - SDR polling paused"));
+        }
+
         self.cache_from_sdr.store(false, Ordering::SeqCst);
-        let csv = executor::run_ipmitool_sdr_csv().await?;
+        let csv = match executor::run_ipmitool_sdr_csv().await {
+            Ok(csv) => csv,
+            Err(e) => {
+                if let Some(cooldown) = self.breaker.lock().unwrap().record_failure(Instant::now()) {
+                    warn!("BMC unresponsive ({}) - pausing SDR polling for {:?}, reporting cached readings as stale", e, cooldown);
+                }
+                return Err(e);
+            }
+        };
+        if self.breaker.lock().unwrap().record_success() {
+            info!("BMC responding again - SDR polling resumed");
+        }
         *cache = Some(csv.clone());
         Ok(csv)
     }
 
-    /// Build a map from fan sensor name → zone ID using the profile's fan_zones[].members.
-    /// If no zone defines members and there's exactly one zone, all fans are assigned to it.
-    fn build_zone_map(&self) -> HashMap<String, String> {
-        let mut map = HashMap::new();
-        let ipmi = match self.ipmi_protocol() {
-            Some(p) => p,
-            None => return map,
-        };
-
-        let has_any_members = ipmi.fan_zones.iter()
-            .any(|z| z.members.as_ref().map_or(false, |m| !m.is_empty()));
+    /// Last good sensors flagged stale, or `error` if there are none yet.
+    async fn stale_sensors(&self, error: anyhow::Error) -> Result<Vec<Sensor>> {
+        let last = self.last_sensors.lock().await;
+        if last.is_empty() {
+            return Err(error);
+        }
+        debug!("Reporting {} cached sensors as stale: {}", last.len(), error);
+        Ok(last.iter().cloned().map(|mut s| { s.stale = true; s }).collect())
+    }
 
-        if has_any_members {
-            // Explicit mapping: use members arrays
-            for zone in &ipmi.fan_zones {
-                if let Some(members) = &zone.members {
-                    for fan_name in members {
-                        map.insert(fan_name.clone(), zone.id.clone());
-                    }
-                }
-            }
-        } else if ipmi.fan_zones.len() == 1 {
-            // Single zone without members: handled post-parse (tag all fans with this zone)
-            // We can't pre-populate the map since we don't know fan names yet.
-            // Instead, return a special marker that discover_fans() will handle.
+    /// Last good fans flagged stale, or `error` if there are none yet.
+    async fn stale_fans(&self, error: anyhow::Error) -> Result<Vec<Fan>> {
+        let last = self.last_fans.lock().await;
+        if last.is_empty() {
+            return Err(error);
         }
+        Ok(last.iter().cloned().map(|mut f| { f.stale = true; f }).collect())
+    }
 
-        map
+    /// Fan → zone assignment from the profile's fan_zones (members / member_fans).
+    fn zone_membership(&self) -> ZoneMembership {
+        self.ipmi_protocol()
+            .map(|p| ZoneMembership::from_zones(&p.fan_zones))
+            .unwrap_or_default()
     }
 
-    /// Get the single-zone fallback ID, if applicable.
-    fn single_zone_id(&self) -> Option<String> {
-        let ipmi = self.ipmi_protocol()?;
-        let has_any_members = ipmi.fan_zones.iter()
-            .any(|z| z.members.as_ref().map_or(false, |m| !m.is_empty()));
-        if !has_any_members && ipmi.fan_zones.len() == 1 {
-            Some(ipmi.fan_zones[0].id.clone())
-        } else {
-            None
-        }
+    /// Metadata of the loaded profile (vendor, model_family), if any.
+    pub fn profile_metadata(&self) -> Option<Metadata> {
+        self.profile.read().unwrap().as_ref().map(|p| p.metadata.clone())
     }
 
     /// Get the hardware name from profile metadata.
@@ -307,8 +411,19 @@ impl HardwareMonitor for IpmiHardwareMonitor {
             self.run_initialization().await?;
         }
 
-        let csv = self.get_sdr_csv().await?;
-        let mut sensors = parser::parse_sensors(&csv, parsing, &self.hardware_name());
+        let report_status = self.settings.report_status_sensors;
+        let csv = match self.get_sdr_csv().await {
+            Ok(csv) => csv,
+            Err(e) => {
+                // Not re-sent as stale: a cached PSU state is no state at all
+                self.last_status_sensors.lock().await.clear();
+                return self.stale_sensors(e).await;
+            }
+        };
+        let mut sensors = parser::parse_sensors(&csv, parsing, &self.hardware_name(), report_status);
+        if report_status {
+            *self.last_status_sensors.lock().await = parser::parse_status_sensors(&csv, parsing, &self.hardware_name());
+        }
 
         // Inject cached thresholds (queried once at init)
         let thresholds = self.sensor_thresholds.lock().await;
@@ -320,6 +435,7 @@ impl HardwareMonitor for IpmiHardwareMonitor {
         }
 
         debug!("Discovered {} temperature sensors via IPMI SDR", sensors.len());
+        *self.last_sensors.lock().await = sensors.clone();
         Ok(sensors)
     }
 
@@ -327,21 +443,13 @@ impl HardwareMonitor for IpmiHardwareMonitor {
         let default_parsing = Self::default_parsing();
         let ipmi = self.ipmi_protocol();
         let parsing = ipmi.as_ref().map(|p| &p.parsing).unwrap_or(&default_parsing);
-        let has_control = ipmi.as_ref().map_or(false, |p| self.settings.enable_fan_control && !p.fan_zones.is_empty());
-
-        let csv = self.get_sdr_csv().await?;
-        let zone_map = self.build_zone_map();
-        let mut fans = parser::parse_fans(&csv, parsing, has_control, &zone_map);
-
-        // Single-zone fallback: if no zone defined members and there's exactly one zone,
-        // tag all discovered fans with that zone.
-        if let Some(zone_id) = self.single_zone_id() {
-            for fan in &mut fans {
-                if fan.zone.is_none() {
-                    fan.zone = Some(zone_id.clone());
-                }
-            }
-        }
+        let has_control = ipmi.as_ref().is_some_and(|p| self.settings.enable_fan_control && !p.fan_zones.is_empty());
+
+        let csv = match self.get_sdr_csv().await {
+            Ok(csv) => csv,
+            Err(e) => return self.stale_fans(e).await,
+        };
+        let mut fans = parser::parse_fans(&csv, parsing, has_control, &self.zone_membership());
 
         // === 3-Tier Fan Speed % Resolution ===
         // IPMI SDR reports RPM but not PWM duty cycle.
@@ -380,7 +488,7 @@ impl HardwareMonitor for IpmiHardwareMonitor {
             if let Some(ref read_cmd) = zone.commands.read_speed {
                 if let Some(ref bytes) = read_cmd.bytes {
                     if self.dry_run {
-                        debug!("Tier 2: [DRY RUN] Would query read_speed for zone {}: {}", zone.id, bytes);
+                        debug!("Tier 2: [DRY RUN] Would query read_speed for zone {}: {}", zone.id, executor::command_line(&["raw", bytes.as_str()]));
                     } else {
                         match executor::run_ipmitool_raw(bytes).await {
                             Ok(response) => {
@@ -407,19 +515,20 @@ impl HardwareMonitor for IpmiHardwareMonitor {
         }
 
         // Tier 3: Commanded speed tracking (universal fallback)
-        // Use the last speed we commanded per zone. Not a true readback
-        // but the best we can do when Tier 1 and 2 are unavailable.
+        // Use the last speed we commanded per zone (persisted across restarts).
+        // Not a true readback but the best we can do when Tier 1 and 2 are
+        // unavailable. target_speed is always what we asked for.
         let speeds = self.commanded_speeds.lock().await;
         for fan in &mut fans {
+            let Some(&spd) = fan.zone.as_ref().and_then(|zone| speeds.get(zone)) else {
+                continue;
+            };
             if fan.speed == 0 {
-                if let Some(zone) = &fan.zone {
-                    if let Some(&spd) = speeds.get(zone) {
-                        fan.speed = spd;
-                        fan.target_speed = spd;
-                    }
-                }
+                fan.speed = spd;
             }
+            fan.target_speed = spd;
         }
+        drop(speeds);
 
         // Clear SDR cache after fans are parsed - both consumers (sensors + fans)
         // have used this cycle's CSV. Next cycle will fetch fresh readings.
@@ -431,9 +540,14 @@ impl HardwareMonitor for IpmiHardwareMonitor {
         }
 
         debug!("Discovered {} fans via IPMI SDR", fans.len());
+        *self.last_fans.lock().await = fans.clone();
         Ok(fans)
     }
 
+    async fn status_sensors(&self) -> Vec<StatusSensor> {
+        self.last_status_sensors.lock().await.clone()
+    }
+
     async fn get_system_info(&self) -> Result<SystemHealth> {
         let uptime = self.start_time.elapsed().as_secs_f64();
 
@@ -445,6 +559,10 @@ impl HardwareMonitor for IpmiHardwareMonitor {
             cpu_usage,
             memory_usage,
             agent_uptime: uptime,
+            ipmi_commands: IpmiCommandStats {
+                circuit_open: self.breaker.lock().unwrap().is_open(),
+                ..executor::command_stats()
+            },
         })
     }
 
@@ -455,12 +573,22 @@ impl HardwareMonitor for IpmiHardwareMonitor {
         if !self.settings.enable_fan_control {
             return Err(anyhow!("Fan control is disabled in agent settings"));
         }
+        self.take_back_control(&ipmi).await?;
 
         // Find matching fan zone(s)
-        let zones: Vec<_> = ipmi.fan_zones.iter()
+        let mut zones: Vec<_> = ipmi.fan_zones.iter()
             .filter(|z| z.id == fan_id || fan_id == "all_fans" || fan_id == "all")
             .collect();
 
+        // An individual SDR fan is driven through the zone that controls it
+        if zones.is_empty() {
+            let membership = ZoneMembership::from_zones(&ipmi.fan_zones);
+            if let Some(zone) = membership.zone_for(fan_id).and_then(|id| ipmi.fan_zones.iter().find(|z| z.id == id)) {
+                warn!("Fan {} is controlled by zone {} - every fan in that zone will follow", fan_id, zone.id);
+                zones.push(zone);
+            }
+        }
+
         if zones.is_empty() {
             return Err(anyhow!("No fan zone matching id '{}' in profile", fan_id));
         }
@@ -474,23 +602,64 @@ impl HardwareMonitor for IpmiHardwareMonitor {
                 info!("Setting {} to {}% -> {} -> ipmitool raw {}", zone.name, speed, speed_value, bytes);
 
                 if self.dry_run {
-                    info!("[DRY RUN] Would execute: ipmitool raw {}", bytes);
+                    info!("[DRY RUN] Would execute: {}", executor::command_line(&["raw", bytes.as_str()]));
                 } else {
                     executor::run_ipmitool_raw(&bytes).await?;
                 }
 
                 // Track commanded speed so telemetry can report it
                 // (IPMI SDR only reports RPM, not duty cycle)
-                self.commanded_speeds.lock().await.insert(zone.id.clone(), speed);
+                let mut speeds = self.commanded_speeds.lock().await;
+                if speeds.insert(zone.id.clone(), speed) != Some(speed) {
+                    self.save_fan_state(&speeds).await;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    async fn max_cooling(&self) -> Result<()> {
+        let ipmi = self.ipmi_protocol()
+            .ok_or_else(|| anyhow!("No profile loaded - fan control unavailable in monitor-only mode"))?;
+
+        // Without fan control the BMC's own curve is the best cooling there is
+        if !self.settings.enable_fan_control {
+            warn!("MAX COOLING: fan control is disabled - handing fans back to BMC auto-control instead");
+            return self.run_reset_to_factory().await;
+        }
+
+        if ipmi.lifecycle.max_cooling.is_empty() {
+            info!("MAX COOLING: Setting all fan zones to 100%");
+            return self.set_fan_speed("all", 100).await;
+        }
+
+        self.take_back_control(&ipmi).await?;
+        info!("MAX COOLING: Running {} max_cooling commands...", ipmi.lifecycle.max_cooling.len());
+        for cmd in &ipmi.lifecycle.max_cooling {
+            if let Some(bytes) = &cmd.bytes {
+                info!("  Max cooling: {} -> {}", cmd.name, bytes);
+                if self.dry_run {
+                    info!("  [DRY RUN] Would execute: {}", executor::command_line(&["raw", bytes.as_str()]));
+                } else if let Err(e) = executor::run_ipmitool_raw(bytes).await {
+                    if cmd.critical {
+                        return Err(anyhow!("Critical max_cooling command failed: {} - {}", cmd.name, e));
+                    }
+                    warn!("Non-critical max_cooling command failed: {} - {}", cmd.name, e);
+                }
             }
         }
 
+        let mut speeds = self.commanded_speeds.lock().await;
+        for zone in &ipmi.fan_zones {
+            speeds.insert(zone.id.clone(), 100);
+        }
+        self.save_fan_state(&speeds).await;
         Ok(())
     }
 
-    async fn emergency_stop(&self) -> Result<()> {
-        info!("EMERGENCY STOP: Setting all fans to 100%");
-        // Run reset_to_factory to return fans to BMC auto-control (max safe speed)
+    async fn release_control(&self) -> Result<()> {
+        info!("Releasing fan control to the BMC");
         self.run_reset_to_factory().await
     }
 
@@ -566,6 +735,9 @@ impl HardwareMonitor for IpmiHardwareMonitor {
 
     async fn reload_profile(&self) -> Result<()> {
         let new_profile = load_profile(&self.profile_path)?;
+        if !check_profile(&self.profile_path, &new_profile) {
+            return Err(anyhow!("Profile failed validation - keeping the previous profile (see agent log)"));
+        }
         {
             let mut profile = self.profile.write().unwrap();
             *profile = Some(new_profile);
@@ -573,7 +745,10 @@ impl HardwareMonitor for IpmiHardwareMonitor {
         // Reset init flag - init commands will re-run on next telemetry cycle
         self.initialized.store(false, Ordering::SeqCst);
         // Clear stale state from previous profile
-        self.commanded_speeds.lock().await.clear();
+        let mut speeds = self.commanded_speeds.lock().await;
+        speeds.clear();
+        self.save_fan_state(&speeds).await;
+        drop(speeds);
         self.sensor_thresholds.lock().await.clear();
         info!("Profile hot-reloaded from {:?}. Init commands will run on next telemetry cycle.", self.profile_path);
         Ok(())
@@ -585,6 +760,36 @@ impl HardwareMonitor for IpmiHardwareMonitor {
     }
 }
 
+/// Log validation problems for a loaded profile. False if it has errors.
+fn check_profile(path: &std::path::Path, profile: &BmcProfile) -> bool {
+    let problems = validate_profile(profile);
+    for problem in &problems {
+        match problem.severity {
+            Severity::Error => error!("Profile {}", problem),
+            Severity::Warning => warn!("Profile {}", problem),
+        }
+    }
+    if has_errors(&problems) {
+        error!("Profile {:?} failed validation (check it with --validate-profile {:?})", path, path);
+        return false;
+    }
+    true
+}
+
+/// Commanded speeds from a previous run. A missing or unreadable file means
+/// nothing was commanded (or the BMC was handed back to auto-control).
+fn load_fan_state(path: &Path) -> HashMap<String, u8> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    serde_json::from_str::<HashMap<String, u8>>(&contents)
+        .map(|speeds| speeds.into_iter().filter(|(_, speed)| *speed <= 100).collect())
+        .unwrap_or_else(|e| {
+            warn!("Ignoring malformed {:?}: {}", path, e);
+            HashMap::new()
+        })
+}
+
 /// Parse a field from `ipmitool fru print` output.
 fn parse_fru_field(output: &str, field: &str) -> Option<String> {
     output.lines()
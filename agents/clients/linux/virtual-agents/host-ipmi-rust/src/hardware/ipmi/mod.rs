@@ -1 +1,2 @@
 pub mod ipmi_monitor;
+pub mod breaker;
@@ -19,6 +19,42 @@ pub struct Sensor {
     pub hardware_name: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub source: Option<String>,
+    /// Cached reading re-sent while the BMC is unresponsive
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
+    /// SDR status of the reading ("ok", "warning", "critical", "absent"), only with
+    /// `hardware.report_status_sensors`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+/// Discrete or non-temperature SDR sensor (PSU status, chassis intrusion, voltage
+/// rail) reported with `hardware.report_status_sensors`. Kept apart from `Sensor` so
+/// fan curves and the failsafe only ever see temperatures.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct StatusSensor {
+    pub id: String,
+    pub name: String,
+    /// "psu", "intrusion", "voltage", "current", "power" or "discrete"
+    #[serde(rename = "type")]
+    pub sensor_type: String,
+    /// Analog reading; None for discrete sensors and missing readings
+    pub value: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub unit: Option<String>,
+    /// "ok", "warning", "critical" or "absent"
+    pub state: String,
+    /// Reading and status columns as the BMC reported them, e.g. "0x0b" / "ok"
+    pub raw: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "hardwareName")]
+    pub hardware_name: Option<String>,
+}
+
+impl StatusSensor {
+    pub fn is_alerting(&self) -> bool {
+        matches!(self.state.as_str(), "warning" | "critical")
+    }
 }
 
 /// Fan information with RPM and PWM control
@@ -38,6 +74,9 @@ pub struct Fan {
     /// OS agents always set this to None (omitted from JSON).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub zone: Option<String>,
+    /// Cached reading re-sent while the BMC is unresponsive
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub stale: bool,
 }
 
 /// System health metrics
@@ -49,6 +88,22 @@ pub struct SystemHealth {
     pub memory_usage: f64,
     #[serde(rename = "agentUptime")]
     pub agent_uptime: f64,
+    #[serde(rename = "ipmiCommands")]
+    pub ipmi_commands: IpmiCommandStats,
+}
+
+/// ipmitool invocation counters since agent start.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IpmiCommandStats {
+    pub commands: u64,
+    /// Transient failures that were retried
+    pub retries: u64,
+    /// Commands that failed after all retries
+    pub failures: u64,
+    pub timeouts: u64,
+    /// BMC unresponsive: polling is backed off and cached readings are reported as stale
+    pub circuit_open: bool,
 }
 
 // ============================================================================
@@ -64,7 +119,7 @@ pub struct HardwareDumpRoot {
 }
 
 /// Metadata section with system context
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "PascalCase")]
 pub struct HardwareDumpMetadata {
     pub agent_version: String,
@@ -121,17 +176,3 @@ pub struct HardwareDumpControlInfo {
     pub range: [i32; 2],
     pub mode: Option<String>,
 }
-
-impl Default for HardwareDumpMetadata {
-    fn default() -> Self {
-        Self {
-            agent_version: String::new(),
-            os_version: String::new(),
-            is_elevated: false,
-            timestamp: String::new(),
-            motherboard: None,
-            kernel_version: None,
-            cpu_model: None,
-        }
-    }
-}
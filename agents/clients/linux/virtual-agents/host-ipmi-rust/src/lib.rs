@@ -0,0 +1,11 @@
+//! Library view of the IPMI agent's hardware layer (profiles, ipmitool executor,
+//! SDR parser, `IpmiHardwareMonitor`), so another Pankha agent can host an IPMI
+//! backend in-process. The binary in main.rs does not depend on it.
+
+pub mod config {
+    pub mod types;
+}
+pub mod hardware;
+pub mod profiles;
+pub mod system;
+pub mod version;
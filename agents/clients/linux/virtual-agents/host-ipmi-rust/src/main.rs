@@ -87,6 +87,11 @@ async fn main() -> Result<()> {
         return run_health_check();
     }
 
+    if let Some(path) = &args.validate_profile {
+        let ok = validate_profile_file(std::path::Path::new(path));
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
     // Systemd service management (Linux only)
     #[cfg(target_os = "linux")]
     if args.install_service {
@@ -126,14 +131,14 @@ async fn main() -> Result<()> {
 
     // If user provided --log-level without other commands, set it for running agent
     if let Some(level) = args.log_level.as_ref() {
-        if !args.daemon_child && !args.test && !args.config && !args.setup {
+        if !args.daemon_child && !args.test && !args.config && !args.setup && !args.fetch_profile {
             // Set log level for running agent
             return set_log_level_runtime(level);
         }
     }
 
     // If no command was provided at all (user just ran the binary), show help
-    if !args.daemon_child && !args.test && !args.config && !args.setup {
+    if !args.daemon_child && !args.test && !args.config && !args.setup && !args.fetch_profile {
         eprintln!("ERROR: No command specified. You must specify a command.");
         eprintln!();
         Args::command().print_help().unwrap();
@@ -235,7 +240,8 @@ async fn main() -> Result<()> {
 
     // Show config if requested
     if args.config {
-        let config = load_config(None).await?;
+        let mut config = load_config(None).await?;
+        config.ipmi = config.ipmi.redacted();
         println!("\n{}", serde_json::to_string_pretty(&config)?);
         return Ok(());
     }
@@ -281,6 +287,18 @@ async fn main() -> Result<()> {
         }
     }
 
+    // Point ipmitool at the BMC (local or over LAN) and check it answers
+    if let Err(e) = system::executor::set_connection(&config.ipmi) {
+        error!("Invalid IPMI connection settings: {}", e);
+        std::process::exit(1);
+    }
+    check_bmc_connection(&config.ipmi).await;
+
+    if args.fetch_profile {
+        let ok = fetch_board_profile(&config, args.profile.as_deref()).await;
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
     // Fetch BMC profile from backend API before starting hardware monitor.
     // URL derived from backend.server_url + agent.id - no extra config needed.
     {
@@ -301,8 +319,9 @@ async fn main() -> Result<()> {
     }
 
     // Create IPMI hardware monitor (keep concrete type for reset_to_factory on shutdown)
-    let ipmi_monitor = Arc::new(IpmiHardwareMonitor::new(config.hardware.clone()));
+    let ipmi_monitor = Arc::new(IpmiHardwareMonitor::new(config.hardware.clone(), &config_file_path));
     let hardware_monitor: Arc<dyn HardwareMonitor> = ipmi_monitor.clone();
+    warn_on_board_mismatch(&ipmi_monitor).await;
 
     // Generate hardware-info.json diagnostic dump on startup (matches original agent behavior)
     match ipmi_monitor.dump_hardware_info().await {
@@ -416,3 +435,116 @@ async fn main() -> Result<()> {
     info!("Agent shutdown complete");
     Ok(())
 }
+
+/// `--validate-profile`: print every problem with its JSON path. True if the
+/// profile has no errors (warnings are allowed).
+fn validate_profile_file(path: &std::path::Path) -> bool {
+    use profiles::validator::{has_errors, validate_file};
+
+    println!("Validating {:?}", path);
+    let problems = validate_file(path);
+    for problem in &problems {
+        println!("  {}", problem);
+    }
+    if has_errors(&problems) {
+        println!("\n❌ Profile has errors - the agent would run it monitor-only");
+        false
+    } else {
+        println!("\n✅ Profile is valid{}", if problems.is_empty() { "" } else { " (with warnings)" });
+        true
+    }
+}
+
+/// `--fetch-profile`: read the board's FRU, download the matching profile from
+/// the repository into the profile path and print what was matched.
+async fn fetch_board_profile(config: &config::types::AgentConfig, profile: Option<&str>) -> bool {
+    let fru = match system::executor::run_ipmitool_fru().await {
+        Ok(fru) => fru,
+        Err(e) => {
+            println!("❌ Cannot read the board FRU: {}", e);
+            return false;
+        }
+    };
+    let board = system::parser::board_identity(&fru);
+    println!("Board: {}", board);
+
+    let profile_path = match profile {
+        Some(path) => PathBuf::from(path),
+        None => match std::env::current_exe() {
+            Ok(exe) => exe.with_file_name("profile.json"),
+            Err(e) => {
+                println!("❌ Cannot determine executable directory: {}", e);
+                return false;
+            }
+        },
+    };
+
+    let index_url = config.profile_repository_url();
+    match websocket::profile_fetch::fetch_profile_for_board(
+        &index_url,
+        &board,
+        &profile_path,
+        config.auth.auth_token.as_deref(),
+    ).await {
+        Ok(entry) => {
+            println!("✅ Matched '{}' ({} {})", entry.id, entry.vendor, entry.boards.join(", "));
+            println!("   Saved to {:?}", profile_path);
+            true
+        }
+        Err(e) => {
+            println!("❌ {}", e);
+            println!("   The existing profile (if any) was left unchanged.");
+            false
+        }
+    }
+}
+
+/// Warn when the loaded profile was written for a different board than the one
+/// the FRU reports - its raw fan commands may do nothing, or the wrong thing.
+async fn warn_on_board_mismatch(monitor: &IpmiHardwareMonitor) {
+    let Some(metadata) = monitor.profile_metadata() else { return };
+    let Some(models) = metadata.model_family.filter(|m| !m.is_empty()) else { return };
+    let Ok(fru) = system::executor::run_ipmitool_fru().await else { return };
+
+    let board = system::parser::board_identity(&fru);
+    if board.manufacturer.is_none() || board.products.is_empty() {
+        return;
+    }
+    if !board.matches(&metadata.vendor, &models) {
+        warn!(
+            "Loaded profile is for {} {} but the FRU reports {}. Check the profile, or run --fetch-profile.",
+            metadata.vendor,
+            models.join(", "),
+            board
+        );
+    }
+}
+
+/// Startup connection test: one `ipmitool mc info`. A failure is logged with the
+/// likely cause but not fatal - the BMC may come up later, and polling backs off.
+async fn check_bmc_connection(settings: &config::types::IpmiConnection) {
+    let settings = settings.clone().with_env_overrides();
+    let target = if settings.is_remote() {
+        format!(
+            "BMC at {}:{} over {} as '{}'",
+            settings.host.as_deref().unwrap_or_default(),
+            settings.port,
+            settings.interface,
+            settings.username.as_deref().unwrap_or_default()
+        )
+    } else {
+        "local BMC (/dev/ipmi0)".to_string()
+    };
+
+    match system::executor::run_ipmitool_mc_info().await {
+        Ok(_) => info!("Connected to {}", target),
+        Err(e) if settings.is_remote() => error!(
+            "Cannot reach {}: {}. Check the host, port and credentials, and that IPMI over LAN is enabled on the BMC.",
+            target, e
+        ),
+        Err(e) => error!(
+            "Cannot reach the {}: {}. Is ipmitool installed and are the ipmi_devintf / ipmi_si modules loaded?",
+            target, e
+        ),
+    }
+}
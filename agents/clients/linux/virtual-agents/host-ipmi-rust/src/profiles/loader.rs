@@ -9,12 +9,11 @@ use tracing::info;
 use super::types::BmcProfile;
 use super::merger::resolve_extends_value;
 
-/// Load a BMC profile from a JSON file, resolve `extends` inheritance,
-/// and validate safety constraints.
+/// Read a profile file and resolve `extends` inheritance, as raw JSON.
 ///
-/// Loads as raw JSON Value first so partial child profiles (e.g., only fan_zones)
-/// can be merged with their base before typed deserialization.
-pub fn load_profile(path: &Path) -> Result<BmcProfile> {
+/// Works on Values so partial child profiles (e.g., only fan_zones) can be
+/// merged with their base before typed deserialization.
+pub fn read_profile_value(path: &Path) -> Result<serde_json::Value> {
     let content = std::fs::read_to_string(path)
         .with_context(|| format!("Failed to read profile: {:?}", path))?;
 
@@ -22,13 +21,19 @@ pub fn load_profile(path: &Path) -> Result<BmcProfile> {
         .with_context(|| format!("Failed to parse profile JSON: {:?}", path))?;
 
     // Resolve extends inheritance if present (merge as raw Values, then deserialize)
-    let final_value = if value.get("extends").and_then(|v| v.as_str()).is_some() {
+    if value.get("extends").and_then(|v| v.as_str()).is_some() {
         let base_dir = path.parent()
             .ok_or_else(|| anyhow!("Cannot determine profile directory"))?;
-        resolve_extends_value(value, base_dir)?
+        resolve_extends_value(value, base_dir)
     } else {
-        value
-    };
+        Ok(value)
+    }
+}
+
+/// Load a BMC profile from a JSON file, resolve `extends` inheritance,
+/// and validate safety constraints.
+pub fn load_profile(path: &Path) -> Result<BmcProfile> {
+    let final_value = read_profile_value(path)?;
 
     let profile: BmcProfile = serde_json::from_value(final_value)
         .with_context(|| format!("Failed to deserialize profile after merge: {:?}", path))?;
@@ -8,6 +8,7 @@
 //!   - fan_zones: REPLACE (model zones replace base entirely)
 //!   - initialization: APPEND (model init added after base)
 //!   - reset_to_factory: REPLACE (model reset replaces base)
+//!   - max_cooling: REPLACE (same)
 
 use std::path::Path;
 use anyhow::{anyhow, Context, Result};
@@ -58,8 +59,8 @@ fn deep_merge(base: serde_json::Value, over: serde_json::Value) -> serde_json::V
                     "fan_zones" => {
                         base_map.insert(key, over_val);
                     }
-                    // reset_to_factory: REPLACE
-                    "reset_to_factory" => {
+                    // reset_to_factory, max_cooling: REPLACE
+                    "reset_to_factory" | "max_cooling" => {
                         base_map.insert(key, over_val);
                     }
                     // initialization: APPEND (model init added after base)
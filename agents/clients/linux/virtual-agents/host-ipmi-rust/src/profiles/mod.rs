@@ -2,3 +2,5 @@ pub mod types;
 pub mod loader;
 pub mod merger;
 pub mod interpolator;
+pub mod validator;
+pub mod repository;
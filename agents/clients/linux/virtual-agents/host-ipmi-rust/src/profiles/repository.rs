@@ -0,0 +1,91 @@
+//! Profile repository index used by `--fetch-profile`.
+//!
+//! A repository is a static JSON file listing profiles by board:
+//! `{ "version": 1, "profiles": [{ "id", "vendor", "boards": [...], "file", "sha256" }] }`.
+//! `boards` entries use the `model_family` syntax (exact names or globs); `file`
+//! is absolute or relative to the index URL. The Pankha server publishes one at
+//! `/api/deploy/profiles/index`.
+
+use anyhow::{anyhow, Context, Result};
+use serde::Deserialize;
+
+use crate::system::parser::BoardIdentity;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct RepositoryIndex {
+    pub profiles: Vec<IndexEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct IndexEntry {
+    pub id: String,
+    pub vendor: String,
+    #[serde(default)]
+    pub boards: Vec<String>,
+    pub file: String,
+    /// Hex SHA-256 of the file exactly as served
+    pub sha256: String,
+}
+
+impl RepositoryIndex {
+    pub fn parse(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("Profile repository index is not valid")
+    }
+
+    /// First profile whose vendor and boards match `board`.
+    pub fn find(&self, board: &BoardIdentity) -> Option<&IndexEntry> {
+        self.profiles.iter().find(|entry| board.matches(&entry.vendor, &entry.boards))
+    }
+}
+
+/// URL of `entry`'s profile file, resolved against the index URL.
+pub fn file_url(index_url: &str, entry: &IndexEntry) -> Result<String> {
+    if entry.file.starts_with("http://") || entry.file.starts_with("https://") {
+        return Ok(entry.file.clone());
+    }
+    let base = index_url
+        .split(['?', '#'])
+        .next()
+        .and_then(|url| url.rsplit_once('/'))
+        .map(|(dir, _)| dir)
+        .ok_or_else(|| anyhow!("Cannot resolve '{}' against {}", entry.file, index_url))?;
+    Ok(format!("{}/{}", base, entry.file.trim_start_matches("./")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INDEX: &str = r#"{
+        "version": 1,
+        "profiles": [
+            { "id": "supermicro_x10", "vendor": "Supermicro", "boards": ["X10SRL-F", "X11*"],
+              "file": "file?id=supermicro_x10", "sha256": "ab" },
+            { "id": "dell_13g", "vendor": "Dell", "boards": ["PowerEdge R730"],
+              "file": "https://example.org/dell/13g.json", "sha256": "cd" }
+        ]
+    }"#;
+
+    fn board(manufacturer: &str, product: &str) -> BoardIdentity {
+        BoardIdentity {
+            manufacturer: Some(manufacturer.to_string()),
+            products: vec![product.to_string()],
+        }
+    }
+
+    #[test]
+    fn finds_profile_by_vendor_and_board() {
+        let index = RepositoryIndex::parse(INDEX).unwrap();
+        let url = "http://hub:3143/api/deploy/profiles/index";
+
+        let entry = index.find(&board("Supermicro", "X11SSH-F")).unwrap();
+        assert_eq!(entry.id, "supermicro_x10");
+        assert_eq!(file_url(url, entry).unwrap(), "http://hub:3143/api/deploy/profiles/file?id=supermicro_x10");
+
+        let entry = index.find(&board("Dell Inc.", "PowerEdge R730")).unwrap();
+        assert_eq!(file_url(url, entry).unwrap(), "https://example.org/dell/13g.json");
+
+        assert!(index.find(&board("Dell Inc.", "PowerEdge R720")).is_none());
+        assert!(index.find(&board("ASRock", "X11SSH-F")).is_none());
+    }
+}
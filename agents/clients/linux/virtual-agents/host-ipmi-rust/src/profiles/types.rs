@@ -57,6 +57,10 @@ pub struct FanZone {
     /// Optional: if omitted and only one zone exists, all fans are assigned to it.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub members: Option<Vec<String>>,
+    /// SDR fan name patterns belonging to this zone (`*`, `?` and `[0-9]` globs,
+    /// case-insensitive), e.g. ["FAN[0-9]"] for FAN1-FAN9. Exact `members` take precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub member_fans: Option<Vec<String>>,
     pub speed_translation: SpeedTranslation,
     pub commands: FanZoneCommands,
 }
@@ -91,6 +95,10 @@ pub struct Command {
 pub struct Lifecycle {
     pub initialization: Vec<LifecycleCommand>,
     pub reset_to_factory: Vec<LifecycleCommand>,
+    /// Commands that put every fan at full speed (`max_cooling`). Optional: without
+    /// them each zone's `set_speed` is sent at 100%.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub max_cooling: Vec<LifecycleCommand>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -0,0 +1,362 @@
+//! Structural and semantic validation of BMC profiles.
+//! Reports every problem with its JSON path instead of stopping at the first,
+//! so a hand-written profile can be fixed in one pass (`--validate-profile`).
+//! The monitor runs the same checks at load time and stays monitor-only when a
+//! profile has errors.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use super::interpolator::{interpolate_command, translate_speed};
+use super::loader::read_profile_value;
+use super::types::{BmcProfile, Command, FanZone, IpmiProtocol, LifecycleCommand, SpeedTranslation};
+
+/// Speeds the set_speed template is interpolated with
+const SAMPLE_SPEEDS: [u8; 5] = [0, 1, 50, 99, 100];
+
+const TRANSLATION_TYPES: [&str; 3] = ["byte_scale", "decimal_hex", "integer"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// The profile would misbehave; fan control is refused
+    Error,
+    /// Legal but probably not what the author meant
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileProblem {
+    pub severity: Severity,
+    /// JSON path, e.g. `protocols.ipmi.fan_zones[1].speed_translation`
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ProfileProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let level = match self.severity {
+            Severity::Error => "ERROR",
+            Severity::Warning => "WARN ",
+        };
+        write!(f, "{} {}: {}", level, self.path, self.message)
+    }
+}
+
+pub fn has_errors(problems: &[ProfileProblem]) -> bool {
+    problems.iter().any(|p| p.severity == Severity::Error)
+}
+
+#[derive(Default)]
+struct Problems(Vec<ProfileProblem>);
+
+impl Problems {
+    fn error(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ProfileProblem { severity: Severity::Error, path: path.into(), message: message.into() });
+    }
+
+    fn warning(&mut self, path: impl Into<String>, message: impl Into<String>) {
+        self.0.push(ProfileProblem { severity: Severity::Warning, path: path.into(), message: message.into() });
+    }
+}
+
+/// Validate the profile file at `path` (with `extends` resolved). Read and JSON
+/// syntax errors come back as a single problem at the document root.
+pub fn validate_file(path: &Path) -> Vec<ProfileProblem> {
+    let value = match read_profile_value(path) {
+        Ok(value) => value,
+        Err(e) => {
+            return vec![ProfileProblem { severity: Severity::Error, path: "$".to_string(), message: format!("{:#}", e) }];
+        }
+    };
+    match serde_json::from_value::<BmcProfile>(value.clone()) {
+        Ok(profile) => validate_profile(&profile),
+        Err(_) => locate_shape_errors(&value),
+    }
+}
+
+/// Narrow a failed deserialization down to the sections that don't fit the schema.
+fn locate_shape_errors(value: &Value) -> Vec<ProfileProblem> {
+    let mut problems = Problems::default();
+
+    fn check<T: DeserializeOwned>(problems: &mut Problems, value: Option<&Value>, path: &str) {
+        match value {
+            None => problems.error(path, "missing"),
+            Some(v) => {
+                if let Err(e) = serde_json::from_value::<T>(v.clone()) {
+                    problems.error(path, e.to_string());
+                }
+            }
+        }
+    }
+
+    check::<super::types::Metadata>(&mut problems, value.get("metadata"), "metadata");
+    let ipmi = value.pointer("/protocols/ipmi");
+    check::<super::types::Parsing>(&mut problems, ipmi.and_then(|i| i.get("parsing")), "protocols.ipmi.parsing");
+    check::<super::types::Lifecycle>(&mut problems, ipmi.and_then(|i| i.get("lifecycle")), "protocols.ipmi.lifecycle");
+    match ipmi.and_then(|i| i.get("fan_zones")) {
+        Some(Value::Array(zones)) => {
+            for (i, zone) in zones.iter().enumerate() {
+                check::<FanZone>(&mut problems, Some(zone), &format!("protocols.ipmi.fan_zones[{}]", i));
+            }
+        }
+        Some(_) => problems.error("protocols.ipmi.fan_zones", "must be an array"),
+        None => problems.error("protocols.ipmi.fan_zones", "missing"),
+    }
+
+    if problems.0.is_empty() {
+        // Shape error somewhere the sections above don't cover
+        if let Err(e) = serde_json::from_value::<BmcProfile>(value.clone()) {
+            problems.error("$", e.to_string());
+        }
+    }
+    problems.0
+}
+
+/// Every problem in an already-parsed profile.
+pub fn validate_profile(profile: &BmcProfile) -> Vec<ProfileProblem> {
+    let mut problems = Problems::default();
+
+    let Some(ipmi) = profile.protocols.as_ref().and_then(|p| p.ipmi.as_ref()) else {
+        problems.error("protocols.ipmi", "missing - the IPMI agent needs an ipmi protocol section");
+        return problems.0;
+    };
+
+    validate_ipmi(ipmi, &mut problems);
+    problems.0
+}
+
+fn validate_ipmi(ipmi: &IpmiProtocol, problems: &mut Problems) {
+    if ipmi.parsing.sdr_format != "csv" {
+        problems.error("protocols.ipmi.parsing.sdr_format", format!("'{}' is not supported (only \"csv\")", ipmi.parsing.sdr_format));
+    }
+    if ipmi.parsing.fan_match_token.trim().is_empty() {
+        problems.error("protocols.ipmi.parsing.fan_match_token", "empty - no fans would be found");
+    }
+    if ipmi.parsing.temp_match_token.trim().is_empty() {
+        problems.error("protocols.ipmi.parsing.temp_match_token", "empty - no sensors would be found");
+    }
+
+    for (i, cmd) in ipmi.lifecycle.initialization.iter().enumerate() {
+        validate_lifecycle_command(cmd, &format!("protocols.ipmi.lifecycle.initialization[{}]", i), problems);
+    }
+    for (i, cmd) in ipmi.lifecycle.reset_to_factory.iter().enumerate() {
+        validate_lifecycle_command(cmd, &format!("protocols.ipmi.lifecycle.reset_to_factory[{}]", i), problems);
+    }
+    for (i, cmd) in ipmi.lifecycle.max_cooling.iter().enumerate() {
+        validate_lifecycle_command(cmd, &format!("protocols.ipmi.lifecycle.max_cooling[{}]", i), problems);
+    }
+
+    if ipmi.fan_zones.is_empty() {
+        problems.warning("protocols.ipmi.fan_zones", "no fan zones - the profile is monitor-only");
+        return;
+    }
+
+    if !ipmi.lifecycle.reset_to_factory.iter().any(|cmd| cmd.critical) {
+        problems.error(
+            "protocols.ipmi.lifecycle.reset_to_factory",
+            "needs at least one critical: true command to hand fans back to the BMC",
+        );
+    }
+
+    let mut ids = HashSet::new();
+    let mut members = HashSet::new();
+    for (i, zone) in ipmi.fan_zones.iter().enumerate() {
+        let path = format!("protocols.ipmi.fan_zones[{}]", i);
+        if !is_valid_zone_id(&zone.id) {
+            problems.error(format!("{}.id", path), format!("'{}' must match ^[a-z][a-z0-9_]*$", zone.id));
+        }
+        if !ids.insert(zone.id.as_str()) {
+            problems.error(format!("{}.id", path), format!("duplicate zone id '{}'", zone.id));
+        }
+        for member in zone.members.iter().flatten() {
+            if !members.insert(member.as_str()) {
+                problems.warning(format!("{}.members", path), format!("'{}' is already a member of another zone", member));
+            }
+        }
+        validate_zone(zone, &path, problems);
+    }
+}
+
+fn validate_lifecycle_command(cmd: &LifecycleCommand, path: &str, problems: &mut Problems) {
+    if cmd.command_type != "ipmitool_raw" {
+        problems.error(format!("{}.type", path), format!("'{}' is not supported (only \"ipmitool_raw\")", cmd.command_type));
+        return;
+    }
+    match &cmd.bytes {
+        None => problems.error(format!("{}.bytes", path), "missing"),
+        Some(bytes) => {
+            if let Err(e) = check_raw_bytes(bytes) {
+                problems.error(format!("{}.bytes", path), e);
+            }
+        }
+    }
+}
+
+fn validate_zone(zone: &FanZone, path: &str, problems: &mut Problems) {
+    let translation_ok = validate_translation(&zone.speed_translation, &format!("{}.speed_translation", path), problems);
+
+    let set_path = format!("{}.commands.set_speed", path);
+    if let Some(template) = command_bytes(&zone.commands.set_speed, &set_path, problems) {
+        if !template.contains("{{SPEED_HEX}}") && !template.contains("{{SPEED}}") {
+            problems.error(format!("{}.bytes", set_path), "has no {{SPEED_HEX}} or {{SPEED}} placeholder");
+        } else if translation_ok {
+            // Every sample speed must produce a sendable command
+            for speed in SAMPLE_SPEEDS {
+                let bytes = interpolate_command(template, &translate_speed(speed, &zone.speed_translation));
+                if let Err(e) = check_raw_bytes(&bytes) {
+                    problems.error(format!("{}.bytes", set_path), format!("at {}%: {}", speed, e));
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Some(read) = &zone.commands.read_speed {
+        let read_path = format!("{}.commands.read_speed", path);
+        if let Some(bytes) = command_bytes(read, &read_path, problems) {
+            if let Err(e) = check_raw_bytes(bytes) {
+                problems.error(format!("{}.bytes", read_path), e);
+            }
+        }
+    }
+}
+
+fn command_bytes<'a>(cmd: &'a Command, path: &str, problems: &mut Problems) -> Option<&'a str> {
+    if cmd.command_type != "ipmitool_raw" {
+        problems.error(format!("{}.type", path), format!("'{}' is not supported (only \"ipmitool_raw\")", cmd.command_type));
+        return None;
+    }
+    if cmd.bytes.is_none() {
+        problems.error(format!("{}.bytes", path), "missing");
+    }
+    cmd.bytes.as_deref()
+}
+
+/// Returns false when the translation is unusable (interpolation would be meaningless).
+fn validate_translation(translation: &SpeedTranslation, path: &str, problems: &mut Problems) -> bool {
+    if !TRANSLATION_TYPES.contains(&translation.translation_type.as_str()) {
+        problems.error(
+            format!("{}.type", path),
+            format!("unknown type '{}' (expected one of {})", translation.translation_type, TRANSLATION_TYPES.join(", ")),
+        );
+        return false;
+    }
+
+    let param = |name: &str| translation.params.get(name).and_then(Value::as_u64);
+    for name in ["input_min", "input_max", "output_min", "output_max"] {
+        if translation.params.get(name).is_some() && param(name).is_none() {
+            problems.error(format!("{}.{}", path, name), "must be a non-negative integer");
+            return false;
+        }
+    }
+
+    let (input_min, input_max) = (param("input_min").unwrap_or(0), param("input_max").unwrap_or(100));
+    if input_min != 0 || input_max != 100 {
+        problems.error(path, format!("covers {}-{}% - the translation must cover 0-100%", input_min, input_max));
+    }
+
+    if translation.translation_type == "byte_scale" {
+        let (min, max) = (param("output_min").unwrap_or(0), param("output_max").unwrap_or(255));
+        if max > 255 {
+            problems.error(format!("{}.output_max", path), format!("{} does not fit in a byte", max));
+            return false;
+        }
+        if min > max {
+            problems.error(path, format!("output_min {} is above output_max {}", min, max));
+            return false;
+        }
+    }
+    true
+}
+
+fn is_valid_zone_id(id: &str) -> bool {
+    let mut chars = id.chars();
+    chars.next().is_some_and(|c| c.is_ascii_lowercase())
+        && chars.all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_')
+}
+
+/// `ipmitool raw` arguments: netfn, cmd, then data bytes, each 0xNN or decimal 0-255.
+fn check_raw_bytes(bytes: &str) -> Result<(), String> {
+    let tokens: Vec<&str> = bytes.split_whitespace().collect();
+    if tokens.len() < 2 {
+        return Err("needs at least a netfn and a command byte".to_string());
+    }
+    for token in tokens {
+        let parsed = match token.strip_prefix("0x").or_else(|| token.strip_prefix("0X")) {
+            Some(hex) => u8::from_str_radix(hex, 16).ok(),
+            None => token.parse::<u8>().ok(),
+        };
+        if parsed.is_none() {
+            return Err(format!("'{}' is not a byte (0x00-0xff or 0-255)", token));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn example() -> Value {
+        serde_json::from_str(include_str!("../../profile.example.json")).unwrap()
+    }
+
+    fn problems_for(value: Value) -> Vec<ProfileProblem> {
+        match serde_json::from_value::<BmcProfile>(value.clone()) {
+            Ok(profile) => validate_profile(&profile),
+            Err(_) => locate_shape_errors(&value),
+        }
+    }
+
+    #[test]
+    fn example_profile_is_valid() {
+        assert_eq!(problems_for(example()), []);
+    }
+
+    #[test]
+    fn problems_carry_json_paths() {
+        let mut profile = example();
+        let zone = &mut profile["protocols"]["ipmi"]["fan_zones"][1];
+        zone["commands"]["set_speed"]["bytes"] = "0x30 0x70 0x66 0x01 0x0g {{SPEED_HEX}}".into();
+        zone["speed_translation"]["input_max"] = 80.into();
+        profile["protocols"]["ipmi"]["lifecycle"]["reset_to_factory"][0]["critical"] = false.into();
+
+        let paths: Vec<_> = problems_for(profile).into_iter().map(|p| p.path).collect();
+        assert_eq!(
+            paths,
+            [
+                "protocols.ipmi.lifecycle.reset_to_factory",
+                "protocols.ipmi.fan_zones[1].speed_translation",
+                "protocols.ipmi.fan_zones[1].commands.set_speed.bytes",
+            ]
+        );
+    }
+
+    #[test]
+    fn max_cooling_commands_are_optional_and_checked() {
+        let mut profile = example();
+        assert!(profile["protocols"]["ipmi"]["lifecycle"].get("max_cooling").is_none());
+
+        profile["protocols"]["ipmi"]["lifecycle"]["max_cooling"] = serde_json::json!([
+            { "name": "Full speed", "type": "ipmitool_raw", "bytes": "0x30 0x45 0x01 0x01", "critical": true },
+            { "name": "Typo", "type": "ipmitool_raw", "bytes": "0x30 0x4g", "critical": false },
+        ]);
+        let paths: Vec<_> = problems_for(profile).into_iter().map(|p| p.path).collect();
+        assert_eq!(paths, ["protocols.ipmi.lifecycle.max_cooling[1].bytes"]);
+    }
+
+    #[test]
+    fn shape_errors_are_located_per_zone() {
+        let mut profile = example();
+        profile["protocols"]["ipmi"]["fan_zones"][1].as_object_mut().unwrap().remove("commands");
+
+        let problems = problems_for(profile);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].path, "protocols.ipmi.fan_zones[1]");
+        assert!(problems[0].message.contains("commands"));
+    }
+}
@@ -1,125 +1,297 @@
 //! ipmitool subprocess executor.
-//! Spawns ipmitool commands and respects PANKHA_IPMI_HOST for emulator testing.
+//! Spawns ipmitool commands against the configured interface: the local
+//! /dev/ipmi0, or a remote BMC over LAN (config.json `ipmi` section, or
+//! PANKHA_IPMI_HOST for emulator testing). The password is handed to ipmitool
+//! through its environment (`-E`), so it never appears in argv or in logs.
+//!
+//! Every invocation goes through one path with a timeout and retries with
+//! exponential backoff for transient failures (busy SDR repository, RMCP+ session
+//! setup). Failures that retrying cannot fix (invalid command, no IPMI device,
+//! bad credentials) are returned immediately.
 
-use anyhow::{anyhow, Context, Result};
-use tracing::{debug, trace};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+use std::time::Duration;
 
-/// Build an ipmitool Command with the correct interface flags.
-/// If PANKHA_IPMI_HOST is set, routes via LAN to a remote BMC/emulator.
-/// Otherwise, uses the local /dev/ipmi0 interface.
-pub fn build_ipmitool_command() -> std::process::Command {
-    let mut cmd = std::process::Command::new("ipmitool");
+use anyhow::{anyhow, Result};
+use tracing::{debug, trace, warn};
 
-    if let Ok(host) = std::env::var("PANKHA_IPMI_HOST") {
-        // Testing mode: route to remote emulator via LAN
-        let port = std::env::var("PANKHA_IPMI_PORT").unwrap_or_else(|_| "623".to_string());
-        let user = std::env::var("PANKHA_IPMI_USER").unwrap_or_else(|_| "admin".to_string());
-        let pass = std::env::var("PANKHA_IPMI_PASS").unwrap_or_else(|_| "password".to_string());
-        debug!("IPMI routing to emulator: {}:{}", host, port);
-        cmd.args(["-I", "lanplus", "-H", &host, "-p", &port, "-U", &user, "-P", &pass]);
-    } else {
-        // Production: local BMC via /dev/ipmi0
-        cmd.args(["-I", "open"]);
-    }
+use crate::config::types::{HardwareSettings, IpmiConnection};
+use crate::hardware::types::IpmiCommandStats;
 
-    cmd
+/// Retry, backoff and timeout applied to every ipmitool invocation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExecPolicy {
+    /// Extra attempts after a transient failure
+    pub retries: u32,
+    /// Delay before the first retry; doubles for each further retry
+    pub backoff: Duration,
+    /// A hung ipmitool is killed after this long and counts as a transient failure
+    pub timeout: Duration,
 }
 
-/// Execute `ipmitool -c sdr list full` and return the CSV output.
-pub async fn run_ipmitool_sdr_csv() -> Result<String> {
-    let mut cmd = build_ipmitool_command();
-    cmd.args(["-c", "sdr", "list", "full"]);
+impl ExecPolicy {
+    pub const DEFAULT: Self = Self {
+        retries: 2,
+        backoff: Duration::from_millis(500),
+        timeout: Duration::from_secs(10),
+    };
 
-    trace!("Executing: ipmitool {:?}", cmd.get_args().collect::<Vec<_>>());
+    pub fn from_settings(settings: &HardwareSettings) -> Self {
+        Self {
+            retries: settings.ipmi_retries,
+            backoff: Duration::from_millis(settings.ipmi_retry_backoff_ms),
+            timeout: Duration::from_secs_f64(settings.ipmi_timeout_secs.max(1.0)),
+        }
+    }
+}
 
-    let output = tokio::process::Command::from(cmd)
-        .output()
-        .await
-        .context("Failed to execute ipmitool")?;
+static POLICY: RwLock<ExecPolicy> = RwLock::new(ExecPolicy::DEFAULT);
 
-    if !output.status.success() {
-        return Err(anyhow!("ipmitool sdr failed: {}", String::from_utf8_lossy(&output.stderr)));
+/// Interface arguments (never the password) and the password, resolved once.
+#[derive(Debug, Clone)]
+struct Connection {
+    args: Vec<String>,
+    password: Option<String>,
+}
+
+impl Connection {
+    fn resolve(settings: &IpmiConnection) -> Result<Self> {
+        let mut args = vec!["-I".to_string(), settings.interface.clone()];
+        match settings.interface.as_str() {
+            "open" => return Ok(Self { args, password: None }),
+            "lanplus" | "lan" => {}
+            other => return Err(anyhow!("Unknown IPMI interface '{}' (expected open, lanplus or lan)", other)),
+        }
+
+        let host = settings.host.as_deref()
+            .ok_or_else(|| anyhow!("IPMI interface '{}' needs ipmi.host in config.json", settings.interface))?;
+        args.extend(["-H".to_string(), host.to_string(), "-p".to_string(), settings.port.to_string()]);
+        if let Some(user) = &settings.username {
+            args.extend(["-U".to_string(), user.clone()]);
+        }
+
+        let password = match &settings.password_file {
+            Some(path) => {
+                let contents = std::fs::read_to_string(path)
+                    .map_err(|e| anyhow!("Cannot read IPMI password file {}: {}", path, e))?;
+                Some(contents.lines().next().unwrap_or_default().to_string())
+            }
+            None => settings.password.clone(),
+        };
+        if password.is_some() {
+            args.push("-E".to_string());
+        }
+        Ok(Self { args, password })
     }
+}
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+static CONNECTION: RwLock<Option<Connection>> = RwLock::new(None);
+
+static COMMANDS: AtomicU64 = AtomicU64::new(0);
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static FAILURES: AtomicU64 = AtomicU64::new(0);
+static TIMEOUTS: AtomicU64 = AtomicU64::new(0);
+
+/// stderr fragments of failures that a retry cannot fix.
+const PERMANENT_ERRORS: &[&str] = &[
+    "Invalid command",
+    "Invalid data field",
+    "Insufficient privilege",
+    "Could not open device",
+    "RAKP 2 HMAC is invalid",
+    "Invalid user name",
+];
+
+/// Replace the policy used by every later ipmitool invocation.
+pub fn set_policy(policy: ExecPolicy) {
+    *POLICY.write().unwrap() = policy;
 }
 
-/// Execute `ipmitool raw <bytes>` for OEM commands (fan speed control, init, reset).
-pub async fn run_ipmitool_raw(bytes: &str) -> Result<String> {
-    let mut cmd = build_ipmitool_command();
-    cmd.arg("raw");
-    for byte in bytes.split_whitespace() {
-        cmd.arg(byte);
+/// Point every later ipmitool invocation at `settings` (with PANKHA_IPMI_*
+/// overrides applied). Fails on an unknown interface, a missing host, or an
+/// unreadable password file.
+pub fn set_connection(settings: &IpmiConnection) -> Result<()> {
+    let connection = Connection::resolve(&settings.clone().with_env_overrides())?;
+    *CONNECTION.write().unwrap() = Some(connection);
+    Ok(())
+}
+
+fn connection() -> Connection {
+    if let Some(connection) = CONNECTION.read().unwrap().as_ref() {
+        return connection.clone();
     }
+    // Not configured (hosted in another agent): local interface unless the
+    // environment says otherwise
+    let connection = Connection::resolve(&IpmiConnection::default().with_env_overrides())
+        .expect("default IPMI connection always resolves");
+    *CONNECTION.write().unwrap() = Some(connection.clone());
+    connection
+}
 
-    debug!("Executing: ipmitool raw {}", bytes);
+/// The ipmitool command line for `args` as it would run, minus the password.
+/// Used for dry-run output and error messages.
+pub fn command_line(args: &[&str]) -> String {
+    let mut parts = vec!["ipmitool".to_string()];
+    parts.extend(connection().args);
+    parts.extend(args.iter().map(|a| a.to_string()));
+    parts.join(" ")
+}
+
+/// Command counters since startup, for SystemHealth.
+pub fn command_stats() -> IpmiCommandStats {
+    IpmiCommandStats {
+        commands: COMMANDS.load(Ordering::Relaxed),
+        retries: RETRIES.load(Ordering::Relaxed),
+        failures: FAILURES.load(Ordering::Relaxed),
+        timeouts: TIMEOUTS.load(Ordering::Relaxed),
+        circuit_open: false,
+    }
+}
 
-    let output = tokio::process::Command::from(cmd)
-        .output()
-        .await
-        .context("Failed to execute ipmitool raw")?;
+fn is_transient(stderr: &str) -> bool {
+    !PERMANENT_ERRORS.iter().any(|marker| stderr.contains(marker))
+}
 
-    if !output.status.success() {
-        return Err(anyhow!("ipmitool raw failed: {}", String::from_utf8_lossy(&output.stderr)));
+/// Build an ipmitool Command with the configured interface flags.
+/// A LAN password goes into IPMI_PASSWORD for `-E`, never onto the command line.
+pub fn build_ipmitool_command() -> std::process::Command {
+    let connection = connection();
+    let mut cmd = std::process::Command::new("ipmitool");
+    cmd.args(&connection.args);
+    if let Some(password) = connection.password {
+        cmd.env("IPMI_PASSWORD", password);
     }
+    cmd
+}
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+struct Failure {
+    error: anyhow::Error,
+    transient: bool,
 }
 
-/// Execute `ipmitool mc info` to verify BMC connectivity.
-pub async fn run_ipmitool_mc_info() -> Result<String> {
+/// One ipmitool run with the policy's timeout.
+async fn run_once(args: &[&str], what: &str, timeout: Duration) -> std::result::Result<String, Failure> {
     let mut cmd = build_ipmitool_command();
-    cmd.args(["mc", "info"]);
+    cmd.args(args);
+    trace!("Executing: {}", command_line(args));
 
-    debug!("Executing: ipmitool mc info");
+    let mut cmd = tokio::process::Command::from(cmd);
+    cmd.kill_on_drop(true);
 
-    let output = tokio::process::Command::from(cmd)
-        .output()
-        .await
-        .context("Failed to execute ipmitool mc info")?;
+    let output = match tokio::time::timeout(timeout, cmd.output()).await {
+        Err(_) => {
+            TIMEOUTS.fetch_add(1, Ordering::Relaxed);
+            return Err(Failure {
+                error: anyhow!("ipmitool {} timed out after {:?}", what, timeout),
+                transient: true,
+            });
+        }
+        // Not installed / not executable: retrying won't help
+        Ok(Err(e)) => {
+            return Err(Failure {
+                error: anyhow!("Failed to execute ipmitool {}: {}", what, e),
+                transient: false,
+            });
+        }
+        Ok(Ok(output)) => output,
+    };
 
     if !output.status.success() {
-        return Err(anyhow!("ipmitool mc info failed: {}", String::from_utf8_lossy(&output.stderr)));
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(Failure {
+            transient: is_transient(&stderr),
+            error: anyhow!("ipmitool {} failed: {}", what, stderr),
+        });
     }
 
     Ok(String::from_utf8_lossy(&output.stdout).to_string())
 }
 
-/// Execute `ipmitool sensor get "<name>"` to retrieve thresholds for a single sensor.
-/// Returns the full text output which is then parsed for threshold values.
-pub async fn run_ipmitool_sensor_get(sensor_name: &str) -> Result<String> {
-    let mut cmd = build_ipmitool_command();
-    cmd.args(["sensor", "get", sensor_name]);
+/// Run ipmitool with `args`, retrying transient failures per the current policy.
+async fn run_ipmitool(args: &[&str], what: &str) -> Result<String> {
+    let policy = *POLICY.read().unwrap();
+    COMMANDS.fetch_add(1, Ordering::Relaxed);
 
-    debug!("Executing: ipmitool sensor get \"{}\"", sensor_name);
+    let mut attempt = 0;
+    loop {
+        match run_once(args, what, policy.timeout).await {
+            Ok(output) => return Ok(output),
+            Err(failure) if failure.transient && attempt < policy.retries => {
+                let delay = policy.backoff * 2u32.saturating_pow(attempt);
+                attempt += 1;
+                RETRIES.fetch_add(1, Ordering::Relaxed);
+                debug!("{} - retry {}/{} in {:?}", failure.error, attempt, policy.retries, delay);
+                tokio::time::sleep(delay).await;
+            }
+            Err(failure) => {
+                FAILURES.fetch_add(1, Ordering::Relaxed);
+                if attempt > 0 {
+                    warn!("ipmitool {} still failing after {} retries", what, attempt);
+                }
+                return Err(failure.error);
+            }
+        }
+    }
+}
 
-    let output = tokio::process::Command::from(cmd)
-        .output()
-        .await
-        .context("Failed to execute ipmitool sensor get")?;
+/// Execute `ipmitool -c sdr list full` and return the CSV output.
+pub async fn run_ipmitool_sdr_csv() -> Result<String> {
+    run_ipmitool(&["-c", "sdr", "list", "full"], "sdr").await
+}
 
-    if !output.status.success() {
-        return Err(anyhow!("ipmitool sensor get failed: {}", String::from_utf8_lossy(&output.stderr)));
-    }
+/// Execute `ipmitool raw <bytes>` for OEM commands (fan speed control, init, reset).
+pub async fn run_ipmitool_raw(bytes: &str) -> Result<String> {
+    debug!("Executing: ipmitool raw {}", bytes);
+    let args: Vec<&str> = std::iter::once("raw").chain(bytes.split_whitespace()).collect();
+    run_ipmitool(&args, "raw").await
+}
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+/// Execute `ipmitool mc info` to verify BMC connectivity.
+pub async fn run_ipmitool_mc_info() -> Result<String> {
+    debug!("Executing: ipmitool mc info");
+    run_ipmitool(&["mc", "info"], "mc info").await
+}
+
+/// Execute `ipmitool sensor get "<name>"` to retrieve thresholds for a single sensor.
+/// Returns the full text output which is then parsed for threshold values.
+pub async fn run_ipmitool_sensor_get(sensor_name: &str) -> Result<String> {
+    debug!("Executing: ipmitool sensor get \"{}\"", sensor_name);
+    run_ipmitool(&["sensor", "get", sensor_name], "sensor get").await
 }
 
 /// Execute `ipmitool fru print` to get hardware inventory.
 pub async fn run_ipmitool_fru() -> Result<String> {
-    let mut cmd = build_ipmitool_command();
-    cmd.args(["fru", "print"]);
-
     debug!("Executing: ipmitool fru print");
+    run_ipmitool(&["fru", "print"], "fru").await
+}
 
-    let output = tokio::process::Command::from(cmd)
-        .output()
-        .await
-        .context("Failed to execute ipmitool fru print")?;
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    if !output.status.success() {
-        return Err(anyhow!("ipmitool fru failed: {}", String::from_utf8_lossy(&output.stderr)));
+    #[test]
+    fn lan_connection_keeps_the_password_off_the_command_line() {
+        let settings = IpmiConnection {
+            interface: "lanplus".to_string(),
+            host: Some("10.0.0.20".to_string()),
+            username: Some("ADMIN".to_string()),
+            password: Some("hunter2".to_string()),
+            ..Default::default()
+        };
+        let connection = Connection::resolve(&settings).unwrap();
+        assert_eq!(connection.args, ["-I", "lanplus", "-H", "10.0.0.20", "-p", "623", "-U", "ADMIN", "-E"]);
+        assert_eq!(connection.password.as_deref(), Some("hunter2"));
+
+        let no_host = IpmiConnection { host: None, ..settings };
+        assert!(Connection::resolve(&no_host).is_err());
     }
 
-    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    #[test]
+    fn busy_bmc_and_session_errors_are_retried() {
+        assert!(is_transient("Get SDR 0042 command failed: Node busy"));
+        assert!(is_transient("Error: Unable to establish IPMI v2 / RMCP+ session"));
+        assert!(!is_transient("Unable to send RAW command (channel=0x0 netfn=0x30 lun=0x0 cmd=0x70 rsp=0xc1): Invalid command"));
+        assert!(!is_transient("Could not open device at /dev/ipmi0 or /dev/ipmi/0 or /dev/ipmidev/0: No such file or directory"));
+    }
 }
@@ -4,8 +4,8 @@
 
 use std::collections::HashMap;
 
-use crate::hardware::types::{Sensor, Fan};
-use crate::profiles::types::Parsing;
+use crate::hardware::types::{Sensor, Fan, StatusSensor};
+use crate::profiles::types::{FanZone, Parsing};
 
 /// Normalize an SDR sensor name into a stable, underscore-separated ID.
 /// "CPU Temp" → "cpu_temp", "Peripheral Temp" → "peripheral_temp", "PCH Temp" → "pch_temp".
@@ -55,7 +55,8 @@ fn classify_sensor_type(chip: &str) -> String {
 /// Parse CSV SDR output into Sensor structs.
 /// Input:  "CPU Temp,42,degrees C,ok\nFAN1,1800,RPM,ok\n..."
 /// Filter: rows where unit column contains `temp_match_token` ("degrees C")
-pub fn parse_sensors(csv: &str, parsing: &Parsing, hardware_name: &str) -> Vec<Sensor> {
+/// `with_state` fills `Sensor.state` from the status column.
+pub fn parse_sensors(csv: &str, parsing: &Parsing, hardware_name: &str, with_state: bool) -> Vec<Sensor> {
     csv.lines()
         .filter_map(|line| {
             let cols: Vec<&str> = line.split(',').collect();
@@ -75,6 +76,8 @@ pub fn parse_sensors(csv: &str, parsing: &Parsing, hardware_name: &str) -> Vec<S
                     chip: Some(chip),
                     hardware_name: Some(hardware_name.to_string()),
                     source: Some("ipmi_sdr".to_string()),
+                    stale: false,
+                    state: with_state.then(|| sdr_state(cols[3]).to_string()),
                 })
             } else {
                 None
@@ -83,6 +86,88 @@ pub fn parse_sensors(csv: &str, parsing: &Parsing, hardware_name: &str) -> Vec<S
         .collect()
 }
 
+/// SDR status column → state: "ok"; "nc" (non-critical) → "warning"; "cr" / "nr"
+/// (critical, non-recoverable) → "critical"; "ns" (no reading) → "absent".
+fn sdr_state(status: &str) -> &'static str {
+    match status.trim() {
+        "ok" => "ok",
+        "nc" => "warning",
+        "cr" | "nr" => "critical",
+        "ns" => "absent",
+        _ => "unknown",
+    }
+}
+
+/// State of a power supply from its discrete status bits: 0x01 present, 0x02
+/// failure, 0x04 predictive failure, 0x08 AC input lost.
+fn psu_state(bits: u16) -> &'static str {
+    if bits & (0x02 | 0x08) != 0 {
+        "critical"
+    } else if bits & 0x04 != 0 {
+        "warning"
+    } else if bits & 0x01 == 0 {
+        "absent"
+    } else {
+        "ok"
+    }
+}
+
+/// Parse CSV SDR output into the sensors that are neither temperatures, fans nor
+/// fan duty cycles: PSU status, chassis intrusion, voltage rails, currents, power.
+/// Input:  "PS1 Status,0x0b,discrete,ok\nChassis Intru,0x01,discrete,ok\n12V,12.10,Volts,ok"
+/// Discrete sensors keep "ok" in the status column whatever their state, so PSU
+/// and intrusion states come from the reading's bits; everything else uses the
+/// status column.
+pub fn parse_status_sensors(csv: &str, parsing: &Parsing, hardware_name: &str) -> Vec<StatusSensor> {
+    csv.lines()
+        .filter_map(|line| {
+            let cols: Vec<&str> = line.split(',').collect();
+            if cols.len() < 4
+                || cols[2].contains(&parsing.temp_match_token)
+                || cols[2].contains(&parsing.fan_match_token)
+                || cols[2].contains("percent")
+            {
+                return None;
+            }
+            let name = cols[0].trim().to_string();
+            let (reading, unit, status) = (cols[1].trim(), cols[2].trim(), cols[3].trim());
+            let id = normalize_sensor_id(&name);
+            let bits = reading.strip_prefix("0x").and_then(|hex| u16::from_str_radix(hex, 16).ok());
+
+            let sensor_type = if id.contains("intru") {
+                "intrusion"
+            } else if id.starts_with("ps") && id[2..].starts_with(|c: char| c.is_ascii_digit() || c == '_')
+                || id.contains("psu") || id.contains("power_supply")
+            {
+                "psu"
+            } else {
+                match unit {
+                    "Volts" => "voltage",
+                    "Amps" => "current",
+                    "Watts" => "power",
+                    _ => "discrete",
+                }
+            };
+            let state = match (sensor_type, bits) {
+                ("intrusion", Some(bits)) if bits != 0 => "critical",
+                ("psu", Some(bits)) => psu_state(bits),
+                _ => sdr_state(status),
+            };
+
+            Some(StatusSensor {
+                id,
+                name,
+                sensor_type: sensor_type.to_string(),
+                value: if bits.is_some() { None } else { reading.parse().ok() },
+                unit: (unit != "discrete").then(|| unit.to_string()),
+                state: state.to_string(),
+                raw: format!("{} / {}", reading, status),
+                hardware_name: Some(hardware_name.to_string()),
+            })
+        })
+        .collect()
+}
+
 /// Parse `ipmitool sensor get` output for threshold values.
 /// Returns (max_temp, crit_temp) extracted from "Upper non-critical" and "Upper critical" lines.
 /// Format: " Upper critical        : 90.000\n Upper non-critical    : 85.000\n"
@@ -159,17 +244,144 @@ pub fn match_percent_to_fan(percent_name: &str, fan_names: &[String]) -> Option<
     None
 }
 
+/// Which profile zone controls each SDR fan.
+/// Exact `members` names win over `member_fans` patterns; patterns are tried in
+/// zone order. A profile with a single zone and no membership lists at all owns
+/// every fan.
+#[derive(Debug, Default)]
+pub struct ZoneMembership {
+    exact: HashMap<String, String>,
+    patterns: Vec<(String, String)>,
+    fallback: Option<String>,
+}
+
+impl ZoneMembership {
+    pub fn from_zones(zones: &[FanZone]) -> Self {
+        let mut membership = Self::default();
+        for zone in zones {
+            for name in zone.members.iter().flatten() {
+                membership.exact.insert(name.clone(), zone.id.clone());
+            }
+            for pattern in zone.member_fans.iter().flatten() {
+                membership.patterns.push((pattern.to_lowercase(), zone.id.clone()));
+            }
+        }
+        if membership.exact.is_empty() && membership.patterns.is_empty() && zones.len() == 1 {
+            membership.fallback = Some(zones[0].id.clone());
+        }
+        membership
+    }
+
+    /// Zone controlling the SDR fan `fan_name`, if any.
+    pub fn zone_for(&self, fan_name: &str) -> Option<&str> {
+        if let Some(zone) = self.exact.get(fan_name) {
+            return Some(zone);
+        }
+        let lower = fan_name.to_lowercase();
+        self.patterns
+            .iter()
+            .find(|(pattern, _)| glob_match(pattern.as_bytes(), lower.as_bytes()))
+            .map(|(_, zone)| zone.as_str())
+            .or(self.fallback.as_deref())
+    }
+}
+
+/// `*` matches any run of characters, `?` exactly one, `[0-9]` / `[ab]` one of a
+/// set; everything else literally.
+fn glob_match(pattern: &[u8], name: &[u8]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some((b'*', rest)) => (0..=name.len()).any(|skip| glob_match(rest, &name[skip..])),
+        Some((b'?', rest)) => !name.is_empty() && glob_match(rest, &name[1..]),
+        Some((b'[', rest)) if rest.contains(&b']') => {
+            let end = rest.iter().position(|&c| c == b']').unwrap();
+            let (set, rest) = (&rest[..end], &rest[end + 1..]);
+            name.first().is_some_and(|&c| in_set(set, c)) && glob_match(rest, &name[1..])
+        }
+        Some((c, rest)) => name.first() == Some(c) && glob_match(rest, &name[1..]),
+    }
+}
+
+/// `c` in a bracket set such as `0-9` or `ab`.
+fn in_set(set: &[u8], c: u8) -> bool {
+    let mut i = 0;
+    while i < set.len() {
+        if i + 2 < set.len() && set[i + 1] == b'-' {
+            if (set[i]..=set[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if set[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+/// Board maker and model from `ipmitool fru print`, used to pick a profile.
+/// `products` holds the board product and the chassis product name (Dell reports
+/// a part number as the board product, "PowerEdge R730" only as product name).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BoardIdentity {
+    pub manufacturer: Option<String>,
+    pub products: Vec<String>,
+}
+
+impl BoardIdentity {
+    /// Whether a profile's `vendor` / `model_family` describe this board.
+    /// Vendor matches when the FRU manufacturer contains it ("Dell" in
+    /// "Dell Inc."); a model must match one product exactly or as a glob.
+    pub fn matches(&self, vendor: &str, models: &[String]) -> bool {
+        let vendor_ok = self.manufacturer.as_deref()
+            .is_some_and(|m| m.to_lowercase().contains(&vendor.trim().to_lowercase()));
+        vendor_ok && models.iter().any(|model| self.products.iter().any(|p| name_matches(model, p)))
+    }
+}
+
+impl std::fmt::Display for BoardIdentity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.manufacturer.as_deref().unwrap_or("unknown"), self.products.join(" / "))
+    }
+}
+
+pub fn board_identity(fru: &str) -> BoardIdentity {
+    // First FRU device (builtin) only; PSUs and add-in cards follow, each block
+    // separated by a blank line
+    let builtin = fru.trim_start().split("\n\n").next().unwrap_or_default();
+    let field = |name: &str| {
+        builtin.lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(key, _)| key.trim() == name)
+            .map(|(_, value)| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+    };
+    let mut products: Vec<String> = ["Board Product", "Product Name"].into_iter().filter_map(field).collect();
+    products.dedup();
+    BoardIdentity {
+        manufacturer: field("Board Mfg").or_else(|| field("Product Manufacturer")),
+        products,
+    }
+}
+
+/// Case-insensitive `pattern` (glob syntax as in `member_fans`) against `name`.
+pub fn name_matches(pattern: &str, name: &str) -> bool {
+    glob_match(pattern.trim().to_lowercase().as_bytes(), name.trim().to_lowercase().as_bytes())
+}
+
 /// Parse CSV SDR output into Fan structs.
 /// Filter: rows where unit column contains `fan_match_token` ("RPM").
-/// `zone_map` maps fan sensor name → zone_id (e.g., "FAN1" → "cpu_zone").
-pub fn parse_fans(csv: &str, parsing: &Parsing, has_control: bool, zone_map: &HashMap<String, String>) -> Vec<Fan> {
+/// Each fan is tagged with its controlling zone from `zones` (e.g., "FAN1" → "cpu_zone").
+pub fn parse_fans(csv: &str, parsing: &Parsing, has_control: bool, zones: &ZoneMembership) -> Vec<Fan> {
     csv.lines()
         .filter_map(|line| {
             let cols: Vec<&str> = line.split(',').collect();
             if cols.len() >= 4 && cols[2].contains(&parsing.fan_match_token) {
                 let name = cols[0].trim().to_string();
                 let rpm: u32 = cols[1].trim().parse().ok()?;
-                let zone = zone_map.get(&name).cloned();
+                let zone = zones.zone_for(&name).map(str::to_string);
                 Some(Fan {
                     id: name.clone(),
                     name,
@@ -180,6 +392,7 @@ pub fn parse_fans(csv: &str, parsing: &Parsing, has_control: bool, zone_map: &Ha
                     has_pwm_control: has_control,
                     pwm_file: None,   // Not applicable for IPMI
                     zone,
+                    stale: false,
                 })
             } else {
                 None
@@ -187,3 +400,141 @@ pub fn parse_fans(csv: &str, parsing: &Parsing, has_control: bool, zone_map: &Ha
         })
         .collect()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `ipmitool -c sdr` from a Supermicro X10 with CPU and peripheral zones.
+    const SUPERMICRO_SDR: &str = "\
+CPU Temp,45,degrees C,ok
+System Temp,31,degrees C,ok
+FAN1,1800,RPM,ok
+FAN2,1700,RPM,ok
+FAN3,0,RPM,ns
+FAN4,1750,RPM,ok
+FANA,900,RPM,ok
+FANB,950,RPM,ok
+12V,12.10,Volts,ok
+";
+
+    fn parsing() -> Parsing {
+        Parsing {
+            sdr_format: "csv".to_string(),
+            fan_match_token: "RPM".to_string(),
+            temp_match_token: "degrees C".to_string(),
+        }
+    }
+
+    fn zones(json: serde_json::Value) -> Vec<FanZone> {
+        serde_json::from_value(json).unwrap()
+    }
+
+    fn zone(id: &str, members: Option<&[&str]>, member_fans: Option<&[&str]>) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "name": id,
+            "members": members,
+            "member_fans": member_fans,
+            "speed_translation": { "type": "decimal_hex" },
+            "commands": { "set_speed": { "type": "ipmitool_raw", "bytes": "0x30 0x70 0x66 0x01 0x00 {{SPEED_HEX}}" } }
+        })
+    }
+
+    fn zone_of<'a>(fans: &'a [Fan], name: &str) -> Option<&'a str> {
+        fans.iter().find(|f| f.name == name).and_then(|f| f.zone.as_deref())
+    }
+
+    #[test]
+    fn member_fan_patterns_assign_fans_to_zones() {
+        let zones = zones(serde_json::json!([
+            zone("cpu_zone", None, Some(&["FAN[0-9]"])),
+            zone("peripheral_zone", None, Some(&["fan?"])),
+        ]));
+        let fans = parse_fans(SUPERMICRO_SDR, &parsing(), true, &ZoneMembership::from_zones(&zones));
+
+        assert_eq!(fans.len(), 6);
+        assert_eq!(zone_of(&fans, "FAN1"), Some("cpu_zone"));
+        assert_eq!(zone_of(&fans, "FAN3"), Some("cpu_zone"));
+        assert_eq!(zone_of(&fans, "FANA"), Some("peripheral_zone"));
+        assert_eq!(zone_of(&fans, "FANB"), Some("peripheral_zone"));
+    }
+
+    #[test]
+    fn exact_members_win_over_patterns() {
+        let zones = zones(serde_json::json!([
+            zone("cpu_zone", None, Some(&["FAN*"])),
+            zone("peripheral_zone", Some(&["FANA"]), None),
+        ]));
+        let membership = ZoneMembership::from_zones(&zones);
+
+        assert_eq!(membership.zone_for("FANA"), Some("peripheral_zone"));
+        assert_eq!(membership.zone_for("FANB"), Some("cpu_zone"));
+        assert_eq!(membership.zone_for("Pump"), None);
+    }
+
+    #[test]
+    fn single_zone_without_members_owns_every_fan() {
+        let zones = zones(serde_json::json!([zone("all_fans", None, None)]));
+        let fans = parse_fans(SUPERMICRO_SDR, &parsing(), true, &ZoneMembership::from_zones(&zones));
+        assert!(fans.iter().all(|f| f.zone.as_deref() == Some("all_fans")));
+
+        let fans = parse_fans(SUPERMICRO_SDR, &parsing(), false, &ZoneMembership::default());
+        assert!(fans.iter().all(|f| f.zone.is_none()));
+    }
+
+    #[test]
+    fn board_identity_reads_the_builtin_fru() {
+        let fru = "\
+FRU Device Description : Builtin FRU Device (ID 0)
+ Board Mfg             : Dell Inc.
+ Board Product         : PowerEdge R730
+ Product Manufacturer  : DELL
+ Product Name          : PowerEdge R730
+
+FRU Device Description : PSU1 (ID 1)
+ Board Mfg             : DELL
+ Board Product         : PWR SPLY,750W,RDNT
+";
+        let board = board_identity(fru);
+        assert_eq!(board.manufacturer.as_deref(), Some("Dell Inc."));
+        assert_eq!(board.products, ["PowerEdge R730"]);
+        assert!(board.matches("Dell", &["PowerEdge R630".into(), "PowerEdge R730".into()]));
+        assert!(!board.matches("Dell", &["PowerEdge R730xd".into()]));
+        assert!(!board.matches("Supermicro", &["PowerEdge*".into()]));
+    }
+
+    #[test]
+    fn status_sensors_with_their_state() {
+        let sdr = "\
+CPU Temp,92,degrees C,cr
+FAN1,1800,RPM,ok
+Fan 1 Duty,40,percent,ok
+12V,10.80,Volts,cr
+VBAT,3.02,Volts,ok
+PS1 Status,0x01,discrete,ok
+PS2 Status,0x0b,discrete,ok
+PS3 Status,0x00,discrete,ok
+Chassis Intru,0x01,discrete,ok
+";
+        let status = parse_status_sensors(sdr, &parsing(), "Supermicro X10");
+        let state_of = |id: &str| status.iter().find(|s| s.id == id).map(|s| (s.sensor_type.as_str(), s.state.as_str()));
+
+        // Temperatures, fans and duty cycles stay out
+        assert_eq!(status.len(), 6);
+        assert_eq!(state_of("12v"), Some(("voltage", "critical")));
+        assert_eq!(state_of("vbat"), Some(("voltage", "ok")));
+        assert_eq!(state_of("ps1_status"), Some(("psu", "ok")));
+        // Present + failure + AC lost, although the status column says ok
+        assert_eq!(state_of("ps2_status"), Some(("psu", "critical")));
+        assert_eq!(state_of("ps3_status"), Some(("psu", "absent")));
+        assert_eq!(state_of("chassis_intru"), Some(("intrusion", "critical")));
+        assert_eq!(status[0].value, Some(10.8));
+        assert_eq!(status[4].value, None);
+
+        // Temperature rows carry the status column only when asked to
+        let sensors = parse_sensors(sdr, &parsing(), "Supermicro X10", true);
+        assert_eq!(sensors[0].state.as_deref(), Some("critical"));
+        assert_eq!(parse_sensors(sdr, &parsing(), "Supermicro X10", false)[0].state, None);
+    }
+}
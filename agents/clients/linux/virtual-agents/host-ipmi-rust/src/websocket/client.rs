@@ -2,6 +2,7 @@
 
 use anyhow::{Context, Result};
 use futures_util::{SinkExt, StreamExt};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::RwLock;
@@ -29,6 +30,8 @@ pub struct WebSocketClient {
     // Edge-triggered dedup of agent-emitted `{type:"error"}` messages.
     // Prevents spamming the backend on every retry while init is broken.
     pub(crate) last_reported_error: Arc<tokio::sync::Mutex<Option<String>>>,
+    // Last state per status sensor id, so `statusAlert` is sent on transitions only
+    pub(crate) status_states: Arc<tokio::sync::Mutex<HashMap<String, String>>>,
 }
 
 impl WebSocketClient {
@@ -39,6 +42,7 @@ impl WebSocketClient {
             running: Arc::new(RwLock::new(false)),
             failsafe_active: Arc::new(RwLock::new(false)),
             last_reported_error: Arc::new(tokio::sync::Mutex::new(None)),
+            status_states: Arc::default(),
         }
     }
 
@@ -121,7 +125,7 @@ impl WebSocketClient {
         if max_temp >= emergency_temp {
             warn!("🚨 FAILSAFE EMERGENCY: {:.1}°C >= {:.1}°C threshold - ALL FANS TO 100%",
                   max_temp, emergency_temp);
-            self.hardware_monitor.emergency_stop().await?;
+            self.hardware_monitor.max_cooling().await?;
         }
 
         Ok(())
@@ -227,6 +231,8 @@ impl WebSocketClient {
 
         // Reset error dedup so this connection reports errors fresh to the new backend session
         *self.last_reported_error.lock().await = None;
+        // ...and repeats alerts for sensors that are still failing
+        self.status_states.lock().await.clear();
 
         let (write, read) = ws_stream.split();
         let write = Arc::new(tokio::sync::Mutex::new(write));
@@ -234,7 +240,7 @@ impl WebSocketClient {
         // Send registration
         {
             let mut w = write.lock().await;
-            self.send_registration(&mut *w).await?;
+            self.send_registration(&mut w).await?;
         }
 
         // Start data sender task
@@ -243,6 +249,7 @@ impl WebSocketClient {
         let running = Arc::clone(&self.running);
         let write_clone = Arc::clone(&write);
         let last_reported_error = Arc::clone(&self.last_reported_error);
+        let status_states = Arc::clone(&self.status_states);
 
         // Max consecutive send_data failures before closing the write half to
         // trigger the outer reconnect loop. At 3s update_interval this is ~30s
@@ -255,7 +262,7 @@ impl WebSocketClient {
             let mut consecutive_failures: u32 = 0;
             while *running.read().await {
                 let mut w = write_clone.lock().await;
-                match Self::send_data(&mut *w, &config, &hardware_monitor, &last_reported_error).await {
+                match Self::send_data(&mut w, &config, &hardware_monitor, &last_reported_error, &status_states).await {
                     Ok(_) => {
                         if consecutive_failures > 0 {
                             info!(
@@ -274,7 +281,7 @@ impl WebSocketClient {
                     Err(e) => {
                         consecutive_failures += 1;
                         // Dampen log spam: first failure, then every 5th attempt
-                        if consecutive_failures == 1 || consecutive_failures % 5 == 0 {
+                        if consecutive_failures == 1 || consecutive_failures.is_multiple_of(5) {
                             error!(
                                 "Failed to send data (attempt {}/{}): {}",
                                 consecutive_failures, MAX_CONSECUTIVE_SEND_FAILURES, e
@@ -335,7 +342,7 @@ impl WebSocketClient {
                             // Update last message time on successful receive
                             last_message_received = std::time::Instant::now();
                             let mut w = write.lock().await;
-                            if let Err(e) = self.handle_message(&text, &mut *w).await {
+                            if let Err(e) = self.handle_message(&text, &mut w).await {
                                 error!("Failed to handle message: {}", e);
                             }
                         }
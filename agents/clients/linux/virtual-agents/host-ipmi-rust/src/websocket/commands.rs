@@ -66,11 +66,17 @@ impl super::client::WebSocketClient {
                 }
             }
             "emergencyStop" => {
-                match self.hardware_monitor.emergency_stop().await {
+                match self.hardware_monitor.max_cooling().await {
                     Ok(_) => (true, None, serde_json::json!({"message": "Emergency stop executed"})),
                     Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
                 }
             }
+            "releaseControl" => {
+                match self.hardware_monitor.release_control().await {
+                    Ok(_) => (true, None, serde_json::json!({"message": "Fans returned to BMC auto-control"})),
+                    Err(e) => (false, Some(e.to_string()), serde_json::json!({})),
+                }
+            }
             "setUpdateInterval" => {
                 if let Some(interval) = payload.get("interval").and_then(|v| v.as_f64()) {
                     match self.set_update_interval(interval).await {
@@ -2,6 +2,7 @@
 
 use anyhow::Result;
 use futures_util::SinkExt;
+use std::collections::HashMap;
 use std::sync::Arc;
 use tokio::sync::{Mutex, RwLock};
 use tokio_tungstenite::tungstenite::protocol::Message;
@@ -9,6 +10,7 @@ use tracing::{debug, info, warn};
 
 use crate::config::types::AgentConfig;
 use crate::hardware::HardwareMonitor;
+use crate::hardware::types::StatusSensor;
 
 use super::client::WsSink;
 
@@ -38,6 +40,24 @@ async fn clear_reported_error(last_reported_error: &Arc<Mutex<Option<String>>>)
     *last_reported_error.lock().await = None;
 }
 
+/// Status sensors whose state changed into or out of warning/critical since the
+/// last call, with their previous state. Records the new states in `states`. A
+/// sensor seen for the first time counts as coming from "ok", so a PSU that is
+/// already failed when the agent starts is reported too.
+fn status_transitions<'a>(
+    states: &mut HashMap<String, String>,
+    sensors: &'a [StatusSensor],
+) -> Vec<(&'a StatusSensor, String)> {
+    sensors.iter()
+        .filter_map(|sensor| {
+            let previous = states.insert(sensor.id.clone(), sensor.state.clone())
+                .unwrap_or_else(|| "ok".to_string());
+            let was_alerting = matches!(previous.as_str(), "warning" | "critical");
+            (previous != sensor.state && (sensor.is_alerting() || was_alerting)).then_some((sensor, previous))
+        })
+        .collect()
+}
+
 impl super::client::WebSocketClient {
     pub(crate) async fn send_registration(&self, write: &mut WsSink) -> Result<()> {
         // Tolerate discovery failures so the WebSocket read loop (command channel)
@@ -129,6 +149,7 @@ impl super::client::WebSocketClient {
         config: &Arc<RwLock<AgentConfig>>,
         hardware_monitor: &Arc<dyn HardwareMonitor>,
         last_reported_error: &Arc<Mutex<Option<String>>>,
+        status_states: &Arc<Mutex<HashMap<String, String>>>,
     ) -> Result<()> {
         use tracing::trace;
 
@@ -158,6 +179,8 @@ impl super::client::WebSocketClient {
         };
         trace!("Collected {} fans", fans.len());
 
+        let status_sensors = hardware_monitor.status_sensors().await;
+
         let system_health = match hardware_monitor.get_system_info().await {
             Ok(h) => h,
             Err(e) => {
@@ -170,7 +193,7 @@ impl super::client::WebSocketClient {
 
         let config_read = config.read().await;
         let timestamp = chrono::Utc::now().timestamp_millis();
-        let data = serde_json::json!({
+        let mut data = serde_json::json!({
             "type": "data",
             "data": {
                 "agentId": config_read.agent.id,
@@ -180,6 +203,29 @@ impl super::client::WebSocketClient {
                 "systemHealth": system_health
             }
         });
+        if !status_sensors.is_empty() {
+            data["data"]["status_sensors"] = serde_json::json!(status_sensors);
+        }
+
+        // Failed PSUs and opened chassis go out as their own message, ahead of the
+        // data, so the backend doesn't have to diff status_sensors to notice
+        for (sensor, previous) in status_transitions(&mut *status_states.lock().await, &status_sensors) {
+            if sensor.is_alerting() {
+                warn!("Status sensor {} is {} ({})", sensor.name, sensor.state, sensor.raw);
+            } else {
+                info!("Status sensor {} recovered: {} ({})", sensor.name, sensor.state, sensor.raw);
+            }
+            let alert = serde_json::json!({
+                "type": "statusAlert",
+                "data": {
+                    "agentId": config_read.agent.id,
+                    "timestamp": timestamp,
+                    "sensor": sensor,
+                    "previous_state": previous,
+                }
+            });
+            write.send(Message::text(alert.to_string())).await?;
+        }
 
         trace!("Sending WebSocket message (timestamp: {})", timestamp);
         write.send(Message::text(data.to_string())).await?;
@@ -194,3 +240,44 @@ impl super::client::WebSocketClient {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn psu(state: &str) -> StatusSensor {
+        StatusSensor {
+            id: "ps1_status".to_string(),
+            name: "PS1 Status".to_string(),
+            sensor_type: "psu".to_string(),
+            value: None,
+            unit: None,
+            state: state.to_string(),
+            raw: "0x01 / ok".to_string(),
+            hardware_name: None,
+        }
+    }
+
+    #[test]
+    fn status_alerts_on_transitions_only() {
+        let mut states = HashMap::new();
+        assert!(status_transitions(&mut states, &[psu("ok")]).is_empty());
+        assert!(status_transitions(&mut states, &[psu("ok")]).is_empty());
+
+        let failed = [psu("critical")];
+        let alerts = status_transitions(&mut states, &failed);
+        assert_eq!(alerts.len(), 1);
+        assert_eq!(alerts[0].1, "ok");
+        assert!(status_transitions(&mut states, &failed).is_empty());
+
+        // Recovery is reported once as well
+        let recovered = [psu("ok")];
+        let alerts = status_transitions(&mut states, &recovered);
+        assert_eq!((alerts[0].0.state.as_str(), alerts[0].1.as_str()), ("ok", "critical"));
+
+        // Already failed when first seen
+        assert_eq!(status_transitions(&mut HashMap::new(), &failed).len(), 1);
+        // Absent (empty slot) is not an alert
+        assert!(status_transitions(&mut HashMap::new(), &[psu("absent")]).is_empty());
+    }
+}
@@ -2,11 +2,19 @@
 //!
 //! Fallback: if fetch fails, agent uses existing profile.json on disk
 //! or the --profile CLI flag.
+//!
+//! `--fetch-profile` instead picks a profile by the board's FRU from a profile
+//! repository index (see profiles::repository).
 
 use std::path::{Path, PathBuf};
-use anyhow::{Context, Result};
+use anyhow::{anyhow, Context, Result};
+use sha2::{Digest, Sha256};
 use tracing::{info, warn, debug};
 
+use crate::profiles::repository::{file_url, IndexEntry, RepositoryIndex};
+use crate::profiles::validator::{has_errors, validate_file};
+use crate::system::parser::BoardIdentity;
+
 /// Fetch the BMC profile JSON from the backend API and write it to `profile.json`.
 /// On failure, falls back to existing profile.json on disk.
 ///
@@ -43,6 +51,49 @@ pub async fn fetch_and_cache_profile(
     }
 }
 
+/// Find the profile for `board` in the repository at `index_url`, check its
+/// sha256, validate it and install it at `profile_path` (atomically, so a
+/// failed download never replaces a working profile).
+pub async fn fetch_profile_for_board(
+    index_url: &str,
+    board: &BoardIdentity,
+    profile_path: &Path,
+    auth_token: Option<&str>,
+) -> Result<IndexEntry> {
+    info!("Fetching profile repository index from {}", index_url);
+    let index = RepositoryIndex::parse(&fetch_profile_http(index_url, auth_token).await?)?;
+    let entry = index.find(board)
+        .ok_or_else(|| anyhow!("No profile in the repository matches {}", board))?
+        .clone();
+
+    let url = file_url(index_url, &entry)?;
+    let body = fetch_profile_http(&url, auth_token).await?;
+    let digest = sha256_hex(body.as_bytes());
+    if !digest.eq_ignore_ascii_case(entry.sha256.trim()) {
+        return Err(anyhow!("Checksum mismatch for {}: expected {}, got {}", url, entry.sha256, digest));
+    }
+
+    let tmp_path = profile_path.with_extension("json.download");
+    std::fs::write(&tmp_path, &body)
+        .with_context(|| format!("Failed to write {:?}", tmp_path))?;
+    let problems = validate_file(&tmp_path);
+    if has_errors(&problems) {
+        let _ = std::fs::remove_file(&tmp_path);
+        let details: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+        return Err(anyhow!("Downloaded profile '{}' is invalid:\n  {}", entry.id, details.join("\n  ")));
+    }
+    std::fs::rename(&tmp_path, profile_path)
+        .with_context(|| format!("Failed to install profile at {:?}", profile_path))?;
+
+    info!("Profile '{}' saved to {:?}", entry.id, profile_path);
+    Ok(entry)
+}
+
+/// Hex SHA-256 of `data`.
+fn sha256_hex(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
 /// Perform the HTTP GET request using curl (same pattern as self_update.rs).
 async fn fetch_profile_http(url: &str, auth_token: Option<&str>) -> Result<String> {
     let mut args: Vec<String> = vec![
@@ -81,3 +132,42 @@ async fn fetch_profile_http(url: &str, auth_token: Option<&str>) -> Result<Strin
     debug!("Profile fetched: {} bytes", body.len());
     Ok(body)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha256_is_lowercase_hex() {
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[tokio::test]
+    async fn downloaded_profile_is_installed_only_when_its_checksum_matches() {
+        let dir = std::env::temp_dir().join(format!("pankha-profile-fetch-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let profile = include_str!("../../profile.example.json");
+        std::fs::write(dir.join("board.json"), profile).unwrap();
+        let index = |sha256: &str| {
+            let json = serde_json::json!({"version": 1, "profiles": [
+                {"id": "example", "vendor": "Supermicro", "boards": ["X11*"], "file": "board.json", "sha256": sha256},
+            ]});
+            std::fs::write(dir.join("index.json"), json.to_string()).unwrap();
+        };
+        let index_url = format!("file://{}", dir.join("index.json").display());
+        let board = BoardIdentity { manufacturer: Some("Supermicro".to_string()), products: vec!["X11SSH-F".to_string()] };
+        let installed = dir.join("profile.json");
+
+        index(&"0".repeat(64));
+        let err = fetch_profile_for_board(&index_url, &board, &installed, None).await.unwrap_err();
+        assert!(err.to_string().starts_with("Checksum mismatch"), "{}", err);
+        assert!(!installed.exists());
+
+        index(&sha256_hex(profile.as_bytes()).to_uppercase());
+        let entry = fetch_profile_for_board(&index_url, &board, &installed, None).await.unwrap();
+        assert_eq!(entry.id, "example");
+        assert_eq!(std::fs::read_to_string(&installed).unwrap(), profile);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
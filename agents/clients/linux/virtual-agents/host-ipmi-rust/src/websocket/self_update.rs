@@ -16,6 +16,7 @@ impl super::client::WebSocketClient {
             running: Arc::clone(&self.running),
             failsafe_active: Arc::clone(&self.failsafe_active),
             last_reported_error: Arc::clone(&self.last_reported_error),
+            status_states: Arc::clone(&self.status_states),
         }
     }
 
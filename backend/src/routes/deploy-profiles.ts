@@ -6,6 +6,8 @@
  * Endpoints:
  *   GET  /api/deploy/profiles                    - Vendor/model catalog (frontend dropdowns)
  *   GET  /api/deploy/profiles/refresh            - Refresh catalog from disk
+ *   GET  /api/deploy/profiles/index              - Board -> profile index (agent --fetch-profile)
+ *   GET  /api/deploy/profiles/file?id=<id>       - Resolved profile JSON, as hashed in the index
  *   GET  /api/deploy/profiles/:vendor/:model     - Resolved profile details (frontend preview)
  *   GET  /api/deploy/profiles/assigned/:agentId  - Agent fetches its assigned profile (Option B)
  *   PUT  /api/deploy/profiles/assign/:agentId    - Admin assigns/changes profile for an agent
@@ -14,6 +16,7 @@
 import { Router } from 'express';
 import path from 'path';
 import fs from 'fs';
+import crypto from 'crypto';
 import { log } from '../utils/logger';
 import Database from '../database/database';
 import { ProfileService } from '../services/ProfileService';
@@ -247,6 +250,53 @@ router.post('/custom', async (req, res) => {
   }
 });
 
+/** Body served by /file - the index's sha256 is computed over exactly these bytes. */
+function profileFileBody(profile: any): string {
+  return JSON.stringify(profile, null, 2);
+}
+
+/**
+ * GET /api/deploy/profiles/index
+ * Static-style repository index for the IPMI agent's --fetch-profile: which
+ * boards each resolved profile covers, where to download it, and its sha256.
+ * `file` is relative to this URL.
+ */
+router.get('/index', (req, res) => {
+  try {
+    const profileService = ProfileService.getInstance();
+    const profiles = profileService.getCatalog().vendors.flatMap((vendor) =>
+      vendor.models.flatMap((model) => {
+        const profile = profileService.getResolvedProfile(model.profile_id);
+        if (!profile) return [];
+        return [{
+          id: model.profile_id,
+          vendor: model.vendor,
+          boards: model.model_family,
+          file: `file?id=${encodeURIComponent(model.profile_id)}`,
+          sha256: crypto.createHash('sha256').update(profileFileBody(profile)).digest('hex'),
+        }];
+      })
+    );
+    res.json({ version: 1, profiles });
+  } catch (error) {
+    log.error('Failed to build profile index:', 'deploy-profiles', error);
+    res.status(500).json({ error: 'Failed to build profile index' });
+  }
+});
+
+/**
+ * GET /api/deploy/profiles/file?id=<vendor/model>
+ * Resolved profile JSON for --fetch-profile downloads.
+ */
+router.get('/file', (req, res) => {
+  const profileId = String(req.query.id || '');
+  const profile = ProfileService.getInstance().getResolvedProfile(profileId);
+  if (!profile) {
+    return res.status(404).json({ error: `Profile "${profileId}" not found` });
+  }
+  res.type('application/json').send(profileFileBody(profile));
+});
+
 /**
  * GET /api/deploy/profiles/:vendor/:model
  * Returns resolved profile details for frontend preview (AIO builder match card).
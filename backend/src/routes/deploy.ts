@@ -613,8 +613,15 @@ rm -f "$DOWNLOAD_RESPONSE"
 
 echo "[4/5] Generating configuration..."
 
-# Generate unique agent ID
-AGENT_ID="linux-$(hostname)-$(head /dev/urandom | tr -dc a-z0-9 | head -c 8)"
+# Generate unique agent ID, unless a previous install left its identity.json
+# (the agent keeps that id over the one in config.json)
+AGENT_ID=""
+if [ -f "$INSTALL_DIR/identity.json" ]; then
+    AGENT_ID=$(run_as_root cat "$INSTALL_DIR/identity.json" | sed -n 's/.*"id": *"\\([^"]*\\)".*/\\1/p' | head -n 1)
+fi
+if [ -z "$AGENT_ID" ]; then
+    AGENT_ID="linux-$(hostname)-$(head /dev/urandom | tr -dc a-z0-9 | head -c 8)"
+fi
 
 # Write config.json
 CONFIG_CONTENT='{
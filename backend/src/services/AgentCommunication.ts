@@ -221,6 +221,24 @@ export class AgentCommunication extends EventEmitter {
     return successfulAgents;
   }
 
+  /**
+   * Fill the metadata of a compact data packet back in from the agent's
+   * registration capabilities, so everything downstream sees full entries.
+   */
+  private expandCompactPacket(agentId: string, data: any): AgentDataPacket {
+    const capabilities: any = this.agentManager.getAgent(agentId)?.capabilities;
+    const expand = (entries: any[] = [], registered: any[] = []) => {
+      const byId = new Map(registered.map((r: any) => [r.id, r]));
+      return entries.map((entry) => ({ ...byId.get(entry.id), ...entry }));
+    };
+    return {
+      ...data,
+      payload: undefined,
+      sensors: expand(data.sensors, capabilities?.sensors),
+      fans: expand(data.fans, capabilities?.fans),
+    };
+  }
+
   /**
    * Handle incoming message from agent
    */
@@ -239,7 +257,9 @@ export class AgentCommunication extends EventEmitter {
           log.debug(`Processing data packet from agent`, "AgentCommunication", {
             agentId,
           });
-          const dataPacket: AgentDataPacket = message.data;
+          const dataPacket: AgentDataPacket = message.data.payload === "compact"
+            ? this.expandCompactPacket(agentId, message.data)
+            : message.data;
           await this.agentManager.updateAgentStatus(agentId, dataPacket);
 
           // Forward data to DataAggregator for API consumption
@@ -273,6 +293,54 @@ export class AgentCommunication extends EventEmitter {
           this.emit("agentError", { agentId, error: message.data });
           break;
 
+        case "configurationApplied":
+          // Pushed settings the agent refused keep their old value agent-side
+          if (message.data?.rejected?.length) {
+            log.warn(
+              `Agent rejected configuration from ${message.data.source}: ` +
+                message.data.rejected
+                  .map((r: any) => `${r.field}=${JSON.stringify(r.value)} (${r.reason})`)
+                  .join(", "),
+              "AgentCommunication",
+              { agentId, effective: message.data.effective }
+            );
+          }
+          this.emit("configurationApplied", { agentId, report: message.data });
+          break;
+
+        case "emergencyAlert":
+          // Failsafe emergency that tripped while the agent was disconnected
+          log.warn(
+            `Agent hit emergency temperature while disconnected: ${message.data?.sensorId} ` +
+              `${message.data?.temperature}°C (threshold ${message.data?.threshold ?? "crit alarm"})`,
+            "AgentCommunication",
+            { agentId, alert: message.data }
+          );
+          this.emit("agentEmergencyAlert", { agentId, alert: message.data });
+          break;
+
+        case "emergencyAction":
+          // Configured emergency action (script or shutdown) run by the agent
+          log.error(
+            `Agent ran emergency action ${message.data?.report?.action}: ` +
+              `${message.data?.report?.success ? "" : "failed: "}${message.data?.report?.result}`,
+            "AgentCommunication",
+            { agentId, report: message.data?.report }
+          );
+          this.emit("agentEmergencyAction", { agentId, report: message.data?.report });
+          break;
+
+        case "statusAlert":
+          // IPMI status sensor (PSU, chassis intrusion, voltage) changed into or out of warning/critical
+          log.warn(
+            `Agent status sensor ${message.data?.sensor?.name}: ${message.data?.previous_state} -> ` +
+              `${message.data?.sensor?.state} (${message.data?.sensor?.raw})`,
+            "AgentCommunication",
+            { agentId, alert: message.data }
+          );
+          this.emit("agentStatusAlert", { agentId, alert: message.data });
+          break;
+
         case "pong":
           // Response to ping
           const connection = this.connections.get(agentId);
@@ -1,7 +1,7 @@
 import { EventEmitter } from "events";
 import { v4 as uuidv4 } from "uuid";
 import Database from "../database/database";
-import { FanControlCommand } from "../types/agent";
+import { FanControlCommand, FanControlMode } from "../types/agent";
 import { AgentManager } from "./AgentManager";
 import { log } from "../utils/logger";
 import { deriveChipName } from "../utils/sensorUtils";
@@ -176,6 +176,60 @@ export class CommandDispatcher extends EventEmitter {
     return this.sendCommand(agentId, "restoreFanToAuto", { fanId }, priority);
   }
 
+  /**
+   * Re-enable control of a fan the agent gave up on after repeated PWM write
+   * failures (reported with has_pwm_control=false and status "error"). Fails
+   * if the retry write fails too.
+   */
+  public async retryFanControl(
+    agentId: string,
+    fanId: string,
+    priority: "low" | "normal" | "high" | "emergency" = "normal"
+  ): Promise<any> {
+    log.info(
+      ` Retrying fan control of ${fanId} for agent ${agentId}`,
+      "CommandDispatcher"
+    );
+    return this.sendCommand(agentId, "retryFanControl", { fanId }, priority);
+  }
+
+  /**
+   * Switch a Linux agent fan's hwmon control mode (pwm_enable). Outside manual
+   * mode the agent refuses setFanSpeed for that fan until it is set back.
+   */
+  public async setFanMode(
+    agentId: string,
+    fanId: string,
+    mode: FanControlMode | number,
+    priority: "low" | "normal" | "high" | "emergency" = "normal"
+  ): Promise<any> {
+    log.info(
+      ` Setting fan ${fanId} of agent ${agentId} to mode ${mode}`,
+      "CommandDispatcher"
+    );
+    return this.sendCommand(agentId, "setFanMode", { fanId, mode }, priority);
+  }
+
+  /**
+   * Disable or re-enable a whole hwmon chip (by name) on a Linux agent. Disabling a
+   * chip with controllable fans needs force; those fans go back to automatic control.
+   */
+  public async setChipEnabled(
+    agentId: string,
+    chip: string,
+    enabled: boolean,
+    force = false,
+    priority: "low" | "normal" | "high" | "emergency" = "normal"
+  ): Promise<any> {
+    log.info(
+      ` ${enabled ? "Enabling" : "Disabling"} chip ${chip} of agent ${agentId}`,
+      "CommandDispatcher"
+    );
+    return enabled
+      ? this.sendCommand(agentId, "enableChip", { chip }, priority)
+      : this.sendCommand(agentId, "disableChip", { chip, force }, priority);
+  }
+
   /**
    * Set update interval for an agent
    */
@@ -767,7 +821,8 @@ export class CommandDispatcher extends EventEmitter {
       }
       if (
         pendingCommand.command.type === "setEmergencyTemp" &&
-        response.data?.temp !== undefined
+        response.data?.temp !== undefined &&
+        !response.data?.sensorType // per-sensor-type overrides live in the agent config only
       ) {
         this.agentManager.setAgentEmergencyTemp(
           pendingCommand.command.agentId,
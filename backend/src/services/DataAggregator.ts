@@ -793,6 +793,8 @@ export class DataAggregator extends EventEmitter {
               ? ("error" as const)
               : fan.status === "stopped"
               ? ("stopped" as const)
+              : fan.status === "absent"
+              ? ("absent" as const)
               : ("ok" as const),
           ...(fan.zone ? { zone: fan.zone } : {}),
         })) || [];
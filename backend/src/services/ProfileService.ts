@@ -30,7 +30,7 @@ export interface ProfileCatalogEntry {
   author: string;
   profile_tier: 'official' | 'experimental';
   is_monitor_only: boolean;
-  zones: { id: string; name: string; members?: string[] }[];
+  zones: { id: string; name: string; members?: string[]; member_fans?: string[] }[];
   has_read_speed: boolean;
   speed_translation_type: string;    // "decimal_hex" | "byte_scale" | "integer"
 }
@@ -54,6 +54,7 @@ export interface ProfileCatalog {
  *   fan_zones:         REPLACE (child array replaces base)
  *   initialization:    APPEND (child commands added after base)
  *   reset_to_factory:  REPLACE (child replaces base entirely)
+ *   max_cooling:       REPLACE (same)
  */
 function deepMergeProfile(base: any, child: any): any {
   const merged = JSON.parse(JSON.stringify(base));
@@ -97,6 +98,11 @@ function deepMergeProfile(base: any, child: any): any {
       if (childIpmi.lifecycle.reset_to_factory) {
         baseIpmi.lifecycle.reset_to_factory = childIpmi.lifecycle.reset_to_factory;
       }
+
+      // max_cooling: REPLACE
+      if (childIpmi.lifecycle.max_cooling) {
+        baseIpmi.lifecycle.max_cooling = childIpmi.lifecycle.max_cooling;
+      }
     }
   }
 
@@ -245,6 +251,7 @@ export class ProfileService {
             id: z.id,
             name: z.name,
             members: z.members,
+            member_fans: z.member_fans,
           }));
 
           const firstZone = ipmi?.fan_zones?.[0];
@@ -1,5 +1,6 @@
 import WebSocket, { WebSocketServer } from "ws";
 import { EventEmitter } from "events";
+import { crc32, gunzipSync, inflateRawSync } from "zlib";
 import { IncomingMessage, Server } from "http";
 import { DataAggregator } from "./DataAggregator";
 import { AgentManager } from "./AgentManager";
@@ -43,12 +44,23 @@ interface ClientConnection {
     connectedAt: Date;
     agentId?: string;
     isAgent?: boolean;
+    // Optional features the agent offered at registration (undefined = predates negotiation)
+    agentFeatures?: string[];
     // Session-verified dashboard connection. Connections start unclassified;
     // isFrontend is granted by a valid session cookie at upgrade, isAgent by
     // an authenticated register message. Neither = no data flows.
     isFrontend?: boolean;
     username?: string;
   };
+  // Chunked command responses being received, by commandId (chunked_responses)
+  chunkedResponses?: Map<string, ChunkedResponse>;
+}
+
+interface ChunkedResponse {
+  totalSize: number;
+  encoding: string;
+  chunks: Buffer[];
+  received: number;
 }
 
 interface WebSocketMessage {
@@ -93,6 +105,27 @@ const MIN_TOKEN_CAPABLE_AGENT_VERSION = "v0.6.3";
 // How long an updating agent has to reconnect before it must pend again.
 const UPDATE_RECONNECT_WINDOW_MS = 300_000;
 
+// Largest deflated agent message accepted once inflated, against decompression bombs.
+const MAX_INFLATED_MESSAGE_BYTES = 16 * 1024 * 1024;
+
+// Agent <-> backend message protocol, and the optional agent features this
+// backend understands. Agents switch off anything not in the negotiated list.
+const PROTOCOL_VERSION = 1;
+const SUPPORTED_AGENT_FEATURES = [
+  "command_dedup",
+  "configuration_applied",
+  "emergency_alert",
+  "telemetry_status",
+  "diagnostics",
+  "self_update",
+  "sensor_type_emergency_temp",
+  "compact_payload",
+  "config_update",
+  "emergency_action",
+  "deflate_data",
+  "chunked_responses",
+];
+
 export class WebSocketHub extends EventEmitter {
   private static instance: WebSocketHub;
   private wss: WebSocketServer | null = null;
@@ -381,8 +414,8 @@ export class WebSocketHub extends EventEmitter {
     }
 
     // Setup message handler
-    ws.on("message", async (data: WebSocket.Data) => {
-      await this.handleClientMessage(clientId, data);
+    ws.on("message", async (data: WebSocket.Data, isBinary: boolean) => {
+      await this.handleClientMessage(clientId, data, isBinary);
     });
 
     // Handle disconnection
@@ -508,10 +541,15 @@ export class WebSocketHub extends EventEmitter {
    */
   private async handleClientMessage(
     clientId: string,
-    data: WebSocket.Data
+    data: WebSocket.Data,
+    isBinary = false
   ): Promise<void> {
     try {
-      const message: WebSocketMessage = JSON.parse(data.toString());
+      // Binary frames are agent data messages deflated with backend.compression
+      const text = isBinary
+        ? inflateRawSync(data as Buffer, { maxOutputLength: MAX_INFLATED_MESSAGE_BYTES }).toString()
+        : data.toString();
+      const message: WebSocketMessage = JSON.parse(text);
       log.trace(`RAW MESSAGE received`, "WebSocketHub", {
         clientId,
         messageType: message.type,
@@ -647,6 +685,56 @@ export class WebSocketHub extends EventEmitter {
           }
           break;
 
+        case "configurationApplied":
+          // Agent's verdict on pushed settings (registration config or a
+          // failed set* command): accepted/rejected fields + effective values.
+          const appliedClient = this.clients.get(clientId);
+          if (appliedClient?.metadata.isAgent && appliedClient.metadata.agentId) {
+            await this.agentCommunication.handleAgentMessage(
+              appliedClient.metadata.agentId,
+              { type: "configurationApplied", data: message.data }
+            );
+          }
+          break;
+
+        case "emergencyAlert":
+          // Agent tripped its local emergency threshold while disconnected
+          // (failsafe mode) and reports which sensor/threshold on reconnect.
+          const alertClient = this.clients.get(clientId);
+          if (alertClient?.metadata.isAgent && alertClient.metadata.agentId) {
+            await this.agentCommunication.handleAgentMessage(
+              alertClient.metadata.agentId,
+              { type: "emergencyAlert", data: message.data }
+            );
+          }
+          break;
+
+        case "emergencyAction":
+          // Result of a last-resort action (script, shutdown) the agent ran
+          // because fans at 100% couldn't hold an emergency.
+          const actionClient = this.clients.get(clientId);
+          if (actionClient?.metadata.isAgent && actionClient.metadata.agentId) {
+            await this.agentCommunication.handleAgentMessage(
+              actionClient.metadata.agentId,
+              { type: "emergencyAction", data: message.data }
+            );
+          }
+          break;
+
+        case "commandResponseStart":
+        case "commandResponseChunk":
+        case "commandResponseEnd":
+          // A command response too large for one frame (diagnostics dump):
+          // reassembled, then handled like one sent whole
+          const chunkClient = this.clients.get(clientId);
+          if (chunkClient?.metadata.isAgent) {
+            const reassembled = this.collectResponseChunk(chunkClient, message as any);
+            if (reassembled) {
+              await this.handleClientMessage(clientId, reassembled);
+            }
+          }
+          break;
+
         case "commandResponse":
           // Handle command response from agent
           const commandClient = this.clients.get(clientId);
@@ -1005,8 +1093,83 @@ export class WebSocketHub extends EventEmitter {
           });
         }
 
-        // Send registration confirmation with configuration
+        // Linux agents diff discovered hardware against their previous run
+        const hw = registrationData.hardware_changes;
+        if (hw) {
+          const count = (c: any) =>
+            (c?.added?.length || 0) + (c?.removed?.length || 0) + (c?.renamed?.length || 0);
+          const changed = count(hw.sensors) + count(hw.fans);
+          if (changed > 0) {
+            log.info(
+              `Agent ${agentId} hardware changed since its last run: ${changed} sensor/fan change(s)`,
+              "WebSocketHub"
+            );
+          }
+        }
+
+        // Other fan-control software (fancontrol, thinkfan, ...) on the agent host
+        const conflicts = registrationData.fan_control_conflicts;
+        if (Array.isArray(conflicts)) {
+          for (const conflict of conflicts) {
+            log.warn(
+              `Agent ${agentId} conflicting fan control software: ${conflict.message}`,
+              "WebSocketHub"
+            );
+          }
+        }
+
+        // Why the agent's previous connection ended (server_closed, health_timeout, ...)
+        const lastDisconnect = registrationData.last_disconnect;
+        if (lastDisconnect) {
+          const uptime =
+            typeof lastDisconnect.connected_secs === "number"
+              ? ` after ${Math.round(lastDisconnect.connected_secs)}s connected`
+              : " before connecting";
+          log.info(
+            `Agent ${agentId} reconnected; last disconnect: ${lastDisconnect.category}${uptime} (${lastDisconnect.error})`,
+            "WebSocketHub"
+          );
+        }
+
+        // Agent restarted into safe mode after a crash loop: no fan control
+        const safeMode = registrationData.safe_mode;
+        if (safeMode) {
+          log.warn(
+            `Agent ${agentId} is in safe mode after ${safeMode.starts} unclean starts within ${safeMode.window_minutes} min; fans are on automatic control until clearSafeMode`,
+            "WebSocketHub"
+          );
+        }
+
+        // Agent in a container; without hwmon there it monitors only
+        if (registrationData.environment === "container") {
+          const fanControl = registrationData.capabilities?.fan_control;
+          log.info(
+            `Agent ${agentId} runs in a container${fanControl === false ? " (monitoring only)" : ""}`,
+            "WebSocketHub"
+          );
+        }
+
+        // Send registration confirmation with configuration and the feature
+        // set both sides support (agents without negotiation send no list)
+        const agentFeatures: string[] | undefined =
+          registrationData.capabilities?.features;
+        if (client) {
+          client.metadata.agentFeatures = agentFeatures;
+        }
+        if (
+          registrationData.protocol_version !== undefined &&
+          registrationData.protocol_version !== PROTOCOL_VERSION
+        ) {
+          log.info(
+            `Agent ${agentId} speaks protocol v${registrationData.protocol_version}, backend v${PROTOCOL_VERSION}`,
+            "WebSocketHub"
+          );
+        }
         this.sendToClient(clientId, "registered", {
+          protocol_version: PROTOCOL_VERSION,
+          features: agentFeatures
+            ? SUPPORTED_AGENT_FEATURES.filter((f) => agentFeatures.includes(f))
+            : SUPPORTED_AGENT_FEATURES,
           agentId: agentId,
           status: "success",
           message: "Agent registered successfully",
@@ -1506,6 +1669,61 @@ export class WebSocketHub extends EventEmitter {
     };
   }
 
+  /**
+   * Take one message of a chunked command response. Returns the commandResponse
+   * JSON once the end message arrives with a matching checksum, else null.
+   */
+  private collectResponseChunk(client: ClientConnection, message: any): string | null {
+    const commandId = message.commandId;
+    if (typeof commandId !== "string") return null;
+    const transfers = (client.chunkedResponses ??= new Map());
+    const transfer = transfers.get(commandId);
+    const drop = (reason: string): null => {
+      log.warn(`Dropping chunked response ${commandId}: ${reason}`, "WebSocketHub", {
+        agentId: client.metadata.agentId,
+      });
+      transfers.delete(commandId);
+      return null;
+    };
+
+    if (message.type === "commandResponseStart") {
+      if (!(message.totalSize >= 0 && message.totalSize <= MAX_INFLATED_MESSAGE_BYTES)) {
+        return drop(`size ${message.totalSize} over limit`);
+      }
+      transfers.set(commandId, {
+        totalSize: message.totalSize,
+        encoding: message.encoding,
+        chunks: [],
+        received: 0,
+      });
+      return null;
+    }
+    if (!transfer) return null;
+
+    if (message.type === "commandResponseChunk") {
+      const chunk = Buffer.from(message.data ?? "", "base64");
+      transfer.received += chunk.length;
+      if (message.index !== transfer.chunks.length) {
+        return drop(`chunk ${message.index} out of order`);
+      }
+      if (transfer.received > transfer.totalSize) {
+        return drop("more data than announced");
+      }
+      transfer.chunks.push(chunk);
+      return null;
+    }
+
+    transfers.delete(commandId);
+    const bytes = Buffer.concat(transfer.chunks);
+    const checksum = `crc32:${crc32(bytes).toString(16).padStart(8, "0")}`;
+    if (bytes.length !== transfer.totalSize || checksum !== message.checksum) {
+      return drop(`checksum mismatch (${checksum}, expected ${message.checksum})`);
+    }
+    return transfer.encoding === "gzip"
+      ? gunzipSync(bytes, { maxOutputLength: MAX_INFLATED_MESSAGE_BYTES }).toString()
+      : bytes.toString();
+  }
+
   /**
    * Send message to specific client
    */
@@ -1808,6 +2026,39 @@ export class WebSocketHub extends EventEmitter {
     return false;
   }
 
+  /**
+   * Push changed settings to a connected agent (same object as the `configuration`
+   * of `registered`). The agent answers with `configurationApplied`. False when the
+   * agent isn't connected or doesn't handle `configUpdate`; it then picks the
+   * settings up at its next registration.
+   */
+  public sendConfigUpdateToAgent(agentId: string, configuration: Record<string, unknown>): boolean {
+    for (const client of this.clients.values()) {
+      if (!client.metadata.isAgent || client.metadata.agentId !== agentId) {
+        continue;
+      }
+      if (!client.metadata.agentFeatures?.includes("config_update")) {
+        return false;
+      }
+      if (client.websocket.readyState !== WebSocket.OPEN) {
+        return false;
+      }
+      client.websocket.send(
+        JSON.stringify({
+          type: "configUpdate",
+          data: { configuration },
+          timestamp: new Date().toISOString(),
+        })
+      );
+      log.debug(`Configuration update sent to agent`, "WebSocketHub", {
+        agentId,
+        fields: Object.keys(configuration),
+      });
+      return true;
+    }
+    return false;
+  }
+
   /**
    * Send command to all connected agents
    */
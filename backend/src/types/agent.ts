@@ -16,14 +16,38 @@ export interface AgentConfig {
 export interface AgentCapabilities {
   sensors: SensorInfo[];
   fans: FanInfo[];
+  fan_control?: boolean;
+  is_elevated?: boolean; // agent runs as root
+  control_capable?: boolean; // at least one fan's PWM is writable
+  dry_run?: boolean; // fan writes are logged, not executed
+  sensor_monitoring?: boolean; // false: hardware.enable_sensor_monitoring is off, no sensors are reported
+  commands?: string[]; // command types the agent handles
+  features?: string[]; // optional protocol features the agent offers
+  truncated?: number; // sensors left out to stay under the agent's message size limit
+  topology?: HardwareTopologyChip[]; // Linux agent: which sensors and fans share a chip
+}
+
+// Registration only; entries name capability sensors/fans by `id`
+export interface HardwareTopologyChip {
+  identifier: string; // '/hwmon/hwmon2'
+  name: string; // 'nct6798'
+  type: string; // 'SuperIO', 'Cpu', 'Gpu', ...
+  entries: Array<{
+    identifier: string; // '/nct6798/fan/2'
+    kind: "temperature" | "fan" | "control"; // fan = tach reading
+    id?: string; // sensor or fan id in sensors/fans
+    linked?: string; // identifier of the paired control or tach
+  }>;
+  children?: HardwareTopologyChip[];
 }
 
 export interface SensorInfo {
   id: string;
-  name: string; // 'Tctl', 'temp1', 'Composite'
+  name: string; // 'Tctl', 'temp1', 'Composite'; Linux agent with naming_version 2: raw chip + label ('k10temp Tctl'), deprecated
+  display_name?: string; // Linux agent (naming_version 2): friendly name ('CPU AMD Tctl'); may change between releases, key on `id`
   label: string; // User-friendly name
   chip: string; // 'k10temp-pci-00c3'
-  type: "cpu" | "gpu" | "motherboard" | "nvme" | "acpi" | "other";
+  type: "cpu" | "gpu" | "motherboard" | "nvme" | "hdd" | "acpi" | "other";
   currentTemp: number; // Current reading
   maxTemp?: number; // Hardware maximum
   critTemp?: number; // Critical threshold
@@ -55,43 +79,160 @@ export interface FanInfo {
 export interface AgentDataPacket {
   agentId: string;
   timestamp: number;
+  // Linux agent: monotonic ms since the agent started; unlike `timestamp` it can't
+  // jump when the host clock is stepped
+  uptime_ms?: number;
+  // Linux agent with payload_profile "compact": registered sensors and fans carry
+  // only their readings; the metadata is in the registration capabilities
+  payload?: "compact";
+  // Linux agent: sensors left out (lowest priority first) to stay under backend.max_message_bytes
+  truncated?: number;
+  // Linux agent with agent.adaptive_interval: time until the next frame
+  interval_ms?: number;
+  // Linux agent: false when hardware.enable_sensor_monitoring is off (sensors is then empty)
+  sensor_monitoring?: boolean;
   sensors: Array<{
     id: string;
     temperature: number;
-    type?: string; // Sensor type (cpu, gpu, etc.)
+    type?: string; // Sensor type (cpu, gpu, etc.; "virtual" for Linux agent virtual sensors)
     max_temp?: number; // Maximum safe temperature
     crit_temp?: number; // Critical temperature threshold
     status?: "ok" | "caution" | "warning" | "critical"; // Optional - calculated on server if not provided
+    stale?: boolean; // IPMI agent: cached reading re-sent while the BMC is unresponsive; Linux agent: last value re-sent through transient read failures, or a virtual sensor's while a source is missing
+    raw_temperature?: number; // Linux agent: instantaneous reading when `temperature` is smoothed
+    state?: "ok" | "warning" | "critical" | "absent" | "unknown"; // IPMI agent: BMC threshold status (report_status_sensors)
   }>;
   fans: Array<{
     id: string;
     speed: number; // Current speed percentage
     rpm: number; // Current RPM
     targetSpeed: number; // Requested speed
-    status: "ok" | "error" | "stopped";
+    pwm?: number; // Linux agent: raw pwmN register (0-255) `speed` was read from
+    status: "ok" | "error" | "stopped" | "absent"; // "absent": Linux agent, empty header
+    // Linux agent: what `status` is based on
+    status_detail?: {
+      reason: "spinning" | "idle" | "stalled" | "no_tach" | "absent" | "fault";
+      presence: "present" | "absent" | "unknown";
+      fault?: boolean; // fanN_fault asserted
+      alarm?: boolean; // a fanN_*alarm asserted
+    };
     zone?: string; // Fan zone ID for IPMI agents (e.g., "cpu_zone", "peripheral_zone")
+    control_method?: "pwm" | "target_rpm" | "nvml"; // Linux agent: how the speed is set
+    pwm_enable?: number; // Linux agent: hwmon pwmN_enable value
+    control_mode?: FanControlMode; // Linux agent: pwm_enable as a mode (setFanMode switches it)
+    drift?: boolean; // Linux agent: register doesn't hold the commanded value (speed = hardware, targetSpeed = commanded)
+    control_error?: string; // Linux agent: why control was given up (status "error"), e.g. "firmware rejects writes"
+    last_set_by?: { // Linux agent: what last had the agent set this fan's speed
+      origin: "backend_command" | "failsafe" | "emergency" | "local_curve" | "local_cli" | "characterization";
+      command_id?: string; // backend_command: the setFanSpeed commandId
+      timestamp: number; // Unix ms
+    };
+    stale?: boolean; // IPMI agent: cached reading re-sent while the BMC is unresponsive
+  }>;
+  // IPMI agent with report_status_sensors: PSU, chassis intrusion, voltage rails
+  status_sensors?: Array<{
+    id: string;
+    name: string;
+    type: "psu" | "intrusion" | "voltage" | "current" | "power" | "discrete";
+    value: number | null;
+    unit?: string;
+    state: "ok" | "warning" | "critical" | "absent" | "unknown";
+    raw: string;
   }>;
   systemHealth: {
     cpuUsage: number;
     memoryUsage: number;
     agentUptime: number;
+    // IPMI agent: ipmitool counters since agent start
+    ipmiCommands?: {
+      commands: number;
+      retries: number;
+      failures: number;
+      timeouts: number;
+      circuitOpen: boolean;
+    };
+    // Linux agent: backend connection latencies over the last 5 minutes (ms)
+    connection?: {
+      ping_rtt_ms?: AgentLatencyStats;
+      pong_delay_ms?: AgentLatencyStats;
+      command_ms?: AgentLatencyStats;
+      send_ms?: AgentLatencyStats;
+      degraded: boolean;
+    };
+    // Linux agent with storage_health: SMART readings per drive, refreshed hourly
+    storageHealth?: Array<{
+      device: string; // "nvme0", "sda": prefix of the drive's sensor ids
+      model?: string;
+      passed?: boolean;
+      power_on_hours?: number;
+      media_errors?: number;
+      percentage_used?: number;
+      reallocated_sectors?: number;
+      pending_sectors?: number;
+      checked_at: number;
+    }>;
+    // Linux agent with notifications.webhook_url: webhook deliveries since start
+    notifications?: {
+      sent: number;
+      failed: number;
+      suppressed: number;
+    };
   };
+  // Connection/failsafe history (Linux agent). last_outage_* describes the most
+  // recent period without a backend; process_* counts since the agent started.
+  status?: {
+    failsafe_active: boolean;
+    failsafe_entered_at: number | null; // Unix ms
+    last_outage_duration_secs: number | null;
+    last_outage_emergency: {
+      triggered: boolean;
+      triggered_at: number | null; // Unix ms
+      peak_temp: number | null;
+    };
+    process_reconnect_count: number;
+    last_resume_at?: number | null; // Unix ms of the last resume from suspend
+    last_suspend_duration_secs?: number | null;
+    process_resume_count?: number;
+  };
+}
+
+// Linux agent: percentiles of one latency in systemHealth.connection
+export interface AgentLatencyStats {
+  p50: number;
+  p95: number;
+  samples: number;
+}
+
+// `emergencyAction` message (Linux agent, emergency_actions config): one configured
+// action run because an emergency held with every fan at 100%
+export interface AgentEmergencyActionReport {
+  action: string; // "script /usr/local/bin/page-oncall" or "shutdown"
+  success: boolean;
+  result: string; // exit status, "poweroff requested", or the error
+  trigger: { sensor_id: string; temperature: number };
+  timestamp: number; // Unix ms
 }
 
 // Command Protocol
+// Linux agent: hwmon pwm_enable 0 (full speed), 1 (manual, speeds from the
+// backend) or 2+ (one of the chip's automatic curves)
+export type FanControlMode = "full_speed" | "manual" | "auto";
+
 export interface FanControlCommand {
   commandId: string;
   agentId: string;
   type:
     | "setFanSpeed"
     | "setProfile"
-    | "emergencyStop"
+    | "emergencyStop" // max cooling: every fan to 100%
+    | "releaseControl" // fans back to chip/driver/BMC automatic control
     | "getStatus"
     | "updateSensorMapping"
     | "rescanSensors"
     | "setUpdateInterval"
     | "setFanStep"
     | "setHysteresis"
+    | "setSmoothing"
     | "setEmergencyTemp"
     | "setLogLevel"
     | "setFailsafeSpeed"
@@ -103,10 +244,25 @@ export interface FanControlCommand {
     | "executeRawIpmi"
     | "reloadProfile"
     | "setExcludedSensors"
-    | "restoreFanToAuto";
+    | "disableChip"
+    | "enableChip"
+    | "restoreFanToAuto"
+    | "retryFanControl"
+    | "setFanMode"
+    | "setVirtualSensor"
+    | "getSensorStats"
+    | "resetSensorStats"
+    | "getEvents"
+    | "getConfigHistory"
+    | "clearSafeMode"
+    | "requestData"
+    | "characterizeFans"
+    | "getCharacterization"
+    | "cancelCharacterization";
   payload: {
     fanId?: string;
     speed?: number;
+    raw?: boolean; // setFanSpeed, Linux agent: speed is a pwmN register value (0-255)
     profileName?: string;
     sensorMappings?: Array<{
       fanId: string;
@@ -117,7 +273,11 @@ export interface FanControlCommand {
     interval?: number; // For setUpdateInterval command
     step?: number; // For setFanStep command
     hysteresis?: number; // For setHysteresis command
-    temp?: number; // For setEmergencyTemp command
+    mode?: "off" | "moving_average" | "exponential" | FanControlMode | number; // setSmoothing: smoothing mode; setFanMode: control mode or raw pwm_enable value
+    window?: number; // For setSmoothing command (moving_average: readings averaged, 1-60)
+    alpha?: number; // For setSmoothing command (exponential: weight of each new reading, 0-1)
+    temp?: number | null; // For setEmergencyTemp command (null with sensorType clears the override)
+    sensorType?: string; // For setEmergencyTemp command - per-sensor-type threshold (e.g. "hdd")
     level?: string; // For setLogLevel command
     failsafeSpeed?: number; // For setFailsafeSpeed command (0-100%)
     enabled?: boolean; // For setEnableFanControl command
@@ -125,12 +285,47 @@ export interface FanControlCommand {
     version?: string | null; // For selfUpdate command (hub version, e.g., "v0.3.2")
     bytes?: string; // For executeRawIpmi command (hex bytes like "0x30 0x70 0x66")
     excludedSensors?: string[]; // For setExcludedSensors command - sensor IDs to skip in failsafe
+    chip?: string; // For disableChip/enableChip commands - hwmon chip name (e.g. "acpitz")
+    force?: boolean; // For disableChip command - required when the chip has controllable fans
+    // For setVirtualSensor command: the definition (id, name, sources, aggregation),
+    // or { id, remove: true } to delete one
+    sources?: Array<{ sensor: string; weight?: number }>;
+    aggregation?: "weighted_avg" | "max" | "min" | "delta";
+    remove?: boolean;
     authToken?: string; // For setAuthToken command - Hub-minted agent credential
+    since?: number; // For getEvents/getConfigHistory commands - only entries at/after this Unix ms timestamp
+    limit?: number; // For getConfigHistory command - most recent changes returned (default 50, max 500)
+    // For characterizeFans command, all optional: duties held (percent, at least 20),
+    // temperature the lowest duties are estimated for, fans to run (default: all
+    // controllable), longest hold of one duty in seconds (default 300)
+    duties?: number[];
+    targetTemp?: number;
+    fans?: string[];
+    maxHoldSecs?: number;
   };
   timestamp: number;
   priority: "low" | "normal" | "high" | "emergency";
 }
 
+// Agent -> backend: outcome of pushed configuration
+export interface ConfigurationAppliedReport {
+  agentId: string;
+  source: string; // "registration" or the set* command type
+  commandId?: string;
+  accepted: string[];
+  rejected: Array<{ field: string; value: unknown; reason: string }>;
+  effective: {
+    update_interval: number;
+    fan_step_percent: number;
+    hysteresis_temp: number;
+    emergency_temp: number;
+    emergency_temp_by_type: Record<string, number>;
+    failsafe_speed: number;
+    enable_fan_control: boolean;
+    log_level: string;
+  };
+}
+
 export interface SensorDetectionResult {
   agentId: string;
   detectedSensors: Array<{
@@ -138,7 +333,7 @@ export interface SensorDetectionResult {
     sensors: Array<{
       name: string; // Raw sensor name from hardware
       label: string; // User-friendly label
-      type: "cpu" | "gpu" | "motherboard" | "nvme" | "acpi" | "other";
+      type: "cpu" | "gpu" | "motherboard" | "nvme" | "hdd" | "acpi" | "other";
       currentTemp: number;
       maxTemp?: number;
       critTemp?: number;
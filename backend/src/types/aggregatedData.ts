@@ -26,7 +26,7 @@ export interface FanReading {
   speed: number;
   rpm: number;
   targetSpeed: number;
-  status: 'ok' | 'error' | 'stopped';
+  status: 'ok' | 'error' | 'stopped' | 'absent';
   dbId?: number;
   zone?: string; // Fan zone ID for IPMI agents (e.g., "cpu_zone", "peripheral_zone")
   isHidden?: boolean; // Fan is hidden by user
@@ -634,7 +634,7 @@ export const DashboardSettingsProvider: React.FC<{ children: React.ReactNode }>
     let key: keyof PerTypeThresholds | undefined;
     if (typeKey.includes('cpu')) key = 'cpu';
     else if (typeKey.includes('gpu')) key = 'gpu';
-    else if (typeKey.includes('nvme') || typeKey.includes('storage') || typeKey.includes('disk')) key = 'nvme';
+    else if (typeKey.includes('nvme') || typeKey.includes('hdd') || typeKey.includes('storage') || typeKey.includes('disk')) key = 'nvme';
     else if (typeKey.includes('mobo') || typeKey.includes('motherboard') || typeKey.includes('mainboard')) key = 'mobo';
 
     if (key && perTypeThresholds[key]) {
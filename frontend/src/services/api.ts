@@ -614,6 +614,7 @@ export interface ProfileCatalogZone {
   id: string;
   name: string;
   members?: string[];
+  member_fans?: string[];
 }
 
 export interface ProfileCatalogEntry {
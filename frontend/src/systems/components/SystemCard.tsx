@@ -690,6 +690,7 @@ const SystemCard: React.FC<SystemCardProps> = ({
       case "network":
         return <img src="/icons/nic-01.png" width={24} height={24} title="NIC" alt="NIC" />;
       case "nvme":
+      case "hdd":
       case "storage":
         return <img src="/icons/hdd-01.png" width={24} height={24} title="Storage" alt="Storage" />;
       case "acpi":
@@ -1299,6 +1300,15 @@ const SystemCard: React.FC<SystemCardProps> = ({
                 <span className="status-dot" />
                 {isUpdating ? 'updating' : isUnsecured ? 'unsecured' : isIpmiNoProfile ? 'read only' : system.status}
               </span>
+              {system.capabilities?.dry_run && (
+                <span
+                  className="status-badge read-only"
+                  title={"Dry-run mode\nFan writes are logged by the agent, not applied to hardware"}
+                >
+                  <span className="status-dot" />
+                  dry run
+                </span>
+              )}
             </div>
 
             <div className="header-actions">
@@ -2262,6 +2272,7 @@ export default React.memo(SystemCard, (prevProps, nextProps) => {
       nextProps.system.enable_fan_control &&
     prevProps.system.read_only === nextProps.system.read_only && // License limit status
     prevProps.system.unsecured === nextProps.system.unsecured && // Auth token status badge
+    prevProps.system.capabilities?.dry_run === nextProps.system.capabilities?.dry_run && // Dry-run badge
     prevProps.system.profile_id === nextProps.system.profile_id && // IPMI profile assignment
     // Explicit sensor/fan array checks (reference equality works because mergeDelta creates new arrays)
     prevProps.system.current_temperatures ===
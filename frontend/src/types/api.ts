@@ -31,6 +31,9 @@ export interface SystemData {
   access_status?: "active" | "over_limit";
   unsecured?: boolean; // Agent has no auth token yet (grandfathered pre-auth binary)
   last_error?: string | null;
+  capabilities?: {
+    dry_run?: boolean; // Agent logs fan writes instead of executing them (--dry-run)
+  } | null;
   system_health?: {
     cpuUsage: number;
     memoryUsage: number;
@@ -64,7 +67,7 @@ export interface FanReading {
   speed: number;
   rpm: number;
   targetSpeed: number;
-  status: "ok" | "error" | "stopped";
+  status: "ok" | "error" | "stopped" | "absent";
   dbId?: number; // Database record ID for fan_profile_assignments
   zone?: string; // Fan zone ID for IPMI agents (e.g., "cpu_zone", "peripheral_zone")
   isHidden?: boolean; // Fan is hidden by user